@@ -2,11 +2,20 @@ use anyhow::{bail, Result};
 
 use crate::ast::{
     AggregateArg, AggregateFunction, Assignment, BinaryOperator, CreateIndexStatement,
-    CreateTableStatement, DeleteStatement, Expr, FromClause, InsertStatement, JoinType, Literal,
-    SelectColumn, SelectStatement, Statement, TableRef, UnaryOperator, UpdateStatement,
+    CreateTableStatement, DeleteStatement, Expr, FromClause, GroupByItem, InsertStatement, JoinType, Literal,
+    OnConflict, OnConflictAction, OrderByExpr, SelectColumn, SelectStatement, Statement, TableRef,
+    UnaryOperator, UpdateStatement,
 };
-use crate::catalog::Catalog;
-use crate::tuple::DataType;
+use crate::bootstrap::{FIRST_USER_TABLE_ID, PG_AUTHID_TABLE_ID};
+use crate::catalog::{Catalog, IndexColumn, TableDef};
+use crate::collation::Collation;
+use crate::functions::ScalarFunction;
+use crate::numeric::Numeric;
+use crate::protocol::{SqlError, INSUFFICIENT_PRIVILEGE};
+use crate::settings;
+use crate::executor::{exprs_equal, literal_to_value};
+use crate::tuple::{cast_value, parse_value, DataType, Value};
+use crate::udf;
 
 // Range Table Entry (RTE) - represents table-like objects in a query
 // Base tables, subqueries, joins, etc. can all be represented as RTEs
@@ -18,6 +27,8 @@ pub enum TableSource {
         #[allow(dead_code)]
         table_name: String,
     },
+    /// unnest(array) in FROM: one row per array element
+    Unnest { array: AnalyzedExpr },
 }
 
 // Output schema for an RTE
@@ -27,6 +38,7 @@ pub struct OutputColumn {
     pub data_type: DataType,
     #[allow(dead_code)]
     pub nullable: bool,
+    pub collation: Collation,
 }
 
 #[derive(Debug, Clone)]
@@ -62,7 +74,11 @@ pub struct AnalyzedSelectStatement {
     pub from_rte_index: usize,
     pub join_conditions: Vec<AnalyzedJoinCondition>, // For nested loop join
     pub where_clause: Option<AnalyzedExpr>,
+    /// Every distinct GROUP BY expression
     pub group_by: Vec<AnalyzedExpr>,
+    /// Grouping sets as indices into group_by: one set holding all of them
+    /// for a plain GROUP BY, none if the query has no GROUP BY
+    pub grouping_sets: Vec<Vec<usize>>,
     pub having: Option<AnalyzedExpr>,
 }
 
@@ -88,6 +104,7 @@ pub struct AnalyzedColumnRef {
     pub column_index: usize,
     pub column_name: String,
     pub data_type: DataType,
+    pub collation: Collation,
 }
 
 #[derive(Debug, Clone)]
@@ -97,6 +114,27 @@ pub struct AnalyzedInsertStatement {
     #[allow(dead_code)]
     pub table_name: String,
     pub values: Vec<AnalyzedExpr>,
+    pub on_conflict: Option<AnalyzedOnConflict>,
+    pub returning: Vec<AnalyzedSelectItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalyzedOnConflict {
+    /// IDs of the unique indexes that decide whether a row conflicts.
+    /// Empty means any unique index on the table (only allowed for DO NOTHING).
+    pub arbiter_index_ids: Vec<u32>,
+    pub action: AnalyzedOnConflictAction,
+}
+
+#[derive(Debug, Clone)]
+pub enum AnalyzedOnConflictAction {
+    DoNothing,
+    /// Expressions are resolved against two RTEs: the target table (rte 0)
+    /// and EXCLUDED, the row proposed for insertion (rte 1).
+    DoUpdate {
+        assignments: Vec<AnalyzedAssignment>,
+        where_clause: Option<AnalyzedExpr>,
+    },
 }
 
 #[allow(dead_code)]
@@ -111,15 +149,19 @@ pub struct AnalyzedCreateTableStatement {
 pub struct AnalyzedColumnDef {
     pub name: String,
     pub data_type: DataType,
+    pub collation: Collation,
 }
 
 #[derive(Debug, Clone)]
 pub struct AnalyzedCreateIndexStatement {
     pub index_name: String,
+    pub unique: bool,
     pub table_id: u32,
     #[allow(dead_code)]
     pub table_name: String,
-    pub column_ids: Vec<usize>,
+    pub columns: Vec<IndexColumn>,
+    /// SQL text of the expression keys, stored in pg_index
+    pub expressions: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -128,6 +170,7 @@ pub struct AnalyzedDeleteStatement {
     pub table_id: u32,
     pub range_table: Vec<RangeTableEntry>,
     pub where_clause: Option<AnalyzedExpr>,
+    pub returning: Vec<AnalyzedSelectItem>,
 }
 
 #[derive(Debug, Clone)]
@@ -137,6 +180,7 @@ pub struct AnalyzedUpdateStatement {
     pub range_table: Vec<RangeTableEntry>,
     pub assignments: Vec<AnalyzedAssignment>,
     pub where_clause: Option<AnalyzedExpr>,
+    pub returning: Vec<AnalyzedSelectItem>,
 }
 
 #[derive(Debug, Clone)]
@@ -154,6 +198,8 @@ pub enum AnalyzedExpr {
         op: BinaryOperator,
         right: Box<AnalyzedExpr>,
         result_type: DataType,
+        /// Collation string comparisons run under; the default for other operators
+        collation: Collation,
     },
     UnaryOp {
         op: UnaryOperator,
@@ -161,15 +207,43 @@ pub enum AnalyzedExpr {
         result_type: DataType,
     },
     Aggregate(AnalyzedAggregate),
+    Function {
+        func: ScalarFunction,
+        args: Vec<AnalyzedExpr>,
+        result_type: DataType,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct AnalyzedAggregate {
     pub func: AggregateFunction,
     pub arg: AnalyzedAggregateArg,
+    /// string_agg's delimiter, or the percentile fraction
+    pub extra_arg: Option<Box<AnalyzedExpr>>,
+    /// Order in which the group's rows are fed to the aggregate
+    pub order_by: Vec<AnalyzedOrderBy>,
+    /// Only rows for which this is true are aggregated
+    pub filter: Option<Box<AnalyzedExpr>>,
     pub result_type: DataType,
 }
 
+#[derive(Debug, Clone)]
+pub struct AnalyzedOrderBy {
+    pub expr: AnalyzedExpr,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl AnalyzedAggregate {
+    /// Collation of the argument, which min() and max() compare under
+    pub fn arg_collation(&self) -> Collation {
+        match &self.arg {
+            AnalyzedAggregateArg::Expr(arg) => arg.collation(),
+            AnalyzedAggregateArg::Asterisk => Collation::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum AnalyzedAggregateArg {
     Asterisk,
@@ -185,9 +259,14 @@ pub struct AnalyzedLiteral {
 #[derive(Debug, Clone)]
 pub enum LiteralValue {
     Integer(i64),
+    Numeric(Numeric),
     String(String),
     Boolean(bool),
     Null,
+    /// Constant already converted to its type, e.g. DATE '2024-01-01'
+    Typed(Value),
+    /// An unbound $n, seen only while a prepared statement is described
+    Parameter(usize),
 }
 
 impl AnalyzedExpr {
@@ -198,13 +277,105 @@ impl AnalyzedExpr {
             AnalyzedExpr::BinaryOp { result_type, .. } => result_type,
             AnalyzedExpr::UnaryOp { result_type, .. } => result_type,
             AnalyzedExpr::Aggregate(agg) => &agg.result_type,
+            AnalyzedExpr::Function { result_type, .. } => result_type,
+        }
+    }
+
+    /// Collation the values of this expression compare, group and index under
+    pub fn collation(&self) -> Collation {
+        self.collation_derivation().collation()
+    }
+
+    fn collation_derivation(&self) -> CollationDerivation {
+        if !self.data_type().is_collatable() {
+            return CollationDerivation::None;
+        }
+        match self {
+            AnalyzedExpr::ColumnRef(col) => CollationDerivation::Implicit(col.collation),
+            AnalyzedExpr::Function {
+                func: ScalarFunction::Collate(collation),
+                ..
+            } => CollationDerivation::Explicit(*collation),
+            // Results such as substring(name, 1, 3) keep their arguments'
+            // collation; conflicting ones leave it undetermined
+            AnalyzedExpr::Function { args, .. } => args
+                .iter()
+                .try_fold(CollationDerivation::None, |derivation, arg| {
+                    derivation.combine(arg.collation_derivation())
+                })
+                .unwrap_or(CollationDerivation::None),
+            _ => CollationDerivation::None,
         }
     }
+
+    /// Whether the expression calls a function that can return different
+    /// results for the same arguments
+    pub fn is_volatile(&self) -> bool {
+        match self {
+            AnalyzedExpr::Function { func, args, .. } => {
+                !func.is_immutable() || args.iter().any(|a| a.is_volatile())
+            }
+            AnalyzedExpr::BinaryOp { left, right, .. } => left.is_volatile() || right.is_volatile(),
+            AnalyzedExpr::UnaryOp { expr, .. } => expr.is_volatile(),
+            AnalyzedExpr::Aggregate(_) | AnalyzedExpr::Literal(_) | AnalyzedExpr::ColumnRef(_) => {
+                false
+            }
+        }
+    }
+
+    pub fn contains_aggregate(&self) -> bool {
+        match self {
+            AnalyzedExpr::Aggregate(_) => true,
+            AnalyzedExpr::BinaryOp { left, right, .. } => {
+                left.contains_aggregate() || right.contains_aggregate()
+            }
+            AnalyzedExpr::UnaryOp { expr, .. } => expr.contains_aggregate(),
+            AnalyzedExpr::Function { args, .. } => args.iter().any(|a| a.contains_aggregate()),
+            AnalyzedExpr::Literal(_) | AnalyzedExpr::ColumnRef(_) => false,
+        }
+    }
+}
+
+/// Where an expression's collation comes from. As in PostgreSQL, a COLLATE
+/// clause wins over the implicit collation of a column, and constants have
+/// none of their own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CollationDerivation {
+    None,
+    Implicit(Collation),
+    Explicit(Collation),
+}
+
+impl CollationDerivation {
+    fn collation(self) -> Collation {
+        match self {
+            CollationDerivation::Implicit(c) | CollationDerivation::Explicit(c) => c,
+            CollationDerivation::None => Collation::default(),
+        }
+    }
+
+    /// Collation of an operation on two operands with these derivations
+    fn combine(self, other: CollationDerivation) -> Result<CollationDerivation> {
+        use CollationDerivation::*;
+        Ok(match (self, other) {
+            (Explicit(a), Explicit(b)) if a != b => bail!(
+                "collation mismatch between explicit collations \"{}\" and \"{}\"",
+                a.name(),
+                b.name()
+            ),
+            (Explicit(a), _) | (_, Explicit(a)) => Explicit(a),
+            (Implicit(a), Implicit(b)) if a != b => bail!(
+                "could not determine which collation to use for string comparison \
+                 (use the COLLATE clause to set the collation explicitly)"
+            ),
+            (Implicit(a), _) | (_, Implicit(a)) => Implicit(a),
+            (None, None) => None,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
 struct ScopeEntry {
-    #[allow(dead_code)]
     name: String,
     rte_index: usize,
 }
@@ -226,10 +397,23 @@ impl Scope {
     }
 }
 
+/// A $n parameter of a prepared statement
+#[derive(Debug, Clone, Default)]
+pub struct Parameter {
+    /// Declared by the client or inferred from the context of its first use
+    pub data_type: Option<DataType>,
+    /// The value bound to it, None while the statement is only described
+    pub value: Option<Value>,
+}
+
 pub struct Analyzer<'a> {
     catalog: &'a Catalog,
     range_table: Vec<RangeTableEntry>,
     scopes: Vec<Scope>,
+    /// GROUP BY expressions of the SELECT being analyzed, for GROUPING()
+    group_by: Vec<AnalyzedExpr>,
+    /// Parameters $1, $2, ... of a prepared statement
+    parameters: Vec<Parameter>,
 }
 
 impl<'a> Analyzer<'a> {
@@ -238,6 +422,8 @@ impl<'a> Analyzer<'a> {
             catalog,
             range_table: Vec::new(),
             scopes: Vec::new(),
+            group_by: Vec::new(),
+            parameters: Vec::new(),
         }
     }
 
@@ -272,7 +458,16 @@ impl<'a> Analyzer<'a> {
             Statement::Delete(s) => self.analyze_delete(s),
             Statement::Update(s) => self.analyze_update(s),
             // Transaction control and utility statements are handled before analyze
-            Statement::Begin | Statement::Commit | Statement::Rollback | Statement::Checkpoint => {
+            Statement::Begin
+            | Statement::Commit
+            | Statement::Rollback
+            | Statement::Checkpoint
+            | Statement::Vacuum(_)
+            | Statement::Set { .. }
+            | Statement::Show(_)
+            | Statement::CreateRole(_)
+            | Statement::AlterRole(_)
+            | Statement::Copy(_) => {
                 anyhow::bail!("transaction control statements should be handled before analyze")
             }
         }
@@ -287,9 +482,88 @@ impl<'a> Analyzer<'a> {
         let mut join_conditions = Vec::new();
         let first_rte_index = self.analyze_from_clause(&stmt.from, &mut join_conditions)?;
 
+        // Resolve GROUP BY clause first, so GROUPING() can refer to it
+        let grouping_sets = self.analyze_group_by(&stmt.group_by)?;
+
         // Resolve columns (SELECT list)
+        let select_items = self.analyze_select_list(&stmt.columns)?;
+
+        // Resolve WHERE clause
+        let where_clause = if let Some(expr) = &stmt.where_clause {
+            Some(self.analyze_expr(expr)?)
+        } else {
+            None
+        };
+
+        // Resolve HAVING clause
+        let having = if let Some(expr) = &stmt.having {
+            Some(self.analyze_expr(expr)?)
+        } else {
+            None
+        };
+
+        self.pop_scope();
+
+        Ok(AnalyzedStatement::Select(AnalyzedSelectStatement {
+            range_table: self.range_table.clone(),
+            select_items,
+            from_rte_index: first_rte_index,
+            join_conditions,
+            where_clause,
+            group_by: std::mem::take(&mut self.group_by),
+            grouping_sets,
+            having,
+        }))
+    }
+
+    /// Expand GROUP BY into its grouping sets, collecting the distinct
+    /// expressions into self.group_by
+    fn analyze_group_by(&mut self, items: &[GroupByItem]) -> Result<Vec<Vec<usize>>> {
+        self.group_by.clear();
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Cross product of each item's sets
+        let mut sets: Vec<Vec<&Expr>> = vec![Vec::new()];
+        for item in items {
+            let item_sets = expand_grouping_sets(item)?;
+            sets = sets
+                .iter()
+                .flat_map(|set| {
+                    item_sets.iter().map(move |item_set| {
+                        set.iter().chain(item_set).copied().collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            if sets.len() > MAX_GROUPING_SETS {
+                bail!("too many grouping sets present (maximum {MAX_GROUPING_SETS})");
+            }
+        }
+        sets.into_iter()
+            .map(|set| {
+                let mut indices = Vec::new();
+                for expr in set {
+                    let expr = self.analyze_expr(expr)?;
+                    let index = match self.group_by.iter().position(|e| exprs_equal(e, &expr)) {
+                        Some(index) => index,
+                        None => {
+                            self.group_by.push(expr);
+                            self.group_by.len() - 1
+                        }
+                    };
+                    if !indices.contains(&index) {
+                        indices.push(index);
+                    }
+                }
+                Ok(indices)
+            })
+            .collect()
+    }
+
+    /// Resolve a SELECT (or RETURNING) list, expanding * to every column in scope
+    fn analyze_select_list(&mut self, columns: &[SelectColumn]) -> Result<Vec<AnalyzedSelectItem>> {
         let mut select_items = Vec::new();
-        for col in &stmt.columns {
+        for col in columns {
             match col {
                 SelectColumn::Asterisk => {
                     // Expand * to all columns from all tables in scope
@@ -302,6 +576,7 @@ impl<'a> Analyzer<'a> {
                                     column_index: col_idx,
                                     column_name: col_def.name.clone(),
                                     data_type: col_def.data_type.clone(),
+                                    collation: col_def.collation,
                                 }),
                                 alias: None,
                             });
@@ -317,39 +592,16 @@ impl<'a> Analyzer<'a> {
                 }
             }
         }
+        Ok(select_items)
+    }
 
-        // Resolve WHERE clause
-        let where_clause = if let Some(expr) = &stmt.where_clause {
-            Some(self.analyze_expr(expr)?)
-        } else {
-            None
-        };
-
-        // Resolve GROUP BY clause
-        let group_by: Vec<AnalyzedExpr> = stmt
-            .group_by
-            .iter()
-            .map(|expr| self.analyze_expr(expr))
-            .collect::<Result<Vec<_>>>()?;
-
-        // Resolve HAVING clause
-        let having = if let Some(expr) = &stmt.having {
-            Some(self.analyze_expr(expr)?)
-        } else {
-            None
-        };
-
-        self.pop_scope();
-
-        Ok(AnalyzedStatement::Select(AnalyzedSelectStatement {
-            range_table: self.range_table.clone(),
-            select_items,
-            from_rte_index: first_rte_index,
-            join_conditions,
-            where_clause,
-            group_by,
-            having,
-        }))
+    /// Resolve a RETURNING list against the target table, which must already be in scope
+    fn analyze_returning(&mut self, columns: &[SelectColumn]) -> Result<Vec<AnalyzedSelectItem>> {
+        let items = self.analyze_select_list(columns)?;
+        if items.iter().any(|item| item.expr.contains_aggregate()) {
+            bail!("aggregate functions are not allowed in RETURNING");
+        }
+        Ok(items)
     }
 
     /// Analyze FROM clause recursively, returning the first RTE index
@@ -390,10 +642,14 @@ impl<'a> Analyzer<'a> {
 
     /// Analyze a single table reference and add it to the range table
     fn analyze_table_ref(&mut self, table_ref: &TableRef) -> Result<usize> {
+        if let Some(args) = &table_ref.function_args {
+            return self.analyze_function_ref(table_ref, args);
+        }
         let table = self
             .catalog
             .get_table(&table_ref.name)
             .ok_or_else(|| anyhow::anyhow!("table '{}' not found", table_ref.name))?;
+        check_table_access(self.catalog, &table, TableAccess::Read)?;
         let table_id = self.catalog.get_table_id(&table_ref.name).unwrap();
 
         let output_columns: Vec<OutputColumn> = table
@@ -403,6 +659,7 @@ impl<'a> Analyzer<'a> {
                 name: c.name.clone(),
                 data_type: c.data_type.clone(),
                 nullable: c.nullable,
+                collation: c.collation,
             })
             .collect();
 
@@ -421,11 +678,45 @@ impl<'a> Analyzer<'a> {
         Ok(rte_index)
     }
 
+    /// A function call in FROM; only unnest(array) is supported
+    fn analyze_function_ref(&mut self, table_ref: &TableRef, args: &[Expr]) -> Result<usize> {
+        if !table_ref.name.eq_ignore_ascii_case("unnest") {
+            bail!("function {}() is not supported in FROM", table_ref.name);
+        }
+        let [arg] = args else {
+            bail!("unnest() takes exactly one argument");
+        };
+        // Like any function in FROM it is LATERAL: the array may refer to
+        // the FROM items before it, which are in scope by now, and is
+        // evaluated for each of their rows
+        let array = self
+            .analyze_expr(arg)
+            .map_err(|e| anyhow::anyhow!("invalid unnest() argument: {e}"))?;
+        let DataType::Array(element_type) = array.data_type().clone() else {
+            bail!("function unnest({}) does not exist", array.data_type().name());
+        };
+
+        let name = table_ref.alias.clone().unwrap_or_else(|| "unnest".to_string());
+        let collation = array.collation();
+        let rte_index = self.add_rte(
+            TableSource::Unnest { array },
+            vec![OutputColumn {
+                name: name.clone(),
+                data_type: *element_type,
+                nullable: true,
+                collation,
+            }],
+        );
+        self.current_scope().add_rte(name, rte_index);
+        Ok(rte_index)
+    }
+
     fn analyze_insert(&mut self, stmt: &InsertStatement) -> Result<AnalyzedStatement> {
         let table = self
             .catalog
             .get_table(&stmt.table)
             .ok_or_else(|| anyhow::anyhow!("table '{}' not found", stmt.table))?;
+        check_table_access(self.catalog, &table, TableAccess::Modify)?;
         let table_id = self.catalog.get_table_id(&stmt.table).unwrap();
 
         // Check value count matches column count
@@ -440,8 +731,9 @@ impl<'a> Analyzer<'a> {
         // Analyze and type-check values
         let mut analyzed_values = Vec::new();
         for (i, value) in stmt.values.iter().enumerate() {
-            let analyzed_expr = self.analyze_expr(value)?;
             let expected_type = &table.columns[i].data_type;
+            let analyzed_expr = self.analyze_expr(value)?;
+            let analyzed_expr = self.resolve_unknown(analyzed_expr, expected_type)?;
             let actual_type = analyzed_expr.data_type();
 
             // Type check (allow NULL for nullable columns)
@@ -453,7 +745,7 @@ impl<'a> Analyzer<'a> {
                 if !table.columns[i].nullable {
                     bail!("column '{}' is not nullable", table.columns[i].name);
                 }
-            } else if actual_type != expected_type {
+            } else if !is_assignable(actual_type, expected_type) {
                 bail!(
                     "type mismatch for column '{}': expected {:?}, got {:?}",
                     table.columns[i].name,
@@ -465,27 +757,191 @@ impl<'a> Analyzer<'a> {
             analyzed_values.push(analyzed_expr);
         }
 
+        let on_conflict = match &stmt.on_conflict {
+            Some(on_conflict) => Some(self.analyze_on_conflict(&stmt.table, on_conflict)?),
+            None => None,
+        };
+
+        // RETURNING sees only the row that was inserted (or updated by ON CONFLICT)
+        self.range_table.clear();
+        let output_columns: Vec<OutputColumn> = table
+            .columns
+            .iter()
+            .map(|c| OutputColumn {
+                name: c.name.clone(),
+                data_type: c.data_type.clone(),
+                nullable: c.nullable,
+                collation: c.collation,
+            })
+            .collect();
+        let rte_index = self.add_rte(
+            TableSource::BaseTable {
+                table_id,
+                table_name: stmt.table.clone(),
+            },
+            output_columns,
+        );
+        self.push_scope();
+        self.current_scope().add_rte(stmt.table.clone(), rte_index);
+        let returning = self.analyze_returning(&stmt.returning)?;
+        self.pop_scope();
+
         Ok(AnalyzedStatement::Insert(AnalyzedInsertStatement {
             table_id,
             table_name: stmt.table.clone(),
             values: analyzed_values,
+            on_conflict,
+            returning,
         }))
     }
 
+    fn analyze_on_conflict(
+        &mut self,
+        table_name: &str,
+        on_conflict: &OnConflict,
+    ) -> Result<AnalyzedOnConflict> {
+        let table = self
+            .catalog
+            .get_table(table_name)
+            .ok_or_else(|| anyhow::anyhow!("table '{table_name}' not found"))?;
+
+        // Resolve the conflict target to the unique indexes that arbitrate it
+        let arbiter_index_ids = if on_conflict.target.is_empty() {
+            if matches!(on_conflict.action, OnConflictAction::DoUpdate { .. }) {
+                bail!("ON CONFLICT DO UPDATE requires a conflict target");
+            }
+            Vec::new()
+        } else {
+            let mut target_ids = Vec::new();
+            for col_name in &on_conflict.target {
+                let col_idx = table
+                    .get_column_id(col_name)
+                    .ok_or_else(|| anyhow::anyhow!("column '{col_name}' not found"))?;
+                target_ids.push(col_idx);
+            }
+            target_ids.sort_unstable();
+
+            let arbiters: Vec<u32> = self
+                .catalog
+                .get_indexes_for_table(table.table_id)
+                .into_iter()
+                .filter(|index| {
+                    let Some(mut index_ids) = index.column_ids() else {
+                        return false;
+                    };
+                    index_ids.sort_unstable();
+                    index.is_unique && index_ids == target_ids
+                })
+                .map(|index| index.index_id)
+                .collect();
+            if arbiters.is_empty() {
+                bail!("there is no unique constraint matching the ON CONFLICT specification");
+            }
+            arbiters
+        };
+
+        let action = match &on_conflict.action {
+            OnConflictAction::DoNothing => AnalyzedOnConflictAction::DoNothing,
+            OnConflictAction::DoUpdate {
+                assignments,
+                where_clause,
+            } => {
+                self.range_table.clear();
+                let output_columns: Vec<OutputColumn> = table
+                    .columns
+                    .iter()
+                    .map(|c| OutputColumn {
+                        name: c.name.clone(),
+                        data_type: c.data_type.clone(),
+                        nullable: c.nullable,
+                collation: c.collation,
+                    })
+                    .collect();
+                let target_rte = self.add_rte(
+                    TableSource::BaseTable {
+                        table_id: table.table_id,
+                        table_name: table_name.to_string(),
+                    },
+                    output_columns.clone(),
+                );
+                let excluded_rte = self.add_rte(
+                    TableSource::BaseTable {
+                        table_id: table.table_id,
+                        table_name: "excluded".to_string(),
+                    },
+                    output_columns,
+                );
+
+                self.push_scope();
+                self.current_scope()
+                    .add_rte(table_name.to_string(), target_rte);
+                self.current_scope()
+                    .add_rte("excluded".to_string(), excluded_rte);
+
+                let mut analyzed_assignments = Vec::new();
+                for Assignment { column, value } in assignments {
+                    let column_index = table
+                        .get_column_id(column)
+                        .ok_or_else(|| anyhow::anyhow!("column '{column}' not found"))?;
+                    let expected_type = &table.columns[column_index].data_type;
+                    let analyzed_value = self.analyze_expr(value)?;
+                    let analyzed_value = self.resolve_unknown(analyzed_value, expected_type)?;
+                    let actual_type = analyzed_value.data_type();
+                    if !is_assignable(actual_type, expected_type) {
+                        bail!(
+                            "type mismatch for column '{column}': expected {expected_type:?}, got {actual_type:?}"
+                        );
+                    }
+
+                    analyzed_assignments.push(AnalyzedAssignment {
+                        column_index,
+                        value: analyzed_value,
+                    });
+                }
+
+                let where_clause = if let Some(expr) = where_clause {
+                    Some(self.analyze_expr(expr)?)
+                } else {
+                    None
+                };
+
+                self.pop_scope();
+
+                AnalyzedOnConflictAction::DoUpdate {
+                    assignments: analyzed_assignments,
+                    where_clause,
+                }
+            }
+        };
+
+        Ok(AnalyzedOnConflict {
+            arbiter_index_ids,
+            action,
+        })
+    }
+
     fn analyze_create_table(&mut self, stmt: &CreateTableStatement) -> Result<AnalyzedStatement> {
         // Check table doesn't exist
         if self.catalog.get_table(&stmt.table).is_some() {
             bail!("table '{}' already exists", stmt.table);
         }
 
-        let analyzed_columns: Vec<AnalyzedColumnDef> = stmt
-            .columns
-            .iter()
-            .map(|c| AnalyzedColumnDef {
-                name: c.name.clone(),
-                data_type: convert_ast_data_type(&c.data_type),
-            })
-            .collect();
+        let mut analyzed_columns = Vec::new();
+        for column in &stmt.columns {
+            let data_type = convert_ast_data_type(&column.data_type);
+            let collation = match &column.collation {
+                Some(_) if !data_type.is_collatable() => {
+                    bail!("collations are not supported by type {}", data_type.name())
+                }
+                Some(name) => Collation::from_name(name)?,
+                None => Collation::default(),
+            };
+            analyzed_columns.push(AnalyzedColumnDef {
+                name: column.name.clone(),
+                data_type,
+                collation,
+            });
+        }
 
         Ok(AnalyzedStatement::CreateTable(
             AnalyzedCreateTableStatement {
@@ -501,33 +957,80 @@ impl<'a> Analyzer<'a> {
             .catalog
             .get_table(&stmt.table_name)
             .ok_or_else(|| anyhow::anyhow!("table '{}' not found", stmt.table_name))?;
+        check_table_access(self.catalog, &table, TableAccess::Modify)?;
 
         // Check index doesn't already exist
         if self.catalog.get_index(&stmt.index_name).is_some() {
             bail!("index '{}' already exists", stmt.index_name);
         }
 
-        // Resolve column names to column indices
-        let mut column_ids = Vec::new();
-        for col_name in &stmt.column_names {
-            let col_idx = table
-                .columns
-                .iter()
-                .position(|c| c.name == *col_name)
-                .ok_or_else(|| anyhow::anyhow!("column '{}' not found in table", col_name))?;
-            column_ids.push(col_idx);
+        // Resolve column names to column indices; anything else is an expression key
+        let mut columns = Vec::new();
+        let mut expressions = Vec::new();
+        for key in &stmt.columns {
+            if let Expr::Column { table: None, name } = key {
+                let col_idx = table
+                    .columns
+                    .iter()
+                    .position(|c| c.name == *name)
+                    .ok_or_else(|| anyhow::anyhow!("column '{}' not found in table", name))?;
+                columns.push(IndexColumn::Column(col_idx));
+            } else {
+                let expr = self.analyze_index_expression(&table, key)?;
+                columns.push(IndexColumn::Expression(expr));
+                expressions.push(key.to_string());
+            }
         }
 
         Ok(AnalyzedStatement::CreateIndex(
             AnalyzedCreateIndexStatement {
                 index_name: stmt.index_name.clone(),
+                unique: stmt.unique,
                 table_id: table.table_id,
                 table_name: stmt.table_name.clone(),
-                column_ids,
+                columns,
+                expressions: (!expressions.is_empty()).then(|| expressions.join(", ")),
             },
         ))
     }
 
+    /// Analyze an index key expression over the columns of `table`. Like
+    /// PostgreSQL, it must not contain aggregates or functions whose result
+    /// changes between calls (now(), current_date, ...).
+    pub fn analyze_index_expression(&mut self, table: &TableDef, expr: &Expr) -> Result<AnalyzedExpr> {
+        self.range_table.clear();
+        let output_columns = table
+            .columns
+            .iter()
+            .map(|c| OutputColumn {
+                name: c.name.clone(),
+                data_type: c.data_type.clone(),
+                nullable: c.nullable,
+                collation: c.collation,
+            })
+            .collect();
+        let rte_index = self.add_rte(
+            TableSource::BaseTable {
+                table_id: table.table_id,
+                table_name: table.name.clone(),
+            },
+            output_columns,
+        );
+        self.push_scope();
+        self.current_scope().add_rte(table.name.clone(), rte_index);
+        let analyzed = self.analyze_expr(expr);
+        self.pop_scope();
+        let analyzed = analyzed?;
+
+        if analyzed.contains_aggregate() {
+            bail!("aggregate functions are not allowed in index expressions");
+        }
+        if analyzed.is_volatile() {
+            bail!("functions in index expression must be marked IMMUTABLE");
+        }
+        Ok(analyzed)
+    }
+
     fn analyze_delete(&mut self, stmt: &DeleteStatement) -> Result<AnalyzedStatement> {
         self.range_table.clear();
 
@@ -535,6 +1038,7 @@ impl<'a> Analyzer<'a> {
             .catalog
             .get_table(&stmt.table)
             .ok_or_else(|| anyhow::anyhow!("table '{}' not found", stmt.table))?;
+        check_table_access(self.catalog, &table, TableAccess::Modify)?;
         let table_id = self.catalog.get_table_id(&stmt.table).unwrap();
 
         let output_columns: Vec<OutputColumn> = table
@@ -544,6 +1048,7 @@ impl<'a> Analyzer<'a> {
                 name: c.name.clone(),
                 data_type: c.data_type.clone(),
                 nullable: c.nullable,
+                collation: c.collation,
             })
             .collect();
 
@@ -557,6 +1062,7 @@ impl<'a> Analyzer<'a> {
 
         self.push_scope();
         self.current_scope().add_rte(stmt.table.clone(), rte_index);
+        self.analyze_joined_tables(&stmt.using)?;
 
         let where_clause = if let Some(expr) = &stmt.where_clause {
             Some(self.analyze_expr(expr)?)
@@ -564,12 +1070,15 @@ impl<'a> Analyzer<'a> {
             None
         };
 
+        let returning = self.analyze_returning(&stmt.returning)?;
+
         self.pop_scope();
 
         Ok(AnalyzedStatement::Delete(AnalyzedDeleteStatement {
             table_id,
             range_table: self.range_table.clone(),
             where_clause,
+            returning,
         }))
     }
 
@@ -580,6 +1089,7 @@ impl<'a> Analyzer<'a> {
             .catalog
             .get_table(&stmt.table)
             .ok_or_else(|| anyhow::anyhow!("table '{}' not found", stmt.table))?;
+        check_table_access(self.catalog, &table, TableAccess::Modify)?;
         let table_id = self.catalog.get_table_id(&stmt.table).unwrap();
 
         let output_columns: Vec<OutputColumn> = table
@@ -589,6 +1099,7 @@ impl<'a> Analyzer<'a> {
                 name: c.name.clone(),
                 data_type: c.data_type.clone(),
                 nullable: c.nullable,
+                collation: c.collation,
             })
             .collect();
 
@@ -602,6 +1113,7 @@ impl<'a> Analyzer<'a> {
 
         self.push_scope();
         self.current_scope().add_rte(stmt.table.clone(), rte_index);
+        self.analyze_joined_tables(&stmt.from)?;
 
         // Analyze assignments
         let mut analyzed_assignments = Vec::new();
@@ -612,12 +1124,12 @@ impl<'a> Analyzer<'a> {
                 .position(|c| c.name == *column)
                 .ok_or_else(|| anyhow::anyhow!("column '{column}' not found"))?;
 
-            let analyzed_value = self.analyze_expr(value)?;
-
             // Type check
             let expected_type = &table.columns[column_index].data_type;
+            let analyzed_value = self.analyze_expr(value)?;
+            let analyzed_value = self.resolve_unknown(analyzed_value, expected_type)?;
             let actual_type = analyzed_value.data_type();
-            if actual_type != expected_type {
+            if !is_assignable(actual_type, expected_type) {
                 bail!(
                     "type mismatch for column '{column}': expected {expected_type:?}, got {actual_type:?}"
                 );
@@ -635,6 +1147,8 @@ impl<'a> Analyzer<'a> {
             None
         };
 
+        let returning = self.analyze_returning(&stmt.returning)?;
+
         self.pop_scope();
 
         Ok(AnalyzedStatement::Update(AnalyzedUpdateStatement {
@@ -642,23 +1156,50 @@ impl<'a> Analyzer<'a> {
             range_table: self.range_table.clone(),
             assignments: analyzed_assignments,
             where_clause,
+            returning,
         }))
     }
 
+    /// Add the UPDATE ... FROM / DELETE ... USING tables after the target table (RTE 0)
+    fn analyze_joined_tables(&mut self, tables: &[TableRef]) -> Result<()> {
+        for table_ref in tables {
+            let scope_name = table_ref.alias.as_deref().unwrap_or(&table_ref.name);
+            if self.current_scope().entries.iter().any(|e| e.name == scope_name) {
+                bail!("table name \"{scope_name}\" specified more than once");
+            }
+            self.analyze_table_ref(table_ref)?;
+        }
+        Ok(())
+    }
+
     fn analyze_expr(&mut self, expr: &Expr) -> Result<AnalyzedExpr> {
         match expr {
             Expr::Literal(lit) => self.analyze_literal(lit),
+            Expr::Parameter(n) => self.analyze_parameter(*n),
             Expr::Column { table, name } => self.analyze_column(table.as_deref(), name),
             Expr::BinaryOp { left, op, right } => {
-                let analyzed_left = self.analyze_expr(left)?;
-                let analyzed_right = self.analyze_expr(right)?;
+                let mut analyzed_left = self.analyze_expr(left)?;
+                let mut analyzed_right = self.analyze_expr(right)?;
+                // A string literal next to a date/time operand takes its type from context
+                if let Some(target) = unknown_literal_target(op, analyzed_right.data_type()) {
+                    analyzed_left = self.resolve_unknown(analyzed_left, &target)?;
+                }
+                if let Some(target) = unknown_literal_target(op, analyzed_left.data_type()) {
+                    analyzed_right = self.resolve_unknown(analyzed_right, &target)?;
+                }
                 let result_type = self.infer_binary_op_type(op, &analyzed_left, &analyzed_right)?;
+                let collation = if is_comparison(op) {
+                    comparison_collation(&analyzed_left, &analyzed_right)?
+                } else {
+                    Collation::default()
+                };
 
                 Ok(AnalyzedExpr::BinaryOp {
                     left: Box::new(analyzed_left),
                     op: op.clone(),
                     right: Box::new(analyzed_right),
                     result_type,
+                    collation,
                 })
             }
             Expr::UnaryOp { op, expr } => {
@@ -671,46 +1212,393 @@ impl<'a> Analyzer<'a> {
                     result_type,
                 })
             }
-            Expr::Aggregate { func, arg } => {
+            Expr::Aggregate {
+                func,
+                arg,
+                extra_arg,
+                order_by,
+                filter,
+            } => {
                 let analyzed_arg = match arg.as_ref() {
                     AggregateArg::Asterisk => AnalyzedAggregateArg::Asterisk,
                     AggregateArg::Expr(e) => {
                         AnalyzedAggregateArg::Expr(Box::new(self.analyze_expr(e)?))
                     }
                 };
+                let extra_arg = match extra_arg {
+                    Some(e) => Some(Box::new(self.analyze_expr(e)?)),
+                    None => None,
+                };
 
-                let result_type = match func {
-                    AggregateFunction::Count => DataType::Int,
-                    AggregateFunction::Sum => DataType::Int,
-                    AggregateFunction::Avg => DataType::Int,
-                    AggregateFunction::Min | AggregateFunction::Max => {
-                        // For MIN/MAX, result type depends on argument type
-                        match &analyzed_arg {
-                            AnalyzedAggregateArg::Asterisk => DataType::Int,
-                            AnalyzedAggregateArg::Expr(e) => e.data_type().clone(),
+                let arg_type = match &analyzed_arg {
+                    AnalyzedAggregateArg::Asterisk => DataType::Int,
+                    AnalyzedAggregateArg::Expr(e) => e.data_type().clone(),
+                };
+                let extra_type = extra_arg.as_ref().map(|e| e.data_type());
+                let result_type = match (func, extra_type) {
+                    (AggregateFunction::StringAgg, Some(delimiter))
+                        if arg_type.is_string() && delimiter.is_string() =>
+                    {
+                        DataType::Text
+                    }
+                    (AggregateFunction::StringAgg, Some(DataType::Bytea))
+                        if arg_type == DataType::Bytea =>
+                    {
+                        DataType::Bytea
+                    }
+                    (AggregateFunction::PercentileCont, Some(fraction))
+                        if arg_type.is_numeric() =>
+                    {
+                        percentile_result_type(fraction, DataType::Double)?
+                    }
+                    (AggregateFunction::PercentileDisc, Some(fraction)) => {
+                        percentile_result_type(fraction, arg_type.clone())?
+                    }
+                    (_, Some(_)) | (AggregateFunction::StringAgg, None) => {
+                        let mut types = vec![arg_type.name()];
+                        types.extend(extra_type.map(|t| t.name()));
+                        if func.is_ordered_set() {
+                            types.reverse();
                         }
+                        bail!("function {func}({}) does not exist", types.join(", "))
+                    }
+                    (AggregateFunction::Count, None) => DataType::BigInt,
+                    // SUM of integers is BIGINT; AVG of integers is NUMERIC
+                    (AggregateFunction::Sum, None) => match arg_type {
+                        DataType::Real | DataType::Double => arg_type,
+                        DataType::Numeric(_) => DataType::Numeric(None),
+                        _ => DataType::BigInt,
+                    },
+                    (AggregateFunction::Avg, None) => match arg_type {
+                        DataType::Real | DataType::Double => DataType::Double,
+                        _ => DataType::Numeric(None),
+                    },
+                    // For MIN/MAX, result type depends on argument type
+                    (AggregateFunction::Min | AggregateFunction::Max, None) => arg_type,
+                    (AggregateFunction::JsonbAgg, None) => DataType::Jsonb,
+                    (AggregateFunction::ArrayAgg, None) => match arg_type {
+                        DataType::Array(_) => bail!("array_agg of arrays is not supported"),
+                        element => DataType::Array(Box::new(element)),
+                    },
+                    (AggregateFunction::BoolAnd | AggregateFunction::BoolOr, None)
+                        if arg_type == DataType::Bool =>
+                    {
+                        DataType::Bool
+                    }
+                    // Like AVG: floats give DOUBLE PRECISION, exact inputs NUMERIC
+                    (
+                        AggregateFunction::Stddev
+                        | AggregateFunction::StddevSamp
+                        | AggregateFunction::StddevPop
+                        | AggregateFunction::Variance
+                        | AggregateFunction::VarSamp
+                        | AggregateFunction::VarPop,
+                        None,
+                    ) if arg_type.is_numeric() => match arg_type {
+                        DataType::Real | DataType::Double => DataType::Double,
+                        _ => DataType::Numeric(None),
+                    },
+                    // The parser never produces these; see the Function arm
+                    (AggregateFunction::User(aggregate), None) => {
+                        bail!("unexpected user-defined aggregate {}", aggregate.name())
                     }
+                    (_, None) => bail!("function {func}({}) does not exist", arg_type.name()),
+                };
+
+                let order_by = order_by
+                    .iter()
+                    .map(|o| self.analyze_order_by(o))
+                    .collect::<Result<Vec<_>>>()?;
+                let filter = match filter {
+                    Some(condition) => {
+                        let condition = self.analyze_expr(condition)?;
+                        if *condition.data_type() != DataType::Bool {
+                            bail!(
+                                "argument of FILTER must be type boolean, not type {}",
+                                condition.data_type().name()
+                            );
+                        }
+                        Some(Box::new(condition))
+                    }
+                    None => None,
                 };
 
                 Ok(AnalyzedExpr::Aggregate(AnalyzedAggregate {
                     func: func.clone(),
                     arg: analyzed_arg,
+                    extra_arg,
+                    order_by,
+                    filter,
                     result_type,
                 }))
             }
+            Expr::Function { name, args } if name == "grouping" => {
+                if args.is_empty() || args.len() > 31 {
+                    bail!("GROUPING must have between 1 and 31 arguments");
+                }
+                let mut columns = Vec::new();
+                for arg in args {
+                    let arg = self.analyze_expr(arg)?;
+                    match self.group_by.iter().position(|e| exprs_equal(e, &arg)) {
+                        Some(index) => columns.push(index),
+                        None => bail!(
+                            "arguments to GROUPING must be grouping expressions of the associated query level"
+                        ),
+                    }
+                }
+                Ok(AnalyzedExpr::Function {
+                    func: ScalarFunction::Grouping(columns),
+                    args: Vec::new(),
+                    result_type: DataType::Int,
+                })
+            }
+            Expr::Function { name, args } if udf::is_aggregate(name) => {
+                let [arg] = args.as_slice() else {
+                    bail!("aggregate {name} takes exactly one argument");
+                };
+                let arg = self.analyze_expr(arg)?;
+                let Some((aggregate, result_type)) = udf::resolve_aggregate(name, arg.data_type())
+                else {
+                    bail!("function {name}({}) does not exist", arg.data_type().name());
+                };
+                Ok(AnalyzedExpr::Aggregate(AnalyzedAggregate {
+                    func: AggregateFunction::User(aggregate),
+                    arg: AnalyzedAggregateArg::Expr(Box::new(arg)),
+                    extra_arg: None,
+                    order_by: Vec::new(),
+                    filter: None,
+                    result_type,
+                }))
+            }
+            Expr::Function { name, args } => {
+                let analyzed_args = args
+                    .iter()
+                    .map(|arg| self.analyze_expr(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                let arg_types: Vec<DataType> =
+                    analyzed_args.iter().map(|a| a.data_type().clone()).collect();
+                let (func, result_type) = ScalarFunction::resolve(name, &arg_types)?;
+                Ok(AnalyzedExpr::Function {
+                    func,
+                    args: analyzed_args,
+                    result_type,
+                })
+            }
+            Expr::Array(elements) => self.analyze_array(elements),
+            Expr::Cast { expr, data_type } => {
+                let target = convert_ast_data_type(data_type);
+                let mut analyzed = self.analyze_expr(expr)?;
+                // $1::date makes $1 a date
+                if matches!(
+                    analyzed,
+                    AnalyzedExpr::Literal(AnalyzedLiteral {
+                        value: LiteralValue::Parameter(_),
+                        ..
+                    })
+                ) {
+                    analyzed = self.resolve_unknown(analyzed, &target)?;
+                }
+                // Constants are converted now, so '2024-01-01'::date is a
+                // literal of its type (and can be used as an index key)
+                if let AnalyzedExpr::Literal(lit) = &analyzed {
+                    let value = cast_value(literal_to_value(lit), &target)?;
+                    return Ok(AnalyzedExpr::Literal(AnalyzedLiteral {
+                        value: LiteralValue::Typed(value),
+                        data_type: target,
+                    }));
+                }
+                if !is_castable(analyzed.data_type(), &target) {
+                    bail!(
+                        "cannot cast type {} to {}",
+                        analyzed.data_type().name(),
+                        target.name()
+                    );
+                }
+                Ok(AnalyzedExpr::Function {
+                    func: ScalarFunction::Cast(target.clone()),
+                    args: vec![analyzed],
+                    result_type: target,
+                })
+            }
+            Expr::Collate { expr, collation } => {
+                let analyzed = self.analyze_expr(expr)?;
+                if !analyzed.data_type().is_collatable() {
+                    bail!("collations are not supported by type {}", analyzed.data_type().name());
+                }
+                let result_type = analyzed.data_type().clone();
+                Ok(AnalyzedExpr::Function {
+                    func: ScalarFunction::Collate(Collation::from_name(collation)?),
+                    args: vec![analyzed],
+                    result_type,
+                })
+            }
+            Expr::Subscript { expr, index } => {
+                let array = self.analyze_expr(expr)?;
+                let index = self.analyze_expr(index)?;
+                let DataType::Array(element_type) = array.data_type().clone() else {
+                    bail!(
+                        "cannot subscript type {} because it is not an array",
+                        array.data_type().name()
+                    );
+                };
+                if !matches!(index.data_type(), DataType::SmallInt | DataType::Int | DataType::BigInt) {
+                    bail!("array subscript must have type integer");
+                }
+                Ok(AnalyzedExpr::Function {
+                    func: ScalarFunction::ArraySubscript,
+                    args: vec![array, index],
+                    result_type: *element_type,
+                })
+            }
+            Expr::Any { left, op, right } => {
+                let mut left = self.analyze_expr(left)?;
+                let mut right = self.analyze_expr(right)?;
+                // '{a,b}' on the right is an array of the left operand's type
+                right = self.resolve_unknown(right, &DataType::Array(Box::new(left.data_type().clone())))?;
+                let DataType::Array(element_type) = right.data_type().clone() else {
+                    bail!("op ANY/ALL (array) requires array on right side");
+                };
+                if let Some(target) = unknown_literal_target(op, &element_type) {
+                    left = self.resolve_unknown(left, &target)?;
+                }
+                // Type-check as a comparison with a single element
+                let element = AnalyzedExpr::Literal(AnalyzedLiteral {
+                    value: LiteralValue::Null,
+                    data_type: *element_type,
+                });
+                if self.infer_binary_op_type(op, &left, &element)? != DataType::Bool {
+                    bail!("op ANY/ALL (array) requires operator to yield boolean");
+                }
+                let collation = comparison_collation(&left, &right)?;
+                Ok(AnalyzedExpr::Function {
+                    func: ScalarFunction::Any(op.clone(), collation),
+                    args: vec![left, right],
+                    result_type: DataType::Bool,
+                })
+            }
         }
     }
 
+    /// ARRAY[...]: elements share one type, with numbers widened to the
+    /// widest element type and string literals taking the type of the others
+    fn analyze_order_by(&mut self, order_by: &OrderByExpr) -> Result<AnalyzedOrderBy> {
+        Ok(AnalyzedOrderBy {
+            expr: self.analyze_expr(&order_by.expr)?,
+            descending: order_by.descending,
+            // NULLs sort as larger than any value unless told otherwise
+            nulls_first: order_by.nulls_first.unwrap_or(order_by.descending),
+        })
+    }
+
+    fn analyze_array(&mut self, elements: &[Expr]) -> Result<AnalyzedExpr> {
+        let mut analyzed = elements
+            .iter()
+            .map(|e| self.analyze_expr(e))
+            .collect::<Result<Vec<_>>>()?;
+        let mut element_type: Option<DataType> = None;
+        for e in analyzed.iter().filter(|e| !is_null_literal(e) && !is_string_literal(e)) {
+            let t = e.data_type();
+            element_type = Some(match element_type {
+                None => t.clone(),
+                Some(current) if current == *t => current,
+                Some(current) if current.is_numeric() && t.is_numeric() => current.wider_numeric(t),
+                Some(current) if current.is_string() && t.is_string() => DataType::Text,
+                Some(current) => bail!(
+                    "ARRAY types {} and {} cannot be matched",
+                    current.name(),
+                    t.name()
+                ),
+            });
+        }
+        let element_type = match element_type {
+            Some(t) => t,
+            None if analyzed.iter().any(is_string_literal) => DataType::Text,
+            None => bail!("cannot determine type of empty array"),
+        };
+        for e in analyzed.iter_mut() {
+            *e = self.resolve_unknown(e.clone(), &element_type)?;
+            if !is_null_literal(e) && !is_assignable(e.data_type(), &element_type) {
+                bail!(
+                    "ARRAY types {} and {} cannot be matched",
+                    element_type.name(),
+                    e.data_type().name()
+                );
+            }
+        }
+        Ok(AnalyzedExpr::Function {
+            func: ScalarFunction::MakeArray(element_type.clone()),
+            args: analyzed,
+            result_type: DataType::Array(Box::new(element_type)),
+        })
+    }
+
     fn analyze_literal(&self, lit: &Literal) -> Result<AnalyzedExpr> {
         let (value, data_type) = match lit {
-            Literal::Integer(n) => (LiteralValue::Integer(*n), DataType::Int),
-            Literal::String(s) => (LiteralValue::String(s.clone()), DataType::Varchar),
+            Literal::Integer(n) => {
+                // Integer literals are INT unless they only fit in BIGINT
+                let data_type = if i32::try_from(*n).is_ok() {
+                    DataType::Int
+                } else {
+                    DataType::BigInt
+                };
+                (LiteralValue::Integer(*n), data_type)
+            }
+            Literal::Decimal(s) => (
+                LiteralValue::Numeric(Numeric::parse(s)?),
+                DataType::Numeric(None),
+            ),
+            Literal::String(s) => (LiteralValue::String(s.clone()), DataType::Text),
             Literal::Boolean(b) => (LiteralValue::Boolean(*b), DataType::Bool),
             Literal::Null => (LiteralValue::Null, DataType::Int), // NULL type is context-dependent
+            Literal::Typed(data_type, text) => {
+                let data_type = convert_ast_data_type(data_type);
+                (LiteralValue::Typed(parse_value(text, &data_type)?), data_type)
+            }
         };
         Ok(AnalyzedExpr::Literal(AnalyzedLiteral { value, data_type }))
     }
 
+    /// $n is a constant of its bound value; while the statement is only
+    /// described it is a placeholder of its type, TEXT until inferred
+    fn analyze_parameter(&self, n: usize) -> Result<AnalyzedExpr> {
+        let Some(parameter) = n.checked_sub(1).and_then(|i| self.parameters.get(i)) else {
+            bail!("there is no parameter ${n}");
+        };
+        let value = match &parameter.value {
+            None => LiteralValue::Parameter(n),
+            Some(Value::Null) => LiteralValue::Null,
+            Some(value) => LiteralValue::Typed(value.clone()),
+        };
+        Ok(AnalyzedExpr::Literal(AnalyzedLiteral {
+            value,
+            data_type: parameter.data_type.clone().unwrap_or(DataType::Text),
+        }))
+    }
+
+    /// Give a parameter or string literal of unknown type the type its
+    /// context expects. A parameter keeps the first type inferred for it.
+    fn resolve_unknown(&mut self, expr: AnalyzedExpr, target: &DataType) -> Result<AnalyzedExpr> {
+        let AnalyzedExpr::Literal(AnalyzedLiteral {
+            value: LiteralValue::Parameter(n),
+            ..
+        }) = expr
+        else {
+            return resolve_unknown_literal(expr, target);
+        };
+        let data_type = self.parameters[n - 1]
+            .data_type
+            .get_or_insert_with(|| match target {
+                // Precision and length limits are applied on assignment
+                DataType::Numeric(_) => DataType::Numeric(None),
+                t => t.clone(),
+            })
+            .clone();
+        Ok(AnalyzedExpr::Literal(AnalyzedLiteral {
+            value: LiteralValue::Parameter(n),
+            data_type,
+        }))
+    }
+
     fn analyze_column(&self, table: Option<&str>, name: &str) -> Result<AnalyzedExpr> {
         // Search through scopes from innermost to outermost
         for scope in self.scopes.iter().rev() {
@@ -730,6 +1618,7 @@ impl<'a> Analyzer<'a> {
                         column_index: col_idx,
                         column_name: name.to_string(),
                         data_type: col.data_type.clone(),
+                        collation: col.collation,
                     }));
                 }
             }
@@ -745,8 +1634,8 @@ impl<'a> Analyzer<'a> {
     fn infer_binary_op_type(
         &self,
         op: &BinaryOperator,
-        _left: &AnalyzedExpr,
-        _right: &AnalyzedExpr,
+        left: &AnalyzedExpr,
+        right: &AnalyzedExpr,
     ) -> Result<DataType> {
         match op {
             BinaryOperator::Eq
@@ -754,32 +1643,640 @@ impl<'a> Analyzer<'a> {
             | BinaryOperator::Lt
             | BinaryOperator::Le
             | BinaryOperator::Gt
-            | BinaryOperator::Ge
-            | BinaryOperator::And
-            | BinaryOperator::Or => Ok(DataType::Bool),
+            | BinaryOperator::Ge => {
+                let (l, r) = (left.data_type(), right.data_type());
+                let comparable = is_implicitly_coercible(l, r) || is_implicitly_coercible(r, l);
+                if !comparable && !is_null_literal(left) && !is_null_literal(right) {
+                    bail!(
+                        "operator does not exist: {} {op} {}",
+                        l.name(),
+                        r.name()
+                    );
+                }
+                Ok(DataType::Bool)
+            }
+            BinaryOperator::And | BinaryOperator::Or => Ok(DataType::Bool),
+            BinaryOperator::Arrow
+            | BinaryOperator::LongArrow
+            | BinaryOperator::HashArrow
+            | BinaryOperator::HashLongArrow
+            | BinaryOperator::AtArrow => json_operator_type(op, left, right),
             BinaryOperator::Add
             | BinaryOperator::Sub
             | BinaryOperator::Mul
-            | BinaryOperator::Div => Ok(DataType::Int),
+            | BinaryOperator::Div => {
+                let (l, r) = (left.data_type(), right.data_type());
+                if l.is_numeric() && r.is_numeric() {
+                    Ok(l.wider_numeric(r))
+                } else if l.is_temporal() || r.is_temporal() {
+                    // NULL takes the type of the other operand
+                    if is_null_literal(left) || is_null_literal(right) {
+                        return Ok(if is_null_literal(left) { r } else { l }.clone());
+                    }
+                    datetime_arithmetic_type(op, l, r).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "operator does not exist: {} {op} {}",
+                            l.name(),
+                            r.name()
+                        )
+                    })
+                } else {
+                    bail!("operator does not exist: {} {op} {}", l.name(), r.name())
+                }
+            }
         }
     }
 
-    fn infer_unary_op_type(&self, op: &UnaryOperator, _expr: &AnalyzedExpr) -> Result<DataType> {
+    fn infer_unary_op_type(&self, op: &UnaryOperator, expr: &AnalyzedExpr) -> Result<DataType> {
         match op {
             UnaryOperator::Not => Ok(DataType::Bool),
+            UnaryOperator::Neg if expr.data_type().is_numeric() => {
+                Ok(expr.data_type().wider_numeric(expr.data_type()))
+            }
+            UnaryOperator::Neg if *expr.data_type() == DataType::Interval => Ok(DataType::Interval),
             UnaryOperator::Neg => Ok(DataType::Int),
         }
     }
 }
 
+/// How a statement uses a table, for check_table_access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableAccess {
+    /// SELECT, and the tables UPDATE ... FROM and DELETE ... USING read
+    Read,
+    /// INSERT, UPDATE, DELETE, COPY FROM, CREATE INDEX and VACUUM
+    Modify,
+}
+
+/// Fail unless the session user may use `table` this way. pg_authid holds
+/// password verifiers, so only superusers may read it; the other catalogs
+/// are readable by all, but only superusers may modify any of them
+pub fn check_table_access(
+    catalog: &Catalog,
+    table: &TableDef,
+    access: TableAccess,
+) -> Result<()> {
+    let superuser_only = match access {
+        TableAccess::Read => table.table_id == PG_AUTHID_TABLE_ID,
+        TableAccess::Modify => table.table_id < FIRST_USER_TABLE_ID,
+    };
+    if superuser_only
+        && !catalog
+            .get_role(&settings::session_user())
+            .is_some_and(|role| role.superuser)
+    {
+        return Err(SqlError::error(
+            INSUFFICIENT_PRIVILEGE,
+            format!("permission denied for table {}", table.name),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Most grouping sets one GROUP BY may expand to
+const MAX_GROUPING_SETS: usize = 4096;
+
+/// Grouping sets of one GROUP BY element, in PostgreSQL's order
+fn expand_grouping_sets(item: &GroupByItem) -> Result<Vec<Vec<&Expr>>> {
+    Ok(match item {
+        GroupByItem::Expr(expr) => vec![vec![expr]],
+        GroupByItem::Set(exprs) => vec![exprs.iter().collect()],
+        // ROLLUP (a, b) = GROUPING SETS ((a, b), (a), ())
+        GroupByItem::Rollup(elements) => (0..=elements.len())
+            .rev()
+            .map(|n| elements[..n].iter().flatten().collect())
+            .collect(),
+        // CUBE (a, b) = GROUPING SETS ((a, b), (a), (b), ())
+        GroupByItem::Cube(elements) => {
+            if elements.len() > 12 {
+                bail!("CUBE is limited to 12 elements");
+            }
+            elements.iter().rev().fold(vec![Vec::new()], |sets, element| {
+                let with: Vec<Vec<&Expr>> = sets
+                    .iter()
+                    .map(|set| element.iter().chain(set.iter().copied()).collect())
+                    .collect();
+                with.into_iter().chain(sets).collect()
+            })
+        }
+        GroupByItem::GroupingSets(items) => {
+            let mut sets = Vec::new();
+            for item in items {
+                sets.extend(expand_grouping_sets(item)?);
+            }
+            sets
+        }
+    })
+}
+
+/// percentile_cont/disc give one result for a numeric fraction and an array
+/// of results for an array of fractions
+fn percentile_result_type(fraction: &DataType, result: DataType) -> Result<DataType> {
+    match fraction {
+        DataType::Array(element) if element.is_numeric() => Ok(DataType::Array(Box::new(result))),
+        fraction if fraction.is_numeric() => Ok(result),
+        other => bail!("percentile fraction must be numeric, not type {}", other.name()),
+    }
+}
+
+/// Whether a value of type `actual` may be stored in a column of type
+/// `expected`. Numeric types convert among themselves with a runtime range
+/// check (see `tuple::coerce_value`).
+fn is_assignable(actual: &DataType, expected: &DataType) -> bool {
+    actual == expected
+        || (actual.is_numeric() && expected.is_numeric())
+        || (actual.is_string() && expected.is_string())
+        || (actual.is_datetime() && expected.is_datetime())
+        || matches!((actual, expected), (DataType::Array(a), DataType::Array(e)) if is_assignable(a, e))
+}
+
+fn is_string_literal(expr: &AnalyzedExpr) -> bool {
+    matches!(
+        expr,
+        AnalyzedExpr::Literal(AnalyzedLiteral {
+            value: LiteralValue::String(_),
+            ..
+        })
+    )
+}
+
+/// Implicit coercions between operand types, which make them comparable:
+/// numbers widen along SMALLINT -> INT -> BIGINT -> NUMERIC -> REAL ->
+/// DOUBLE PRECISION, CHAR and VARCHAR convert to TEXT, and DATE, TIMESTAMP
+/// and TIMESTAMPTZ convert to each other. Arrays follow their elements.
+/// String literals have no type of their own until resolved against the
+/// other operand (see resolve_unknown_literal).
+fn is_implicitly_coercible(from: &DataType, to: &DataType) -> bool {
+    match (from, to) {
+        (DataType::Array(f), DataType::Array(t)) => is_implicitly_coercible(f, t),
+        (f, t) if f.is_numeric() && t.is_numeric() => f.numeric_rank() <= t.numeric_rank(),
+        (f, t) if f.is_string() && t.is_string() => true,
+        (f, t) if f.is_datetime() && t.is_datetime() => true,
+        (f, t) => f == t,
+    }
+}
+
+/// Whether CAST from one type to another is allowed (see tuple::cast_value)
+fn is_castable(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    let is_integer = |t: &DataType| matches!(t, SmallInt | Int | BigInt);
+    match (from, to) {
+        (f, t) if f == t => true,
+        (Array(f), Array(t)) => is_castable(f, t),
+        (f, t) if f.is_string() || t.is_string() => true,
+        (f, t) if f.is_numeric() && t.is_numeric() => true,
+        (Bool, t) | (t, Bool) if is_integer(t) => true,
+        (f, t) if f.is_datetime() && t.is_datetime() => true,
+        (Timestamp | TimestampTz, Time) | (Time, Interval) | (Interval, Time) => true,
+        (Jsonb, t) => t.is_numeric() || *t == Bool,
+        _ => false,
+    }
+}
+
+fn is_null_literal(expr: &AnalyzedExpr) -> bool {
+    matches!(
+        expr,
+        AnalyzedExpr::Literal(AnalyzedLiteral {
+            value: LiteralValue::Null,
+            ..
+        })
+    )
+}
+
+/// The type a string literal operand should take when the other operand of
+/// `op` has type `other`: the same type for comparisons (`int_col = '5'`) and
+/// numeric arithmetic, INTERVAL for date/time arithmetic (`ts - '1 day'`).
+/// Next to a string the literal stays text.
+fn unknown_literal_target(op: &BinaryOperator, other: &DataType) -> Option<DataType> {
+    let comparison = matches!(
+        op,
+        BinaryOperator::Eq
+            | BinaryOperator::Ne
+            | BinaryOperator::Lt
+            | BinaryOperator::Le
+            | BinaryOperator::Gt
+            | BinaryOperator::Ge
+    );
+    let arithmetic = matches!(
+        op,
+        BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::Mul | BinaryOperator::Div
+    );
+    match other {
+        t if t.is_temporal() && matches!(op, BinaryOperator::Add | BinaryOperator::Sub) => {
+            Some(DataType::Interval)
+        }
+        DataType::Jsonb if *op == BinaryOperator::AtArrow => Some(DataType::Jsonb),
+        DataType::Varchar(_) | DataType::Text => None,
+        t if comparison || (arithmetic && t.is_numeric()) => Some(t.clone()),
+        DataType::Bool if matches!(op, BinaryOperator::And | BinaryOperator::Or) => {
+            Some(DataType::Bool)
+        }
+        _ => None,
+    }
+}
+
+/// Convert a string literal to a constant of the target type using the
+/// type's input syntax, or blank-pad it for comparison with CHAR(n). Other
+/// string targets and any other expression are returned unchanged.
+fn resolve_unknown_literal(expr: AnalyzedExpr, target: &DataType) -> Result<AnalyzedExpr> {
+    let AnalyzedExpr::Literal(AnalyzedLiteral {
+        value: LiteralValue::String(text),
+        ..
+    }) = &expr
+    else {
+        return Ok(expr);
+    };
+    let (value, data_type) = match target {
+        DataType::Char(length) => {
+            let padding = (*length as usize).saturating_sub(text.chars().count());
+            (LiteralValue::String(format!("{text}{}", " ".repeat(padding))), DataType::Text)
+        }
+        t if t.is_string() => return Ok(expr),
+        // Precision and length limits are applied on assignment, not here
+        DataType::Numeric(_) => (
+            LiteralValue::Typed(parse_value(text, &DataType::Numeric(None))?),
+            DataType::Numeric(None),
+        ),
+        t => (LiteralValue::Typed(parse_value(text, t)?), t.clone()),
+    };
+    Ok(AnalyzedExpr::Literal(AnalyzedLiteral { value, data_type }))
+}
+
+/// Result type of a JSON operator: -> and #> give jsonb, ->> and #>> give
+/// text, @> gives boolean. A key is text, an array position an integer, and a
+/// path text in array form ('{a,0,b}').
+fn json_operator_type(op: &BinaryOperator, left: &AnalyzedExpr, right: &AnalyzedExpr) -> Result<DataType> {
+    let (l, r) = (left.data_type(), right.data_type());
+    let valid = *l == DataType::Jsonb || is_null_literal(left);
+    let result = match op {
+        BinaryOperator::Arrow | BinaryOperator::LongArrow
+            if r.is_string() || matches!(r, DataType::SmallInt | DataType::Int | DataType::BigInt) =>
+        {
+            Some(op)
+        }
+        BinaryOperator::HashArrow | BinaryOperator::HashLongArrow if r.is_string() => Some(op),
+        BinaryOperator::AtArrow if *r == DataType::Jsonb || is_null_literal(right) => Some(op),
+        _ => None,
+    };
+    match result {
+        Some(BinaryOperator::Arrow | BinaryOperator::HashArrow) if valid => Ok(DataType::Jsonb),
+        Some(BinaryOperator::LongArrow | BinaryOperator::HashLongArrow) if valid => Ok(DataType::Text),
+        Some(BinaryOperator::AtArrow) if valid => Ok(DataType::Bool),
+        _ => bail!("operator does not exist: {} {op} {}", l.name(), r.name()),
+    }
+}
+
+/// Result type of date/time arithmetic, or None if the operator does not
+/// apply to these operand types (see executor::evaluate_datetime_op)
+fn datetime_arithmetic_type(op: &BinaryOperator, l: &DataType, r: &DataType) -> Option<DataType> {
+    use BinaryOperator::{Add, Div, Mul, Sub};
+    use DataType::*;
+    let is_integer = |t: &DataType| matches!(t, SmallInt | Int | BigInt);
+    match (op, l, r) {
+        (Add, Date, n) | (Add, n, Date) | (Sub, Date, n) if is_integer(n) => Some(Date),
+        (Sub, Date, Date) => Some(Int),
+        (Add, Date, Time) | (Add, Time, Date) => Some(Timestamp),
+        (Add, Date | Timestamp, Interval) | (Add, Interval, Date | Timestamp) => Some(Timestamp),
+        (Sub, Date | Timestamp, Interval) => Some(Timestamp),
+        (Add, TimestampTz, Interval) | (Add, Interval, TimestampTz) => Some(TimestampTz),
+        (Sub, TimestampTz, Interval) => Some(TimestampTz),
+        (Sub, a, b) if a.is_datetime() && b.is_datetime() => Some(Interval),
+        (Add, Time, Interval) | (Add, Interval, Time) | (Sub, Time, Interval) => Some(Time),
+        (Sub, Time, Time) => Some(Interval),
+        (Add | Sub, Interval, Interval) => Some(Interval),
+        (Mul, Interval, n) | (Mul, n, Interval) | (Div, Interval, n) if n.is_numeric() => {
+            Some(Interval)
+        }
+        _ => None,
+    }
+}
+
+fn is_comparison(op: &BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Eq
+            | BinaryOperator::Ne
+            | BinaryOperator::Lt
+            | BinaryOperator::Le
+            | BinaryOperator::Gt
+            | BinaryOperator::Ge
+    )
+}
+
+/// Collation a comparison of `left` with `right` runs under
+fn comparison_collation(left: &AnalyzedExpr, right: &AnalyzedExpr) -> Result<Collation> {
+    Ok(left
+        .collation_derivation()
+        .combine(right.collation_derivation())?
+        .collation())
+}
+
 fn convert_ast_data_type(dt: &crate::ast::DataType) -> DataType {
     match dt {
         crate::ast::DataType::Int => DataType::Int,
-        crate::ast::DataType::Varchar => DataType::Varchar,
+        crate::ast::DataType::SmallInt => DataType::SmallInt,
+        crate::ast::DataType::BigInt => DataType::BigInt,
+        crate::ast::DataType::Real => DataType::Real,
+        crate::ast::DataType::Double => DataType::Double,
+        crate::ast::DataType::Numeric(typmod) => DataType::Numeric(*typmod),
+        crate::ast::DataType::Varchar(length) => DataType::Varchar(*length),
+        crate::ast::DataType::Char(length) => DataType::Char(*length),
+        crate::ast::DataType::Text => DataType::Text,
+        crate::ast::DataType::Bool => DataType::Bool,
+        crate::ast::DataType::Date => DataType::Date,
+        crate::ast::DataType::Time => DataType::Time,
+        crate::ast::DataType::Timestamp => DataType::Timestamp,
+        crate::ast::DataType::TimestampTz => DataType::TimestampTz,
+        crate::ast::DataType::Interval => DataType::Interval,
+        crate::ast::DataType::Bytea => DataType::Bytea,
+        crate::ast::DataType::Jsonb => DataType::Jsonb,
+        crate::ast::DataType::Array(element) => {
+            DataType::Array(Box::new(convert_ast_data_type(element)))
+        }
     }
 }
 
-pub fn analyze(catalog: &Catalog, stmt: &Statement) -> Result<AnalyzedStatement> {
+/// Analyze a prepared statement. Parameters without a value are described
+/// only: those without a type get the one their context implies, or TEXT.
+pub fn analyze_with_parameters(
+    catalog: &Catalog,
+    stmt: &Statement,
+    parameters: &mut Vec<Parameter>,
+) -> Result<AnalyzedStatement> {
     let mut analyzer = Analyzer::new(catalog);
-    analyzer.analyze(stmt)
+    analyzer.parameters = std::mem::take(parameters);
+    let result = analyzer.analyze(stmt);
+    *parameters = analyzer.parameters;
+    for parameter in parameters.iter_mut() {
+        parameter.data_type.get_or_insert(DataType::Text);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::testing::TestDb;
+
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE items (id INT, name VARCHAR(20), price NUMERIC(8,2), info JSONB)");
+        db.query("INSERT INTO items VALUES (1, 'widget', 9.99, '{\"stock\": 12}')");
+        db
+    }
+
+    fn analyze_sql(db: &TestDb, sql: &str) -> Result<AnalyzedStatement> {
+        analyze_with_parameters(&db.catalog, &crate::parser::parse(sql)?, &mut Vec::new())
+    }
+
+    #[test]
+    fn test_implicit_coercions() {
+        use DataType::*;
+        assert!(is_implicitly_coercible(&SmallInt, &BigInt));
+        assert!(!is_implicitly_coercible(&BigInt, &SmallInt));
+        assert!(is_implicitly_coercible(&Int, &Numeric(None)));
+        assert!(is_implicitly_coercible(&Numeric(Some((8, 2))), &Double));
+        assert!(!is_implicitly_coercible(&Double, &Numeric(None)));
+        assert!(is_implicitly_coercible(&Char(3), &Text));
+        assert!(is_implicitly_coercible(&Date, &TimestampTz));
+        assert!(is_implicitly_coercible(&Array(Box::new(Int)), &Array(Box::new(BigInt))));
+        assert!(!is_implicitly_coercible(&Int, &Text));
+        assert!(!is_implicitly_coercible(&Bool, &Int));
+        assert!(!is_implicitly_coercible(&Time, &Timestamp));
+    }
+
+    #[test]
+    fn test_castable_types() {
+        use DataType::*;
+        assert!(is_castable(&Int, &Text));
+        assert!(is_castable(&Text, &Date));
+        assert!(is_castable(&Double, &SmallInt));
+        assert!(is_castable(&Bool, &Int));
+        assert!(is_castable(&Jsonb, &Numeric(None)));
+        assert!(is_castable(&Timestamp, &Time));
+        assert!(!is_castable(&Bool, &Double));
+        assert!(!is_castable(&Date, &Int));
+        assert!(!is_castable(&Jsonb, &Date));
+        assert!(!is_castable(&Array(Box::new(Date)), &Array(Box::new(Int))));
+    }
+
+    #[test]
+    fn test_comparison_operands_coerce() {
+        let db = setup();
+        assert!(analyze_sql(&db, "SELECT name FROM items WHERE price > 5").is_ok());
+        assert!(analyze_sql(&db, "SELECT name FROM items WHERE id = '1'").is_ok());
+        assert!(analyze_sql(&db, "SELECT name FROM items WHERE name = 'widget'").is_ok());
+
+        let err = analyze_sql(&db, "SELECT name FROM items WHERE id = 'one'").unwrap_err();
+        assert_eq!(err.to_string(), "invalid input syntax for type integer: \"one\"");
+        let err = analyze_sql(&db, "SELECT name FROM items WHERE name = 5").unwrap_err();
+        assert_eq!(err.to_string(), "operator does not exist: character varying = integer");
+    }
+
+    #[test]
+    fn test_cast_of_constant_is_folded() {
+        let db = setup();
+        let AnalyzedStatement::Select(select) =
+            analyze_sql(&db, "SELECT id FROM items WHERE id = '7'::int").unwrap()
+        else {
+            panic!("not a SELECT");
+        };
+        let Some(AnalyzedExpr::BinaryOp { right, .. }) = &select.where_clause else {
+            panic!("unexpected WHERE clause {:?}", select.where_clause);
+        };
+        assert!(matches!(
+            right.as_ref(),
+            AnalyzedExpr::Literal(AnalyzedLiteral {
+                value: LiteralValue::Typed(Value::Int(7)),
+                data_type: DataType::Int,
+            })
+        ));
+
+        let err = analyze_sql(&db, "SELECT 'x'::int FROM items").unwrap_err();
+        assert_eq!(err.to_string(), "invalid input syntax for type integer: \"x\"");
+        let err = analyze_sql(&db, "SELECT CAST(info AS DATE) FROM items").unwrap_err();
+        assert_eq!(err.to_string(), "cannot cast type jsonb to date");
+    }
+
+    #[test]
+    fn test_cast_results() {
+        let db = setup();
+        assert_eq!(
+            db.query("SELECT CAST(price AS INT), id::text, (info->'stock')::int FROM items"),
+            vec![vec![Value::Int(10), Value::Varchar("1".to_string()), Value::Int(12)]]
+        );
+        assert_eq!(
+            db.query("SELECT (id + 1)::smallint, CAST(id AS BOOLEAN), price::real FROM items"),
+            vec![vec![Value::SmallInt(2), Value::Bool(true), Value::Real(9.99)]]
+        );
+        let err = db.run("SELECT (id * 100000)::smallint FROM items").unwrap_err();
+        assert_eq!(err.to_string(), "smallint out of range");
+    }
+
+    #[test]
+    fn test_grouping_set_expansion_order() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (a INT, b INT, c INT, x INT)");
+        let grouping_sets = |group_by: &str| {
+            let sql = format!("SELECT sum(x) FROM t GROUP BY {group_by}");
+            match analyze_sql(&db, &sql).unwrap() {
+                AnalyzedStatement::Select(s) => s.grouping_sets,
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(grouping_sets("a, b"), vec![vec![0, 1]]);
+        // Sets are listed as PostgreSQL expands them, indexing the GROUP BY
+        // expressions in order of first appearance
+        assert_eq!(
+            grouping_sets("ROLLUP (a, b, c)"),
+            vec![vec![0, 1, 2], vec![0, 1], vec![0], vec![]]
+        );
+        assert_eq!(
+            grouping_sets("CUBE (a, b, c)"),
+            vec![
+                vec![0, 1, 2],
+                vec![0, 1],
+                vec![0, 2],
+                vec![0],
+                vec![1, 2],
+                vec![1],
+                vec![2],
+                vec![],
+            ]
+        );
+        // A parenthesized element is rolled up as a unit
+        assert_eq!(grouping_sets("ROLLUP (a, (b, c))"), vec![vec![0, 1, 2], vec![0], vec![]]);
+        // Items combine as a cross product
+        assert_eq!(
+            grouping_sets("a, CUBE (b, c)"),
+            vec![vec![0, 1, 2], vec![0, 1], vec![0, 2], vec![0]]
+        );
+        assert_eq!(
+            grouping_sets("GROUPING SETS ((a, b), ROLLUP (c), ())"),
+            vec![vec![0, 1], vec![2], vec![], vec![]]
+        );
+        // Repeated expressions are grouped once
+        assert_eq!(grouping_sets("ROLLUP (a, a)"), vec![vec![0], vec![0], vec![]]);
+    }
+
+    #[test]
+    fn test_grouping_set_limits() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (a INT, b INT, c INT, x INT)");
+        let cube = ["a"; 13].join(", ");
+        let err = analyze_sql(&db, &format!("SELECT sum(x) FROM t GROUP BY CUBE ({cube})"));
+        assert_eq!(err.unwrap_err().to_string(), "CUBE is limited to 12 elements");
+        let rollups = ["ROLLUP (a, b, c)"; 7].join(", ");
+        let err = analyze_sql(&db, &format!("SELECT sum(x) FROM t GROUP BY {rollups}"));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "too many grouping sets present (maximum 4096)"
+        );
+
+        let err = analyze_sql(&db, "SELECT GROUPING(c) FROM t GROUP BY ROLLUP (a, b)");
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "arguments to GROUPING must be grouping expressions of the associated query level"
+        );
+    }
+
+    /// Types of the parameters of `sql` after analysis, starting from
+    /// `declared` (None where the client left the type to the server)
+    fn parameter_types(
+        db: &TestDb,
+        sql: &str,
+        declared: &[Option<DataType>],
+    ) -> Result<Vec<DataType>> {
+        let (stmt, used) = crate::parser::parse_prepared(sql)?;
+        let mut parameters: Vec<Parameter> = (0..used.max(declared.len()))
+            .map(|i| Parameter {
+                data_type: declared.get(i).cloned().flatten(),
+                value: None,
+            })
+            .collect();
+        analyze_with_parameters(&db.catalog, &stmt, &mut parameters)?;
+        Ok(parameters.into_iter().map(|p| p.data_type.unwrap()).collect())
+    }
+
+    #[test]
+    fn test_parameter_type_inference() {
+        use DataType::*;
+        let db = setup();
+        // From the column a parameter is compared with or assigned to; one
+        // compared with a string stays TEXT
+        assert_eq!(
+            parameter_types(&db, "SELECT name FROM items WHERE id = $1 AND name = $2", &[])
+                .unwrap(),
+            [Int, Text]
+        );
+        assert_eq!(
+            parameter_types(&db, "INSERT INTO items VALUES ($1, $2, $3, $4)", &[]).unwrap(),
+            [Int, Varchar(Some(20)), Numeric(None), Jsonb]
+        );
+        assert_eq!(
+            parameter_types(&db, "UPDATE items SET price = $2 WHERE id = $1", &[]).unwrap(),
+            [Int, Numeric(None)]
+        );
+        // From a cast, or TEXT without any context
+        assert_eq!(
+            parameter_types(&db, "SELECT $1::date, $2 FROM items", &[]).unwrap(),
+            [Date, Text]
+        );
+        // A declared type is kept, and declared parameters may go unused
+        assert_eq!(
+            parameter_types(&db, "SELECT name FROM items WHERE id = $1", &[Some(BigInt), None])
+                .unwrap(),
+            [BigInt, Text]
+        );
+        // The first type inferred for a parameter sticks
+        assert_eq!(
+            parameter_types(&db, "SELECT name FROM items WHERE id = $1 OR price = $1", &[])
+                .unwrap(),
+            [Int]
+        );
+    }
+
+    #[test]
+    fn test_parameter_errors() {
+        let db = setup();
+        let stmt = crate::parser::parse_prepared("SELECT $2 FROM items").unwrap().0;
+        let mut parameters = vec![Parameter::default()];
+        let err = analyze_with_parameters(&db.catalog, &stmt, &mut parameters).unwrap_err();
+        assert_eq!(err.to_string(), "there is no parameter $2");
+
+        let sql = "SELECT name FROM items WHERE name = $1";
+        let err = parameter_types(&db, sql, &[Some(DataType::Bool)]).unwrap_err();
+        assert_eq!(err.to_string(), "operator does not exist: character varying = boolean");
+    }
+
+    #[test]
+    fn test_bound_parameters_are_constants() {
+        let db = setup();
+        let stmt = crate::parser::parse_prepared("SELECT $1 + 1, $2 FROM items").unwrap().0;
+        let mut parameters = vec![
+            Parameter {
+                data_type: Some(DataType::Int),
+                value: Some(Value::Int(41)),
+            },
+            Parameter {
+                data_type: Some(DataType::Text),
+                value: Some(Value::Null),
+            },
+        ];
+        let AnalyzedStatement::Select(select) =
+            analyze_with_parameters(&db.catalog, &stmt, &mut parameters).unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(*select.select_items[0].expr.data_type(), DataType::Int);
+        assert!(matches!(
+            &select.select_items[1].expr,
+            AnalyzedExpr::Literal(AnalyzedLiteral {
+                value: LiteralValue::Null,
+                ..
+            })
+        ));
+    }
 }
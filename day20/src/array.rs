@@ -0,0 +1,131 @@
+use anyhow::{bail, Result};
+
+// ============================================================================
+// Array text format
+// ============================================================================
+//
+// One-dimensional arrays in PostgreSQL's text form: '{1,2,NULL}' or
+// '{"a b","with \"quotes\"",plain}'. An unquoted NULL is a null element.
+// Elements are kept as text here; tuple::parse_value converts them to the
+// element type.
+
+/// Split the text form of an array into its elements (None for NULL)
+pub fn parse(text: &str) -> Result<Vec<Option<String>>> {
+    let malformed = || anyhow::anyhow!("malformed array literal: \"{text}\"");
+    let inner = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or_else(malformed)?;
+    if inner.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut elements = Vec::new();
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut element = String::new();
+        let quoted = chars.next_if_eq(&'"').is_some();
+        if quoted {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => element.push(chars.next().ok_or_else(malformed)?),
+                    Some(c) => element.push(c),
+                    None => return Err(malformed()),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        } else {
+            // Trailing whitespace is dropped unless it was escaped
+            let mut kept = 0;
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                match c {
+                    '{' => bail!("multidimensional arrays are not supported"),
+                    '"' | '}' => return Err(malformed()),
+                    '\\' => {
+                        element.push(chars.next().ok_or_else(malformed)?);
+                        kept = element.len();
+                    }
+                    c => {
+                        element.push(c);
+                        if !c.is_whitespace() {
+                            kept = element.len();
+                        }
+                    }
+                }
+            }
+            element.truncate(kept);
+            if element.is_empty() {
+                return Err(malformed());
+            }
+        }
+        let is_null = !quoted && element.eq_ignore_ascii_case("null");
+        elements.push((!is_null).then_some(element));
+        match chars.next() {
+            Some(',') => continue,
+            None => break,
+            Some(_) => return Err(malformed()),
+        }
+    }
+    Ok(elements)
+}
+
+/// Text form of an array from the text of its elements (None for NULL),
+/// quoting elements that would otherwise not read back the same
+pub fn format(elements: impl IntoIterator<Item = Option<String>>) -> String {
+    let elements: Vec<String> = elements
+        .into_iter()
+        .map(|element| match element {
+            None => "NULL".to_string(),
+            Some(text) if needs_quotes(&text) => {
+                format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            Some(text) => text,
+        })
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
+fn needs_quotes(text: &str) -> bool {
+    text.is_empty()
+        || text.eq_ignore_ascii_case("null")
+        || text
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(parse("{}").unwrap(), vec![]);
+        assert_eq!(parse(" { 1 , 2,NULL } ").unwrap(), vec![some("1"), some("2"), None]);
+        assert_eq!(
+            parse(r#"{"a b","say \"hi\"", x\ ,"NULL"}"#).unwrap(),
+            vec![some("a b"), some("say \"hi\""), some("x "), some("NULL")]
+        );
+        assert!(parse("1,2").is_err());
+        assert!(parse("{1,,2}").is_err());
+        assert!(parse(r#"{"open}"#).is_err());
+        assert!(parse("{{1},{2}}").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let elements = vec![
+            Some("plain".to_string()),
+            None,
+            Some("".to_string()),
+            Some("null".to_string()),
+            Some("a,\"b\"\\".to_string()),
+        ];
+        let text = format(elements.clone());
+        assert_eq!(text, r#"{plain,NULL,"","null","a,\"b\"\\"}"#);
+        assert_eq!(parse(&text).unwrap(), elements);
+    }
+}
@@ -1,5 +1,9 @@
 // SQL Abstract Syntax Tree
 
+use std::fmt;
+
+use crate::udf::AggregateUdfRef;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     Select(SelectStatement),
@@ -12,13 +16,66 @@ pub enum Statement {
     Commit,
     Rollback,
     Checkpoint,
+    /// VACUUM [table]: reclaim dead rows of one table, or of all tables
+    Vacuum(Option<String>),
+    /// SET name = value; a value of None is SET name TO DEFAULT or RESET name
+    Set {
+        name: String,
+        value: Option<String>,
+    },
+    /// SHOW name
+    Show(String),
+    /// CREATE ROLE / CREATE USER
+    CreateRole(RoleStatement),
+    /// ALTER ROLE / ALTER USER
+    AlterRole(RoleStatement),
+    /// COPY ... FROM STDIN / COPY ... TO STDOUT
+    Copy(CopyStatement),
+}
+
+/// A role and the options given for it; None leaves an option unchanged,
+/// or at its default for a new role
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleStatement {
+    pub name: String,
+    pub superuser: Option<bool>,
+    pub login: Option<bool>,
+    /// Some(None) is PASSWORD NULL
+    pub password: Option<Option<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyStatement {
+    pub target: CopyTarget,
+    pub options: CopyOptions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyTarget {
+    /// COPY table [(columns)] FROM STDIN; no columns means all of them
+    From { table: String, columns: Vec<String> },
+    /// COPY (query) TO STDOUT; COPY table [(columns)] TO STDOUT is parsed
+    /// as the equivalent SELECT
+    To(Box<SelectStatement>),
+}
+
+/// COPY options as written; they are validated when the statement runs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CopyOptions {
+    pub csv: bool,
+    pub delimiter: Option<String>,
+    pub header: bool,
+    pub quote: Option<String>,
+    pub null: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CreateIndexStatement {
     pub index_name: String,
+    pub unique: bool,
     pub table_name: String,
-    pub column_names: Vec<String>,
+    /// Key columns: plain column references or expressions such as (data->>'name')
+    pub columns: Vec<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -26,10 +83,26 @@ pub struct SelectStatement {
     pub columns: Vec<SelectColumn>,
     pub from: FromClause,
     pub where_clause: Option<Expr>,
-    pub group_by: Vec<Expr>,
+    pub group_by: Vec<GroupByItem>,
     pub having: Option<Expr>,
 }
 
+/// One comma-separated element of GROUP BY. The grouping sets of the whole
+/// clause are the cross product of its elements' sets.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupByItem {
+    Expr(Expr),
+    /// ROLLUP (a, (b, c), ...): each element is one expression or a
+    /// parenthesized list grouped as a unit
+    Rollup(Vec<Vec<Expr>>),
+    /// CUBE (a, (b, c), ...)
+    Cube(Vec<Vec<Expr>>),
+    /// GROUPING SETS (...)
+    GroupingSets(Vec<GroupByItem>),
+    /// (a, b) or () inside GROUPING SETS
+    Set(Vec<Expr>),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FromClause {
     Table(TableRef),
@@ -51,6 +124,8 @@ pub enum JoinType {
 pub struct TableRef {
     pub name: String,
     pub alias: Option<String>,
+    /// Arguments when the FROM item is a function call such as unnest(...)
+    pub function_args: Option<Vec<Expr>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -63,6 +138,24 @@ pub enum SelectColumn {
 pub struct InsertStatement {
     pub table: String,
     pub values: Vec<Expr>,
+    pub on_conflict: Option<OnConflict>,
+    pub returning: Vec<SelectColumn>,
+}
+
+// ON CONFLICT [(columns)] DO NOTHING | DO UPDATE SET ... [WHERE ...]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnConflict {
+    pub target: Vec<String>,
+    pub action: OnConflictAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnConflictAction {
+    DoNothing,
+    DoUpdate {
+        assignments: Vec<Assignment>,
+        where_clause: Option<Expr>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -74,14 +167,18 @@ pub struct CreateTableStatement {
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeleteStatement {
     pub table: String,
+    pub using: Vec<TableRef>,
     pub where_clause: Option<Expr>,
+    pub returning: Vec<SelectColumn>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct UpdateStatement {
     pub table: String,
     pub assignments: Vec<Assignment>,
+    pub from: Vec<TableRef>,
     pub where_clause: Option<Expr>,
+    pub returning: Vec<SelectColumn>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -94,12 +191,34 @@ pub struct Assignment {
 pub struct ColumnDef {
     pub name: String,
     pub data_type: DataType,
+    /// COLLATE clause of a string column
+    pub collation: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     Int,
-    Varchar,
+    SmallInt,
+    BigInt,
+    Real,
+    Double,
+    /// NUMERIC / DECIMAL with optional (precision, scale)
+    Numeric(Option<(u32, u32)>),
+    /// VARCHAR / CHARACTER VARYING with optional maximum length
+    Varchar(Option<u32>),
+    /// CHAR(n) / CHARACTER(n); plain CHAR is CHAR(1)
+    Char(u32),
+    Text,
+    Bool,
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+    Interval,
+    Bytea,
+    Jsonb,
+    /// element_type[]
+    Array(Box<DataType>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -121,7 +240,44 @@ pub enum Expr {
     Aggregate {
         func: AggregateFunction,
         arg: Box<AggregateArg>,
+        // string_agg's delimiter, or the fraction of percentile_cont/disc
+        extra_arg: Option<Box<Expr>>,
+        // ORDER BY inside the call, or WITHIN GROUP (ORDER BY ...) for the
+        // ordered-set aggregates, whose arg is then the ORDER BY expression
+        order_by: Vec<OrderByExpr>,
+        // FILTER (WHERE ...)
+        filter: Option<Box<Expr>>,
+    },
+    // Scalar function call: name(args), or a niladic function such as current_date
+    Function {
+        name: String,
+        args: Vec<Expr>,
     },
+    // ARRAY[elem, ...]
+    Array(Vec<Expr>),
+    // array[index], 1-based
+    Subscript {
+        expr: Box<Expr>,
+        index: Box<Expr>,
+    },
+    // left op ANY (array)
+    Any {
+        left: Box<Expr>,
+        op: BinaryOperator,
+        right: Box<Expr>,
+    },
+    // CAST(expr AS type) or expr::type
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    // expr COLLATE "name"
+    Collate {
+        expr: Box<Expr>,
+        collation: String,
+    },
+    // $n, a parameter of a prepared statement (1-based)
+    Parameter(usize),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -131,6 +287,63 @@ pub enum AggregateFunction {
     Avg,
     Min,
     Max,
+    JsonbAgg,
+    StringAgg,
+    ArrayAgg,
+    BoolAnd,
+    BoolOr,
+    /// stddev, another name for stddev_samp with a column named after it
+    Stddev,
+    StddevSamp,
+    StddevPop,
+    /// variance, another name for var_samp with a column named after it
+    Variance,
+    VarSamp,
+    VarPop,
+    PercentileCont,
+    PercentileDisc,
+    /// A registered user-defined aggregate. The parser reads these calls as
+    /// functions; the analyzer turns them into aggregates.
+    User(AggregateUdfRef),
+}
+
+impl AggregateFunction {
+    /// Aggregates spelled as ordinary identifiers (the rest are keywords)
+    pub fn from_name(name: &str) -> Option<AggregateFunction> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "jsonb_agg" => AggregateFunction::JsonbAgg,
+            "string_agg" => AggregateFunction::StringAgg,
+            "array_agg" => AggregateFunction::ArrayAgg,
+            "bool_and" | "every" => AggregateFunction::BoolAnd,
+            "bool_or" => AggregateFunction::BoolOr,
+            "stddev" => AggregateFunction::Stddev,
+            "stddev_samp" => AggregateFunction::StddevSamp,
+            "stddev_pop" => AggregateFunction::StddevPop,
+            "variance" => AggregateFunction::Variance,
+            "var_samp" => AggregateFunction::VarSamp,
+            "var_pop" => AggregateFunction::VarPop,
+            "percentile_cont" => AggregateFunction::PercentileCont,
+            "percentile_disc" => AggregateFunction::PercentileDisc,
+            _ => return None,
+        })
+    }
+
+    /// Ordered-set aggregates take their input from WITHIN GROUP (ORDER BY ...)
+    pub fn is_ordered_set(&self) -> bool {
+        matches!(
+            self,
+            AggregateFunction::PercentileCont | AggregateFunction::PercentileDisc
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByExpr {
+    pub expr: Expr,
+    pub descending: bool,
+    /// NULLS FIRST / NULLS LAST; by default NULLs sort as if larger than
+    /// any value
+    pub nulls_first: Option<bool>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -142,9 +355,12 @@ pub enum AggregateArg {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Integer(i64),
+    Decimal(String),
     String(String),
     Boolean(bool),
     Null,
+    // Constant of a named type, e.g. DATE '2024-01-01' or INTERVAL '1 day'
+    Typed(DataType, String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -161,6 +377,16 @@ pub enum BinaryOperator {
     Sub,
     Mul,
     Div,
+    /// -> : JSON object field or array element
+    Arrow,
+    /// ->> : JSON object field or array element as text
+    LongArrow,
+    /// #> : JSON value at a path
+    HashArrow,
+    /// #>> : JSON value at a path as text
+    HashLongArrow,
+    /// @> : JSON containment
+    AtArrow,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -168,3 +394,174 @@ pub enum UnaryOperator {
     Not,
     Neg,
 }
+
+// SQL text of an expression, used to store index expressions in pg_index.
+// Operations are fully parenthesized so the text parses back to the same tree.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(lit) => write!(f, "{lit}"),
+            Expr::Column { table: Some(table), name } => write!(f, "{table}.{name}"),
+            Expr::Column { table: None, name } => f.write_str(name),
+            Expr::BinaryOp { left, op, right } => write!(f, "({left} {op} {right})"),
+            Expr::UnaryOp { op: UnaryOperator::Not, expr } => write!(f, "(NOT {expr})"),
+            Expr::UnaryOp { op: UnaryOperator::Neg, expr } => write!(f, "(- {expr})"),
+            Expr::Aggregate {
+                func,
+                arg,
+                extra_arg,
+                order_by,
+                filter,
+            } => {
+                let order_by: Vec<String> = order_by.iter().map(|o| o.to_string()).collect();
+                match (func.is_ordered_set(), arg.as_ref(), extra_arg) {
+                    (true, _, Some(fraction)) => write!(
+                        f,
+                        "{func}({fraction}) WITHIN GROUP (ORDER BY {})",
+                        order_by.join(", ")
+                    )?,
+                    (_, AggregateArg::Asterisk, _) => write!(f, "{func}(*)")?,
+                    (_, AggregateArg::Expr(e), extra_arg) => {
+                        write!(f, "{func}({e}")?;
+                        if let Some(extra_arg) = extra_arg {
+                            write!(f, ", {extra_arg}")?;
+                        }
+                        if !order_by.is_empty() {
+                            write!(f, " ORDER BY {}", order_by.join(", "))?;
+                        }
+                        f.write_str(")")?;
+                    }
+                }
+                match filter {
+                    Some(filter) => write!(f, " FILTER (WHERE {filter})"),
+                    None => Ok(()),
+                }
+            }
+            Expr::Function { name, args } if name == "extract" && args.len() == 2 => {
+                match &args[0] {
+                    Expr::Literal(Literal::String(field)) => {
+                        write!(f, "extract({field} FROM {})", args[1])
+                    }
+                    field => write!(f, "date_part({field}, {})", args[1]),
+                }
+            }
+            Expr::Function { name, args } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{name}({})", args.join(", "))
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "ARRAY[{}]", elements.join(", "))
+            }
+            Expr::Subscript { expr, index } => write!(f, "{expr}[{index}]"),
+            Expr::Any { left, op, right } => write!(f, "({left} {op} ANY ({right}))"),
+            Expr::Cast { expr, data_type } => write!(f, "({expr})::{data_type}"),
+            Expr::Collate { expr, collation } => write!(f, "({expr} COLLATE \"{collation}\")"),
+            Expr::Parameter(n) => write!(f, "${n}"),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(n) => write!(f, "{n}"),
+            Literal::Decimal(s) => f.write_str(s),
+            Literal::String(s) => write!(f, "'{s}'"),
+            Literal::Boolean(b) => write!(f, "{b}"),
+            Literal::Null => f.write_str("NULL"),
+            Literal::Typed(data_type, s) => write!(f, "{data_type} '{s}'"),
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Int => f.write_str("INT"),
+            DataType::SmallInt => f.write_str("SMALLINT"),
+            DataType::BigInt => f.write_str("BIGINT"),
+            DataType::Real => f.write_str("REAL"),
+            DataType::Double => f.write_str("DOUBLE PRECISION"),
+            DataType::Numeric(Some((precision, scale))) => write!(f, "NUMERIC({precision}, {scale})"),
+            DataType::Numeric(None) => f.write_str("NUMERIC"),
+            DataType::Varchar(Some(length)) => write!(f, "VARCHAR({length})"),
+            DataType::Varchar(None) => f.write_str("VARCHAR"),
+            DataType::Char(length) => write!(f, "CHAR({length})"),
+            DataType::Text => f.write_str("TEXT"),
+            DataType::Bool => f.write_str("BOOLEAN"),
+            DataType::Date => f.write_str("DATE"),
+            DataType::Time => f.write_str("TIME"),
+            DataType::Timestamp => f.write_str("TIMESTAMP"),
+            DataType::TimestampTz => f.write_str("TIMESTAMPTZ"),
+            DataType::Interval => f.write_str("INTERVAL"),
+            DataType::Bytea => f.write_str("BYTEA"),
+            DataType::Jsonb => f.write_str("JSONB"),
+            DataType::Array(element) => write!(f, "{element}[]"),
+        }
+    }
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::JsonbAgg => "jsonb_agg",
+            AggregateFunction::StringAgg => "string_agg",
+            AggregateFunction::ArrayAgg => "array_agg",
+            AggregateFunction::BoolAnd => "bool_and",
+            AggregateFunction::BoolOr => "bool_or",
+            AggregateFunction::Stddev => "stddev",
+            AggregateFunction::StddevSamp => "stddev_samp",
+            AggregateFunction::StddevPop => "stddev_pop",
+            AggregateFunction::Variance => "variance",
+            AggregateFunction::VarSamp => "var_samp",
+            AggregateFunction::VarPop => "var_pop",
+            AggregateFunction::PercentileCont => "percentile_cont",
+            AggregateFunction::PercentileDisc => "percentile_disc",
+            AggregateFunction::User(aggregate) => aggregate.name(),
+        })
+    }
+}
+
+impl fmt::Display for OrderByExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)?;
+        if self.descending {
+            f.write_str(" DESC")?;
+        }
+        match self.nulls_first {
+            Some(true) => f.write_str(" NULLS FIRST"),
+            Some(false) => f.write_str(" NULLS LAST"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Eq => "=",
+            BinaryOperator::Ne => "<>",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Arrow => "->",
+            BinaryOperator::LongArrow => "->>",
+            BinaryOperator::HashArrow => "#>",
+            BinaryOperator::HashLongArrow => "#>>",
+            BinaryOperator::AtArrow => "@>",
+        })
+    }
+}
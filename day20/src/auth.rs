@@ -0,0 +1,712 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::catalog::Catalog;
+use crate::protocol::{
+    Connection, SqlError, StartupMessage, Stream, INVALID_AUTHORIZATION_SPECIFICATION,
+    INVALID_PASSWORD,
+};
+
+// ============================================================================
+// Client authentication
+// ============================================================================
+//
+// Roles keep a password verifier in pg_authid, never the password itself:
+// SCRAM-SHA-256 (RFC 5802 / RFC 7677) by default, or MD5, in PostgreSQL's
+// formats. Rules in pg_hba.conf pick how each connection authenticates from
+// its database, user and address.
+
+/// Rules file, in the data directory
+pub const HBA_FILE: &str = "pg_hba.conf";
+
+/// Rules used when there is no HBA_FILE: the bootstrap superuser has no
+/// password, so only loopback connections are trusted and every other
+/// address needs SCRAM-SHA-256
+const DEFAULT_HBA: &str = "host all all 127.0.0.1/32 trust\n\
+                           host all all ::1 trust\n\
+                           host all all all scram-sha-256\n";
+
+/// PBKDF2 iterations of new SCRAM verifiers, as in PostgreSQL
+const SCRAM_ITERATIONS: u32 = 4096;
+
+const SCRAM_SALT_LEN: usize = 16;
+
+const SCRAM_NONCE_LEN: usize = 18;
+
+/// Prefix of a stored SCRAM-SHA-256 verifier
+const SCRAM_PREFIX: &str = "SCRAM-SHA-256$";
+
+/// Prefix of a stored MD5 verifier
+const MD5_PREFIX: &str = "md5";
+
+const SCRAM_MECHANISM: &str = "SCRAM-SHA-256";
+
+// ----------------------------------------------------------------------------
+// pg_hba.conf
+// ----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMethod {
+    /// Accept without a password
+    Trust,
+    /// Refuse the connection
+    Reject,
+    /// MD5 challenge, or SCRAM when the role's verifier is SCRAM
+    Md5,
+    /// SCRAM-SHA-256 only
+    ScramSha256,
+}
+
+/// One line of pg_hba.conf:
+/// `host DATABASE USER ADDRESS METHOD`. DATABASE and USER are `all` or
+/// comma-separated names; ADDRESS is `all` or a CIDR block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HbaRule {
+    /// None matches all databases
+    databases: Option<Vec<String>>,
+    /// None matches all users
+    users: Option<Vec<String>>,
+    /// Network and prefix length; None matches all addresses
+    address: Option<(IpAddr, u8)>,
+    pub method: AuthMethod,
+}
+
+impl HbaRule {
+    fn matches(&self, database: &str, user: &str, addr: IpAddr) -> bool {
+        let listed = |names: &Option<Vec<String>>, name: &str| {
+            names.as_ref().is_none_or(|names| names.iter().any(|n| n == name))
+        };
+        listed(&self.databases, database)
+            && listed(&self.users, user)
+            && self.address.is_none_or(|(network, bits)| in_network(addr, network, bits))
+    }
+}
+
+/// Parse pg_hba.conf. Blank lines and `#` comments are ignored.
+pub fn parse_hba(text: &str) -> Result<Vec<HbaRule>> {
+    let mut rules = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |reason: &str| {
+            anyhow::anyhow!("invalid line {} of {HBA_FILE}: {reason}", number + 1)
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [kind, database, user, address, method] = fields[..] else {
+            return Err(invalid("expected \"host DATABASE USER ADDRESS METHOD\""));
+        };
+        if kind != "host" {
+            return Err(invalid(&format!("unsupported connection type \"{kind}\"")));
+        }
+        let names = |field: &str| match field {
+            "all" => None,
+            names => Some(names.split(',').map(str::to_string).collect()),
+        };
+        let address = match address {
+            "all" => None,
+            cidr => Some(parse_cidr(cidr).ok_or_else(|| invalid("invalid address"))?),
+        };
+        let method = match method {
+            "trust" => AuthMethod::Trust,
+            "reject" => AuthMethod::Reject,
+            "md5" => AuthMethod::Md5,
+            "scram-sha-256" => AuthMethod::ScramSha256,
+            other => return Err(invalid(&format!("unsupported method \"{other}\""))),
+        };
+        rules.push(HbaRule {
+            databases: names(database),
+            users: names(user),
+            address,
+            method,
+        });
+    }
+    Ok(rules)
+}
+
+/// How a connection authenticates: the method of the first matching rule of
+/// pg_hba.conf, read again for every connection. Without the file the
+/// DEFAULT_HBA rules apply; when no rule matches the connection is rejected.
+pub fn hba_method(database: &str, user: &str, addr: IpAddr) -> Result<AuthMethod> {
+    let rules = match fs::read_to_string(HBA_FILE) {
+        Ok(text) => parse_hba(&text)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => parse_hba(DEFAULT_HBA)?,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(rules
+        .iter()
+        .find(|rule| rule.matches(database, user, addr))
+        .map_or(AuthMethod::Reject, |rule| rule.method))
+}
+
+fn parse_cidr(text: &str) -> Option<(IpAddr, u8)> {
+    let (addr, bits) = match text.split_once('/') {
+        Some((addr, bits)) => (addr.parse::<IpAddr>().ok()?, Some(bits.parse::<u8>().ok()?)),
+        None => (text.parse::<IpAddr>().ok()?, None),
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let bits = bits.unwrap_or(max);
+    (bits <= max).then_some((addr, bits))
+}
+
+fn in_network(addr: IpAddr, network: IpAddr, bits: u8) -> bool {
+    // An IPv4 client may show up as an IPv4-mapped IPv6 address
+    let addr = match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        v4 => v4,
+    };
+    let prefix = |a: &[u8]| -> Vec<bool> {
+        a.iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+            .take(bits as usize)
+            .collect()
+    };
+    match (addr, network) {
+        (IpAddr::V4(a), IpAddr::V4(n)) => prefix(&a.octets()) == prefix(&n.octets()),
+        (IpAddr::V6(a), IpAddr::V6(n)) => prefix(&a.octets()) == prefix(&n.octets()),
+        _ => false,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Password verifiers
+// ----------------------------------------------------------------------------
+
+/// A stored password verifier
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verifier {
+    /// Hex MD5 of password + user name
+    Md5(String),
+    Scram(ScramVerifier),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScramVerifier {
+    iterations: u32,
+    salt: Vec<u8>,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl Verifier {
+    /// Parse a verifier as stored in pg_authid.rolpassword
+    pub fn parse(text: &str) -> Option<Verifier> {
+        if let Some(hex) = text.strip_prefix(MD5_PREFIX) {
+            let valid = hex.len() == 32 && hex.bytes().all(|b| b.is_ascii_hexdigit());
+            return valid.then(|| Verifier::Md5(hex.to_ascii_lowercase()));
+        }
+        // SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>
+        let rest = text.strip_prefix(SCRAM_PREFIX)?;
+        let (params, keys) = rest.split_once('$')?;
+        let (iterations, salt) = params.split_once(':')?;
+        let (stored_key, server_key) = keys.split_once(':')?;
+        Some(Verifier::Scram(ScramVerifier {
+            iterations: iterations.parse().ok()?,
+            salt: BASE64.decode(salt).ok()?,
+            stored_key: BASE64.decode(stored_key).ok()?,
+            server_key: BASE64.decode(server_key).ok()?,
+        }))
+    }
+}
+
+impl ScramVerifier {
+    fn new(password: &str, salt: Vec<u8>, iterations: u32) -> Self {
+        let salted = salted_password(password.as_bytes(), &salt, iterations);
+        let client_key = hmac(&salted, b"Client Key");
+        ScramVerifier {
+            iterations,
+            salt,
+            stored_key: Sha256::digest(&client_key).to_vec(),
+            server_key: hmac(&salted, b"Server Key"),
+        }
+    }
+
+    /// A verifier no password matches, so that an unknown role goes through
+    /// the same exchange as a wrong password
+    pub fn mock() -> Self {
+        ScramVerifier {
+            iterations: SCRAM_ITERATIONS,
+            salt: random_bytes(SCRAM_SALT_LEN),
+            stored_key: random_bytes(32),
+            server_key: random_bytes(32),
+        }
+    }
+
+    /// Text stored in pg_authid.rolpassword
+    pub fn to_stored(&self) -> String {
+        format!(
+            "{SCRAM_PREFIX}{}:{}${}:{}",
+            self.iterations,
+            BASE64.encode(&self.salt),
+            BASE64.encode(&self.stored_key),
+            BASE64.encode(&self.server_key)
+        )
+    }
+}
+
+/// The verifier stored for a new password. A password that already is an
+/// MD5 or SCRAM verifier is stored as given.
+pub fn encrypt_password(user: &str, password: &str, scram: bool) -> String {
+    if Verifier::parse(password).is_some() {
+        return password.to_string();
+    }
+    if scram {
+        ScramVerifier::new(password, random_bytes(SCRAM_SALT_LEN), SCRAM_ITERATIONS).to_stored()
+    } else {
+        format!("{MD5_PREFIX}{}", md5_hex(format!("{password}{user}").as_bytes()))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// MD5 challenge
+// ----------------------------------------------------------------------------
+
+/// A random salt for AuthenticationMD5Password
+pub fn md5_salt() -> [u8; 4] {
+    random_bytes(4).try_into().unwrap()
+}
+
+/// Whether a PasswordMessage answers the MD5 challenge: the client sends
+/// "md5" + md5(md5(password + user) + salt)
+pub fn md5_response_matches(verifier_hex: &str, salt: &[u8; 4], response: &str) -> bool {
+    let expected = md5_hex(&[verifier_hex.as_bytes(), salt].concat());
+    constant_time_eq(response.as_bytes(), format!("{MD5_PREFIX}{expected}").as_bytes())
+}
+
+// ----------------------------------------------------------------------------
+// SCRAM-SHA-256 exchange
+// ----------------------------------------------------------------------------
+
+/// Server side of a SCRAM exchange, between server-first-message and
+/// client-final-message
+pub struct ScramExchange {
+    verifier: ScramVerifier,
+    /// client-first-message-bare
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+    /// Expected c= of client-final-message: the GS2 header, base64
+    channel_binding: String,
+}
+
+impl ScramExchange {
+    /// Handle client-first-message; returns the state and the
+    /// server-first-message to send
+    pub fn start(verifier: ScramVerifier, client_first: &[u8]) -> Result<(Self, String)> {
+        let server_nonce = BASE64.encode(random_bytes(SCRAM_NONCE_LEN));
+        Self::start_with_nonce(verifier, client_first, &server_nonce)
+    }
+
+    fn start_with_nonce(
+        verifier: ScramVerifier,
+        client_first: &[u8],
+        server_nonce: &str,
+    ) -> Result<(Self, String)> {
+        let client_first = std::str::from_utf8(client_first)?;
+        // gs2-header: channel binding is not supported, so only "n" (client
+        // does not support it) and "y" (client thinks the server does not)
+        let (header, bare) = match client_first.split_once(",,") {
+            Some((header @ ("n" | "y"), bare)) => (header, bare),
+            Some((header, _)) if header.starts_with('p') => {
+                bail!("channel binding is not supported")
+            }
+            _ => bail!("malformed SCRAM message"),
+        };
+        let client_nonce = bare
+            .split(',')
+            .find_map(|attr| attr.strip_prefix("r="))
+            .filter(|nonce| !nonce.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("malformed SCRAM message: missing nonce"))?;
+
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            BASE64.encode(&verifier.salt),
+            verifier.iterations
+        );
+        let exchange = ScramExchange {
+            verifier,
+            client_first_bare: bare.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+            channel_binding: BASE64.encode(format!("{header},,")),
+        };
+        Ok((exchange, server_first))
+    }
+
+    /// Handle client-final-message; returns the server-final-message when
+    /// the client proved it knows the password, None otherwise
+    pub fn finish(&self, client_final: &[u8]) -> Result<Option<String>> {
+        let client_final = std::str::from_utf8(client_final)?;
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or_else(|| anyhow::anyhow!("malformed SCRAM message: missing proof"))?;
+        // The GS2 header of client-first-message, sent again
+        let channel_binding = without_proof.split(',').find_map(|attr| attr.strip_prefix("c="));
+        if channel_binding != Some(self.channel_binding.as_str()) {
+            return Err(SqlError::fatal(
+                INVALID_AUTHORIZATION_SPECIFICATION,
+                "SCRAM channel binding check failed",
+            )
+            .into());
+        }
+        let nonce = without_proof.split(',').find_map(|attr| attr.strip_prefix("r="));
+        if nonce != Some(self.nonce.as_str()) {
+            bail!("SCRAM nonce does not match");
+        }
+        let proof = BASE64.decode(proof)?;
+
+        let auth_message = format!(
+            "{},{},{without_proof}",
+            self.client_first_bare, self.server_first
+        );
+        let signature = hmac(&self.verifier.stored_key, auth_message.as_bytes());
+        if proof.len() != signature.len() {
+            return Ok(None);
+        }
+        // ClientKey = ClientProof XOR ClientSignature; H(ClientKey) must be
+        // the StoredKey
+        let client_key: Vec<u8> = proof.iter().zip(&signature).map(|(p, s)| p ^ s).collect();
+        if !constant_time_eq(&Sha256::digest(&client_key), &self.verifier.stored_key) {
+            return Ok(None);
+        }
+        let server_signature = hmac(&self.verifier.server_key, auth_message.as_bytes());
+        Ok(Some(format!("v={}", BASE64.encode(server_signature))))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Startup handshake
+// ----------------------------------------------------------------------------
+
+/// Authenticate a new connection as pg_hba.conf requires. Returns the role
+/// the session runs as; an error is sent to the client, which is then
+/// disconnected.
+pub fn authenticate<S: Stream>(
+    conn: &mut Connection<S>,
+    catalog: &Catalog,
+    startup: &StartupMessage,
+) -> Result<String> {
+    let param = |name: &str| {
+        startup
+            .params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let Some(user) = param("user") else {
+        return Err(SqlError::fatal(
+            INVALID_AUTHORIZATION_SPECIFICATION,
+            "no PostgreSQL user name specified in startup packet",
+        )
+        .into());
+    };
+    let database = param("database").unwrap_or(user);
+    let method = hba_method(database, user, conn.peer_addr()?)?;
+    authenticate_with(conn, catalog, user, database, method)
+}
+
+/// Authenticate `user` by `method`, the pg_hba.conf rule for the connection
+fn authenticate_with<S: Stream>(
+    conn: &mut Connection<S>,
+    catalog: &Catalog,
+    user: &str,
+    database: &str,
+    method: AuthMethod,
+) -> Result<String> {
+    let addr = conn.peer_addr()?;
+    let fatal = |code, message: String| Err(SqlError::fatal(code, message).into());
+
+    let role = catalog.get_role(user);
+    match method {
+        AuthMethod::Trust => {}
+        AuthMethod::Reject => {
+            return fatal(
+                INVALID_AUTHORIZATION_SPECIFICATION,
+                format!(
+                    "no {HBA_FILE} entry for host \"{addr}\", user \"{user}\", \
+                     database \"{database}\""
+                ),
+            )
+        }
+        AuthMethod::Md5 | AuthMethod::ScramSha256 => {
+            let verifier = role
+                .as_ref()
+                .and_then(|role| role.password.as_deref())
+                .and_then(Verifier::parse);
+            let authenticated = match (method, verifier) {
+                (AuthMethod::Md5, Some(Verifier::Md5(hex))) => {
+                    let salt = md5_salt();
+                    conn.send_auth_md5_password(&salt)?;
+                    let response = conn.read_password_message()?;
+                    let response = String::from_utf8_lossy(&response);
+                    md5_response_matches(&hex, &salt, response.trim_end_matches('\0'))
+                }
+                (_, Some(Verifier::Scram(verifier))) => authenticate_scram(conn, verifier)?,
+                // An MD5 verifier cannot check a SCRAM proof. Unknown roles
+                // and roles without a password get the same exchange as a
+                // wrong password, so clients cannot tell them apart.
+                _ => {
+                    authenticate_scram(conn, ScramVerifier::mock())?;
+                    false
+                }
+            };
+            if !authenticated {
+                return fatal(
+                    INVALID_PASSWORD,
+                    format!("password authentication failed for user \"{user}\""),
+                );
+            }
+        }
+    }
+
+    match role {
+        None => fatal(
+            INVALID_AUTHORIZATION_SPECIFICATION,
+            format!("role \"{user}\" does not exist"),
+        ),
+        Some(role) if !role.can_login => fatal(
+            INVALID_AUTHORIZATION_SPECIFICATION,
+            format!("role \"{user}\" is not permitted to log in"),
+        ),
+        Some(role) => Ok(role.name),
+    }
+}
+
+/// Run a SCRAM-SHA-256 exchange; returns whether the client proved it knows
+/// the password
+fn authenticate_scram<S: Stream>(conn: &mut Connection<S>, verifier: ScramVerifier) -> Result<bool> {
+    conn.send_auth_sasl(&[SCRAM_MECHANISM])?;
+    let (mechanism, client_first) = conn.read_sasl_initial_response()?;
+    if mechanism != SCRAM_MECHANISM {
+        bail!("client selected an invalid SASL authentication mechanism");
+    }
+    let (exchange, server_first) = ScramExchange::start(verifier, &client_first)?;
+    conn.send_auth_sasl_continue(server_first.as_bytes())?;
+    let client_final = conn.read_password_message()?;
+    match exchange.finish(&client_final)? {
+        Some(server_final) => {
+            conn.send_auth_sasl_final(server_final.as_bytes())?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Primitives
+// ----------------------------------------------------------------------------
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// PBKDF2-HMAC-SHA-256 with a single output block, SCRAM's Hi()
+fn salted_password(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut u = hmac(password, &[salt, &1u32.to_be_bytes()].concat());
+    let mut result = u.clone();
+    for _ in 1..iterations {
+        u = hmac(password, &u);
+        result.iter_mut().zip(&u).for_each(|(r, x)| *r ^= x);
+    }
+    result
+}
+
+fn md5_hex(data: &[u8]) -> String {
+    Md5::digest(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    getrandom::fill(&mut buf).expect("no system random source");
+    buf
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::testing::TestDb;
+    use crate::protocol::Severity;
+    use crate::settings;
+
+    #[test]
+    fn test_scram_rfc7677_example() {
+        // The exchange from RFC 7677, section 3
+        let salt = BASE64.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let verifier = ScramVerifier::new("pencil", salt, 4096);
+        let (exchange, server_first) = ScramExchange::start_with_nonce(
+            verifier,
+            b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO",
+            "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0",
+        )
+        .unwrap();
+        assert_eq!(
+            server_first,
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        );
+        let client_final = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                            p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+        assert_eq!(
+            exchange.finish(client_final.as_bytes()).unwrap().as_deref(),
+            Some("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+        );
+        let wrong = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                     p=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        assert_eq!(exchange.finish(wrong.as_bytes()).unwrap(), None);
+        assert!(exchange.finish(b"c=biws,r=other,p=AAAA").is_err());
+
+        // c= must repeat the GS2 header "n,,"; "y,," would be eSws
+        for binding in ["eSws", "", "biwsAA=="] {
+            let tampered = client_final.replacen("c=biws", &format!("c={binding}"), 1);
+            let error = exchange.finish(tampered.as_bytes()).unwrap_err();
+            assert_eq!(error.to_string(), "SCRAM channel binding check failed");
+            let sql_error = SqlError::find(&error).unwrap();
+            assert_eq!(sql_error.code, INVALID_AUTHORIZATION_SPECIFICATION);
+        }
+        let without_binding = client_final.replacen("c=biws,", "", 1);
+        assert!(exchange.finish(without_binding.as_bytes()).is_err());
+
+        let (exchange, _) = ScramExchange::start_with_nonce(
+            ScramVerifier::new("pencil", vec![0; 16], 4096),
+            b"y,,n=user,r=abc",
+            "def",
+        )
+        .unwrap();
+        let error = exchange.finish(b"c=biws,r=abcdef,p=AAAA").unwrap_err();
+        assert_eq!(error.to_string(), "SCRAM channel binding check failed");
+        assert_eq!(exchange.finish(b"c=eSws,r=abcdef,p=AAAA").unwrap(), None);
+    }
+
+    #[test]
+    fn test_stored_verifiers() {
+        let stored = encrypt_password("alice", "secret", true);
+        assert!(stored.starts_with("SCRAM-SHA-256$4096:"));
+        let Some(Verifier::Scram(verifier)) = Verifier::parse(&stored) else {
+            panic!("not a SCRAM verifier: {stored}");
+        };
+        assert_eq!(verifier.to_stored(), stored);
+        // A verifier given as the password is kept as is
+        assert_eq!(encrypt_password("bob", &stored, false), stored);
+
+        // md5(password + user), as PostgreSQL stores it
+        let md5 = encrypt_password("alice", "secret", false);
+        assert_eq!(md5, format!("md5{}", md5_hex(b"secretalice")));
+        let Some(Verifier::Md5(hex)) = Verifier::parse(&md5) else {
+            panic!("not an MD5 verifier: {md5}");
+        };
+        let salt = [1, 2, 3, 4];
+        let response = format!("md5{}", md5_hex(&[hex.as_bytes(), &salt].concat()));
+        assert!(md5_response_matches(&hex, &salt, &response));
+        assert!(!md5_response_matches(&hex, &[0, 0, 0, 0], &response));
+        assert_eq!(Verifier::parse("plain"), None);
+    }
+
+    #[test]
+    fn test_authentication_errors_are_fatal() {
+        let db = TestDb::new();
+        settings::set_session_user("postgres");
+        let verifier = encrypt_password("alice", "secret", false);
+        db.query(&format!("INSERT INTO pg_authid VALUES ('alice', false, true, '{verifier}')"));
+        db.query("INSERT INTO pg_authid VALUES ('nobody', false, false, NULL)");
+
+        let authenticate = |user: &str, method: AuthMethod, input: Vec<u8>| {
+            let mut conn = Connection::for_test(input);
+            authenticate_with(&mut conn, &db.catalog, user, "postgres", method)
+        };
+        let failure = |result: Result<String>| {
+            let error = result.unwrap_err();
+            let sql_error = SqlError::find(&error).unwrap();
+            assert_eq!(sql_error.severity, Severity::Fatal, "{error}");
+            (sql_error.code, error.to_string())
+        };
+
+        assert_eq!(authenticate("alice", AuthMethod::Trust, vec![]).unwrap(), "alice");
+        assert_eq!(
+            failure(authenticate("alice", AuthMethod::Reject, vec![])),
+            (
+                INVALID_AUTHORIZATION_SPECIFICATION,
+                "no pg_hba.conf entry for host \"127.0.0.1\", user \"alice\", \
+                 database \"postgres\""
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            failure(authenticate("bob", AuthMethod::Trust, vec![])),
+            (INVALID_AUTHORIZATION_SPECIFICATION, "role \"bob\" does not exist".to_string())
+        );
+        assert_eq!(
+            failure(authenticate("nobody", AuthMethod::Trust, vec![])),
+            (
+                INVALID_AUTHORIZATION_SPECIFICATION,
+                "role \"nobody\" is not permitted to log in".to_string()
+            )
+        );
+
+        // A PasswordMessage with the MD5 response of the wrong password
+        let response = format!("md5{}\0", md5_hex(b"wrong"));
+        let input = [
+            &[b'p'][..],
+            &(response.len() as i32 + 4).to_be_bytes(),
+            response.as_bytes(),
+        ]
+        .concat();
+        assert_eq!(
+            failure(authenticate("alice", AuthMethod::Md5, input)),
+            (
+                INVALID_PASSWORD,
+                "password authentication failed for user \"alice\"".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_hba_rules() {
+        let rules = parse_hba(
+            "# local admin\n\
+             host all postgres 127.0.0.1/32 trust\n\
+             host sales alice,bob 10.0.0.0/8 md5\n\
+             host all all ::1 scram-sha-256\n\
+             host all all all reject   # everyone else\n",
+        )
+        .unwrap();
+        let method = |db: &str, user: &str, addr: &str| {
+            let addr = addr.parse().unwrap();
+            rules.iter().find(|r| r.matches(db, user, addr)).map(|r| r.method)
+        };
+        assert_eq!(method("x", "postgres", "127.0.0.1"), Some(AuthMethod::Trust));
+        assert_eq!(method("x", "postgres", "::ffff:127.0.0.1"), Some(AuthMethod::Trust));
+        assert_eq!(method("sales", "bob", "10.1.2.3"), Some(AuthMethod::Md5));
+        assert_eq!(method("hr", "bob", "10.1.2.3"), Some(AuthMethod::Reject));
+        assert_eq!(method("hr", "bob", "::1"), Some(AuthMethod::ScramSha256));
+        assert!(parse_hba("host all all all password").is_err());
+        assert!(parse_hba("local all all trust").is_err());
+        assert!(parse_hba("host all all 10.0.0.0/33 trust").is_err());
+
+        // Without pg_hba.conf only loopback connections are trusted
+        let rules = parse_hba(DEFAULT_HBA).unwrap();
+        let method = |addr: &str| {
+            let addr = addr.parse().unwrap();
+            rules.iter().find(|r| r.matches("x", "postgres", addr)).map(|r| r.method)
+        };
+        assert_eq!(method("127.0.0.1"), Some(AuthMethod::Trust));
+        assert_eq!(method("::ffff:127.0.0.1"), Some(AuthMethod::Trust));
+        assert_eq!(method("::1"), Some(AuthMethod::Trust));
+        assert_eq!(method("127.0.0.2"), Some(AuthMethod::ScramSha256));
+        assert_eq!(method("10.1.2.3"), Some(AuthMethod::ScramSha256));
+    }
+}
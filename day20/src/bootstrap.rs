@@ -3,6 +3,7 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 
 use crate::buffer_pool::BufferPoolManager;
+use crate::collation::Collation;
 use crate::page::NO_NEXT_PAGE;
 use crate::transaction_manager::{TransactionManager, TxnStatus};
 use crate::tuple::{serialize_tuple_mvcc, Value};
@@ -11,23 +12,49 @@ use crate::tuple::{serialize_tuple_mvcc, Value};
 pub const PG_CLASS_TABLE_ID: u32 = 0;
 pub const PG_ATTRIBUTE_TABLE_ID: u32 = 1;
 pub const PG_INDEX_TABLE_ID: u32 = 2;
+pub const PG_AUTHID_TABLE_ID: u32 = 3;
+/// Table ID of the first table created by CREATE TABLE
+pub const FIRST_USER_TABLE_ID: u32 = 4;
 
 // System table page IDs
 pub const PG_CLASS_PAGE_ID: u32 = 0;
 pub const PG_ATTRIBUTE_PAGE_ID: u32 = 1;
 pub const PG_INDEX_PAGE_ID: u32 = 2;
+pub const PG_AUTHID_PAGE_ID: u32 = 3;
 
 // Data type constants for pg_attribute
 pub const DATA_TYPE_INT: i32 = 0;
 pub const DATA_TYPE_VARCHAR: i32 = 1;
 pub const DATA_TYPE_BOOL: i32 = 2;
+pub const DATA_TYPE_SMALLINT: i32 = 3;
+pub const DATA_TYPE_BIGINT: i32 = 4;
+pub const DATA_TYPE_REAL: i32 = 5;
+pub const DATA_TYPE_DOUBLE: i32 = 6;
+pub const DATA_TYPE_NUMERIC: i32 = 7;
+pub const DATA_TYPE_DATE: i32 = 8;
+pub const DATA_TYPE_TIME: i32 = 9;
+pub const DATA_TYPE_TIMESTAMP: i32 = 10;
+pub const DATA_TYPE_TIMESTAMPTZ: i32 = 11;
+pub const DATA_TYPE_INTERVAL: i32 = 12;
+pub const DATA_TYPE_TEXT: i32 = 13;
+pub const DATA_TYPE_CHAR: i32 = 14;
+pub const DATA_TYPE_BYTEA: i32 = 15;
+pub const DATA_TYPE_JSONB: i32 = 16;
+// An array type is stored as this offset plus its element type
+pub const DATA_TYPE_ARRAY: i32 = 100;
 
 /// System transaction ID used for bootstrap (reserved)
 pub const SYSTEM_TXN_ID: u64 = 1;
 
 /// Bootstrap the database by creating system catalog tables.
 /// This should only be called on a fresh database (init=true).
-pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionManager) -> Result<()> {
+/// `superuser` names the role created with it, which can log in without a
+/// password.
+pub fn bootstrap(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    txn_manager: &TransactionManager,
+    superuser: &str,
+) -> Result<()> {
     println!("[Bootstrap] Initializing system catalog...");
 
     let mut bpm_guard = bpm.lock().unwrap();
@@ -40,7 +67,7 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
         page.set_next_page_id(NO_NEXT_PAGE);
 
         // Insert pg_class entries
-        // pg_class schema: (table_id: Int, name: Varchar, first_page_id: Int)
+        // pg_class schema: (table_id: Int, name: Varchar, first_page_id: Int, toast_page_id: Int)
         // Using xmin=SYSTEM_TXN_ID, xmax=0 (system transaction, not deleted)
 
         // Entry for pg_class itself
@@ -51,6 +78,7 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
                 Value::Int(PG_CLASS_TABLE_ID as i32),
                 Value::Varchar("pg_class".to_string()),
                 Value::Int(PG_CLASS_PAGE_ID as i32),
+                Value::Int(NO_NEXT_PAGE as i32),
             ],
         );
         page.insert(&pg_class_tuple)?;
@@ -63,6 +91,7 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
                 Value::Int(PG_ATTRIBUTE_TABLE_ID as i32),
                 Value::Varchar("pg_attribute".to_string()),
                 Value::Int(PG_ATTRIBUTE_PAGE_ID as i32),
+                Value::Int(NO_NEXT_PAGE as i32),
             ],
         );
         page.insert(&pg_attribute_tuple)?;
@@ -75,9 +104,24 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
                 Value::Int(PG_INDEX_TABLE_ID as i32),
                 Value::Varchar("pg_index".to_string()),
                 Value::Int(PG_INDEX_PAGE_ID as i32),
+                Value::Int(NO_NEXT_PAGE as i32),
             ],
         );
         page.insert(&pg_index_tuple)?;
+
+        // Entry for pg_authid; unlike the tables above it is found through
+        // pg_class and pg_attribute like a user table
+        let pg_authid_tuple = serialize_tuple_mvcc(
+            SYSTEM_TXN_ID, // xmin
+            0,             // xmax
+            &[
+                Value::Int(PG_AUTHID_TABLE_ID as i32),
+                Value::Varchar("pg_authid".to_string()),
+                Value::Int(PG_AUTHID_PAGE_ID as i32),
+                Value::Int(NO_NEXT_PAGE as i32),
+            ],
+        );
+        page.insert(&pg_authid_tuple)?;
     }
     bpm_guard.unpin_page(pg_class_page_id, true)?;
 
@@ -89,26 +133,36 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
         page.set_next_page_id(NO_NEXT_PAGE);
 
         // Insert pg_attribute entries
-        // pg_attribute schema: (table_id: Int, column_name: Varchar, data_type: Int, nullable: Bool, ordinal_position: Int)
+        // pg_attribute schema: (table_id: Int, column_name: Varchar, data_type: Int, nullable: Bool, ordinal_position: Int, typmod: Int, collation: Int)
 
         // Columns for pg_class (table_id=0)
         let columns = [
             (PG_CLASS_TABLE_ID, "table_id", DATA_TYPE_INT, false, 0),
             (PG_CLASS_TABLE_ID, "name", DATA_TYPE_VARCHAR, false, 1),
             (PG_CLASS_TABLE_ID, "first_page_id", DATA_TYPE_INT, false, 2),
+            (PG_CLASS_TABLE_ID, "toast_page_id", DATA_TYPE_INT, false, 3), // -1 = none
             // Columns for pg_attribute (table_id=1)
             (PG_ATTRIBUTE_TABLE_ID, "table_id", DATA_TYPE_INT, false, 0),
             (PG_ATTRIBUTE_TABLE_ID, "column_name", DATA_TYPE_VARCHAR, false, 1),
             (PG_ATTRIBUTE_TABLE_ID, "data_type", DATA_TYPE_INT, false, 2),
             (PG_ATTRIBUTE_TABLE_ID, "nullable", DATA_TYPE_BOOL, false, 3),
             (PG_ATTRIBUTE_TABLE_ID, "ordinal_position", DATA_TYPE_INT, false, 4),
+            (PG_ATTRIBUTE_TABLE_ID, "typmod", DATA_TYPE_INT, false, 5), // -1 = none
+            (PG_ATTRIBUTE_TABLE_ID, "collation", DATA_TYPE_INT, false, 6), // Collation::id
             // Columns for pg_index (table_id=2)
-            // pg_index schema: (index_id, index_name, table_id, column_ids, root_page_id)
+            // pg_index schema: (index_id, index_name, table_id, column_ids, root_page_id, is_unique, expressions)
             (PG_INDEX_TABLE_ID, "index_id", DATA_TYPE_INT, false, 0),
             (PG_INDEX_TABLE_ID, "index_name", DATA_TYPE_VARCHAR, false, 1),
             (PG_INDEX_TABLE_ID, "table_id", DATA_TYPE_INT, false, 2),
             (PG_INDEX_TABLE_ID, "column_ids", DATA_TYPE_VARCHAR, false, 3), // comma-separated
             (PG_INDEX_TABLE_ID, "root_page_id", DATA_TYPE_INT, false, 4),
+            (PG_INDEX_TABLE_ID, "is_unique", DATA_TYPE_BOOL, false, 5),
+            (PG_INDEX_TABLE_ID, "expressions", DATA_TYPE_VARCHAR, true, 6), // SQL of expression keys
+            // Columns for pg_authid (table_id=3)
+            (PG_AUTHID_TABLE_ID, "rolname", DATA_TYPE_VARCHAR, false, 0),
+            (PG_AUTHID_TABLE_ID, "rolsuper", DATA_TYPE_BOOL, false, 1),
+            (PG_AUTHID_TABLE_ID, "rolcanlogin", DATA_TYPE_BOOL, false, 2),
+            (PG_AUTHID_TABLE_ID, "rolpassword", DATA_TYPE_VARCHAR, true, 3), // verifier, see auth.rs
         ];
 
         for (table_id, col_name, data_type, nullable, ordinal) in columns {
@@ -121,6 +175,8 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
                     Value::Int(data_type),
                     Value::Bool(nullable),
                     Value::Int(ordinal),
+                    Value::Int(-1),
+                    Value::Int(Collation::C.id()),
                 ],
             );
             page.insert(&tuple)?;
@@ -138,6 +194,27 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
     }
     bpm_guard.unpin_page(pg_index_page_id, true)?;
 
+    // Create page 3 for pg_authid, holding the bootstrap superuser
+    let (pg_authid_page_id, pg_authid_page_arc) = bpm_guard.new_page()?;
+    assert_eq!(pg_authid_page_id, PG_AUTHID_PAGE_ID);
+    {
+        let mut page = pg_authid_page_arc.write().unwrap();
+        page.set_next_page_id(NO_NEXT_PAGE);
+        // pg_authid schema: (rolname, rolsuper, rolcanlogin, rolpassword)
+        let superuser_tuple = serialize_tuple_mvcc(
+            SYSTEM_TXN_ID, // xmin
+            0,             // xmax
+            &[
+                Value::Varchar(superuser.to_string()),
+                Value::Bool(true),
+                Value::Bool(true),
+                Value::Null,
+            ],
+        );
+        page.insert(&superuser_tuple)?;
+    }
+    bpm_guard.unpin_page(pg_authid_page_id, true)?;
+
     // Flush to disk
     bpm_guard.flush_all()?;
 
@@ -151,6 +228,7 @@ pub fn bootstrap(bpm: &Arc<Mutex<BufferPoolManager>>, txn_manager: &TransactionM
     println!("[Bootstrap]   - pg_class (table_id=0, page_id=0)");
     println!("[Bootstrap]   - pg_attribute (table_id=1, page_id=1)");
     println!("[Bootstrap]   - pg_index (table_id=2, page_id=2)");
+    println!("[Bootstrap]   - pg_authid (table_id=3, page_id=3), superuser \"{superuser}\"");
 
     Ok(())
 }
@@ -1,19 +1,27 @@
 use std::cmp::Ordering;
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use crate::buffer_pool::BufferPoolManager;
 use crate::executor::Rid;
 use crate::page::btree_internal::InternalNode;
 use crate::page::btree_leaf::LeafNode;
 use crate::page::btree_meta::MetaNode;
-use crate::tuple::{DataType, Value};
+use crate::page::PAGE_SIZE;
+use crate::datetime::Interval;
+use crate::json::Json;
+use crate::numeric::Numeric;
+use crate::tuple::{compare_datetimes, compare_numbers, DataType, Value};
 
 // ============================================================================
 // IndexKey - Composite key for B-Tree index
 // ============================================================================
 
+/// Largest serialized key accepted by insert. Keeping keys to a quarter of a
+/// page guarantees that either half of a split node has room for one more.
+pub const MAX_KEY_SIZE: usize = PAGE_SIZE / 4;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct IndexKey(pub Vec<Value>);
 
@@ -33,78 +41,230 @@ impl IndexKey {
         // Write number of values
         buf.push(self.0.len() as u8);
         for value in &self.0 {
-            match value {
-                Value::Null => {
-                    buf.push(0); // type tag
-                }
-                Value::Int(n) => {
-                    buf.push(1); // type tag
-                    buf.extend_from_slice(&n.to_le_bytes());
-                }
-                Value::Varchar(s) => {
-                    buf.push(2); // type tag
-                    let bytes = s.as_bytes();
-                    buf.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
-                    buf.extend_from_slice(bytes);
-                }
-                Value::Bool(b) => {
-                    buf.push(3); // type tag
-                    buf.push(if *b { 1 } else { 0 });
-                }
-            }
+            write_value(&mut buf, value);
         }
         buf
     }
 
     /// Deserialize key from bytes
-    pub fn deserialize(data: &[u8]) -> Self {
+    pub fn deserialize(data: &[u8]) -> Result<Self> {
         let mut pos = 0;
         let count = data[pos] as usize;
         pos += 1;
 
         let mut values = Vec::with_capacity(count);
         for _ in 0..count {
-            let type_tag = data[pos];
-            pos += 1;
-            let value = match type_tag {
-                0 => Value::Null,
-                1 => {
-                    let n = i32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
-                    pos += 4;
-                    Value::Int(n)
-                }
-                2 => {
-                    let len = u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()) as usize;
-                    pos += 2;
-                    let s = String::from_utf8_lossy(&data[pos..pos + len]).to_string();
-                    pos += len;
-                    Value::Varchar(s)
-                }
-                3 => {
-                    let b = data[pos] != 0;
-                    pos += 1;
-                    Value::Bool(b)
-                }
-                _ => Value::Null,
-            };
-            values.push(value);
+            values.push(read_value(data, &mut pos)?);
         }
-        IndexKey(values)
+        Ok(IndexKey(values))
     }
 
     /// Get serialized size
     pub fn serialized_size(&self) -> usize {
-        let mut size = 1; // count byte
-        for value in &self.0 {
-            size += 1; // type tag
-            match value {
-                Value::Null => {}
-                Value::Int(_) => size += 4,
-                Value::Varchar(s) => size += 2 + s.len(),
-                Value::Bool(_) => size += 1,
+        1 + self.0.iter().map(value_size).sum::<usize>() // count byte + values
+    }
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => {
+            buf.push(0); // type tag
+        }
+        Value::Int(n) => {
+            buf.push(1); // type tag
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        Value::Varchar(s) => {
+            buf.push(2); // type tag
+            let bytes = s.as_bytes();
+            buf.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+            buf.extend_from_slice(bytes);
+        }
+        Value::Bool(b) => {
+            buf.push(3); // type tag
+            buf.push(if *b { 1 } else { 0 });
+        }
+        Value::SmallInt(n) => {
+            buf.push(4); // type tag
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        Value::BigInt(n) => {
+            buf.push(5); // type tag
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        Value::Real(f) => {
+            buf.push(6); // type tag
+            buf.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Double(f) => {
+            buf.push(7); // type tag
+            buf.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Numeric(n) => {
+            buf.push(8); // type tag
+            let text = n.to_string();
+            buf.extend_from_slice(&(text.len() as u16).to_le_bytes());
+            buf.extend_from_slice(text.as_bytes());
+        }
+        Value::Date(d) => {
+            buf.push(9); // type tag
+            buf.extend_from_slice(&d.to_le_bytes());
+        }
+        Value::Time(t) => {
+            buf.push(10); // type tag
+            buf.extend_from_slice(&t.to_le_bytes());
+        }
+        Value::Timestamp(ts) => {
+            buf.push(11); // type tag
+            buf.extend_from_slice(&ts.to_le_bytes());
+        }
+        Value::TimestampTz(ts) => {
+            buf.push(12); // type tag
+            buf.extend_from_slice(&ts.to_le_bytes());
+        }
+        Value::Bytea(b) => {
+            buf.push(14); // type tag
+            buf.extend_from_slice(&(b.len() as u16).to_le_bytes());
+            buf.extend_from_slice(b);
+        }
+        Value::Interval(i) => {
+            buf.push(13); // type tag
+            buf.extend_from_slice(&i.months.to_le_bytes());
+            buf.extend_from_slice(&i.days.to_le_bytes());
+            buf.extend_from_slice(&i.micros.to_le_bytes());
+        }
+        Value::Jsonb(j) => {
+            buf.push(15); // type tag
+            let bytes = j.to_bytes();
+            buf.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&bytes);
+        }
+        Value::Array(elements) => {
+            buf.push(16); // type tag
+            buf.extend_from_slice(&(elements.len() as u16).to_le_bytes());
+            for element in elements {
+                write_value(buf, element);
             }
         }
-        size
+        Value::Toast(_) => unreachable!("index keys are built from detoasted values"),
+    }
+}
+
+fn read_value(data: &[u8], offset: &mut usize) -> Result<Value> {
+    let mut pos = *offset;
+    let type_tag = data[pos];
+    pos += 1;
+    let value = match type_tag {
+        0 => Value::Null,
+        1 => {
+            let n = i32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+            pos += 4;
+            Value::Int(n)
+        }
+        2 => {
+            let len = u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()) as usize;
+            pos += 2;
+            let s = String::from_utf8_lossy(&data[pos..pos + len]).to_string();
+            pos += len;
+            Value::Varchar(s)
+        }
+        3 => {
+            let b = data[pos] != 0;
+            pos += 1;
+            Value::Bool(b)
+        }
+        4 => {
+            let n = i16::from_le_bytes(data[pos..pos + 2].try_into().unwrap());
+            pos += 2;
+            Value::SmallInt(n)
+        }
+        5 => {
+            let n = i64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+            pos += 8;
+            Value::BigInt(n)
+        }
+        6 => {
+            let f = f32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+            pos += 4;
+            Value::Real(f)
+        }
+        7 => {
+            let f = f64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+            pos += 8;
+            Value::Double(f)
+        }
+        8 => {
+            let len = u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()) as usize;
+            pos += 2;
+            let text = String::from_utf8_lossy(&data[pos..pos + len]).to_string();
+            pos += len;
+            Value::Numeric(Numeric::parse(&text)?)
+        }
+        9 => {
+            let d = i32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+            pos += 4;
+            Value::Date(d)
+        }
+        10..=12 => {
+            let n = i64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+            pos += 8;
+            match type_tag {
+                10 => Value::Time(n),
+                11 => Value::Timestamp(n),
+                _ => Value::TimestampTz(n),
+            }
+        }
+        13 => {
+            let months = i32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+            let days = i32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap());
+            let micros = i64::from_le_bytes(data[pos + 8..pos + 16].try_into().unwrap());
+            pos += 16;
+            Value::Interval(Interval::new(months, days, micros))
+        }
+        14 => {
+            let len = u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()) as usize;
+            pos += 2;
+            let bytes = data[pos..pos + len].to_vec();
+            pos += len;
+            Value::Bytea(bytes)
+        }
+        15 => {
+            let len = u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()) as usize;
+            pos += 2;
+            let (json, _) = Json::from_bytes(&data[pos..pos + len])?;
+            pos += len;
+            Value::Jsonb(json)
+        }
+        16 => {
+            let count = u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()) as usize;
+            pos += 2;
+            Value::Array((0..count).map(|_| read_value(data, &mut pos)).collect::<Result<_>>()?)
+        }
+        _ => bail!("invalid type tag {type_tag} in index key"),
+    };
+    *offset = pos;
+    Ok(value)
+}
+
+fn value_size(value: &Value) -> usize {
+    // Type tag, then the payload
+    1 + match value {
+        Value::Null => 0,
+        Value::Int(_) => 4,
+        Value::Varchar(s) => 2 + s.len(),
+        Value::Bool(_) => 1,
+        Value::SmallInt(_) => 2,
+        Value::BigInt(_) => 8,
+        Value::Real(_) => 4,
+        Value::Double(_) => 8,
+        Value::Numeric(n) => 2 + n.to_string().len(),
+        Value::Date(_) => 4,
+        Value::Time(_) | Value::Timestamp(_) | Value::TimestampTz(_) => 8,
+        Value::Interval(_) => 16,
+        Value::Bytea(b) => 2 + b.len(),
+        Value::Jsonb(j) => 2 + j.to_bytes().len(),
+        Value::Array(elements) => 2 + elements.iter().map(value_size).sum::<usize>(),
+        Value::Toast(_) => unreachable!("index keys are built from detoasted values"),
     }
 }
 
@@ -127,18 +287,46 @@ impl Ord for IndexKey {
 }
 
 fn compare_values(a: &Value, b: &Value) -> Ordering {
+    // Numbers of any width or type compare by value
+    if let Some(ord) = compare_numbers(a, b) {
+        return ord;
+    }
+    // Dates and timestamps likewise compare on a common timeline
+    if let Some(ord) = compare_datetimes(a, b) {
+        return ord;
+    }
     match (a, b) {
-        (Value::Null, Value::Null) => Ordering::Equal,
-        (Value::Null, _) => Ordering::Less,
-        (_, Value::Null) => Ordering::Greater,
-        (Value::Int(a), Value::Int(b)) => a.cmp(b),
         (Value::Varchar(a), Value::Varchar(b)) => a.cmp(b),
         (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
-        // Type mismatch: order by type discriminant for deterministic ordering
-        (Value::Int(_), _) => Ordering::Less,
-        (_, Value::Int(_)) => Ordering::Greater,
-        (Value::Varchar(_), _) => Ordering::Less,
-        (_, Value::Varchar(_)) => Ordering::Greater,
+        (Value::Bytea(a), Value::Bytea(b)) => a.cmp(b),
+        (Value::Jsonb(a), Value::Jsonb(b)) => a.cmp(b),
+        // Element by element, then by length
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(x, y)| compare_values(x, y))
+            .find(|ord| ord.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        // Type mismatch: order by type for deterministic ordering
+        (a, b) => type_order(a).cmp(&type_order(b)),
+    }
+}
+
+/// Sort position of a value's type when comparing mismatched types:
+/// NULL < numbers < varchar < bool < date/timestamp < time < interval < bytea
+/// < jsonb < array
+fn type_order(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Varchar(_) => 2,
+        Value::Bool(_) => 3,
+        Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_) => 4,
+        Value::Time(_) => 5,
+        Value::Interval(_) => 6,
+        Value::Bytea(_) => 7,
+        Value::Jsonb(_) => 8,
+        Value::Array(_) => 9,
+        _ => 1,
     }
 }
 
@@ -258,7 +446,49 @@ impl BTree {
         drop(page_guard);
         bpm.unpin_page(leaf_page_id, false)?;
 
-        Ok(result)
+        result
+    }
+
+    /// Return the RIDs of every entry whose key equals `key` (duplicates included)
+    pub fn search_all(&self, key: &IndexKey) -> Result<Vec<Rid>> {
+        let root_id = match self.get_root_page_id()? {
+            Some(id) => id,
+            None => return Ok(vec![]),
+        };
+
+        // Duplicates may span several leaves, so start from the first one holding the key
+        let leaf_id = self.find_leaf(root_id, key)?;
+        let mut current_leaf = Some(self.find_first_leaf_with_key(leaf_id, key)?);
+
+        let mut rids = Vec::new();
+        while let Some(leaf_id) = current_leaf {
+            let mut bpm = self.bpm.lock().unwrap();
+            let page = bpm.fetch_page(leaf_id)?;
+            let page_guard = page.read().unwrap();
+
+            let passed_key = Self::collect_equal_rids(&page_guard.data, key, &mut rids);
+            current_leaf = LeafNode::get_next_leaf(&page_guard.data);
+            drop(page_guard);
+            bpm.unpin_page(leaf_id, false)?;
+            if passed_key? {
+                return Ok(rids);
+            }
+        }
+
+        Ok(rids)
+    }
+
+    /// Add the RIDs of a leaf's entries equal to `key` to `rids`. Returns true
+    /// if the leaf also holds a greater key, so later leaves hold no more.
+    fn collect_equal_rids(data: &[u8], key: &IndexKey, rids: &mut Vec<Rid>) -> Result<bool> {
+        let count = LeafNode::key_count(data);
+        for i in LeafNode::find_key_position(data, key)?..count {
+            if LeafNode::get_key(data, i)? != *key {
+                return Ok(true);
+            }
+            rids.push(LeafNode::get_rid(data, i));
+        }
+        Ok(false)
     }
 
     /// Find the leaf page that should contain the given key
@@ -281,7 +511,7 @@ impl BTree {
             drop(page_guard);
             bpm.unpin_page(current_page_id, false)?;
 
-            current_page_id = child_page_id;
+            current_page_id = child_page_id?;
         }
     }
 
@@ -291,6 +521,15 @@ impl BTree {
 
     /// Insert a key-RID pair into the B-Tree
     pub fn insert(&mut self, key: &IndexKey, rid: Rid) -> Result<()> {
+        let key_size = key.serialized_size();
+        if key_size > MAX_KEY_SIZE {
+            bail!(
+                "index row size {} exceeds btree maximum {}",
+                key_size,
+                MAX_KEY_SIZE
+            );
+        }
+
         // If tree is empty, create it
         if self.meta_page_id.is_none() {
             self.create_empty()?;
@@ -328,7 +567,7 @@ impl BTree {
             let mut new_page_guard = new_page.write().unwrap();
 
             // Split the node
-            let split_key = LeafNode::split(&mut page_guard.data, &mut new_page_guard.data);
+            let split_key = LeafNode::split(&mut page_guard.data, &mut new_page_guard.data)?;
 
             // Link the leaves
             LeafNode::set_next_leaf(&mut page_guard.data, Some(new_page_id));
@@ -336,11 +575,9 @@ impl BTree {
 
             // Determine which node should receive the new key
             if *key < split_key {
-                LeafNode::insert(&mut page_guard.data, key, rid)
-                    .expect("should have space after split");
+                LeafNode::insert(&mut page_guard.data, key, rid)?;
             } else {
-                LeafNode::insert(&mut new_page_guard.data, key, rid)
-                    .expect("should have space after split");
+                LeafNode::insert(&mut new_page_guard.data, key, rid)?;
             }
 
             drop(new_page_guard);
@@ -352,7 +589,7 @@ impl BTree {
         }
 
         // No split needed - just insert
-        LeafNode::insert(&mut page_guard.data, key, rid).expect("should have space");
+        LeafNode::insert(&mut page_guard.data, key, rid)?;
         drop(page_guard);
         bpm.unpin_page(page_id, true)?;
 
@@ -441,6 +678,7 @@ impl BTree {
 
         drop(page_guard);
         bpm.unpin_page(current_page_id, false)?;
+        drop(bpm);
 
         // Recursively search in children
         for child_id in children {
@@ -467,8 +705,7 @@ impl BTree {
         InternalNode::init(&mut page_guard.data, right_page_id);
 
         // Insert the key with left child
-        InternalNode::insert(&mut page_guard.data, &key, left_page_id)
-            .expect("new root should have space");
+        InternalNode::insert(&mut page_guard.data, &key, left_page_id)?;
 
         drop(page_guard);
         bpm.unpin_page(new_root_id, true)?;
@@ -499,11 +736,11 @@ impl BTree {
 
             // Split the node
             let (split_key, _) =
-                InternalNode::split(&mut page_guard.data, &mut new_page_guard.data);
+                InternalNode::split(&mut page_guard.data, &mut new_page_guard.data)?;
 
             // Determine which node should receive the new key
             if *key < split_key {
-                Self::insert_key_with_children(&mut page_guard.data, key, left_child, right_child);
+                Self::insert_key_with_children(&mut page_guard.data, key, left_child, right_child)?;
             } else {
                 // key >= split_key: insert into dst
                 Self::insert_key_with_children(
@@ -511,7 +748,7 @@ impl BTree {
                     key,
                     left_child,
                     right_child,
-                );
+                )?;
             }
 
             drop(new_page_guard);
@@ -523,7 +760,7 @@ impl BTree {
         }
 
         // No split needed - insert directly
-        Self::insert_key_with_children(&mut page_guard.data, key, left_child, right_child);
+        Self::insert_key_with_children(&mut page_guard.data, key, left_child, right_child)?;
 
         drop(page_guard);
         bpm.unpin_page(page_id, true)?;
@@ -538,14 +775,14 @@ impl BTree {
         key: &IndexKey,
         left_child: u32,
         right_child: u32,
-    ) {
+    ) -> Result<()> {
         // Insert (key, left_child)
-        InternalNode::insert(data, key, left_child).expect("should have space");
+        InternalNode::insert(data, key, left_child)?;
 
         // Find where the key was inserted
         let count = InternalNode::key_count(data);
         for i in 0..count {
-            if InternalNode::get_key(data, i) == *key {
+            if InternalNode::get_key(data, i)? == *key {
                 if i + 1 < count {
                     // Update next entry's child to right_child
                     InternalNode::set_child(data, i + 1, right_child);
@@ -553,9 +790,10 @@ impl BTree {
                     // This key is last, update rightmost_child
                     InternalNode::set_rightmost_child(data, right_child);
                 }
-                return;
+                break;
             }
         }
+        Ok(())
     }
 
     // ========================================================================
@@ -594,37 +832,19 @@ impl BTree {
             let page = bpm.fetch_page(leaf_id)?;
             let page_guard = page.read().unwrap();
 
-            let count = LeafNode::key_count(&page_guard.data);
             let next_leaf = LeafNode::get_next_leaf(&page_guard.data);
 
-            // For the first leaf, use find_key_position to skip entries before start
-            let start_idx = if is_first_leaf {
-                is_first_leaf = false;
-                match start {
-                    Some(s) => LeafNode::find_key_position(&page_guard.data, s),
-                    None => 0,
-                }
-            } else {
-                0
-            };
-
-            // Iterate from start_idx to end
-            for i in start_idx..count {
-                let key = LeafNode::get_key(&page_guard.data, i);
-                // Check end bound
-                if let Some(e) = end {
-                    if key >= *e {
-                        drop(page_guard);
-                        bpm.unpin_page(leaf_id, false)?;
-                        return Ok(results);
-                    }
-                }
-                let rid = LeafNode::get_rid(&page_guard.data, i);
-                results.push((key, rid));
-            }
+            // For the first leaf, skip entries before start
+            let leaf_start = if is_first_leaf { start } else { None };
+            is_first_leaf = false;
+            let reached_end =
+                Self::collect_range(&page_guard.data, leaf_start, end, &mut results);
 
             drop(page_guard);
             bpm.unpin_page(leaf_id, false)?;
+            if reached_end? {
+                return Ok(results);
+            }
 
             current_leaf = next_leaf;
         }
@@ -632,6 +852,32 @@ impl BTree {
         Ok(results)
     }
 
+    /// Add a leaf's entries in [start, end) to `results`. Returns true if the
+    /// leaf also holds a key >= end, so later leaves hold no more.
+    fn collect_range(
+        data: &[u8],
+        start: Option<&IndexKey>,
+        end: Option<&IndexKey>,
+        results: &mut Vec<(IndexKey, Rid)>,
+    ) -> Result<bool> {
+        let count = LeafNode::key_count(data);
+        let start_idx = match start {
+            Some(s) => LeafNode::find_key_position(data, s)?,
+            None => 0,
+        };
+        for i in start_idx..count {
+            let key = LeafNode::get_key(data, i)?;
+            // Check end bound
+            if let Some(e) = end {
+                if key >= *e {
+                    return Ok(true);
+                }
+            }
+            results.push((key, LeafNode::get_rid(data, i)));
+        }
+        Ok(false)
+    }
+
     /// Find the first (leftmost) leaf that contains keys >= target_key
     /// by scanning backward from the given leaf
     fn find_first_leaf_with_key(&self, leaf_id: u32, target_key: &IndexKey) -> Result<u32> {
@@ -659,7 +905,7 @@ impl BTree {
                         let result = Self::leaf_has_keys_gte(&prev_guard.data, target_key);
                         drop(prev_guard);
                         bpm.unpin_page(prev_id, false)?;
-                        result
+                        result?
                     };
 
                     if has_target_keys {
@@ -673,14 +919,14 @@ impl BTree {
     }
 
     /// Check if a leaf has any keys >= target
-    fn leaf_has_keys_gte(data: &[u8], target: &IndexKey) -> bool {
+    fn leaf_has_keys_gte(data: &[u8], target: &IndexKey) -> Result<bool> {
         let count = LeafNode::key_count(data);
         if count == 0 {
-            return false;
+            return Ok(false);
         }
         // Check last key (since keys are sorted)
-        let last_key = LeafNode::get_key(data, count - 1);
-        last_key >= *target
+        let last_key = LeafNode::get_key(data, count - 1)?;
+        Ok(last_key >= *target)
     }
 
     /// Find the leftmost leaf node
@@ -850,11 +1096,23 @@ mod tests {
         ]);
 
         let serialized = key.serialize();
-        let deserialized = IndexKey::deserialize(&serialized);
+        let deserialized = IndexKey::deserialize(&serialized).unwrap();
 
         assert_eq!(key, deserialized);
     }
 
+    #[test]
+    fn test_indexkey_deserialize_rejects_corrupt_values() {
+        // A NUMERIC whose text does not parse
+        let mut data = vec![1, 8];
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(b"1x2");
+        assert!(IndexKey::deserialize(&data).is_err());
+
+        // An unknown type tag
+        assert!(IndexKey::deserialize(&[1, 200]).is_err());
+    }
+
     #[test]
     fn test_indexkey_ordering() {
         let k1 = IndexKey::single(Value::Int(10));
@@ -871,6 +1129,90 @@ mod tests {
         assert!(c1 < c2);
     }
 
+    #[test]
+    fn test_indexkey_wide_integers() {
+        let key = IndexKey::new(vec![
+            Value::SmallInt(-7),
+            Value::BigInt(9_000_000_000),
+            Value::Null,
+        ]);
+        let serialized = key.serialize();
+        assert_eq!(serialized.len(), key.serialized_size());
+        assert_eq!(IndexKey::deserialize(&serialized).unwrap(), key);
+
+        // Integers of different widths order by value
+        let small = IndexKey::single(Value::SmallInt(5));
+        let big = IndexKey::single(Value::BigInt(-9_000_000_000));
+        assert!(big < small);
+        assert!(IndexKey::single(Value::Int(5)) < IndexKey::single(Value::BigInt(6)));
+        assert_eq!(small.cmp(&IndexKey::single(Value::Int(5))), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_indexkey_datetimes() {
+        let key = IndexKey::new(vec![
+            Value::Date(-3),
+            Value::Time(45_000_000),
+            Value::TimestampTz(1_000_000),
+            Value::Interval(Interval::new(1, 2, 3)),
+        ]);
+        let serialized = key.serialize();
+        assert_eq!(serialized.len(), key.serialized_size());
+        assert_eq!(IndexKey::deserialize(&serialized).unwrap(), key);
+
+        // Dates order before timestamps later the same day
+        let midnight = IndexKey::single(Value::Date(10));
+        let noon = IndexKey::single(Value::Timestamp(10 * 86_400_000_000 + 43_200_000_000));
+        assert!(midnight < noon);
+        assert!(IndexKey::single(Value::Date(-1)) < IndexKey::single(Value::Date(0)));
+        // '1 month' sorts after '29 days'
+        let month = IndexKey::single(Value::Interval(Interval::new(1, 0, 0)));
+        assert!(IndexKey::single(Value::Interval(Interval::new(0, 29, 0))) < month);
+    }
+
+    #[test]
+    fn test_indexkey_bytea() {
+        let key = IndexKey::new(vec![Value::Bytea(vec![0xde, 0xad]), Value::Bytea(vec![])]);
+        let serialized = key.serialize();
+        assert_eq!(serialized.len(), key.serialized_size());
+        assert_eq!(IndexKey::deserialize(&serialized).unwrap(), key);
+
+        // Bytewise: a prefix sorts first, and 0x80 is above 0x7f
+        let short = IndexKey::single(Value::Bytea(vec![0x01]));
+        assert!(short < IndexKey::single(Value::Bytea(vec![0x01, 0x00])));
+        assert!(IndexKey::single(Value::Bytea(vec![0x7f])) < IndexKey::single(Value::Bytea(vec![0x80])));
+    }
+
+    #[test]
+    fn test_indexkey_jsonb() {
+        let doc = Json::parse(r#"{"a": [1, "x", null], "b": true}"#).unwrap();
+        let key = IndexKey::new(vec![Value::Jsonb(doc), Value::Int(1)]);
+        let serialized = key.serialize();
+        assert_eq!(serialized.len(), key.serialized_size());
+        assert_eq!(IndexKey::deserialize(&serialized).unwrap(), key);
+
+        // jsonb ordering: strings sort before numbers
+        let string = IndexKey::single(Value::Jsonb(Json::parse(r#""z""#).unwrap()));
+        assert!(string < IndexKey::single(Value::Jsonb(Json::parse("1").unwrap())));
+    }
+
+    #[test]
+    fn test_indexkey_array() {
+        let array = |values: &[i32]| Value::Array(values.iter().map(|n| Value::Int(*n)).collect());
+        let key = IndexKey::new(vec![
+            Value::Array(vec![Value::Varchar("a".into()), Value::Null]),
+            array(&[1, 2]),
+        ]);
+        let serialized = key.serialize();
+        assert_eq!(serialized.len(), key.serialized_size());
+        assert_eq!(IndexKey::deserialize(&serialized).unwrap(), key);
+
+        // Element by element, then shorter first
+        assert!(IndexKey::single(array(&[1, 2])) < IndexKey::single(array(&[1, 3])));
+        assert!(IndexKey::single(array(&[1, 2])) < IndexKey::single(array(&[1, 2, 0])));
+        assert!(IndexKey::single(array(&[2])) > IndexKey::single(array(&[1, 9])));
+    }
+
     #[test]
     fn test_btree_insert_reverse_order() {
         let (mut btree, _dir) = setup_btree();
@@ -903,8 +1245,8 @@ mod tests {
         // Verify range scan returns sorted results
         let results = btree.range_scan(None, None).unwrap();
         assert_eq!(results.len(), 100);
-        for i in 0..100 {
-            assert_eq!(results[i].0, IndexKey::single(Value::Int(i as i32)));
+        for (i, (key, _)) in results.iter().enumerate() {
+            assert_eq!(*key, IndexKey::single(Value::Int(i as i32)));
         }
     }
 
@@ -949,6 +1291,22 @@ mod tests {
             assert_eq!(*key, IndexKey::single(Value::Int(100 + i as i32)));
         }
     }
+
+    #[test]
+    fn test_btree_insert_three_levels() {
+        let (btree, _dir) = setup_btree();
+        let mut btree = BTree::new(btree.bpm, vec![DataType::Varchar(None)]);
+
+        // Wide keys fill pages quickly, so splits reach below the root's children
+        let key = |i: u32| IndexKey::single(Value::Varchar(format!("{i:0>200}")));
+        for i in 0..2000 {
+            btree.insert(&key(i), Rid { page_id: i, slot_id: 0 }).unwrap();
+        }
+        for i in 0..2000 {
+            assert_eq!(btree.search(&key(i)).unwrap(), Some(Rid { page_id: i, slot_id: 0 }));
+        }
+        assert_eq!(btree.range_scan(None, None).unwrap().len(), 2000);
+    }
 }
 
 #[cfg(test)]
@@ -1003,6 +1361,28 @@ mod duplicate_tests {
         );
     }
 
+    #[test]
+    fn test_search_all_duplicates_across_leaves() {
+        let (mut btree, _dir) = setup_btree();
+
+        // Surround a run of duplicates with other keys so they span several leaves
+        for i in 0..200 {
+            let key = IndexKey::single(Value::Int(i % 3));
+            let rid = Rid {
+                page_id: i as u32,
+                slot_id: 0,
+            };
+            btree.insert(&key, rid).unwrap();
+        }
+
+        let rids = btree.search_all(&IndexKey::single(Value::Int(1))).unwrap();
+        assert_eq!(rids.len(), 67);
+        assert!(rids.iter().all(|rid| rid.page_id % 3 == 1));
+
+        let missing = btree.search_all(&IndexKey::single(Value::Int(5))).unwrap();
+        assert!(missing.is_empty());
+    }
+
     #[test]
     fn test_duplicate_keys_with_split() {
         let (mut btree, _dir) = setup_btree();
@@ -1214,8 +1594,8 @@ mod edge_case_tests {
         // Full scan with None bounds
         let results = btree.range_scan(None, None).unwrap();
         assert_eq!(results.len(), 50);
-        for i in 0..50 {
-            assert_eq!(results[i].0, IndexKey::single(Value::Int(i as i32)));
+        for (i, (key, _)) in results.iter().enumerate() {
+            assert_eq!(*key, IndexKey::single(Value::Int(i as i32)));
         }
     }
 
@@ -1338,7 +1718,7 @@ mod edge_case_tests {
             wal_manager,
         )));
 
-        let mut btree = BTree::new(bpm, vec![DataType::Int, DataType::Varchar]);
+        let mut btree = BTree::new(bpm, vec![DataType::Int, DataType::Varchar(None)]);
 
         let key1 = IndexKey::new(vec![Value::Int(10), Value::Varchar("alice".to_string())]);
         let key2 = IndexKey::new(vec![Value::Int(10), Value::Varchar("bob".to_string())]);
@@ -1418,9 +1798,9 @@ mod edge_case_tests {
             wal_manager,
         )));
 
-        let mut btree = BTree::new(bpm, vec![DataType::Varchar]);
+        let mut btree = BTree::new(bpm, vec![DataType::Varchar(None)]);
 
-        let names = vec!["charlie", "alice", "bob", "david"];
+        let names = ["charlie", "alice", "bob", "david"];
         for (i, name) in names.iter().enumerate() {
             let key = IndexKey::single(Value::Varchar(name.to_string()));
             btree
@@ -0,0 +1,184 @@
+use anyhow::{bail, Result};
+
+// ============================================================================
+// BYTEA text formats
+// ============================================================================
+//
+// Input accepts PostgreSQL's hex format ('\xdeadbeef') and escape format
+// (plain text where '\\' is a backslash and '\ooo' an octal byte). Output is
+// always hex.
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Parse the text input form of a BYTEA value
+pub fn parse(text: &str) -> Result<Vec<u8>> {
+    match text
+        .strip_prefix("\\x")
+        .or_else(|| text.strip_prefix("\\X"))
+    {
+        Some(hex) => decode_hex(hex),
+        None => decode_escape(text),
+    }
+}
+
+/// Hex output format: '\x' followed by two lowercase digits per byte
+pub fn format(bytes: &[u8]) -> String {
+    format!("\\x{}", encode_hex(bytes))
+}
+
+/// encode(bytes, format) for the 'hex', 'base64' and 'escape' formats
+pub fn encode(bytes: &[u8], format: &str) -> Result<String> {
+    match format.to_ascii_lowercase().as_str() {
+        "hex" => Ok(encode_hex(bytes)),
+        "base64" => Ok(encode_base64(bytes)),
+        "escape" => Ok(encode_escape(bytes)),
+        other => bail!("unrecognized encoding: \"{other}\""),
+    }
+}
+
+/// decode(text, format), the inverse of encode
+pub fn decode(text: &str, format: &str) -> Result<Vec<u8>> {
+    match format.to_ascii_lowercase().as_str() {
+        "hex" => decode_hex(text),
+        "base64" => decode_base64(text),
+        "escape" => decode_escape(text),
+        other => bail!("unrecognized encoding: \"{other}\""),
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        out.push(HEX_DIGITS[(b >> 4) as usize] as char);
+        out.push(HEX_DIGITS[(b & 0xf) as usize] as char);
+    }
+    out
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    // Whitespace between byte pairs is allowed
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        bail!("invalid hexadecimal data: odd number of digits");
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let digit = |d: u8| {
+                (d as char)
+                    .to_digit(16)
+                    .ok_or_else(|| anyhow::anyhow!("invalid hexadecimal digit: \"{}\"", d as char))
+            };
+            Ok((digit(pair[0])? * 16 + digit(pair[1])?) as u8)
+        })
+        .collect()
+}
+
+fn encode_escape(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    out
+}
+
+fn decode_escape(text: &str) -> Result<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+        } else if bytes.get(i + 1) == Some(&b'\\') {
+            out.push(b'\\');
+            i += 2;
+        } else {
+            let octal = bytes.get(i + 1..i + 4).filter(|d| {
+                matches!(d[0], b'0'..=b'3') && d[1..].iter().all(|c| matches!(c, b'0'..=b'7'))
+            });
+            match octal {
+                Some(d) => out.push((d[0] - b'0') * 64 + (d[1] - b'0') * 8 + (d[2] - b'0')),
+                None => bail!("invalid input syntax for type bytea"),
+            }
+            i += 4;
+        }
+    }
+    Ok(out)
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|b| !b.is_ascii_whitespace()) {
+        if c == b'=' {
+            break;
+        }
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "invalid symbol \"{}\" found while decoding base64 sequence",
+                    c as char
+                )
+            })?;
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_formats() {
+        assert_eq!(parse("\\xDEADbeef").unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(parse("a\\\\b\\001").unwrap(), b"a\\b\x01".to_vec());
+        assert!(parse("\\x123").is_err());
+        assert!(parse("bad\\9").is_err());
+        assert_eq!(format(&[0, 15, 255]), "\\x000fff");
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        for data in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\x00\xff\\ok"] {
+            for fmt in ["hex", "base64", "escape"] {
+                assert_eq!(decode(&encode(data, fmt).unwrap(), fmt).unwrap(), data);
+            }
+        }
+        assert_eq!(encode(b"foob", "base64").unwrap(), "Zm9vYg==");
+        assert_eq!(encode(b"a\x01\\", "escape").unwrap(), "a\\001\\\\");
+        assert!(encode(b"x", "rot13").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::protocol::{SqlError, QUERY_CANCELED};
+use crate::settings;
+
+// ============================================================================
+// Query cancellation
+// ============================================================================
+//
+// Each session is told a process ID and secret key in BackendKeyData. A
+// client cancels the running statement by opening a new connection and
+// sending CancelRequest with that key, which sets the session's cancel flag.
+// Executors poll the flag with check_for_interrupts() as they loop over
+// rows, so the statement fails at its next check; statement_timeout works
+// the same way with a deadline instead of a flag.
+
+/// Sessions that can be cancelled, by process ID
+pub struct CancelRegistry {
+    next_pid: AtomicI32,
+    sessions: Mutex<HashMap<i32, (i32, Arc<AtomicBool>)>>,
+}
+
+/// Key of a session, registered until dropped
+pub struct BackendKey {
+    pub pid: i32,
+    pub secret: i32,
+    registry: Arc<CancelRegistry>,
+}
+
+impl CancelRegistry {
+    pub fn new() -> Self {
+        CancelRegistry {
+            // Start from the server's own PID so keys look like PostgreSQL's
+            next_pid: AtomicI32::new(std::process::id() as i32 + 1),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Give the session on this thread a key and a cancel flag
+    pub fn register(self: &Arc<Self>) -> BackendKey {
+        let pid = self.next_pid.fetch_add(1, Ordering::Relaxed);
+        let mut secret = [0u8; 4];
+        getrandom::fill(&mut secret).expect("no system random source");
+        let secret = i32::from_be_bytes(secret);
+
+        let flag = Arc::new(AtomicBool::new(false));
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(pid, (secret, Arc::clone(&flag)));
+        CANCEL_FLAG.with(|f| *f.borrow_mut() = Some(flag));
+        BackendKey {
+            pid,
+            secret,
+            registry: Arc::clone(self),
+        }
+    }
+
+    /// Handle a CancelRequest. Requests with an unknown key are ignored,
+    /// as nothing is sent back to the client either way.
+    pub fn cancel(&self, pid: i32, secret: i32) -> bool {
+        match self.sessions.lock().unwrap().get(&pid) {
+            Some((key, flag)) if *key == secret => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Drop for BackendKey {
+    fn drop(&mut self) {
+        self.registry.sessions.lock().unwrap().remove(&self.pid);
+        CANCEL_FLAG.with(|f| *f.borrow_mut() = None);
+    }
+}
+
+thread_local! {
+    static CANCEL_FLAG: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Called when a statement starts: forget cancel requests that arrived
+/// while the session was idle and start the statement_timeout clock
+pub fn start_statement() {
+    CANCEL_FLAG.with(|f| {
+        if let Some(flag) = &*f.borrow() {
+            flag.store(false, Ordering::Relaxed);
+        }
+    });
+    let timeout = settings::statement_timeout_ms();
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+    DEADLINE.with(|d| d.set(deadline));
+}
+
+/// Fail if the running statement was cancelled or ran out of time
+pub fn check_for_interrupts() -> Result<()> {
+    let cancelled = CANCEL_FLAG.with(|f| {
+        f.borrow()
+            .as_ref()
+            .is_some_and(|flag| flag.swap(false, Ordering::Relaxed))
+    });
+    if cancelled {
+        return Err(
+            SqlError::error(QUERY_CANCELED, "canceling statement due to user request").into(),
+        );
+    }
+    if DEADLINE.with(|d| d.get().is_some_and(|deadline| Instant::now() >= deadline)) {
+        DEADLINE.with(|d| d.set(None));
+        return Err(
+            SqlError::error(QUERY_CANCELED, "canceling statement due to statement timeout").into(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlstate(result: Result<()>) -> &'static str {
+        SqlError::find(&result.unwrap_err()).unwrap().code
+    }
+
+    #[test]
+    fn test_interrupts_are_query_canceled() {
+        let registry = Arc::new(CancelRegistry::new());
+        let key = registry.register();
+        start_statement();
+        check_for_interrupts().unwrap();
+
+        assert!(registry.cancel(key.pid, key.secret));
+        let result = check_for_interrupts();
+        assert_eq!(
+            result.as_ref().unwrap_err().to_string(),
+            "canceling statement due to user request"
+        );
+        assert_eq!(sqlstate(result), QUERY_CANCELED);
+
+        settings::set("statement_timeout", Some("1ms")).unwrap();
+        start_statement();
+        std::thread::sleep(Duration::from_millis(5));
+        let result = check_for_interrupts();
+        assert_eq!(
+            result.as_ref().unwrap_err().to_string(),
+            "canceling statement due to statement timeout"
+        );
+        assert_eq!(sqlstate(result), QUERY_CANCELED);
+    }
+}
@@ -3,19 +3,27 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 
 use crate::bootstrap::{
-    DATA_TYPE_BOOL, DATA_TYPE_INT, DATA_TYPE_VARCHAR, PG_ATTRIBUTE_PAGE_ID, PG_ATTRIBUTE_TABLE_ID,
-    PG_CLASS_PAGE_ID, PG_CLASS_TABLE_ID, PG_INDEX_PAGE_ID, PG_INDEX_TABLE_ID,
+    DATA_TYPE_ARRAY, DATA_TYPE_BIGINT, DATA_TYPE_BOOL, DATA_TYPE_BYTEA, DATA_TYPE_CHAR,
+    DATA_TYPE_DATE, DATA_TYPE_DOUBLE, DATA_TYPE_INT, DATA_TYPE_INTERVAL, DATA_TYPE_JSONB,
+    DATA_TYPE_NUMERIC, DATA_TYPE_REAL, DATA_TYPE_SMALLINT, DATA_TYPE_TEXT, DATA_TYPE_TIME,
+    DATA_TYPE_TIMESTAMP, DATA_TYPE_TIMESTAMPTZ, DATA_TYPE_VARCHAR, FIRST_USER_TABLE_ID,
+    PG_ATTRIBUTE_PAGE_ID, PG_ATTRIBUTE_TABLE_ID, PG_AUTHID_TABLE_ID, PG_CLASS_PAGE_ID,
+    PG_CLASS_TABLE_ID, PG_INDEX_PAGE_ID, PG_INDEX_TABLE_ID,
 };
+use crate::analyzer::{AnalyzedExpr, Analyzer};
 use crate::buffer_pool::BufferPoolManager;
+use crate::collation::Collation;
 use crate::page::NO_NEXT_PAGE;
 use crate::tuple::{deserialize_tuple_mvcc, Column, DataType, Schema, Value};
 
 #[derive(Debug, Clone)]
 pub struct TableDef {
     pub table_id: u32,
-    #[allow(dead_code)]
     pub name: String,
     pub first_page_id: u32,
+    /// First page of the TOAST relation holding out-of-line values, or
+    /// NO_NEXT_PAGE when the table has no column that can be toasted
+    pub toast_page_id: u32,
     pub columns: Vec<ColumnDef>,
 }
 
@@ -24,17 +32,77 @@ pub struct ColumnDef {
     pub name: String,
     pub data_type: DataType,
     pub nullable: bool,
+    /// How values compare; only differs from the default for string columns
+    pub collation: Collation,
+}
+
+/// A role from pg_authid
+#[derive(Debug, Clone)]
+pub struct RoleDef {
+    pub name: String,
+    pub superuser: bool,
+    pub can_login: bool,
+    /// Password verifier (auth::Verifier), None when no password is set
+    pub password: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct IndexDef {
-    #[allow(dead_code)]
     pub index_id: u32,
     pub index_name: String,
     #[allow(dead_code)]
     pub table_id: u32,
-    pub column_ids: Vec<usize>, // column indices in the table
-    pub meta_page_id: u32,      // B-Tree meta page ID (stores root pointer)
+    pub columns: Vec<IndexColumn>,
+    pub meta_page_id: u32, // B-Tree meta page ID (stores root pointer)
+    pub is_unique: bool,
+}
+
+/// Marks an expression key in pg_index.column_ids; the expressions themselves
+/// are in pg_index.expressions, in key order
+pub const EXPRESSION_KEY: &str = "expr";
+
+/// One key column of an index
+#[derive(Debug, Clone)]
+pub enum IndexColumn {
+    /// A table column, by position
+    Column(usize),
+    /// An expression over the table's columns, e.g. (data->>'name')
+    Expression(AnalyzedExpr),
+}
+
+impl IndexDef {
+    /// Positions of the key columns, or None if any key is an expression
+    pub fn column_ids(&self) -> Option<Vec<usize>> {
+        self.columns
+            .iter()
+            .map(|column| match column {
+                IndexColumn::Column(id) => Some(*id),
+                IndexColumn::Expression(_) => None,
+            })
+            .collect()
+    }
+
+    /// Types of the key columns
+    pub fn key_schema(&self, table: &TableDef) -> Vec<DataType> {
+        self.columns.iter().map(|column| column.data_type(table)).collect()
+    }
+}
+
+impl IndexColumn {
+    pub fn data_type(&self, table: &TableDef) -> DataType {
+        match self {
+            IndexColumn::Column(id) => table.columns[*id].data_type.clone(),
+            IndexColumn::Expression(expr) => expr.data_type().clone(),
+        }
+    }
+
+    /// Collation the key is ordered by: the column's, or the expression's
+    pub fn collation(&self, table: &TableDef) -> Collation {
+        match self {
+            IndexColumn::Column(id) => table.columns[*id].collation,
+            IndexColumn::Expression(expr) => expr.collation(),
+        }
+    }
 }
 
 pub struct Catalog {
@@ -56,12 +124,16 @@ impl Catalog {
                 },
                 Column {
                     name: "name".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                 },
                 Column {
                     name: "first_page_id".to_string(),
                     data_type: DataType::Int,
                 },
+                Column {
+                    name: "toast_page_id".to_string(),
+                    data_type: DataType::Int,
+                },
             ],
         }
     }
@@ -76,7 +148,7 @@ impl Catalog {
                 },
                 Column {
                     name: "column_name".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                 },
                 Column {
                     name: "data_type".to_string(),
@@ -90,6 +162,14 @@ impl Catalog {
                     name: "ordinal_position".to_string(),
                     data_type: DataType::Int,
                 },
+                Column {
+                    name: "typmod".to_string(),
+                    data_type: DataType::Int,
+                },
+                Column {
+                    name: "collation".to_string(),
+                    data_type: DataType::Int,
+                },
             ],
         }
     }
@@ -104,7 +184,7 @@ impl Catalog {
                 },
                 Column {
                     name: "index_name".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                 },
                 Column {
                     name: "table_id".to_string(),
@@ -112,12 +192,20 @@ impl Catalog {
                 },
                 Column {
                     name: "column_ids".to_string(),
-                    data_type: DataType::Varchar, // comma-separated
+                    data_type: DataType::Varchar(None), // comma-separated
                 },
                 Column {
                     name: "root_page_id".to_string(),
                     data_type: DataType::Int,
                 },
+                Column {
+                    name: "is_unique".to_string(),
+                    data_type: DataType::Bool,
+                },
+                Column {
+                    name: "expressions".to_string(),
+                    data_type: DataType::Varchar(None),
+                },
             ],
         }
     }
@@ -166,29 +254,8 @@ impl Catalog {
         }
 
         // Read from pg_class to find the table
-        let pg_class_schema = Self::pg_class_schema();
-        let tuples = self
-            .read_table_tuples(PG_CLASS_PAGE_ID, &pg_class_schema)
-            .ok()?;
-
-        for values in tuples {
-            if let (Value::Int(table_id), Value::Varchar(table_name), Value::Int(first_page_id)) =
-                (&values[0], &values[1], &values[2])
-            {
-                if table_name == name {
-                    // Found the table, now get its columns
-                    let columns = self.get_columns_for_table(*table_id as u32);
-                    return Some(TableDef {
-                        table_id: *table_id as u32,
-                        name: table_name.clone(),
-                        first_page_id: *first_page_id as u32,
-                        columns,
-                    });
-                }
-            }
-        }
-
-        None
+        let entry = self.read_pg_class().into_iter().find(|entry| entry.name == name)?;
+        Some(self.table_def(entry))
     }
 
     /// Get table by ID
@@ -205,28 +272,76 @@ impl Catalog {
         }
 
         // Read from pg_class to find the table
+        let entry = self.read_pg_class().into_iter().find(|entry| entry.table_id == id)?;
+        Some(self.table_def(entry))
+    }
+
+    /// All tables but pg_class, pg_attribute and pg_index, in pg_class order
+    pub fn get_tables(&self) -> Vec<TableDef> {
+        self.read_pg_class()
+            .into_iter()
+            .filter(|entry| entry.table_id > PG_INDEX_TABLE_ID)
+            .map(|entry| self.table_def(entry))
+            .collect()
+    }
+
+    /// Live pg_class rows, without their columns
+    fn read_pg_class(&self) -> Vec<TableDef> {
         let pg_class_schema = Self::pg_class_schema();
-        let tuples = self
-            .read_table_tuples(PG_CLASS_PAGE_ID, &pg_class_schema)
-            .ok()?;
+        let Ok(tuples) = self.read_table_tuples(PG_CLASS_PAGE_ID, &pg_class_schema) else {
+            return vec![];
+        };
 
+        let mut entries = Vec::new();
         for values in tuples {
-            if let (Value::Int(table_id), Value::Varchar(table_name), Value::Int(first_page_id)) =
-                (&values[0], &values[1], &values[2])
+            if let (
+                Value::Int(table_id),
+                Value::Varchar(table_name),
+                Value::Int(first_page_id),
+                Value::Int(toast_page_id),
+            ) = (&values[0], &values[1], &values[2], &values[3])
             {
-                if *table_id as u32 == id {
-                    let columns = self.get_columns_for_table(*table_id as u32);
-                    return Some(TableDef {
-                        table_id: *table_id as u32,
-                        name: table_name.clone(),
-                        first_page_id: *first_page_id as u32,
-                        columns,
-                    });
-                }
+                entries.push(TableDef {
+                    table_id: *table_id as u32,
+                    name: table_name.clone(),
+                    first_page_id: *first_page_id as u32,
+                    toast_page_id: *toast_page_id as u32,
+                    columns: vec![],
+                });
             }
         }
+        entries
+    }
+
+    /// Complete a pg_class entry with its columns from pg_attribute
+    fn table_def(&self, entry: TableDef) -> TableDef {
+        let columns = self.get_columns_for_table(entry.table_id);
+        TableDef { columns, ..entry }
+    }
+
+    /// Get a role from pg_authid by name
+    pub fn get_role(&self, name: &str) -> Option<RoleDef> {
+        let pg_authid = self.get_table_by_id(PG_AUTHID_TABLE_ID)?;
+        let tuples = self
+            .read_table_tuples(pg_authid.first_page_id, &pg_authid.to_schema())
+            .ok()?;
 
-        None
+        tuples.into_iter().find_map(|values| match &values[..] {
+            [Value::Varchar(rolname), Value::Bool(superuser), Value::Bool(can_login), password]
+                if rolname == name =>
+            {
+                Some(RoleDef {
+                    name: rolname.clone(),
+                    superuser: *superuser,
+                    can_login: *can_login,
+                    password: match password {
+                        Value::Varchar(password) => Some(password.clone()),
+                        _ => None,
+                    },
+                })
+            }
+            _ => None,
+        })
     }
 
     /// Get table ID by name
@@ -251,21 +366,20 @@ impl Catalog {
                 Value::Int(data_type),
                 Value::Bool(nullable),
                 Value::Int(ordinal),
-            ) = (&values[0], &values[1], &values[2], &values[3], &values[4])
-            {
+                Value::Int(typmod),
+                Value::Int(collation),
+            ) = (
+                &values[0], &values[1], &values[2], &values[3], &values[4], &values[5], &values[6],
+            ) {
                 if *tid as u32 == table_id {
-                    let dt = match *data_type {
-                        DATA_TYPE_INT => DataType::Int,
-                        DATA_TYPE_VARCHAR => DataType::Varchar,
-                        DATA_TYPE_BOOL => DataType::Bool,
-                        _ => DataType::Int, // fallback
-                    };
+                    let dt = Self::data_type_from_int(*data_type).with_typmod(*typmod);
                     columns.push((
                         *ordinal,
                         ColumnDef {
                             name: col_name.clone(),
                             data_type: dt,
                             nullable: *nullable,
+                            collation: Collation::from_id(*collation),
                         },
                     ));
                 }
@@ -277,27 +391,64 @@ impl Catalog {
         columns.into_iter().map(|(_, col)| col).collect()
     }
 
+    /// Inverse of the type codes written by CREATE TABLE
+    fn data_type_from_int(code: i32) -> DataType {
+        match code {
+            DATA_TYPE_INT => DataType::Int,
+            DATA_TYPE_VARCHAR => DataType::Varchar(None),
+            DATA_TYPE_CHAR => DataType::Char(1),
+            DATA_TYPE_TEXT => DataType::Text,
+            DATA_TYPE_BOOL => DataType::Bool,
+            DATA_TYPE_SMALLINT => DataType::SmallInt,
+            DATA_TYPE_BIGINT => DataType::BigInt,
+            DATA_TYPE_REAL => DataType::Real,
+            DATA_TYPE_DOUBLE => DataType::Double,
+            DATA_TYPE_NUMERIC => DataType::Numeric(None),
+            DATA_TYPE_DATE => DataType::Date,
+            DATA_TYPE_TIME => DataType::Time,
+            DATA_TYPE_TIMESTAMP => DataType::Timestamp,
+            DATA_TYPE_TIMESTAMPTZ => DataType::TimestampTz,
+            DATA_TYPE_INTERVAL => DataType::Interval,
+            DATA_TYPE_BYTEA => DataType::Bytea,
+            DATA_TYPE_JSONB => DataType::Jsonb,
+            code if code >= DATA_TYPE_ARRAY => {
+                DataType::Array(Box::new(Self::data_type_from_int(code - DATA_TYPE_ARRAY)))
+            }
+            _ => DataType::Int, // fallback
+        }
+    }
+
     /// Get hardcoded pg_class definition
     fn get_pg_class_def(&self) -> TableDef {
         TableDef {
             table_id: PG_CLASS_TABLE_ID,
             name: "pg_class".to_string(),
             first_page_id: PG_CLASS_PAGE_ID,
+            toast_page_id: NO_NEXT_PAGE,
             columns: vec![
                 ColumnDef {
                     name: "table_id".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "name".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "first_page_id".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
+                },
+                ColumnDef {
+                    name: "toast_page_id".to_string(),
+                    data_type: DataType::Int,
+                    nullable: false,
+                    collation: Collation::C,
                 },
             ],
         }
@@ -309,31 +460,49 @@ impl Catalog {
             table_id: PG_ATTRIBUTE_TABLE_ID,
             name: "pg_attribute".to_string(),
             first_page_id: PG_ATTRIBUTE_PAGE_ID,
+            toast_page_id: NO_NEXT_PAGE,
             columns: vec![
                 ColumnDef {
                     name: "table_id".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "column_name".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "data_type".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "nullable".to_string(),
                     data_type: DataType::Bool,
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "ordinal_position".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
+                },
+                ColumnDef {
+                    name: "typmod".to_string(),
+                    data_type: DataType::Int,
+                    nullable: false,
+                    collation: Collation::C,
+                },
+                ColumnDef {
+                    name: "collation".to_string(),
+                    data_type: DataType::Int,
+                    nullable: false,
+                    collation: Collation::C,
                 },
             ],
         }
@@ -344,13 +513,13 @@ impl Catalog {
         let pg_class_schema = Self::pg_class_schema();
         let tuples = self.read_table_tuples(PG_CLASS_PAGE_ID, &pg_class_schema)?;
 
-        let mut max_id: u32 = 2; // pg_class(0), pg_attribute(1), pg_index(2) exist
+        let mut next_id = FIRST_USER_TABLE_ID;
         for values in tuples {
             if let Value::Int(table_id) = &values[0] {
-                max_id = max_id.max(*table_id as u32);
+                next_id = next_id.max(*table_id as u32 + 1);
             }
         }
-        Ok(max_id + 1)
+        Ok(next_id)
     }
 
     /// Get hardcoded pg_index definition
@@ -359,31 +528,49 @@ impl Catalog {
             table_id: PG_INDEX_TABLE_ID,
             name: "pg_index".to_string(),
             first_page_id: PG_INDEX_PAGE_ID,
+            toast_page_id: NO_NEXT_PAGE,
             columns: vec![
                 ColumnDef {
                     name: "index_id".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "index_name".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "table_id".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "column_ids".to_string(),
-                    data_type: DataType::Varchar,
+                    data_type: DataType::Varchar(None),
                     nullable: false,
+                    collation: Collation::C,
                 },
                 ColumnDef {
                     name: "root_page_id".to_string(),
                     data_type: DataType::Int,
                     nullable: false,
+                    collation: Collation::C,
+                },
+                ColumnDef {
+                    name: "is_unique".to_string(),
+                    data_type: DataType::Bool,
+                    nullable: false,
+                    collation: Collation::C,
+                },
+                ColumnDef {
+                    name: "expressions".to_string(),
+                    data_type: DataType::Varchar(None),
+                    nullable: true,
+                    collation: Collation::C,
                 },
             ],
         }
@@ -397,32 +584,11 @@ impl Catalog {
             Err(_) => return vec![],
         };
 
-        let mut indexes = Vec::new();
-        for values in tuples {
-            if let (
-                Value::Int(idx_id),
-                Value::Varchar(idx_name),
-                Value::Int(tid),
-                Value::Varchar(col_ids_str),
-                Value::Int(meta_page_id),
-            ) = (&values[0], &values[1], &values[2], &values[3], &values[4])
-            {
-                if *tid as u32 == table_id {
-                    let column_ids: Vec<usize> = col_ids_str
-                        .split(',')
-                        .filter_map(|s| s.trim().parse().ok())
-                        .collect();
-                    indexes.push(IndexDef {
-                        index_id: *idx_id as u32,
-                        index_name: idx_name.clone(),
-                        table_id: *tid as u32,
-                        column_ids,
-                        meta_page_id: *meta_page_id as u32,
-                    });
-                }
-            }
-        }
-        indexes
+        tuples
+            .iter()
+            .filter(|values| values[2] == Value::Int(table_id as i32))
+            .filter_map(|values| self.index_from_row(values))
+            .collect()
     }
 
     /// Get next available index ID
@@ -446,31 +612,62 @@ impl Catalog {
             .read_table_tuples(PG_INDEX_PAGE_ID, &pg_index_schema)
             .ok()?;
 
-        for values in tuples {
-            if let (
-                Value::Int(idx_id),
-                Value::Varchar(idx_name),
-                Value::Int(tid),
-                Value::Varchar(col_ids_str),
-                Value::Int(meta_page_id),
-            ) = (&values[0], &values[1], &values[2], &values[3], &values[4])
-            {
-                if idx_name == name {
-                    let column_ids: Vec<usize> = col_ids_str
-                        .split(',')
-                        .filter_map(|s| s.trim().parse().ok())
-                        .collect();
-                    return Some(IndexDef {
-                        index_id: *idx_id as u32,
-                        index_name: idx_name.clone(),
-                        table_id: *tid as u32,
-                        column_ids,
-                        meta_page_id: *meta_page_id as u32,
-                    });
-                }
+        tuples
+            .iter()
+            .find(|values| values[1] == Value::Varchar(name.to_string()))
+            .and_then(|values| self.index_from_row(values))
+    }
+
+    /// Build an IndexDef from a pg_index row. Expression keys are stored as
+    /// SQL text and analyzed again against the table.
+    fn index_from_row(&self, values: &[Value]) -> Option<IndexDef> {
+        let (
+            Value::Int(idx_id),
+            Value::Varchar(idx_name),
+            Value::Int(tid),
+            Value::Varchar(col_ids_str),
+            Value::Int(meta_page_id),
+            Value::Bool(is_unique),
+        ) = (
+            &values[0], &values[1], &values[2], &values[3], &values[4], &values[5],
+        )
+        else {
+            return None;
+        };
+
+        let mut expressions = match &values[6] {
+            Value::Varchar(sql) => {
+                let table = self.get_table_by_id(*tid as u32)?;
+                // The text was produced from expressions that analyzed
+                // successfully when the index was created
+                let exprs = crate::parser::parse_expr_list(sql).ok()?;
+                let mut analyzer = Analyzer::new(self);
+                exprs
+                    .iter()
+                    .map(|expr| analyzer.analyze_index_expression(&table, expr))
+                    .collect::<Result<Vec<_>>>()
+                    .ok()?
             }
+            _ => Vec::new(),
         }
-        None
+        .into_iter();
+
+        let columns = col_ids_str
+            .split(',')
+            .map(|s| match s.trim() {
+                EXPRESSION_KEY => expressions.next().map(IndexColumn::Expression),
+                id => id.parse().ok().map(IndexColumn::Column),
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(IndexDef {
+            index_id: *idx_id as u32,
+            index_name: idx_name.clone(),
+            table_id: *tid as u32,
+            columns,
+            meta_page_id: *meta_page_id as u32,
+            is_unique: *is_unique,
+        })
     }
 }
 
@@ -480,7 +677,6 @@ impl TableDef {
         self.columns.iter().find(|c| c.name == name)
     }
 
-    #[allow(dead_code)]
     pub fn get_column_id(&self, name: &str) -> Option<usize> {
         self.columns.iter().position(|c| c.name == name)
     }
@@ -240,7 +240,7 @@ impl ClogInner {
         let victim = self
             .replacer
             .victim()
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "no victim frame"))?;
+            .ok_or_else(|| std::io::Error::other("no victim frame"))?;
         self.evict(victim)?;
         Ok(victim)
     }
@@ -297,6 +297,7 @@ impl ClogInner {
     fn write_page_to_file(file_path: &Path, page_id: u64, data: &[u8; PAGE_SIZE]) -> std::io::Result<()> {
         let mut file = OpenOptions::new()
             .create(true)
+            .truncate(false)
             .write(true)
             .open(file_path)?;
         file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use anyhow::{bail, Result};
+
+use crate::tuple::Value;
+
+// ============================================================================
+// Collations
+// ============================================================================
+//
+// A collation decides how strings compare. "C" compares them bytewise and is
+// the default; "case_insensitive" compares their lowercase forms, so 'ABC'
+// and 'abc' are equal (a nondeterministic collation, in PostgreSQL's terms).
+// Comparisons, grouping and index keys all go through sort_key, which maps a
+// value to the form that compares correctly bytewise.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Collation {
+    #[default]
+    C,
+    CaseInsensitive,
+}
+
+impl Collation {
+    /// Look up a collation by the name used in COLLATE
+    pub fn from_name(name: &str) -> Result<Collation> {
+        match name {
+            "C" | "POSIX" | "default" => Ok(Collation::C),
+            "case_insensitive" => Ok(Collation::CaseInsensitive),
+            _ => bail!("collation \"{name}\" for encoding \"UTF8\" does not exist"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Collation::C => "C",
+            Collation::CaseInsensitive => "case_insensitive",
+        }
+    }
+
+    /// Code stored in pg_attribute
+    pub fn id(&self) -> i32 {
+        match self {
+            Collation::C => 0,
+            Collation::CaseInsensitive => 1,
+        }
+    }
+
+    /// Inverse of id(); unknown codes fall back to the default
+    pub fn from_id(id: i32) -> Collation {
+        match id {
+            1 => Collation::CaseInsensitive,
+            _ => Collation::C,
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.fold(a).cmp(&self.fold(b))
+    }
+
+    /// The value that compares, hashes and sorts bytewise the way `value`
+    /// does under this collation. Only strings (and arrays of them) change.
+    pub fn sort_key(&self, value: Value) -> Value {
+        match (self, value) {
+            (Collation::C, value) => value,
+            (_, Value::Varchar(s)) => Value::Varchar(self.fold(&s).into_owned()),
+            (_, Value::Array(elements)) => {
+                Value::Array(elements.into_iter().map(|e| self.sort_key(e)).collect())
+            }
+            (_, value) => value,
+        }
+    }
+
+    fn fold<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        match self {
+            Collation::C => Cow::Borrowed(s),
+            Collation::CaseInsensitive => Cow::Owned(s.to_lowercase()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(Collation::C.compare("abc", "ABC"), Ordering::Greater);
+        assert_eq!(Collation::C.compare("B", "a"), Ordering::Less);
+
+        let ci = Collation::CaseInsensitive;
+        assert_eq!(ci.compare("abc", "ABC"), Ordering::Equal);
+        assert_eq!(ci.compare("B", "a"), Ordering::Greater);
+        assert_eq!(ci.compare("apple", "Banana"), Ordering::Less);
+        assert_eq!(ci.compare("ÉCOLE", "école"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_sort_key() {
+        let ci = Collation::CaseInsensitive;
+        assert_eq!(ci.sort_key(Value::Varchar("MiXeD".into())), Value::Varchar("mixed".into()));
+        assert_eq!(
+            ci.sort_key(Value::Array(vec![Value::Varchar("A".into()), Value::Null])),
+            Value::Array(vec![Value::Varchar("a".into()), Value::Null])
+        );
+        assert_eq!(ci.sort_key(Value::Int(1)), Value::Int(1));
+        assert_eq!(Collation::C.sort_key(Value::Varchar("A".into())), Value::Varchar("A".into()));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Collation::from_name("C").unwrap(), Collation::C);
+        assert_eq!(Collation::from_name("POSIX").unwrap(), Collation::C);
+        let ci = Collation::from_name("case_insensitive").unwrap();
+        assert_eq!(Collation::from_id(ci.id()), ci);
+        assert_eq!(ci.name(), "case_insensitive");
+        assert!(Collation::from_name("en_US").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+use crate::settings;
+
+// ============================================================================
+// Server configuration
+// ============================================================================
+//
+// Server-wide parameters, read once at startup from postgresql.conf in the
+// data directory: one `name = value` per line, values optionally in single
+// quotes. Unlike the session settings they cannot be changed with SET.
+
+const CONFIG_FILE: &str = "postgresql.conf";
+
+/// Longest accepted authentication_timeout, in milliseconds
+const MAX_AUTHENTICATION_TIMEOUT_MS: i64 = 600 * 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Address to accept connections on; '*' is all IPv4 interfaces
+    pub listen_address: IpAddr,
+    /// Accept TLS connections from clients that send SSLRequest
+    pub ssl: bool,
+    /// PEM certificate chain, the server's certificate first
+    pub ssl_cert_file: String,
+    /// PEM private key of the certificate
+    pub ssl_key_file: String,
+    /// Refuse clients connecting from other hosts without TLS
+    pub ssl_require_remote: bool,
+    /// Most client sessions at once; one worker thread runs each
+    pub max_connections: usize,
+    /// Connection slots only superusers may take, so that they can still
+    /// get in when ordinary roles have used up the rest
+    pub superuser_reserved_connections: usize,
+    /// Time a client has from connecting to having authenticated; it holds
+    /// a connection slot meanwhile
+    pub authentication_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen_address: IpAddr::from([127, 0, 0, 1]),
+            ssl: false,
+            ssl_cert_file: "server.crt".to_string(),
+            ssl_key_file: "server.key".to_string(),
+            ssl_require_remote: false,
+            max_connections: 100,
+            superuser_reserved_connections: 3,
+            authentication_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Config {
+    /// Read postgresql.conf; without it every parameter has its default
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(CONFIG_FILE) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut config = Config::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                bail!("syntax error in {CONFIG_FILE} line {}", number + 1);
+            };
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let value = value
+                .strip_prefix('\'')
+                .and_then(|v| v.strip_suffix('\''))
+                .unwrap_or(value);
+            let invalid = || anyhow::anyhow!("invalid value for parameter \"{name}\": \"{value}\"");
+            let count = || value.parse::<usize>().map_err(|_| invalid());
+            let boolean = || match value.to_ascii_lowercase().as_str() {
+                "on" | "true" | "yes" | "1" => Ok(true),
+                "off" | "false" | "no" | "0" => Ok(false),
+                _ => Err(invalid()),
+            };
+            match name.as_str() {
+                "listen_addresses" => {
+                    config.listen_address = match value {
+                        "*" => IpAddr::from([0, 0, 0, 0]),
+                        "localhost" => IpAddr::from([127, 0, 0, 1]),
+                        address => address.parse().map_err(|_| invalid())?,
+                    }
+                }
+                "ssl" => config.ssl = boolean()?,
+                "ssl_cert_file" => config.ssl_cert_file = value.to_string(),
+                "ssl_key_file" => config.ssl_key_file = value.to_string(),
+                "ssl_require_remote" => config.ssl_require_remote = boolean()?,
+                "max_connections" => config.max_connections = count()?,
+                "superuser_reserved_connections" => {
+                    config.superuser_reserved_connections = count()?
+                }
+                "authentication_timeout" => {
+                    // Seconds without a unit, as in PostgreSQL
+                    let ms = match value.parse::<i64>() {
+                        Ok(seconds) => seconds.saturating_mul(1000),
+                        Err(_) => {
+                            settings::parse_duration(&name, value, MAX_AUTHENTICATION_TIMEOUT_MS)?
+                        }
+                    };
+                    if !(1000..=MAX_AUTHENTICATION_TIMEOUT_MS).contains(&ms) {
+                        bail!(
+                            "{value} is outside the valid range for parameter \"{name}\" \
+                             (1s .. 10min)"
+                        );
+                    }
+                    config.authentication_timeout = Duration::from_millis(ms as u64);
+                }
+                _ => bail!("unrecognized configuration parameter \"{name}\" in {CONFIG_FILE}"),
+            }
+        }
+        if config.ssl_require_remote && !config.ssl {
+            bail!("ssl_require_remote requires ssl = on");
+        }
+        if config.superuser_reserved_connections >= config.max_connections {
+            bail!("superuser_reserved_connections must be less than max_connections");
+        }
+        Ok(config)
+    }
+
+    /// TLS settings for accepted connections, None when ssl is off
+    pub fn tls(&self) -> Result<Option<Arc<rustls::ServerConfig>>> {
+        if !self.ssl {
+            return Ok(None);
+        }
+        let certs = CertificateDer::pem_file_iter(&self.ssl_cert_file)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| {
+                format!(
+                    "could not load server certificate file \"{}\"",
+                    self.ssl_cert_file
+                )
+            })?;
+        let key = PrivateKeyDer::from_pem_file(&self.ssl_key_file).with_context(|| {
+            format!("could not load private key file \"{}\"", self.ssl_key_file)
+        })?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        Ok(Some(Arc::new(config)))
+    }
+
+    /// Whether a client must use TLS: only remote ones, and only when
+    /// ssl_require_remote is on
+    pub fn requires_tls(&self, addr: IpAddr) -> bool {
+        self.ssl_require_remote && !addr.to_canonical().is_loopback()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            "# TLS for remote clients\n\
+             listen_addresses = '*'\n\
+             ssl = on\n\
+             ssl_cert_file = 'certs/server.crt'   # chain\n\
+             ssl_require_remote = true\n",
+        )
+        .unwrap();
+        assert_eq!(config.listen_address, IpAddr::from([0, 0, 0, 0]));
+        assert!(config.ssl);
+        assert_eq!(config.ssl_cert_file, "certs/server.crt");
+        assert_eq!(config.ssl_key_file, "server.key");
+        assert!(config.requires_tls("192.0.2.10".parse().unwrap()));
+        assert!(!config.requires_tls("127.0.0.1".parse().unwrap()));
+        assert!(!config.requires_tls("::ffff:127.0.0.1".parse().unwrap()));
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("ssl = maybe").is_err());
+        assert!(Config::parse("listen_addresses = 'nowhere'").is_err());
+        assert!(Config::parse("ssl_require_remote = on").is_err());
+        assert!(Config::parse("no_such_parameter = 1").is_err());
+
+        let config = Config::parse("max_connections = 10\nsuperuser_reserved_connections = 2")
+            .unwrap();
+        assert_eq!(config.max_connections, 10);
+        assert_eq!(config.superuser_reserved_connections, 2);
+        assert!(Config::parse("max_connections = -1").is_err());
+        assert!(Config::parse("max_connections = 3").is_err());
+
+        let timeout = |text| Config::parse(text).map(|config| config.authentication_timeout);
+        assert_eq!(timeout("").unwrap(), Duration::from_secs(60));
+        assert_eq!(timeout("authentication_timeout = 5").unwrap(), Duration::from_secs(5));
+        assert_eq!(timeout("authentication_timeout = '2min'").unwrap(), Duration::from_secs(120));
+        assert_eq!(
+            timeout("authentication_timeout = 1500ms").unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert!(timeout("authentication_timeout = 0").is_err());
+        assert!(timeout("authentication_timeout = 11min").is_err());
+        assert!(timeout("authentication_timeout = soon").is_err());
+    }
+}
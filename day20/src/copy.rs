@@ -0,0 +1,481 @@
+use anyhow::{bail, Result};
+
+use crate::ast::CopyOptions;
+
+// ============================================================================
+// COPY data formats
+// ============================================================================
+//
+// The text format has one line per row, fields separated by a tab, NULL
+// written as \N and backslash escapes (\t, \n, \\, octal, \x hex) for the
+// characters that would otherwise end a field or a row. CSV separates
+// fields with commas, quotes fields that contain special characters with
+// double quotes (a quote inside is doubled), and writes NULL as an
+// unquoted empty field. Data arrives from the client in arbitrary chunks,
+// so CopyReader buffers until a row is complete.
+
+const END_OF_DATA: &[u8] = b"\\.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyFormat {
+    pub csv: bool,
+    /// The first line names the columns rather than holding a row
+    pub header: bool,
+    delimiter: char,
+    quote: char,
+    null: String,
+}
+
+impl CopyFormat {
+    pub fn new(options: &CopyOptions) -> Result<Self> {
+        let single_byte = |value: &str, what: &str| {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Ok(c),
+                _ => bail!("COPY {what} must be a single one-byte character"),
+            }
+        };
+        let delimiter = match &options.delimiter {
+            Some(delimiter) => single_byte(delimiter, "delimiter")?,
+            None if options.csv => ',',
+            None => '\t',
+        };
+        let quote = match &options.quote {
+            Some(_) if !options.csv => bail!("COPY quote available only in CSV mode"),
+            Some(quote) => single_byte(quote, "quote")?,
+            None => '"',
+        };
+        let null = match &options.null {
+            Some(null) => null.clone(),
+            None if options.csv => String::new(),
+            None => "\\N".to_string(),
+        };
+
+        if delimiter == '\n' || delimiter == '\r' {
+            bail!("COPY delimiter cannot be newline or carriage return");
+        }
+        if null.contains(['\n', '\r']) {
+            bail!("COPY null representation cannot use newline or carriage return");
+        }
+        if !options.csv && delimiter == '\\' {
+            bail!("COPY delimiter cannot be \"\\\"");
+        }
+        if options.csv && delimiter == quote {
+            bail!("COPY delimiter and quote must be different");
+        }
+        if null.contains(delimiter) {
+            bail!("COPY delimiter must not appear in the NULL specification");
+        }
+        Ok(CopyFormat {
+            csv: options.csv,
+            header: options.header,
+            delimiter,
+            quote,
+            null,
+        })
+    }
+
+    /// One output line, newline included; None fields are NULL
+    pub fn format_row(&self, fields: &[Option<String>]) -> String {
+        let mut line = String::new();
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                line.push(self.delimiter);
+            }
+            match field {
+                None => line.push_str(&self.null),
+                Some(value) if self.csv => self.write_csv_field(&mut line, value),
+                Some(value) => self.write_text_field(&mut line, value),
+            }
+        }
+        line.push('\n');
+        line
+    }
+
+    /// The header line naming the columns
+    pub fn format_header(&self, names: &[String]) -> String {
+        let fields: Vec<_> = names.iter().cloned().map(Some).collect();
+        self.format_row(&fields)
+    }
+
+    fn write_text_field(&self, line: &mut String, value: &str) {
+        for c in value.chars() {
+            match c {
+                '\\' => line.push_str("\\\\"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                '\t' if self.delimiter == '\t' => line.push_str("\\t"),
+                '\u{8}' => line.push_str("\\b"),
+                '\u{c}' => line.push_str("\\f"),
+                '\u{b}' => line.push_str("\\v"),
+                c if c == self.delimiter => {
+                    line.push('\\');
+                    line.push(c);
+                }
+                c => line.push(c),
+            }
+        }
+    }
+
+    fn write_csv_field(&self, line: &mut String, value: &str) {
+        // Quote anything that could be read back as a delimiter, a row end,
+        // NULL or the end-of-data marker
+        let needs_quotes = value == self.null
+            || value == "\\."
+            || value.contains([self.delimiter, self.quote, '\n', '\r']);
+        if !needs_quotes {
+            line.push_str(value);
+            return;
+        }
+        line.push(self.quote);
+        for c in value.chars() {
+            if c == self.quote {
+                line.push(c);
+            }
+            line.push(c);
+        }
+        line.push(self.quote);
+    }
+
+    fn parse_text_row(&self, line: &str) -> Result<Vec<Option<String>>> {
+        let mut fields = Vec::new();
+        let mut chars = line.chars().peekable();
+        let mut raw = String::new();
+        loop {
+            match chars.next() {
+                Some('\\') => {
+                    raw.push('\\');
+                    if let Some(c) = chars.next() {
+                        raw.push(c);
+                    }
+                }
+                Some(c) if c == self.delimiter => {
+                    fields.push(self.text_field(&raw)?);
+                    raw.clear();
+                }
+                Some(c) => raw.push(c),
+                None => {
+                    fields.push(self.text_field(&raw)?);
+                    return Ok(fields);
+                }
+            }
+        }
+    }
+
+    // NULL is recognized before escapes are processed, as in PostgreSQL.
+    // Octal and hex escapes give bytes, which together must be valid UTF-8.
+    fn text_field(&self, raw: &str) -> Result<Option<String>> {
+        if raw == self.null {
+            return Ok(None);
+        }
+        let mut bytes = Vec::with_capacity(raw.len());
+        let mut chars = raw.chars().peekable();
+        let digits = |chars: &mut std::iter::Peekable<std::str::Chars>, radix, first| {
+            let mut n = first;
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(radix)) {
+                    Some(digit) => {
+                        n = n * radix + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            n as u8
+        };
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                let mut utf8 = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                continue;
+            }
+            let byte = match chars.next() {
+                Some('b') => 0x08,
+                Some('f') => 0x0c,
+                Some('n') => b'\n',
+                Some('r') => b'\r',
+                Some('t') => b'\t',
+                Some('v') => 0x0b,
+                Some(d @ '0'..='7') => digits(&mut chars, 8, d.to_digit(8).unwrap()),
+                Some('x') if chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) => {
+                    digits(&mut chars, 16, 0)
+                }
+                Some(other) => {
+                    let mut utf8 = [0; 4];
+                    bytes.extend_from_slice(other.encode_utf8(&mut utf8).as_bytes());
+                    continue;
+                }
+                None => bail!("unterminated backslash escape"),
+            };
+            bytes.push(byte);
+        }
+        match String::from_utf8(bytes) {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => bail!("invalid byte sequence for encoding \"UTF8\""),
+        }
+    }
+
+    fn parse_csv_row(&self, line: &str) -> Result<Vec<Option<String>>> {
+        let mut fields = Vec::new();
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut chars = line.chars().peekable();
+        loop {
+            match chars.next() {
+                Some(c) if in_quotes && c == self.quote => {
+                    if chars.peek() == Some(&self.quote) {
+                        value.push(c);
+                        chars.next();
+                    } else {
+                        in_quotes = false;
+                    }
+                }
+                Some(c) if in_quotes => value.push(c),
+                Some(c) if c == self.quote => {
+                    quoted = true;
+                    in_quotes = true;
+                }
+                Some(c) if c == self.delimiter => {
+                    fields.push(self.csv_field(&mut value, quoted));
+                    quoted = false;
+                }
+                Some(c) => value.push(c),
+                None if in_quotes => bail!("unterminated CSV quoted field"),
+                None => {
+                    fields.push(self.csv_field(&mut value, quoted));
+                    return Ok(fields);
+                }
+            }
+        }
+    }
+
+    // A quoted field is never NULL, so "" is an empty string
+    fn csv_field(&self, value: &mut String, quoted: bool) -> Option<String> {
+        let value = std::mem::take(value);
+        if !quoted && value == self.null {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+/// Splits the COPY data stream into rows
+pub struct CopyReader {
+    format: CopyFormat,
+    buffer: Vec<u8>,
+    /// Start of the row being read in `buffer`
+    start: usize,
+    /// Where the search for the end of the row resumes
+    scan: usize,
+    /// Inside a CSV quoted field, where a newline is data
+    in_quotes: bool,
+    line: usize,
+    /// The end-of-data marker \. has been read; the rest is ignored
+    done: bool,
+}
+
+impl CopyReader {
+    pub fn new(format: CopyFormat) -> Self {
+        CopyReader {
+            format,
+            buffer: Vec::new(),
+            start: 0,
+            scan: 0,
+            in_quotes: false,
+            line: 0,
+            done: false,
+        }
+    }
+
+    /// Number of the last line read, for error messages
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Add data; `on_row` is called for each row it completes
+    pub fn feed(
+        &mut self,
+        data: &[u8],
+        mut on_row: impl FnMut(Vec<Option<String>>) -> Result<()>,
+    ) -> Result<()> {
+        if self.done {
+            return Ok(());
+        }
+        self.buffer.extend_from_slice(data);
+        while self.scan < self.buffer.len() {
+            let byte = self.buffer[self.scan];
+            self.scan += 1;
+            if self.format.csv && byte == self.format.quote as u8 {
+                // A doubled quote toggles twice, leaving the state unchanged
+                self.in_quotes = !self.in_quotes;
+            } else if byte == b'\n' && !self.in_quotes {
+                let (start, end) = (self.start, self.scan - 1);
+                self.start = self.scan;
+                self.read_row(start, end, &mut on_row)?;
+                if self.done {
+                    break;
+                }
+            }
+        }
+        self.buffer.drain(..self.start);
+        self.scan -= self.start;
+        self.start = 0;
+        Ok(())
+    }
+
+    /// End of the data stream: a last row without a newline is still a row
+    pub fn finish(
+        &mut self,
+        mut on_row: impl FnMut(Vec<Option<String>>) -> Result<()>,
+    ) -> Result<()> {
+        if self.done || self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.in_quotes {
+            self.line += 1;
+            bail!("unterminated CSV quoted field");
+        }
+        let end = self.buffer.len();
+        self.start = end;
+        self.read_row(0, end, &mut on_row)?;
+        self.buffer.clear();
+        self.start = 0;
+        self.scan = 0;
+        Ok(())
+    }
+
+    fn read_row(
+        &mut self,
+        start: usize,
+        mut end: usize,
+        on_row: &mut impl FnMut(Vec<Option<String>>) -> Result<()>,
+    ) -> Result<()> {
+        if end > start && self.buffer[end - 1] == b'\r' {
+            end -= 1;
+        }
+        self.line += 1;
+        let line = &self.buffer[start..end];
+        if line == END_OF_DATA {
+            self.done = true;
+            return Ok(());
+        }
+        if self.line == 1 && self.format.header {
+            return Ok(());
+        }
+        let Ok(line) = std::str::from_utf8(line) else {
+            bail!("invalid byte sequence for encoding \"UTF8\"");
+        };
+        let fields = if self.format.csv {
+            self.format.parse_csv_row(line)?
+        } else {
+            self.format.parse_text_row(line)?
+        };
+        on_row(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(format: &CopyFormat, chunks: &[&[u8]]) -> Result<Vec<Vec<Option<String>>>> {
+        let mut reader = CopyReader::new(format.clone());
+        let mut rows = Vec::new();
+        for chunk in chunks {
+            reader.feed(chunk, |row| {
+                rows.push(row);
+                Ok(())
+            })?;
+        }
+        reader.finish(|row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
+    fn row(fields: &[Option<&str>]) -> Vec<Option<String>> {
+        fields.iter().map(|f| f.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn test_text_format() {
+        let format = CopyFormat::new(&CopyOptions::default()).unwrap();
+        let rows = read(&format, &[b"1\tone\n2\t\\N\n3\ta\\tb\\\\c\\nd\n"]).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                row(&[Some("1"), Some("one")]),
+                row(&[Some("2"), None]),
+                row(&[Some("3"), Some("a\tb\\c\nd")]),
+            ]
+        );
+
+        // Rows split across chunks, CRLF line ends, the end marker
+        let rows = read(&format, &[b"1\tt", b"wo\r", b"\n2\t\\", b"303\\251\n\\.\nignored\n"]).unwrap();
+        assert_eq!(rows, vec![row(&[Some("1"), Some("two")]), row(&[Some("2"), Some("\u{e9}")])]);
+        // A last line without a newline
+        assert_eq!(read(&format, &[b"x\ty"]).unwrap(), vec![row(&[Some("x"), Some("y")])]);
+
+        let line = format.format_row(&row(&[Some("a\tb\\c\nd"), None, Some("")]));
+        assert_eq!(line, "a\\tb\\\\c\\nd\t\\N\t\n");
+        assert_eq!(read(&format, &[line.as_bytes()]).unwrap(), vec![row(&[Some("a\tb\\c\nd"), None, Some("")])]);
+    }
+
+    #[test]
+    fn test_csv_format() {
+        let options = CopyOptions {
+            csv: true,
+            header: true,
+            ..Default::default()
+        };
+        let format = CopyFormat::new(&options).unwrap();
+        let rows = read(&format, &[b"id,name\n1,\"multi\nline, \"\"quoted\"\"\"\n2,\n3,\"\"\n"]).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                row(&[Some("1"), Some("multi\nline, \"quoted\"")]),
+                row(&[Some("2"), None]),
+                row(&[Some("3"), Some("")]),
+            ]
+        );
+        assert!(read(&format, &[b"id\n\"open"]).is_err());
+
+        assert_eq!(format.format_header(&["id".to_string(), "name".to_string()]), "id,name\n");
+        assert_eq!(format.format_row(&row(&[Some("a,b"), None, Some(""), Some("x\"y")])), "\"a,b\",,\"\",\"x\"\"y\"\n");
+
+        let options = CopyOptions {
+            csv: true,
+            delimiter: Some("|".to_string()),
+            quote: Some("'".to_string()),
+            null: Some("NULL".to_string()),
+            ..Default::default()
+        };
+        let format = CopyFormat::new(&options).unwrap();
+        assert_eq!(read(&format, &[b"'a|b'|NULL|'NULL'\n"]).unwrap(), vec![row(&[Some("a|b"), None, Some("NULL")])]);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let invalid = |options: CopyOptions| CopyFormat::new(&options).is_err();
+        assert!(invalid(CopyOptions {
+            delimiter: Some("ab".to_string()),
+            ..Default::default()
+        }));
+        assert!(invalid(CopyOptions {
+            quote: Some("'".to_string()),
+            ..Default::default()
+        }));
+        assert!(invalid(CopyOptions {
+            csv: true,
+            delimiter: Some("\"".to_string()),
+            ..Default::default()
+        }));
+        assert!(invalid(CopyOptions {
+            null: Some("a\tb".to_string()),
+            ..Default::default()
+        }));
+    }
+}
@@ -0,0 +1,914 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+
+use crate::numeric::Numeric;
+
+// ============================================================================
+// Date/time support for DATE, TIME, TIMESTAMP, TIMESTAMPTZ and INTERVAL
+// ============================================================================
+//
+// Representation follows PostgreSQL:
+//   DATE         i32 days since 2000-01-01
+//   TIME         i64 microseconds since midnight
+//   TIMESTAMP    i64 microseconds since 2000-01-01 00:00:00
+//   TIMESTAMPTZ  i64 microseconds since 2000-01-01 00:00:00 UTC
+//   INTERVAL     months + days + microseconds, kept separately because
+//                months and days vary in length
+//
+// The session time zone is always UTC.
+
+pub const USECS_PER_SEC: i64 = 1_000_000;
+pub const USECS_PER_MINUTE: i64 = 60 * USECS_PER_SEC;
+pub const USECS_PER_HOUR: i64 = 60 * USECS_PER_MINUTE;
+pub const USECS_PER_DAY: i64 = 24 * USECS_PER_HOUR;
+const DAYS_PER_MONTH: i64 = 30;
+
+/// Days from 1970-01-01 (Unix epoch) to 2000-01-01 (PostgreSQL epoch)
+const UNIX_TO_PG_EPOCH_DAYS: i64 = 10_957;
+
+/// PostgreSQL's DATE range, 4714-11-24 BC up to 5874898-01-01, in days
+/// since 2000-01-01
+const MIN_DATE: i64 = -2_451_545;
+const END_DATE: i64 = 2_145_031_949;
+
+/// PostgreSQL's TIMESTAMP range, 4714-11-24 BC up to 294277-01-01
+const MIN_TIMESTAMP: i64 = MIN_DATE * USECS_PER_DAY;
+const END_TIMESTAMP: i64 = 106_751_983 * USECS_PER_DAY;
+
+/// Largest year a date literal may have
+const MAX_YEAR: i64 = 5_874_897;
+
+thread_local! {
+    /// Start time of the transaction running on this connection thread
+    static TRANSACTION_TIMESTAMP: Cell<Option<i64>> = const { Cell::new(None) };
+}
+
+/// Record the start of a transaction; now() returns this value until the
+/// next transaction starts, as in PostgreSQL.
+pub fn set_transaction_timestamp() {
+    TRANSACTION_TIMESTAMP.with(|ts| ts.set(Some(system_timestamp())));
+}
+
+/// Current timestamp (UTC) as of the start of the current transaction.
+pub fn transaction_timestamp() -> i64 {
+    TRANSACTION_TIMESTAMP
+        .with(|ts| ts.get())
+        .unwrap_or_else(system_timestamp)
+}
+
+/// Current wall-clock timestamp (UTC), ignoring the transaction start
+pub fn system_timestamp() -> i64 {
+    let since_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as i64)
+        .unwrap_or(0);
+    since_unix - UNIX_TO_PG_EPOCH_DAYS * USECS_PER_DAY
+}
+
+// ----------------------------------------------------------------------------
+// Calendar arithmetic (proleptic Gregorian, civil-from-days algorithm)
+// ----------------------------------------------------------------------------
+
+/// Days since 2000-01-01 for a calendar date
+pub fn date_from_ymd(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12; // March = 0
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468 - UNIX_TO_PG_EPOCH_DAYS
+}
+
+/// Calendar date for days since 2000-01-01
+pub fn ymd_from_date(date: i64) -> (i64, u32, u32) {
+    let z = date + UNIX_TO_PG_EPOCH_DAYS + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Split a timestamp into (days since 2000-01-01, microseconds into the day)
+fn split_timestamp(ts: i64) -> (i64, i64) {
+    (ts.div_euclid(USECS_PER_DAY), ts.rem_euclid(USECS_PER_DAY))
+}
+
+pub fn date_to_timestamp(date: i32) -> Result<i64> {
+    check_timestamp((date as i64).checked_mul(USECS_PER_DAY))
+}
+
+/// DATE + TIME
+pub fn date_plus_time(date: i32, time: i64) -> Result<i64> {
+    check_timestamp(date_to_timestamp(date)?.checked_add(time))
+}
+
+/// A timestamp computed with checked arithmetic, if it is in range
+fn check_timestamp(ts: Option<i64>) -> Result<i64> {
+    ts.filter(|ts| (MIN_TIMESTAMP..END_TIMESTAMP).contains(ts))
+        .ok_or_else(|| anyhow::anyhow!("timestamp out of range"))
+}
+
+/// Days since 2000-01-01 as a DATE, if they are in range
+pub fn check_date(days: i64) -> Result<i32> {
+    if !(MIN_DATE..END_DATE).contains(&days) {
+        bail!("date out of range");
+    }
+    Ok(days as i32)
+}
+
+pub fn timestamp_to_date(ts: i64) -> Result<i32> {
+    i32::try_from(split_timestamp(ts).0).map_err(|_| anyhow::anyhow!("date out of range"))
+}
+
+// ----------------------------------------------------------------------------
+// Parsing
+// ----------------------------------------------------------------------------
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Option<T> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parse 'YYYY-MM-DD'
+pub fn parse_date(s: &str) -> Result<i32> {
+    let days = parse_date_part(s.trim(), s, "date")?;
+    check_date(days).map_err(|_| anyhow::anyhow!("date out of range: \"{s}\""))
+}
+
+fn parse_date_part(text: &str, input: &str, type_name: &str) -> Result<i64> {
+    let invalid = || anyhow::anyhow!("invalid input syntax for type {type_name}: \"{input}\"");
+    let mut parts = text.splitn(3, '-');
+    let (Some(y), Some(m), Some(d)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let year: i64 = parse_number(y).ok_or_else(invalid)?;
+    let month: u32 = parse_number(m).ok_or_else(invalid)?;
+    let day: u32 = parse_number(d).ok_or_else(invalid)?;
+    if year > MAX_YEAR
+        || !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+    {
+        bail!("date/time field value out of range: \"{input}\"");
+    }
+    Ok(date_from_ymd(year, month, day))
+}
+
+/// Parse 'HH:MM[:SS[.ffffff]]'
+pub fn parse_time(s: &str) -> Result<i64> {
+    parse_time_part(s.trim(), s, "time")
+}
+
+fn parse_time_part(text: &str, input: &str, type_name: &str) -> Result<i64> {
+    let invalid = || anyhow::anyhow!("invalid input syntax for type {type_name}: \"{input}\"");
+    let mut parts = text.splitn(3, ':');
+    let hour: i64 = parts.next().and_then(parse_number).ok_or_else(invalid)?;
+    let minute: i64 = parts.next().and_then(parse_number).ok_or_else(invalid)?;
+    let (second, micros) = match parts.next() {
+        Some(sec) => {
+            let (whole, frac) = sec.split_once('.').unwrap_or((sec, ""));
+            let second: i64 = parse_number(whole).ok_or_else(invalid)?;
+            (second, parse_fraction(frac).ok_or_else(invalid)?)
+        }
+        None => (0, 0),
+    };
+    let out_of_range = || anyhow::anyhow!("date/time field value out of range: \"{input}\"");
+    if hour > 24 || minute > 59 || second > 59 {
+        return Err(out_of_range());
+    }
+    let time = hour * USECS_PER_HOUR + minute * USECS_PER_MINUTE + second * USECS_PER_SEC + micros;
+    if time > USECS_PER_DAY {
+        return Err(out_of_range());
+    }
+    Ok(time)
+}
+
+/// Fractional seconds digits to microseconds, rounded
+fn parse_fraction(frac: &str) -> Option<i64> {
+    if frac.is_empty() {
+        return Some(0);
+    }
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let padded = format!("{frac:0<7}");
+    let micros: i64 = padded[..6].parse().ok()?;
+    Some(micros + if padded.as_bytes()[6] >= b'5' { 1 } else { 0 })
+}
+
+/// Parse 'YYYY-MM-DD[( |T)HH:MM[:SS[.ffffff]]][zone]'. Returns the local
+/// timestamp and the zone offset in seconds east of UTC, if one was given.
+fn parse_timestamp_parts(s: &str, type_name: &str) -> Result<(i64, Option<i64>)> {
+    let text = s.trim();
+    let (date_text, rest) = match text.find([' ', 'T']) {
+        Some(pos) => (&text[..pos], text[pos + 1..].trim()),
+        None => (text, ""),
+    };
+    let date = parse_date_part(date_text, s, type_name)?;
+
+    // Split a trailing zone (Z, UTC, +HH, -HH:MM, ...) from the time
+    let (time_text, zone_text) = if let Some(stripped) = rest.strip_suffix('Z') {
+        (stripped, Some("+00"))
+    } else if let Some(stripped) = rest.strip_suffix("UTC") {
+        (stripped.trim(), Some("+00"))
+    } else {
+        match rest.find(['+', '-']) {
+            Some(pos) => (rest[..pos].trim(), Some(&rest[pos..])),
+            None => (rest, None),
+        }
+    };
+    let time = if time_text.is_empty() {
+        0
+    } else {
+        parse_time_part(time_text, s, type_name)?
+    };
+    let offset = zone_text
+        .map(|zone| {
+            parse_zone_offset(zone).ok_or_else(|| {
+                anyhow::anyhow!("invalid input syntax for type {type_name}: \"{s}\"")
+            })
+        })
+        .transpose()?;
+    let ts = check_timestamp(date.checked_mul(USECS_PER_DAY).and_then(|ts| ts.checked_add(time)))
+        .map_err(|_| anyhow::anyhow!("timestamp out of range: \"{s}\""))?;
+    Ok((ts, offset))
+}
+
+/// '+HH', '+HH:MM' or '+HHMM' to seconds east of UTC
+fn parse_zone_offset(zone: &str) -> Option<i64> {
+    let sign = match zone.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (parse_number::<i64>(&digits)?, 0),
+        4 => (
+            parse_number::<i64>(&digits[..2])?,
+            parse_number::<i64>(&digits[2..])?,
+        ),
+        _ => return None,
+    };
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parse a TIMESTAMP literal; any time zone in the input is ignored.
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    Ok(parse_timestamp_parts(s, "timestamp")?.0)
+}
+
+/// Parse a TIMESTAMPTZ literal, converting to UTC (no zone = UTC).
+pub fn parse_timestamptz(s: &str) -> Result<i64> {
+    let (local, offset) = parse_timestamp_parts(s, "timestamp with time zone")?;
+    Ok(local - offset.unwrap_or(0) * USECS_PER_SEC)
+}
+
+// ----------------------------------------------------------------------------
+// Formatting
+// ----------------------------------------------------------------------------
+
+pub fn format_date(date: i32) -> String {
+    let (year, month, day) = ymd_from_date(date as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// HH:MM:SS with fractional seconds only when non-zero
+pub fn format_time(time: i64) -> String {
+    let (hours, rem) = (time / USECS_PER_HOUR, time % USECS_PER_HOUR);
+    let (minutes, rem) = (rem / USECS_PER_MINUTE, rem % USECS_PER_MINUTE);
+    let (seconds, micros) = (rem / USECS_PER_SEC, rem % USECS_PER_SEC);
+    let mut text = format!("{hours:02}:{minutes:02}:{seconds:02}");
+    if micros != 0 {
+        let frac = format!("{micros:06}");
+        text.push('.');
+        text.push_str(frac.trim_end_matches('0'));
+    }
+    text
+}
+
+pub fn format_timestamp(ts: i64) -> String {
+    let (date, time) = split_timestamp(ts);
+    let (year, month, day) = ymd_from_date(date);
+    format!("{year:04}-{month:02}-{day:02} {}", format_time(time))
+}
+
+pub fn format_timestamptz(ts: i64) -> String {
+    format!("{}+00", format_timestamp(ts))
+}
+
+// ----------------------------------------------------------------------------
+// Interval
+// ----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+impl Interval {
+    pub fn new(months: i32, days: i32, micros: i64) -> Self {
+        Interval {
+            months,
+            days,
+            micros,
+        }
+    }
+
+    /// Total length assuming 30-day months and 24-hour days, used for
+    /// comparison ('1 day' = '24 hours', as in PostgreSQL)
+    fn span(&self) -> i128 {
+        (self.months as i128 * DAYS_PER_MONTH as i128 + self.days as i128) * USECS_PER_DAY as i128
+            + self.micros as i128
+    }
+
+    /// Parse PostgreSQL-style input such as '1 year 2 months', '3 days 04:05:06',
+    /// '-1.5 hours' or '2 weeks ago'.
+    pub fn parse(s: &str) -> Result<Interval> {
+        let invalid = || anyhow::anyhow!("invalid input syntax for type interval: \"{s}\"");
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        if tokens.is_empty() {
+            return Err(invalid());
+        }
+        let (mut months, mut days, mut micros) = (0f64, 0f64, 0f64);
+        let mut i = 0;
+        let mut ago = false;
+        while i < tokens.len() {
+            let token = tokens[i];
+            if token.eq_ignore_ascii_case("ago") && i == tokens.len() - 1 {
+                ago = true;
+                i += 1;
+                continue;
+            }
+            if token.contains(':') {
+                let (negative, text) = match token.strip_prefix('-') {
+                    Some(rest) => (true, rest),
+                    None => (false, token.strip_prefix('+').unwrap_or(token)),
+                };
+                let time = parse_interval_time(text, s)?;
+                micros += if negative { -time } else { time } as f64;
+                i += 1;
+                continue;
+            }
+            // A number followed by a unit, possibly written together ("10min")
+            let split = token
+                .find(|c: char| c.is_ascii_alphabetic())
+                .unwrap_or(token.len());
+            let number: f64 = token[..split].parse().map_err(|_| invalid())?;
+            let unit = if split < token.len() {
+                &token[split..]
+            } else {
+                i += 1;
+                tokens.get(i).copied().unwrap_or("second")
+            };
+            i += 1;
+            match unit.to_ascii_lowercase().as_str() {
+                "microsecond" | "microseconds" | "us" | "usec" | "usecs" => micros += number,
+                "millisecond" | "milliseconds" | "ms" | "msec" | "msecs" => {
+                    micros += number * 1_000.0
+                }
+                "second" | "seconds" | "s" | "sec" | "secs" => {
+                    micros += number * USECS_PER_SEC as f64
+                }
+                "minute" | "minutes" | "m" | "min" | "mins" => {
+                    micros += number * USECS_PER_MINUTE as f64
+                }
+                "hour" | "hours" | "h" | "hr" | "hrs" => micros += number * USECS_PER_HOUR as f64,
+                "day" | "days" | "d" => days += number,
+                "week" | "weeks" | "w" => days += number * 7.0,
+                "month" | "months" | "mon" | "mons" => months += number,
+                "year" | "years" | "y" | "yr" | "yrs" => months += number * 12.0,
+                "decade" | "decades" => months += number * 120.0,
+                "century" | "centuries" => months += number * 1_200.0,
+                _ => return Err(invalid()),
+            }
+        }
+
+        // Cascade fractional months into days and fractional days into time
+        let whole_months = months.trunc();
+        days += (months - whole_months) * DAYS_PER_MONTH as f64;
+        let whole_days = days.trunc();
+        micros += (days - whole_days) * USECS_PER_DAY as f64;
+        let sign = if ago { -1.0 } else { 1.0 };
+        let out_of_range = || anyhow::anyhow!("interval out of range");
+        Ok(Interval::new(
+            to_i32(sign * whole_months).ok_or_else(out_of_range)?,
+            to_i32(sign * whole_days).ok_or_else(out_of_range)?,
+            to_i64((sign * micros).round()).ok_or_else(out_of_range)?,
+        ))
+    }
+
+    pub fn add(&self, other: &Interval) -> Result<Interval> {
+        let out_of_range = || anyhow::anyhow!("interval out of range");
+        Ok(Interval::new(
+            self.months
+                .checked_add(other.months)
+                .ok_or_else(out_of_range)?,
+            self.days.checked_add(other.days).ok_or_else(out_of_range)?,
+            self.micros
+                .checked_add(other.micros)
+                .ok_or_else(out_of_range)?,
+        ))
+    }
+
+    pub fn neg(&self) -> Result<Interval> {
+        Interval::new(0, 0, 0).sub(self)
+    }
+
+    pub fn sub(&self, other: &Interval) -> Result<Interval> {
+        let out_of_range = || anyhow::anyhow!("interval out of range");
+        Ok(Interval::new(
+            self.months
+                .checked_sub(other.months)
+                .ok_or_else(out_of_range)?,
+            self.days.checked_sub(other.days).ok_or_else(out_of_range)?,
+            self.micros
+                .checked_sub(other.micros)
+                .ok_or_else(out_of_range)?,
+        ))
+    }
+
+    /// Multiply by a factor; fractional months spill into days and
+    /// fractional days into time, as PostgreSQL does.
+    pub fn mul(&self, factor: f64) -> Result<Interval> {
+        let out_of_range = || anyhow::anyhow!("interval out of range");
+        let months = self.months as f64 * factor;
+        let whole_months = months.trunc();
+        let days = self.days as f64 * factor + (months - whole_months) * DAYS_PER_MONTH as f64;
+        let whole_days = days.trunc();
+        let micros = self.micros as f64 * factor + (days - whole_days) * USECS_PER_DAY as f64;
+        Ok(Interval::new(
+            to_i32(whole_months).ok_or_else(out_of_range)?,
+            to_i32(whole_days).ok_or_else(out_of_range)?,
+            to_i64(micros.round()).ok_or_else(out_of_range)?,
+        ))
+    }
+}
+
+fn to_i32(f: f64) -> Option<i32> {
+    (f >= i32::MIN as f64 && f <= i32::MAX as f64).then_some(f as i32)
+}
+
+fn to_i64(f: f64) -> Option<i64> {
+    (f >= i64::MIN as f64 && f < i64::MAX as f64).then_some(f as i64)
+}
+
+/// 'HH:MM[:SS[.ffffff]]' in an interval, where hours may exceed 24
+fn parse_interval_time(text: &str, input: &str) -> Result<i64> {
+    let invalid = || anyhow::anyhow!("invalid input syntax for type interval: \"{input}\"");
+    let mut parts = text.splitn(3, ':');
+    let hours: i64 = parts.next().and_then(parse_number).ok_or_else(invalid)?;
+    let minutes: i64 = parts.next().and_then(parse_number).ok_or_else(invalid)?;
+    let (seconds, micros) = match parts.next() {
+        Some(sec) => {
+            let (whole, frac) = sec.split_once('.').unwrap_or((sec, ""));
+            let seconds: i64 = parse_number(whole).ok_or_else(invalid)?;
+            (seconds, parse_fraction(frac).ok_or_else(invalid)?)
+        }
+        None => (0, 0),
+    };
+    hours
+        .checked_mul(USECS_PER_HOUR)
+        .and_then(|t| t.checked_add(minutes.checked_mul(USECS_PER_MINUTE)?))
+        .and_then(|t| t.checked_add(seconds.checked_mul(USECS_PER_SEC)?))
+        .and_then(|t| t.checked_add(micros))
+        .ok_or_else(|| anyhow::anyhow!("date/time field value out of range: \"{input}\""))
+}
+
+impl std::fmt::Display for Interval {
+    /// PostgreSQL's default "postgres" interval style, e.g. "1 year 2 mons 3 days 04:05:06"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn unit(n: i64, singular: &str, plural: &str) -> String {
+            format!("{n} {}", if n == 1 { singular } else { plural })
+        }
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(unit(years as i64, "year", "years"));
+        }
+        if months != 0 {
+            parts.push(unit(months as i64, "mon", "mons"));
+        }
+        if self.days != 0 {
+            parts.push(unit(self.days as i64, "day", "days"));
+        }
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 {
+                "-"
+            } else if self.months < 0 || self.days < 0 {
+                "+"
+            } else {
+                ""
+            };
+            let time = self.micros.unsigned_abs() as i64;
+            let hours = time / USECS_PER_HOUR;
+            let rest = format_time(time % USECS_PER_HOUR);
+            // format_time renders the hour as "00"; substitute the full hour count
+            parts.push(format!("{sign}{hours:02}{}", &rest[2..]));
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl PartialEq for Interval {
+    fn eq(&self, other: &Self) -> bool {
+        self.span() == other.span()
+    }
+}
+
+impl Eq for Interval {}
+
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.span().cmp(&other.span())
+    }
+}
+
+impl Hash for Interval {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.span().hash(state);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Arithmetic, extract and date_trunc
+// ----------------------------------------------------------------------------
+
+/// timestamp + interval: months first (clamping the day to the month's
+/// length), then days, then time.
+pub fn timestamp_plus_interval(ts: i64, interval: &Interval) -> Result<i64> {
+    let (mut date, time) = split_timestamp(ts);
+    if interval.months != 0 {
+        let (year, month, day) = ymd_from_date(date);
+        let total = year * 12 + (month as i64 - 1) + interval.months as i64;
+        let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+        date = date_from_ymd(year, month, day.min(days_in_month(year, month)));
+    }
+    date += interval.days as i64;
+    date.checked_mul(USECS_PER_DAY)
+        .and_then(|ts| ts.checked_add(time))
+        .and_then(|ts| ts.checked_add(interval.micros))
+        .ok_or_else(|| anyhow::anyhow!("timestamp out of range"))
+}
+
+/// timestamp - timestamp, as days plus time (hours folded into days)
+pub fn timestamp_difference(a: i64, b: i64) -> Result<Interval> {
+    let diff = a
+        .checked_sub(b)
+        .ok_or_else(|| anyhow::anyhow!("interval out of range"))?;
+    let days = i32::try_from(diff / USECS_PER_DAY)
+        .map_err(|_| anyhow::anyhow!("interval out of range"))?;
+    Ok(Interval::new(0, days, diff % USECS_PER_DAY))
+}
+
+/// The value a field of a date/time value is extracted from
+pub enum DateTimeSource {
+    Date(i32),
+    Time(i64),
+    Timestamp(i64),
+    Interval(Interval),
+}
+
+impl DateTimeSource {
+    fn type_name(&self) -> &'static str {
+        match self {
+            DateTimeSource::Date(_) => "date",
+            DateTimeSource::Time(_) => "time without time zone",
+            DateTimeSource::Timestamp(_) => "timestamp",
+            DateTimeSource::Interval(_) => "interval",
+        }
+    }
+}
+
+/// extract(field FROM source), returned as NUMERIC like PostgreSQL 14+
+pub fn extract(field: &str, source: &DateTimeSource) -> Result<Numeric> {
+    let field = field.to_ascii_lowercase();
+    let unsupported = || {
+        anyhow::anyhow!(
+            "unit \"{field}\" not supported for type {}",
+            source.type_name()
+        )
+    };
+    let (date, time) = match source {
+        DateTimeSource::Date(d) => (Some(*d as i64), None),
+        DateTimeSource::Time(t) => (None, Some(*t)),
+        DateTimeSource::Timestamp(ts) => {
+            let (date, time) = split_timestamp(*ts);
+            (Some(date), Some(time))
+        }
+        DateTimeSource::Interval(interval) => return extract_interval(&field, interval),
+    };
+
+    // Date fields
+    if let Some(date) = date {
+        let (year, month, day) = ymd_from_date(date);
+        let isodow = (date + 5).rem_euclid(7) + 1; // 2000-01-01 was a Saturday
+        let value = match field.as_str() {
+            "millennium" => Some((year + 999).div_euclid(1000)),
+            "century" => Some((year + 99).div_euclid(100)),
+            "decade" => Some(year.div_euclid(10)),
+            "year" => Some(year),
+            "quarter" => Some((month as i64 - 1) / 3 + 1),
+            "month" => Some(month as i64),
+            "day" => Some(day as i64),
+            "dow" => Some(isodow % 7),
+            "isodow" => Some(isodow),
+            "doy" => Some(date - date_from_ymd(year, 1, 1) + 1),
+            "week" => Some(iso_week(date)),
+            _ => None,
+        };
+        if let Some(n) = value {
+            return Ok(Numeric::from_i64(n));
+        }
+    }
+
+    match (field.as_str(), time) {
+        ("epoch", _) => {
+            // Whole days in seconds, which no DATE overflows, then the time
+            let days = date.unwrap_or(0) + UNIX_TO_PG_EPOCH_DAYS;
+            let seconds = Numeric::from_i64(days * (USECS_PER_DAY / USECS_PER_SEC));
+            match time {
+                Some(t) => Ok(seconds.add(&micros_to_seconds(t))),
+                None => Ok(seconds),
+            }
+        }
+        ("hour", Some(t)) => Ok(Numeric::from_i64(t / USECS_PER_HOUR)),
+        ("minute", Some(t)) => Ok(Numeric::from_i64(t % USECS_PER_HOUR / USECS_PER_MINUTE)),
+        ("second", Some(t)) => Ok(micros_to_seconds(t % USECS_PER_MINUTE)),
+        ("milliseconds", Some(t)) => {
+            Numeric::parse(&format!("{:.3}", (t % USECS_PER_MINUTE) as f64 / 1000.0))
+        }
+        ("microseconds", Some(t)) => Ok(Numeric::from_i64(t % USECS_PER_MINUTE)),
+        _ if is_known_field(&field) => Err(unsupported()),
+        _ => bail!(
+            "unit \"{field}\" not recognized for type {}",
+            source.type_name()
+        ),
+    }
+}
+
+fn extract_interval(field: &str, interval: &Interval) -> Result<Numeric> {
+    let value = match field {
+        "millennium" => interval.months as i64 / 12_000,
+        "century" => interval.months as i64 / 1_200,
+        "decade" => interval.months as i64 / 120,
+        "year" => interval.months as i64 / 12,
+        "quarter" => (interval.months as i64 % 12) / 3 + 1,
+        "month" => interval.months as i64 % 12,
+        "day" => interval.days as i64,
+        "hour" => interval.micros / USECS_PER_HOUR,
+        "minute" => interval.micros % USECS_PER_HOUR / USECS_PER_MINUTE,
+        "second" => return Ok(micros_to_seconds(interval.micros % USECS_PER_MINUTE)),
+        "milliseconds" => {
+            let ms = (interval.micros % USECS_PER_MINUTE) as f64 / 1000.0;
+            return Numeric::parse(&format!("{ms:.3}"));
+        }
+        "microseconds" => interval.micros % USECS_PER_MINUTE,
+        "epoch" => {
+            // Years count as 365.25 days and remaining months as 30 days
+            let years = interval.months as i64 / 12;
+            let months = interval.months as i64 % 12;
+            let micros = (years * 36_525 * USECS_PER_DAY) / 100
+                + (months * DAYS_PER_MONTH + interval.days as i64) * USECS_PER_DAY
+                + interval.micros;
+            return Ok(micros_to_seconds(micros));
+        }
+        _ if is_known_field(field) => bail!("unit \"{field}\" not supported for type interval"),
+        _ => bail!("unit \"{field}\" not recognized for type interval"),
+    };
+    Ok(Numeric::from_i64(value))
+}
+
+fn is_known_field(field: &str) -> bool {
+    matches!(
+        field,
+        "millennium"
+            | "century"
+            | "decade"
+            | "year"
+            | "quarter"
+            | "month"
+            | "week"
+            | "day"
+            | "dow"
+            | "isodow"
+            | "doy"
+            | "hour"
+            | "minute"
+            | "second"
+            | "milliseconds"
+            | "microseconds"
+            | "epoch"
+    )
+}
+
+/// Microseconds as seconds with six decimal places
+fn micros_to_seconds(micros: i64) -> Numeric {
+    let sign = if micros < 0 { "-" } else { "" };
+    let abs = micros.unsigned_abs();
+    let text = format!("{sign}{}.{:06}", abs / 1_000_000, abs % 1_000_000);
+    Numeric::parse(&text).expect("formatted seconds are valid numeric")
+}
+
+/// ISO 8601 week number (weeks start on Monday; week 1 contains January 4th)
+fn iso_week(date: i64) -> i64 {
+    let isodow = (date + 5).rem_euclid(7) + 1;
+    let thursday = date + (4 - isodow);
+    let (year, _, _) = ymd_from_date(thursday);
+    (thursday - date_from_ymd(year, 1, 1)) / 7 + 1
+}
+
+/// date_trunc(unit, timestamp)
+pub fn date_trunc(unit: &str, ts: i64) -> Result<i64> {
+    let (date, time) = split_timestamp(ts);
+    let (year, month, _) = ymd_from_date(date);
+    let truncated_time = |step: i64| Ok(ts - time % step);
+    let start_of = |date: i64| check_timestamp(date.checked_mul(USECS_PER_DAY));
+    let first_of = |year: i64, month: u32| start_of(date_from_ymd(year, month, 1));
+    match unit.to_ascii_lowercase().as_str() {
+        "microseconds" => Ok(ts),
+        "milliseconds" => truncated_time(1_000),
+        "second" => truncated_time(USECS_PER_SEC),
+        "minute" => truncated_time(USECS_PER_MINUTE),
+        "hour" => truncated_time(USECS_PER_HOUR),
+        "day" => truncated_time(USECS_PER_DAY),
+        "week" => start_of(date - (date + 5).rem_euclid(7)),
+        "month" => first_of(year, month),
+        "quarter" => first_of(year, (month - 1) / 3 * 3 + 1),
+        "year" => first_of(year, 1),
+        "decade" => first_of(year.div_euclid(10) * 10, 1),
+        "century" => first_of((year - 1).div_euclid(100) * 100 + 1, 1),
+        "millennium" => first_of((year - 1).div_euclid(1000) * 1000 + 1, 1),
+        other => bail!("unit \"{other}\" not recognized for type timestamp"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_round_trip() {
+        assert_eq!(date_from_ymd(2000, 1, 1), 0);
+        assert_eq!(date_from_ymd(1999, 12, 31), -1);
+        for date in [-800_000, -1, 0, 59, 60, 366, 9_000, 3_000_000] {
+            let (y, m, d) = ymd_from_date(date);
+            assert_eq!(date_from_ymd(y, m, d), date);
+        }
+        assert_eq!(format_date(parse_date("2024-02-29").unwrap()), "2024-02-29");
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2023-13-01").is_err());
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let error = |result: Result<i64>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(parse_timestamp("300000-01-01")),
+            "timestamp out of range: \"300000-01-01\""
+        );
+        assert!(parse_timestamp("294276-12-31 23:59:59.999999").is_ok());
+        assert_eq!(
+            error(parse_time("99999999999999:00")),
+            "date/time field value out of range: \"99999999999999:00\""
+        );
+        assert_eq!(
+            Interval::parse("99999999999:00").unwrap_err().to_string(),
+            "date/time field value out of range: \"99999999999:00\""
+        );
+        assert_eq!(
+            parse_date("99999999999999-01-01").unwrap_err().to_string(),
+            "date/time field value out of range: \"99999999999999-01-01\""
+        );
+        assert!(parse_date("5874897-12-31").is_ok());
+
+        assert_eq!(check_date(END_DATE - 1).unwrap(), 2_145_031_948);
+        assert_eq!(check_date(END_DATE).unwrap_err().to_string(), "date out of range");
+        assert_eq!(check_date(MIN_DATE - 1).unwrap_err().to_string(), "date out of range");
+        assert_eq!(error(date_to_timestamp(i32::MAX)), "timestamp out of range");
+        assert_eq!(error(date_to_timestamp(106_751_983)), "timestamp out of range");
+        assert!(date_to_timestamp(106_751_982).is_ok());
+        assert_eq!(error(date_plus_time(106_751_982, USECS_PER_DAY)), "timestamp out of range");
+
+        // The epoch of a DATE past the TIMESTAMP range
+        let epoch = extract("epoch", &DateTimeSource::Date(2_000_000_000)).unwrap();
+        assert_eq!(epoch.to_string(), "172800946684800");
+    }
+
+    #[test]
+    fn test_timestamp_parse_and_format() {
+        let ts = parse_timestamp("2024-03-15 13:45:30.25").unwrap();
+        assert_eq!(format_timestamp(ts), "2024-03-15 13:45:30.25");
+        assert_eq!(
+            format_timestamp(parse_timestamp("2024-03-15").unwrap()),
+            "2024-03-15 00:00:00"
+        );
+        let tz = parse_timestamptz("2024-03-15T13:45:30+02:00").unwrap();
+        assert_eq!(format_timestamptz(tz), "2024-03-15 11:45:30+00");
+        assert!(parse_timestamp("2024-03-15 25:00:00").is_err());
+        assert_eq!(
+            format_time(parse_time("23:59:59.000001").unwrap()),
+            "23:59:59.000001"
+        );
+    }
+
+    #[test]
+    fn test_interval_parse_format_and_compare() {
+        let iv = Interval::parse("1 year 2 months 3 days 04:05:06").unwrap();
+        assert_eq!(iv.to_string(), "1 year 2 mons 3 days 04:05:06");
+        assert_eq!(
+            Interval::parse("1.5 hours").unwrap().to_string(),
+            "01:30:00"
+        );
+        assert_eq!(
+            Interval::parse("2 weeks ago").unwrap().to_string(),
+            "-14 days"
+        );
+        assert_eq!(
+            Interval::parse("0 seconds").unwrap().to_string(),
+            "00:00:00"
+        );
+        assert_eq!(
+            Interval::parse("-1 day 2 hours").unwrap().to_string(),
+            "-1 days +02:00:00"
+        );
+        assert_eq!(
+            Interval::parse("1 day").unwrap(),
+            Interval::parse("24 hours").unwrap()
+        );
+        assert!(Interval::parse("1 fortnight").is_err());
+    }
+
+    #[test]
+    fn test_interval_arithmetic() {
+        let jan31 = parse_timestamp("2024-01-31 10:00").unwrap();
+        let month = Interval::parse("1 month").unwrap();
+        let ts = timestamp_plus_interval(jan31, &month).unwrap();
+        assert_eq!(format_timestamp(ts), "2024-02-29 10:00:00");
+        let diff = timestamp_difference(ts, jan31).unwrap();
+        assert_eq!(diff.to_string(), "29 days");
+        assert_eq!(month.mul(1.5).unwrap().to_string(), "1 mon 15 days");
+    }
+
+    #[test]
+    fn test_extract_and_trunc() {
+        let ts = parse_timestamp("2024-03-15 13:45:30.5").unwrap();
+        let field = |f: &str| {
+            extract(f, &DateTimeSource::Timestamp(ts))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(field("year"), "2024");
+        assert_eq!(field("quarter"), "1");
+        assert_eq!(field("dow"), "5");
+        assert_eq!(field("week"), "11");
+        assert_eq!(field("second"), "30.500000");
+        assert_eq!(field("epoch"), "1710510330.500000");
+        assert!(extract("hour", &DateTimeSource::Date(0)).is_err());
+        assert_eq!(
+            format_timestamp(date_trunc("month", ts).unwrap()),
+            "2024-03-01 00:00:00"
+        );
+        assert_eq!(
+            format_timestamp(date_trunc("week", ts).unwrap()),
+            "2024-03-11 00:00:00"
+        );
+        assert_eq!(
+            format_timestamp(date_trunc("hour", ts).unwrap()),
+            "2024-03-15 13:00:00"
+        );
+    }
+}
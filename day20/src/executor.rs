@@ -1,4 +1,5 @@
 mod aggregate;
+mod bulk_insert;
 mod create_index;
 mod create_table;
 mod delete;
@@ -8,27 +9,42 @@ mod insert;
 mod nested_loop_join;
 mod projection;
 mod seq_scan;
+mod spill;
+#[cfg(test)]
+pub mod testing;
+mod unnest;
 mod update;
+mod vacuum;
 
+use std::cmp::Ordering;
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 
 use crate::analyzer::{
-    AnalyzedColumnRef, AnalyzedExpr, AnalyzedLiteral, AnalyzedSelectItem, AnalyzedSelectStatement,
+    AnalyzedAggregate, AnalyzedAggregateArg, AnalyzedColumnRef, AnalyzedExpr, AnalyzedLiteral, AnalyzedSelectItem, AnalyzedSelectStatement,
     AnalyzedStatement, LiteralValue, RangeTableEntry, TableSource,
 };
-use crate::ast::{BinaryOperator, UnaryOperator};
+use crate::ast::{BinaryOperator, JoinType, UnaryOperator};
 use crate::btree::IndexKey;
 use crate::buffer_pool::BufferPoolManager;
-use crate::catalog::{Catalog, IndexDef};
+use crate::catalog::{Catalog, IndexColumn, IndexDef};
+use crate::collation::Collation;
+use crate::functions::ScalarFunction;
 use crate::lock_manager::LockManager;
+use crate::toast;
 use crate::transaction::{Transaction, UndoLogEntry};
 use crate::transaction_manager::TransactionManager;
-use crate::tuple::Value;
+use crate::datetime::{self, Interval};
+use crate::json::{self, Json};
+use crate::tuple::{
+    coerce_value, compare_datetimes, compare_numbers, integer_value, DataType, Value,
+};
+use crate::visibility::Snapshot;
 use crate::wal::{CLRRedo, Lsn, WalManager, WalRecordType};
 
 pub use aggregate::AggregateExecutor;
+pub use bulk_insert::BulkInsert;
 pub use create_index::CreateIndexExecutor;
 pub use create_table::CreateTableExecutor;
 pub use delete::DeleteExecutor;
@@ -38,7 +54,10 @@ pub use insert::InsertExecutor;
 pub use nested_loop_join::NestedLoopJoinExecutor;
 pub use projection::{ProjectionExecutor, ProjectionWithOffsetsExecutor};
 pub use seq_scan::SeqScanExecutor;
+pub use spill::remove_temp_files;
+pub use unnest::UnnestExecutor;
 pub use update::UpdateExecutor;
+pub use vacuum::vacuum_table;
 
 // Row ID: page_id + slot_id
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -93,24 +112,49 @@ impl Tuple {
 pub trait Executor {
     fn open(&mut self) -> Result<()>;
     fn next(&mut self) -> Result<Option<Tuple>>;
+
+    /// Open again as the inner side of a join, for the next row of its
+    /// outer side; only unnest() looks at that row
+    fn rescan(&mut self, _outer: &Tuple) -> Result<()> {
+        self.open()
+    }
 }
 
-// Expression evaluation for single-table queries
-pub fn evaluate_expr(expr: &AnalyzedExpr, tuple: &Tuple) -> Result<Value> {
+// Expression evaluation for single-table queries. Out-of-line values are
+// read through `bpm`.
+pub fn evaluate_expr(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    expr: &AnalyzedExpr,
+    tuple: &Tuple,
+) -> Result<Value> {
     match expr {
         AnalyzedExpr::Literal(lit) => Ok(literal_to_value(lit)),
-        AnalyzedExpr::ColumnRef(col_ref) => Ok(tuple.values[col_ref.column_index].clone()),
+        // Toasted values are fetched here, only once an expression uses them
+        AnalyzedExpr::ColumnRef(col_ref) => {
+            toast::detoast(bpm, &tuple.values[col_ref.column_index])
+        }
         AnalyzedExpr::BinaryOp {
-            left, op, right, ..
+            left,
+            op,
+            right,
+            collation,
+            ..
         } => {
-            let left_val = evaluate_expr(left, tuple)?;
-            let right_val = evaluate_expr(right, tuple)?;
+            let left_val = collation.sort_key(evaluate_expr(bpm, left, tuple)?);
+            let right_val = collation.sort_key(evaluate_expr(bpm, right, tuple)?);
             evaluate_binary_op(op, &left_val, &right_val)
         }
         AnalyzedExpr::UnaryOp { op, expr, .. } => {
-            let val = evaluate_expr(expr, tuple)?;
+            let val = evaluate_expr(bpm, expr, tuple)?;
             evaluate_unary_op(op, &val)
         }
+        AnalyzedExpr::Function { func, args, .. } => {
+            let values = args
+                .iter()
+                .map(|arg| evaluate_expr(bpm, arg, tuple))
+                .collect::<Result<Vec<_>>>()?;
+            func.evaluate(&values)
+        }
         AnalyzedExpr::Aggregate(_) => {
             anyhow::bail!("aggregate functions should be evaluated by AggregateExecutor")
         }
@@ -119,6 +163,7 @@ pub fn evaluate_expr(expr: &AnalyzedExpr, tuple: &Tuple) -> Result<Value> {
 
 // Expression evaluation with RTE offsets (for JOIN queries)
 pub fn evaluate_expr_with_offsets(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
     expr: &AnalyzedExpr,
     tuple: &Tuple,
     rte_offsets: &[usize],
@@ -128,19 +173,32 @@ pub fn evaluate_expr_with_offsets(
         AnalyzedExpr::ColumnRef(col_ref) => {
             let offset = rte_offsets.get(col_ref.rte_index).copied().unwrap_or(0);
             let actual_index = offset + col_ref.column_index;
-            Ok(tuple.values[actual_index].clone())
+            toast::detoast(bpm, &tuple.values[actual_index])
         }
         AnalyzedExpr::BinaryOp {
-            left, op, right, ..
+            left,
+            op,
+            right,
+            collation,
+            ..
         } => {
-            let left_val = evaluate_expr_with_offsets(left, tuple, rte_offsets)?;
-            let right_val = evaluate_expr_with_offsets(right, tuple, rte_offsets)?;
+            let left_val =
+                collation.sort_key(evaluate_expr_with_offsets(bpm, left, tuple, rte_offsets)?);
+            let right_val =
+                collation.sort_key(evaluate_expr_with_offsets(bpm, right, tuple, rte_offsets)?);
             evaluate_binary_op(op, &left_val, &right_val)
         }
         AnalyzedExpr::UnaryOp { op, expr, .. } => {
-            let val = evaluate_expr_with_offsets(expr, tuple, rte_offsets)?;
+            let val = evaluate_expr_with_offsets(bpm, expr, tuple, rte_offsets)?;
             evaluate_unary_op(op, &val)
         }
+        AnalyzedExpr::Function { func, args, .. } => {
+            let values = args
+                .iter()
+                .map(|arg| evaluate_expr_with_offsets(bpm, arg, tuple, rte_offsets))
+                .collect::<Result<Vec<_>>>()?;
+            func.evaluate(&values)
+        }
         AnalyzedExpr::Aggregate(_) => {
             anyhow::bail!("aggregate functions should be evaluated by AggregateExecutor")
         }
@@ -148,11 +206,12 @@ pub fn evaluate_expr_with_offsets(
 }
 
 pub fn evaluate_predicate_with_offsets(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
     expr: &AnalyzedExpr,
     tuple: &Tuple,
     rte_offsets: &[usize],
 ) -> Result<bool> {
-    let result = evaluate_expr_with_offsets(expr, tuple, rte_offsets)?;
+    let result = evaluate_expr_with_offsets(bpm, expr, tuple, rte_offsets)?;
     match result {
         Value::Bool(b) => Ok(b),
         Value::Null => Ok(false),
@@ -162,38 +221,76 @@ pub fn evaluate_predicate_with_offsets(
 
 pub fn literal_to_value(lit: &AnalyzedLiteral) -> Value {
     match &lit.value {
-        LiteralValue::Integer(n) => Value::Int(*n as i32),
+        LiteralValue::Integer(n) => match i32::try_from(*n) {
+            Ok(n) => Value::Int(n),
+            Err(_) => Value::BigInt(*n),
+        },
+        LiteralValue::Numeric(n) => Value::Numeric(n.clone()),
         LiteralValue::String(s) => Value::Varchar(s.clone()),
         LiteralValue::Boolean(b) => Value::Bool(*b),
         LiteralValue::Null => Value::Null,
+        LiteralValue::Typed(v) => v.clone(),
+        // Statements are only executed once their parameters are bound
+        LiteralValue::Parameter(_) => Value::Null,
+    }
+}
+
+/// Structural equality of non-aggregate expressions, used to match GROUP BY
+/// expressions and index expressions
+pub fn exprs_equal(a: &AnalyzedExpr, b: &AnalyzedExpr) -> bool {
+    match (a, b) {
+        (AnalyzedExpr::ColumnRef(c1), AnalyzedExpr::ColumnRef(c2)) => {
+            c1.column_index == c2.column_index
+        }
+        (AnalyzedExpr::Literal(l1), AnalyzedExpr::Literal(l2)) => {
+            literal_to_value(l1) == literal_to_value(l2)
+        }
+        (
+            AnalyzedExpr::BinaryOp { left: l1, op: o1, right: r1, .. },
+            AnalyzedExpr::BinaryOp { left: l2, op: o2, right: r2, .. },
+        ) => o1 == o2 && exprs_equal(l1, l2) && exprs_equal(r1, r2),
+        (
+            AnalyzedExpr::UnaryOp { op: o1, expr: e1, .. },
+            AnalyzedExpr::UnaryOp { op: o2, expr: e2, .. },
+        ) => o1 == o2 && exprs_equal(e1, e2),
+        (
+            AnalyzedExpr::Function { func: f1, args: a1, .. },
+            AnalyzedExpr::Function { func: f2, args: a2, .. },
+        ) => f1 == f2 && a1.len() == a2.len() && a1.iter().zip(a2).all(|(x, y)| exprs_equal(x, y)),
+        (AnalyzedExpr::Aggregate(a1), AnalyzedExpr::Aggregate(a2)) => aggregates_equal(a1, a2),
+        _ => false,
     }
 }
 
+/// Whether two aggregate calls compute the same value, so that one
+/// accumulator can serve both
+pub fn aggregates_equal(a: &AnalyzedAggregate, b: &AnalyzedAggregate) -> bool {
+    let args_equal = match (&a.arg, &b.arg) {
+        (AnalyzedAggregateArg::Asterisk, AnalyzedAggregateArg::Asterisk) => true,
+        (AnalyzedAggregateArg::Expr(e1), AnalyzedAggregateArg::Expr(e2)) => exprs_equal(e1, e2),
+        _ => false,
+    };
+    let optional_equal = |x: &Option<Box<AnalyzedExpr>>, y: &Option<Box<AnalyzedExpr>>| match (x, y) {
+        (None, None) => true,
+        (Some(x), Some(y)) => exprs_equal(x, y),
+        _ => false,
+    };
+    a.func == b.func
+        && args_equal
+        && optional_equal(&a.extra_arg, &b.extra_arg)
+        && optional_equal(&a.filter, &b.filter)
+        && a.order_by.len() == b.order_by.len()
+        && a.order_by.iter().zip(&b.order_by).all(|(x, y)| {
+            x.descending == y.descending
+                && x.nulls_first == y.nulls_first
+                && exprs_equal(&x.expr, &y.expr)
+        })
+}
+
 pub fn evaluate_binary_op(op: &BinaryOperator, left: &Value, right: &Value) -> Result<Value> {
     match (left, right) {
-        (Value::Int(l), Value::Int(r)) => {
-            let result = match op {
-                BinaryOperator::Eq => Value::Bool(*l == *r),
-                BinaryOperator::Ne => Value::Bool(*l != *r),
-                BinaryOperator::Lt => Value::Bool(*l < *r),
-                BinaryOperator::Le => Value::Bool(*l <= *r),
-                BinaryOperator::Gt => Value::Bool(*l > *r),
-                BinaryOperator::Ge => Value::Bool(*l >= *r),
-                BinaryOperator::Add => Value::Int(*l + *r),
-                BinaryOperator::Sub => Value::Int(*l - *r),
-                BinaryOperator::Mul => Value::Int(*l * *r),
-                BinaryOperator::Div => {
-                    if *r == 0 {
-                        Value::Null
-                    } else {
-                        Value::Int(*l / *r)
-                    }
-                }
-                BinaryOperator::And | BinaryOperator::Or => {
-                    anyhow::bail!("AND/OR not supported for integers")
-                }
-            };
-            Ok(result)
+        (l, r) if l.as_f64().is_some() && r.as_f64().is_some() => {
+            evaluate_numeric_op(op, left, right)
         }
         (Value::Varchar(l), Value::Varchar(r)) => {
             let result = match op {
@@ -217,22 +314,284 @@ pub fn evaluate_binary_op(op: &BinaryOperator, left: &Value, right: &Value) -> R
             };
             Ok(result)
         }
+        (Value::Bytea(l), Value::Bytea(r)) => {
+            let result = match op {
+                BinaryOperator::Eq => Value::Bool(l == r),
+                BinaryOperator::Ne => Value::Bool(l != r),
+                BinaryOperator::Lt => Value::Bool(l < r),
+                BinaryOperator::Le => Value::Bool(l <= r),
+                BinaryOperator::Gt => Value::Bool(l > r),
+                BinaryOperator::Ge => Value::Bool(l >= r),
+                _ => anyhow::bail!("unsupported binary op for bytea"),
+            };
+            Ok(result)
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            let ordering = compare_arrays(l, r)?;
+            let result = match op {
+                BinaryOperator::Eq => ordering == Ordering::Equal,
+                BinaryOperator::Ne => ordering != Ordering::Equal,
+                BinaryOperator::Lt => ordering == Ordering::Less,
+                BinaryOperator::Le => ordering != Ordering::Greater,
+                BinaryOperator::Gt => ordering == Ordering::Greater,
+                BinaryOperator::Ge => ordering != Ordering::Less,
+                _ => anyhow::bail!("unsupported binary op for arrays"),
+            };
+            Ok(Value::Bool(result))
+        }
         (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+        (l, r) if l.is_temporal() || r.is_temporal() => evaluate_datetime_op(op, l, r),
+        (Value::Jsonb(l), r) => evaluate_json_op(op, l, r),
         _ => anyhow::bail!("type mismatch in binary operation"),
     }
 }
 
+// Arrays compare element by element, then by length. NULL elements are
+// equal to each other and sort after all other values, as in PostgreSQL.
+fn compare_arrays(left: &[Value], right: &[Value]) -> Result<Ordering> {
+    for (l, r) in left.iter().zip(right) {
+        let ordering = match (l, r) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            _ if evaluate_binary_op(&BinaryOperator::Lt, l, r)? == Value::Bool(true) => {
+                Ordering::Less
+            }
+            _ if evaluate_binary_op(&BinaryOperator::Eq, l, r)? == Value::Bool(true) => {
+                Ordering::Equal
+            }
+            _ => Ordering::Greater,
+        };
+        if ordering.is_ne() {
+            return Ok(ordering);
+        }
+    }
+    Ok(left.len().cmp(&right.len()))
+}
+
+// JSON operators. -> and #> return jsonb, ->> and #>> their text form; a
+// missing key, index or path gives NULL.
+fn evaluate_json_op(op: &BinaryOperator, left: &Json, right: &Value) -> Result<Value> {
+    let found = match (op, right) {
+        (BinaryOperator::Arrow | BinaryOperator::LongArrow, Value::Varchar(key)) => {
+            left.get_field(key)
+        }
+        (BinaryOperator::Arrow | BinaryOperator::LongArrow, index) if index.as_i64().is_some() => {
+            left.get_element(index.as_i64().unwrap())
+        }
+        (BinaryOperator::HashArrow | BinaryOperator::HashLongArrow, Value::Varchar(path)) => {
+            left.get_path(&json::parse_path(path)?)
+        }
+        (BinaryOperator::AtArrow, Value::Jsonb(r)) => return Ok(Value::Bool(left.contains(r))),
+        (_, Value::Jsonb(r)) => {
+            let ordering = left.cmp(r);
+            let result = match op {
+                BinaryOperator::Eq => ordering == Ordering::Equal,
+                BinaryOperator::Ne => ordering != Ordering::Equal,
+                BinaryOperator::Lt => ordering == Ordering::Less,
+                BinaryOperator::Le => ordering != Ordering::Greater,
+                BinaryOperator::Gt => ordering == Ordering::Greater,
+                BinaryOperator::Ge => ordering != Ordering::Less,
+                _ => anyhow::bail!("unsupported binary op for jsonb"),
+            };
+            return Ok(Value::Bool(result));
+        }
+        _ => anyhow::bail!("type mismatch in binary operation"),
+    };
+    Ok(match (op, found) {
+        (_, None) => Value::Null,
+        (BinaryOperator::LongArrow | BinaryOperator::HashLongArrow, Some(json)) => {
+            json.as_text().map_or(Value::Null, Value::Varchar)
+        }
+        (_, Some(json)) => Value::Jsonb(json.clone()),
+    })
+}
+
+// Date/time comparison and arithmetic. Result types match the analyzer's
+// datetime_arithmetic_type: date ± integer is a date, timestamp ± interval
+// keeps the timestamp's type, and differences of timestamps are intervals.
+fn evaluate_datetime_op(op: &BinaryOperator, left: &Value, right: &Value) -> Result<Value> {
+    if let Some(ordering) = compare_datetimes(left, right) {
+        match op {
+            BinaryOperator::Eq => return Ok(Value::Bool(ordering == Ordering::Equal)),
+            BinaryOperator::Ne => return Ok(Value::Bool(ordering != Ordering::Equal)),
+            BinaryOperator::Lt => return Ok(Value::Bool(ordering == Ordering::Less)),
+            BinaryOperator::Le => return Ok(Value::Bool(ordering != Ordering::Greater)),
+            BinaryOperator::Gt => return Ok(Value::Bool(ordering == Ordering::Greater)),
+            BinaryOperator::Ge => return Ok(Value::Bool(ordering != Ordering::Less)),
+            _ => {}
+        }
+    }
+
+    let plus_interval = |point: &Value, interval: &Interval| -> Result<Value> {
+        let ts = datetime::timestamp_plus_interval(point.as_timestamp().unwrap()?, interval)?;
+        Ok(match point {
+            Value::TimestampTz(_) => Value::TimestampTz(ts),
+            _ => Value::Timestamp(ts),
+        })
+    };
+    let time_plus = |time: i64, micros: i64| Value::Time((time + micros).rem_euclid(datetime::USECS_PER_DAY));
+
+    use BinaryOperator::{Add, Div, Mul, Sub};
+    match (op, left, right) {
+        (Add, Value::Date(d), n) | (Add, n, Value::Date(d)) if n.as_i64().is_some() => {
+            date_plus_days(*d, n.as_i64().unwrap())
+        }
+        (Sub, Value::Date(d), n) if n.as_i64().is_some() => {
+            date_plus_days(*d, n.as_i64().unwrap().checked_neg().unwrap_or(i64::MAX))
+        }
+        (Sub, Value::Date(a), Value::Date(b)) => integer_value(*a as i64 - *b as i64, &DataType::Int),
+        (Add, Value::Date(d), Value::Time(t)) | (Add, Value::Time(t), Value::Date(d)) => {
+            Ok(Value::Timestamp(datetime::date_plus_time(*d, *t)?))
+        }
+        (Add, point, Value::Interval(i)) | (Add, Value::Interval(i), point)
+            if point.as_timestamp().is_some() =>
+        {
+            plus_interval(point, i)
+        }
+        (Sub, point, Value::Interval(i)) if point.as_timestamp().is_some() => {
+            plus_interval(point, &i.neg()?)
+        }
+        (Sub, a, b) if a.as_timestamp().is_some() && b.as_timestamp().is_some() => Ok(
+            Value::Interval(datetime::timestamp_difference(a.as_timestamp().unwrap()?, b.as_timestamp().unwrap()?)?),
+        ),
+        (Add, Value::Time(t), Value::Interval(i)) | (Add, Value::Interval(i), Value::Time(t)) => {
+            Ok(time_plus(*t, i.micros))
+        }
+        (Sub, Value::Time(t), Value::Interval(i)) => Ok(time_plus(*t, -i.micros)),
+        (Sub, Value::Time(a), Value::Time(b)) => Ok(Value::Interval(Interval::new(0, 0, a - b))),
+        (Add, Value::Interval(a), Value::Interval(b)) => Ok(Value::Interval(a.add(b)?)),
+        (Sub, Value::Interval(a), Value::Interval(b)) => Ok(Value::Interval(a.sub(b)?)),
+        (Mul, Value::Interval(i), n) | (Mul, n, Value::Interval(i)) if n.as_f64().is_some() => {
+            Ok(Value::Interval(i.mul(n.as_f64().unwrap())?))
+        }
+        (Div, Value::Interval(i), n) if n.as_f64().is_some() => {
+            let divisor = n.as_f64().unwrap();
+            if divisor == 0.0 {
+                return Ok(Value::Null);
+            }
+            Ok(Value::Interval(i.mul(1.0 / divisor)?))
+        }
+        _ => anyhow::bail!("type mismatch in binary operation"),
+    }
+}
+
+fn date_plus_days(date: i32, days: i64) -> Result<Value> {
+    let days = (date as i64).checked_add(days).unwrap_or(i64::MAX);
+    datetime::check_date(days).map(Value::Date)
+}
+
+// Numeric operands are promoted to the wider of the two types
+// (SMALLINT < INT < BIGINT < NUMERIC < REAL < DOUBLE PRECISION). Integer
+// arithmetic is done in i64 and range-checked against the result type.
+fn evaluate_numeric_op(op: &BinaryOperator, left: &Value, right: &Value) -> Result<Value> {
+    let ordering = compare_numbers(left, right).unwrap();
+    match op {
+        BinaryOperator::Eq => return Ok(Value::Bool(ordering == Ordering::Equal)),
+        BinaryOperator::Ne => return Ok(Value::Bool(ordering != Ordering::Equal)),
+        BinaryOperator::Lt => return Ok(Value::Bool(ordering == Ordering::Less)),
+        BinaryOperator::Le => return Ok(Value::Bool(ordering != Ordering::Greater)),
+        BinaryOperator::Gt => return Ok(Value::Bool(ordering == Ordering::Greater)),
+        BinaryOperator::Ge => return Ok(Value::Bool(ordering != Ordering::Less)),
+        BinaryOperator::And | BinaryOperator::Or => {
+            anyhow::bail!("AND/OR not supported for numbers")
+        }
+        BinaryOperator::Arrow
+        | BinaryOperator::LongArrow
+        | BinaryOperator::HashArrow
+        | BinaryOperator::HashLongArrow
+        | BinaryOperator::AtArrow => anyhow::bail!("operator {op} not supported for numbers"),
+        BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::Mul | BinaryOperator::Div => {}
+    }
+
+    let result_type = left.data_type().unwrap().wider_numeric(&right.data_type().unwrap());
+    match result_type {
+        DataType::Numeric(_) => {
+            let (l, r) = (left.to_numeric().unwrap(), right.to_numeric().unwrap());
+            let result = match op {
+                BinaryOperator::Add => l.add(&r),
+                BinaryOperator::Sub => l.sub(&r),
+                BinaryOperator::Mul => l.mul(&r),
+                _ => match l.div(&r) {
+                    Some(q) => q,
+                    None => return Ok(Value::Null),
+                },
+            };
+            Ok(Value::Numeric(result))
+        }
+        DataType::Real | DataType::Double => {
+            // A NUMERIC operand too large for a float fails here
+            let as_double = |v: &Value| coerce_value(v.clone(), &DataType::Double);
+            let (l, r) = (as_double(left)?.as_f64().unwrap(), as_double(right)?.as_f64().unwrap());
+            let result = match op {
+                BinaryOperator::Add => l + r,
+                BinaryOperator::Sub => l - r,
+                BinaryOperator::Mul => l * r,
+                _ if r == 0.0 => return Ok(Value::Null),
+                _ => l / r,
+            };
+            if result.is_infinite() && l.is_finite() && r.is_finite() {
+                anyhow::bail!("value out of range: overflow");
+            }
+            // REAL results are computed in f64 and narrowed (with its own overflow check)
+            coerce_value(Value::Double(result), &result_type)
+        }
+        _ => {
+            let (l, r) = (left.as_i64().unwrap(), right.as_i64().unwrap());
+            let result = match op {
+                BinaryOperator::Add => l.checked_add(r),
+                BinaryOperator::Sub => l.checked_sub(r),
+                BinaryOperator::Mul => l.checked_mul(r),
+                _ if r == 0 => return Ok(Value::Null),
+                _ => l.checked_div(r),
+            };
+            match result {
+                Some(n) => integer_value(n, &result_type),
+                None => anyhow::bail!("{} out of range", result_type.name()),
+            }
+        }
+    }
+}
+
 pub fn evaluate_unary_op(op: &UnaryOperator, val: &Value) -> Result<Value> {
     match (op, val) {
         (UnaryOperator::Not, Value::Bool(b)) => Ok(Value::Bool(!*b)),
-        (UnaryOperator::Neg, Value::Int(n)) => Ok(Value::Int(-*n)),
+        (UnaryOperator::Neg, v) if v.as_i64().is_some() => {
+            let data_type = v.data_type().unwrap();
+            match v.as_i64().unwrap().checked_neg() {
+                Some(n) => integer_value(n, &data_type),
+                None => anyhow::bail!("{} out of range", data_type.name()),
+            }
+        }
+        (UnaryOperator::Neg, Value::Real(f)) => Ok(Value::Real(-*f)),
+        (UnaryOperator::Neg, Value::Double(f)) => Ok(Value::Double(-*f)),
+        (UnaryOperator::Neg, Value::Numeric(n)) => Ok(Value::Numeric(n.neg())),
+        (UnaryOperator::Neg, Value::Interval(i)) => Ok(Value::Interval(i.neg()?)),
         (_, Value::Null) => Ok(Value::Null),
         _ => anyhow::bail!("type mismatch in unary operation"),
     }
 }
 
-fn evaluate_predicate(expr: &AnalyzedExpr, tuple: &Tuple) -> Result<bool> {
-    let result = evaluate_expr(expr, tuple)?;
+// Evaluate the RETURNING list of INSERT/UPDATE/DELETE against a modified row
+fn project_returning(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    returning: &[AnalyzedSelectItem],
+    row: &Tuple,
+    rte_offsets: &[usize],
+) -> Result<Tuple> {
+    let values = returning
+        .iter()
+        .map(|item| evaluate_expr_with_offsets(bpm, &item.expr, row, rte_offsets))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Tuple::new(values))
+}
+
+fn evaluate_predicate(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    expr: &AnalyzedExpr,
+    tuple: &Tuple,
+) -> Result<bool> {
+    let result = evaluate_expr(bpm, expr, tuple)?;
     match result {
         Value::Bool(b) => Ok(b),
         Value::Null => Ok(false),
@@ -242,6 +601,7 @@ fn evaluate_predicate(expr: &AnalyzedExpr, tuple: &Tuple) -> Result<bool> {
 
 // Execution Engine - orchestrates query execution
 pub struct ExecutionEngine<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     executor: Box<dyn Executor + 'a>,
 }
 
@@ -255,15 +615,29 @@ impl<'a> ExecutionEngine<'a> {
         wal_manager: Option<Arc<WalManager>>,
         txn_manager: Option<&'a TransactionManager>,
     ) -> Result<Self> {
-        let executor =
-            Self::build_executor(bpm, catalog, stmt, txn, lock_manager, wal_manager, txn_manager)?;
-        Ok(ExecutionEngine { executor })
+        let executor = Self::build_executor(
+            Arc::clone(&bpm),
+            catalog,
+            stmt,
+            txn,
+            lock_manager,
+            wal_manager,
+            txn_manager,
+        )?;
+        Ok(ExecutionEngine { bpm, executor })
     }
 
     pub fn execute(&mut self) -> Result<Vec<Tuple>> {
         self.executor.open()?;
         let mut results = Vec::new();
-        while let Some(tuple) = self.executor.next()? {
+        while let Some(mut tuple) = self.executor.next()? {
+            // Columns passed through without being evaluated may still be
+            // toasted; the results leave the engine with their actual values
+            for value in &mut tuple.values {
+                if matches!(value, Value::Toast(_)) {
+                    *value = toast::detoast(&self.bpm, value)?;
+                }
+            }
             results.push(tuple);
         }
         Ok(results)
@@ -351,6 +725,7 @@ impl<'a> ExecutionEngine<'a> {
                 txn,
                 lock_manager,
                 wal_manager,
+                txn_manager,
             )?)),
             AnalyzedStatement::CreateTable(s) => Ok(Box::new(CreateTableExecutor::new(
                 bpm,
@@ -412,74 +787,77 @@ impl<'a> ExecutionEngine<'a> {
             offset += rte.output_columns.len();
         }
 
+        let mut sorted_input = false;
         let scan: Box<dyn Executor> = if stmt.join_conditions.is_empty() {
             let rte = &stmt.range_table[stmt.from_rte_index];
-            let table_id = match &rte.source {
-                TableSource::BaseTable { table_id, .. } => *table_id,
-            };
-
-            // Try to use IndexScan if applicable
-            if let Some((index_def, key)) =
-                Self::try_find_index_for_where(catalog, table_id, &stmt.where_clause)
-            {
-                println!("[Optimizer] Using IndexScan with index '{}'", index_def.index_name);
-                // For equality search, use start_key only (end_key = None)
-                // The Filter executor will filter out non-matching rows
-                Box::new(IndexScanExecutor::new(
-                    bpm,
-                    catalog,
-                    table_id,
-                    index_def,
-                    Some(key),
-                    None, // Let range_scan return all entries >= start_key
-                    snapshot,
-                    txn_manager,
-                ))
-            } else {
-                Box::new(SeqScanExecutor::new(
-                    bpm,
-                    catalog,
-                    table_id,
-                    txn,
-                    lock_manager,
-                    snapshot,
-                    txn_manager,
-                ))
+            match &rte.source {
+                TableSource::Unnest { array } => {
+                    Box::new(UnnestExecutor::new(Arc::clone(&bpm), array.clone(), Vec::new()))
+                }
+                TableSource::BaseTable { table_id, .. } => {
+                    let table_id = *table_id;
+                    // Try to use IndexScan if applicable
+                    if let Some((index_def, key)) =
+                        Self::try_find_index_for_where(catalog, table_id, &stmt.where_clause)
+                    {
+                        println!("[Optimizer] Using IndexScan with index '{}'", index_def.index_name);
+                        // Rows come back in index order, so grouping by the
+                        // index key can emit each group as soon as it ends
+                        sorted_input =
+                            Self::index_orders_group_by(catalog, table_id, &index_def, stmt);
+                        if sorted_input {
+                            println!("[Optimizer] Using sorted aggregation on index order");
+                        }
+                        // For equality search, use start_key only (end_key = None)
+                        // The Filter executor will filter out non-matching rows
+                        Box::new(IndexScanExecutor::new(
+                            Arc::clone(&bpm),
+                            catalog,
+                            table_id,
+                            index_def,
+                            Some(key),
+                            None, // Let range_scan return all entries >= start_key
+                            snapshot,
+                            txn_manager,
+                        ))
+                    } else {
+                        Box::new(SeqScanExecutor::new(
+                            Arc::clone(&bpm),
+                            catalog,
+                            table_id,
+                            txn,
+                            lock_manager,
+                            snapshot,
+                            txn_manager,
+                        ))
+                    }
+                }
             }
         } else {
-            let first_rte = &stmt.range_table[0];
-            let first_table_id = match &first_rte.source {
-                TableSource::BaseTable { table_id, .. } => *table_id,
-            };
-
-            let mut current: Box<dyn Executor> = Box::new(SeqScanExecutor::new(
+            let mut current = Self::build_rte_scan(
                 Arc::clone(&bpm),
                 catalog,
-                first_table_id,
-                None,
-                None,
+                &stmt.range_table[0],
+                &rte_offsets,
                 snapshot.clone(),
                 txn_manager,
-            ));
+            );
 
             for join_cond in &stmt.join_conditions {
                 let right_rte = &stmt.range_table[join_cond.right_rte_index];
-                let right_table_id = match &right_rte.source {
-                    TableSource::BaseTable { table_id, .. } => *table_id,
-                };
                 let right_col_count = right_rte.output_columns.len();
 
-                let right_scan: Box<dyn Executor> = Box::new(SeqScanExecutor::new(
+                let right_scan = Self::build_rte_scan(
                     Arc::clone(&bpm),
                     catalog,
-                    right_table_id,
-                    None,
-                    None,
+                    right_rte,
+                    &rte_offsets,
                     snapshot.clone(),
                     txn_manager,
-                ));
+                );
 
                 current = Box::new(NestedLoopJoinExecutor::new(
+                    Arc::clone(&bpm),
                     current,
                     right_scan,
                     join_cond.condition.clone(),
@@ -494,9 +872,10 @@ impl<'a> ExecutionEngine<'a> {
 
         let filtered: Box<dyn Executor> = if let Some(pred) = &stmt.where_clause {
             if stmt.join_conditions.is_empty() {
-                Box::new(FilterExecutor::new(scan, pred.clone()))
+                Box::new(FilterExecutor::new(Arc::clone(&bpm), scan, pred.clone()))
             } else {
                 Box::new(FilterWithOffsetsExecutor::new(
+                    Arc::clone(&bpm),
                     scan,
                     pred.clone(),
                     rte_offsets.clone(),
@@ -507,29 +886,37 @@ impl<'a> ExecutionEngine<'a> {
         };
 
         // Check if we need aggregation (GROUP BY or aggregate functions)
-        let needs_aggregate = !stmt.group_by.is_empty()
+        let needs_aggregate = !stmt.grouping_sets.is_empty()
             || stmt
                 .select_items
                 .iter()
-                .any(|item| Self::contains_aggregate(&item.expr))
+                .any(|item| item.expr.contains_aggregate())
             || stmt
                 .having
                 .as_ref()
-                .map(|h| Self::contains_aggregate(h))
+                .map(AnalyzedExpr::contains_aggregate)
                 .unwrap_or(false);
 
         if needs_aggregate {
+            // Without GROUP BY the whole input is one group
+            let grouping_sets = match stmt.grouping_sets.is_empty() {
+                true => vec![Vec::new()],
+                false => stmt.grouping_sets.clone(),
+            };
             let agg_exec: Box<dyn Executor + 'a> = Box::new(AggregateExecutor::new(
+                Arc::clone(&bpm),
                 filtered,
                 stmt.group_by.clone(),
+                grouping_sets,
                 stmt.select_items.clone(),
+                sorted_input,
             ));
             // Apply HAVING as a FilterExecutor on top of AggregateExecutor
             if let Some(having) = &stmt.having {
                 // Transform HAVING expression to reference output columns
                 let transformed_having =
-                    Self::transform_having_expr(having, &stmt.select_items, &stmt.group_by);
-                Box::new(FilterExecutor::new(agg_exec, transformed_having))
+                    Self::transform_having_expr(having, &stmt.select_items);
+                Box::new(FilterExecutor::new(bpm, agg_exec, transformed_having))
             } else {
                 agg_exec
             }
@@ -541,9 +928,10 @@ impl<'a> ExecutionEngine<'a> {
                 .collect();
 
             if stmt.join_conditions.is_empty() {
-                Box::new(ProjectionExecutor::new(filtered, exprs))
+                Box::new(ProjectionExecutor::new(bpm, filtered, exprs))
             } else {
                 Box::new(ProjectionWithOffsetsExecutor::new(
+                    bpm,
                     filtered,
                     exprs,
                     rte_offsets,
@@ -552,23 +940,11 @@ impl<'a> ExecutionEngine<'a> {
         }
     }
 
-    fn contains_aggregate(expr: &AnalyzedExpr) -> bool {
-        match expr {
-            AnalyzedExpr::Aggregate(_) => true,
-            AnalyzedExpr::BinaryOp { left, right, .. } => {
-                Self::contains_aggregate(left) || Self::contains_aggregate(right)
-            }
-            AnalyzedExpr::UnaryOp { expr, .. } => Self::contains_aggregate(expr),
-            AnalyzedExpr::Literal(_) | AnalyzedExpr::ColumnRef(_) => false,
-        }
-    }
-
     /// Transform HAVING expression to reference output columns from AggregateExecutor.
     /// Aggregates and GROUP BY columns are replaced with ColumnRefs to output indices.
     fn transform_having_expr(
         expr: &AnalyzedExpr,
         select_items: &[AnalyzedSelectItem],
-        group_by: &[AnalyzedExpr],
     ) -> AnalyzedExpr {
         match expr {
             AnalyzedExpr::Aggregate(agg) => {
@@ -580,6 +956,7 @@ impl<'a> ExecutionEngine<'a> {
                             column_index: i,
                             column_name: format!("agg_{}", i),
                             data_type: agg.result_type.clone(),
+                            collation: agg.arg_collation(),
                         });
                     }
                 }
@@ -596,6 +973,7 @@ impl<'a> ExecutionEngine<'a> {
                                 column_index: i,
                                 column_name: col_ref.column_name.clone(),
                                 data_type: col_ref.data_type.clone(),
+                                collation: col_ref.collation,
                             });
                         }
                     }
@@ -607,11 +985,13 @@ impl<'a> ExecutionEngine<'a> {
                 op,
                 right,
                 result_type,
+                collation,
             } => AnalyzedExpr::BinaryOp {
-                left: Box::new(Self::transform_having_expr(left, select_items, group_by)),
+                left: Box::new(Self::transform_having_expr(left, select_items)),
                 op: op.clone(),
-                right: Box::new(Self::transform_having_expr(right, select_items, group_by)),
+                right: Box::new(Self::transform_having_expr(right, select_items)),
                 result_type: result_type.clone(),
+                collation: *collation,
             },
             AnalyzedExpr::UnaryOp {
                 op,
@@ -619,7 +999,37 @@ impl<'a> ExecutionEngine<'a> {
                 result_type,
             } => AnalyzedExpr::UnaryOp {
                 op: op.clone(),
-                expr: Box::new(Self::transform_having_expr(inner, select_items, group_by)),
+                expr: Box::new(Self::transform_having_expr(inner, select_items)),
+                result_type: result_type.clone(),
+            },
+            AnalyzedExpr::Function {
+                func: ScalarFunction::Grouping(_),
+                result_type,
+                ..
+            } => {
+                // Computed by the aggregate executor, so it must be read from
+                // the select list
+                match select_items.iter().position(|item| exprs_equal(&item.expr, expr)) {
+                    Some(i) => AnalyzedExpr::ColumnRef(AnalyzedColumnRef {
+                        rte_index: 0,
+                        column_index: i,
+                        column_name: "grouping".to_string(),
+                        data_type: result_type.clone(),
+                        collation: Collation::default(),
+                    }),
+                    None => expr.clone(),
+                }
+            }
+            AnalyzedExpr::Function {
+                func,
+                args,
+                result_type,
+            } => AnalyzedExpr::Function {
+                func: func.clone(),
+                args: args
+                    .iter()
+                    .map(|arg| Self::transform_having_expr(arg, select_items))
+                    .collect(),
                 result_type: result_type.clone(),
             },
             AnalyzedExpr::Literal(_) => expr.clone(),
@@ -630,20 +1040,7 @@ impl<'a> ExecutionEngine<'a> {
     fn exprs_match(a: &AnalyzedExpr, b: &AnalyzedExpr) -> bool {
         match (a, b) {
             (AnalyzedExpr::Aggregate(agg1), AnalyzedExpr::Aggregate(agg2)) => {
-                if agg1.func != agg2.func {
-                    return false;
-                }
-                match (&agg1.arg, &agg2.arg) {
-                    (
-                        crate::analyzer::AnalyzedAggregateArg::Asterisk,
-                        crate::analyzer::AnalyzedAggregateArg::Asterisk,
-                    ) => true,
-                    (
-                        crate::analyzer::AnalyzedAggregateArg::Expr(e1),
-                        crate::analyzer::AnalyzedAggregateArg::Expr(e2),
-                    ) => Self::exprs_match(e1, e2),
-                    _ => false,
-                }
+                aggregates_equal(agg1, agg2)
             }
             (AnalyzedExpr::ColumnRef(c1), AnalyzedExpr::ColumnRef(c2)) => {
                 c1.column_index == c2.column_index
@@ -664,8 +1061,6 @@ impl<'a> ExecutionEngine<'a> {
         wal_manager: Option<Arc<WalManager>>,
         txn_manager: Option<&'a TransactionManager>,
     ) -> Box<dyn Executor + 'a> {
-        let table_id = Self::get_table_id_from_range_table(&stmt.range_table);
-
         let snapshot = match &txn {
             Some(t) if t.snapshot.is_some() => t.snapshot.clone(),
             _ => txn_manager.map(|tm| {
@@ -674,25 +1069,20 @@ impl<'a> ExecutionEngine<'a> {
             }),
         };
 
-        let scan: Box<dyn Executor> = Box::new(SeqScanExecutor::new(
+        let (filtered, rte_offsets) = Self::build_dml_scan(
             Arc::clone(&bpm),
             catalog,
-            table_id,
-            None,
-            None,
+            &stmt.range_table,
+            &stmt.where_clause,
             snapshot,
             txn_manager,
-        ));
-
-        let filtered: Box<dyn Executor> = if let Some(pred) = &stmt.where_clause {
-            Box::new(FilterExecutor::new(scan, pred.clone()))
-        } else {
-            scan
-        };
+        );
 
         Box::new(DeleteExecutor::new(
             bpm,
             filtered,
+            rte_offsets,
+            stmt.returning.clone(),
             txn,
             lock_manager,
             wal_manager,
@@ -718,43 +1108,131 @@ impl<'a> ExecutionEngine<'a> {
             }),
         };
 
-        let scan: Box<dyn Executor> = Box::new(SeqScanExecutor::new(
+        let (filtered, rte_offsets) = Self::build_dml_scan(
             Arc::clone(&bpm),
             catalog,
-            table_id,
-            None,
-            None,
+            &stmt.range_table,
+            &stmt.where_clause,
             snapshot,
             txn_manager,
-        ));
-
-        let filtered: Box<dyn Executor> = if let Some(pred) = &stmt.where_clause {
-            Box::new(FilterExecutor::new(scan, pred.clone()))
-        } else {
-            scan
-        };
+        );
 
         Box::new(UpdateExecutor::new(
             bpm,
             catalog,
             table_id,
             filtered,
+            rte_offsets,
             stmt.assignments.clone(),
+            stmt.returning.clone(),
             txn,
             lock_manager,
             wal_manager,
+            txn_manager,
         ))
     }
 
+    /// Scan for UPDATE/DELETE: the target table (RTE 0) cross joined with any
+    /// FROM/USING tables, filtered by WHERE. Output tuples carry the target row's RID.
+    fn build_dml_scan(
+        bpm: Arc<Mutex<BufferPoolManager>>,
+        catalog: &'a Catalog,
+        range_table: &[RangeTableEntry],
+        where_clause: &Option<AnalyzedExpr>,
+        snapshot: Option<Snapshot>,
+        txn_manager: Option<&'a TransactionManager>,
+    ) -> (Box<dyn Executor + 'a>, Vec<usize>) {
+        let mut rte_offsets = Vec::new();
+        let mut offset = 0;
+        for rte in range_table {
+            rte_offsets.push(offset);
+            offset += rte.output_columns.len();
+        }
+
+        let scan_rte = |rte: &RangeTableEntry| {
+            Self::build_rte_scan(
+                Arc::clone(&bpm),
+                catalog,
+                rte,
+                &rte_offsets,
+                snapshot.clone(),
+                txn_manager,
+            )
+        };
+
+        let mut current = scan_rte(&range_table[0]);
+        for rte in &range_table[1..] {
+            // Join predicates live in WHERE, so the joins themselves are cross products
+            let always_true = AnalyzedExpr::Literal(AnalyzedLiteral {
+                value: LiteralValue::Boolean(true),
+                data_type: DataType::Bool,
+            });
+            current = Box::new(NestedLoopJoinExecutor::new(
+                Arc::clone(&bpm),
+                current,
+                scan_rte(rte),
+                always_true,
+                JoinType::Inner,
+                rte_offsets.clone(),
+                rte.output_columns.len(),
+            ));
+        }
+
+        let filtered: Box<dyn Executor + 'a> = match where_clause {
+            Some(pred) if range_table.len() == 1 => {
+                Box::new(FilterExecutor::new(Arc::clone(&bpm), current, pred.clone()))
+            }
+            Some(pred) => Box::new(FilterWithOffsetsExecutor::new(
+                Arc::clone(&bpm),
+                current,
+                pred.clone(),
+                rte_offsets.clone(),
+            )),
+            None => current,
+        };
+
+        (filtered, rte_offsets)
+    }
+
+    /// Full scan of one FROM item: a sequential scan of a base table, or the
+    /// rows of unnest(array). `rte_offsets` locate the FROM items before it
+    /// in the rows of a join, which the array may refer to.
+    fn build_rte_scan(
+        bpm: Arc<Mutex<BufferPoolManager>>,
+        catalog: &'a Catalog,
+        rte: &RangeTableEntry,
+        rte_offsets: &[usize],
+        snapshot: Option<Snapshot>,
+        txn_manager: Option<&'a TransactionManager>,
+    ) -> Box<dyn Executor + 'a> {
+        match &rte.source {
+            TableSource::BaseTable { table_id, .. } => Box::new(SeqScanExecutor::new(
+                bpm,
+                catalog,
+                *table_id,
+                None,
+                None,
+                snapshot,
+                txn_manager,
+            )),
+            TableSource::Unnest { array } => {
+                Box::new(UnnestExecutor::new(bpm, array.clone(), rte_offsets.to_vec()))
+            }
+        }
+    }
+
     fn get_table_id_from_range_table(range_table: &[RangeTableEntry]) -> u32 {
         let rte = &range_table[0];
         match &rte.source {
             TableSource::BaseTable { table_id, .. } => *table_id,
+            TableSource::Unnest { .. } => unreachable!("UPDATE target is always a base table"),
         }
     }
 
     /// Try to find an index that can be used for the WHERE clause.
-    /// Currently only supports simple equality conditions: column = literal
+    /// Currently only supports simple equality conditions: key = literal, where
+    /// the key is an indexed column or the expression of an expression index.
+    /// The index must order its key by the collation the comparison uses.
     fn try_find_index_for_where(
         catalog: &Catalog,
         table_id: u32,
@@ -762,15 +1240,27 @@ impl<'a> ExecutionEngine<'a> {
     ) -> Option<(IndexDef, IndexKey)> {
         let where_expr = where_clause.as_ref()?;
 
-        // Extract column_index and literal value from equality condition
-        let (column_index, value) = Self::extract_equality_condition(where_expr)?;
+        // Extract the key expression and literal value from an equality condition
+        let (key_expr, literal, collation) = Self::extract_equality_condition(where_expr)?;
+        let value = Self::index_key_value(literal, key_expr.data_type())?;
 
-        // Find an index on this column
+        // Find an index on this column or expression
+        let table = catalog.get_table_by_id(table_id)?;
         let indexes = catalog.get_indexes_for_table(table_id);
         for index_def in indexes {
             // Only support single-column indexes for now
-            if index_def.column_ids.len() == 1 && index_def.column_ids[0] == column_index {
-                let key = IndexKey::new(vec![value.clone()]);
+            let usable = match index_def.columns.as_slice() {
+                [column @ IndexColumn::Column(id)] => {
+                    matches!(key_expr, AnalyzedExpr::ColumnRef(col) if col.column_index == *id)
+                        && column.collation(&table) == collation
+                }
+                [column @ IndexColumn::Expression(expr)] => {
+                    exprs_equal(expr, key_expr) && column.collation(&table) == collation
+                }
+                _ => false,
+            };
+            if usable {
+                let key = IndexKey::new(vec![collation.sort_key(value.clone())]);
                 return Some((index_def, key));
             }
         }
@@ -778,28 +1268,236 @@ impl<'a> ExecutionEngine<'a> {
         None
     }
 
-    /// Extract (column_index, literal_value) from a simple equality condition.
-    /// Returns None if the expression is not a simple `column = literal` or `literal = column`.
-    fn extract_equality_condition(expr: &AnalyzedExpr) -> Option<(usize, Value)> {
+    /// Whether a scan of this index returns rows ordered by the query's only
+    /// GROUP BY expression, so that a group is complete once the key changes
+    fn index_orders_group_by(
+        catalog: &Catalog,
+        table_id: u32,
+        index_def: &IndexDef,
+        stmt: &AnalyzedSelectStatement,
+    ) -> bool {
+        let ([group_key], [_]) = (stmt.group_by.as_slice(), stmt.grouping_sets.as_slice()) else {
+            return false;
+        };
+        let Some(table) = catalog.get_table_by_id(table_id) else {
+            return false;
+        };
+        match index_def.columns.as_slice() {
+            [column @ IndexColumn::Column(id)] => {
+                matches!(group_key, AnalyzedExpr::ColumnRef(col) if col.column_index == *id)
+                    && column.collation(&table) == group_key.collation()
+            }
+            [column @ IndexColumn::Expression(expr)] => {
+                exprs_equal(expr, group_key) && column.collation(&table) == group_key.collation()
+            }
+            _ => false,
+        }
+    }
+
+    /// Convert a literal to the indexed column's type. Returns None when the
+    /// conversion is lossy (e.g. `int_col = 1.5`): the index would probe the
+    /// wrong key, so the caller falls back to a filtered scan.
+    fn index_key_value(literal: Value, data_type: &DataType) -> Option<Value> {
+        let value = coerce_value(literal.clone(), data_type).ok()?;
+        match compare_numbers(&value, &literal).or_else(|| compare_datetimes(&value, &literal)) {
+            Some(Ordering::Equal) | None => Some(value),
+            Some(_) => None,
+        }
+    }
+
+    /// Extract (key expression, literal value, collation) from a simple
+    /// equality condition `key = literal` or `literal = key`.
+    fn extract_equality_condition(expr: &AnalyzedExpr) -> Option<(&AnalyzedExpr, Value, Collation)> {
         match expr {
-            AnalyzedExpr::BinaryOp { left, op, right, .. } if *op == BinaryOperator::Eq => {
-                // Try column = literal
-                if let (AnalyzedExpr::ColumnRef(col), AnalyzedExpr::Literal(lit)) =
-                    (left.as_ref(), right.as_ref())
-                {
-                    let value = literal_to_value(lit);
-                    return Some((col.column_index, value));
-                }
-                // Try literal = column
-                if let (AnalyzedExpr::Literal(lit), AnalyzedExpr::ColumnRef(col)) =
-                    (left.as_ref(), right.as_ref())
-                {
-                    let value = literal_to_value(lit);
-                    return Some((col.column_index, value));
+            AnalyzedExpr::BinaryOp {
+                left,
+                op,
+                right,
+                collation,
+                ..
+            } if *op == BinaryOperator::Eq => {
+                match (left.as_ref(), right.as_ref()) {
+                    (AnalyzedExpr::Literal(_), AnalyzedExpr::Literal(_)) => None,
+                    (key, AnalyzedExpr::Literal(lit)) | (AnalyzedExpr::Literal(lit), key) => {
+                        Some((key, literal_to_value(lit), *collation))
+                    }
+                    _ => None,
                 }
-                None
             }
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::testing::TestDb;
+    use super::*;
+
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE items (id INT, name VARCHAR, qty INT)");
+        db.query("INSERT INTO items VALUES (1, 'bolt', 10)");
+        db.query("INSERT INTO items VALUES (2, 'nut', 20)");
+        db
+    }
+
+    fn row(id: i32, name: &str, qty: i32) -> Vec<Value> {
+        vec![Value::Int(id), Value::Varchar(name.to_string()), Value::Int(qty)]
+    }
+
+    #[test]
+    fn test_insert_returning() {
+        let db = setup();
+        assert_eq!(
+            db.query("INSERT INTO items VALUES (3, 'gear', 5) RETURNING *"),
+            vec![row(3, "gear", 5)]
+        );
+        assert_eq!(
+            db.query("INSERT INTO items VALUES (4, 'cog', 7) RETURNING qty * 2, id"),
+            vec![vec![Value::Int(14), Value::Int(4)]]
+        );
+        // Without RETURNING the statement reports its row count
+        assert_eq!(
+            db.query("INSERT INTO items VALUES (5, 'pin', 1)"),
+            vec![vec![Value::Int(1)]]
+        );
+    }
+
+    #[test]
+    fn test_update_returning_new_values() {
+        let db = setup();
+        let mut returned = db.query("UPDATE items SET qty = qty + 1 RETURNING id, qty");
+        returned.sort_by_key(|r| format!("{r:?}"));
+        assert_eq!(
+            returned,
+            vec![
+                vec![Value::Int(1), Value::Int(11)],
+                vec![Value::Int(2), Value::Int(21)]
+            ]
+        );
+        assert!(db
+            .query("UPDATE items SET qty = 0 WHERE id = 9 RETURNING *")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_delete_returning_old_values() {
+        let db = setup();
+        assert_eq!(
+            db.query("DELETE FROM items WHERE id = 2 RETURNING name, qty"),
+            vec![vec![Value::Varchar("nut".to_string()), Value::Int(20)]]
+        );
+        assert_eq!(db.query("SELECT * FROM items"), vec![row(1, "bolt", 10)]);
+    }
+
+    #[test]
+    fn test_char_columns_pad_and_check_length() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE codes (code CHAR(3), label VARCHAR(4))");
+        db.query("INSERT INTO codes VALUES ('ab', 'x')");
+        assert_eq!(
+            db.query("SELECT code, label FROM codes"),
+            vec![vec![Value::Varchar("ab ".to_string()), Value::Varchar("x".to_string())]]
+        );
+        // A literal compared with CHAR(n) is padded the same way
+        assert_eq!(db.query("SELECT label FROM codes WHERE code = 'ab'").len(), 1);
+
+        let err = db.run("INSERT INTO codes VALUES ('abcd', 'x')").unwrap_err();
+        assert_eq!(err.to_string(), "value too long for type character(3)");
+        let err = db.run("UPDATE codes SET label = 'toolong'").unwrap_err();
+        assert_eq!(err.to_string(), "value too long for type character varying(4)");
+        assert_eq!(db.query("SELECT * FROM codes").len(), 1);
+    }
+
+    #[test]
+    fn test_char_length_ignores_padding() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE codes (code CHAR(5), label VARCHAR(5))");
+        db.query("INSERT INTO codes VALUES ('ab', 'ab  ')");
+        // The blank padding is not counted, but octet_length counts it
+        assert_eq!(
+            db.query(
+                "SELECT length(code), char_length(code), octet_length(code), length(label) \
+                 FROM codes"
+            ),
+            vec![vec![Value::Int(2), Value::Int(2), Value::Int(5), Value::Int(4)]]
+        );
+        assert_eq!(
+            db.query("SELECT length('ab'::char(5)) FROM codes"),
+            vec![vec![Value::Int(2)]]
+        );
+    }
+
+    #[test]
+    fn test_toasted_values_round_trip() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE docs (id INT, body VARCHAR)");
+        // Letters from a xorshift generator barely compress, so the value is
+        // stored out of line in the TOAST heap
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let body: String = (0..6000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (b'a' + (state % 26) as u8) as char
+            })
+            .collect();
+        db.query(&format!("INSERT INTO docs VALUES (1, '{body}')"));
+
+        let body = Value::Varchar(body);
+        assert_eq!(db.query("SELECT body FROM docs"), vec![vec![body.clone()]]);
+        assert_eq!(db.query("SELECT * FROM docs"), vec![vec![Value::Int(1), body.clone()]]);
+        let Value::Varchar(text) = &body else { unreachable!() };
+        let found = db.query(&format!("SELECT id FROM docs WHERE body = '{text}'"));
+        assert_eq!(found, vec![vec![Value::Int(1)]]);
+
+        // An update writes the new version with the value fetched back
+        db.query("UPDATE docs SET id = 2");
+        assert_eq!(db.query("SELECT id, body FROM docs"), vec![vec![Value::Int(2), body]]);
+    }
+
+    #[test]
+    fn test_datetime_overflow_is_an_error() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (a DATE, t TIMESTAMP, x TIME)");
+        db.query("INSERT INTO t VALUES ('2024-01-01', '2024-01-01 00:00', '12:00')");
+        for (sql, message) in [
+            ("SELECT a + 2145030000 FROM t", "date out of range"),
+            ("SELECT CAST(a + 200000000 AS TIMESTAMP) FROM t", "timestamp out of range"),
+            ("SELECT date_trunc('month', a + 200000000) FROM t", "timestamp out of range"),
+            ("SELECT (a + 200000000) + x FROM t", "timestamp out of range"),
+            ("SELECT a - 2147483647 FROM t", "date out of range"),
+            ("SELECT TIMESTAMP '300000-01-01' FROM t", "timestamp out of range: \"300000-01-01\""),
+            (
+                "SELECT TIME '99999999999999:00' FROM t",
+                "date/time field value out of range: \"99999999999999:00\"",
+            ),
+            (
+                "SELECT INTERVAL '99999999999:00' FROM t",
+                "date/time field value out of range: \"99999999999:00\"",
+            ),
+        ] {
+            let error = db.run(sql).err().unwrap_or_else(|| panic!("{sql}: no error"));
+            assert_eq!(error.to_string(), message, "{sql}");
+        }
+        // A DATE beyond the TIMESTAMP range still compares with timestamps
+        assert_eq!(
+            db.query("SELECT (a + 2000000000) > t, extract(epoch FROM a + 2000000000) FROM t"),
+            [[
+                Value::Bool(true),
+                Value::Numeric(crate::numeric::Numeric::parse("172801704067200").unwrap())
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_returning_errors() {
+        let db = setup();
+        assert!(db.run("INSERT INTO items VALUES (3, 'x', 1) RETURNING missing").is_err());
+        assert!(db.run("DELETE FROM items RETURNING count(*)").is_err());
+        // The failed statements changed nothing
+        assert_eq!(db.query("SELECT * FROM items").len(), 2);
+    }
+}
@@ -1,64 +1,198 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::{DefaultHasher, Entry};
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 
-use crate::analyzer::{AnalyzedAggregate, AnalyzedAggregateArg, AnalyzedExpr, AnalyzedSelectItem};
-use crate::ast::AggregateFunction;
-use crate::tuple::Value;
+use crate::analyzer::{
+    AnalyzedAggregate, AnalyzedAggregateArg, AnalyzedExpr, AnalyzedOrderBy, AnalyzedSelectItem,
+};
+use crate::ast::{AggregateFunction, BinaryOperator};
+use crate::buffer_pool::BufferPoolManager;
+use crate::cancel::check_for_interrupts;
+use crate::collation::Collation;
+use crate::functions::ScalarFunction;
+use crate::json::Json;
+use crate::settings;
+use crate::toast;
+use crate::tuple::{
+    coerce_value, compare_datetimes, compare_numbers, Column, DataType, Schema, Value,
+};
+use crate::udf::Accumulator;
 
-use super::{evaluate_binary_op, evaluate_unary_op, literal_to_value, Executor, Tuple};
+use super::spill::SpillFile;
+use super::{
+    aggregates_equal, evaluate_binary_op, evaluate_unary_op, exprs_equal, literal_to_value,
+    Executor, Tuple,
+};
 
-/// Running state for each aggregate function
+/// Number of temp files the groups that do not fit in work_mem are spread over
+const SPILL_PARTITIONS: usize = 8;
+
+/// Estimated hash table bookkeeping per group, in bytes
+const GROUP_OVERHEAD: usize = 64;
+
+/// One input row's values for an aggregate
 #[derive(Debug, Clone)]
+struct AggregateInput {
+    value: Value,
+    /// string_agg's delimiter or the percentile fraction; NULL if absent
+    extra: Value,
+    /// Values of the aggregate's ORDER BY expressions
+    sort_keys: Vec<Value>,
+}
+
+impl AggregateInput {
+    fn estimated_size(&self) -> usize {
+        value_size(&self.value)
+            + value_size(&self.extra)
+            + self.sort_keys.iter().map(value_size).sum::<usize>()
+    }
+}
+
+/// Running state for each aggregate function
+#[derive(Debug)]
 enum AggregateAccumulator {
     Count { count: i64 },
-    Sum { sum: i64, has_value: bool },
-    Avg { sum: i64, count: i64 },
-    Min { min: Option<Value> },
-    Max { max: Option<Value> },
+    /// Running sum in the aggregate's result type
+    Sum { sum: Option<Value>, sum_type: DataType },
+    /// Running sum in NUMERIC (exact inputs) or DOUBLE PRECISION (floats)
+    Avg { sum: Option<Value>, sum_type: DataType, count: i64 },
+    /// Strings compare under the argument's collation
+    Min { min: Option<Value>, collation: Collation },
+    Max { max: Option<Value>, collation: Collation },
+    /// Inputs, NULLs included, converted to JSON in finalize
+    JsonbAgg { items: Vec<Value> },
+    /// Text or bytea joined so far
+    StringAgg { result: Option<Value> },
+    /// Inputs, NULLs included
+    ArrayAgg { items: Vec<Value> },
+    BoolAnd { result: Option<bool> },
+    BoolOr { result: Option<bool> },
+    /// Sum and sum of squares in NUMERIC (exact inputs) or DOUBLE PRECISION
+    Variance {
+        sum: Option<Value>,
+        sum_squares: Option<Value>,
+        sum_type: DataType,
+        count: i64,
+        sample: bool,
+        root: bool,
+    },
+    /// Non-NULL inputs, which arrive sorted, and the fraction of the first row
+    Percentile {
+        values: Vec<Value>,
+        fraction: Option<Value>,
+        continuous: bool,
+    },
+    /// Rows held back until finalize, then fed to `inner` in ORDER BY order
+    Sorted {
+        rows: Vec<AggregateInput>,
+        order_by: Vec<AnalyzedOrderBy>,
+        inner: Box<AggregateAccumulator>,
+    },
+    /// State owned by a user-defined aggregate
+    User(Box<dyn Accumulator>),
 }
 
 impl AggregateAccumulator {
-    fn new(func: &AggregateFunction) -> Self {
-        match func {
+    fn new(agg: &AnalyzedAggregate) -> Self {
+        let accumulator = Self::unordered(agg);
+        match &agg.arg {
+            AnalyzedAggregateArg::Expr(_) if !agg.order_by.is_empty() => {
+                AggregateAccumulator::Sorted {
+                    rows: Vec::new(),
+                    order_by: agg.order_by.clone(),
+                    inner: Box::new(accumulator),
+                }
+            }
+            _ => accumulator,
+        }
+    }
+
+    fn unordered(agg: &AnalyzedAggregate) -> Self {
+        let variance = |sample, root| AggregateAccumulator::Variance {
+            sum: None,
+            sum_squares: None,
+            sum_type: agg.result_type.clone(),
+            count: 0,
+            sample,
+            root,
+        };
+        match &agg.func {
             AggregateFunction::Count => AggregateAccumulator::Count { count: 0 },
             AggregateFunction::Sum => AggregateAccumulator::Sum {
-                sum: 0,
-                has_value: false,
+                sum: None,
+                sum_type: agg.result_type.clone(),
+            },
+            AggregateFunction::Avg => AggregateAccumulator::Avg {
+                sum: None,
+                sum_type: agg.result_type.clone(),
+                count: 0,
+            },
+            AggregateFunction::Min => AggregateAccumulator::Min {
+                min: None,
+                collation: agg.arg_collation(),
             },
-            AggregateFunction::Avg => AggregateAccumulator::Avg { sum: 0, count: 0 },
-            AggregateFunction::Min => AggregateAccumulator::Min { min: None },
-            AggregateFunction::Max => AggregateAccumulator::Max { max: None },
+            AggregateFunction::Max => AggregateAccumulator::Max {
+                max: None,
+                collation: agg.arg_collation(),
+            },
+            AggregateFunction::JsonbAgg => AggregateAccumulator::JsonbAgg { items: Vec::new() },
+            AggregateFunction::StringAgg => AggregateAccumulator::StringAgg { result: None },
+            AggregateFunction::ArrayAgg => AggregateAccumulator::ArrayAgg { items: Vec::new() },
+            AggregateFunction::BoolAnd => AggregateAccumulator::BoolAnd { result: None },
+            AggregateFunction::BoolOr => AggregateAccumulator::BoolOr { result: None },
+            AggregateFunction::Stddev | AggregateFunction::StddevSamp => variance(true, true),
+            AggregateFunction::StddevPop => variance(false, true),
+            AggregateFunction::Variance | AggregateFunction::VarSamp => variance(true, false),
+            AggregateFunction::VarPop => variance(false, false),
+            AggregateFunction::PercentileCont | AggregateFunction::PercentileDisc => {
+                AggregateAccumulator::Percentile {
+                    values: Vec::new(),
+                    fraction: None,
+                    continuous: agg.func == AggregateFunction::PercentileCont,
+                }
+            }
+            AggregateFunction::User(aggregate) => {
+                let arg_type = match &agg.arg {
+                    AnalyzedAggregateArg::Expr(arg) => arg.data_type().clone(),
+                    AnalyzedAggregateArg::Asterisk => DataType::Int,
+                };
+                AggregateAccumulator::User(aggregate.accumulator(&arg_type))
+            }
         }
     }
 
-    fn accumulate(&mut self, value: &Value) {
+    fn accumulate(&mut self, input: AggregateInput) -> Result<()> {
+        let value = &input.value;
         match self {
             AggregateAccumulator::Count { count } => {
                 if *value != Value::Null {
                     *count += 1;
                 }
             }
-            AggregateAccumulator::Sum { sum, has_value } => {
-                if let Value::Int(n) = value {
-                    *sum += *n as i64;
-                    *has_value = true;
-                }
+            AggregateAccumulator::Sum { sum, sum_type } => {
+                add_to_sum(sum, sum_type, value)?;
             }
-            AggregateAccumulator::Avg { sum, count } => {
-                if let Value::Int(n) = value {
-                    *sum += *n as i64;
+            AggregateAccumulator::Avg {
+                sum,
+                sum_type,
+                count,
+            } => {
+                if add_to_sum(sum, sum_type, value)? {
                     *count += 1;
                 }
             }
-            AggregateAccumulator::Min { min } => {
+            AggregateAccumulator::Min { min, collation } => {
                 if *value == Value::Null {
-                    return;
+                    return Ok(());
                 }
                 *min = Some(match min.take() {
                     None => value.clone(),
                     Some(current) => {
-                        if compare_values(value, &current) < 0 {
+                        if compare_values(value, &current, *collation) < 0 {
                             value.clone()
                         } else {
                             current
@@ -66,14 +200,14 @@ impl AggregateAccumulator {
                     }
                 });
             }
-            AggregateAccumulator::Max { max } => {
+            AggregateAccumulator::Max { max, collation } => {
                 if *value == Value::Null {
-                    return;
+                    return Ok(());
                 }
                 *max = Some(match max.take() {
                     None => value.clone(),
                     Some(current) => {
-                        if compare_values(value, &current) > 0 {
+                        if compare_values(value, &current, *collation) > 0 {
                             value.clone()
                         } else {
                             current
@@ -81,6 +215,112 @@ impl AggregateAccumulator {
                     }
                 });
             }
+            AggregateAccumulator::JsonbAgg { items } => items.push(input.value),
+            AggregateAccumulator::StringAgg { result } => {
+                // The delimiter goes before every value but the first; a NULL
+                // delimiter counts as empty
+                *result = match (result.take(), input.value, &input.extra) {
+                    (current, Value::Null, _) => current,
+                    (None, value, _) => Some(value),
+                    (Some(Value::Varchar(mut s)), Value::Varchar(value), delimiter) => {
+                        if let Value::Varchar(delimiter) = delimiter {
+                            s.push_str(delimiter);
+                        }
+                        s.push_str(&value);
+                        Some(Value::Varchar(s))
+                    }
+                    (Some(Value::Bytea(mut b)), Value::Bytea(value), delimiter) => {
+                        if let Value::Bytea(delimiter) = delimiter {
+                            b.extend_from_slice(delimiter);
+                        }
+                        b.extend_from_slice(&value);
+                        Some(Value::Bytea(b))
+                    }
+                    (_, value, _) => anyhow::bail!("string_agg cannot join {value:?}"),
+                };
+            }
+            AggregateAccumulator::ArrayAgg { items } => items.push(input.value),
+            AggregateAccumulator::BoolAnd { result } => {
+                if let Value::Bool(b) = value {
+                    *result = Some(result.unwrap_or(true) && *b);
+                }
+            }
+            AggregateAccumulator::BoolOr { result } => {
+                if let Value::Bool(b) = value {
+                    *result = Some(result.unwrap_or(false) || *b);
+                }
+            }
+            AggregateAccumulator::Variance {
+                sum,
+                sum_squares,
+                sum_type,
+                count,
+                ..
+            } => {
+                if add_to_sum(sum, sum_type, value)? {
+                    let x = coerce_value(value.clone(), sum_type)?;
+                    let square = evaluate_binary_op(&BinaryOperator::Mul, &x, &x)?;
+                    add_to_sum(sum_squares, sum_type, &square)?;
+                    *count += 1;
+                }
+            }
+            AggregateAccumulator::Percentile {
+                values, fraction, ..
+            } => {
+                fraction.get_or_insert_with(|| input.extra.clone());
+                if input.value != Value::Null {
+                    values.push(input.value);
+                }
+            }
+            AggregateAccumulator::Sorted { rows, .. } => rows.push(input),
+            AggregateAccumulator::User(accumulator) => accumulator.accumulate(value)?,
+        }
+        Ok(())
+    }
+
+    /// Whether the state keeps its inputs until finalize, growing with each row
+    fn retains_inputs(&self) -> bool {
+        matches!(
+            self,
+            AggregateAccumulator::JsonbAgg { .. }
+                | AggregateAccumulator::StringAgg { .. }
+                | AggregateAccumulator::ArrayAgg { .. }
+                | AggregateAccumulator::Percentile { .. }
+                | AggregateAccumulator::Sorted { .. }
+        )
+    }
+
+    /// Take out the inputs the state keeps, in the order they came, leaving
+    /// it as if it had seen none of them; accumulating them again restores it
+    fn take_inputs(&mut self) -> Vec<AggregateInput> {
+        let input = |value| AggregateInput {
+            value,
+            extra: Value::Null,
+            sort_keys: Vec::new(),
+        };
+        match self {
+            AggregateAccumulator::JsonbAgg { items } | AggregateAccumulator::ArrayAgg { items } => {
+                std::mem::take(items).into_iter().map(input).collect()
+            }
+            // The text joined so far stands in for the values joined into it
+            AggregateAccumulator::StringAgg { result } => {
+                result.take().into_iter().map(input).collect()
+            }
+            AggregateAccumulator::Percentile {
+                values, fraction, ..
+            } => {
+                let fraction = fraction.take().unwrap_or(Value::Null);
+                std::mem::take(values)
+                    .into_iter()
+                    .map(|value| AggregateInput {
+                        value,
+                        extra: fraction.clone(),
+                        sort_keys: Vec::new(),
+                    })
+                    .collect()
+            }
+            AggregateAccumulator::Sorted { rows, .. } => std::mem::take(rows),
+            _ => Vec::new(),
         }
     }
 
@@ -90,34 +330,206 @@ impl AggregateAccumulator {
         }
     }
 
-    fn finalize(&self) -> Value {
-        match self {
-            AggregateAccumulator::Count { count } => Value::Int(*count as i32),
-            AggregateAccumulator::Sum { sum, has_value } => {
-                if *has_value {
-                    Value::Int(*sum as i32)
+    fn finalize(self) -> Result<Value> {
+        Ok(match self {
+            AggregateAccumulator::Count { count } => Value::BigInt(count),
+            AggregateAccumulator::Sum { sum, .. } => sum.unwrap_or(Value::Null),
+            AggregateAccumulator::Avg {
+                sum: Some(sum),
+                count,
+                ..
+            } => evaluate_binary_op(&BinaryOperator::Div, &sum, &Value::BigInt(count))?,
+            AggregateAccumulator::Avg { sum: None, .. } => Value::Null,
+            AggregateAccumulator::Min { min, .. } => min.unwrap_or(Value::Null),
+            AggregateAccumulator::Max { max, .. } => max.unwrap_or(Value::Null),
+            AggregateAccumulator::JsonbAgg { items } if items.is_empty() => Value::Null,
+            AggregateAccumulator::JsonbAgg { items } => {
+                Value::Jsonb(Json::Array(items.iter().map(Json::from_value).collect()))
+            }
+            AggregateAccumulator::StringAgg { result } => result.unwrap_or(Value::Null),
+            AggregateAccumulator::ArrayAgg { items } if items.is_empty() => Value::Null,
+            AggregateAccumulator::ArrayAgg { items } => Value::Array(items),
+            AggregateAccumulator::BoolAnd { result } | AggregateAccumulator::BoolOr { result } => {
+                result.map(Value::Bool).unwrap_or(Value::Null)
+            }
+            AggregateAccumulator::Variance {
+                sum: Some(sum),
+                sum_squares: Some(sum_squares),
+                sum_type,
+                count,
+                sample,
+                root,
+            } if count > sample as i64 => {
+                let variance = variance(&sum, &sum_squares, &sum_type, count, sample)?;
+                if root {
+                    square_root(variance)?
                 } else {
-                    Value::Null
+                    variance
                 }
             }
-            AggregateAccumulator::Avg { sum, count } => {
-                if *count > 0 {
-                    Value::Int((*sum / *count) as i32)
+            AggregateAccumulator::Variance { .. } => Value::Null,
+            AggregateAccumulator::Percentile { values, .. } if values.is_empty() => Value::Null,
+            AggregateAccumulator::Percentile {
+                values,
+                fraction,
+                continuous,
+            } => match fraction.unwrap_or(Value::Null) {
+                Value::Array(fractions) => Value::Array(
+                    fractions
+                        .iter()
+                        .map(|f| percentile(&values, f, continuous))
+                        .collect::<Result<_>>()?,
+                ),
+                fraction => percentile(&values, &fraction, continuous)?,
+            },
+            AggregateAccumulator::Sorted {
+                mut rows,
+                order_by,
+                mut inner,
+            } => {
+                rows.sort_by(|a, b| compare_sort_keys(&a.sort_keys, &b.sort_keys, &order_by));
+                for row in rows {
+                    inner.accumulate(row)?;
+                }
+                inner.finalize()?
+            }
+            AggregateAccumulator::User(accumulator) => accumulator.finalize()?,
+        })
+    }
+}
+
+/// (n * sum(x^2) - sum(x)^2) / (n * (n - 1)) for a sample, or / n^2 for the
+/// whole population
+fn variance(
+    sum: &Value,
+    sum_squares: &Value,
+    sum_type: &DataType,
+    count: i64,
+    sample: bool,
+) -> Result<Value> {
+    use BinaryOperator::{Div, Mul, Sub};
+    let n = coerce_value(Value::BigInt(count), sum_type)?;
+    let n_minus = coerce_value(Value::BigInt(if sample { count - 1 } else { count }), sum_type)?;
+    let numerator = evaluate_binary_op(
+        &Sub,
+        &evaluate_binary_op(&Mul, &n, sum_squares)?,
+        &evaluate_binary_op(&Mul, sum, sum)?,
+    )?;
+    let denominator = evaluate_binary_op(&Mul, &n, &n_minus)?;
+    Ok(match evaluate_binary_op(&Div, &numerator, &denominator)? {
+        // Float rounding can leave a tiny negative result for equal inputs
+        Value::Double(v) if v < 0.0 => Value::Double(0.0),
+        variance => variance,
+    })
+}
+
+fn square_root(value: Value) -> Result<Value> {
+    match value {
+        Value::Double(v) => Ok(Value::Double(v.sqrt())),
+        Value::Numeric(n) => match n.sqrt() {
+            Some(root) => Ok(Value::Numeric(root)),
+            None => anyhow::bail!("cannot take square root of a negative number"),
+        },
+        other => anyhow::bail!("cannot take square root of {other:?}"),
+    }
+}
+
+/// The value at `fraction` of the way through sorted `values`: interpolated
+/// for percentile_cont, the first value at or past it for percentile_disc
+fn percentile(values: &[Value], fraction: &Value, continuous: bool) -> Result<Value> {
+    let Some(f) = fraction.as_f64() else {
+        return Ok(Value::Null);
+    };
+    if !(0.0..=1.0).contains(&f) {
+        anyhow::bail!("percentile value {f} is not between 0 and 1");
+    }
+    if !continuous {
+        let position = (f * values.len() as f64).ceil() as usize;
+        return Ok(values[position.max(1) - 1].clone());
+    }
+    let position = f * (values.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    let (low, high) = match (values[lower].as_f64(), values[upper].as_f64()) {
+        (Some(low), Some(high)) => (low, high),
+        _ => anyhow::bail!("percentile_cont needs numeric input"),
+    };
+    Ok(Value::Double(low + (high - low) * (position - lower as f64)))
+}
+
+/// Order rows by their ORDER BY values; NULLs go last in ascending order
+/// unless NULLS FIRST says otherwise
+fn compare_sort_keys(a: &[Value], b: &[Value], order_by: &[AnalyzedOrderBy]) -> Ordering {
+    for ((a, b), order) in a.iter().zip(b).zip(order_by) {
+        let ordering = match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) if order.nulls_first => Ordering::Less,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) if order.nulls_first => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (a, b) => {
+                let ordering = compare_values(a, b, order.expr.collation()).cmp(&0);
+                if order.descending {
+                    ordering.reverse()
                 } else {
-                    Value::Null
+                    ordering
                 }
             }
-            AggregateAccumulator::Min { min } => min.clone().unwrap_or(Value::Null),
-            AggregateAccumulator::Max { max } => max.clone().unwrap_or(Value::Null),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
         }
     }
+    Ordering::Equal
+}
+
+/// Add a non-NULL numeric input to a running SUM/AVG total kept in `sum_type`.
+/// Returns whether the value was counted.
+fn add_to_sum(sum: &mut Option<Value>, sum_type: &DataType, value: &Value) -> Result<bool> {
+    if value.as_f64().is_none() {
+        return Ok(false);
+    }
+    let value = coerce_value(value.clone(), sum_type)?;
+    *sum = Some(match sum.take() {
+        None => value,
+        Some(total) => evaluate_binary_op(&BinaryOperator::Add, &total, &value)?,
+    });
+    Ok(true)
 }
 
 /// State for one group: group key values + accumulators for each aggregate
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct GroupState {
     group_values: Vec<Value>,
+    /// Index of the grouping set the group belongs to
+    grouping_set: usize,
     accumulators: Vec<AggregateAccumulator>,
+    /// Estimated memory used by the inputs the accumulators keep
+    retained: usize,
+}
+
+impl GroupState {
+    /// Rough memory footprint when created, for work_mem accounting
+    fn estimated_size(&self, key: &[Value]) -> usize {
+        GROUP_OVERHEAD
+            + key.iter().map(value_size).sum::<usize>()
+            + self.group_values.iter().map(value_size).sum::<usize>()
+            + self.accumulators.len() * std::mem::size_of::<AggregateAccumulator>()
+    }
+}
+
+/// A temp file of spilled rows, with the groups evicted to it
+struct SpilledPartition {
+    file: SpillFile,
+    /// Number of times its rows have been spilled
+    depth: u64,
+    groups: HashMap<Vec<Value>, GroupState>,
+}
+
+/// One input row, evaluated: its GROUP BY values and its input to each
+/// aggregate (None where the aggregate's FILTER rejects it)
+struct EvaluatedRow {
+    values: Vec<Value>,
+    inputs: Vec<Option<AggregateInput>>,
 }
 
 /// Aggregate executor using streaming/incremental aggregation.
@@ -126,14 +538,49 @@ struct GroupState {
 /// 1. Processes tuples one at a time
 /// 2. Updates aggregate state in a HashMap keyed by group values
 /// 3. Memory usage is O(number of groups) instead of O(number of tuples)
+///
+/// With ROLLUP, CUBE or GROUPING SETS each row is added to one group per
+/// grouping set, so every level is computed in the same pass.
+///
+/// The groups are kept within work_mem: once the table is full, rows of
+/// groups already in it are still aggregated in memory, while rows of new
+/// groups are written to temp files partitioned by a hash of the group key.
+/// When the input ends the groups in memory are emitted, and each partition
+/// is then read back and aggregated the same way, spilling again if it still
+/// does not fit. A group's rows all land in one partition, so every group is
+/// finished in exactly one pass.
+///
+/// Aggregates that keep their inputs (array_agg, string_agg, jsonb_agg,
+/// percentiles and aggregates with ORDER BY) grow with every row, and that
+/// growth counts against work_mem too. When a group's kept inputs would grow
+/// past it, the group is evicted: the inputs are written to its partition
+/// as rows, ahead of the rest of its rows, while its other accumulators
+/// move along with the partition and take the group up again when it is
+/// read back. Only a group alone in memory keeps growing, as its result has
+/// to be built in memory anyway.
+///
+/// When the input arrives ordered by the GROUP BY key (from an index scan),
+/// each group is emitted as soon as the key changes and only one is in
+/// memory at a time.
 pub struct AggregateExecutor<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     child: Box<dyn Executor + 'a>,
     group_by: Vec<AnalyzedExpr>,
+    /// Sets of group_by indices to group by; a plain GROUP BY has one
+    grouping_sets: Vec<Vec<usize>>,
     select_items: Vec<AnalyzedSelectItem>,
     /// Aggregate info extracted from select_items
     aggregates: Vec<AnalyzedAggregate>,
-    /// Grouped results after processing
+    /// Input is ordered by the single GROUP BY key
+    sorted_input: bool,
+    /// Memory the groups may use before new ones spill, in bytes
+    work_mem: usize,
+    /// Estimated memory used by `groups`
+    memory_used: usize,
+    /// Groups being aggregated in the current pass
     groups: HashMap<Vec<Value>, GroupState>,
+    /// Groups of the current pass moved to the partitions with their rows
+    evicted: HashMap<Vec<Value>, GroupState>,
     /// Results iterator
     results: Vec<Tuple>,
     pos: usize,
@@ -142,9 +589,12 @@ pub struct AggregateExecutor<'a> {
 
 impl<'a> AggregateExecutor<'a> {
     pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
         child: Box<dyn Executor + 'a>,
         group_by: Vec<AnalyzedExpr>,
+        grouping_sets: Vec<Vec<usize>>,
         select_items: Vec<AnalyzedSelectItem>,
+        sorted_input: bool,
     ) -> Self {
         // Extract all aggregates from select_items
         let mut aggregates = Vec::new();
@@ -153,11 +603,17 @@ impl<'a> AggregateExecutor<'a> {
         }
 
         AggregateExecutor {
+            bpm,
             child,
             group_by,
+            grouping_sets,
             select_items,
             aggregates,
+            sorted_input,
+            work_mem: settings::work_mem(),
+            memory_used: 0,
             groups: HashMap::new(),
+            evicted: HashMap::new(),
             results: Vec::new(),
             pos: 0,
             processed: false,
@@ -169,7 +625,7 @@ impl<'a> AggregateExecutor<'a> {
         match expr {
             AnalyzedExpr::Aggregate(agg) => {
                 // Check if this aggregate is already in the list
-                let already_exists = out.iter().any(|a| Self::aggregates_equal(a, agg));
+                let already_exists = out.iter().any(|a| aggregates_equal(a, agg));
                 if !already_exists {
                     out.push(agg.clone());
                 }
@@ -181,64 +637,96 @@ impl<'a> AggregateExecutor<'a> {
             AnalyzedExpr::UnaryOp { expr, .. } => {
                 Self::collect_aggregates(expr, out);
             }
-            AnalyzedExpr::Literal(_) | AnalyzedExpr::ColumnRef(_) => {}
-        }
-    }
-
-    fn aggregates_equal(a: &AnalyzedAggregate, b: &AnalyzedAggregate) -> bool {
-        // Simple equality check - same function and same arg structure
-        if a.func != b.func {
-            return false;
-        }
-        match (&a.arg, &b.arg) {
-            (AnalyzedAggregateArg::Asterisk, AnalyzedAggregateArg::Asterisk) => true,
-            (AnalyzedAggregateArg::Expr(e1), AnalyzedAggregateArg::Expr(e2)) => {
-                Self::exprs_equal(e1, e2)
-            }
-            _ => false,
-        }
-    }
-
-    fn exprs_equal(a: &AnalyzedExpr, b: &AnalyzedExpr) -> bool {
-        match (a, b) {
-            (AnalyzedExpr::ColumnRef(c1), AnalyzedExpr::ColumnRef(c2)) => {
-                c1.column_index == c2.column_index
-            }
-            (AnalyzedExpr::Literal(l1), AnalyzedExpr::Literal(l2)) => {
-                literal_to_value(l1) == literal_to_value(l2)
+            AnalyzedExpr::Function { args, .. } => {
+                for arg in args {
+                    Self::collect_aggregates(arg, out);
+                }
             }
-            _ => false,
+            AnalyzedExpr::Literal(_) | AnalyzedExpr::ColumnRef(_) => {}
         }
     }
 
     /// Evaluate a non-aggregate expression on a tuple
-    fn evaluate_expr(expr: &AnalyzedExpr, tuple: &Tuple) -> Result<Value> {
+    fn evaluate_expr(&self, expr: &AnalyzedExpr, tuple: &Tuple) -> Result<Value> {
         match expr {
             AnalyzedExpr::Literal(lit) => Ok(literal_to_value(lit)),
-            AnalyzedExpr::ColumnRef(col_ref) => Ok(tuple.values[col_ref.column_index].clone()),
+            AnalyzedExpr::ColumnRef(col_ref) => {
+                toast::detoast(&self.bpm, &tuple.values[col_ref.column_index])
+            }
             AnalyzedExpr::BinaryOp {
-                left, op, right, ..
+                left,
+                op,
+                right,
+                collation,
+                ..
             } => {
-                let left_val = Self::evaluate_expr(left, tuple)?;
-                let right_val = Self::evaluate_expr(right, tuple)?;
+                let left_val = collation.sort_key(self.evaluate_expr(left, tuple)?);
+                let right_val = collation.sort_key(self.evaluate_expr(right, tuple)?);
                 evaluate_binary_op(op, &left_val, &right_val)
             }
             AnalyzedExpr::UnaryOp { op, expr, .. } => {
-                let val = Self::evaluate_expr(expr, tuple)?;
+                let val = self.evaluate_expr(expr, tuple)?;
                 evaluate_unary_op(op, &val)
             }
+            AnalyzedExpr::Function { func, args, .. } => {
+                let values = args
+                    .iter()
+                    .map(|arg| self.evaluate_expr(arg, tuple))
+                    .collect::<Result<Vec<_>>>()?;
+                func.evaluate(&values)
+            }
             AnalyzedExpr::Aggregate(_) => {
                 anyhow::bail!("aggregate in evaluate_expr - should use finalized values")
             }
         }
     }
 
-    /// Compute group key from a tuple
-    fn compute_group_key(&self, tuple: &Tuple) -> Result<Vec<Value>> {
-        self.group_by
-            .iter()
-            .map(|expr| Self::evaluate_expr(expr, tuple))
-            .collect()
+    /// The tuple's input to `agg`, or None if its FILTER rejects the tuple
+    fn aggregate_input(
+        &self,
+        agg: &AnalyzedAggregate,
+        tuple: &Tuple,
+    ) -> Result<Option<AggregateInput>> {
+        if let Some(filter) = &agg.filter {
+            if self.evaluate_expr(filter, tuple)? != Value::Bool(true) {
+                return Ok(None);
+            }
+        }
+        let AnalyzedAggregateArg::Expr(expr) = &agg.arg else {
+            // count(*) looks at no values
+            return Ok(Some(AggregateInput {
+                value: Value::Null,
+                extra: Value::Null,
+                sort_keys: Vec::new(),
+            }));
+        };
+        Ok(Some(AggregateInput {
+            value: self.evaluate_expr(expr, tuple)?,
+            extra: match &agg.extra_arg {
+                Some(extra) => self.evaluate_expr(extra, tuple)?,
+                None => Value::Null,
+            },
+            sort_keys: agg
+                .order_by
+                .iter()
+                .map(|o| self.evaluate_expr(&o.expr, tuple))
+                .collect::<Result<_>>()?,
+        }))
+    }
+
+    fn evaluate_row(&self, tuple: &Tuple) -> Result<EvaluatedRow> {
+        Ok(EvaluatedRow {
+            values: self
+                .group_by
+                .iter()
+                .map(|expr| self.evaluate_expr(expr, tuple))
+                .collect::<Result<_>>()?,
+            inputs: self
+                .aggregates
+                .iter()
+                .map(|agg| self.aggregate_input(agg, tuple))
+                .collect::<Result<_>>()?,
+        })
     }
 
     /// Process all input tuples and build aggregate state
@@ -248,74 +736,353 @@ impl<'a> AggregateExecutor<'a> {
         }
         self.processed = true;
 
+        // Finished groups as (grouping set, output row)
+        let mut output = Vec::new();
+        let mut partitions = None;
+        let mut has_input = false;
+
         // Process each input tuple
         while let Some(tuple) = self.child.next()? {
-            let key = if self.group_by.is_empty() {
-                vec![]
-            } else {
-                self.compute_group_key(&tuple)?
-            };
+            check_for_interrupts()?;
+            has_input = true;
+            let row = self.evaluate_row(&tuple)?;
+            for set_index in 0..self.grouping_sets.len() {
+                self.add_row(set_index, &row, &mut partitions, 0, &mut output)?;
+            }
+        }
+
+        // A grouping set without expressions (as in a query without GROUP BY)
+        // produces a row even when there is no input
+        if !has_input {
+            for (set_index, set) in self.grouping_sets.iter().enumerate() {
+                if set.is_empty() {
+                    self.groups.insert(
+                        vec![Value::Int(set_index as i32)],
+                        GroupState {
+                            group_values: vec![Value::Null; self.group_by.len()],
+                            grouping_set: set_index,
+                            accumulators: self
+                                .aggregates
+                                .iter()
+                                .map(AggregateAccumulator::new)
+                                .collect(),
+                            retained: 0,
+                        },
+                    );
+                }
+            }
+        }
+        self.finish_groups(&mut output)?;
+
+        // Aggregate the spilled rows one partition at a time
+        let mut pending = self.take_partitions(partitions, 0);
+        while let Some(mut partition) = pending.pop() {
+            self.memory_used = partition
+                .groups
+                .iter()
+                .map(|(key, group)| group.estimated_size(key))
+                .sum();
+            self.groups = partition.groups;
+            let depth = partition.depth;
+            let mut partitions = None;
+            let mut reader = partition.file.reader()?;
+            while let Some(values) = reader.next_row()? {
+                check_for_interrupts()?;
+                let (set_index, row) = self.decode_spilled_row(values)?;
+                self.add_row(set_index, &row, &mut partitions, depth, &mut output)?;
+            }
+            self.finish_groups(&mut output)?;
+            pending.extend(self.take_partitions(partitions, depth));
+        }
+
+        // Results come one grouping set after another
+        output.sort_by_key(|(grouping_set, _)| *grouping_set);
+        self.results = output.into_iter().map(|(_, tuple)| tuple).collect();
+        Ok(())
+    }
 
-            // Get or create group state
-            let group = self.groups.entry(key.clone()).or_insert_with(|| GroupState {
-                group_values: key,
-                accumulators: self
-                    .aggregates
+    /// Add a row to its group in one grouping set, creating the group if
+    /// there is room and spilling the row otherwise. `depth` counts how many
+    /// times the row has been spilled already.
+    fn add_row(
+        &mut self,
+        set_index: usize,
+        row: &EvaluatedRow,
+        partitions: &mut Option<Vec<SpillFile>>,
+        depth: u64,
+        output: &mut Vec<(usize, Tuple)>,
+    ) -> Result<()> {
+        // Rows are grouped under each key's collation, so 'ABC' and 'abc'
+        // share a group under case_insensitive; the group shows the first
+        let key: Vec<Value> = std::iter::once(Value::Int(set_index as i32))
+            .chain(
+                self.grouping_sets[set_index]
                     .iter()
-                    .map(|agg| AggregateAccumulator::new(&agg.func))
-                    .collect(),
-            });
+                    .map(|&i| self.group_by[i].collation().sort_key(row.values[i].clone())),
+            )
+            .collect();
 
-            // Update each accumulator
-            for (i, agg) in self.aggregates.iter().enumerate() {
-                match &agg.arg {
-                    AnalyzedAggregateArg::Asterisk => {
-                        group.accumulators[i].accumulate_count_star();
-                    }
-                    AnalyzedAggregateArg::Expr(expr) => {
-                        let val = Self::evaluate_expr(expr, &tuple)?;
-                        group.accumulators[i].accumulate(&val);
-                    }
-                }
+        if self.evicted.contains_key(&key) {
+            return self.spill_row(set_index, &key, row, partitions, depth);
+        }
+        if !self.sorted_input
+            && self.memory_used >= self.work_mem
+            && self.groups.len() > 1
+            && self.grows_inputs(&key, row)
+        {
+            self.evict_group(set_index, &key, partitions, depth)?;
+            return self.spill_row(set_index, &key, row, partitions, depth);
+        }
+        if !self.groups.is_empty() && !self.groups.contains_key(&key) {
+            if self.sorted_input {
+                // All rows of the previous group have been seen
+                self.finish_groups(output)?;
+            } else if self.memory_used >= self.work_mem {
+                return self.spill_row(set_index, &key, row, partitions, depth);
             }
         }
 
-        // If no groups and no GROUP BY, create empty group for scalar aggregates
-        if self.groups.is_empty() && self.group_by.is_empty() {
-            self.groups.insert(
-                vec![],
-                GroupState {
-                    group_values: vec![],
+        // Get or create group state
+        let group = match self.groups.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                // Expressions outside the set read as NULL in its groups
+                let set = &self.grouping_sets[set_index];
+                let group_values = row
+                    .values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| match set.contains(&i) {
+                        true => value.clone(),
+                        false => Value::Null,
+                    })
+                    .collect();
+                let group = GroupState {
+                    group_values,
+                    grouping_set: set_index,
                     accumulators: self
                         .aggregates
                         .iter()
-                        .map(|agg| AggregateAccumulator::new(&agg.func))
+                        .map(AggregateAccumulator::new)
                         .collect(),
-                },
-            );
+                    retained: 0,
+                };
+                self.memory_used += group.estimated_size(entry.key());
+                entry.insert(group)
+            }
+        };
+
+        // Update each accumulator
+        for (i, (agg, input)) in self.aggregates.iter().zip(&row.inputs).enumerate() {
+            let Some(input) = input else {
+                continue;
+            };
+            let accumulator = &mut group.accumulators[i];
+            match &agg.arg {
+                AnalyzedAggregateArg::Asterisk => accumulator.accumulate_count_star(),
+                AnalyzedAggregateArg::Expr(_) => {
+                    if accumulator.retains_inputs() {
+                        self.memory_used += input.estimated_size();
+                        group.retained += input.estimated_size();
+                    }
+                    accumulator.accumulate(input.clone())?;
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// Whether adding the row to its group would grow the inputs it keeps
+    fn grows_inputs(&self, key: &[Value], row: &EvaluatedRow) -> bool {
+        self.groups.get(key).is_some_and(|group| {
+            group
+                .accumulators
+                .iter()
+                .zip(&row.inputs)
+                .any(|(accumulator, input)| input.is_some() && accumulator.retains_inputs())
+        })
+    }
+
+    /// Move a group out of memory: the inputs its accumulators keep are
+    /// written to its partition, and the rest of it waits in `evicted` to be
+    /// carried along with the partition
+    fn evict_group(
+        &mut self,
+        set_index: usize,
+        key: &[Value],
+        partitions: &mut Option<Vec<SpillFile>>,
+        depth: u64,
+    ) -> Result<()> {
+        let mut group = self.groups.remove(key).unwrap();
+        let mut kept: Vec<_> = group
+            .accumulators
+            .iter_mut()
+            .map(|accumulator| accumulator.take_inputs().into_iter())
+            .collect();
+        loop {
+            let inputs: Vec<Option<AggregateInput>> = kept.iter_mut().map(Iterator::next).collect();
+            if inputs.iter().all(Option::is_none) {
+                break;
+            }
+            let row = EvaluatedRow {
+                values: group.group_values.clone(),
+                inputs,
+            };
+            self.spill_row(set_index, key, &row, partitions, depth)?;
+        }
+        self.memory_used -= group.retained;
+        group.retained = 0;
+        self.evicted.insert(key.to_vec(), group);
+        Ok(())
+    }
 
-        // Build result tuples
-        for (_key, group) in &self.groups {
+    /// The partitions a pass spilled to, each with the groups evicted to it
+    fn take_partitions(
+        &mut self,
+        partitions: Option<Vec<SpillFile>>,
+        depth: u64,
+    ) -> Vec<SpilledPartition> {
+        let mut partitions: Vec<SpilledPartition> = partitions
+            .into_iter()
+            .flatten()
+            .map(|file| SpilledPartition {
+                file,
+                depth: depth + 1,
+                groups: HashMap::new(),
+            })
+            .collect();
+        for (key, group) in std::mem::take(&mut self.evicted) {
+            partitions[partition_of(&key, depth)].groups.insert(key, group);
+        }
+        partitions
+    }
+
+    /// Write a row whose group is not in memory to the partition chosen by
+    /// its key
+    fn spill_row(
+        &self,
+        set_index: usize,
+        key: &[Value],
+        row: &EvaluatedRow,
+        partitions: &mut Option<Vec<SpillFile>>,
+        depth: u64,
+    ) -> Result<()> {
+        let partitions = match partitions {
+            Some(partitions) => partitions,
+            None => {
+                println!(
+                    "[Aggregate] work_mem exceeded with {} groups, spilling to {SPILL_PARTITIONS} partitions",
+                    self.groups.len()
+                );
+                partitions.insert(
+                    (0..SPILL_PARTITIONS)
+                        .map(|_| SpillFile::create(self.spill_schema()))
+                        .collect::<Result<_>>()?,
+                )
+            }
+        };
+        let partition = partition_of(key, depth);
+
+        // Grouping set, GROUP BY values, then for each aggregate whether the
+        // row passed its FILTER, its argument, extra argument and sort keys
+        let mut values = vec![Value::Int(set_index as i32)];
+        values.extend(row.values.iter().cloned());
+        for (agg, input) in self.aggregates.iter().zip(&row.inputs) {
+            values.push(Value::Bool(input.is_some()));
+            match input {
+                Some(input) => {
+                    values.push(input.value.clone());
+                    values.push(input.extra.clone());
+                    values.extend(input.sort_keys.iter().cloned());
+                }
+                None => values.extend(vec![Value::Null; 2 + agg.order_by.len()]),
+            }
+        }
+        partitions[partition].write(&values)
+    }
+
+    /// Layout of the rows written by spill_row
+    fn spill_schema(&self) -> Schema {
+        let mut types = vec![DataType::Int];
+        types.extend(self.group_by.iter().map(|expr| expr.data_type().clone()));
+        for agg in &self.aggregates {
+            types.push(DataType::Bool);
+            types.push(match &agg.arg {
+                AnalyzedAggregateArg::Expr(expr) => expr.data_type().clone(),
+                AnalyzedAggregateArg::Asterisk => DataType::Int,
+            });
+            types.push(match &agg.extra_arg {
+                Some(extra) => extra.data_type().clone(),
+                None => DataType::Int,
+            });
+            types.extend(agg.order_by.iter().map(|o| o.expr.data_type().clone()));
+        }
+        Schema {
+            columns: types
+                .into_iter()
+                .map(|data_type| Column {
+                    name: String::new(),
+                    data_type,
+                })
+                .collect(),
+        }
+    }
+
+    /// Inverse of spill_row: the grouping set and the evaluated row
+    fn decode_spilled_row(&self, values: Vec<Value>) -> Result<(usize, EvaluatedRow)> {
+        let mut values = values.into_iter();
+        let Some(Value::Int(set_index)) = values.next() else {
+            anyhow::bail!("spilled row without a grouping set");
+        };
+        let group_values = values.by_ref().take(self.group_by.len()).collect();
+        let inputs = self
+            .aggregates
+            .iter()
+            .map(|agg| {
+                let included = values.next() == Some(Value::Bool(true));
+                let value = values.next().unwrap_or(Value::Null);
+                let extra = values.next().unwrap_or(Value::Null);
+                let sort_keys = values.by_ref().take(agg.order_by.len()).collect();
+                included.then_some(AggregateInput {
+                    value,
+                    extra,
+                    sort_keys,
+                })
+            })
+            .collect();
+        Ok((
+            set_index as usize,
+            EvaluatedRow {
+                values: group_values,
+                inputs,
+            },
+        ))
+    }
+
+    /// Finalize the groups in memory into output rows and empty the table
+    fn finish_groups(&mut self, output: &mut Vec<(usize, Tuple)>) -> Result<()> {
+        for (_, group) in std::mem::take(&mut self.groups) {
             // Finalize aggregates
             let finalized: Vec<Value> = group
                 .accumulators
-                .iter()
+                .into_iter()
                 .map(|acc| acc.finalize())
-                .collect();
+                .collect::<Result<_>>()?;
 
             // Evaluate select items
             let mut output_values = Vec::new();
             for item in &self.select_items {
-                let value =
-                    self.evaluate_select_expr(&item.expr, &group.group_values, &finalized)?;
+                let value = self.evaluate_select_expr(
+                    &item.expr,
+                    &group.group_values,
+                    &self.grouping_sets[group.grouping_set],
+                    &finalized,
+                )?;
                 output_values.push(value);
             }
-
-            let result_tuple = Tuple::new(output_values);
-            self.results.push(result_tuple);
+            output.push((group.grouping_set, Tuple::new(output_values)));
         }
-
+        self.memory_used = 0;
         Ok(())
     }
 
@@ -324,39 +1091,59 @@ impl<'a> AggregateExecutor<'a> {
         &self,
         expr: &AnalyzedExpr,
         group_values: &[Value],
+        grouping_set: &[usize],
         finalized_aggs: &[Value],
     ) -> Result<Value> {
+        // A grouped column or expression such as date_trunc('month', ts) is
+        // read from the key
+        if let Some(i) = self.group_by.iter().position(|gb| exprs_equal(gb, expr)) {
+            return Ok(group_values[i].clone());
+        }
         match expr {
             AnalyzedExpr::Literal(lit) => Ok(literal_to_value(lit)),
             AnalyzedExpr::ColumnRef(col_ref) => {
-                // Look up in group_by expressions
-                for (i, gb_expr) in self.group_by.iter().enumerate() {
-                    if let AnalyzedExpr::ColumnRef(gb_col) = gb_expr {
-                        if gb_col.column_index == col_ref.column_index {
-                            return Ok(group_values[i].clone());
-                        }
-                    }
-                }
                 anyhow::bail!(
                     "column not in GROUP BY: {}",
                     col_ref.column_name
                 )
             }
             AnalyzedExpr::BinaryOp {
-                left, op, right, ..
+                left,
+                op,
+                right,
+                collation,
+                ..
             } => {
-                let left_val = self.evaluate_select_expr(left, group_values, finalized_aggs)?;
-                let right_val = self.evaluate_select_expr(right, group_values, finalized_aggs)?;
-                evaluate_binary_op(op, &left_val, &right_val)
+                let left_val = self.evaluate_select_expr(left, group_values, grouping_set, finalized_aggs)?;
+                let right_val = self.evaluate_select_expr(right, group_values, grouping_set, finalized_aggs)?;
+                evaluate_binary_op(op, &collation.sort_key(left_val), &collation.sort_key(right_val))
             }
             AnalyzedExpr::UnaryOp { op, expr, .. } => {
-                let val = self.evaluate_select_expr(expr, group_values, finalized_aggs)?;
+                let val = self.evaluate_select_expr(expr, group_values, grouping_set, finalized_aggs)?;
                 evaluate_unary_op(op, &val)
             }
+            AnalyzedExpr::Function {
+                func: ScalarFunction::Grouping(columns),
+                ..
+            } => {
+                // One bit per argument, the first the most significant, set
+                // when that expression is not grouped in this row
+                let bits = columns.iter().fold(0, |bits, column| {
+                    (bits << 1) | i32::from(!grouping_set.contains(column))
+                });
+                Ok(Value::Int(bits))
+            }
+            AnalyzedExpr::Function { func, args, .. } => {
+                let values = args
+                    .iter()
+                    .map(|arg| self.evaluate_select_expr(arg, group_values, grouping_set, finalized_aggs))
+                    .collect::<Result<Vec<_>>>()?;
+                func.evaluate(&values)
+            }
             AnalyzedExpr::Aggregate(agg) => {
                 // Find this aggregate in our list
                 for (i, a) in self.aggregates.iter().enumerate() {
-                    if Self::aggregates_equal(a, agg) {
+                    if aggregates_equal(a, agg) {
                         return Ok(finalized_aggs[i].clone());
                     }
                 }
@@ -366,10 +1153,22 @@ impl<'a> AggregateExecutor<'a> {
     }
 }
 
+/// The partition a group's rows are spilled to. The hash is seeded with the
+/// depth so that rows spilled together once are spread over different
+/// partitions the next time.
+fn partition_of(key: &[Value], depth: u64) -> usize {
+    let mut hasher = DefaultHasher::new();
+    depth.hash(&mut hasher);
+    key.hash(&mut hasher);
+    (hasher.finish() % SPILL_PARTITIONS as u64) as usize
+}
+
 impl Executor for AggregateExecutor<'_> {
     fn open(&mut self) -> Result<()> {
         self.child.open()?;
         self.groups.clear();
+        self.evicted.clear();
+        self.memory_used = 0;
         self.results.clear();
         self.pos = 0;
         self.processed = false;
@@ -389,12 +1188,331 @@ impl Executor for AggregateExecutor<'_> {
     }
 }
 
-fn compare_values(a: &Value, b: &Value) -> i32 {
+fn compare_values(a: &Value, b: &Value, collation: Collation) -> i32 {
     match (a, b) {
-        (Value::Int(a), Value::Int(b)) => a.cmp(b) as i32,
-        (Value::Varchar(a), Value::Varchar(b)) => a.cmp(b) as i32,
+        (a, b) if a.as_f64().is_some() && b.as_f64().is_some() => {
+            compare_numbers(a, b).unwrap() as i32
+        }
+        (Value::Varchar(a), Value::Varchar(b)) => collation.compare(a, b) as i32,
         (Value::Bool(a), Value::Bool(b)) => a.cmp(b) as i32,
-        _ => 0,
+        (Value::Bytea(a), Value::Bytea(b)) => a.cmp(b) as i32,
+        (Value::Jsonb(a), Value::Jsonb(b)) => a.cmp(b) as i32,
+        // Element by element with NULL elements last, then by length
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(x, y)| match (x, y) {
+                (Value::Null, Value::Null) => 0,
+                (Value::Null, _) => 1,
+                (_, Value::Null) => -1,
+                _ => compare_values(x, y, collation),
+            })
+            .find(|&c| c != 0)
+            .unwrap_or_else(|| a.len().cmp(&b.len()) as i32),
+        _ => compare_datetimes(a, b).map_or(0, |ord| ord as i32),
     }
 }
 
+/// Rough memory footprint of a value, for work_mem accounting
+fn value_size(value: &Value) -> usize {
+    std::mem::size_of::<Value>()
+        + match value {
+            Value::Varchar(s) => s.len(),
+            Value::Bytea(b) => b.len(),
+            Value::Numeric(n) => n.to_bytes().len(),
+            Value::Jsonb(j) => j.to_bytes().len(),
+            Value::Array(elements) => elements.iter().map(value_size).sum(),
+            _ => 0,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::analyzer::{analyze_with_parameters, AnalyzedStatement};
+    use crate::executor::spill;
+    use crate::executor::testing::TestDb;
+    use crate::parser::parse;
+    use crate::tuple::format_value;
+
+    /// Held by tests that spill, as they count the temp files on disk
+    static SPILLING: Mutex<()> = Mutex::new(());
+
+    /// Hands out prepared rows, as a scan would
+    struct Rows(std::vec::IntoIter<Tuple>);
+
+    impl Executor for Rows {
+        fn open(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<Option<Tuple>> {
+            Ok(self.0.next())
+        }
+    }
+
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE sales (region VARCHAR, product VARCHAR, qty INT)");
+        db.query("INSERT INTO sales VALUES ('east', 'a', 1)");
+        db.query("INSERT INTO sales VALUES ('east', 'b', 2)");
+        db.query("INSERT INTO sales VALUES ('west', 'a', 4)");
+        db
+    }
+
+    /// Rows as text, NULL as None; the order of groups within one grouping
+    /// set is not defined, so each run of rows with the same grouping set
+    /// (given by `set_column`) is sorted
+    fn query(db: &TestDb, sql: &str, set_column: usize) -> Vec<Vec<Option<String>>> {
+        let mut rows: Vec<Vec<Option<String>>> = db
+            .query(sql)
+            .iter()
+            .map(|row| row.iter().map(format_value).collect())
+            .collect();
+        for chunk in rows.chunk_by_mut(|a, b| a[set_column] == b[set_column]) {
+            chunk.sort();
+        }
+        rows
+    }
+
+    fn row(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn test_rollup_rows_and_grouping() {
+        let db = setup();
+        let rows = query(
+            &db,
+            "SELECT GROUPING(region, product), region, product, sum(qty) FROM sales \
+             GROUP BY ROLLUP (region, product)",
+            0,
+        );
+        assert_eq!(
+            rows,
+            vec![
+                row(&[Some("0"), Some("east"), Some("a"), Some("1")]),
+                row(&[Some("0"), Some("east"), Some("b"), Some("2")]),
+                row(&[Some("0"), Some("west"), Some("a"), Some("4")]),
+                row(&[Some("1"), Some("east"), None, Some("3")]),
+                row(&[Some("1"), Some("west"), None, Some("4")]),
+                row(&[Some("3"), None, None, Some("7")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cube_rows_and_grouping() {
+        let db = setup();
+        let rows = query(
+            &db,
+            "SELECT GROUPING(region, product), GROUPING(product), region, product, count(*) \
+             FROM sales GROUP BY CUBE (region, product)",
+            0,
+        );
+        assert_eq!(
+            rows,
+            vec![
+                row(&[Some("0"), Some("0"), Some("east"), Some("a"), Some("1")]),
+                row(&[Some("0"), Some("0"), Some("east"), Some("b"), Some("1")]),
+                row(&[Some("0"), Some("0"), Some("west"), Some("a"), Some("1")]),
+                row(&[Some("1"), Some("1"), Some("east"), None, Some("2")]),
+                row(&[Some("1"), Some("1"), Some("west"), None, Some("1")]),
+                row(&[Some("2"), Some("0"), None, Some("a"), Some("2")]),
+                row(&[Some("2"), Some("0"), None, Some("b"), Some("1")]),
+                row(&[Some("3"), Some("1"), None, None, Some("3")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_grouping_set_without_input() {
+        let db = setup();
+        db.query("DELETE FROM sales");
+        // Only the () set produces a row when there is no input
+        let rows = query(
+            &db,
+            "SELECT GROUPING(region), region, count(*) FROM sales GROUP BY ROLLUP (region)",
+            0,
+        );
+        assert_eq!(rows, vec![row(&[Some("1"), None, Some("0")])]);
+    }
+
+    #[test]
+    fn test_min_max_of_arrays() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (i INT)");
+        db.query("INSERT INTO t VALUES (1)");
+        db.query("INSERT INTO t VALUES (5)");
+        db.query("INSERT INTO t VALUES (3)");
+        let rows = db.query(
+            "SELECT max(ARRAY[i, 2]), min(ARRAY[i, 2]), max(ARRAY[2, i]), \
+             min(ARRAY[1, i, NULL]) FROM t",
+        );
+        let formatted: Vec<_> = rows[0].iter().map(format_value).collect();
+        assert_eq!(
+            formatted,
+            ["{5,2}", "{1,2}", "{2,5}", "{1,1,NULL}"].map(|s| Some(s.to_string()))
+        );
+    }
+
+    /// (n, count, sum) rows of a GROUP BY n over the numbers 0..groups, each
+    /// twice, ordered by n
+    fn numbers_query(groups: i64) -> String {
+        let numbers: Vec<String> = (0..groups).chain(0..groups).map(|n| n.to_string()).collect();
+        format!(
+            "SELECT n, count(*), sum(n) FROM unnest(ARRAY[{}]) AS n GROUP BY n",
+            numbers.join(", ")
+        )
+    }
+
+    #[test]
+    fn test_spill_repartition_and_cleanup() {
+        let _spilling = SPILLING.lock().unwrap();
+        let db = TestDb::new();
+        settings::set("work_mem", Some("64kB")).unwrap();
+        let files = spill::files_created();
+        let rows = db.run(&numbers_query(8000));
+        settings::set("work_mem", None).unwrap();
+
+        // More groups than fit spill to the partitions, and the partitions
+        // are still too large to aggregate, so each is split again
+        assert!(spill::files_created() - files > SPILL_PARTITIONS as u64);
+        let mut rows = rows.unwrap();
+        rows.sort_by_key(|row| row[0].as_i64());
+        assert_eq!(rows.len(), 8000);
+        for (n, row) in rows.iter().enumerate() {
+            let n = n as i64;
+            assert_eq!(
+                row.iter().map(Value::as_i64).collect::<Vec<_>>(),
+                [Some(n), Some(2), Some(2 * n)]
+            );
+        }
+        // Every temp file is gone once the query is done
+        assert_eq!(spill::files_on_disk(), 0);
+
+        // and also when it fails: n * 1000000 overflows from n = 2148, after
+        // the spilling has started
+        settings::set("work_mem", Some("64kB")).unwrap();
+        let files = spill::files_created();
+        let sql = numbers_query(4000).replace("sum(n)", "sum(n * 1000000)");
+        let err = db.run(&sql).unwrap_err();
+        settings::set("work_mem", None).unwrap();
+        assert_eq!(err.to_string(), "integer out of range");
+        assert!(spill::files_created() > files);
+        assert_eq!(spill::files_on_disk(), 0);
+    }
+
+    #[test]
+    fn test_kept_inputs_spill_with_their_group() {
+        let _spilling = SPILLING.lock().unwrap();
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (k INT, v VARCHAR)");
+        let stmt = parse(
+            "SELECT k, count(*), string_agg(v, ','), array_agg(v), \
+             string_agg(v, ',' ORDER BY v DESC) FROM t GROUP BY k",
+        )
+        .unwrap();
+        let AnalyzedStatement::Select(select) =
+            analyze_with_parameters(&db.catalog, &stmt, &mut Vec::new()).unwrap()
+        else {
+            unreachable!()
+        };
+        // A few groups, each keeping far more than work_mem
+        let value = |k: i32, i: i32| format!("{k}-{i:04}-{}", "x".repeat(50));
+        let rows: Vec<Tuple> = (0..2000)
+            .flat_map(|i| (0..4).map(move |k| (k, i)))
+            .map(|(k, i)| Tuple::new(vec![Value::Int(k), Value::Varchar(value(k, i))]))
+            .collect();
+
+        settings::set("work_mem", Some("64kB")).unwrap();
+        let files = spill::files_created();
+        let mut executor = AggregateExecutor::new(
+            Arc::clone(&db.bpm),
+            Box::new(Rows(rows.into_iter())),
+            select.group_by,
+            select.grouping_sets,
+            select.select_items,
+            false,
+        );
+        executor.open().unwrap();
+        let mut results = Vec::new();
+        while let Some(tuple) = executor.next().unwrap() {
+            results.push(tuple);
+        }
+        settings::set("work_mem", None).unwrap();
+        assert!(spill::files_created() > files);
+        drop(executor);
+        assert_eq!(spill::files_on_disk(), 0);
+
+        // Each group still sees its inputs once, in input order
+        results.sort_by_key(|tuple| tuple.values[0].as_i64());
+        assert_eq!(results.len(), 4);
+        for (k, tuple) in (0..4).zip(&results) {
+            let values: Vec<String> = (0..2000).map(|i| value(k, i)).collect();
+            let descending: Vec<String> = values.iter().rev().cloned().collect();
+            assert_eq!(
+                tuple.values,
+                [
+                    Value::Int(k),
+                    Value::BigInt(2000),
+                    Value::Varchar(values.join(",")),
+                    Value::Array(values.into_iter().map(Value::Varchar).collect()),
+                    Value::Varchar(descending.join(",")),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sorted_input_emits_groups_in_order() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (k INT, v INT)");
+        let stmt = parse("SELECT k, count(*), sum(v) FROM t GROUP BY k").unwrap();
+        let AnalyzedStatement::Select(select) =
+            analyze_with_parameters(&db.catalog, &stmt, &mut Vec::new()).unwrap()
+        else {
+            unreachable!()
+        };
+        // Rows arrive ordered by k, as from an index scan on it
+        let rows: Vec<Tuple> = (0..3000)
+            .flat_map(|k| [k, k])
+            .map(|k| Tuple::new(vec![Value::Int(k), Value::Int(1)]))
+            .collect();
+
+        settings::set("work_mem", Some("64kB")).unwrap();
+        let mut executor = AggregateExecutor::new(
+            Arc::clone(&db.bpm),
+            Box::new(Rows(rows.into_iter())),
+            select.group_by,
+            select.grouping_sets,
+            select.select_items,
+            true,
+        );
+        executor.open().unwrap();
+        let mut keys = Vec::new();
+        while let Some(tuple) = executor.next().unwrap() {
+            assert_eq!(tuple.values[1..], [Value::BigInt(2), Value::BigInt(2)]);
+            keys.push(tuple.values[0].as_i64().unwrap());
+        }
+        settings::set("work_mem", None).unwrap();
+
+        // Groups are finished as their keys end, one at a time however small
+        // work_mem is, so they come out in input order rather than spilled
+        assert_eq!(keys, (0..3000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sorted_aggregation_with_index_scan() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (k INT, v INT)");
+        db.query("CREATE INDEX t_k ON t (k)");
+        for (k, v) in [(1, 10), (2, 20), (2, 5), (3, 1)] {
+            db.query(&format!("INSERT INTO t VALUES ({k}, {v})"));
+        }
+        let rows = db.query("SELECT k, count(*), sum(v) FROM t WHERE k = 2 GROUP BY k");
+        assert_eq!(rows, vec![vec![Value::Int(2), Value::BigInt(2), Value::BigInt(25)]]);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::btree::IndexKey;
+use crate::buffer_pool::BufferPoolManager;
+use crate::cancel::check_for_interrupts;
+use crate::catalog::{IndexDef, TableDef};
+use crate::lock_manager::{LockManager, LockMode, UniqueInsertGuard};
+use crate::page::{NO_NEXT_PAGE, PAGE_SIZE};
+use crate::toast;
+use crate::transaction::{Transaction, UndoLogEntry};
+use crate::transaction_manager::TransactionManager;
+use crate::tuple::{serialize_tuple_mvcc, DataType, Value, INVALID_TXN_ID};
+use crate::wal::{WalManager, WalRecordType};
+
+use super::insert::{
+    insert_index_entries, insert_into_heap, log_insert, probe_unique_indexes, unique_keys,
+    wait_for_row, UniqueProbe,
+};
+use super::Rid;
+
+/// Loads many rows into one table, as COPY FROM does. Rows are buffered
+/// until they fill a page, then written to the end of the heap together
+/// under a single MultiInsert WAL record instead of one record per row.
+pub struct BulkInsert<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
+    table: &'a TableDef,
+    types: Vec<DataType>,
+    indexes: Vec<IndexDef>,
+    txn: &'a mut Transaction,
+    lock_manager: &'a LockManager,
+    wal_manager: Arc<WalManager>,
+    txn_manager: &'a TransactionManager,
+    /// Page the next batch goes to: the last page of the heap chain
+    page_id: u32,
+    /// Rows waiting to be written: the values, for index keys, and the tuple
+    pending: Vec<(Vec<Value>, Vec<u8>)>,
+    pending_size: usize,
+    /// Whether the table has unique indexes to check
+    has_unique: bool,
+    /// (index ID, key) of the pending rows in the unique indexes, which the
+    /// indexes themselves cannot report as duplicates until the batch is written
+    pending_keys: HashSet<(u32, IndexKey)>,
+    /// Locks on the pending keys, held from their unique checks until the
+    /// index entries of the batch are in place
+    unique_guard: Option<UniqueInsertGuard<'a>>,
+    count: u64,
+}
+
+impl<'a> BulkInsert<'a> {
+    pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
+        table: &'a TableDef,
+        indexes: Vec<IndexDef>,
+        txn: &'a mut Transaction,
+        lock_manager: &'a LockManager,
+        wal_manager: Arc<WalManager>,
+        txn_manager: &'a TransactionManager,
+    ) -> Result<Self> {
+        if !txn.is_active() {
+            bail!("COPY requires a transaction");
+        }
+
+        let mut page_id = table.first_page_id;
+        loop {
+            let page_arc = bpm.lock().unwrap().fetch_page(page_id)?;
+            let next_page_id = page_arc.read().unwrap().next_page_id();
+            bpm.lock().unwrap().unpin_page(page_id, false)?;
+            if next_page_id == NO_NEXT_PAGE {
+                break;
+            }
+            page_id = next_page_id;
+        }
+
+        let has_unique = indexes.iter().any(|i| i.is_unique);
+        Ok(BulkInsert {
+            bpm,
+            table,
+            types: table.columns.iter().map(|c| c.data_type.clone()).collect(),
+            indexes,
+            txn,
+            lock_manager,
+            wal_manager,
+            txn_manager,
+            page_id,
+            pending: Vec::new(),
+            pending_size: 0,
+            has_unique,
+            pending_keys: HashSet::new(),
+            unique_guard: None,
+            count: 0,
+        })
+    }
+
+    /// Add a row, already converted to the column types
+    pub fn insert(&mut self, values: Vec<Value>) -> Result<()> {
+        check_for_interrupts()?;
+        self.check_unique(&values)?;
+
+        let (txn_id, bpm, table) = (self.txn.id, &self.bpm, self.table);
+        let txn = &mut *self.txn;
+        let wal_manager = Some(Arc::clone(&self.wal_manager));
+        let row = toast::toast_row(bpm, &values, &self.types, |chunk| {
+            let chunk_data = serialize_tuple_mvcc(txn_id, INVALID_TXN_ID, chunk);
+            let rid = insert_into_heap(bpm, table.toast_page_id, &chunk_data)?;
+            log_insert(bpm, Some(&mut *txn), &wal_manager, rid, chunk_data)?;
+            Ok(rid)
+        })?;
+        let data = serialize_tuple_mvcc(txn_id, INVALID_TXN_ID, &row);
+
+        self.pending_size += data.len() + 4;
+        self.pending.push((values, data));
+        if self.pending_size >= PAGE_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining rows; returns the number of rows loaded
+    pub fn finish(mut self) -> Result<u64> {
+        self.flush()?;
+        Ok(self.count)
+    }
+
+    fn check_unique(&mut self, values: &[Value]) -> Result<()> {
+        if !self.has_unique {
+            return Ok(());
+        }
+        let keys = unique_keys(
+            &self.bpm,
+            self.table,
+            &self.indexes.iter().filter(|i| i.is_unique).collect::<Vec<_>>(),
+            values,
+        )?;
+
+        loop {
+            match &mut self.unique_guard {
+                None => self.unique_guard = Some(self.lock_manager.lock_unique_insert(&keys)?),
+                Some(guard) => {
+                    if !guard.try_add(&keys) {
+                        // Another session holds one of the keys: write what we
+                        // have and let go of our locks before waiting for it
+                        self.flush()?;
+                        self.unique_guard = Some(self.lock_manager.lock_unique_insert(&keys)?);
+                    }
+                }
+            }
+            let probe = probe_unique_indexes(
+                &self.bpm,
+                self.table,
+                &self.indexes.iter().filter(|i| i.is_unique).collect::<Vec<_>>(),
+                values,
+                None,
+                self.txn.id,
+                Some(self.txn_manager),
+            )?;
+            match probe {
+                UniqueProbe::Clear => break,
+                UniqueProbe::InProgress(rid) => {
+                    // Write what we have and let go of the locks before waiting
+                    self.flush()?;
+                    wait_for_row(Some(self.lock_manager), Some(&mut *self.txn), rid)?;
+                }
+                UniqueProbe::Conflict { index_name, .. } => {
+                    bail!("duplicate key value violates unique constraint \"{index_name}\"");
+                }
+            }
+        }
+
+        if let Some((index_id, _)) = keys.iter().find(|key| self.pending_keys.contains(key)) {
+            let index_def = self.indexes.iter().find(|i| i.index_id == *index_id).unwrap();
+            bail!(
+                "duplicate key value violates unique constraint \"{}\"",
+                index_def.index_name
+            );
+        }
+        self.pending_keys.extend(keys);
+        Ok(())
+    }
+
+    /// Write the pending rows, a page at a time, then index them
+    fn flush(&mut self) -> Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_size = 0;
+        let mut rows = pending.into_iter().peekable();
+
+        while rows.peek().is_some() {
+            let page_arc = self.bpm.lock().unwrap().fetch_page_mut(self.page_id)?;
+            let mut page = page_arc.write().unwrap();
+
+            let mut written = Vec::new();
+            while let Some((_, data)) = rows.peek() {
+                let Ok(slot_id) = page.insert(data) else {
+                    break;
+                };
+                let (values, data) = rows.next().unwrap();
+                written.push((slot_id, values, data));
+            }
+
+            if !written.is_empty() {
+                let prev_lsn = self.txn.last_lsn;
+                let lsn = self.wal_manager.append(
+                    self.txn.id,
+                    prev_lsn,
+                    WalRecordType::MultiInsert {
+                        page_id: self.page_id,
+                        tuples: written
+                            .iter()
+                            .map(|(slot_id, _, data)| (*slot_id, data.clone()))
+                            .collect(),
+                    },
+                );
+                page.page_lsn = lsn;
+                self.txn.set_last_lsn(lsn);
+
+                // Undo row by row; all but the first row of the record go
+                // back to the record itself, so a rollback interrupted by a
+                // crash undoes the rest of it during recovery
+                for (i, (slot_id, _, data)) in written.iter().enumerate() {
+                    self.txn.add_undo_entry(UndoLogEntry::Insert {
+                        lsn,
+                        prev_lsn: if i == 0 { prev_lsn } else { lsn },
+                        rid: Rid {
+                            page_id: self.page_id,
+                            slot_id: *slot_id,
+                        },
+                        data: data.clone(),
+                    });
+                }
+            }
+
+            // The page is full: continue on the next page of the chain,
+            // adding one if this is the last
+            let page_id = self.page_id;
+            if rows.peek().is_some() {
+                if written.is_empty() && page.tuple_count() == 0 {
+                    bail!("row is too big for a heap page");
+                }
+                self.page_id = match page.next_page_id() {
+                    NO_NEXT_PAGE => {
+                        // Logged so that recovery links the page into the chain
+                        // even if this page is not written before a crash
+                        let (new_page_id, new_page_arc) = self.bpm.lock().unwrap().new_page()?;
+                        let lsn = self.wal_manager.append(
+                            self.txn.id,
+                            self.txn.last_lsn,
+                            WalRecordType::AllocatePage {
+                                page_id: new_page_id,
+                                table_id: self.table.table_id,
+                                prev_page_id: page_id,
+                            },
+                        );
+                        self.txn.set_last_lsn(lsn);
+                        {
+                            let mut new_page = new_page_arc.write().unwrap();
+                            new_page.set_next_page_id(NO_NEXT_PAGE);
+                            new_page.page_lsn = lsn;
+                        }
+                        self.bpm.lock().unwrap().unpin_page(new_page_id, true)?;
+                        page.set_next_page_id(new_page_id);
+                        page.page_lsn = lsn;
+                        new_page_id
+                    }
+                    next_page_id => next_page_id,
+                };
+            }
+            drop(page);
+            self.bpm.lock().unwrap().unpin_page(page_id, true)?;
+
+            for (slot_id, values, _) in written {
+                let rid = Rid { page_id, slot_id };
+                // Only needed so concurrent unique checks wait for our outcome
+                if self.has_unique {
+                    self.lock_manager.lock(self.txn.id, rid, LockMode::Exclusive)?;
+                    self.txn.add_lock(rid);
+                }
+                insert_index_entries(&self.bpm, self.table, &self.indexes, &values, rid)?;
+                self.count += 1;
+            }
+        }
+
+        self.pending_keys.clear();
+        self.unique_guard = None;
+        Ok(())
+    }
+}
@@ -1,18 +1,20 @@
+use std::collections::BTreeSet;
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use crate::analyzer::AnalyzedCreateIndexStatement;
 use crate::bootstrap::PG_INDEX_TABLE_ID;
-use crate::btree::{BTree, IndexKey};
+use crate::btree::BTree;
 use crate::buffer_pool::BufferPoolManager;
-use crate::catalog::Catalog;
+use crate::catalog::{Catalog, IndexColumn, EXPRESSION_KEY};
 use crate::lock_manager::LockManager;
 use crate::page::NO_NEXT_PAGE;
 use crate::transaction::{Transaction, UndoLogEntry};
 use crate::tuple::{deserialize_tuple_mvcc, serialize_tuple_mvcc, TxnId, Value, INVALID_TXN_ID};
 use crate::wal::{WalManager, WalRecordType};
 
+use super::insert::index_key;
 use super::{Executor, Rid, Tuple};
 
 pub struct CreateIndexExecutor<'a> {
@@ -126,12 +128,12 @@ impl Executor for CreateIndexExecutor<'_> {
             .get_table_by_id(self.stmt.table_id)
             .ok_or_else(|| anyhow::anyhow!("table not found"))?;
 
-        // Determine key schema from column IDs
+        // Determine key schema from the key columns and expressions
         let key_schema: Vec<_> = self
             .stmt
-            .column_ids
+            .columns
             .iter()
-            .map(|&col_idx| table.columns[col_idx].data_type.clone())
+            .map(|column| column.data_type(&table))
             .collect();
 
         // Create B-Tree (creates meta page + initial root leaf page)
@@ -160,6 +162,7 @@ impl Executor for CreateIndexExecutor<'_> {
         // Index ALL tuples regardless of visibility - visibility is checked at query time
         let schema = table.to_schema();
         let mut current_page_id = table.first_page_id;
+        let mut seen_keys = BTreeSet::new();
 
         while current_page_id != NO_NEXT_PAGE {
             let page_arc = self.bpm.lock().unwrap().fetch_page(current_page_id)?;
@@ -168,21 +171,41 @@ impl Executor for CreateIndexExecutor<'_> {
             let tuple_count = page.tuple_count();
             for slot_id in 0..tuple_count {
                 if let Some(tuple_data) = page.get_tuple(slot_id) {
-                    let (_xmin, _xmax, values) = deserialize_tuple_mvcc(tuple_data, &schema)?;
-
-                    // Build index key from specified columns
-                    let key_values: Vec<Value> = self
-                        .stmt
-                        .column_ids
-                        .iter()
-                        .map(|&col_idx| values[col_idx].clone())
-                        .collect();
-                    let key = IndexKey::new(key_values);
+                    let (_xmin, xmax, values) = deserialize_tuple_mvcc(tuple_data, &schema)?;
+
+                    // Build index key from specified columns and expressions
+                    let key = match index_key(&self.bpm, &table, &self.stmt.columns, &values) {
+                        Ok(key) => key,
+                        Err(e) => {
+                            drop(page);
+                            self.bpm.lock().unwrap().unpin_page(current_page_id, false)?;
+                            return Err(e);
+                        }
+                    };
+
+                    // Rows that are not deleted must not share a (non-NULL) key
+                    if self.stmt.unique
+                        && xmax == INVALID_TXN_ID
+                        && !key.0.contains(&Value::Null)
+                        && !seen_keys.insert(key.clone())
+                    {
+                        drop(page);
+                        self.bpm.lock().unwrap().unpin_page(current_page_id, false)?;
+                        bail!(
+                            "could not create unique index \"{}\": table contains duplicate keys",
+                            self.stmt.index_name
+                        );
+                    }
+
                     let rid = Rid {
                         page_id: current_page_id,
                         slot_id,
                     };
-                    btree.insert(&key, rid)?;
+                    if let Err(e) = btree.insert(&key, rid) {
+                        drop(page);
+                        self.bpm.lock().unwrap().unpin_page(current_page_id, false)?;
+                        return Err(e);
+                    }
                 }
             }
 
@@ -193,12 +216,16 @@ impl Executor for CreateIndexExecutor<'_> {
         }
 
         // Insert pg_index record
-        // pg_index schema: (index_id, index_name, table_id, column_ids, meta_page_id)
+        // pg_index schema: (index_id, index_name, table_id, column_ids, meta_page_id, is_unique,
+        // expressions)
         let column_ids_str = self
             .stmt
-            .column_ids
+            .columns
             .iter()
-            .map(|&id| id.to_string())
+            .map(|column| match column {
+                IndexColumn::Column(id) => id.to_string(),
+                IndexColumn::Expression(_) => EXPRESSION_KEY.to_string(),
+            })
             .collect::<Vec<_>>()
             .join(",");
 
@@ -211,6 +238,8 @@ impl Executor for CreateIndexExecutor<'_> {
                 Value::Int(self.stmt.table_id as i32),
                 Value::Varchar(column_ids_str),
                 Value::Int(meta_page_id as i32),
+                Value::Bool(self.stmt.unique),
+                self.stmt.expressions.clone().map_or(Value::Null, Value::Varchar),
             ],
         );
         let pg_index_rid = self.insert_into_pg_index(&pg_index_tuple)?;
@@ -3,11 +3,18 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 
 use crate::analyzer::AnalyzedCreateTableStatement;
-use crate::bootstrap::{DATA_TYPE_BOOL, DATA_TYPE_INT, DATA_TYPE_VARCHAR, PG_ATTRIBUTE_TABLE_ID, PG_CLASS_TABLE_ID};
+use crate::bootstrap::{
+    DATA_TYPE_ARRAY, DATA_TYPE_BIGINT, DATA_TYPE_BOOL, DATA_TYPE_BYTEA, DATA_TYPE_CHAR,
+    DATA_TYPE_DATE, DATA_TYPE_DOUBLE, DATA_TYPE_INT, DATA_TYPE_INTERVAL, DATA_TYPE_JSONB,
+    DATA_TYPE_NUMERIC, DATA_TYPE_REAL, DATA_TYPE_SMALLINT, DATA_TYPE_TEXT, DATA_TYPE_TIME,
+    DATA_TYPE_TIMESTAMP, DATA_TYPE_TIMESTAMPTZ, DATA_TYPE_VARCHAR, PG_ATTRIBUTE_TABLE_ID,
+    PG_CLASS_TABLE_ID,
+};
 use crate::buffer_pool::BufferPoolManager;
 use crate::catalog::Catalog;
 use crate::lock_manager::LockManager;
 use crate::page::NO_NEXT_PAGE;
+use crate::toast;
 use crate::transaction::{Transaction, UndoLogEntry};
 use crate::tuple::{serialize_tuple_mvcc, DataType, TxnId, Value, INVALID_TXN_ID};
 use crate::wal::{WalManager, WalRecordType};
@@ -48,11 +55,54 @@ impl<'a> CreateTableExecutor<'a> {
     fn data_type_to_int(dt: &DataType) -> i32 {
         match dt {
             DataType::Int => DATA_TYPE_INT,
-            DataType::Varchar => DATA_TYPE_VARCHAR,
+            DataType::Varchar(_) => DATA_TYPE_VARCHAR,
+            DataType::Char(_) => DATA_TYPE_CHAR,
+            DataType::Text => DATA_TYPE_TEXT,
             DataType::Bool => DATA_TYPE_BOOL,
+            DataType::SmallInt => DATA_TYPE_SMALLINT,
+            DataType::BigInt => DATA_TYPE_BIGINT,
+            DataType::Real => DATA_TYPE_REAL,
+            DataType::Double => DATA_TYPE_DOUBLE,
+            DataType::Numeric(_) => DATA_TYPE_NUMERIC,
+            DataType::Date => DATA_TYPE_DATE,
+            DataType::Time => DATA_TYPE_TIME,
+            DataType::Timestamp => DATA_TYPE_TIMESTAMP,
+            DataType::TimestampTz => DATA_TYPE_TIMESTAMPTZ,
+            DataType::Interval => DATA_TYPE_INTERVAL,
+            DataType::Bytea => DATA_TYPE_BYTEA,
+            DataType::Jsonb => DATA_TYPE_JSONB,
+            DataType::Array(element) => DATA_TYPE_ARRAY + Self::data_type_to_int(element),
         }
     }
 
+    /// Allocate the first page of a new heap for the table
+    fn allocate_first_page(&mut self, table_id: u32) -> Result<u32> {
+        let (new_page_id, new_page_arc) = self.bpm.lock().unwrap().new_page()?;
+        {
+            let mut new_page = new_page_arc.write().unwrap();
+            new_page.set_next_page_id(NO_NEXT_PAGE);
+
+            if let Some(ref wal_manager) = self.wal_manager {
+                if let Some(ref mut txn) = self.txn {
+                    let prev_lsn = txn.last_lsn;
+                    let lsn = wal_manager.append(
+                        txn.id,
+                        prev_lsn,
+                        WalRecordType::AllocatePage {
+                            page_id: new_page_id,
+                            table_id,
+                            prev_page_id: NO_NEXT_PAGE,
+                        },
+                    );
+                    txn.set_last_lsn(lsn);
+                    new_page.page_lsn = lsn;
+                }
+            }
+        }
+        self.bpm.lock().unwrap().unpin_page(new_page_id, true)?;
+        Ok(new_page_id)
+    }
+
     fn insert_into_table(
         &self,
         table_id: u32,
@@ -134,29 +184,19 @@ impl Executor for CreateTableExecutor<'_> {
 
         let new_table_id = self.catalog.next_table_id()?;
 
-        let (new_page_id, new_page_arc) = self.bpm.lock().unwrap().new_page()?;
-        {
-            let mut new_page = new_page_arc.write().unwrap();
-            new_page.set_next_page_id(NO_NEXT_PAGE);
+        let new_page_id = self.allocate_first_page(new_table_id)?;
 
-            if let Some(ref wal_manager) = self.wal_manager {
-                if let Some(ref mut txn) = self.txn {
-                    let prev_lsn = txn.last_lsn;
-                    let lsn = wal_manager.append(
-                        txn.id,
-                        prev_lsn,
-                        WalRecordType::AllocatePage {
-                            page_id: new_page_id,
-                            table_id: new_table_id,
-                            prev_page_id: NO_NEXT_PAGE,
-                        },
-                    );
-                    txn.set_last_lsn(lsn);
-                    new_page.page_lsn = lsn;
-                }
-            }
-        }
-        self.bpm.lock().unwrap().unpin_page(new_page_id, true)?;
+        // Only tables with variable-length columns get a TOAST relation
+        let toast_page_id = if self
+            .stmt
+            .columns
+            .iter()
+            .any(|col| toast::is_toastable(&col.data_type))
+        {
+            self.allocate_first_page(new_table_id)?
+        } else {
+            NO_NEXT_PAGE
+        };
 
         let pg_class_tuple = serialize_tuple_mvcc(
             xmin,
@@ -165,6 +205,7 @@ impl Executor for CreateTableExecutor<'_> {
                 Value::Int(new_table_id as i32),
                 Value::Varchar(self.stmt.table_name.clone()),
                 Value::Int(new_page_id as i32),
+                Value::Int(toast_page_id as i32),
             ],
         );
         let (pg_class_rid, _new_page_info) =
@@ -216,6 +257,8 @@ impl Executor for CreateTableExecutor<'_> {
                     Value::Int(Self::data_type_to_int(&col.data_type)),
                     Value::Bool(false),
                     Value::Int(ordinal as i32),
+                    Value::Int(col.data_type.typmod()),
+                    Value::Int(col.collation.id()),
                 ],
             );
             let (pg_attribute_rid, _new_page_info) =
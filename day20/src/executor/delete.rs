@@ -1,29 +1,38 @@
+use std::collections::{HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 
+use crate::analyzer::AnalyzedSelectItem;
 use crate::buffer_pool::BufferPoolManager;
 use crate::lock_manager::{LockManager, LockMode};
+use crate::toast;
 use crate::transaction::{Transaction, UndoLogEntry};
 use crate::tuple::{TxnId, Value, INVALID_TXN_ID};
 use crate::wal::{WalManager, WalRecordType};
 
-use super::{Executor, Tuple};
+use super::{project_returning, Executor, Rid, Tuple};
 
 pub struct DeleteExecutor<'a> {
     bpm: Arc<Mutex<BufferPoolManager>>,
     child: Box<dyn Executor + 'a>,
+    /// Column offsets of each RTE in the child's tuples (target table first)
+    rte_offsets: Vec<usize>,
+    returning: Vec<AnalyzedSelectItem>,
     txn: Option<&'a mut Transaction>,
     lock_manager: Option<&'a LockManager>,
     wal_manager: Option<Arc<WalManager>>,
     executed: bool,
     deleted_count: i32,
+    returned: VecDeque<Tuple>,
 }
 
 impl<'a> DeleteExecutor<'a> {
     pub fn new(
         bpm: Arc<Mutex<BufferPoolManager>>,
         child: Box<dyn Executor + 'a>,
+        rte_offsets: Vec<usize>,
+        returning: Vec<AnalyzedSelectItem>,
         txn: Option<&'a mut Transaction>,
         lock_manager: Option<&'a LockManager>,
         wal_manager: Option<Arc<WalManager>>,
@@ -31,11 +40,14 @@ impl<'a> DeleteExecutor<'a> {
         DeleteExecutor {
             bpm,
             child,
+            rte_offsets,
+            returning,
             txn,
             lock_manager,
             wal_manager,
             executed: false,
             deleted_count: 0,
+            returned: VecDeque::new(),
         }
     }
 }
@@ -44,31 +56,34 @@ impl Executor for DeleteExecutor<'_> {
     fn open(&mut self) -> Result<()> {
         self.executed = false;
         self.deleted_count = 0;
+        self.returned.clear();
         self.child.open()
     }
 
     fn next(&mut self) -> Result<Option<Tuple>> {
         if self.executed {
-            return Ok(None);
+            return Ok(self.returned.pop_front());
         }
         self.executed = true;
 
         let xmax: TxnId = self.txn.as_ref().map(|t| t.id).unwrap_or(INVALID_TXN_ID);
 
+        // With USING, a target row can match several joined rows; delete it once
+        let mut seen = HashSet::new();
         let mut targets = Vec::new();
         while let Some(tuple) = self.child.next()? {
             if let Some(rid) = tuple.rid {
-                targets.push(rid);
+                if seen.insert(rid) {
+                    targets.push((rid, tuple));
+                }
             }
         }
 
         if let Some(ref mut txn) = self.txn {
             if txn.is_active() {
                 if let Some(lock_manager) = self.lock_manager {
-                    for rid in &targets {
-                        lock_manager
-                            .lock(txn.id, *rid, LockMode::Exclusive)
-                            .map_err(|e| anyhow::anyhow!("{e}"))?;
+                    for (rid, _) in &targets {
+                        lock_manager.lock(txn.id, *rid, LockMode::Exclusive)?;
                         txn.add_lock(*rid);
                     }
                 }
@@ -76,38 +91,127 @@ impl Executor for DeleteExecutor<'_> {
         }
 
         self.deleted_count = targets.len() as i32;
-        for rid in targets {
-            let page_arc = self.bpm.lock().unwrap().fetch_page_mut(rid.page_id)?;
-            let mut page_guard = page_arc.write().unwrap();
-
-            page_guard.set_tuple_xmax(rid.slot_id, xmax)?;
-
-            if let Some(ref mut txn) = self.txn {
-                if txn.is_active() {
-                    if let Some(ref wal_manager) = self.wal_manager {
-                        let prev_lsn = txn.last_lsn;
-                        let lsn = wal_manager.append(
-                            txn.id,
-                            prev_lsn,
-                            WalRecordType::Delete { rid, xmax },
-                        );
-                        txn.set_last_lsn(lsn);
-                        page_guard.page_lsn = lsn;
-
-                        txn.add_undo_entry(UndoLogEntry::Delete {
-                            lsn,
-                            prev_lsn,
-                            rid,
-                            old_xmax: INVALID_TXN_ID,
-                        });
-                    }
-                }
+        for (rid, row) in targets {
+            delete_tuple(&self.bpm, rid, xmax, self.txn.as_deref_mut(), &self.wal_manager)?;
+            let target_len = self.rte_offsets.get(1).copied().unwrap_or(row.values.len());
+            delete_toast_chunks(
+                &self.bpm,
+                &row.values[..target_len],
+                xmax,
+                self.txn.as_deref_mut(),
+                &self.wal_manager,
+            )?;
+
+            if !self.returning.is_empty() {
+                self.returned.push_back(project_returning(
+                    &self.bpm,
+                    &self.returning,
+                    &row,
+                    &self.rte_offsets,
+                )?);
             }
-
-            drop(page_guard);
-            self.bpm.lock().unwrap().unpin_page(rid.page_id, true)?;
         }
 
+        if !self.returning.is_empty() {
+            return Ok(self.returned.pop_front());
+        }
         Ok(Some(Tuple::new(vec![Value::Int(self.deleted_count)])))
     }
 }
+
+/// Mark the tuple at `rid` deleted by `xmax`, WAL-logging it and remembering
+/// how to undo it.
+pub(super) fn delete_tuple(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    rid: Rid,
+    xmax: TxnId,
+    txn: Option<&mut Transaction>,
+    wal_manager: &Option<Arc<WalManager>>,
+) -> Result<()> {
+    let page_arc = bpm.lock().unwrap().fetch_page_mut(rid.page_id)?;
+    let mut page_guard = page_arc.write().unwrap();
+
+    page_guard.set_tuple_xmax(rid.slot_id, xmax)?;
+
+    if let (Some(txn), Some(wal_manager)) = (txn, wal_manager) {
+        if txn.is_active() {
+            let prev_lsn = txn.last_lsn;
+            let lsn = wal_manager.append(txn.id, prev_lsn, WalRecordType::Delete { rid, xmax });
+            txn.set_last_lsn(lsn);
+            page_guard.page_lsn = lsn;
+
+            txn.add_undo_entry(UndoLogEntry::Delete {
+                lsn,
+                prev_lsn,
+                rid,
+                old_xmax: INVALID_TXN_ID,
+            });
+        }
+    }
+
+    drop(page_guard);
+    bpm.lock().unwrap().unpin_page(rid.page_id, true)?;
+    Ok(())
+}
+
+/// Delete the chunks of a row's out-of-line values along with the row, so
+/// that VACUUM reclaims them together.
+pub(super) fn delete_toast_chunks(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    values: &[Value],
+    xmax: TxnId,
+    mut txn: Option<&mut Transaction>,
+    wal_manager: &Option<Arc<WalManager>>,
+) -> Result<()> {
+    for value in values {
+        for rid in toast::chunk_rids(bpm, value)? {
+            delete_tuple(bpm, rid, xmax, txn.as_deref_mut(), wal_manager)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::TestDb;
+    use crate::tuple::Value;
+
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE orders (id INT, user_id INT)");
+        db.query("CREATE TABLE users (id INT, banned BOOLEAN)");
+        db.query("INSERT INTO orders VALUES (1, 10)");
+        db.query("INSERT INTO orders VALUES (2, 20)");
+        db.query("INSERT INTO orders VALUES (3, 10)");
+        db.query("INSERT INTO users VALUES (10, true)");
+        db.query("INSERT INTO users VALUES (20, false)");
+        db
+    }
+
+    #[test]
+    fn test_delete_using() {
+        let db = setup();
+        let deleted = db.query(
+            "DELETE FROM orders USING users WHERE orders.user_id = users.id AND users.banned",
+        );
+        assert_eq!(deleted, vec![vec![Value::Int(2)]]);
+        assert_eq!(
+            db.query("SELECT id FROM orders"),
+            vec![vec![Value::Int(2)]]
+        );
+    }
+
+    #[test]
+    fn test_delete_using_deletes_each_target_row_once() {
+        let db = setup();
+        // Both orders of user 10 now join two users rows
+        db.query("INSERT INTO users VALUES (10, true)");
+        let mut deleted = db.query(
+            "DELETE FROM orders USING users \
+             WHERE orders.user_id = users.id AND users.banned RETURNING orders.id",
+        );
+        deleted.sort_by_key(|row| format!("{row:?}"));
+        assert_eq!(deleted, vec![vec![Value::Int(1)], vec![Value::Int(3)]]);
+        assert_eq!(db.query("SELECT id FROM orders").len(), 1);
+    }
+}
@@ -1,18 +1,26 @@
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
 
 use crate::analyzer::AnalyzedExpr;
+use crate::buffer_pool::BufferPoolManager;
 
 use super::{evaluate_predicate, evaluate_predicate_with_offsets, Executor, Tuple};
 
 /// Filter executor for WHERE clause (single table queries)
 pub struct FilterExecutor<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     child: Box<dyn Executor + 'a>,
     predicate: AnalyzedExpr,
 }
 
 impl<'a> FilterExecutor<'a> {
-    pub fn new(child: Box<dyn Executor + 'a>, predicate: AnalyzedExpr) -> Self {
-        FilterExecutor { child, predicate }
+    pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
+        child: Box<dyn Executor + 'a>,
+        predicate: AnalyzedExpr,
+    ) -> Self {
+        FilterExecutor { bpm, child, predicate }
     }
 }
 
@@ -23,7 +31,7 @@ impl Executor for FilterExecutor<'_> {
 
     fn next(&mut self) -> Result<Option<Tuple>> {
         while let Some(tuple) = self.child.next()? {
-            if evaluate_predicate(&self.predicate, &tuple)? {
+            if evaluate_predicate(&self.bpm, &self.predicate, &tuple)? {
                 return Ok(Some(tuple));
             }
         }
@@ -33,6 +41,7 @@ impl Executor for FilterExecutor<'_> {
 
 /// Filter executor with RTE offsets (for JOIN queries)
 pub struct FilterWithOffsetsExecutor<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     child: Box<dyn Executor + 'a>,
     predicate: AnalyzedExpr,
     rte_offsets: Vec<usize>,
@@ -40,11 +49,13 @@ pub struct FilterWithOffsetsExecutor<'a> {
 
 impl<'a> FilterWithOffsetsExecutor<'a> {
     pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
         child: Box<dyn Executor + 'a>,
         predicate: AnalyzedExpr,
         rte_offsets: Vec<usize>,
     ) -> Self {
         FilterWithOffsetsExecutor {
+            bpm,
             child,
             predicate,
             rte_offsets,
@@ -59,7 +70,13 @@ impl Executor for FilterWithOffsetsExecutor<'_> {
 
     fn next(&mut self) -> Result<Option<Tuple>> {
         while let Some(tuple) = self.child.next()? {
-            if evaluate_predicate_with_offsets(&self.predicate, &tuple, &self.rte_offsets)? {
+            let matches = evaluate_predicate_with_offsets(
+                &self.bpm,
+                &self.predicate,
+                &tuple,
+                &self.rte_offsets,
+            )?;
+            if matches {
                 return Ok(Some(tuple));
             }
         }
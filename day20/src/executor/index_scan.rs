@@ -4,6 +4,7 @@ use anyhow::Result;
 
 use crate::btree::{BTree, IndexKey};
 use crate::buffer_pool::BufferPoolManager;
+use crate::cancel::check_for_interrupts;
 use crate::catalog::{Catalog, IndexDef};
 use crate::transaction_manager::TransactionManager;
 use crate::tuple::deserialize_tuple_mvcc;
@@ -26,6 +27,7 @@ pub struct IndexScanExecutor<'a> {
 }
 
 impl<'a> IndexScanExecutor<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bpm: Arc<Mutex<BufferPoolManager>>,
         catalog: &'a Catalog,
@@ -63,12 +65,7 @@ impl Executor for IndexScanExecutor<'_> {
             .get_table_by_id(self.table_id)
             .ok_or_else(|| anyhow::anyhow!("table not found"))?;
 
-        let key_schema = self
-            .index_def
-            .column_ids
-            .iter()
-            .map(|&col_idx| table.columns[col_idx].data_type.clone())
-            .collect();
+        let key_schema = self.index_def.key_schema(&table);
 
         // Create B-Tree and perform range scan
         let mut btree = BTree::new(Arc::clone(&self.bpm), key_schema);
@@ -97,6 +94,7 @@ impl Executor for IndexScanExecutor<'_> {
         let schema = table.to_schema();
 
         while self.current_idx < self.rids.len() {
+            check_for_interrupts()?;
             let rid = self.rids[self.current_idx];
             self.current_idx += 1;
 
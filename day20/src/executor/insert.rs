@@ -1,27 +1,42 @@
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
-use crate::analyzer::{AnalyzedExpr, AnalyzedInsertStatement};
+use crate::analyzer::{
+    AnalyzedAssignment, AnalyzedExpr, AnalyzedInsertStatement, AnalyzedOnConflict,
+    AnalyzedOnConflictAction, AnalyzedSelectItem,
+};
 use crate::btree::{BTree, IndexKey};
 use crate::buffer_pool::BufferPoolManager;
-use crate::catalog::Catalog;
+use crate::catalog::{Catalog, IndexColumn, IndexDef, TableDef};
 use crate::lock_manager::{LockManager, LockMode};
 use crate::page::NO_NEXT_PAGE;
+use crate::toast;
 use crate::transaction::{Transaction, UndoLogEntry};
-use crate::tuple::{serialize_tuple_mvcc, TxnId, Value, INVALID_TXN_ID};
+use crate::transaction_manager::{TransactionManager, TxnStatus};
+use crate::tuple::{
+    coerce_value, deserialize_tuple_mvcc, get_tuple_xmax, get_tuple_xmin, serialize_tuple_mvcc,
+    DataType, TxnId, Value, INVALID_TXN_ID,
+};
 use crate::wal::{WalManager, WalRecordType};
 
-use super::{literal_to_value, Executor, Rid, Tuple};
+use super::update::update_tuple;
+use super::{
+    evaluate_expr, evaluate_expr_with_offsets, evaluate_predicate_with_offsets, project_returning,
+    Executor, Rid, Tuple,
+};
 
 pub struct InsertExecutor<'a> {
     bpm: Arc<Mutex<BufferPoolManager>>,
     catalog: &'a Catalog,
     table_id: u32,
     values: Vec<Value>,
+    on_conflict: Option<AnalyzedOnConflict>,
+    returning: Vec<AnalyzedSelectItem>,
     txn: Option<&'a mut Transaction>,
     lock_manager: Option<&'a LockManager>,
     wal_manager: Option<Arc<WalManager>>,
+    txn_manager: Option<&'a TransactionManager>,
     executed: bool,
 }
 
@@ -33,13 +48,18 @@ impl<'a> InsertExecutor<'a> {
         txn: Option<&'a mut Transaction>,
         lock_manager: Option<&'a LockManager>,
         wal_manager: Option<Arc<WalManager>>,
+        txn_manager: Option<&'a TransactionManager>,
     ) -> Result<Self> {
+        let table = catalog
+            .get_table_by_id(stmt.table_id)
+            .ok_or_else(|| anyhow::anyhow!("table not found: {}", stmt.table_id))?;
+        // VALUES are constant expressions, converted to the column types
         let values: Vec<Value> = stmt
             .values
             .iter()
-            .map(|expr| match expr {
-                AnalyzedExpr::Literal(lit) => Ok(literal_to_value(lit)),
-                _ => anyhow::bail!("only literals supported in INSERT"),
+            .zip(&table.columns)
+            .map(|(expr, column)| {
+                coerce_value(evaluate_expr(&bpm, expr, &Tuple::new(vec![]))?, &column.data_type)
             })
             .collect::<Result<Vec<_>>>()?;
 
@@ -48,12 +68,116 @@ impl<'a> InsertExecutor<'a> {
             catalog,
             table_id: stmt.table_id,
             values,
+            on_conflict: stmt.on_conflict.clone(),
+            returning: stmt.returning.clone(),
             txn,
             lock_manager,
             wal_manager,
+            txn_manager,
             executed: false,
         })
     }
+
+    fn current_txn_id(&self) -> TxnId {
+        self.txn
+            .as_ref()
+            .filter(|t| t.is_active())
+            .map(|t| t.id)
+            .unwrap_or(INVALID_TXN_ID)
+    }
+
+    /// Apply DO UPDATE to the conflicting row.
+    fn upsert(
+        &mut self,
+        table: &TableDef,
+        rid: Rid,
+        assignments: &[AnalyzedAssignment],
+        where_clause: &Option<AnalyzedExpr>,
+    ) -> Result<UpsertOutcome> {
+        let txn_id = self.current_txn_id();
+        let (Some(txn), Some(lock_manager)) = (self.txn.as_deref_mut(), self.lock_manager) else {
+            bail!("ON CONFLICT DO UPDATE requires a transaction");
+        };
+        lock_manager.lock(txn_id, rid, LockMode::Exclusive)?;
+        txn.add_lock(rid);
+
+        // The row may have been deleted or updated by the transaction we waited for
+        let Some(data) = fetch_tuple(&self.bpm, rid)? else {
+            return Ok(UpsertOutcome::Retry);
+        };
+        if classify_row(&data, txn_id, self.txn_manager) != RowState::Live {
+            return Ok(UpsertOutcome::Retry);
+        }
+        let (_, _, existing) = deserialize_tuple_mvcc(&data, &table.to_schema())?;
+
+        // Expressions see the existing row followed by the EXCLUDED row
+        let rte_offsets = [0, existing.len()];
+        let mut combined = existing.clone();
+        combined.extend(self.values.iter().cloned());
+        let combined = Tuple::new(combined);
+
+        if let Some(pred) = where_clause {
+            if !evaluate_predicate_with_offsets(&self.bpm, pred, &combined, &rte_offsets)? {
+                return Ok(UpsertOutcome::Skipped);
+            }
+        }
+
+        let mut new_values = existing;
+        for AnalyzedAssignment {
+            column_index,
+            value,
+        } in assignments
+        {
+            let new_value = evaluate_expr_with_offsets(&self.bpm, value, &combined, &rte_offsets)?;
+            new_values[*column_index] =
+                coerce_value(new_value, &table.columns[*column_index].data_type)?;
+        }
+
+        update_tuple(
+            &self.bpm,
+            self.catalog,
+            self.table_id,
+            rid,
+            &new_values,
+            self.txn.as_deref_mut(),
+            self.lock_manager,
+            &self.wal_manager,
+            self.txn_manager,
+        )?;
+        Ok(UpsertOutcome::Updated(new_values))
+    }
+
+    // Result row: the affected row count, or the RETURNING list evaluated against `values`
+    fn output_row(&self, values: Vec<Value>) -> Result<Tuple> {
+        if self.returning.is_empty() {
+            Ok(Tuple::new(vec![Value::Int(1)]))
+        } else {
+            project_returning(&self.bpm, &self.returning, &Tuple::new(values), &[0])
+        }
+    }
+
+    /// Write the row to the heap, log it and lock it. Index entries are added by the caller.
+    fn insert_row(&mut self, table: &TableDef) -> Result<Rid> {
+        let rid = write_row(
+            &self.bpm,
+            table,
+            &self.values,
+            self.txn.as_deref_mut(),
+            &self.wal_manager,
+        )?;
+
+        if let Some(ref mut txn) = self.txn {
+            if txn.is_active() {
+                // Nobody else can see the new row yet, so this never waits
+                if let Some(lock_manager) = self.lock_manager {
+                    lock_manager.lock(txn.id, rid, LockMode::Exclusive)?;
+                    txn.add_lock(rid);
+                }
+            }
+        }
+
+        Ok(rid)
+    }
 }
 
 impl Executor for InsertExecutor<'_> {
@@ -73,121 +197,497 @@ impl Executor for InsertExecutor<'_> {
             .get_table_by_id(self.table_id)
             .ok_or_else(|| anyhow::anyhow!("table not found"))?;
 
-        let xmin: TxnId = self.txn.as_ref().map(|t| t.id).unwrap_or(INVALID_TXN_ID);
-        let xmax: TxnId = INVALID_TXN_ID;
-
-        let tuple_data = serialize_tuple_mvcc(xmin, xmax, &self.values);
-
-        let rid = {
-            let mut current_page_id = table.first_page_id;
-            let mut last_page_id = current_page_id;
-            let mut result_rid: Option<Rid> = None;
-
-            while current_page_id != NO_NEXT_PAGE {
-                let page_arc = self.bpm.lock().unwrap().fetch_page_mut(current_page_id)?;
-                let mut page_guard = page_arc.write().unwrap();
-
-                if let Ok(slot_id) = page_guard.insert(&tuple_data) {
-                    drop(page_guard);
-                    self.bpm
-                        .lock()
-                        .unwrap()
-                        .unpin_page(current_page_id, true)?;
-                    result_rid = Some(Rid {
-                        page_id: current_page_id,
-                        slot_id,
-                    });
-                    break;
-                }
+        let indexes = self.catalog.get_indexes_for_table(self.table_id);
+        let unique_indexes: Vec<&IndexDef> = indexes.iter().filter(|i| i.is_unique).collect();
+        let arbiters: Vec<&IndexDef> = match &self.on_conflict {
+            Some(on_conflict) if !on_conflict.arbiter_index_ids.is_empty() => unique_indexes
+                .iter()
+                .copied()
+                .filter(|i| on_conflict.arbiter_index_ids.contains(&i.index_id))
+                .collect(),
+            Some(_) => unique_indexes.clone(),
+            None => Vec::new(),
+        };
+        let txn_id = self.current_txn_id();
+        let keys = unique_keys(&self.bpm, &table, &unique_indexes, &self.values)?;
 
-                last_page_id = current_page_id;
-                current_page_id = page_guard.next_page_id();
-                drop(page_guard);
-                self.bpm.lock().unwrap().unpin_page(last_page_id, false)?;
-            }
+        loop {
+            let guard = match self.lock_manager {
+                Some(lock_manager) if !keys.is_empty() => {
+                    Some(lock_manager.lock_unique_insert(&keys)?)
+                }
+                _ => None,
+            };
 
-            match result_rid {
-                Some(rid) => rid,
-                None => {
-                    let (new_page_id, new_page_arc) = self.bpm.lock().unwrap().new_page()?;
-                    let mut new_page = new_page_arc.write().unwrap();
-                    new_page.set_next_page_id(NO_NEXT_PAGE);
-                    let slot_id = new_page.insert(&tuple_data)?;
-                    drop(new_page);
-                    self.bpm.lock().unwrap().unpin_page(new_page_id, true)?;
-
-                    let page_arc = self.bpm.lock().unwrap().fetch_page_mut(last_page_id)?;
-                    let mut page = page_arc.write().unwrap();
-                    page.set_next_page_id(new_page_id);
-                    drop(page);
-                    self.bpm.lock().unwrap().unpin_page(last_page_id, true)?;
-
-                    Rid {
-                        page_id: new_page_id,
-                        slot_id,
+            if let Some(on_conflict) = self.on_conflict.clone() {
+                match probe_unique_indexes(
+                    &self.bpm,
+                    &table,
+                    &arbiters,
+                    &self.values,
+                    None,
+                    txn_id,
+                    self.txn_manager,
+                )? {
+                    UniqueProbe::Clear => {}
+                    UniqueProbe::InProgress(rid) => {
+                        drop(guard);
+                        wait_for_row(self.lock_manager, self.txn.as_deref_mut(), rid)?;
+                        continue;
+                    }
+                    UniqueProbe::Conflict { rid, .. } => {
+                        drop(guard);
+                        match &on_conflict.action {
+                            AnalyzedOnConflictAction::DoNothing => return Ok(None),
+                            AnalyzedOnConflictAction::DoUpdate {
+                                assignments,
+                                where_clause,
+                            } => match self.upsert(&table, rid, assignments, where_clause)? {
+                                UpsertOutcome::Updated(values) => {
+                                    return Ok(Some(self.output_row(values)?))
+                                }
+                                UpsertOutcome::Skipped => return Ok(None),
+                                UpsertOutcome::Retry => continue,
+                            },
+                        }
                     }
                 }
             }
-        };
 
-        if let Some(ref mut txn) = self.txn {
-            if txn.is_active() {
-                if let Some(ref wal_manager) = self.wal_manager {
-                    let prev_lsn = txn.last_lsn;
-                    let lsn = wal_manager.append(
-                        txn.id,
-                        prev_lsn,
-                        WalRecordType::Insert {
-                            rid,
-                            data: tuple_data.clone(),
-                        },
-                    );
-                    txn.set_last_lsn(lsn);
-
-                    let page_arc = self.bpm.lock().unwrap().fetch_page_mut(rid.page_id)?;
-                    let mut page_guard = page_arc.write().unwrap();
-                    page_guard.page_lsn = lsn;
-                    drop(page_guard);
-                    self.bpm.lock().unwrap().unpin_page(rid.page_id, true)?;
-
-                    txn.add_undo_entry(UndoLogEntry::Insert {
-                        lsn,
-                        prev_lsn,
-                        rid,
-                        data: tuple_data,
-                    });
+            match probe_unique_indexes(
+                &self.bpm,
+                &table,
+                &unique_indexes,
+                &self.values,
+                None,
+                txn_id,
+                self.txn_manager,
+            )? {
+                UniqueProbe::Clear => {}
+                UniqueProbe::InProgress(rid) => {
+                    drop(guard);
+                    wait_for_row(self.lock_manager, self.txn.as_deref_mut(), rid)?;
+                    continue;
                 }
+                UniqueProbe::Conflict { index_name, .. } => {
+                    bail!("duplicate key value violates unique constraint \"{index_name}\"");
+                }
+            }
 
-                if let Some(lock_manager) = self.lock_manager {
-                    lock_manager
-                        .lock(txn.id, rid, LockMode::Exclusive)
-                        .map_err(|e| anyhow::anyhow!("{e}"))?;
-                    txn.add_lock(rid);
+            let rid = self.insert_row(&table)?;
+            insert_index_entries(&self.bpm, &table, &indexes, &self.values, rid)?;
+            drop(guard);
+
+            return Ok(Some(self.output_row(self.values.clone())?));
+        }
+    }
+}
+
+enum UpsertOutcome {
+    Updated(Vec<Value>),
+    /// The DO UPDATE ... WHERE clause rejected the existing row
+    Skipped,
+    /// The conflicting row went away while we waited for its lock
+    Retry,
+}
+
+/// Outcome of checking a row against the unique indexes of its table.
+pub(super) enum UniqueProbe {
+    Clear,
+    Conflict { index_name: String, rid: Rid },
+    /// A transaction that has not finished yet inserted or deleted a row with the same key
+    InProgress(Rid),
+}
+
+#[derive(Debug, PartialEq)]
+enum RowState {
+    Dead,
+    Live,
+    InProgress,
+}
+
+/// Keys of `values` in each of `indexes`, for LockManager::lock_unique_insert.
+/// Keys containing NULL never conflict and are left out.
+pub(super) fn unique_keys(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    table: &TableDef,
+    indexes: &[&IndexDef],
+    values: &[Value],
+) -> Result<Vec<(u32, IndexKey)>> {
+    let mut keys = Vec::with_capacity(indexes.len());
+    for index_def in indexes {
+        let key = index_key(bpm, table, &index_def.columns, values)?;
+        if !key.0.contains(&Value::Null) {
+            keys.push((index_def.index_id, key));
+        }
+    }
+    Ok(keys)
+}
+
+/// Look for live rows that would collide with `values` on any of `indexes`.
+/// Keys containing NULL never conflict. `exclude` skips the row being updated.
+pub(super) fn probe_unique_indexes(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    table: &TableDef,
+    indexes: &[&IndexDef],
+    values: &[Value],
+    exclude: Option<Rid>,
+    txn_id: TxnId,
+    txn_manager: Option<&TransactionManager>,
+) -> Result<UniqueProbe> {
+    let mut in_progress = None;
+
+    for index_def in indexes {
+        let key = index_key(bpm, table, &index_def.columns, values)?;
+        if key.0.contains(&Value::Null) {
+            continue;
+        }
+
+        let btree = open_index(bpm, table, index_def);
+        for rid in btree.search_all(&key)? {
+            if Some(rid) == exclude {
+                continue;
+            }
+            let Some(data) = fetch_tuple(bpm, rid)? else {
+                continue;
+            };
+            match classify_row(&data, txn_id, txn_manager) {
+                RowState::Dead => {}
+                RowState::Live => {
+                    return Ok(UniqueProbe::Conflict {
+                        index_name: index_def.index_name.clone(),
+                        rid,
+                    })
                 }
+                RowState::InProgress => in_progress = Some(rid),
             }
         }
+    }
 
-        // Maintain indexes
-        let indexes = self.catalog.get_indexes_for_table(self.table_id);
-        for index_def in indexes {
-            let key_schema: Vec<_> = index_def
-                .column_ids
-                .iter()
-                .map(|&col_idx| table.columns[col_idx].data_type.clone())
-                .collect();
+    Ok(match in_progress {
+        Some(rid) => UniqueProbe::InProgress(rid),
+        None => UniqueProbe::Clear,
+    })
+}
 
-            let mut btree = BTree::new(Arc::clone(&self.bpm), key_schema);
-            btree.set_meta_page_id(index_def.meta_page_id);
+/// Block until the transaction holding `rid` finishes, without keeping the lock afterwards.
+pub(super) fn wait_for_row(
+    lock_manager: Option<&LockManager>,
+    txn: Option<&mut Transaction>,
+    rid: Rid,
+) -> Result<()> {
+    let (Some(lock_manager), Some(txn)) = (lock_manager, txn) else {
+        bail!("could not wait for concurrent transaction");
+    };
+    if txn.held_locks.contains(&rid) {
+        return Ok(());
+    }
+    lock_manager.lock(txn.id, rid, LockMode::Shared)?;
+    lock_manager.unlock_all(txn.id, &[rid].into_iter().collect());
+    Ok(())
+}
 
-            let key_values: Vec<Value> = index_def
-                .column_ids
-                .iter()
-                .map(|&col_idx| self.values[col_idx].clone())
-                .collect();
-            let key = IndexKey::new(key_values);
-            btree.insert(&key, rid)?;
+/// Add entries for a freshly written row to every index of the table.
+pub(super) fn insert_index_entries(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    table: &TableDef,
+    indexes: &[IndexDef],
+    values: &[Value],
+    rid: Rid,
+) -> Result<()> {
+    for index_def in indexes {
+        let mut btree = open_index(bpm, table, index_def);
+        btree.insert(&index_key(bpm, table, &index_def.columns, values)?, rid)?;
+    }
+    Ok(())
+}
+
+/// Store a new row version: toast its large values, then write it to the
+/// table's heap, WAL-logging the row and its chunks.
+pub(super) fn write_row(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    table: &TableDef,
+    values: &[Value],
+    mut txn: Option<&mut Transaction>,
+    wal_manager: &Option<Arc<WalManager>>,
+) -> Result<Rid> {
+    let xmin: TxnId = txn.as_ref().map(|t| t.id).unwrap_or(INVALID_TXN_ID);
+    let xmax: TxnId = INVALID_TXN_ID;
+
+    let types: Vec<DataType> = table.columns.iter().map(|c| c.data_type.clone()).collect();
+    let row = toast::toast_row(bpm, values, &types, |chunk| {
+        let chunk_data = serialize_tuple_mvcc(xmin, xmax, chunk);
+        let rid = insert_into_heap(bpm, table.toast_page_id, &chunk_data)?;
+        log_insert(bpm, txn.as_deref_mut(), wal_manager, rid, chunk_data)?;
+        Ok(rid)
+    })?;
+
+    let tuple_data = serialize_tuple_mvcc(xmin, xmax, &row);
+    let rid = insert_into_heap(bpm, table.first_page_id, &tuple_data)?;
+    log_insert(bpm, txn, wal_manager, rid, tuple_data)?;
+    Ok(rid)
+}
+
+/// Put a tuple on the first page of a heap chain with room for it, adding a
+/// page at the end of the chain if none has.
+pub(super) fn insert_into_heap(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    first_page_id: u32,
+    tuple_data: &[u8],
+) -> Result<Rid> {
+    let mut current_page_id = first_page_id;
+    let mut last_page_id = current_page_id;
+
+    while current_page_id != NO_NEXT_PAGE {
+        let page_arc = bpm.lock().unwrap().fetch_page_mut(current_page_id)?;
+        let mut page_guard = page_arc.write().unwrap();
+
+        if let Ok(slot_id) = page_guard.insert(tuple_data) {
+            drop(page_guard);
+            bpm.lock().unwrap().unpin_page(current_page_id, true)?;
+            return Ok(Rid {
+                page_id: current_page_id,
+                slot_id,
+            });
         }
 
-        Ok(Some(Tuple::new(vec![Value::Int(1)])))
+        last_page_id = current_page_id;
+        current_page_id = page_guard.next_page_id();
+        drop(page_guard);
+        bpm.lock().unwrap().unpin_page(last_page_id, false)?;
+    }
+
+    let (new_page_id, new_page_arc) = bpm.lock().unwrap().new_page()?;
+    let mut new_page = new_page_arc.write().unwrap();
+    new_page.set_next_page_id(NO_NEXT_PAGE);
+    let slot_id = new_page.insert(tuple_data)?;
+    drop(new_page);
+    bpm.lock().unwrap().unpin_page(new_page_id, true)?;
+
+    let page_arc = bpm.lock().unwrap().fetch_page_mut(last_page_id)?;
+    let mut page = page_arc.write().unwrap();
+    page.set_next_page_id(new_page_id);
+    drop(page);
+    bpm.lock().unwrap().unpin_page(last_page_id, true)?;
+
+    Ok(Rid {
+        page_id: new_page_id,
+        slot_id,
+    })
+}
+
+/// WAL-log a freshly inserted tuple and remember how to undo it
+pub(super) fn log_insert(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    txn: Option<&mut Transaction>,
+    wal_manager: &Option<Arc<WalManager>>,
+    rid: Rid,
+    tuple_data: Vec<u8>,
+) -> Result<()> {
+    let (Some(txn), Some(wal_manager)) = (txn, wal_manager) else {
+        return Ok(());
+    };
+    if !txn.is_active() {
+        return Ok(());
+    }
+
+    let prev_lsn = txn.last_lsn;
+    let lsn = wal_manager.append(
+        txn.id,
+        prev_lsn,
+        WalRecordType::Insert {
+            rid,
+            data: tuple_data.clone(),
+        },
+    );
+    txn.set_last_lsn(lsn);
+
+    let page_arc = bpm.lock().unwrap().fetch_page_mut(rid.page_id)?;
+    let mut page_guard = page_arc.write().unwrap();
+    page_guard.page_lsn = lsn;
+    drop(page_guard);
+    bpm.lock().unwrap().unpin_page(rid.page_id, true)?;
+
+    txn.add_undo_entry(UndoLogEntry::Insert {
+        lsn,
+        prev_lsn,
+        rid,
+        data: tuple_data,
+    });
+    Ok(())
+}
+
+/// Index key of a table row, evaluating any expression keys. String keys are
+/// stored in the sort-key form of their collation, so the tree orders them
+/// by it.
+pub(super) fn index_key(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    table: &TableDef,
+    columns: &[IndexColumn],
+    values: &[Value],
+) -> Result<IndexKey> {
+    let row = Tuple::new(values.to_vec());
+    let key_values = columns
+        .iter()
+        .map(|column| {
+            let value = match column {
+                IndexColumn::Column(col_idx) => toast::detoast(bpm, &values[*col_idx])?,
+                IndexColumn::Expression(expr) => evaluate_expr(bpm, expr, &row)?,
+            };
+            Ok(column.collation(table).sort_key(value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(IndexKey::new(key_values))
+}
+
+fn open_index(bpm: &Arc<Mutex<BufferPoolManager>>, table: &TableDef, index_def: &IndexDef) -> BTree {
+    let mut btree = BTree::new(Arc::clone(bpm), index_def.key_schema(table));
+    btree.set_meta_page_id(index_def.meta_page_id);
+    btree
+}
+
+pub(super) fn fetch_tuple(bpm: &Arc<Mutex<BufferPoolManager>>, rid: Rid) -> Result<Option<Vec<u8>>> {
+    let page_arc = bpm.lock().unwrap().fetch_page(rid.page_id)?;
+    let page_guard = page_arc.read().unwrap();
+    let data = page_guard.get_tuple(rid.slot_id).map(|d| d.to_vec());
+    drop(page_guard);
+    bpm.lock().unwrap().unpin_page(rid.page_id, false)?;
+    Ok(data)
+}
+
+/// Decide whether a row version counts for uniqueness, ignoring snapshots:
+/// a committed row conflicts even if our snapshot cannot see it.
+fn classify_row(
+    data: &[u8],
+    txn_id: TxnId,
+    txn_manager: Option<&TransactionManager>,
+) -> RowState {
+    let status = |id: TxnId| match txn_manager {
+        Some(tm) => tm.get_txn_status(id),
+        None => TxnStatus::Committed,
+    };
+
+    let xmin = get_tuple_xmin(data);
+    if xmin != INVALID_TXN_ID && xmin != txn_id {
+        match status(xmin) {
+            TxnStatus::Aborted => return RowState::Dead,
+            TxnStatus::InProgress => return RowState::InProgress,
+            TxnStatus::Committed => {}
+        }
+    }
+
+    let xmax = get_tuple_xmax(data);
+    if xmax == INVALID_TXN_ID {
+        return RowState::Live;
+    }
+    if xmax == txn_id {
+        return RowState::Dead;
+    }
+    match status(xmax) {
+        TxnStatus::Committed => RowState::Dead,
+        TxnStatus::Aborted => RowState::Live,
+        TxnStatus::InProgress => RowState::InProgress,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::super::testing::TestDb;
+    use super::*;
+
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (id INT, v INT)");
+        db.query("CREATE UNIQUE INDEX t_id ON t (id)");
+        db.query("INSERT INTO t VALUES (1, 10)");
+        db
+    }
+
+    fn rows(db: &TestDb) -> Vec<Vec<Value>> {
+        db.query("SELECT id, v FROM t")
+    }
+
+    #[test]
+    fn test_on_conflict_do_nothing() {
+        let db = setup();
+        let inserted = db.query("INSERT INTO t VALUES (1, 20) ON CONFLICT DO NOTHING");
+        assert!(inserted.is_empty());
+        let inserted = db.query("INSERT INTO t VALUES (2, 20) ON CONFLICT (id) DO NOTHING");
+        assert_eq!(inserted.len(), 1);
+        assert_eq!(
+            rows(&db),
+            vec![
+                vec![Value::Int(1), Value::Int(10)],
+                vec![Value::Int(2), Value::Int(20)]
+            ]
+        );
+
+        let err = db.run("INSERT INTO t VALUES (1, 30)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "duplicate key value violates unique constraint \"t_id\""
+        );
+    }
+
+    #[test]
+    fn test_on_conflict_do_update_with_excluded() {
+        let db = setup();
+        let returned = db.query(
+            "INSERT INTO t VALUES (1, 5) ON CONFLICT (id) \
+             DO UPDATE SET v = t.v + excluded.v RETURNING id, v",
+        );
+        assert_eq!(returned, vec![vec![Value::Int(1), Value::Int(15)]]);
+        assert_eq!(rows(&db), vec![vec![Value::Int(1), Value::Int(15)]]);
+    }
+
+    #[test]
+    fn test_on_conflict_do_update_where_skips() {
+        let db = setup();
+        let sql = "INSERT INTO t VALUES (1, 7) ON CONFLICT (id) \
+                   DO UPDATE SET v = excluded.v WHERE t.v < excluded.v";
+        assert!(db.query(sql).is_empty());
+        assert_eq!(rows(&db), vec![vec![Value::Int(1), Value::Int(10)]]);
+
+        let sql = "INSERT INTO t VALUES (1, 70) ON CONFLICT (id) \
+                   DO UPDATE SET v = excluded.v WHERE t.v < excluded.v";
+        assert_eq!(db.query(sql).len(), 1);
+        assert_eq!(rows(&db), vec![vec![Value::Int(1), Value::Int(70)]]);
+    }
+
+    #[test]
+    fn test_on_conflict_retries_after_concurrent_delete() {
+        let db = setup();
+        let table = db.catalog.get_table("t").unwrap();
+        let rid = Rid {
+            page_id: table.first_page_id,
+            slot_id: 0,
+        };
+
+        // Another transaction holds the conflicting row, so the upsert
+        // finds it live and then waits for its lock
+        let mut other = db.begin();
+        db.lock_manager.lock(other.id, rid, LockMode::Exclusive).unwrap();
+        other.add_lock(rid);
+
+        thread::scope(|s| {
+            let upsert = s.spawn(|| {
+                db.run("INSERT INTO t VALUES (1, 99) ON CONFLICT (id) DO UPDATE SET v = 0")
+            });
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !db.lock_manager.has_waiters(rid) {
+                assert!(Instant::now() < deadline, "upsert never waited for the row");
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            // The row is gone once the lock is granted: the upsert starts
+            // over and inserts instead
+            db.execute(&mut other, "DELETE FROM t WHERE id = 1").unwrap();
+            db.commit(&mut other);
+            assert_eq!(upsert.join().unwrap().unwrap().len(), 1);
+        });
+        assert_eq!(rows(&db), vec![vec![Value::Int(1), Value::Int(99)]]);
     }
 }
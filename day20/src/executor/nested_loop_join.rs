@@ -1,7 +1,11 @@
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
 
 use crate::analyzer::AnalyzedExpr;
 use crate::ast::JoinType;
+use crate::buffer_pool::BufferPoolManager;
+use crate::cancel::check_for_interrupts;
 use crate::tuple::Value;
 
 use super::{evaluate_predicate_with_offsets, Executor, Tuple};
@@ -24,6 +28,7 @@ use super::{evaluate_predicate_with_offsets, Executor, Tuple};
 /// - If a left row has no matching right rows, emit it with NULLs for right columns
 /// - Uses `left_matched` flag to track if current left row found any match
 pub struct NestedLoopJoinExecutor<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     left: Box<dyn Executor + 'a>,
     right: Box<dyn Executor + 'a>,
     condition: AnalyzedExpr,
@@ -41,6 +46,7 @@ pub struct NestedLoopJoinExecutor<'a> {
 
 impl<'a> NestedLoopJoinExecutor<'a> {
     pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
         left: Box<dyn Executor + 'a>,
         right: Box<dyn Executor + 'a>,
         condition: AnalyzedExpr,
@@ -49,6 +55,7 @@ impl<'a> NestedLoopJoinExecutor<'a> {
         right_col_count: usize,
     ) -> Self {
         NestedLoopJoinExecutor {
+            bpm,
             left,
             right,
             condition,
@@ -60,11 +67,15 @@ impl<'a> NestedLoopJoinExecutor<'a> {
         }
     }
 
-    /// Combine left and right tuples into a single tuple
+    /// Combine left and right tuples into a single tuple.
+    /// The left RID is kept so UPDATE ... FROM / DELETE ... USING can find the target row.
     fn combine_tuples(&self, left: &Tuple, right: &Tuple) -> Tuple {
         let mut values = left.values.clone();
         values.extend(right.values.clone());
-        Tuple::new(values)
+        Tuple {
+            values,
+            rid: left.rid,
+        }
     }
 
     /// Create a tuple with all NULL values for right columns
@@ -83,14 +94,18 @@ impl Executor for NestedLoopJoinExecutor<'_> {
 
     fn next(&mut self) -> Result<Option<Tuple>> {
         loop {
+            // Every pair is checked here, even when the scans below are cheap
+            check_for_interrupts()?;
+
             // Get next left tuple if needed
             if self.current_left.is_none() {
                 match self.left.next()? {
                     Some(left_tuple) => {
-                        self.current_left = Some(left_tuple);
                         self.left_matched = false;
-                        // Re-scan right executor for new left tuple
-                        self.right.open()?;
+                        // Re-scan right executor for new left tuple, which
+                        // it may refer to (unnest(t.tags))
+                        self.right.rescan(&left_tuple)?;
+                        self.current_left = Some(left_tuple);
                     }
                     None => {
                         return Ok(None);
@@ -107,6 +122,7 @@ impl Executor for NestedLoopJoinExecutor<'_> {
 
                     // Check join condition
                     if evaluate_predicate_with_offsets(
+                        &self.bpm,
                         &self.condition,
                         &combined,
                         &self.rte_offsets,
@@ -1,19 +1,27 @@
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
 
 use crate::analyzer::AnalyzedExpr;
+use crate::buffer_pool::BufferPoolManager;
 use crate::tuple::Value;
 
 use super::{evaluate_expr, evaluate_expr_with_offsets, Executor, Tuple};
 
 /// Projection executor for SELECT expressions (single table queries)
 pub struct ProjectionExecutor<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     child: Box<dyn Executor + 'a>,
     exprs: Vec<AnalyzedExpr>,
 }
 
 impl<'a> ProjectionExecutor<'a> {
-    pub fn new(child: Box<dyn Executor + 'a>, exprs: Vec<AnalyzedExpr>) -> Self {
-        ProjectionExecutor { child, exprs }
+    pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
+        child: Box<dyn Executor + 'a>,
+        exprs: Vec<AnalyzedExpr>,
+    ) -> Self {
+        ProjectionExecutor { bpm, child, exprs }
     }
 }
 
@@ -27,7 +35,7 @@ impl Executor for ProjectionExecutor<'_> {
             let projected: Vec<Value> = self
                 .exprs
                 .iter()
-                .map(|expr| evaluate_expr(expr, &tuple))
+                .map(|expr| evaluate_expr(&self.bpm, expr, &tuple))
                 .collect::<Result<Vec<_>>>()?;
             return Ok(Some(Tuple::new(projected)));
         }
@@ -37,6 +45,7 @@ impl Executor for ProjectionExecutor<'_> {
 
 /// Projection executor with RTE offsets (for JOIN queries)
 pub struct ProjectionWithOffsetsExecutor<'a> {
+    bpm: Arc<Mutex<BufferPoolManager>>,
     child: Box<dyn Executor + 'a>,
     exprs: Vec<AnalyzedExpr>,
     rte_offsets: Vec<usize>,
@@ -44,11 +53,13 @@ pub struct ProjectionWithOffsetsExecutor<'a> {
 
 impl<'a> ProjectionWithOffsetsExecutor<'a> {
     pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
         child: Box<dyn Executor + 'a>,
         exprs: Vec<AnalyzedExpr>,
         rte_offsets: Vec<usize>,
     ) -> Self {
         ProjectionWithOffsetsExecutor {
+            bpm,
             child,
             exprs,
             rte_offsets,
@@ -66,7 +77,9 @@ impl Executor for ProjectionWithOffsetsExecutor<'_> {
             let projected: Vec<Value> = self
                 .exprs
                 .iter()
-                .map(|expr| evaluate_expr_with_offsets(expr, &tuple, &self.rte_offsets))
+                .map(|expr| {
+                    evaluate_expr_with_offsets(&self.bpm, expr, &tuple, &self.rte_offsets)
+                })
                 .collect::<Result<Vec<_>>>()?;
             return Ok(Some(Tuple::new(projected)));
         }
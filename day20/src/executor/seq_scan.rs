@@ -3,6 +3,7 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 
 use crate::buffer_pool::BufferPoolManager;
+use crate::cancel::check_for_interrupts;
 use crate::catalog::Catalog;
 use crate::lock_manager::LockManager;
 use crate::page::NO_NEXT_PAGE;
@@ -70,6 +71,7 @@ impl Executor for SeqScanExecutor<'_> {
         let schema = table.to_schema();
 
         loop {
+            check_for_interrupts()?;
             if self.current_page_id == NO_NEXT_PAGE {
                 return Ok(None);
             }
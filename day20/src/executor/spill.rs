@@ -0,0 +1,102 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Result;
+
+use crate::tuple::{deserialize_tuple, serialize_tuple, Schema, Value};
+
+/// Directory, under the data directory, holding the temp files of running
+/// queries
+const TEMP_DIR: &str = "pgsql_tmp";
+
+static NEXT_FILE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Remove temp files left behind by queries that were running when the server
+/// stopped
+pub fn remove_temp_files() {
+    let _ = fs::remove_dir_all(TEMP_DIR);
+}
+
+/// Rows an executor has written out because they did not fit in work_mem.
+/// Each row is a length-prefixed tuple of the file's schema. The file is
+/// removed when dropped.
+pub struct SpillFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    schema: Schema,
+}
+
+impl SpillFile {
+    pub fn create(schema: Schema) -> Result<Self> {
+        fs::create_dir_all(TEMP_DIR)?;
+        let id = NEXT_FILE_ID.fetch_add(1, Ordering::Relaxed);
+        let path = PathBuf::from(TEMP_DIR).join(format!("pgsql_tmp{}.{id}", std::process::id()));
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        Ok(SpillFile {
+            path,
+            writer: BufWriter::new(file),
+            schema,
+        })
+    }
+
+    pub fn write(&mut self, values: &[Value]) -> Result<()> {
+        let data = serialize_tuple(values);
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Read the rows back in the order they were written
+    pub fn reader(&mut self) -> Result<SpillReader<'_>> {
+        self.writer.flush()?;
+        Ok(SpillReader {
+            reader: BufReader::new(File::open(&self.path)?),
+            schema: &self.schema,
+        })
+    }
+}
+
+/// Number of temp files created by this process so far
+#[cfg(test)]
+pub fn files_created() -> u64 {
+    NEXT_FILE_ID.load(Ordering::Relaxed)
+}
+
+/// Number of this process's temp files currently on disk
+#[cfg(test)]
+pub fn files_on_disk() -> usize {
+    let prefix = format!("pgsql_tmp{}.", std::process::id());
+    fs::read_dir(TEMP_DIR).map_or(0, |entries| {
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .count()
+    })
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct SpillReader<'a> {
+    reader: BufReader<File>,
+    schema: &'a Schema,
+}
+
+impl SpillReader<'_> {
+    pub fn next_row(&mut self) -> Result<Option<Vec<Value>>> {
+        let mut len = [0u8; 4];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(deserialize_tuple(&data, self.schema)?))
+    }
+}
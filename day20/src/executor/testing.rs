@@ -0,0 +1,135 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use tempfile::TempDir;
+
+use crate::analyzer::analyze_with_parameters;
+use crate::bootstrap;
+use crate::buffer_pool::BufferPoolManager;
+use crate::catalog::Catalog;
+use crate::clog::Clog;
+use crate::disk::DiskManager;
+use crate::lock_manager::LockManager;
+use crate::parser::parse;
+use crate::transaction::Transaction;
+use crate::transaction_manager::TransactionManager;
+use crate::tuple::Value;
+use crate::wal::{WalManager, WalRecordType};
+
+use super::ExecutionEngine;
+
+/// A freshly bootstrapped database in a temporary directory, for tests that
+/// run SQL through the analyzer and executors
+pub struct TestDb {
+    pub bpm: Arc<Mutex<BufferPoolManager>>,
+    pub catalog: Arc<Catalog>,
+    pub lock_manager: Arc<LockManager>,
+    pub wal_manager: Arc<WalManager>,
+    pub txn_manager: Arc<TransactionManager>,
+    dir: TempDir,
+}
+
+impl TestDb {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        let clog = Arc::new(Clog::new(&path("")).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(clog));
+        let disk_manager = DiskManager::open(&path("table.db")).unwrap();
+        let wal_manager = Arc::new(WalManager::new_for_init(&path("wal")).unwrap());
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new(
+            disk_manager,
+            Arc::clone(&wal_manager),
+        )));
+        bootstrap::bootstrap(&bpm, &txn_manager, "postgres").unwrap();
+        let catalog = Arc::new(Catalog::new(Arc::clone(&bpm)));
+
+        TestDb {
+            bpm,
+            catalog,
+            lock_manager: Arc::new(LockManager::new()),
+            wal_manager,
+            txn_manager,
+            dir,
+        }
+    }
+
+    /// Path of a file in the database directory
+    pub fn path(&self, name: &str) -> String {
+        self.dir.path().join(name).to_str().unwrap().to_string()
+    }
+
+    pub fn begin(&self) -> Transaction {
+        let mut txn = Transaction::new();
+        let txn_id = self.txn_manager.begin();
+        txn.begin_with_id(txn_id, self.txn_manager.get_snapshot(txn_id));
+        let lsn = self.wal_manager.append(txn_id, 0, WalRecordType::Begin);
+        txn.set_last_lsn(lsn);
+        self.txn_manager.update_last_lsn(txn_id, lsn);
+        txn
+    }
+
+    /// Run a statement in `txn`, returning the rows the executor produced:
+    /// the RETURNING rows, or the row count of a DML statement without them
+    pub fn execute(&self, txn: &mut Transaction, sql: &str) -> Result<Vec<Vec<Value>>> {
+        let stmt = parse(sql)?;
+        let analyzed = analyze_with_parameters(&self.catalog, &stmt, &mut Vec::new())?;
+        let mut engine = ExecutionEngine::new(
+            Arc::clone(&self.bpm),
+            &self.catalog,
+            &analyzed,
+            Some(txn),
+            Some(&self.lock_manager),
+            Some(Arc::clone(&self.wal_manager)),
+            Some(&self.txn_manager),
+        )?;
+        Ok(engine.execute()?.into_iter().map(|t| t.values).collect())
+    }
+
+    pub fn commit(&self, txn: &mut Transaction) {
+        self.wal_manager
+            .append(txn.id, txn.last_lsn, WalRecordType::Commit);
+        self.txn_manager.commit(txn.id);
+        let held_locks = txn.take_held_locks();
+        self.lock_manager.unlock_all(txn.id, &held_locks);
+        txn.commit();
+    }
+
+    pub fn rollback(&self, txn: &mut Transaction) {
+        let (txn_id, last_lsn) = (txn.id, txn.last_lsn);
+        let undo_log = txn.take_undo_log();
+        let lsn = ExecutionEngine::perform_rollback(
+            &self.bpm,
+            &self.wal_manager,
+            txn_id,
+            undo_log,
+            last_lsn,
+        )
+        .unwrap();
+        self.wal_manager.append(txn_id, lsn, WalRecordType::Abort);
+        self.txn_manager.abort(txn_id);
+        let held_locks = txn.take_held_locks();
+        self.lock_manager.unlock_all(txn_id, &held_locks);
+    }
+
+    /// Run a statement in a transaction of its own, committed if it succeeds
+    pub fn run(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        let mut txn = self.begin();
+        match self.execute(&mut txn, sql) {
+            Ok(rows) => {
+                self.commit(&mut txn);
+                Ok(rows)
+            }
+            Err(e) => {
+                self.rollback(&mut txn);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run a query that must succeed
+    pub fn query(&self, sql: &str) -> Vec<Vec<Value>> {
+        self.run(sql).unwrap_or_else(|e| panic!("{sql}: {e}"))
+    }
+}
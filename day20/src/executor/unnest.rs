@@ -0,0 +1,127 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::analyzer::AnalyzedExpr;
+use crate::buffer_pool::BufferPoolManager;
+use crate::tuple::Value;
+
+use super::{evaluate_expr_with_offsets, Executor, Tuple};
+
+/// unnest(array) in FROM: one single-column row per element. The array
+/// expression may refer to the FROM items before it, as in
+/// `t JOIN unnest(t.tags) ON true`; a join then rescans it with each row of
+/// those items, and the array is evaluated against that row.
+pub struct UnnestExecutor {
+    bpm: Arc<Mutex<BufferPoolManager>>,
+    array: AnalyzedExpr,
+    /// Offsets of the FROM items in the rows passed to rescan
+    rte_offsets: Vec<usize>,
+    elements: Vec<Value>,
+    position: usize,
+}
+
+impl UnnestExecutor {
+    pub fn new(
+        bpm: Arc<Mutex<BufferPoolManager>>,
+        array: AnalyzedExpr,
+        rte_offsets: Vec<usize>,
+    ) -> Self {
+        UnnestExecutor {
+            bpm,
+            array,
+            rte_offsets,
+            elements: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn evaluate(&mut self, outer: &Tuple) -> Result<()> {
+        self.elements =
+            match evaluate_expr_with_offsets(&self.bpm, &self.array, outer, &self.rte_offsets)? {
+                Value::Array(elements) => elements,
+                // unnest(NULL) has no rows
+                _ => Vec::new(),
+            };
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl Executor for UnnestExecutor {
+    // Only an array that refers to no other FROM item is evaluated here
+    fn open(&mut self) -> Result<()> {
+        self.evaluate(&Tuple::new(vec![]))
+    }
+
+    fn rescan(&mut self, outer: &Tuple) -> Result<()> {
+        self.evaluate(outer)
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        let Some(element) = self.elements.get(self.position) else {
+            return Ok(None);
+        };
+        self.position += 1;
+        Ok(Some(Tuple::new(vec![element.clone()])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::TestDb;
+    use super::*;
+
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE posts (id INT, tags TEXT[])");
+        db.query("INSERT INTO posts VALUES (1, ARRAY['a', 'b'])");
+        db.query("INSERT INTO posts VALUES (2, ARRAY['c'])");
+        db.query("INSERT INTO posts VALUES (3, '{}')");
+        db
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Varchar(s.to_string())
+    }
+
+    #[test]
+    fn test_unnest_constant_array() {
+        let db = TestDb::new();
+        assert_eq!(
+            db.query("SELECT n FROM unnest(ARRAY[3, 1, 2]) AS n"),
+            vec![vec![Value::Int(3)], vec![Value::Int(1)], vec![Value::Int(2)]]
+        );
+        assert!(db.query("SELECT * FROM unnest(NULL::INT[])").is_empty());
+    }
+
+    #[test]
+    fn test_unnest_refers_to_earlier_from_item() {
+        let db = setup();
+        assert_eq!(
+            db.query("SELECT id, tag FROM posts JOIN unnest(posts.tags) AS tag ON true"),
+            vec![
+                vec![Value::Int(1), text("a")],
+                vec![Value::Int(1), text("b")],
+                vec![Value::Int(2), text("c")],
+            ]
+        );
+        // A row whose array is empty keeps its place in a LEFT JOIN
+        assert_eq!(
+            db.query("SELECT id, tag FROM posts LEFT JOIN unnest(tags) AS tag ON tag <> 'a'"),
+            vec![
+                vec![Value::Int(1), text("b")],
+                vec![Value::Int(2), text("c")],
+                vec![Value::Int(3), Value::Null],
+            ]
+        );
+        // Only the FROM items before it are visible
+        let err = db
+            .run("SELECT * FROM unnest(posts.tags) AS tag JOIN posts ON true")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid unnest() argument: column 'posts.tags' not found"
+        );
+    }
+}
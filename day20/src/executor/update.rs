@@ -1,54 +1,72 @@
+use std::collections::{HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
-use crate::analyzer::AnalyzedAssignment;
-use crate::btree::{BTree, IndexKey};
+use crate::analyzer::{AnalyzedAssignment, AnalyzedSelectItem};
 use crate::buffer_pool::BufferPoolManager;
-use crate::catalog::Catalog;
+use crate::catalog::{Catalog, IndexDef};
 use crate::lock_manager::{LockManager, LockMode};
-use crate::page::NO_NEXT_PAGE;
-use crate::transaction::{Transaction, UndoLogEntry};
-use crate::tuple::{serialize_tuple_mvcc, TxnId, Value, INVALID_TXN_ID};
-use crate::wal::{WalManager, WalRecordType};
+use crate::transaction::Transaction;
+use crate::transaction_manager::TransactionManager;
+use crate::tuple::{coerce_value, deserialize_tuple_mvcc, Value, INVALID_TXN_ID};
+use crate::wal::WalManager;
 
-use super::{evaluate_expr, Executor, Rid, Tuple};
+use super::delete::{delete_tuple, delete_toast_chunks};
+use super::insert::{
+    fetch_tuple, insert_index_entries, probe_unique_indexes, unique_keys, wait_for_row, write_row,
+    UniqueProbe,
+};
+use super::{evaluate_expr_with_offsets, project_returning, Executor, Rid, Tuple};
 
 pub struct UpdateExecutor<'a> {
     bpm: Arc<Mutex<BufferPoolManager>>,
     catalog: &'a Catalog,
     table_id: u32,
     child: Box<dyn Executor + 'a>,
+    /// Column offsets of each RTE in the child's tuples (target table first)
+    rte_offsets: Vec<usize>,
     assignments: Vec<AnalyzedAssignment>,
+    returning: Vec<AnalyzedSelectItem>,
     txn: Option<&'a mut Transaction>,
     lock_manager: Option<&'a LockManager>,
     wal_manager: Option<Arc<WalManager>>,
+    txn_manager: Option<&'a TransactionManager>,
     executed: bool,
     updated_count: i32,
+    returned: VecDeque<Tuple>,
 }
 
 impl<'a> UpdateExecutor<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bpm: Arc<Mutex<BufferPoolManager>>,
         catalog: &'a Catalog,
         table_id: u32,
         child: Box<dyn Executor + 'a>,
+        rte_offsets: Vec<usize>,
         assignments: Vec<AnalyzedAssignment>,
+        returning: Vec<AnalyzedSelectItem>,
         txn: Option<&'a mut Transaction>,
         lock_manager: Option<&'a LockManager>,
         wal_manager: Option<Arc<WalManager>>,
+        txn_manager: Option<&'a TransactionManager>,
     ) -> Self {
         UpdateExecutor {
             bpm,
             catalog,
             table_id,
             child,
+            rte_offsets,
             assignments,
+            returning,
             txn,
             lock_manager,
             wal_manager,
+            txn_manager,
             executed: false,
             updated_count: 0,
+            returned: VecDeque::new(),
         }
     }
 }
@@ -57,217 +75,228 @@ impl Executor for UpdateExecutor<'_> {
     fn open(&mut self) -> Result<()> {
         self.executed = false;
         self.updated_count = 0;
+        self.returned.clear();
         self.child.open()
     }
 
     fn next(&mut self) -> Result<Option<Tuple>> {
         if self.executed {
-            return Ok(None);
+            return Ok(self.returned.pop_front());
         }
         self.executed = true;
 
+        // With FROM, a target row can match several joined rows; update it once
+        let table = self
+            .catalog
+            .get_table_by_id(self.table_id)
+            .ok_or_else(|| anyhow::anyhow!("table not found: {}", self.table_id))?;
+        let mut seen = HashSet::new();
         let mut updates = Vec::new();
         while let Some(tuple) = self.child.next()? {
             if let Some(rid) = tuple.rid {
-                let mut new_values = tuple.values.clone();
+                if !seen.insert(rid) {
+                    continue;
+                }
+                let target_len = self
+                    .rte_offsets
+                    .get(1)
+                    .copied()
+                    .unwrap_or(tuple.values.len());
+                let mut new_values = tuple.values[..target_len].to_vec();
                 for AnalyzedAssignment {
                     column_index,
                     value,
                 } in &self.assignments
                 {
-                    let new_value = evaluate_expr(value, &tuple)?;
-                    new_values[*column_index] = new_value;
+                    let new_value =
+                        evaluate_expr_with_offsets(&self.bpm, value, &tuple, &self.rte_offsets)?;
+                    new_values[*column_index] =
+                        coerce_value(new_value, &table.columns[*column_index].data_type)?;
                 }
-                updates.push((rid, new_values));
+                updates.push((rid, new_values, tuple));
             }
         }
 
         if let Some(ref mut txn) = self.txn {
             if txn.is_active() {
                 if let Some(lock_manager) = self.lock_manager {
-                    for (old_rid, _) in &updates {
-                        lock_manager
-                            .lock(txn.id, *old_rid, LockMode::Exclusive)
-                            .map_err(|e| anyhow::anyhow!("{e}"))?;
+                    for (old_rid, _, _) in &updates {
+                        lock_manager.lock(txn.id, *old_rid, LockMode::Exclusive)?;
                         txn.add_lock(*old_rid);
                     }
                 }
             }
         }
 
-        let xmax = self
-            .txn
-            .as_ref()
-            .map(|t| t.id)
-            .unwrap_or(INVALID_TXN_ID);
-
         self.updated_count = updates.len() as i32;
-        for (old_rid, new_values) in updates {
-            let page_arc = self.bpm.lock().unwrap().fetch_page_mut(old_rid.page_id)?;
-            let mut page_guard = page_arc.write().unwrap();
-            page_guard.set_tuple_xmax(old_rid.slot_id, xmax)?;
+        for (old_rid, new_values, mut row) in updates {
+            update_tuple(
+                &self.bpm,
+                self.catalog,
+                self.table_id,
+                old_rid,
+                &new_values,
+                self.txn.as_deref_mut(),
+                self.lock_manager,
+                &self.wal_manager,
+                self.txn_manager,
+            )?;
 
-            let delete_lsn;
-            let delete_prev_lsn;
-            if let Some(ref mut txn) = self.txn {
-                if txn.is_active() {
-                    if let Some(ref wal_manager) = self.wal_manager {
-                        delete_prev_lsn = txn.last_lsn;
-                        delete_lsn = wal_manager.append(
-                            txn.id,
-                            delete_prev_lsn,
-                            WalRecordType::Delete {
-                                rid: old_rid,
-                                xmax,
-                            },
-                        );
-                        txn.set_last_lsn(delete_lsn);
-                        page_guard.page_lsn = delete_lsn;
-                    } else {
-                        delete_lsn = 0;
-                        delete_prev_lsn = 0;
-                    }
-                } else {
-                    delete_lsn = 0;
-                    delete_prev_lsn = 0;
-                }
-            } else {
-                delete_lsn = 0;
-                delete_prev_lsn = 0;
+            if !self.returning.is_empty() {
+                // RETURNING sees the new version of the target row
+                row.values.splice(..new_values.len(), new_values);
+                self.returned.push_back(project_returning(
+                    &self.bpm,
+                    &self.returning,
+                    &row,
+                    &self.rte_offsets,
+                )?);
             }
+        }
 
-            drop(page_guard);
-            self.bpm.lock().unwrap().unpin_page(old_rid.page_id, true)?;
-
-            let new_xmin = xmax;
-            let new_xmax: TxnId = INVALID_TXN_ID;
-            let tuple_data = serialize_tuple_mvcc(new_xmin, new_xmax, &new_values);
-
-            // Get the table definition to find the first page
-            let table = self
-                .catalog
-                .get_table_by_id(self.table_id)
-                .ok_or_else(|| anyhow::anyhow!("table not found"))?;
-
-            // Find a page with space or create new one (following the linked list)
-            let new_rid = {
-                let mut current_page_id = table.first_page_id;
-                let mut last_page_id = current_page_id;
-                let mut result_rid: Option<Rid> = None;
-
-                while current_page_id != NO_NEXT_PAGE {
-                    let page_arc = self.bpm.lock().unwrap().fetch_page_mut(current_page_id)?;
-                    let mut page_guard = page_arc.write().unwrap();
+        if !self.returning.is_empty() {
+            return Ok(self.returned.pop_front());
+        }
+        Ok(Some(Tuple::new(vec![Value::Int(self.updated_count)])))
+    }
+}
 
-                    if let Ok(slot_id) = page_guard.insert(&tuple_data) {
-                        drop(page_guard);
-                        self.bpm.lock().unwrap().unpin_page(current_page_id, true)?;
-                        result_rid = Some(Rid {
-                            page_id: current_page_id,
-                            slot_id,
-                        });
-                        break;
-                    }
+/// Replace the row at `old_rid` with a new version holding `new_values`.
+/// The caller must already hold an exclusive lock on `old_rid`.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn update_tuple(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    catalog: &Catalog,
+    table_id: u32,
+    old_rid: Rid,
+    new_values: &[Value],
+    mut txn: Option<&mut Transaction>,
+    lock_manager: Option<&LockManager>,
+    wal_manager: &Option<Arc<WalManager>>,
+    txn_manager: Option<&TransactionManager>,
+) -> Result<()> {
+    // Get the table definition to find the first page
+    let table = catalog
+        .get_table_by_id(table_id)
+        .ok_or_else(|| anyhow::anyhow!("table not found"))?;
+    let indexes = catalog.get_indexes_for_table(table_id);
+    let unique_indexes: Vec<&IndexDef> = indexes.iter().filter(|i| i.is_unique).collect();
 
-                    last_page_id = current_page_id;
-                    current_page_id = page_guard.next_page_id();
-                    drop(page_guard);
-                    self.bpm.lock().unwrap().unpin_page(last_page_id, false)?;
-                }
+    let xmax = txn.as_ref().map(|t| t.id).unwrap_or(INVALID_TXN_ID);
 
-                match result_rid {
-                    Some(rid) => rid,
-                    None => {
-                        // No space in existing pages, create new page
-                        let (new_page_id, new_page_arc) = self.bpm.lock().unwrap().new_page()?;
-                        let mut new_page = new_page_arc.write().unwrap();
-                        new_page.set_next_page_id(NO_NEXT_PAGE);
-                        let slot_id = new_page.insert(&tuple_data)?;
-                        drop(new_page);
-                        self.bpm.lock().unwrap().unpin_page(new_page_id, true)?;
+    // Check unique indexes before touching anything, waiting out undecided rows
+    let keys = unique_keys(bpm, &table, &unique_indexes, new_values)?;
+    let guard = loop {
+        let guard = match lock_manager {
+            Some(lock_manager) if !keys.is_empty() => Some(lock_manager.lock_unique_insert(&keys)?),
+            _ => None,
+        };
+        match probe_unique_indexes(
+            bpm,
+            &table,
+            &unique_indexes,
+            new_values,
+            Some(old_rid),
+            xmax,
+            txn_manager,
+        )? {
+            UniqueProbe::Clear => break guard,
+            UniqueProbe::InProgress(rid) => {
+                drop(guard);
+                wait_for_row(lock_manager, txn.as_deref_mut(), rid)?;
+            }
+            UniqueProbe::Conflict { index_name, .. } => {
+                bail!("duplicate key value violates unique constraint \"{index_name}\"");
+            }
+        }
+    };
 
-                        // Link the new page
-                        let page_arc = self.bpm.lock().unwrap().fetch_page_mut(last_page_id)?;
-                        let mut page = page_arc.write().unwrap();
-                        page.set_next_page_id(new_page_id);
-                        drop(page);
-                        self.bpm.lock().unwrap().unpin_page(last_page_id, true)?;
+    // The old version goes away together with its out-of-line values
+    delete_tuple(bpm, old_rid, xmax, txn.as_deref_mut(), wal_manager)?;
+    if let Some(old_data) = fetch_tuple(bpm, old_rid)? {
+        let (_, _, old_values) = deserialize_tuple_mvcc(&old_data, &table.to_schema())?;
+        delete_toast_chunks(bpm, &old_values, xmax, txn.as_deref_mut(), wal_manager)?;
+    }
 
-                        Rid {
-                            page_id: new_page_id,
-                            slot_id,
-                        }
-                    }
-                }
-            };
+    let new_rid = write_row(bpm, &table, new_values, txn.as_deref_mut(), wal_manager)?;
 
-            if let Some(ref mut txn) = self.txn {
-                if txn.is_active() {
-                    if let Some(ref wal_manager) = self.wal_manager {
-                        let insert_prev_lsn = txn.last_lsn;
-                        let insert_lsn = wal_manager.append(
-                            txn.id,
-                            insert_prev_lsn,
-                            WalRecordType::Insert {
-                                rid: new_rid,
-                                data: tuple_data.clone(),
-                            },
-                        );
-                        txn.set_last_lsn(insert_lsn);
+    if let Some(ref mut txn) = txn {
+        if txn.is_active() {
+            if let Some(lock_manager) = lock_manager {
+                lock_manager.lock(txn.id, new_rid, LockMode::Exclusive)?;
+                txn.add_lock(new_rid);
+            }
+        }
+    }
 
-                        let page_arc = self.bpm.lock().unwrap().fetch_page_mut(new_rid.page_id)?;
-                        let mut page_guard = page_arc.write().unwrap();
-                        page_guard.page_lsn = insert_lsn;
-                        drop(page_guard);
-                        self.bpm.lock().unwrap().unpin_page(new_rid.page_id, true)?;
+    // Maintain indexes - insert new entry for the new tuple
+    insert_index_entries(bpm, &table, &indexes, new_values, new_rid)?;
+    drop(guard);
+    Ok(())
+}
 
-                        txn.add_undo_entry(UndoLogEntry::Delete {
-                            lsn: delete_lsn,
-                            prev_lsn: delete_prev_lsn,
-                            rid: old_rid,
-                            old_xmax: INVALID_TXN_ID,
-                        });
-                        txn.add_undo_entry(UndoLogEntry::Insert {
-                            lsn: insert_lsn,
-                            prev_lsn: insert_prev_lsn,
-                            rid: new_rid,
-                            data: tuple_data,
-                        });
-                    }
+#[cfg(test)]
+mod tests {
+    use super::super::testing::TestDb;
+    use crate::tuple::Value;
 
-                    if let Some(lock_manager) = self.lock_manager {
-                        lock_manager
-                            .lock(txn.id, new_rid, LockMode::Exclusive)
-                            .map_err(|e| anyhow::anyhow!("{e}"))?;
-                        txn.add_lock(new_rid);
-                    }
-                }
-            }
+    fn setup() -> TestDb {
+        let db = TestDb::new();
+        db.query("CREATE TABLE orders (id INT, user_id INT, total INT)");
+        db.query("CREATE TABLE users (id INT, bonus INT)");
+        db.query("INSERT INTO orders VALUES (1, 10, 100)");
+        db.query("INSERT INTO orders VALUES (2, 20, 200)");
+        db.query("INSERT INTO orders VALUES (3, 30, 300)");
+        db.query("INSERT INTO users VALUES (10, 1)");
+        db.query("INSERT INTO users VALUES (20, 2)");
+        db
+    }
 
-            // Maintain indexes - insert new entry for the new tuple
-            if let Some(table) = self.catalog.get_table_by_id(self.table_id) {
-                let indexes = self.catalog.get_indexes_for_table(self.table_id);
-                for index_def in indexes {
-                    let key_schema: Vec<_> = index_def
-                        .column_ids
-                        .iter()
-                        .map(|&col_idx| table.columns[col_idx].data_type.clone())
-                        .collect();
+    fn totals(db: &TestDb) -> Vec<(i32, i32)> {
+        let mut rows: Vec<_> = db
+            .query("SELECT id, total FROM orders")
+            .into_iter()
+            .map(|row| match row[..] {
+                [Value::Int(id), Value::Int(total)] => (id, total),
+                _ => panic!("unexpected row {row:?}"),
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
 
-                    let mut btree = BTree::new(Arc::clone(&self.bpm), key_schema);
-                    btree.set_meta_page_id(index_def.meta_page_id);
+    #[test]
+    fn test_update_from() {
+        let db = setup();
+        let updated = db.query(
+            "UPDATE orders SET total = total + u.bonus FROM users u \
+             WHERE orders.user_id = u.id",
+        );
+        assert_eq!(updated, vec![vec![Value::Int(2)]]);
+        assert_eq!(totals(&db), vec![(1, 101), (2, 202), (3, 300)]);
+    }
 
-                    let key_values: Vec<Value> = index_def
-                        .column_ids
-                        .iter()
-                        .map(|&col_idx| new_values[col_idx].clone())
-                        .collect();
-                    let key = IndexKey::new(key_values);
-                    btree.insert(&key, new_rid)?;
-                }
-            }
-        }
+    #[test]
+    fn test_update_from_returning_joined_columns() {
+        let db = setup();
+        assert_eq!(
+            db.query(
+                "UPDATE orders SET total = 0 FROM users u \
+                 WHERE orders.user_id = u.id AND u.bonus = 2 RETURNING orders.id, u.bonus"
+            ),
+            vec![vec![Value::Int(2), Value::Int(2)]]
+        );
+    }
 
-        Ok(Some(Tuple::new(vec![Value::Int(self.updated_count)])))
+    #[test]
+    fn test_update_from_updates_each_target_row_once() {
+        let db = setup();
+        // Order 1 now joins two users rows
+        db.query("INSERT INTO users VALUES (10, 5)");
+        let updated = db.query(
+            "UPDATE orders SET total = total + 1 FROM users u WHERE orders.user_id = u.id",
+        );
+        assert_eq!(updated, vec![vec![Value::Int(2)]]);
+        assert_eq!(totals(&db), vec![(1, 101), (2, 201), (3, 300)]);
     }
 }
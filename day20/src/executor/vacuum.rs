@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::buffer_pool::BufferPoolManager;
+use crate::catalog::TableDef;
+use crate::page::NO_NEXT_PAGE;
+use crate::transaction_manager::{TransactionManager, TxnStatus};
+use crate::tuple::{get_tuple_xmax, get_tuple_xmin, TxnId, INVALID_TXN_ID};
+use crate::wal::{WalManager, WalRecordType};
+
+/// Physically remove the row versions of a table that no transaction can
+/// see any more, along with the chunks of their out-of-line values, and
+/// compact the pages they were on. Returns the number of tuples removed.
+pub fn vacuum_table(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    table: &TableDef,
+    txn_manager: &TransactionManager,
+    wal_manager: &WalManager,
+) -> Result<usize> {
+    let horizon = txn_manager.oldest_xmin();
+    let mut removed = 0;
+    for first_page_id in [table.first_page_id, table.toast_page_id] {
+        let mut page_id = first_page_id;
+        while page_id != NO_NEXT_PAGE {
+            let page_arc = bpm.lock().unwrap().fetch_page_mut(page_id)?;
+            let mut page = page_arc.write().unwrap();
+
+            let mut dead = Vec::new();
+            for slot_id in 0..page.tuple_count() {
+                if let Some(data) = page.get_tuple(slot_id) {
+                    if is_dead(data, horizon, txn_manager) {
+                        dead.push(slot_id);
+                    }
+                }
+            }
+            let dirty = !dead.is_empty();
+            removed += dead.len();
+            if dirty {
+                for &slot_id in &dead {
+                    page.delete(slot_id)?;
+                }
+                page.compact();
+                page.page_lsn =
+                    wal_manager.append(0, 0, WalRecordType::Vacuum { page_id, slots: dead });
+            }
+
+            let next_page_id = page.next_page_id();
+            drop(page);
+            bpm.lock().unwrap().unpin_page(page_id, dirty)?;
+            page_id = next_page_id;
+        }
+    }
+    Ok(removed)
+}
+
+/// A tuple is dead once its inserting transaction aborted, or its deleting
+/// transaction committed before every running transaction began.
+fn is_dead(data: &[u8], horizon: TxnId, txn_manager: &TransactionManager) -> bool {
+    let xmin = get_tuple_xmin(data);
+    if xmin != INVALID_TXN_ID && txn_manager.get_txn_status(xmin) == TxnStatus::Aborted {
+        return true;
+    }
+    let xmax = get_tuple_xmax(data);
+    xmax != INVALID_TXN_ID
+        && xmax < horizon
+        && txn_manager.get_txn_status(xmax) == TxnStatus::Committed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::TestDb;
+    use super::*;
+    use crate::disk::DiskManager;
+    use crate::recovery::RecoveryManager;
+
+    fn page_image(bpm: &Arc<Mutex<BufferPoolManager>>, page_id: u32) -> Vec<u8> {
+        let page_arc = bpm.lock().unwrap().fetch_page(page_id).unwrap();
+        let data = page_arc.read().unwrap().data.to_vec();
+        bpm.lock().unwrap().unpin_page(page_id, false).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_vacuum_is_redone_after_crash() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (id INT)");
+        for id in 0..3 {
+            db.query(&format!("INSERT INTO t VALUES ({id})"));
+        }
+        db.query("DELETE FROM t WHERE id <> 1");
+        let table = db.catalog.get_table("t").unwrap();
+        db.bpm.lock().unwrap().flush_all().unwrap();
+
+        let removed = vacuum_table(&db.bpm, &table, &db.txn_manager, &db.wal_manager).unwrap();
+        assert_eq!(removed, 2);
+        let vacuumed = page_image(&db.bpm, table.first_page_id);
+        db.wal_manager.flush();
+
+        // Crash before the vacuumed page is written: recovery starts from the
+        // page on disk and must compact it again
+        let bpm = Arc::new(Mutex::new(BufferPoolManager::new(
+            DiskManager::open(&db.path("table.db")).unwrap(),
+            Arc::clone(&db.wal_manager),
+        )));
+        assert_ne!(page_image(&bpm, table.first_page_id), vacuumed);
+        RecoveryManager::recover(
+            &db.path(""),
+            &db.path("wal"),
+            &bpm,
+            &db.wal_manager,
+            &db.txn_manager,
+        )
+        .unwrap();
+        assert_eq!(page_image(&bpm, table.first_page_id), vacuumed);
+    }
+}
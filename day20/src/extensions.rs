@@ -0,0 +1,119 @@
+use anyhow::{bail, Result};
+
+use crate::datetime;
+use crate::tuple::{DataType, Value};
+use crate::udf::{self, Accumulator, AggregateUdf, ScalarUdf, Volatility};
+
+// ============================================================================
+// Bundled extension functions
+// ============================================================================
+//
+// Functions that are not built into the analyzer but registered through the
+// udf API at startup, the same way an embedding application would add its own.
+
+pub fn register_all() -> Result<()> {
+    udf::register_scalar_function(Levenshtein)?;
+    udf::register_scalar_function(ClockTimestamp)?;
+    udf::register_aggregate_function(GeometricMean)?;
+    Ok(())
+}
+
+/// levenshtein(text, text): edit distance in characters
+struct Levenshtein;
+
+impl ScalarUdf for Levenshtein {
+    fn name(&self) -> &str {
+        "levenshtein"
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Option<DataType> {
+        match arg_types {
+            [a, b] if a.is_string() && b.is_string() => Some(DataType::Int),
+            _ => None,
+        }
+    }
+
+    fn evaluate(&self, args: &[Value]) -> Result<Value> {
+        let [Value::Varchar(a), Value::Varchar(b)] = args else {
+            bail!("levenshtein expects two strings");
+        };
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let substitution = diagonal + usize::from(ca != *cb);
+                diagonal = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+            }
+        }
+        Ok(Value::Int(row[b.len()] as i32))
+    }
+}
+
+/// clock_timestamp(): the current time, changing even within a statement
+struct ClockTimestamp;
+
+impl ScalarUdf for ClockTimestamp {
+    fn name(&self) -> &str {
+        "clock_timestamp"
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Option<DataType> {
+        arg_types.is_empty().then_some(DataType::TimestampTz)
+    }
+
+    fn volatility(&self) -> Volatility {
+        Volatility::Volatile
+    }
+
+    fn evaluate(&self, _args: &[Value]) -> Result<Value> {
+        Ok(Value::TimestampTz(datetime::system_timestamp()))
+    }
+}
+
+/// geometric_mean(numeric): nth root of the product of the non-NULL inputs,
+/// which must be positive
+struct GeometricMean;
+
+#[derive(Debug, Default)]
+struct GeometricMeanState {
+    log_sum: f64,
+    count: u64,
+}
+
+impl AggregateUdf for GeometricMean {
+    fn name(&self) -> &str {
+        "geometric_mean"
+    }
+
+    fn return_type(&self, arg_type: &DataType) -> Option<DataType> {
+        arg_type.is_numeric().then_some(DataType::Double)
+    }
+
+    fn accumulator(&self, _arg_type: &DataType) -> Box<dyn Accumulator> {
+        Box::<GeometricMeanState>::default()
+    }
+}
+
+impl Accumulator for GeometricMeanState {
+    fn accumulate(&mut self, value: &Value) -> Result<()> {
+        let Some(x) = value.as_f64() else {
+            return Ok(());
+        };
+        if x <= 0.0 {
+            bail!("geometric_mean input must be positive, got {x}");
+        }
+        self.log_sum += x.ln();
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finalize(&self) -> Result<Value> {
+        if self.count == 0 {
+            return Ok(Value::Null);
+        }
+        Ok(Value::Double((self.log_sum / self.count as f64).exp()))
+    }
+}
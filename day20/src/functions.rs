@@ -0,0 +1,303 @@
+use anyhow::{bail, Result};
+
+use crate::ast::BinaryOperator;
+use crate::bytea;
+use crate::collation::Collation;
+use crate::datetime::{self, DateTimeSource};
+use crate::json::Json;
+use crate::tuple::{cast_value, coerce_value, DataType, Value};
+use crate::udf::{self, ScalarUdfRef, Volatility};
+
+/// Built-in scalar functions, and registered user-defined ones
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalarFunction {
+    /// now() / current_timestamp
+    Now,
+    CurrentDate,
+    CurrentTime,
+    LocalTimestamp,
+    /// extract(field FROM source)
+    Extract,
+    /// date_trunc(unit, timestamp)
+    DateTrunc,
+    /// length(text) in characters, length(bytea) in bytes
+    Length,
+    /// length(character(n)) in characters, without the trailing blanks
+    BpcharLength,
+    /// substring(string, start [, count]) with 1-based positions
+    Substring,
+    /// encode(bytea, format)
+    Encode,
+    /// decode(text, format)
+    Decode,
+    /// jsonb_build_object(key, value, ...)
+    JsonbBuildObject,
+    /// ARRAY[a, b, ...] with elements converted to the element type
+    MakeArray(DataType),
+    /// array[index] with a 1-based index; out of range gives NULL
+    ArraySubscript,
+    /// value op ANY (array), comparing under the collation
+    Any(BinaryOperator, Collation),
+    /// array_length(array, dimension)
+    ArrayLength,
+    /// CAST(value AS type) of a non-constant value
+    Cast(DataType),
+    /// value COLLATE "name": the value itself, compared under the collation
+    Collate(Collation),
+    /// GROUPING(a, b, ...) over these GROUP BY expressions: a bit per
+    /// argument, set if it is not grouped in the current row's grouping set.
+    /// Only the aggregate executor can compute it.
+    Grouping(Vec<usize>),
+    /// A function registered through udf::register_scalar_function
+    User(ScalarUdfRef),
+}
+
+impl ScalarFunction {
+    /// Find the function called `name` that accepts `arg_types`, returning it
+    /// with its result type.
+    pub fn resolve(name: &str, arg_types: &[DataType]) -> Result<(ScalarFunction, DataType)> {
+        use DataType::*;
+        let resolved = match (name, arg_types) {
+            ("now" | "current_timestamp", []) => Some((ScalarFunction::Now, TimestampTz)),
+            ("current_date", []) => Some((ScalarFunction::CurrentDate, Date)),
+            ("current_time", []) => Some((ScalarFunction::CurrentTime, Time)),
+            ("localtimestamp", []) => Some((ScalarFunction::LocalTimestamp, Timestamp)),
+            ("extract" | "date_part", [field, source])
+                if field.is_string() && source.is_temporal() =>
+            {
+                Some((ScalarFunction::Extract, Numeric(None)))
+            }
+            ("date_trunc", [unit, Date | Timestamp]) if unit.is_string() => {
+                Some((ScalarFunction::DateTrunc, Timestamp))
+            }
+            ("date_trunc", [unit, TimestampTz]) if unit.is_string() => {
+                Some((ScalarFunction::DateTrunc, TimestampTz))
+            }
+            ("length" | "char_length", [Char(_)]) => Some((ScalarFunction::BpcharLength, Int)),
+            ("length" | "char_length" | "octet_length", [arg])
+                if arg.is_string() || *arg == Bytea =>
+            {
+                Some((ScalarFunction::Length, Int))
+            }
+            ("substring" | "substr", [arg, start, count @ ..])
+                if (arg.is_string() || *arg == Bytea)
+                    && is_integer(start)
+                    && count.len() <= 1
+                    && count.iter().all(is_integer) =>
+            {
+                let result = if *arg == Bytea { Bytea } else { Text };
+                Some((ScalarFunction::Substring, result))
+            }
+            ("encode", [Bytea, format]) if format.is_string() => {
+                Some((ScalarFunction::Encode, Text))
+            }
+            ("decode", [text, format]) if text.is_string() && format.is_string() => {
+                Some((ScalarFunction::Decode, Bytea))
+            }
+            ("jsonb_build_object", args) if args.len().is_multiple_of(2) => {
+                Some((ScalarFunction::JsonbBuildObject, Jsonb))
+            }
+            ("array_length", [Array(_), dimension]) if is_integer(dimension) => {
+                Some((ScalarFunction::ArrayLength, Int))
+            }
+            _ => udf::resolve_scalar(name, arg_types)
+                .map(|(function, result)| (ScalarFunction::User(function), result)),
+        };
+        match resolved {
+            Some(resolved) => Ok(resolved),
+            None => {
+                let types: Vec<String> = arg_types.iter().map(|t| t.name()).collect();
+                bail!("function {name}({}) does not exist", types.join(", "))
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ScalarFunction::Now => "now",
+            ScalarFunction::CurrentDate => "current_date",
+            ScalarFunction::CurrentTime => "current_time",
+            ScalarFunction::LocalTimestamp => "localtimestamp",
+            ScalarFunction::Extract => "extract",
+            ScalarFunction::DateTrunc => "date_trunc",
+            ScalarFunction::Length | ScalarFunction::BpcharLength => "length",
+            ScalarFunction::Substring => "substring",
+            ScalarFunction::Encode => "encode",
+            ScalarFunction::Decode => "decode",
+            ScalarFunction::JsonbBuildObject => "jsonb_build_object",
+            ScalarFunction::MakeArray(_) => "array",
+            ScalarFunction::ArraySubscript => "array_subscript",
+            ScalarFunction::Any(..) => "any",
+            ScalarFunction::ArrayLength => "array_length",
+            ScalarFunction::Cast(_) => "cast",
+            ScalarFunction::Collate(_) => "collate",
+            ScalarFunction::Grouping(_) => "grouping",
+            ScalarFunction::User(function) => function.name(),
+        }
+    }
+
+    /// Whether the result depends only on the arguments (required in index
+    /// expressions); the current-time functions are not
+    pub fn is_immutable(&self) -> bool {
+        if let ScalarFunction::User(function) = self {
+            return function.volatility() == Volatility::Immutable;
+        }
+        !matches!(
+            self,
+            ScalarFunction::Now
+                | ScalarFunction::CurrentDate
+                | ScalarFunction::CurrentTime
+                | ScalarFunction::LocalTimestamp
+        )
+    }
+
+    /// Apply the function to evaluated arguments. A NULL argument gives NULL,
+    /// except for jsonb_build_object where NULL values become JSON nulls and
+    /// ARRAY[] where they become NULL elements, and user functions that are
+    /// not strict.
+    pub fn evaluate(&self, args: &[Value]) -> Result<Value> {
+        match self {
+            ScalarFunction::User(function) if !function.is_strict() => {
+                return function.evaluate(args);
+            }
+            ScalarFunction::JsonbBuildObject => return build_object(args),
+            ScalarFunction::MakeArray(element_type) => {
+                let elements = args
+                    .iter()
+                    .map(|arg| coerce_value(arg.clone(), element_type))
+                    .collect::<Result<Vec<_>>>()?;
+                return Ok(Value::Array(elements));
+            }
+            _ => {}
+        }
+        if args.iter().any(|arg| matches!(arg, Value::Null)) {
+            return Ok(Value::Null);
+        }
+        let now = datetime::transaction_timestamp();
+        match (self, args) {
+            (ScalarFunction::Now, []) => Ok(Value::TimestampTz(now)),
+            (ScalarFunction::CurrentDate, []) => Ok(Value::Date(datetime::timestamp_to_date(now)?)),
+            (ScalarFunction::CurrentTime, []) => {
+                Ok(Value::Time(now.rem_euclid(datetime::USECS_PER_DAY)))
+            }
+            (ScalarFunction::LocalTimestamp, []) => Ok(Value::Timestamp(now)),
+            (ScalarFunction::Extract, [Value::Varchar(field), source]) => {
+                let source = match source {
+                    Value::Date(d) => DateTimeSource::Date(*d),
+                    Value::Time(t) => DateTimeSource::Time(*t),
+                    Value::Timestamp(ts) | Value::TimestampTz(ts) => DateTimeSource::Timestamp(*ts),
+                    Value::Interval(i) => DateTimeSource::Interval(*i),
+                    other => bail!("cannot extract from {other:?}"),
+                };
+                Ok(Value::Numeric(datetime::extract(field, &source)?))
+            }
+            (ScalarFunction::DateTrunc, [Value::Varchar(unit), source]) => match source {
+                Value::TimestampTz(ts) => Ok(Value::TimestampTz(datetime::date_trunc(unit, *ts)?)),
+                other => match other.as_timestamp() {
+                    Some(ts) => Ok(Value::Timestamp(datetime::date_trunc(unit, ts?)?)),
+                    None => bail!("cannot truncate {other:?}"),
+                },
+            },
+            (ScalarFunction::Length, [Value::Varchar(s)]) => {
+                Ok(Value::Int(s.chars().count() as i32))
+            }
+            (ScalarFunction::Length, [Value::Bytea(b)]) => Ok(Value::Int(b.len() as i32)),
+            (ScalarFunction::BpcharLength, [Value::Varchar(s)]) => {
+                Ok(Value::Int(s.trim_end_matches(' ').chars().count() as i32))
+            }
+            (ScalarFunction::Substring, [source, start, count @ ..]) => {
+                let start = start.as_i64().unwrap_or(1);
+                let count = count.first().and_then(Value::as_i64);
+                match source {
+                    Value::Varchar(s) => {
+                        let chars: Vec<char> = s.chars().collect();
+                        let range = substring_range(chars.len(), start, count)?;
+                        Ok(Value::Varchar(chars[range].iter().collect()))
+                    }
+                    Value::Bytea(b) => Ok(Value::Bytea(
+                        b[substring_range(b.len(), start, count)?].to_vec(),
+                    )),
+                    other => bail!("cannot take substring of {other:?}"),
+                }
+            }
+            (ScalarFunction::Encode, [Value::Bytea(b), Value::Varchar(format)]) => {
+                Ok(Value::Varchar(bytea::encode(b, format)?))
+            }
+            (ScalarFunction::Decode, [Value::Varchar(text), Value::Varchar(format)]) => {
+                Ok(Value::Bytea(bytea::decode(text, format)?))
+            }
+            (ScalarFunction::ArraySubscript, [Value::Array(elements), index]) => {
+                let element = index
+                    .as_i64()
+                    .and_then(|i| usize::try_from(i - 1).ok())
+                    .and_then(|i| elements.get(i));
+                Ok(element.cloned().unwrap_or(Value::Null))
+            }
+            (ScalarFunction::Any(op, collation), [left, Value::Array(elements)]) => {
+                any(op, *collation, left, elements)
+            }
+            (ScalarFunction::Cast(data_type), [value]) => cast_value(value.clone(), data_type),
+            (ScalarFunction::Collate(_), [value]) => Ok(value.clone()),
+            (ScalarFunction::Grouping(_), _) => {
+                bail!("GROUPING() can only be used in the select list or HAVING of a grouped query")
+            }
+            (ScalarFunction::User(function), args) => function.evaluate(args),
+            (ScalarFunction::ArrayLength, [Value::Array(elements), dimension]) => {
+                // Empty arrays have no dimensions
+                if elements.is_empty() || dimension.as_i64() != Some(1) {
+                    return Ok(Value::Null);
+                }
+                Ok(Value::Int(elements.len() as i32))
+            }
+            _ => bail!("invalid arguments for function {}", self.name()),
+        }
+    }
+}
+
+fn build_object(args: &[Value]) -> Result<Value> {
+    let mut pairs = Vec::with_capacity(args.len() / 2);
+    for (i, pair) in args.chunks(2).enumerate() {
+        let key = match Json::from_value(&pair[0]).as_text() {
+            Some(key) => key,
+            None => bail!("argument {}: key must not be null", 2 * i + 1),
+        };
+        pairs.push((key, Json::from_value(&pair[1])));
+    }
+    Ok(Value::Jsonb(Json::object(pairs)))
+}
+
+/// True if `op` holds for any element; otherwise NULL if some comparison was
+/// NULL, else false
+fn any(op: &BinaryOperator, collation: Collation, left: &Value, elements: &[Value]) -> Result<Value> {
+    let left = collation.sort_key(left.clone());
+    let mut saw_null = false;
+    for element in elements {
+        let element = collation.sort_key(element.clone());
+        match crate::executor::evaluate_binary_op(op, &left, &element)? {
+            Value::Bool(true) => return Ok(Value::Bool(true)),
+            Value::Null => saw_null = true,
+            _ => {}
+        }
+    }
+    Ok(if saw_null { Value::Null } else { Value::Bool(false) })
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int | DataType::SmallInt | DataType::BigInt
+    )
+}
+
+/// Index range selected by substring(_, start, count) in a sequence of `len`
+/// elements. Positions before 1 still count against `count`, as in PostgreSQL.
+fn substring_range(len: usize, start: i64, count: Option<i64>) -> Result<std::ops::Range<usize>> {
+    let end = match count {
+        Some(count) if count < 0 => bail!("negative substring length not allowed"),
+        Some(count) => start.saturating_add(count),
+        None => i64::MAX,
+    };
+    let clamp = |pos: i64| (pos.max(1) - 1).min(len as i64) as usize;
+    let (from, to) = (clamp(start), clamp(end));
+    Ok(from..to.max(from))
+}
@@ -1,31 +1,61 @@
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 
-use crate::analyzer::{analyze, AnalyzedExpr, AnalyzedSelectItem, AnalyzedStatement};
-use crate::ast::Statement;
+use crate::analyzer::{
+    analyze_with_parameters, check_table_access, AnalyzedExpr, AnalyzedLiteral, AnalyzedSelectItem,
+    AnalyzedStatement, LiteralValue, Parameter, TableAccess,
+};
+use crate::ast::{CopyTarget, RoleStatement, Statement};
+use crate::auth;
 use crate::bootstrap;
 use crate::buffer_pool::BufferPoolManager;
+use crate::cancel::{self, CancelRegistry};
 use crate::catalog::Catalog;
 use crate::checkpoint;
 use crate::clog::{self, Clog};
+use crate::config::Config;
+use crate::copy::CopyFormat;
+use crate::datetime;
 use crate::disk::DiskManager;
-use crate::executor::{ExecutionEngine, Tuple};
+use crate::executor::{remove_temp_files, vacuum_table, ExecutionEngine, Tuple};
+use crate::functions::ScalarFunction;
 use crate::lock_manager::LockManager;
-use crate::parser::parse;
-use crate::protocol::{ColumnDesc, Connection, FrontendMessage};
+use crate::parser::{parse, parse_prepared};
+use crate::protocol::{
+    ClientStream, ColumnDesc, Connection, FrontendMessage, Negotiated, SqlError, Startup,
+    StartupMessage, Stream, TransactionStatus, INVALID_AUTHORIZATION_SPECIFICATION,
+    IN_FAILED_SQL_TRANSACTION, TOO_MANY_CONNECTIONS,
+};
 use crate::recovery::RecoveryManager;
+use crate::settings;
 use crate::transaction::Transaction;
 use crate::transaction_manager::TransactionManager;
-use crate::tuple::{DataType, Value};
+use crate::tuple::{format_binary, format_value, DataType, Value};
 use crate::wal::{WalManager, WalRecordType};
 
+mod copy;
+mod extended_query;
+mod pool;
+
+use copy::CopyIn;
+use extended_query::ExtendedQuery;
+use pool::{SessionPool, Slot};
+
 const DATA_DIR: &str = ".";
 const DATA_FILE: &str = "table.db";
 const WAL_DIR: &str = "wal";
 const PORT: u16 = 5433;
+/// How long a client turned away has to send its startup message
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Most clients being turned away at once
+const MAX_REJECTIONS: usize = 16;
 
 pub struct Instance {
     catalog: Arc<Catalog>,
@@ -37,7 +67,7 @@ pub struct Instance {
 
 struct QueryResult {
     columns: Vec<ColumnDesc>,
-    rows: Vec<Vec<Option<String>>>,
+    rows: Vec<Vec<Value>>,
     command_tag: String,
 }
 
@@ -48,10 +78,49 @@ enum ExecuteResult {
     Commit,
     Rollback,
     Checkpoint,
+    Vacuum,
+    Set,
+    CreateRole,
+    AlterRole,
+    /// COPY FROM STDIN, to be fed its data by run_copy
+    CopyIn(CopyIn),
+    /// COPY TO STDOUT: the rows of its query, sent by run_copy
+    CopyOut {
+        format: CopyFormat,
+        result: QueryResult,
+    },
+}
+
+impl ExecuteResult {
+    /// The rows and command tag sent to the client
+    fn into_query_result(self) -> QueryResult {
+        let command_tag = match self {
+            ExecuteResult::Query(result) => return result,
+            ExecuteResult::Begin => "BEGIN",
+            ExecuteResult::Commit => "COMMIT",
+            ExecuteResult::Rollback => "ROLLBACK",
+            ExecuteResult::Checkpoint => "CHECKPOINT",
+            ExecuteResult::Vacuum => "VACUUM",
+            ExecuteResult::Set => "SET",
+            ExecuteResult::CreateRole => "CREATE ROLE",
+            ExecuteResult::AlterRole => "ALTER ROLE",
+            ExecuteResult::CopyIn(_) | ExecuteResult::CopyOut { .. } => {
+                unreachable!("COPY results are handled by run_copy")
+            }
+        };
+        QueryResult {
+            columns: vec![],
+            rows: vec![],
+            command_tag: command_tag.to_string(),
+        }
+    }
 }
 
 impl Instance {
     pub fn new(init: bool) -> Result<Self> {
+        // Temp files of queries cut short by a shutdown or crash are garbage
+        remove_temp_files();
+
         if init {
             let _ = std::fs::remove_file(DATA_FILE);
             let _ = checkpoint::delete_checkpoint_meta(DATA_DIR);
@@ -70,8 +139,10 @@ impl Instance {
             let lock_manager = Arc::new(LockManager::new());
 
             // Bootstrap: create system catalog tables
-            // (also marks system transaction as committed in CLOG)
-            bootstrap::bootstrap(&bpm, &txn_manager)?;
+            // (also marks system transaction as committed in CLOG).
+            // Like initdb, name the superuser after the OS user.
+            let superuser = std::env::var("USER").unwrap_or_else(|_| "postgres".to_string());
+            bootstrap::bootstrap(&bpm, &txn_manager, &superuser)?;
 
             // Create catalog after bootstrap (needs BPM)
             let catalog = Arc::new(Catalog::new(Arc::clone(&bpm)));
@@ -114,38 +185,33 @@ impl Instance {
     }
 
     pub fn start(&self) -> Result<()> {
-        let listener = TcpListener::bind(format!("127.0.0.1:{PORT}"))?;
+        let config = Config::load()?;
+        let tls = config.tls()?;
+        let cancel_registry = Arc::new(CancelRegistry::new());
+        let pool = SessionPool::new(config.max_connections, config.superuser_reserved_connections);
+
+        let listener = TcpListener::bind((config.listen_address, PORT))?;
         println!("=== PostgreSQL Wire Protocol Server (Multi-threaded) ===");
-        println!("Listening on port {PORT}");
+        println!("Listening on {}:{PORT}", config.listen_address);
         println!("Connect with: psql -h localhost -p {PORT}");
+        println!("Max connections: {}", config.max_connections);
+        if tls.is_some() {
+            println!("TLS enabled");
+        }
+        if !Path::new(auth::HBA_FILE).exists() {
+            println!(
+                "WARNING: no {}, trusting loopback connections and requiring \
+                 scram-sha-256 from other addresses",
+                auth::HBA_FILE
+            );
+        }
         println!();
 
+        let rejections = Arc::new(AtomicUsize::new(0));
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    println!("[Server] New connection from {:?}", stream.peer_addr());
-                    let conn = Connection::new(stream);
-
-                    // Clone Arc references for the new thread
-                    let catalog = Arc::clone(&self.catalog);
-                    let bpm = Arc::clone(&self.bpm);
-                    let lock_manager = Arc::clone(&self.lock_manager);
-                    let wal_manager = Arc::clone(&self.wal_manager);
-                    let txn_manager = Arc::clone(&self.txn_manager);
-
-                    // Spawn a new thread for each connection
-                    thread::spawn(move || {
-                        if let Err(e) = Self::handle_client(
-                            conn,
-                            catalog,
-                            bpm,
-                            lock_manager,
-                            wal_manager,
-                            txn_manager,
-                        ) {
-                            println!("[Server] Error handling client: {e}");
-                        }
-                    });
+                    self.accept_client(stream, &config, &tls, &pool, &cancel_registry, &rejections)
                 }
                 Err(e) => {
                     println!("[Server] Accept error: {e}");
@@ -156,124 +222,388 @@ impl Instance {
         Ok(())
     }
 
-    fn handle_client(
-        mut conn: Connection,
+    /// Hand a new client to a session worker, or turn it away when all
+    /// connection slots are in use. The client holds its slot from here, so
+    /// it has authentication_timeout to authenticate before losing it.
+    fn accept_client(
+        &self,
+        stream: TcpStream,
+        config: &Config,
+        tls: &Option<Arc<rustls::ServerConfig>>,
+        pool: &SessionPool,
+        cancel_registry: &Arc<CancelRegistry>,
+        rejections: &Arc<AtomicUsize>,
+    ) {
+        println!("[Server] New connection from {:?}", stream.peer_addr());
+        let Some(slot) = pool.try_acquire() else {
+            Self::reject_connection(
+                stream,
+                "sorry, too many clients already",
+                cancel_registry,
+                rejections,
+            );
+            return;
+        };
+        let tls_required = stream
+            .peer_addr()
+            .is_ok_and(|addr| config.requires_tls(addr.ip()));
+        let mut conn = Connection::new(ClientStream::new(stream));
+        if let Err(e) = conn.set_deadline(Some(Instant::now() + config.authentication_timeout)) {
+            println!("[Server] Error handling client: {e}");
+            return;
+        }
+
+        // Clone Arc references for the session worker
+        let catalog = Arc::clone(&self.catalog);
+        let bpm = Arc::clone(&self.bpm);
+        let lock_manager = Arc::clone(&self.lock_manager);
+        let wal_manager = Arc::clone(&self.wal_manager);
+        let txn_manager = Arc::clone(&self.txn_manager);
+        let tls = tls.clone();
+        let cancel_registry = Arc::clone(cancel_registry);
+
+        let started = pool.execute(move || {
+            let result = match conn.negotiate(tls.as_ref()) {
+                // The connection only carries the request
+                Ok((_, Startup::Cancel { pid, secret })) => {
+                    if cancel_registry.cancel(pid, secret) {
+                        println!("[Server] Cancel request for process {pid}");
+                    }
+                    Ok(())
+                }
+                Ok((Negotiated::Tls(mut conn), Startup::Session(startup))) => {
+                    Self::handle_client(
+                        &mut *conn,
+                        startup,
+                        catalog,
+                        bpm,
+                        lock_manager,
+                        wal_manager,
+                        txn_manager,
+                        &cancel_registry,
+                        &slot,
+                    )
+                }
+                Ok((Negotiated::Plain(mut conn), _)) if tls_required => {
+                    let host = conn.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                    conn.send_error(
+                        &SqlError::fatal(
+                            INVALID_AUTHORIZATION_SPECIFICATION,
+                            format!("SSL connection is required for host \"{host}\""),
+                        )
+                        .into(),
+                    )
+                }
+                Ok((Negotiated::Plain(mut conn), Startup::Session(startup))) => {
+                    Self::handle_client(
+                        &mut conn,
+                        startup,
+                        catalog,
+                        bpm,
+                        lock_manager,
+                        wal_manager,
+                        txn_manager,
+                        &cancel_registry,
+                        &slot,
+                    )
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                println!("[Server] Error handling client: {e}");
+            }
+        });
+        // The session and its slot are dropped with the job
+        if let Err(e) = started {
+            println!("[Server] Error handling client: {e}");
+        }
+    }
+
+    /// Turn a client away without a session worker: the error follows its
+    /// startup message, in plain text, and cancel requests still go through.
+    /// This runs off the accept loop, on one of at most MAX_REJECTIONS
+    /// threads; a client that has not sent its startup message within
+    /// REJECT_TIMEOUT is disconnected. Beyond MAX_REJECTIONS clients are
+    /// disconnected at once.
+    fn reject_connection(
+        stream: TcpStream,
+        message: &'static str,
+        cancel_registry: &Arc<CancelRegistry>,
+        rejections: &Arc<AtomicUsize>,
+    ) {
+        let taken = rejections.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+            (n < MAX_REJECTIONS).then_some(n + 1)
+        });
+        if taken.is_err() {
+            println!("[Server] Too many clients being rejected, closing connection");
+            return;
+        }
+        let cancel_registry = Arc::clone(cancel_registry);
+        let rejections = Arc::clone(rejections);
+        thread::spawn(move || {
+            let mut conn = Connection::new(ClientStream::new(stream));
+            let negotiated = conn
+                .set_deadline(Some(Instant::now() + REJECT_TIMEOUT))
+                .and_then(|()| conn.negotiate(None));
+            match negotiated {
+                Ok((_, Startup::Cancel { pid, secret })) => {
+                    cancel_registry.cancel(pid, secret);
+                }
+                Ok((Negotiated::Plain(mut conn), Startup::Session(_))) => {
+                    println!("[Server] Rejected client: {message}");
+                    let _ = conn.send_error(&SqlError::fatal(TOO_MANY_CONNECTIONS, message).into());
+                }
+                _ => {}
+            }
+            rejections.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn handle_client<S: Stream>(
+        conn: &mut Connection<S>,
+        startup: StartupMessage,
         catalog: Arc<Catalog>,
         bpm: Arc<Mutex<BufferPoolManager>>,
         lock_manager: Arc<LockManager>,
         wal_manager: Arc<WalManager>,
         txn_manager: Arc<TransactionManager>,
+        cancel_registry: &Arc<CancelRegistry>,
+        slot: &Slot,
     ) -> Result<()> {
-        // Read startup message
-        let startup = conn.read_startup()?;
         println!(
             "[Server] Startup: {:?} (thread: {:?})",
             startup.params,
             thread::current().id()
         );
 
-        // Send authentication OK
+        let user = match auth::authenticate(conn, &catalog, &startup) {
+            Ok(user) => user,
+            Err(e) => {
+                println!("[Server] Authentication failed: {e}");
+                // The client may already have hung up
+                let _ = conn.send_error(&e);
+                return Ok(());
+            }
+        };
+        // The last free slots are kept for superusers
+        if slot.is_reserved() && !catalog.get_role(&user).is_some_and(|role| role.superuser) {
+            println!("[Server] Only reserved connection slots left, rejecting {user}");
+            let _ = conn.send_error(
+                &SqlError::fatal(
+                    TOO_MANY_CONNECTIONS,
+                    "remaining connection slots are reserved for roles with the SUPERUSER attribute",
+                )
+                .into(),
+            );
+            return Ok(());
+        }
+        // Authenticated in time; the session itself has no deadline
+        conn.set_deadline(None)?;
+        // Worker threads are reused, so drop the previous session's settings
+        settings::reset_session();
+        settings::set_session_user(&user);
         conn.send_auth_ok()?;
 
         // Send some parameter status messages
         conn.send_parameter_status("server_version", "0.0.1")?;
         conn.send_parameter_status("client_encoding", "UTF8")?;
+        conn.send_parameter_status("DateStyle", "ISO, MDY")?;
+        conn.send_parameter_status("TimeZone", "UTC")?;
 
-        // Send backend key data
-        conn.send_backend_key_data(1, 12345)?;
+        // Send backend key data; the key is withdrawn when the session ends
+        let key = cancel_registry.register();
+        conn.send_backend_key_data(key.pid, key.secret)?;
 
         // Ready for query
-        conn.send_ready_for_query()?;
+        conn.send_ready_for_query(TransactionStatus::Idle)?;
 
         // Transaction state for this connection
         let mut txn = Transaction::new();
 
-        // Main query loop
-        loop {
-            match conn.read_message()? {
-                Some(FrontendMessage::Query(sql)) => {
-                    println!(
-                        "[Server] Query: {sql} (thread: {:?})",
-                        thread::current().id()
-                    );
-
-                    if sql.trim().is_empty() {
-                        conn.send_empty_query()?;
-                        conn.send_ready_for_query()?;
-                        continue;
-                    }
+        // Prepared statements and portals of the extended query protocol.
+        // After an error, messages are discarded until the next Sync.
+        let mut extended = ExtendedQuery::default();
+        let mut skip_until_sync = false;
+
+        // Main query loop. A session that fails or panics still ends below,
+        // so its transaction is rolled back and its locks are released
+        // before the worker serves another client.
+        let session = panic::catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+            loop {
+                match conn.read_message()? {
+                    // Discarding after an error; even a Query waits for Sync
+                    Some(message)
+                        if skip_until_sync
+                            && !matches!(
+                                message,
+                                FrontendMessage::Sync | FrontendMessage::Terminate
+                            ) => {}
+                    Some(FrontendMessage::Query(sql)) => {
+                        println!(
+                            "[Server] Query: {sql} (thread: {:?})",
+                            thread::current().id()
+                        );
+
+                        if sql.trim().is_empty() {
+                            conn.send_empty_query()?;
+                            conn.send_ready_for_query(Self::transaction_status(&txn))?;
+                            continue;
+                        }
 
-                    match Self::execute_sql_internal(
-                        &sql,
-                        &catalog,
-                        &bpm,
-                        &lock_manager,
-                        &wal_manager,
-                        &txn_manager,
-                        &mut txn,
-                    ) {
-                        Ok(ExecuteResult::Query(result)) => {
-                            if !result.columns.is_empty() {
-                                conn.send_row_description(&result.columns)?;
-                                for row in &result.rows {
-                                    conn.send_data_row(row)?;
+                        // A simple query replaces the unnamed statement and portal
+                        extended.close_unnamed();
+
+                        cancel::start_statement();
+                        let in_block = txn.is_active() && !txn.implicit;
+                        match Self::execute_sql_internal(
+                            &sql,
+                            &catalog,
+                            &bpm,
+                            &lock_manager,
+                            &wal_manager,
+                            &txn_manager,
+                            &mut txn,
+                        )
+                        .and_then(|result| {
+                            Self::run_copy(
+                                result,
+                                conn,
+                                &catalog,
+                                &bpm,
+                                &lock_manager,
+                                &wal_manager,
+                                &txn_manager,
+                                &mut txn,
+                            )
+                        }) {
+                            Ok(result) => {
+                                if !result.columns.is_empty() {
+                                    conn.send_row_description(&result.columns, &[])?;
+                                    for row in &result.rows {
+                                        conn.send_data_row(&Self::encode_row(row, &[])?)?;
+                                    }
+                                }
+                                conn.send_command_complete(&result.command_tag)?;
+                                // A simple query also ends the implicit transaction
+                                // of the extended query messages before it
+                                if txn.implicit {
+                                    Self::commit_transaction(
+                                        &lock_manager,
+                                        &wal_manager,
+                                        &txn_manager,
+                                        &mut txn,
+                                    );
                                 }
                             }
-                            conn.send_command_complete(&result.command_tag)?;
-                        }
-                        Ok(ExecuteResult::Begin) => {
-                            conn.send_command_complete("BEGIN")?;
-                        }
-                        Ok(ExecuteResult::Commit) => {
-                            conn.send_command_complete("COMMIT")?;
+                            Err(e) => {
+                                println!("[Server] Error: {e}");
+                                conn.send_error(&e)?;
+                                Self::statement_failed(
+                                    in_block,
+                                    &bpm,
+                                    &lock_manager,
+                                    &wal_manager,
+                                    &txn_manager,
+                                    &mut txn,
+                                );
+                            }
                         }
-                        Ok(ExecuteResult::Rollback) => {
-                            conn.send_command_complete("ROLLBACK")?;
+                        conn.send_ready_for_query(Self::transaction_status(&txn))?;
+                    }
+                    Some(FrontendMessage::Sync) => {
+                        skip_until_sync = false;
+                        // The messages since the last Sync commit together
+                        if txn.implicit {
+                            Self::commit_transaction(
+                                &lock_manager,
+                                &wal_manager,
+                                &txn_manager,
+                                &mut txn,
+                            );
                         }
-                        Ok(ExecuteResult::Checkpoint) => {
-                            conn.send_command_complete("CHECKPOINT")?;
+                        // Portals do not outlive the transaction they were created in
+                        if !txn.is_active() {
+                            extended.close_portals();
                         }
-                        Err(e) => {
+                        conn.send_ready_for_query(Self::transaction_status(&txn))?;
+                    }
+                    Some(FrontendMessage::Flush) => {}
+                    // Left over from a COPY FROM STDIN that failed
+                    Some(
+                        FrontendMessage::CopyData(_)
+                        | FrontendMessage::CopyDone
+                        | FrontendMessage::CopyFail(_),
+                    ) => {}
+                    Some(FrontendMessage::Terminate) => {
+                        println!(
+                            "[Server] Client disconnected (thread: {:?})",
+                            thread::current().id()
+                        );
+                        break;
+                    }
+                    Some(
+                        message @ (FrontendMessage::Parse { .. }
+                        | FrontendMessage::Bind { .. }
+                        | FrontendMessage::Describe(_)
+                        | FrontendMessage::Execute { .. }
+                        | FrontendMessage::Close(_)),
+                    ) => {
+                        cancel::start_statement();
+                        // A failure rolls back an implicit transaction as a whole
+                        let in_block = txn.is_active() && !txn.implicit;
+                        if let Err(e) = Self::handle_extended_message(
+                            message,
+                            conn,
+                            &mut extended,
+                            &catalog,
+                            &bpm,
+                            &lock_manager,
+                            &wal_manager,
+                            &txn_manager,
+                            &mut txn,
+                        ) {
                             println!("[Server] Error: {e}");
-                            conn.send_error(&e.to_string())?;
+                            conn.send_error(&e)?;
+                            skip_until_sync = true;
+                            Self::statement_failed(
+                                in_block,
+                                &bpm,
+                                &lock_manager,
+                                &wal_manager,
+                                &txn_manager,
+                                &mut txn,
+                            );
                         }
                     }
-                    conn.send_ready_for_query()?;
-                }
-                Some(FrontendMessage::Terminate) => {
-                    println!(
-                        "[Server] Client disconnected (thread: {:?})",
-                        thread::current().id()
-                    );
-                    break;
-                }
-                Some(FrontendMessage::Unknown(t)) => {
-                    println!("[Server] Unknown message type: {t}");
-                }
-                None => {
-                    println!(
-                        "[Server] Connection closed (thread: {:?})",
-                        thread::current().id()
-                    );
-                    break;
+                    Some(FrontendMessage::Unknown(t)) => {
+                        println!("[Server] Unknown message type: {t}");
+                    }
+                    None => {
+                        println!(
+                            "[Server] Connection closed (thread: {:?})",
+                            thread::current().id()
+                        );
+                        break;
+                    }
                 }
             }
-        }
+            Ok(())
+        }));
 
-        // If transaction is still active, rollback on disconnect
+        // If transaction is still active, rollback on disconnect, error or panic
         if txn.is_active() {
-            let txn_id = txn.id;
-            let last_lsn = txn.last_lsn;
-            let undo_log = txn.take_undo_log();
-            let _ =
-                ExecutionEngine::perform_rollback(&bpm, &wal_manager, txn_id, undo_log, last_lsn);
-            // Write Abort record
-            wal_manager.append(txn_id, last_lsn, WalRecordType::Abort);
-            // Remove from ATT
-            txn_manager.abort(txn_id);
-            // Release all locks
-            let held_locks = txn.take_held_locks();
-            lock_manager.unlock_all(txn_id, &held_locks);
+            let _ = Self::rollback_transaction(
+                &bpm,
+                &lock_manager,
+                &wal_manager,
+                &txn_manager,
+                &mut txn,
+            );
+        }
+        match session {
+            Ok(result) => result?,
+            Err(panic) => panic::resume_unwind(panic),
         }
 
         // Flush data to disk before closing
@@ -281,6 +611,102 @@ impl Instance {
         Ok(())
     }
 
+    /// Undo the changes of the connection's transaction and end it
+    fn rollback_transaction(
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) -> Result<()> {
+        let txn_id = txn.id;
+        let last_lsn = txn.last_lsn;
+        let undo_log = txn.take_undo_log();
+        // Perform rollback with CLR writing
+        let new_last_lsn =
+            ExecutionEngine::perform_rollback(bpm, wal_manager, txn_id, undo_log, last_lsn)?;
+        // Write WAL Abort record
+        wal_manager.append(txn_id, new_last_lsn, WalRecordType::Abort);
+        wal_manager.flush();
+        // Remove from ATT
+        txn_manager.abort(txn_id);
+        // Release all locks after rollback
+        let held_locks = txn.take_held_locks();
+        lock_manager.unlock_all(txn_id, &held_locks);
+        Ok(())
+    }
+
+    /// Open a transaction: for BEGIN, or for the messages up to Sync
+    fn begin_transaction(
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) {
+        // Get transaction ID from TransactionManager
+        let txn_id = txn_manager.begin();
+        // Take snapshot at transaction start for REPEATABLE READ
+        let snapshot = txn_manager.get_snapshot(txn_id);
+        txn.begin_with_id(txn_id, snapshot);
+        // Write WAL Begin record (prev_lsn = 0 for first record)
+        let lsn = wal_manager.append(txn.id, 0, WalRecordType::Begin);
+        txn.set_last_lsn(lsn);
+        txn_manager.update_last_lsn(txn_id, lsn);
+    }
+
+    /// Make the changes of the connection's transaction permanent and end it
+    fn commit_transaction(
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) {
+        let txn_id = txn.id;
+        // Write WAL Commit record and flush for durability
+        wal_manager.append(txn_id, txn.last_lsn, WalRecordType::Commit);
+        wal_manager.flush();
+        // Remove from ATT
+        txn_manager.commit(txn_id);
+        // Release all locks before commit
+        let held_locks = txn.take_held_locks();
+        lock_manager.unlock_all(txn_id, &held_locks);
+        txn.commit();
+    }
+
+    /// After a statement fails, a transaction block it ran in is aborted
+    /// until ROLLBACK, and the implicit transaction of a statement run on its
+    /// own is rolled back at once
+    fn statement_failed(
+        in_block: bool,
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) {
+        if in_block {
+            if txn.is_active() {
+                txn.set_aborted();
+            }
+        } else if txn.is_active() {
+            if let Err(e) =
+                Self::rollback_transaction(bpm, lock_manager, wal_manager, txn_manager, txn)
+            {
+                println!("[Server] Rollback of failed statement failed: {e}");
+            }
+        }
+    }
+
+    /// Transaction status for ReadyForQuery
+    fn transaction_status(txn: &Transaction) -> TransactionStatus {
+        if txn.is_aborted() {
+            TransactionStatus::Failed
+        } else if txn.is_active() {
+            TransactionStatus::InTransaction
+        } else {
+            TransactionStatus::Idle
+        }
+    }
+
     fn execute_sql_internal(
         sql: &str,
         catalog: &Catalog,
@@ -300,63 +726,161 @@ impl Instance {
         }
 
         let stmt = parse(sql)?;
+        Self::execute_statement(
+            &stmt,
+            &[],
+            catalog,
+            bpm,
+            lock_manager,
+            wal_manager,
+            txn_manager,
+            txn,
+        )
+    }
+
+    /// CREATE ROLE or ALTER ROLE, run as an INSERT into or UPDATE of
+    /// pg_authid so that it is transactional like any other write
+    #[allow(clippy::too_many_arguments)]
+    fn execute_role(
+        create: bool,
+        role: &RoleStatement,
+        catalog: &Catalog,
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) -> Result<()> {
+        let action = if create { "create" } else { "alter" };
+        if !catalog
+            .get_role(&settings::session_user())
+            .is_some_and(|r| r.superuser)
+        {
+            anyhow::bail!("permission denied to {action} role");
+        }
+        let (sql, current) = match (create, catalog.get_role(&role.name)) {
+            (true, Some(_)) => anyhow::bail!("role \"{}\" already exists", role.name),
+            (false, None) => anyhow::bail!("role \"{}\" does not exist", role.name),
+            (true, None) => ("INSERT INTO pg_authid VALUES ($1, $2, $3, $4)", None),
+            (false, Some(current)) => (
+                "UPDATE pg_authid SET rolsuper = $2, rolcanlogin = $3, rolpassword = $4 \
+                 WHERE rolname = $1",
+                Some(current),
+            ),
+        };
+
+        // Options not given keep their current value, or the default
+        let password = match &role.password {
+            // An empty password is no password, as in PostgreSQL
+            Some(Some(password)) if !password.is_empty() => Some(auth::encrypt_password(
+                &role.name,
+                password,
+                settings::password_scram(),
+            )),
+            Some(_) => None,
+            None => current.as_ref().and_then(|c| c.password.clone()),
+        };
+        let values = [
+            (DataType::Varchar(None), Value::Varchar(role.name.clone())),
+            (
+                DataType::Bool,
+                Value::Bool(
+                    role.superuser
+                        .unwrap_or(current.as_ref().is_some_and(|c| c.superuser)),
+                ),
+            ),
+            (
+                DataType::Bool,
+                Value::Bool(
+                    role.login
+                        .unwrap_or(current.as_ref().is_some_and(|c| c.can_login)),
+                ),
+            ),
+            (
+                DataType::Varchar(None),
+                password.map_or(Value::Null, Value::Varchar),
+            ),
+        ];
+        let parameters: Vec<Parameter> = values
+            .into_iter()
+            .map(|(data_type, value)| Parameter {
+                data_type: Some(data_type),
+                value: Some(value),
+            })
+            .collect();
+        let (statement, _) = parse_prepared(sql)?;
+        Self::execute_statement(
+            &statement,
+            &parameters,
+            catalog,
+            bpm,
+            lock_manager,
+            wal_manager,
+            txn_manager,
+            txn,
+        )?;
+        Ok(())
+    }
+
+    /// Execute a parsed statement, with the values bound to its parameters
+    #[allow(clippy::too_many_arguments)]
+    fn execute_statement(
+        stmt: &Statement,
+        parameters: &[Parameter],
+        catalog: &Catalog,
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) -> Result<ExecuteResult> {
+        // A failed transaction block only accepts its end
+        if txn.is_aborted() && !matches!(stmt, Statement::Commit | Statement::Rollback) {
+            return Err(SqlError::error(
+                IN_FAILED_SQL_TRANSACTION,
+                "current transaction is aborted, commands ignored until end of transaction block",
+            )
+            .into());
+        }
+
+        // now() is the start time of the transaction: BEGIN, or this statement
+        // when it runs on its own
+        if !txn.is_active() {
+            datetime::set_transaction_timestamp();
+        }
 
         // Handle transaction control and utility statements before analyze
-        match &stmt {
+        match stmt {
             Statement::Begin => {
+                // BEGIN turns an implicit transaction into a block, which
+                // keeps what ran before it
+                if txn.implicit {
+                    txn.implicit = false;
+                    return Ok(ExecuteResult::Begin);
+                }
                 if txn.is_active() {
                     anyhow::bail!("there is already a transaction in progress");
                 }
-                // Get transaction ID from TransactionManager
-                let txn_id = txn_manager.begin();
-                // Take snapshot at transaction start for REPEATABLE READ
-                let snapshot = txn_manager.get_snapshot(txn_id);
-                txn.begin_with_id(txn_id, snapshot);
-                // Write WAL Begin record (prev_lsn = 0 for first record)
-                let lsn = wal_manager.append(txn.id, 0, WalRecordType::Begin);
-                txn.set_last_lsn(lsn);
-                txn_manager.update_last_lsn(txn_id, lsn);
+                Self::begin_transaction(wal_manager, txn_manager, txn);
                 return Ok(ExecuteResult::Begin);
             }
             Statement::Commit => {
                 if !txn.is_active() {
                     anyhow::bail!("there is no transaction in progress");
                 }
-                let txn_id = txn.id;
-                // Write WAL Commit record and flush for durability
-                wal_manager.append(txn_id, txn.last_lsn, WalRecordType::Commit);
-                wal_manager.flush();
-                // Remove from ATT
-                txn_manager.commit(txn_id);
-                // Release all locks before commit
-                let held_locks = txn.take_held_locks();
-                lock_manager.unlock_all(txn_id, &held_locks);
-                txn.commit();
+                // COMMIT of a failed transaction block rolls it back
+                if txn.is_aborted() {
+                    Self::rollback_transaction(bpm, lock_manager, wal_manager, txn_manager, txn)?;
+                    return Ok(ExecuteResult::Rollback);
+                }
+                Self::commit_transaction(lock_manager, wal_manager, txn_manager, txn);
                 return Ok(ExecuteResult::Commit);
             }
             Statement::Rollback => {
                 if !txn.is_active() {
                     anyhow::bail!("there is no transaction in progress");
                 }
-                let txn_id = txn.id;
-                let last_lsn = txn.last_lsn;
-                let undo_log = txn.take_undo_log();
-                // Perform rollback with CLR writing
-                let new_last_lsn = ExecutionEngine::perform_rollback(
-                    bpm,
-                    wal_manager,
-                    txn_id,
-                    undo_log,
-                    last_lsn,
-                )?;
-                // Write WAL Abort record
-                wal_manager.append(txn_id, new_last_lsn, WalRecordType::Abort);
-                wal_manager.flush();
-                // Remove from ATT
-                txn_manager.abort(txn_id);
-                // Release all locks after rollback
-                let held_locks = txn.take_held_locks();
-                lock_manager.unlock_all(txn_id, &held_locks);
+                Self::rollback_transaction(bpm, lock_manager, wal_manager, txn_manager, txn)?;
                 return Ok(ExecuteResult::Rollback);
             }
             Statement::Checkpoint => {
@@ -364,10 +888,88 @@ impl Instance {
                 Self::perform_checkpoint(bpm, wal_manager, txn_manager)?;
                 return Ok(ExecuteResult::Checkpoint);
             }
+            Statement::Vacuum(table_name) => {
+                if txn.is_active() {
+                    anyhow::bail!("VACUUM cannot run inside a transaction block");
+                }
+                let tables = match table_name {
+                    Some(name) => {
+                        let table = catalog.get_table(name).ok_or_else(|| {
+                            anyhow::anyhow!("relation \"{name}\" does not exist")
+                        })?;
+                        check_table_access(catalog, &table, TableAccess::Modify)?;
+                        vec![table]
+                    }
+                    // Like PostgreSQL, skip the tables the user may not vacuum
+                    None => catalog
+                        .get_tables()
+                        .into_iter()
+                        .filter(|t| check_table_access(catalog, t, TableAccess::Modify).is_ok())
+                        .collect(),
+                };
+                for table in &tables {
+                    let removed = vacuum_table(bpm, table, txn_manager, wal_manager)?;
+                    println!("[Vacuum] {}: removed {removed} dead tuples", table.name);
+                }
+                return Ok(ExecuteResult::Vacuum);
+            }
+            Statement::Set { name, value } => {
+                settings::set(name, value.as_deref())?;
+                return Ok(ExecuteResult::Set);
+            }
+            Statement::Show(name) => {
+                let value = settings::show(name)?;
+                return Ok(ExecuteResult::Query(QueryResult {
+                    columns: vec![ColumnDesc::new_text(&name.to_ascii_lowercase())],
+                    rows: vec![vec![Value::Varchar(value)]],
+                    command_tag: "SHOW".to_string(),
+                }));
+            }
+            Statement::CreateRole(role) | Statement::AlterRole(role) => {
+                let create = matches!(stmt, Statement::CreateRole(_));
+                Self::execute_role(
+                    create,
+                    role,
+                    catalog,
+                    bpm,
+                    lock_manager,
+                    wal_manager,
+                    txn_manager,
+                    txn,
+                )?;
+                return Ok(match create {
+                    true => ExecuteResult::CreateRole,
+                    false => ExecuteResult::AlterRole,
+                });
+            }
+            Statement::Copy(copy) => {
+                let format = CopyFormat::new(&copy.options)?;
+                return match &copy.target {
+                    CopyTarget::From { table, columns } => Ok(ExecuteResult::CopyIn(
+                        Self::copy_in(catalog, table, columns, format)?,
+                    )),
+                    CopyTarget::To(query) => {
+                        let ExecuteResult::Query(result) = Self::execute_statement(
+                            &Statement::Select((**query).clone()),
+                            parameters,
+                            catalog,
+                            bpm,
+                            lock_manager,
+                            wal_manager,
+                            txn_manager,
+                            txn,
+                        )?
+                        else {
+                            unreachable!("SELECT returns rows")
+                        };
+                        Ok(ExecuteResult::CopyOut { format, result })
+                    }
+                };
+            }
             _ => {}
         }
 
-        let analyzed = analyze(catalog, &stmt)?;
+        let analyzed = analyze_with_parameters(catalog, stmt, &mut parameters.to_vec())?;
 
         // Pass transaction and lock_manager to engine
         let txn_is_active = txn.is_active();
@@ -392,8 +994,7 @@ impl Instance {
                     Some(txn_manager.as_ref()),
                 )?;
                 let results = engine.execute()?;
-                let rows: Vec<Vec<Option<String>>> =
-                    results.iter().map(Self::tuple_to_row).collect();
+                let rows: Vec<Vec<Value>> = results.into_iter().map(|t| t.values).collect();
 
                 let row_count = rows.len();
                 Ok(ExecuteResult::Query(QueryResult {
@@ -402,7 +1003,7 @@ impl Instance {
                     command_tag: format!("SELECT {row_count}"),
                 }))
             }
-            AnalyzedStatement::Insert(_) => {
+            AnalyzedStatement::Insert(insert_stmt) => {
                 // MVCC requires a transaction for proper xmin handling
                 let autocommit = !txn_is_active;
                 if autocommit {
@@ -415,7 +1016,7 @@ impl Instance {
                     txn_manager.update_last_lsn(txn_id, lsn);
                 }
 
-                let count = {
+                let results = {
                     let mut engine = ExecutionEngine::new(
                         Arc::clone(bpm),
                         catalog,
@@ -425,8 +1026,7 @@ impl Instance {
                         Some(Arc::clone(wal_manager)),
                         Some(txn_manager.as_ref()),
                     )?;
-                    let results = engine.execute()?;
-                    results.len()
+                    engine.execute()?
                 };
 
                 if autocommit {
@@ -440,11 +1040,11 @@ impl Instance {
                     txn.commit();
                 }
 
-                Ok(ExecuteResult::Query(QueryResult {
-                    columns: vec![],
-                    rows: vec![],
-                    command_tag: format!("INSERT 0 {count}"),
-                }))
+                Ok(ExecuteResult::Query(Self::dml_result(
+                    "INSERT 0",
+                    &insert_stmt.returning,
+                    results,
+                )))
             }
             AnalyzedStatement::CreateTable(_) => {
                 // DDL requires a transaction for WAL and undo support
@@ -528,7 +1128,7 @@ impl Instance {
                     command_tag: "CREATE INDEX".to_string(),
                 }))
             }
-            AnalyzedStatement::Delete(_) => {
+            AnalyzedStatement::Delete(delete_stmt) => {
                 // MVCC requires a transaction for proper xmax handling
                 let autocommit = !txn_is_active;
                 if autocommit {
@@ -541,7 +1141,7 @@ impl Instance {
                     txn_manager.update_last_lsn(txn_id, lsn);
                 }
 
-                let count = {
+                let results = {
                     let mut engine = ExecutionEngine::new(
                         Arc::clone(bpm),
                         catalog,
@@ -551,8 +1151,7 @@ impl Instance {
                         Some(Arc::clone(wal_manager)),
                         Some(txn_manager.as_ref()),
                     )?;
-                    let results = engine.execute()?;
-                    results.len() as i32
+                    engine.execute()?
                 };
 
                 if autocommit {
@@ -566,13 +1165,13 @@ impl Instance {
                     txn.commit();
                 }
 
-                Ok(ExecuteResult::Query(QueryResult {
-                    columns: vec![],
-                    rows: vec![],
-                    command_tag: format!("DELETE {count}"),
-                }))
+                Ok(ExecuteResult::Query(Self::dml_result(
+                    "DELETE",
+                    &delete_stmt.returning,
+                    results,
+                )))
             }
-            AnalyzedStatement::Update(_) => {
+            AnalyzedStatement::Update(update_stmt) => {
                 // MVCC requires a transaction for proper xmax handling
                 let autocommit = !txn_is_active;
                 if autocommit {
@@ -585,7 +1184,7 @@ impl Instance {
                     txn_manager.update_last_lsn(txn_id, lsn);
                 }
 
-                let count = {
+                let results = {
                     let mut engine = ExecutionEngine::new(
                         Arc::clone(bpm),
                         catalog,
@@ -595,8 +1194,7 @@ impl Instance {
                         Some(Arc::clone(wal_manager)),
                         Some(txn_manager.as_ref()),
                     )?;
-                    let results = engine.execute()?;
-                    results.len() as i32
+                    engine.execute()?
                 };
 
                 if autocommit {
@@ -610,11 +1208,11 @@ impl Instance {
                     txn.commit();
                 }
 
-                Ok(ExecuteResult::Query(QueryResult {
-                    columns: vec![],
-                    rows: vec![],
-                    command_tag: format!("UPDATE {count}"),
-                }))
+                Ok(ExecuteResult::Query(Self::dml_result(
+                    "UPDATE",
+                    &update_stmt.returning,
+                    results,
+                )))
             }
         }
     }
@@ -673,6 +1271,28 @@ impl Instance {
         Ok(())
     }
 
+    // INSERT/UPDATE/DELETE result: the RETURNING rows if requested, otherwise only a tag.
+    // Without RETURNING the executor yields a single tuple holding the affected row count.
+    fn dml_result(tag: &str, returning: &[AnalyzedSelectItem], results: Vec<Tuple>) -> QueryResult {
+        if returning.is_empty() {
+            let count = match results.first().map(|t| &t.values[..]) {
+                Some([Value::Int(n)]) => *n as usize,
+                _ => 0,
+            };
+            QueryResult {
+                columns: vec![],
+                rows: vec![],
+                command_tag: format!("{tag} {count}"),
+            }
+        } else {
+            QueryResult {
+                columns: returning.iter().map(Self::get_column_desc).collect(),
+                command_tag: format!("{tag} {}", results.len()),
+                rows: results.into_iter().map(|t| t.values).collect(),
+            }
+        }
+    }
+
     fn get_column_desc(item: &AnalyzedSelectItem) -> ColumnDesc {
         let data_type = item.expr.data_type();
         let name = item
@@ -680,42 +1300,593 @@ impl Instance {
             .clone()
             .unwrap_or_else(|| Self::format_expr_name(&item.expr));
 
+        Self::type_desc(&name, data_type).with_type_modifier(data_type.typmod())
+    }
+
+    fn type_desc(name: &str, data_type: &DataType) -> ColumnDesc {
         match data_type {
-            DataType::Int => ColumnDesc::new_int(&name),
-            DataType::Varchar => ColumnDesc::new_varchar(&name),
-            DataType::Bool => ColumnDesc::new_bool(&name),
+            DataType::Int => ColumnDesc::new_int(name),
+            DataType::Varchar(_) => ColumnDesc::new_varchar(name),
+            DataType::Char(_) => ColumnDesc::new_bpchar(name),
+            DataType::Text => ColumnDesc::new_text(name),
+            DataType::Bool => ColumnDesc::new_bool(name),
+            DataType::SmallInt => ColumnDesc::new_int2(name),
+            DataType::BigInt => ColumnDesc::new_int8(name),
+            DataType::Real => ColumnDesc::new_float4(name),
+            DataType::Double => ColumnDesc::new_float8(name),
+            DataType::Numeric(_) => ColumnDesc::new_numeric(name),
+            DataType::Date => ColumnDesc::new_date(name),
+            DataType::Time => ColumnDesc::new_time(name),
+            DataType::Timestamp => ColumnDesc::new_timestamp(name),
+            DataType::TimestampTz => ColumnDesc::new_timestamptz(name),
+            DataType::Interval => ColumnDesc::new_interval(name),
+            DataType::Bytea => ColumnDesc::new_bytea(name),
+            DataType::Jsonb => ColumnDesc::new_jsonb(name),
+            DataType::Array(element) => Self::type_desc(name, element).into_array(),
         }
     }
 
     fn format_expr_name(expr: &AnalyzedExpr) -> String {
         match expr {
             AnalyzedExpr::ColumnRef(col_ref) => col_ref.column_name.clone(),
+            // Typed constants are named after their type, as in PostgreSQL
+            AnalyzedExpr::Literal(AnalyzedLiteral {
+                value: LiteralValue::Typed(_),
+                data_type,
+            }) => Self::type_column_name(data_type),
+            AnalyzedExpr::Literal(AnalyzedLiteral {
+                value: LiteralValue::Parameter(_),
+                ..
+            }) => "?column?".to_string(),
             AnalyzedExpr::Literal(lit) => format!("{:?}", lit.value),
             AnalyzedExpr::BinaryOp { .. } => "?column?".to_string(),
+            // tags[1] is named after the array, and name COLLATE "C" after
+            // name, as in PostgreSQL
+            AnalyzedExpr::Function {
+                func: ScalarFunction::ArraySubscript | ScalarFunction::Collate(_),
+                args,
+                ..
+            } => Self::format_expr_name(&args[0]),
+            // A cast keeps the name of its operand, or takes the type's name
+            AnalyzedExpr::Function {
+                func: ScalarFunction::Cast(data_type),
+                args,
+                ..
+            } => match Self::format_expr_name(&args[0]) {
+                name if name == "?column?" => Self::type_column_name(data_type),
+                name => name,
+            },
+            AnalyzedExpr::Function {
+                func: ScalarFunction::Any(..),
+                ..
+            } => "?column?".to_string(),
+            AnalyzedExpr::Function { func, .. } => func.name().to_string(),
             AnalyzedExpr::UnaryOp { .. } => "?column?".to_string(),
-            AnalyzedExpr::Aggregate(agg) => {
-                let func_name = match agg.func {
-                    crate::ast::AggregateFunction::Count => "count",
-                    crate::ast::AggregateFunction::Sum => "sum",
-                    crate::ast::AggregateFunction::Avg => "avg",
-                    crate::ast::AggregateFunction::Min => "min",
-                    crate::ast::AggregateFunction::Max => "max",
-                };
-                func_name.to_string()
-            }
+            AnalyzedExpr::Aggregate(agg) => agg.func.to_string(),
         }
     }
 
-    fn tuple_to_row(tuple: &Tuple) -> Vec<Option<String>> {
-        tuple
-            .values
-            .iter()
-            .map(|v| match v {
-                Value::Int(n) => Some(n.to_string()),
-                Value::Varchar(s) => Some(s.clone()),
-                Value::Bool(b) => Some(if *b { "t" } else { "f" }.to_string()),
-                Value::Null => None,
+    fn type_column_name(data_type: &DataType) -> String {
+        match data_type {
+            DataType::Time => "time".to_string(),
+            DataType::Timestamp => "timestamp".to_string(),
+            DataType::TimestampTz => "timestamptz".to_string(),
+            other => other.name(),
+        }
+    }
+
+    /// Encode a result row for DataRow, each column in its format code
+    /// (0 = text, 1 = binary); an empty `formats` means all text
+    fn encode_row(row: &[Value], formats: &[i16]) -> Result<Vec<Option<Vec<u8>>>> {
+        row.iter()
+            .enumerate()
+            .map(|(i, value)| match formats.get(i) {
+                Some(1) => format_binary(value),
+                _ => Ok(format_value(value).map(String::into_bytes)),
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::testing::TestDb;
+    use crate::protocol::INSUFFICIENT_PRIVILEGE;
+    use crate::udf;
+    use std::io::{Read, Write};
+
+    /// Run a simple query as the session loop does: a failure aborts the
+    /// block or rolls back the statement. Returns the command tag, or the
+    /// error, and the status sent in the following ReadyForQuery.
+    fn simple_query(
+        db: &TestDb,
+        txn: &mut Transaction,
+        sql: &str,
+    ) -> (Result<String, String>, TransactionStatus) {
+        let in_block = txn.is_active();
+        let result = Instance::execute_sql_internal(
+            sql,
+            &db.catalog,
+            &db.bpm,
+            &db.lock_manager,
+            &db.wal_manager,
+            &db.txn_manager,
+            txn,
+        );
+        let result = match result {
+            Ok(result) => Ok(result.into_query_result().command_tag),
+            Err(e) => {
+                Instance::statement_failed(
+                    in_block,
+                    &db.bpm,
+                    &db.lock_manager,
+                    &db.wal_manager,
+                    &db.txn_manager,
+                    txn,
+                );
+                Err(e.to_string())
+            }
+        };
+        (result, Instance::transaction_status(txn))
+    }
+
+    #[test]
+    fn test_aggregate_column_names() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (x INT)");
+        let sql = "SELECT stddev(x), stddev_samp(x), stddev_pop(x), variance(x), var_samp(x), \
+                   var_pop(x), count(*), string_agg(x::text, ',') FROM t";
+        let AnalyzedStatement::Select(select) =
+            analyze_with_parameters(&db.catalog, &parse(sql).unwrap(), &mut Vec::new()).unwrap()
+        else {
+            panic!("not a SELECT");
+        };
+        let names: Vec<String> = select
+            .select_items
+            .iter()
+            .map(|item| Instance::get_column_desc(item).name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "stddev",
+                "stddev_samp",
+                "stddev_pop",
+                "variance",
+                "var_samp",
+                "var_pop",
+                "count",
+                "string_agg"
+            ]
+        );
+    }
+
+    /// A frontend message: type, length and body
+    fn message(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![msg_type];
+        buf.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    fn cstr(s: &str) -> Vec<u8> {
+        [s.as_bytes(), &[0]].concat()
+    }
+
+    /// Parse, Bind and Execute of `sql`, through the unnamed statement and
+    /// portal, without parameters
+    fn execute_unnamed(sql: &str) -> Vec<u8> {
+        let no_codes = 0i16.to_be_bytes();
+        [
+            message(b'P', &[cstr(""), cstr(sql), no_codes.to_vec()].concat()),
+            message(
+                b'B',
+                &[cstr(""), cstr(""), [no_codes; 3].concat()].concat(),
+            ),
+            message(b'E', &[cstr(""), 0i32.to_be_bytes().to_vec()].concat()),
+        ]
+        .concat()
+    }
+
+    /// Run a session as the superuser on the messages a client sends; returns
+    /// the type and body of each message sent back after the first
+    /// ReadyForQuery
+    fn run_session(db: &TestDb, input: Vec<u8>) -> Vec<(u8, Vec<u8>)> {
+        let mut conn = Connection::for_test(input);
+        let pool = SessionPool::new(1, 0);
+        Instance::handle_client(
+            &mut conn,
+            StartupMessage {
+                params: vec![("user".to_string(), "postgres".to_string())],
+            },
+            Arc::clone(&db.catalog),
+            Arc::clone(&db.bpm),
+            Arc::clone(&db.lock_manager),
+            Arc::clone(&db.wal_manager),
+            Arc::clone(&db.txn_manager),
+            &Arc::new(CancelRegistry::new()),
+            &pool.try_acquire().unwrap(),
+        )
+        .unwrap();
+
+        let mut output = conn.output();
+        let mut messages = Vec::new();
+        while let [msg_type, rest @ ..] = output {
+            let len = i32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            messages.push((*msg_type, rest[4..len].to_vec()));
+            output = &rest[len..];
+        }
+        let ready = messages.iter().position(|(t, _)| *t == b'Z').unwrap();
+        messages.split_off(ready + 1)
+    }
+
+    /// panic_session(): panics, as a bug in a session would
+    struct PanicSession;
+
+    impl udf::ScalarUdf for PanicSession {
+        fn name(&self) -> &str {
+            "panic_session"
+        }
+
+        fn return_type(&self, arg_types: &[DataType]) -> Option<DataType> {
+            arg_types.is_empty().then_some(DataType::Int)
+        }
+
+        fn volatility(&self) -> udf::Volatility {
+            udf::Volatility::Volatile
+        }
+
+        fn evaluate(&self, _args: &[Value]) -> Result<Value> {
+            panic!("session bug");
+        }
+    }
+
+    #[test]
+    fn test_panicked_session_releases_its_locks() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (id INT, x INT)");
+        db.query("INSERT INTO t VALUES (1, 1)");
+        udf::register_scalar_function(PanicSession).unwrap();
+
+        let input = [
+            message(b'Q', &cstr("BEGIN")),
+            message(b'Q', &cstr("UPDATE t SET x = 2 WHERE id = 1")),
+            message(b'Q', &cstr("SELECT panic_session() FROM t")),
+        ]
+        .concat();
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| run_session(&db, input)));
+        assert!(panicked.is_err());
+
+        // The UPDATE is rolled back and its row lock released, so other
+        // sessions need not wait for the lock timeout
+        assert!(!db.lock_manager.has_locks());
+        db.query("UPDATE t SET x = 3 WHERE id = 1");
+        assert_eq!(db.query("SELECT x FROM t"), [[Value::Int(3)]]);
+    }
+
+    #[test]
+    fn test_extended_messages_share_a_transaction_until_sync() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (id INT)");
+        db.query("CREATE UNIQUE INDEX t_id ON t (id)");
+        db.query("INSERT INTO t VALUES (1)");
+
+        let input = [
+            execute_unnamed("INSERT INTO t VALUES (2)"),
+            // Fails, which rolls back the INSERT before it
+            execute_unnamed("INSERT INTO t VALUES (1)"),
+            // Skipped until Sync
+            execute_unnamed("INSERT INTO t VALUES (3)"),
+            message(b'S', &[]),
+            execute_unnamed("INSERT INTO t VALUES (4)"),
+            execute_unnamed("INSERT INTO t VALUES (5)"),
+            message(b'S', &[]),
+            message(b'X', &[]),
+        ]
+        .concat();
+        let types: Vec<(u8, Vec<u8>)> = run_session(&db, input)
+            .into_iter()
+            .map(|(t, body)| (t, if t == b'Z' { body } else { vec![] }))
+            .collect();
+        let sent = |t: u8| (t, vec![]);
+        assert_eq!(
+            types,
+            [
+                sent(b'1'),
+                sent(b'2'),
+                sent(b'C'),
+                sent(b'1'),
+                sent(b'2'),
+                sent(b'E'),
+                (b'Z', b"I".to_vec()),
+                sent(b'1'),
+                sent(b'2'),
+                sent(b'C'),
+                sent(b'1'),
+                sent(b'2'),
+                sent(b'C'),
+                (b'Z', b"I".to_vec()),
+            ]
+        );
+        let mut ids = db.query("SELECT id FROM t");
+        ids.sort_by_key(|row| row[0].as_i64());
+        assert_eq!(ids, [[Value::Int(1)], [Value::Int(4)], [Value::Int(5)]]);
+    }
+
+    #[test]
+    fn test_pg_authid_is_for_superusers() {
+        let db = TestDb::new();
+        let mut txn = Transaction::new();
+        settings::set_session_user("postgres");
+        for sql in [
+            "CREATE ROLE alice LOGIN PASSWORD 'secret'",
+            "ALTER ROLE alice NOLOGIN",
+            "SELECT rolname, rolpassword FROM pg_authid",
+        ] {
+            simple_query(&db, &mut txn, sql).0.unwrap();
+        }
+
+        settings::set_session_user("alice");
+        for sql in [
+            "SELECT rolname, rolpassword FROM pg_authid",
+            "SELECT count(*) FROM pg_class JOIN pg_authid ON true",
+            "UPDATE pg_authid SET rolsuper = true WHERE rolname = 'alice'",
+            "INSERT INTO pg_authid VALUES ('mallory', true, true, NULL)",
+            "DELETE FROM pg_authid",
+            "COPY pg_authid FROM STDIN",
+            "COPY (SELECT rolpassword FROM pg_authid) TO STDOUT",
+            "CREATE INDEX leak ON pg_authid ((rolpassword::INT))",
+            "CREATE UNIQUE INDEX dos ON pg_authid (rolsuper)",
+            "VACUUM pg_authid",
+        ] {
+            let error = Instance::execute_sql_internal(
+                sql,
+                &db.catalog,
+                &db.bpm,
+                &db.lock_manager,
+                &db.wal_manager,
+                &db.txn_manager,
+                &mut txn,
+            )
+            .err()
+            .unwrap_or_else(|| panic!("{sql}: allowed"));
+            assert_eq!(error.to_string(), "permission denied for table pg_authid", "{sql}");
+            assert_eq!(SqlError::find(&error).unwrap().code, INSUFFICIENT_PRIVILEGE);
+        }
+        // The role is unchanged
+        assert!(!db.catalog.get_role("alice").unwrap().superuser);
+        assert!(db.catalog.get_index("dos").is_none());
+        simple_query(&db, &mut txn, "VACUUM").0.unwrap();
+    }
+
+    #[test]
+    fn test_catalogs_are_read_only_for_other_users() {
+        let db = TestDb::new();
+        let mut txn = Transaction::new();
+        settings::set_session_user("postgres");
+        simple_query(&db, &mut txn, "CREATE ROLE alice LOGIN").0.unwrap();
+
+        settings::set_session_user("alice");
+        simple_query(
+            &db,
+            &mut txn,
+            "CREATE TABLE mine (rolname VARCHAR, rolsuper BOOLEAN, rolcanlogin BOOLEAN, \
+             rolpassword VARCHAR)",
+        )
+        .0
+        .unwrap();
+        simple_query(&db, &mut txn, "SELECT name FROM pg_class").0.unwrap();
+        for (sql, table) in [
+            // Point a table of its own at pg_authid's heap
+            ("UPDATE pg_class SET first_page_id = 3 WHERE name = 'mine'", "pg_class"),
+            ("INSERT INTO pg_attribute VALUES (4, 'x', 0, 4, false, 0)", "pg_attribute"),
+            ("DELETE FROM pg_index", "pg_index"),
+            ("COPY pg_class FROM STDIN", "pg_class"),
+            ("CREATE INDEX c ON pg_class (name)", "pg_class"),
+            ("VACUUM pg_attribute", "pg_attribute"),
+        ] {
+            let error = Instance::execute_sql_internal(
+                sql,
+                &db.catalog,
+                &db.bpm,
+                &db.lock_manager,
+                &db.wal_manager,
+                &db.txn_manager,
+                &mut txn,
+            )
+            .err()
+            .unwrap_or_else(|| panic!("{sql}: allowed"));
+            assert_eq!(error.to_string(), format!("permission denied for table {table}"));
+        }
+        assert_eq!(simple_query(&db, &mut txn, "SELECT * FROM mine").0.unwrap(), "SELECT 0");
+        assert!(!db.catalog.get_role("alice").unwrap().superuser);
+    }
+
+    /// A StartupMessage for `user`, as a client sends it
+    fn startup_message(user: &str) -> Vec<u8> {
+        let body = [
+            &196608i32.to_be_bytes()[..],
+            &cstr("user"),
+            &cstr(user),
+            &[0],
+        ]
+        .concat();
+        [&(body.len() as i32 + 4).to_be_bytes()[..], &body].concat()
+    }
+
+    #[test]
+    fn test_unauthenticated_client_loses_its_slot() {
+        let db = TestDb::new();
+        let instance = Instance {
+            catalog: Arc::clone(&db.catalog),
+            bpm: Arc::clone(&db.bpm),
+            lock_manager: Arc::clone(&db.lock_manager),
+            wal_manager: Arc::clone(&db.wal_manager),
+            txn_manager: Arc::clone(&db.txn_manager),
+        };
+        let config = Config {
+            authentication_timeout: Duration::from_millis(200),
+            ..Config::default()
+        };
+        let pool = SessionPool::new(1, 0);
+        let cancel_registry = Arc::new(CancelRegistry::new());
+        let rejections = Arc::new(AtomicUsize::new(0));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let connect = || {
+            let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            instance.accept_client(stream, &config, &None, &pool, &cancel_registry, &rejections);
+            client
+        };
+
+        // A client that never sends its startup message takes the only
+        // slot, so the next one is turned away
+        let mut idle = connect();
+        let mut turned_away = connect();
+        turned_away.write_all(&startup_message("postgres")).unwrap();
+        let mut response = Vec::new();
+        turned_away.read_to_end(&mut response).unwrap();
+        assert_eq!(response[0], b'E');
+        let fields: Vec<&[u8]> = response[5..].split(|&b| b == 0).collect();
+        assert!(fields.contains(&&b"SFATAL"[..]), "{response:?}");
+        assert!(fields.contains(&&b"C53300"[..]), "{response:?}");
+        assert!(fields.contains(&&b"Msorry, too many clients already"[..]), "{response:?}");
+
+        // After authentication_timeout the idle client is disconnected and
+        // its slot given back
+        let mut buf = [0; 1];
+        assert_eq!(idle.read(&mut buf).unwrap_or(0), 0);
+        let slot = (0..100).find_map(|_| {
+            thread::sleep(Duration::from_millis(10));
+            pool.try_acquire()
+        });
+        assert!(slot.is_some());
+        drop(slot);
+
+        // A client authenticating in time keeps its session past the timeout
+        let mut client = connect();
+        client.write_all(&startup_message("postgres")).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        client.write_all(&message(b'Q', &cstr("SELECT 1 FROM pg_class"))).unwrap();
+        client.write_all(&message(b'X', &[])).unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        assert!(response.starts_with(b"R"), "{response:?}");
+        assert!(response.windows(7).any(|w| w == b"SELECT "), "{response:?}");
+    }
+
+    const ABORTED: &str =
+        "current transaction is aborted, commands ignored until end of transaction block";
+
+    #[test]
+    fn test_transaction_status_transitions() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (id INT)");
+        db.query("CREATE UNIQUE INDEX t_id ON t (id)");
+        let mut txn = Transaction::new();
+        assert_eq!(Instance::transaction_status(&txn), TransactionStatus::Idle);
+
+        let (result, status) = simple_query(&db, &mut txn, "BEGIN");
+        assert_eq!(result.unwrap(), "BEGIN");
+        assert_eq!(status, TransactionStatus::InTransaction);
+
+        let (result, status) = simple_query(&db, &mut txn, "INSERT INTO t VALUES (1)");
+        assert_eq!(result.unwrap(), "INSERT 0 1");
+        assert_eq!(status, TransactionStatus::InTransaction);
+
+        let (result, status) = simple_query(&db, &mut txn, "COMMIT");
+        assert_eq!(result.unwrap(), "COMMIT");
+        assert_eq!(status, TransactionStatus::Idle);
+
+        // A failure outside a block rolls its statement back and stays idle
+        let (result, status) = simple_query(&db, &mut txn, "INSERT INTO t VALUES (1)");
+        assert!(result.is_err());
+        assert_eq!(status, TransactionStatus::Idle);
+        let (result, status) = simple_query(&db, &mut txn, "SELECT id FROM t");
+        assert_eq!(result.unwrap(), "SELECT 1");
+        assert_eq!(status, TransactionStatus::Idle);
+
+        // A failure inside a block leaves it failed until ROLLBACK
+        simple_query(&db, &mut txn, "BEGIN").0.unwrap();
+        simple_query(&db, &mut txn, "INSERT INTO t VALUES (2)").0.unwrap();
+        let (result, status) = simple_query(&db, &mut txn, "SELECT * FROM missing");
+        assert!(result.is_err());
+        assert_eq!(status, TransactionStatus::Failed);
+        let (result, status) = simple_query(&db, &mut txn, "ROLLBACK");
+        assert_eq!(result.unwrap(), "ROLLBACK");
+        assert_eq!(status, TransactionStatus::Idle);
+        let (result, _) = simple_query(&db, &mut txn, "SELECT id FROM t");
+        assert_eq!(result.unwrap(), "SELECT 1");
+
+        // Transaction control errors outside a block leave it idle
+        for sql in ["COMMIT", "ROLLBACK"] {
+            let (result, status) = simple_query(&db, &mut txn, sql);
+            assert_eq!(result.unwrap_err(), "there is no transaction in progress");
+            assert_eq!(status, TransactionStatus::Idle);
+        }
+
+        // BEGIN inside a block fails and so aborts it
+        simple_query(&db, &mut txn, "BEGIN").0.unwrap();
+        let (result, status) = simple_query(&db, &mut txn, "BEGIN");
+        assert_eq!(result.unwrap_err(), "there is already a transaction in progress");
+        assert_eq!(status, TransactionStatus::Failed);
+        simple_query(&db, &mut txn, "ROLLBACK").0.unwrap();
+    }
+
+    #[test]
+    fn test_aborted_block_rejects_commands() {
+        let db = TestDb::new();
+        db.query("CREATE TABLE t (id INT)");
+        db.query("CREATE UNIQUE INDEX t_id ON t (id)");
+        let mut txn = Transaction::new();
+
+        simple_query(&db, &mut txn, "BEGIN").0.unwrap();
+        simple_query(&db, &mut txn, "INSERT INTO t VALUES (1)").0.unwrap();
+        let (result, status) = simple_query(&db, &mut txn, "INSERT INTO t VALUES (1)");
+        assert!(result.is_err());
+        assert_eq!(status, TransactionStatus::Failed);
+
+        // Every statement but the end of the block is rejected, and the
+        // rejection keeps the block failed
+        for sql in [
+            "SELECT id FROM t",
+            "INSERT INTO t VALUES (2)",
+            "BEGIN",
+            "SET work_mem = '1MB'",
+            "CHECKPOINT",
+        ] {
+            let error = Instance::execute_sql_internal(
+                sql,
+                &db.catalog,
+                &db.bpm,
+                &db.lock_manager,
+                &db.wal_manager,
+                &db.txn_manager,
+                &mut txn,
+            )
+            .err()
+            .unwrap();
+            assert_eq!(error.to_string(), ABORTED, "{sql}");
+            assert_eq!(SqlError::find(&error).unwrap().code, IN_FAILED_SQL_TRANSACTION);
+            Instance::statement_failed(
+                true,
+                &db.bpm,
+                &db.lock_manager,
+                &db.wal_manager,
+                &db.txn_manager,
+                &mut txn,
+            );
+            let status = Instance::transaction_status(&txn);
+            assert_eq!(status, TransactionStatus::Failed, "{sql}");
+        }
+
+        // COMMIT of a failed block rolls it back
+        let (result, status) = simple_query(&db, &mut txn, "COMMIT");
+        assert_eq!(result.unwrap(), "ROLLBACK");
+        assert_eq!(status, TransactionStatus::Idle);
+        let (result, status) = simple_query(&db, &mut txn, "SELECT id FROM t");
+        assert_eq!(result.unwrap(), "SELECT 0");
+        assert_eq!(status, TransactionStatus::Idle);
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::analyzer::{check_table_access, TableAccess};
+use crate::buffer_pool::BufferPoolManager;
+use crate::catalog::{Catalog, TableDef};
+use crate::copy::{CopyFormat, CopyReader};
+use crate::executor::BulkInsert;
+use crate::lock_manager::LockManager;
+use crate::protocol::{Connection, FrontendMessage, Stream};
+use crate::transaction::Transaction;
+use crate::transaction_manager::TransactionManager;
+use crate::tuple::{format_value, parse_value, Value};
+use crate::wal::{WalManager, WalRecordType};
+
+use super::{ExecuteResult, Instance, QueryResult};
+
+// ============================================================================
+// COPY
+// ============================================================================
+//
+// COPY FROM STDIN and COPY TO STDOUT switch the connection into a sub-protocol:
+// after CopyInResponse the client streams CopyData messages ending with
+// CopyDone (or CopyFail to give up); after CopyOutResponse the server sends
+// one CopyData per row and CopyDone. Statements are checked and queries run
+// by execute_statement first; the data is exchanged here.
+
+/// COPY FROM STDIN, checked and waiting for its data
+pub(super) struct CopyIn {
+    table: TableDef,
+    /// Table column of each field of a row
+    columns: Vec<usize>,
+    format: CopyFormat,
+}
+
+impl Instance {
+    /// Check the table and column list of COPY FROM
+    pub(super) fn copy_in(
+        catalog: &Catalog,
+        table_name: &str,
+        column_names: &[String],
+        format: CopyFormat,
+    ) -> Result<CopyIn> {
+        let table = catalog
+            .get_table(table_name)
+            .ok_or_else(|| anyhow::anyhow!("relation \"{table_name}\" does not exist"))?;
+        check_table_access(catalog, &table, TableAccess::Modify)?;
+        let columns = if column_names.is_empty() {
+            (0..table.columns.len()).collect()
+        } else {
+            let mut columns = Vec::new();
+            for name in column_names {
+                let Some(index) = table.columns.iter().position(|c| &c.name == name) else {
+                    bail!("column \"{name}\" of relation \"{table_name}\" does not exist");
+                };
+                if columns.contains(&index) {
+                    bail!("column \"{name}\" specified more than once");
+                }
+                columns.push(index);
+            }
+            columns
+        };
+        // Columns left out are NULL
+        for (i, column) in table.columns.iter().enumerate() {
+            if !column.nullable && !columns.contains(&i) {
+                bail!("column '{}' is not nullable", column.name);
+            }
+        }
+        Ok(CopyIn {
+            table,
+            columns,
+            format,
+        })
+    }
+
+    /// Exchange the data of COPY FROM STDIN or COPY TO STDOUT; the results
+    /// of other statements are passed through
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn run_copy<S: Stream>(
+        result: ExecuteResult,
+        conn: &mut Connection<S>,
+        catalog: &Catalog,
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) -> Result<QueryResult> {
+        let count = match result {
+            ExecuteResult::CopyIn(copy) => Self::copy_from(
+                copy,
+                conn,
+                catalog,
+                bpm,
+                lock_manager,
+                wal_manager,
+                txn_manager,
+                txn,
+            )?,
+            ExecuteResult::CopyOut { format, result } => {
+                conn.send_copy_out_response(result.columns.len())?;
+                if format.header {
+                    let names: Vec<String> = result.columns.iter().map(|c| c.name.clone()).collect();
+                    conn.send_copy_data(format.format_header(&names).as_bytes())?;
+                }
+                for row in &result.rows {
+                    let fields: Vec<Option<String>> = row.iter().map(format_value).collect();
+                    conn.send_copy_data(format.format_row(&fields).as_bytes())?;
+                }
+                conn.send_copy_done()?;
+                result.rows.len() as u64
+            }
+            result => return Ok(result.into_query_result()),
+        };
+        Ok(QueryResult {
+            columns: vec![],
+            rows: vec![],
+            command_tag: format!("COPY {count}"),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn copy_from<S: Stream>(
+        copy: CopyIn,
+        conn: &mut Connection<S>,
+        catalog: &Catalog,
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) -> Result<u64> {
+        conn.send_copy_in_response(copy.columns.len())?;
+
+        let autocommit = !txn.is_active();
+        if autocommit {
+            // Start implicit transaction with snapshot
+            let txn_id = txn_manager.begin();
+            let snapshot = txn_manager.get_snapshot(txn_id);
+            txn.begin_with_id(txn_id, snapshot);
+            let lsn = wal_manager.append(txn.id, 0, WalRecordType::Begin);
+            txn.set_last_lsn(lsn);
+            txn_manager.update_last_lsn(txn_id, lsn);
+        }
+
+        let count = {
+            let table = &copy.table;
+            let mut loader = BulkInsert::new(
+                Arc::clone(bpm),
+                table,
+                catalog.get_indexes_for_table(table.table_id),
+                txn,
+                lock_manager,
+                Arc::clone(wal_manager),
+                txn_manager,
+            )?;
+            let mut on_row = |fields: Vec<Option<String>>| {
+                if fields.len() < copy.columns.len() {
+                    let missing = &table.columns[copy.columns[fields.len()]];
+                    bail!("missing data for column \"{}\"", missing.name);
+                }
+                if fields.len() > copy.columns.len() {
+                    bail!("extra data after last expected column");
+                }
+                let mut values = vec![Value::Null; table.columns.len()];
+                for (field, &index) in fields.into_iter().zip(&copy.columns) {
+                    let column = &table.columns[index];
+                    values[index] = match field {
+                        Some(text) => parse_value(&text, &column.data_type)?,
+                        None if column.nullable => Value::Null,
+                        None => bail!("column '{}' is not nullable", column.name),
+                    };
+                }
+                loader.insert(values)
+            };
+
+            let mut reader = CopyReader::new(copy.format);
+            let context = |e: anyhow::Error, line: usize| {
+                anyhow::anyhow!("{e} (COPY {}, line {line})", table.name)
+            };
+            loop {
+                match conn.read_message()? {
+                    Some(FrontendMessage::CopyData(data)) => reader
+                        .feed(&data, &mut on_row)
+                        .map_err(|e| context(e, reader.line()))?,
+                    Some(FrontendMessage::CopyDone) => {
+                        reader
+                            .finish(&mut on_row)
+                            .map_err(|e| context(e, reader.line()))?;
+                        break;
+                    }
+                    Some(FrontendMessage::CopyFail(message)) => {
+                        bail!("COPY from stdin failed: {message}")
+                    }
+                    // Ignored during COPY, as in PostgreSQL
+                    Some(FrontendMessage::Sync | FrontendMessage::Flush) => {}
+                    Some(_) => bail!("unexpected message during COPY from stdin"),
+                    None => bail!("unexpected EOF on client connection during COPY from stdin"),
+                }
+            }
+            loader.finish()?
+        };
+
+        if autocommit {
+            // Implicit commit
+            let txn_id = txn.id;
+            wal_manager.append(txn_id, txn.last_lsn, WalRecordType::Commit);
+            wal_manager.flush();
+            txn_manager.commit(txn_id);
+            let held_locks = txn.take_held_locks();
+            lock_manager.unlock_all(txn_id, &held_locks);
+            txn.commit();
+        }
+        Ok(count)
+    }
+}
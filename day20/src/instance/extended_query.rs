@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{bail, Result};
+
+use crate::analyzer::{analyze_with_parameters, AnalyzedStatement, Parameter};
+use crate::ast::Statement;
+use crate::buffer_pool::BufferPoolManager;
+use crate::catalog::Catalog;
+use crate::datetime;
+use crate::lock_manager::LockManager;
+use crate::parser::parse_prepared;
+use crate::protocol::{ColumnDesc, Connection, FrontendMessage, Stream, Target};
+use crate::transaction::Transaction;
+use crate::transaction_manager::TransactionManager;
+use crate::tuple::{parse_binary, parse_value, DataType, Value};
+use crate::wal::WalManager;
+
+use super::{Instance, QueryResult};
+
+// ============================================================================
+// Extended query protocol
+// ============================================================================
+//
+// Parse prepares a statement, Bind gives it parameter values to create a
+// portal, Execute runs the portal. Describe reports the parameter and result
+// types, and Close drops a statement or portal. The unnamed statement and
+// portal (name "") are replaced by the next Parse or Bind.
+
+/// A statement prepared by Parse
+struct PreparedStatement {
+    /// None for an empty query string
+    statement: Option<Statement>,
+    /// Types of $1, $2, ...: declared by the client or inferred
+    parameter_types: Vec<DataType>,
+    /// Result columns, None for statements that return no rows
+    columns: Option<Vec<ColumnDesc>>,
+}
+
+/// A prepared statement with bound parameters, ready to run
+struct Portal {
+    statement: Option<Statement>,
+    parameters: Vec<Parameter>,
+    columns: Option<Vec<ColumnDesc>>,
+    /// Format code of each result column: 0 = text, 1 = binary
+    result_formats: Vec<i16>,
+    /// Result of the first Execute; later ones continue from `sent`
+    result: Option<QueryResult>,
+    sent: usize,
+}
+
+/// Prepared statements and portals of one connection
+#[derive(Default)]
+pub(super) struct ExtendedQuery {
+    statements: HashMap<String, PreparedStatement>,
+    portals: HashMap<String, Portal>,
+}
+
+impl ExtendedQuery {
+    /// Drop the unnamed statement and portal, as a simple query does
+    pub(super) fn close_unnamed(&mut self) {
+        self.statements.remove("");
+        self.portals.remove("");
+    }
+
+    /// Drop all portals when the transaction they belong to ends
+    pub(super) fn close_portals(&mut self) {
+        self.portals.clear();
+    }
+
+    fn parse(
+        &mut self,
+        catalog: &Catalog,
+        name: String,
+        query: &str,
+        declared_types: &[i32],
+    ) -> Result<()> {
+        if !name.is_empty() && self.statements.contains_key(&name) {
+            bail!("prepared statement \"{name}\" already exists");
+        }
+        let query = query.trim();
+        let prepared = if query.is_empty() {
+            PreparedStatement {
+                statement: None,
+                parameter_types: vec![],
+                columns: None,
+            }
+        } else {
+            let (statement, used) = parse_prepared(query)?;
+            let mut parameters: Vec<Parameter> = (0..used.max(declared_types.len()))
+                .map(|i| Parameter {
+                    data_type: declared_types.get(i).and_then(|&oid| declared_type(oid)),
+                    value: None,
+                })
+                .collect();
+            let columns = describe(catalog, &statement, &mut parameters)?;
+            PreparedStatement {
+                statement: Some(statement),
+                parameter_types: parameters
+                    .into_iter()
+                    .map(|p| p.data_type.unwrap_or(DataType::Text))
+                    .collect(),
+                columns,
+            }
+        };
+        self.statements.insert(name, prepared);
+        Ok(())
+    }
+
+    fn bind(
+        &mut self,
+        portal: String,
+        statement: &str,
+        formats: &[i16],
+        values: Vec<Option<Vec<u8>>>,
+        result_formats: &[i16],
+    ) -> Result<()> {
+        let Some(prepared) = self.statements.get(statement) else {
+            bail!("prepared statement \"{statement}\" does not exist");
+        };
+        if !portal.is_empty() && self.portals.contains_key(&portal) {
+            bail!("portal \"{portal}\" already exists");
+        }
+        if values.len() != prepared.parameter_types.len() {
+            bail!(
+                "bind message supplies {} parameters, but prepared statement \"{statement}\" requires {}",
+                values.len(),
+                prepared.parameter_types.len()
+            );
+        }
+        let column_count = prepared.columns.as_ref().map_or(0, Vec::len);
+        let result_formats = match result_formats {
+            [] => vec![0; column_count],
+            [format] => vec![*format; column_count],
+            formats if formats.len() == column_count => formats.to_vec(),
+            formats => bail!(
+                "bind message has {} result formats but query has {column_count} columns",
+                formats.len()
+            ),
+        };
+        if let Some(format) = result_formats.iter().find(|&&f| f != 0 && f != 1) {
+            bail!("unsupported format code: {format}");
+        }
+
+        let mut parameters = Vec::with_capacity(values.len());
+        for (i, (value, data_type)) in values
+            .into_iter()
+            .zip(&prepared.parameter_types)
+            .enumerate()
+        {
+            // One format code applies to all parameters; none means text
+            let format = match formats {
+                [] => 0,
+                [format] => *format,
+                formats => formats.get(i).copied().unwrap_or(0),
+            };
+            let value = match (value, format) {
+                (None, _) => Value::Null,
+                (Some(bytes), 0) => parse_value(&String::from_utf8(bytes)?, data_type)?,
+                (Some(bytes), 1) => parse_binary(&bytes, data_type)?,
+                (Some(_), format) => bail!("unsupported format code: {format}"),
+            };
+            parameters.push(Parameter {
+                data_type: Some(data_type.clone()),
+                value: Some(value),
+            });
+        }
+
+        self.portals.insert(
+            portal,
+            Portal {
+                statement: prepared.statement.clone(),
+                parameters,
+                columns: prepared.columns.clone(),
+                result_formats,
+                result: None,
+                sent: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn describe<S: Stream>(&self, conn: &mut Connection<S>, target: &Target) -> Result<()> {
+        // A statement is described before its result formats are known
+        let (columns, formats) = match target {
+            Target::Statement(name) => {
+                let Some(prepared) = self.statements.get(name) else {
+                    bail!("prepared statement \"{name}\" does not exist");
+                };
+                let type_oids: Vec<i32> = prepared
+                    .parameter_types
+                    .iter()
+                    .map(|t| Instance::type_desc("", t).type_oid)
+                    .collect();
+                conn.send_parameter_description(&type_oids)?;
+                (&prepared.columns, &[][..])
+            }
+            Target::Portal(name) => match self.portals.get(name) {
+                Some(portal) => (&portal.columns, &portal.result_formats[..]),
+                None => bail!("portal \"{name}\" does not exist"),
+            },
+        };
+        match columns {
+            Some(columns) => conn.send_row_description(columns, formats),
+            None => conn.send_no_data(),
+        }
+    }
+
+    fn close(&mut self, target: &Target) {
+        // Closing something that does not exist is not an error
+        match target {
+            Target::Statement(name) => {
+                self.statements.remove(name);
+            }
+            Target::Portal(name) => {
+                self.portals.remove(name);
+            }
+        }
+    }
+}
+
+impl Instance {
+    /// Handle Parse, Bind, Describe, Execute or Close
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn handle_extended_message<S: Stream>(
+        message: FrontendMessage,
+        conn: &mut Connection<S>,
+        extended: &mut ExtendedQuery,
+        catalog: &Catalog,
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        lock_manager: &Arc<LockManager>,
+        wal_manager: &Arc<WalManager>,
+        txn_manager: &Arc<TransactionManager>,
+        txn: &mut Transaction,
+    ) -> Result<()> {
+        match message {
+            FrontendMessage::Parse {
+                name,
+                query,
+                parameter_types,
+            } => {
+                println!(
+                    "[Server] Parse: {query} (thread: {:?})",
+                    thread::current().id()
+                );
+                extended.parse(catalog, name, &query, &parameter_types)?;
+                conn.send_parse_complete()
+            }
+            FrontendMessage::Bind {
+                portal,
+                statement,
+                parameter_formats,
+                parameters,
+                result_formats,
+            } => {
+                extended.bind(
+                    portal,
+                    &statement,
+                    &parameter_formats,
+                    parameters,
+                    &result_formats,
+                )?;
+                conn.send_bind_complete()
+            }
+            FrontendMessage::Describe(target) => extended.describe(conn, &target),
+            FrontendMessage::Execute { portal, max_rows } => {
+                let Some(portal) = extended.portals.get_mut(&portal) else {
+                    bail!("portal \"{portal}\" does not exist");
+                };
+                let Some(statement) = &portal.statement else {
+                    return conn.send_empty_query();
+                };
+                if portal.result.is_none() {
+                    // Outside a block, the portals executed up to Sync share
+                    // one implicit transaction
+                    if !txn.is_active() && opens_implicit_transaction(statement) {
+                        datetime::set_transaction_timestamp();
+                        Self::begin_transaction(wal_manager, txn_manager, txn);
+                        txn.implicit = true;
+                    }
+                    let result = Self::execute_statement(
+                        statement,
+                        &portal.parameters,
+                        catalog,
+                        bpm,
+                        lock_manager,
+                        wal_manager,
+                        txn_manager,
+                        txn,
+                    )?;
+                    portal.result = Some(Self::run_copy(
+                        result,
+                        conn,
+                        catalog,
+                        bpm,
+                        lock_manager,
+                        wal_manager,
+                        txn_manager,
+                        txn,
+                    )?);
+                }
+                let result = portal.result.as_ref().unwrap();
+
+                // Send up to max_rows rows; the client asks for more with
+                // another Execute after PortalSuspended
+                let remaining = &result.rows[portal.sent..];
+                let count = match usize::try_from(max_rows) {
+                    Ok(max_rows) if max_rows > 0 => max_rows.min(remaining.len()),
+                    _ => remaining.len(),
+                };
+                for row in &remaining[..count] {
+                    conn.send_data_row(&Self::encode_row(row, &portal.result_formats)?)?;
+                }
+                portal.sent += count;
+                if portal.sent < result.rows.len() {
+                    conn.send_portal_suspended()
+                } else {
+                    conn.send_command_complete(&result.command_tag)
+                }
+            }
+            FrontendMessage::Close(target) => {
+                extended.close(&target);
+                conn.send_close_complete()
+            }
+            _ => bail!("unexpected message in extended query protocol"),
+        }
+    }
+}
+
+/// Whether a statement runs in the implicit transaction of its Execute.
+/// Transaction control manages transactions itself, and VACUUM cannot run
+/// inside one.
+fn opens_implicit_transaction(statement: &Statement) -> bool {
+    !matches!(
+        statement,
+        Statement::Begin | Statement::Commit | Statement::Rollback | Statement::Vacuum(_)
+    )
+}
+
+/// Result columns of a statement, inferring the types of its parameters.
+/// Utility statements are not analyzed.
+fn describe(
+    catalog: &Catalog,
+    statement: &Statement,
+    parameters: &mut Vec<Parameter>,
+) -> Result<Option<Vec<ColumnDesc>>> {
+    let returning = match statement {
+        Statement::Show(name) => {
+            return Ok(Some(vec![ColumnDesc::new_text(&name.to_ascii_lowercase())]));
+        }
+        Statement::Select(_)
+        | Statement::Insert(_)
+        | Statement::Update(_)
+        | Statement::Delete(_) => match analyze_with_parameters(catalog, statement, parameters)? {
+            AnalyzedStatement::Select(select) => select.select_items,
+            AnalyzedStatement::Insert(insert) => insert.returning,
+            AnalyzedStatement::Update(update) => update.returning,
+            AnalyzedStatement::Delete(delete) => delete.returning,
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+    Ok(match returning.is_empty() {
+        true => None,
+        false => Some(returning.iter().map(Instance::get_column_desc).collect()),
+    })
+}
+
+/// The type a client declared for a parameter by its OID. 0 (unspecified)
+/// and types without a counterpart here are inferred instead.
+fn declared_type(oid: i32) -> Option<DataType> {
+    [
+        DataType::Int,
+        DataType::SmallInt,
+        DataType::BigInt,
+        DataType::Real,
+        DataType::Double,
+        DataType::Numeric(None),
+        DataType::Text,
+        DataType::Varchar(None),
+        DataType::Bool,
+        DataType::Date,
+        DataType::Time,
+        DataType::Timestamp,
+        DataType::TimestampTz,
+        DataType::Interval,
+        DataType::Bytea,
+        DataType::Jsonb,
+    ]
+    .into_iter()
+    .flat_map(|t| [DataType::Array(Box::new(t.clone())), t])
+    .find(|t| Instance::type_desc("", t).type_oid == oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::testing::TestDb;
+
+    fn setup() -> (TestDb, ExtendedQuery) {
+        let db = TestDb::new();
+        db.query("CREATE TABLE items (id INT, name VARCHAR(20), price NUMERIC(8,2))");
+        (db, ExtendedQuery::default())
+    }
+
+    #[test]
+    fn test_parse_infers_parameter_types() {
+        let (db, mut extended) = setup();
+        extended
+            .parse(&db.catalog, "s1".into(), "SELECT name, price FROM items WHERE id = $1", &[])
+            .unwrap();
+        let prepared = &extended.statements["s1"];
+        assert_eq!(prepared.parameter_types, [DataType::Int]);
+        let columns = prepared.columns.as_ref().unwrap();
+        assert_eq!(columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["name", "price"]);
+
+        // Declared types (20 = int8) win over inference, unspecified ones (0)
+        // are inferred, and a statement without RETURNING has no columns
+        extended
+            .parse(&db.catalog, "".into(), "INSERT INTO items VALUES ($1, $2, $3)", &[20, 0])
+            .unwrap();
+        let prepared = &extended.statements[""];
+        assert_eq!(
+            prepared.parameter_types,
+            [DataType::BigInt, DataType::Varchar(Some(20)), DataType::Numeric(None)]
+        );
+        assert!(prepared.columns.is_none());
+
+        // An empty query is allowed and describes as nothing
+        extended.parse(&db.catalog, "empty".into(), "  ", &[]).unwrap();
+        assert!(extended.statements["empty"].statement.is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (db, mut extended) = setup();
+        extended.parse(&db.catalog, "s1".into(), "SELECT id FROM items", &[]).unwrap();
+        let err = extended.parse(&db.catalog, "s1".into(), "SELECT id FROM items", &[]);
+        assert_eq!(err.unwrap_err().to_string(), "prepared statement \"s1\" already exists");
+        // The unnamed statement is replaced instead
+        extended.parse(&db.catalog, "".into(), "SELECT id FROM items", &[]).unwrap();
+        extended.parse(&db.catalog, "".into(), "SELECT name FROM items", &[]).unwrap();
+        assert!(extended.parse(&db.catalog, "s2".into(), "SELECT nope FROM items", &[]).is_err());
+        assert!(!extended.statements.contains_key("s2"));
+    }
+
+    #[test]
+    fn test_bind_parameters() {
+        let (db, mut extended) = setup();
+        let sql = "SELECT name FROM items WHERE id = $1 AND name = $2";
+        extended.parse(&db.catalog, "s1".into(), sql, &[]).unwrap();
+
+        // $1 in binary, $2 in text
+        let values = vec![Some(7i32.to_be_bytes().to_vec()), Some(b"bolt".to_vec())];
+        extended.bind("p1".into(), "s1", &[1, 0], values, &[1]).unwrap();
+        let portal = &extended.portals["p1"];
+        let bound: Vec<_> = portal.parameters.iter().map(|p| p.value.clone().unwrap()).collect();
+        assert_eq!(bound, [Value::Int(7), Value::Varchar("bolt".into())]);
+        // One result format applies to every column
+        assert_eq!(portal.result_formats, [1]);
+
+        // NULL and text parameters, with no format codes at all
+        extended.bind("".into(), "s1", &[], vec![None, Some(b"nut".to_vec())], &[]).unwrap();
+        assert_eq!(extended.portals[""].parameters[0].value, Some(Value::Null));
+        assert_eq!(extended.portals[""].result_formats, [0]);
+    }
+
+    #[test]
+    fn test_bind_errors() {
+        let (db, mut extended) = setup();
+        let sql = "SELECT name, price FROM items WHERE id = $1";
+        extended.parse(&db.catalog, "s1".into(), sql, &[]).unwrap();
+        let int = || vec![Some(b"1".to_vec())];
+        let error = |extended: &mut ExtendedQuery, values, formats: &[i16], results: &[i16]| {
+            extended.bind("".into(), "s1", formats, values, results).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error(&mut extended, vec![], &[], &[]),
+            "bind message supplies 0 parameters, but prepared statement \"s1\" requires 1"
+        );
+        assert_eq!(
+            error(&mut extended, vec![Some(b"one".to_vec())], &[], &[]),
+            "invalid input syntax for type integer: \"one\""
+        );
+        assert_eq!(
+            error(&mut extended, vec![Some(vec![0, 1])], &[1], &[]),
+            "incorrect binary data format for type integer"
+        );
+        assert_eq!(error(&mut extended, int(), &[2], &[]), "unsupported format code: 2");
+        assert_eq!(
+            error(&mut extended, int(), &[], &[0, 1, 1]),
+            "bind message has 3 result formats but query has 2 columns"
+        );
+        assert_eq!(error(&mut extended, int(), &[], &[0, 3]), "unsupported format code: 3");
+        let err = extended.bind("".into(), "nope", &[], int(), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "prepared statement \"nope\" does not exist");
+
+        extended.bind("p1".into(), "s1", &[], int(), &[]).unwrap();
+        let err = extended.bind("p1".into(), "s1", &[], int(), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "portal \"p1\" already exists");
+    }
+
+    #[test]
+    fn test_describe_statement() {
+        let (db, mut extended) = setup();
+        let sql = "UPDATE items SET price = $2 WHERE id = $1 RETURNING id";
+        extended.parse(&db.catalog, "s1".into(), sql, &[]).unwrap();
+        let mut conn = Connection::for_test(Vec::new());
+        extended.describe(&mut conn, &Target::Statement("s1".into())).unwrap();
+
+        let mut expected = Connection::for_test(Vec::new());
+        expected.send_parameter_description(&[23, 1700]).unwrap();
+        expected.send_row_description(&[ColumnDesc::new_int("id")], &[]).unwrap();
+        assert_eq!(conn.output(), expected.output());
+
+        let err = extended.describe(&mut conn, &Target::Portal("p1".into()));
+        assert_eq!(err.unwrap_err().to_string(), "portal \"p1\" does not exist");
+    }
+}
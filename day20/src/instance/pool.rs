@@ -0,0 +1,190 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Result;
+
+// ============================================================================
+// Session worker pool
+// ============================================================================
+//
+// Sessions run on a fixed set of max_connections threads started with the
+// server. The accept loop takes a connection slot for each client before
+// handing it to a worker, so a slot always has an idle worker waiting for
+// it; clients beyond max_connections are turned away at once.
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Worker threads for client sessions
+pub(super) struct SessionPool {
+    sender: Sender<Job>,
+    slots: Arc<Slots>,
+}
+
+struct Slots {
+    in_use: AtomicUsize,
+    max_connections: usize,
+    superuser_reserved: usize,
+}
+
+/// A connection slot, given back when dropped
+pub(super) struct Slot {
+    slots: Arc<Slots>,
+    reserved: bool,
+}
+
+impl SessionPool {
+    pub(super) fn new(max_connections: usize, superuser_reserved: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..max_connections {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("session-{i}"))
+                .spawn(move || Self::work(&receiver))
+                .expect("failed to start session worker");
+        }
+        SessionPool {
+            sender,
+            slots: Arc::new(Slots {
+                in_use: AtomicUsize::new(0),
+                max_connections,
+                superuser_reserved,
+            }),
+        }
+    }
+
+    fn work(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            // Only one idle worker waits on the channel at a time
+            let job = receiver.lock().unwrap().recv();
+            match job {
+                // A session that panics must not take its worker with it,
+                // or the pool would have fewer workers than slots. The
+                // session has rolled back its transaction and released its
+                // locks before the panic reaches here.
+                Ok(job) => {
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        println!("[Server] Session panicked, worker continues");
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Take a connection slot, or None when all max_connections are in use
+    pub(super) fn try_acquire(&self) -> Option<Slot> {
+        let slots = &self.slots;
+        let in_use = slots
+            .in_use
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < slots.max_connections).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Slot {
+            slots: Arc::clone(slots),
+            reserved: in_use >= slots.max_connections - slots.superuser_reserved,
+        })
+    }
+
+    /// Run a session on a worker; the caller holds a slot for it
+    pub(super) fn execute(&self, job: impl FnOnce() + Send + 'static) -> Result<()> {
+        self.sender
+            .send(Box::new(job))
+            .map_err(|_| anyhow::anyhow!("session workers have stopped"))
+    }
+}
+
+impl Slot {
+    /// Whether this is one of the slots kept for superusers: the connections
+    /// in use, this one included, reached into them when it was taken
+    pub(super) fn is_reserved(&self) -> bool {
+        self.reserved
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.slots.in_use.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_up_to_max_connections() {
+        let pool = SessionPool::new(3, 0);
+        let mut held: Vec<Slot> = (0..3).map(|_| pool.try_acquire().unwrap()).collect();
+        assert!(pool.try_acquire().is_none());
+
+        // A dropped slot is given back
+        held.pop();
+        let slot = pool.try_acquire().unwrap();
+        assert!(pool.try_acquire().is_none());
+        drop(slot);
+        drop(held);
+        assert_eq!(pool.slots.in_use.load(Ordering::Acquire), 0);
+        assert!(pool.try_acquire().is_some());
+    }
+
+    #[test]
+    fn test_reserved_slots() {
+        let pool = SessionPool::new(4, 2);
+        let first = pool.try_acquire().unwrap();
+        let second = pool.try_acquire().unwrap();
+        let third = pool.try_acquire().unwrap();
+        let fourth = pool.try_acquire().unwrap();
+        assert!(!first.is_reserved());
+        assert!(!second.is_reserved());
+        assert!(third.is_reserved());
+        assert!(fourth.is_reserved());
+        assert!(pool.try_acquire().is_none());
+
+        // Whether a slot is reserved is settled when it is taken, not by
+        // the slots taken or given back after it
+        drop(first);
+        drop(second);
+        drop(fourth);
+        assert!(third.is_reserved());
+        let slot = pool.try_acquire().unwrap();
+        assert!(!slot.is_reserved());
+        assert!(pool.try_acquire().unwrap().is_reserved());
+    }
+
+    #[test]
+    fn test_execute_runs_on_workers() {
+        let pool = SessionPool::new(2, 0);
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..4 {
+            let sender = sender.clone();
+            pool.execute(move || {
+                let name = thread::current().name().map(str::to_string);
+                sender.send(name).unwrap();
+            })
+            .unwrap();
+        }
+        for _ in 0..4 {
+            let name = receiver.recv().unwrap().unwrap();
+            assert!(name.starts_with("session-"), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_panicking_session_keeps_its_worker() {
+        let pool = SessionPool::new(1, 0);
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..3 {
+            pool.execute(|| panic!("session failed")).unwrap();
+            let sender = sender.clone();
+            pool.execute(move || sender.send(()).unwrap()).unwrap();
+            receiver
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .expect("no worker left to run the session");
+        }
+    }
+}
@@ -0,0 +1,642 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+use crate::bytea;
+use crate::datetime;
+use crate::numeric::Numeric;
+use crate::tuple::Value;
+
+// ============================================================================
+// JSONB - parsed JSON documents
+// ============================================================================
+//
+// Input text is validated and parsed into a tree. As in PostgreSQL's jsonb,
+// object keys are kept sorted (shorter keys first, then bytewise) with
+// duplicates removed, the last value winning, so equal documents have equal
+// trees and equal binary forms.
+//
+// Binary form, one tag byte per node:
+//   0 null | 1 false | 2 true | 3 number (Numeric bytes)
+//   4 string [len: u32][utf8] | 5 array [count: u32][elements...]
+//   6 object [count: u32]([key len: u32][key][value])...
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_ARRAY: u8 = 5;
+const TAG_OBJECT: u8 = 6;
+
+/// Nesting limit for parsed documents, to bound recursion
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(Numeric),
+    String(String),
+    Array(Vec<Json>),
+    /// Pairs sorted by `key_order`, keys unique
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parse and validate JSON text
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut parser = JsonParser {
+            input: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.input.len() {
+            return Err(parser.error());
+        }
+        Ok(value)
+    }
+
+    /// Build an object from pairs in any order; a repeated key keeps its last value
+    pub fn object(pairs: Vec<(String, Json)>) -> Json {
+        let mut sorted: Vec<(String, Json)> = Vec::with_capacity(pairs.len());
+        for (key, value) in pairs {
+            match sorted.binary_search_by(|(k, _)| key_order(k, &key)) {
+                Ok(i) => sorted[i].1 = value,
+                Err(i) => sorted.insert(i, (key, value)),
+            }
+        }
+        Json::Object(sorted)
+    }
+
+    /// Convert a SQL value, as to_jsonb() does: numbers and booleans map to
+    /// their JSON counterparts, other types to their text form.
+    pub fn from_value(value: &Value) -> Json {
+        match value {
+            Value::Null => Json::Null,
+            Value::Bool(b) => Json::Bool(*b),
+            Value::Varchar(s) => Json::String(s.clone()),
+            Value::Jsonb(json) => json.clone(),
+            Value::Array(elements) => Json::Array(elements.iter().map(Json::from_value).collect()),
+            Value::Real(_) | Value::Double(_) => {
+                let f = value.as_f64().unwrap();
+                match Numeric::from_f64(f) {
+                    Ok(n) if f.is_finite() => Json::Number(n),
+                    _ => Json::String(f.to_string()),
+                }
+            }
+            Value::Date(d) => Json::String(datetime::format_date(*d)),
+            Value::Time(t) => Json::String(datetime::format_time(*t)),
+            // JSON timestamps use the ISO 8601 "T" separator
+            Value::Timestamp(ts) => Json::String(datetime::format_timestamp(*ts).replacen(' ', "T", 1)),
+            Value::TimestampTz(ts) => {
+                Json::String(datetime::format_timestamptz(*ts).replacen(' ', "T", 1))
+            }
+            Value::Interval(i) => Json::String(i.to_string()),
+            Value::Bytea(b) => Json::String(bytea::format(b)),
+            Value::Numeric(n) => Json::Number(n.clone()),
+            Value::SmallInt(_) | Value::Int(_) | Value::BigInt(_) => {
+                Json::Number(Numeric::from_i64(value.as_i64().unwrap()))
+            }
+            Value::Toast(_) => unreachable!("expressions are evaluated to detoasted values"),
+        }
+    }
+
+    /// Text of a value as returned by ->> : strings without quotes, JSON
+    /// null as SQL NULL
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            Json::Null => None,
+            Json::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    /// Object field by key (->)
+    pub fn get_field(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs
+                .binary_search_by(|(k, _)| key_order(k, key))
+                .ok()
+                .map(|i| &pairs[i].1),
+            _ => None,
+        }
+    }
+
+    /// Array element by zero-based position, negative counting from the end (->)
+    pub fn get_element(&self, index: i64) -> Option<&Json> {
+        match self {
+            Json::Array(elements) => {
+                let index = if index < 0 {
+                    elements.len() as i64 + index
+                } else {
+                    index
+                };
+                usize::try_from(index).ok().and_then(|i| elements.get(i))
+            }
+            _ => None,
+        }
+    }
+
+    /// Follow a path of keys and array positions (#>)
+    pub fn get_path(&self, path: &[String]) -> Option<&Json> {
+        path.iter().try_fold(self, |node, step| match node {
+            Json::Array(_) => node.get_element(step.parse().ok()?),
+            _ => node.get_field(step),
+        })
+    }
+
+    /// Containment (@>): every key/value of `other` appears in `self`, and
+    /// every element of an array in `other` is contained in some element of
+    /// the matching array. A top-level array also contains a bare scalar.
+    pub fn contains(&self, other: &Json) -> bool {
+        match (self, other) {
+            (Json::Array(elements), scalar)
+                if !matches!(scalar, Json::Array(_) | Json::Object(_)) =>
+            {
+                elements.contains(scalar)
+            }
+            _ => self.contains_nested(other),
+        }
+    }
+
+    fn contains_nested(&self, other: &Json) -> bool {
+        match (self, other) {
+            (Json::Object(pairs), Json::Object(wanted)) => wanted.iter().all(|(key, value)| {
+                pairs
+                    .binary_search_by(|(k, _)| key_order(k, key))
+                    .is_ok_and(|i| pairs[i].1.contains_nested(value))
+            }),
+            (Json::Array(elements), Json::Array(wanted)) => wanted.iter().all(|w| {
+                elements.iter().any(|e| match (e, w) {
+                    (Json::Array(_), Json::Array(_)) | (Json::Object(_), Json::Object(_)) => {
+                        e.contains_nested(w)
+                    }
+                    _ => e == w,
+                })
+            }),
+            (a, b) => a == b,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_bytes(&mut buf);
+        buf
+    }
+
+    fn write_bytes(&self, buf: &mut Vec<u8>) {
+        match self {
+            Json::Null => buf.push(TAG_NULL),
+            Json::Bool(false) => buf.push(TAG_FALSE),
+            Json::Bool(true) => buf.push(TAG_TRUE),
+            Json::Number(n) => {
+                buf.push(TAG_NUMBER);
+                buf.extend(n.to_bytes());
+            }
+            Json::String(s) => {
+                buf.push(TAG_STRING);
+                write_str(buf, s);
+            }
+            Json::Array(elements) => {
+                buf.push(TAG_ARRAY);
+                buf.extend_from_slice(&(elements.len() as u32).to_ne_bytes());
+                for element in elements {
+                    element.write_bytes(buf);
+                }
+            }
+            Json::Object(pairs) => {
+                buf.push(TAG_OBJECT);
+                buf.extend_from_slice(&(pairs.len() as u32).to_ne_bytes());
+                for (key, value) in pairs {
+                    write_str(buf, key);
+                    value.write_bytes(buf);
+                }
+            }
+        }
+    }
+
+    /// Inverse of `to_bytes`; returns the value and the number of bytes read
+    pub fn from_bytes(data: &[u8]) -> Result<(Json, usize)> {
+        let read_u32 = |at: usize| -> Result<usize> {
+            Ok(u32::from_ne_bytes(data[at..at + 4].try_into()?) as usize)
+        };
+        let read_str = |at: usize| -> Result<(String, usize)> {
+            let len = read_u32(at)?;
+            Ok((String::from_utf8(data[at + 4..at + 4 + len].to_vec())?, 4 + len))
+        };
+        match data[0] {
+            TAG_NULL => Ok((Json::Null, 1)),
+            TAG_FALSE => Ok((Json::Bool(false), 1)),
+            TAG_TRUE => Ok((Json::Bool(true), 1)),
+            TAG_NUMBER => {
+                let (n, len) = Numeric::from_bytes(&data[1..])?;
+                Ok((Json::Number(n), 1 + len))
+            }
+            TAG_STRING => {
+                let (s, len) = read_str(1)?;
+                Ok((Json::String(s), 1 + len))
+            }
+            TAG_ARRAY => {
+                let count = read_u32(1)?;
+                let mut offset = 5;
+                let mut elements = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let (element, len) = Json::from_bytes(&data[offset..])?;
+                    elements.push(element);
+                    offset += len;
+                }
+                Ok((Json::Array(elements), offset))
+            }
+            TAG_OBJECT => {
+                let count = read_u32(1)?;
+                let mut offset = 5;
+                let mut pairs = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let (key, key_len) = read_str(offset)?;
+                    let (value, len) = Json::from_bytes(&data[offset + key_len..])?;
+                    pairs.push((key, value));
+                    offset += key_len + len;
+                }
+                Ok((Json::Object(pairs), offset))
+            }
+            tag => bail!("invalid jsonb tag {tag}"),
+        }
+    }
+
+    /// Sort rank of each kind of value: null < string < number < boolean <
+    /// array < object, as in PostgreSQL's jsonb btree ordering
+    fn rank(&self) -> u8 {
+        match self {
+            Json::Null => 0,
+            Json::String(_) => 1,
+            Json::Number(_) => 2,
+            Json::Bool(_) => 3,
+            Json::Array(_) => 4,
+            Json::Object(_) => 5,
+        }
+    }
+}
+
+// Containers compare by size first, then element by element (objects key
+// by key, then value by value).
+impl Ord for Json {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Json::String(a), Json::String(b)) => a.cmp(b),
+            (Json::Number(a), Json::Number(b)) => a.cmp(b),
+            (Json::Bool(a), Json::Bool(b)) => a.cmp(b),
+            (Json::Array(a), Json::Array(b)) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+            (Json::Object(a), Json::Object(b)) => a
+                .len()
+                .cmp(&b.len())
+                .then_with(|| {
+                    let keys = a.iter().zip(b).map(|((x, _), (y, _))| key_order(x, y));
+                    keys.fold(Ordering::Equal, Ordering::then)
+                })
+                .then_with(|| a.iter().map(|(_, v)| v).cmp(b.iter().map(|(_, v)| v))),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Json {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Output in PostgreSQL's jsonb style: `{"a": 1, "b": [true, null]}`
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_quoted(f, s),
+            Json::Array(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(pairs) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write_quoted(f, key)?;
+                    write!(f, ": {value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Parse a text path such as '{a,b,0}' (the text[] form taken by #>)
+pub fn parse_path(text: &str) -> Result<Vec<String>> {
+    let Some(inner) = text.trim().strip_prefix('{').and_then(|t| t.strip_suffix('}')) else {
+        bail!("malformed array literal: \"{text}\"");
+    };
+    if inner.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(inner
+        .split(',')
+        .map(|step| step.trim().trim_matches('"').to_string())
+        .collect())
+}
+
+/// jsonb key order: shorter keys first, then bytewise
+fn key_order(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_ne_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{08}' => f.write_str("\\b")?,
+            '\u{0c}' => f.write_str("\\f")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+struct JsonParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self) -> anyhow::Error {
+        let text = String::from_utf8_lossy(self.input);
+        anyhow::anyhow!("invalid input syntax for type json: \"{text}\"")
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.input.get(self.pos).is_some_and(|b| b" \t\r\n".contains(b)) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.input.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Json> {
+        if depth > MAX_DEPTH {
+            bail!("stack depth limit exceeded");
+        }
+        self.skip_whitespace();
+        match self.input.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut pairs = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.parse_string()?;
+                        self.expect(b':')?;
+                        pairs.push((key, self.parse_value(depth + 1)?));
+                        if !self.eat(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b'}')?;
+                }
+                Ok(Json::object(pairs))
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut elements = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        elements.push(self.parse_value(depth + 1)?);
+                        if !self.eat(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b']')?;
+                }
+                Ok(Json::Array(elements))
+            }
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => {
+                for (word, value) in [
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                    ("null", Json::Null),
+                ] {
+                    if self.input[self.pos..].starts_with(word.as_bytes()) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error())
+            }
+            None => Err(self.error()),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            let from = p.pos;
+            while p.input.get(p.pos).is_some_and(u8::is_ascii_digit) {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+        if self.input.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        // No leading zeros: "0" or [1-9][0-9]*
+        if self.input.get(self.pos) == Some(&b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return Err(self.error());
+        }
+        if self.input.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error());
+            }
+        }
+        if matches!(self.input.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.input.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error());
+            }
+        }
+        let text = std::str::from_utf8(&self.input[start..self.pos])?;
+        Ok(Json::Number(Numeric::parse(text)?))
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        if self.input.get(self.pos) != Some(&b'"') {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.input.get(self.pos) {
+                None => return Err(self.error()),
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    let escaped = match self.input.get(self.pos + 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{08}',
+                        Some(b'f') => '\u{0c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 2;
+                            let c = self.parse_unicode_escape()?;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                            continue;
+                        }
+                        _ => return Err(self.error()),
+                    };
+                    out.push(escaped as u8);
+                    self.pos += 2;
+                }
+                Some(b) if *b < 0x20 => return Err(self.error()),
+                Some(b) => {
+                    out.push(*b);
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// The XXXX of \uXXXX (already past the "\u"), joining surrogate pairs
+    fn parse_unicode_escape(&mut self) -> Result<char> {
+        let hex4 = |p: &mut Self| -> Result<u32> {
+            let digits = p.input.get(p.pos..p.pos + 4).ok_or_else(|| p.error())?;
+            let code = std::str::from_utf8(digits)
+                .ok()
+                .and_then(|d| u32::from_str_radix(d, 16).ok())
+                .ok_or_else(|| p.error())?;
+            p.pos += 4;
+            Ok(code)
+        };
+        let high = hex4(self)?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.input[self.pos..].starts_with(b"\\u") {
+                bail!("invalid input syntax for type json: unpaired Unicode surrogate");
+            }
+            self.pos += 2;
+            let low = hex4(self)?;
+            if !(0xdc00..0xe000).contains(&low) {
+                bail!("invalid input syntax for type json: unpaired Unicode surrogate");
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        if code == 0 {
+            bail!("unsupported Unicode escape sequence: \\u0000 cannot be converted to text");
+        }
+        char::from_u32(code).ok_or_else(|| {
+            anyhow::anyhow!("invalid input syntax for type json: unpaired Unicode surrogate")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let json = Json::parse(r#" {"b": [1, 2.50, -3e2], "a": "x\"é", "b": true, "cc": null} "#)
+            .unwrap();
+        // Keys sorted shorter-first, the duplicate "b" keeps its last value
+        assert_eq!(json.to_string(), r#"{"a": "x\"é", "b": true, "cc": null}"#);
+        assert_eq!(Json::parse("[1.50, {}]").unwrap().to_string(), "[1.50, {}]");
+
+        for bad in ["", "{", "[1,]", "01", "1.", "'a'", "{\"a\" 1}", "nul", "[1] x"] {
+            assert!(Json::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let json = Json::parse(r#"{"tags": ["a", "b"], "n": 12.5, "ok": false, "z": null}"#).unwrap();
+        let bytes = json.to_bytes();
+        assert_eq!(Json::from_bytes(&bytes).unwrap(), (json, bytes.len()));
+    }
+
+    #[test]
+    fn test_access_and_containment() {
+        let json = Json::parse(r#"{"a": {"b": [10, 20, {"c": "x"}]}, "tags": ["red", "blue"]}"#)
+            .unwrap();
+        let path: Vec<String> = ["a", "b", "-1", "c"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(json.get_path(&path), Some(&Json::String("x".into())));
+        assert_eq!(json.get_field("a").and_then(|a| a.get_field("b")).and_then(|b| b.get_element(1)).unwrap().to_string(), "20");
+        assert!(json.get_field("missing").is_none());
+
+        let contains = |wanted: &str| json.contains(&Json::parse(wanted).unwrap());
+        assert!(contains(r#"{"tags": ["blue"]}"#));
+        assert!(contains(r#"{"a": {"b": [{"c": "x"}]}}"#));
+        assert!(!contains(r#"{"tags": ["green"]}"#));
+        assert!(!contains(r#"{"a": {"b": 10}}"#));
+        assert!(Json::parse("[1, 2]").unwrap().contains(&Json::parse("2").unwrap()));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let parse = |s: &str| Json::parse(s).unwrap();
+        assert!(parse("null") < parse(r#""z""#));
+        assert!(parse(r#""z""#) < parse("1"));
+        assert!(parse("2") < parse("10"));
+        assert!(parse("[9]") < parse("[1, 1]"));
+        assert!(parse(r#"{"a": 1}"#) < parse(r#"{"a": 2}"#));
+        assert_eq!(parse("1.0"), parse("1"));
+    }
+}
@@ -13,7 +13,16 @@ pub enum Token {
     Table,
     Index,
     Int,
+    SmallInt,
+    BigInt,
+    Real,
+    Float,
+    Double,
+    Numeric,
     Varchar,
+    Char,
+    Text,
+    Boolean,
     And,
     Or,
     Not,
@@ -27,6 +36,7 @@ pub enum Token {
     Commit,
     Rollback,
     Checkpoint,
+    Vacuum,
     Join,
     Inner,
     Left,
@@ -40,10 +50,18 @@ pub enum Token {
     Avg,
     Min,
     Max,
+    Unique,
+    Conflict,
+    Do,
+    Nothing,
+    Returning,
+    Using,
+    Collate,
 
     // Identifiers and literals
     Ident(String),
     Integer(i64),
+    Decimal(String), // decimal literal, or integer literal too large for i64
     String(String),
 
     // Symbols
@@ -62,6 +80,15 @@ pub enum Token {
     Minus,     // -
     Slash,     // /
     Dot,       // .
+    Arrow,         // ->
+    LongArrow,     // ->>
+    HashArrow,     // #>
+    HashLongArrow, // #>>
+    AtArrow,       // @>
+    LBracket,      // [
+    RBracket,      // ]
+    DoubleColon,   // ::
+    Parameter(usize), // $1, a parameter of a prepared statement
 
     Eof,
 }
@@ -142,18 +169,57 @@ impl Lexer {
                 self.advance();
                 Token::RParen
             }
+            '[' => {
+                self.advance();
+                Token::LBracket
+            }
+            ':' if self.input.get(self.pos + 1) == Some(&':') => {
+                self.pos += 2;
+                Token::DoubleColon
+            }
+            ']' => {
+                self.advance();
+                Token::RBracket
+            }
             '+' => {
                 self.advance();
                 Token::Plus
             }
             '-' => {
                 self.advance();
-                Token::Minus
+                if self.peek() == Some('>') {
+                    self.advance();
+                    if self.peek() == Some('>') {
+                        self.advance();
+                        Token::LongArrow
+                    } else {
+                        Token::Arrow
+                    }
+                } else {
+                    Token::Minus
+                }
+            }
+            '#' if self.input.get(self.pos + 1) == Some(&'>') => {
+                self.pos += 2;
+                if self.peek() == Some('>') {
+                    self.advance();
+                    Token::HashLongArrow
+                } else {
+                    Token::HashArrow
+                }
+            }
+            '@' if self.input.get(self.pos + 1) == Some(&'>') => {
+                self.pos += 2;
+                Token::AtArrow
             }
             '/' => {
                 self.advance();
                 Token::Slash
             }
+            // A leading dot may start a decimal literal such as .5
+            '.' if self.input.get(self.pos + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                self.read_number()?
+            }
             '.' => {
                 self.advance();
                 Token::Dot
@@ -183,7 +249,17 @@ impl Lexer {
                     Token::Gt
                 }
             }
+            '$' if self.input.get(self.pos + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                self.advance();
+                let mut digits = String::new();
+                self.read_digits(&mut digits);
+                let n = digits
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("parameter number too large: ${digits}"))?;
+                Token::Parameter(n)
+            }
             '\'' => self.read_string()?,
+            '"' => self.read_quoted_ident()?,
             _ if ch.is_ascii_digit() => self.read_number()?,
             _ if ch.is_ascii_alphabetic() || ch == '_' => self.read_ident_or_keyword()?,
             _ => bail!("unexpected character: {ch}"),
@@ -211,8 +287,61 @@ impl Lexer {
         Ok(Token::String(s))
     }
 
+    // "Name": an identifier taken as written, with "" for a double quote
+    fn read_quoted_ident(&mut self) -> Result<Token> {
+        self.advance(); // consume opening quote
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                Some('"') if self.peek() == Some('"') => {
+                    self.advance();
+                    s.push('"');
+                }
+                Some('"') => break,
+                Some(ch) => s.push(ch),
+                None => bail!("unterminated quoted identifier"),
+            }
+        }
+        if s.is_empty() {
+            bail!("zero-length delimited identifier");
+        }
+        Ok(Token::Ident(s))
+    }
+
     fn read_number(&mut self) -> Result<Token> {
         let mut s = String::new();
+        self.read_digits(&mut s);
+        let mut is_decimal = false;
+        if self.peek() == Some('.') {
+            is_decimal = true;
+            s.push('.');
+            self.advance();
+            self.read_digits(&mut s);
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            is_decimal = true;
+            s.push('e');
+            self.advance();
+            if let Some(sign @ ('+' | '-')) = self.peek() {
+                s.push(sign);
+                self.advance();
+            }
+            let len = s.len();
+            self.read_digits(&mut s);
+            if s.len() == len {
+                bail!("trailing junk after numeric literal: {s}");
+            }
+        }
+        if is_decimal {
+            return Ok(Token::Decimal(s));
+        }
+        match s.parse::<i64>() {
+            Ok(n) => Ok(Token::Integer(n)),
+            Err(_) => Ok(Token::Decimal(s)),
+        }
+    }
+
+    fn read_digits(&mut self, s: &mut String) {
         while let Some(ch) = self.peek() {
             if ch.is_ascii_digit() {
                 s.push(ch);
@@ -221,8 +350,6 @@ impl Lexer {
                 break;
             }
         }
-        let n: i64 = s.parse()?;
-        Ok(Token::Integer(n))
     }
 
     fn read_ident_or_keyword(&mut self) -> Result<Token> {
@@ -247,6 +374,18 @@ impl Lexer {
             "INDEX" => Token::Index,
             "INT" => Token::Int,
             "INTEGER" => Token::Int,
+            "INT4" => Token::Int,
+            "SMALLINT" => Token::SmallInt,
+            "INT2" => Token::SmallInt,
+            "BIGINT" => Token::BigInt,
+            "INT8" => Token::BigInt,
+            "REAL" => Token::Real,
+            "FLOAT4" => Token::Real,
+            "FLOAT" => Token::Float,
+            "FLOAT8" => Token::Float,
+            "DOUBLE" => Token::Double,
+            "NUMERIC" => Token::Numeric,
+            "DECIMAL" => Token::Numeric,
             "VARCHAR" => Token::Varchar,
             "AND" => Token::And,
             "OR" => Token::Or,
@@ -261,6 +400,7 @@ impl Lexer {
             "COMMIT" => Token::Commit,
             "ROLLBACK" => Token::Rollback,
             "CHECKPOINT" => Token::Checkpoint,
+            "VACUUM" => Token::Vacuum,
             "JOIN" => Token::Join,
             "INNER" => Token::Inner,
             "LEFT" => Token::Left,
@@ -274,6 +414,16 @@ impl Lexer {
             "AVG" => Token::Avg,
             "MIN" => Token::Min,
             "MAX" => Token::Max,
+            "UNIQUE" => Token::Unique,
+            "CONFLICT" => Token::Conflict,
+            "DO" => Token::Do,
+            "NOTHING" => Token::Nothing,
+            "RETURNING" => Token::Returning,
+            "USING" => Token::Using,
+            "COLLATE" => Token::Collate,
+            "CHAR" | "CHARACTER" => Token::Char,
+            "TEXT" => Token::Text,
+            "BOOLEAN" | "BOOL" => Token::Boolean,
             _ => Token::Ident(s),
         };
         Ok(token)
@@ -1,9 +1,20 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::sync::{Condvar, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::btree::IndexKey;
+use crate::cancel::check_for_interrupts;
 use crate::executor::Rid;
 
+/// Number of unique-insert locks; a key takes the one its hash picks
+const UNIQUE_INSERT_STRIPES: usize = 256;
+
+/// Longest a lock wait goes without checking for a cancel request or
+/// statement_timeout
+const INTERRUPT_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LockMode {
     Shared,    // For SELECT
@@ -13,17 +24,27 @@ pub enum LockMode {
 #[derive(Debug)]
 pub enum LockError {
     Timeout,
+    /// The statement was cancelled or ran out of time while waiting
+    Interrupted(anyhow::Error),
 }
 
 impl std::fmt::Display for LockError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             LockError::Timeout => write!(f, "lock acquisition timeout (possible deadlock)"),
+            LockError::Interrupted(e) => write!(f, "{e}"),
         }
     }
 }
 
-impl std::error::Error for LockError {}
+impl std::error::Error for LockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LockError::Timeout => None,
+            LockError::Interrupted(e) => Some(e.as_ref()),
+        }
+    }
+}
 
 struct LockRequest {
     txn_id: u64,
@@ -85,6 +106,52 @@ pub struct LockManager {
     lock_table: Mutex<HashMap<Rid, LockState>>,
     cond: Condvar,
     timeout: Duration,
+    // Serialize unique-index checks with the insertion that follows them,
+    // so two sessions cannot both decide that the same key is free. Only
+    // sessions inserting keys that hash alike wait for each other. These
+    // are the stripes taken; waiters are woken when some are given back.
+    unique_insert: Mutex<HashSet<usize>>,
+    unique_insert_freed: Condvar,
+}
+
+/// Unique-insert locks held for some (index ID, key) pairs
+pub struct UniqueInsertGuard<'a> {
+    lock_manager: &'a LockManager,
+    held: HashSet<usize>,
+}
+
+fn unique_insert_stripe((index_id, key): &(u32, IndexKey)) -> usize {
+    let mut hasher = DefaultHasher::new();
+    index_id.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish() as usize % UNIQUE_INSERT_STRIPES
+}
+
+impl UniqueInsertGuard<'_> {
+    /// Also lock these keys, without waiting: false, with none of them
+    /// taken, if another session holds one. Waiting could deadlock, as the
+    /// locks already held were not taken in order with these.
+    pub fn try_add(&mut self, keys: &[(u32, IndexKey)]) -> bool {
+        let stripes: HashSet<usize> = keys.iter().map(unique_insert_stripe).collect();
+        let mut taken = self.lock_manager.unique_insert.lock().unwrap();
+        if stripes.iter().any(|s| !self.held.contains(s) && taken.contains(s)) {
+            return false;
+        }
+        taken.extend(&stripes);
+        self.held.extend(stripes);
+        true
+    }
+}
+
+impl Drop for UniqueInsertGuard<'_> {
+    fn drop(&mut self) {
+        if self.held.is_empty() {
+            return;
+        }
+        let mut taken = self.lock_manager.unique_insert.lock().unwrap();
+        taken.retain(|s| !self.held.contains(s));
+        self.lock_manager.unique_insert_freed.notify_all();
+    }
 }
 
 impl LockManager {
@@ -93,9 +160,48 @@ impl LockManager {
             lock_table: Mutex::new(HashMap::new()),
             cond: Condvar::new(),
             timeout: Duration::from_secs(30),
+            unique_insert: Mutex::new(HashSet::new()),
+            unique_insert_freed: Condvar::new(),
         }
     }
 
+    /// Hold this guard from the unique-index check of these keys until the new index entries
+    /// are in place. Never wait for a row lock while holding it.
+    pub fn lock_unique_insert(
+        &self,
+        keys: &[(u32, IndexKey)],
+    ) -> Result<UniqueInsertGuard<'_>, LockError> {
+        let stripes: HashSet<usize> = keys.iter().map(unique_insert_stripe).collect();
+        // All at once, so sessions locking several never deadlock
+        let mut taken = self.unique_insert.lock().unwrap();
+        while stripes.iter().any(|s| taken.contains(s)) {
+            taken = self
+                .unique_insert_freed
+                .wait_timeout(taken, INTERRUPT_CHECK_INTERVAL)
+                .unwrap()
+                .0;
+            check_for_interrupts().map_err(LockError::Interrupted)?;
+        }
+        taken.extend(&stripes);
+        Ok(UniqueInsertGuard {
+            lock_manager: self,
+            held: stripes,
+        })
+    }
+
+    /// Whether some transaction is waiting for a lock on `rid`
+    #[cfg(test)]
+    pub fn has_waiters(&self, rid: Rid) -> bool {
+        let table = self.lock_table.lock().unwrap();
+        table.get(&rid).is_some_and(|state| !state.wait_queue.is_empty())
+    }
+
+    /// Whether any transaction holds or waits for a row lock
+    #[cfg(test)]
+    pub fn has_locks(&self) -> bool {
+        !self.lock_table.lock().unwrap().is_empty()
+    }
+
     pub fn lock(&self, txn_id: u64, rid: Rid, mode: LockMode) -> Result<(), LockError> {
         let mut table = self.lock_table.lock().unwrap();
 
@@ -118,25 +224,33 @@ impl LockManager {
         // Must wait - add to queue
         state.wait_queue.push_back(LockRequest { txn_id, mode });
 
-        // Wait for lock to be granted
-        let mut result = self
-            .cond
-            .wait_timeout_while(table, self.timeout, |table| {
-                let state = table.get(&rid).unwrap();
-                !state.holders.contains_key(&txn_id)
-            })
-            .unwrap();
-
-        if result.1.timed_out() {
-            // Remove from wait queue on timeout
-            let table = &mut *result.0;
-            if let Some(state) = table.get_mut(&rid) {
+        // Wait for the lock to be granted, checking for a cancel request or
+        // statement_timeout on each wakeup
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let now = Instant::now();
+            let failure = if now >= deadline {
+                Some(LockError::Timeout)
+            } else {
+                check_for_interrupts().err().map(LockError::Interrupted)
+            };
+            if let Some(error) = failure {
+                let state = table.get_mut(&rid).unwrap();
                 state.wait_queue.retain(|r| r.txn_id != txn_id);
+                // Our request may have held back the ones queued after it
+                self.grant_waiting_locks(state);
+                self.cond.notify_all();
+                return Err(error);
+            }
+            table = self
+                .cond
+                .wait_timeout(table, (deadline - now).min(INTERRUPT_CHECK_INTERVAL))
+                .unwrap()
+                .0;
+            if table.get(&rid).unwrap().holders.contains_key(&txn_id) {
+                return Ok(());
             }
-            return Err(LockError::Timeout);
         }
-
-        Ok(())
     }
 
     pub fn unlock_all(&self, txn_id: u64, held_locks: &HashSet<Rid>) {
@@ -189,3 +303,84 @@ impl LockManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    use super::*;
+    use crate::cancel::{self, CancelRegistry};
+    use crate::protocol::{SqlError, QUERY_CANCELED};
+    use crate::settings;
+    use crate::tuple::Value;
+
+    #[test]
+    fn test_unique_insert_locks_by_key() {
+        let lock_manager = LockManager::new();
+        let key = |i: i32| (1, IndexKey::single(Value::Int(i)));
+        let guard = lock_manager.lock_unique_insert(&[key(1)]).unwrap();
+
+        // Another session inserting the same key must wait
+        let mut other = lock_manager.lock_unique_insert(&[]).unwrap();
+        assert!(!other.try_add(&[key(1)]));
+        let free = (2..100)
+            .find(|&i| unique_insert_stripe(&key(i)) != unique_insert_stripe(&key(1)))
+            .unwrap();
+        assert!(other.try_add(&[key(free)]));
+        assert!(other.try_add(&[key(free)]));
+
+        drop(guard);
+        assert!(other.try_add(&[key(1), key(free)]));
+    }
+
+    #[test]
+    fn test_lock_wait_is_interruptible() {
+        let lock_manager = Arc::new(LockManager::new());
+        let rid = Rid {
+            page_id: 1,
+            slot_id: 0,
+        };
+        lock_manager.lock(1, rid, LockMode::Exclusive).unwrap();
+
+        // A statement blocked on the row lock is cancelled
+        let registry = Arc::new(CancelRegistry::new());
+        let (sender, receiver) = mpsc::channel();
+        let waiter = {
+            let lock_manager = Arc::clone(&lock_manager);
+            let registry = Arc::clone(&registry);
+            thread::spawn(move || {
+                let key = registry.register();
+                sender.send((key.pid, key.secret)).unwrap();
+                cancel::start_statement();
+                lock_manager.lock(2, rid, LockMode::Exclusive)
+            })
+        };
+        let (pid, secret) = receiver.recv().unwrap();
+        while !lock_manager.has_waiters(rid) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(registry.cancel(pid, secret));
+        let error = anyhow::Error::from(waiter.join().unwrap().unwrap_err());
+        assert_eq!(error.to_string(), "canceling statement due to user request");
+        assert_eq!(SqlError::find(&error).unwrap().code, QUERY_CANCELED);
+        assert!(!lock_manager.has_waiters(rid));
+
+        // ... and so is one that runs into statement_timeout
+        let waiter = {
+            let lock_manager = Arc::clone(&lock_manager);
+            thread::spawn(move || {
+                settings::set("statement_timeout", Some("50ms")).unwrap();
+                cancel::start_statement();
+                lock_manager.lock(3, rid, LockMode::Shared)
+            })
+        };
+        let error = anyhow::Error::from(waiter.join().unwrap().unwrap_err());
+        assert_eq!(error.to_string(), "canceling statement due to statement timeout");
+        assert_eq!(SqlError::find(&error).unwrap().code, QUERY_CANCELED);
+
+        // The holder keeps its lock, and the next waiter still gets it
+        lock_manager.unlock_all(1, &[rid].into_iter().collect());
+        lock_manager.lock(4, rid, LockMode::Exclusive).unwrap();
+    }
+}
@@ -1,23 +1,38 @@
 mod analyzer;
+mod array;
 mod ast;
+mod auth;
 mod bootstrap;
 mod btree;
 mod buffer_pool;
+mod bytea;
+mod cancel;
 mod catalog;
 mod checkpoint;
 mod clog;
+mod collation;
+mod config;
+mod copy;
+mod datetime;
 mod disk;
 mod executor;
+mod extensions;
+mod functions;
 mod instance;
+mod json;
 mod lexer;
 mod lock_manager;
+mod numeric;
 mod page;
 mod parser;
 mod protocol;
 mod recovery;
+mod settings;
+mod toast;
 mod transaction;
 mod transaction_manager;
 mod tuple;
+mod udf;
 mod visibility;
 mod wal;
 
@@ -29,6 +44,7 @@ use instance::Instance;
 
 fn main() -> Result<()> {
     let init = env::args().any(|arg| arg == "--init");
+    extensions::register_all()?;
     let instance = Instance::new(init)?;
     instance.start()
 }
@@ -0,0 +1,636 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use anyhow::{bail, Result};
+
+// ============================================================================
+// Numeric - arbitrary precision decimal for NUMERIC/DECIMAL
+// ============================================================================
+//
+// A value is stored as sign + unscaled decimal digits + scale:
+//   -12.340  =>  negative=true, digits=[1,2,3,4,0], scale=3
+//
+// The scale is kept as written (12.340 displays with three decimals), while
+// equality, ordering and hashing compare by value (12.340 = 12.34).
+
+/// Minimum significant digits of a division result (same as PostgreSQL)
+const MIN_SIG_DIGITS: i32 = 16;
+/// Upper bound on the scale of computed results
+const MAX_RESULT_SCALE: i32 = 1000;
+
+pub const MAX_PRECISION: u32 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct Numeric {
+    negative: bool,
+    /// Unscaled digits, most significant first, no leading zeros (empty = 0)
+    digits: Vec<u8>,
+    /// Number of digits after the decimal point
+    scale: u32,
+}
+
+impl Numeric {
+    fn new(negative: bool, mut digits: Vec<u8>, scale: u32) -> Self {
+        let leading = digits.iter().take_while(|&&d| d == 0).count();
+        digits.drain(..leading);
+        Numeric {
+            negative: negative && !digits.is_empty(),
+            digits,
+            scale,
+        }
+    }
+
+    /// Parse decimal text such as "12", "-0.5", ".25" or "1.5e3".
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid input syntax for type numeric: \"{s}\"");
+        let text = s.trim();
+        let (negative, text) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(pos) => {
+                let exp: i32 = text[pos + 1..].parse().map_err(|_| invalid())?;
+                // Anything further out cannot be a valid NUMERIC, and a huge
+                // positive exponent would otherwise allocate its zeros
+                if exp.unsigned_abs() > (MAX_RESULT_SCALE as u32 + MAX_PRECISION) {
+                    bail!("value overflows numeric format");
+                }
+                (&text[..pos], exp)
+            }
+            None => (text, 0),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(invalid());
+        }
+        let mut digits = Vec::with_capacity(int_part.len() + frac_part.len());
+        for ch in int_part.chars().chain(frac_part.chars()) {
+            digits.push(ch.to_digit(10).ok_or_else(invalid)? as u8);
+        }
+
+        // Fold the exponent into the scale
+        let mut scale = i32::try_from(frac_part.len())
+            .ok()
+            .and_then(|len| len.checked_sub(exponent))
+            .ok_or_else(invalid)?;
+        if scale < 0 {
+            digits.extend(std::iter::repeat_n(0, (-scale) as usize));
+            scale = 0;
+        }
+        if scale > MAX_RESULT_SCALE {
+            return Err(invalid());
+        }
+        Ok(Numeric::new(negative, digits, scale as u32))
+    }
+
+    pub fn from_i64(n: i64) -> Self {
+        let digits = n.unsigned_abs().to_string().bytes().map(|b| b - b'0').collect();
+        Numeric::new(n < 0, digits, 0)
+    }
+
+    pub fn from_f64(f: f64) -> Result<Self> {
+        if f.is_nan() {
+            bail!("cannot convert NaN to numeric");
+        }
+        if f.is_infinite() {
+            bail!("cannot convert infinity to numeric");
+        }
+        // Display for f64 prints the shortest round-trip form without an exponent
+        Numeric::parse(&f.to_string())
+    }
+
+    /// Round to an integer and convert, or None if it does not fit in i64.
+    pub fn to_i64(&self) -> Option<i64> {
+        let rounded = self.round(0);
+        let mut n: i64 = 0;
+        for &d in &rounded.digits {
+            n = n.checked_mul(10)?.checked_sub(d as i64)?;
+        }
+        if rounded.negative {
+            Some(n)
+        } else {
+            n.checked_neg()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// Round half away from zero to `scale` decimal places.
+    pub fn round(&self, scale: u32) -> Numeric {
+        if scale >= self.scale {
+            return Numeric::new(self.negative, self.aligned(scale), scale);
+        }
+        let drop = (self.scale - scale) as usize;
+        if drop > self.digits.len() {
+            return Numeric::new(false, vec![], scale);
+        }
+        let keep = self.digits.len() - drop;
+        let mut digits = self.digits[..keep].to_vec();
+        if self.digits[keep] >= 5 {
+            digits = add_magnitudes(&digits, &[1]);
+        }
+        Numeric::new(self.negative, digits, scale)
+    }
+
+    /// Apply a NUMERIC(precision, scale) column constraint.
+    pub fn apply_typmod(&self, precision: u32, scale: u32) -> Result<Numeric> {
+        let rounded = self.round(scale);
+        let int_digits = rounded.digits.len().saturating_sub(scale as usize);
+        if int_digits > (precision - scale) as usize {
+            bail!(
+                "numeric field overflow: a field with precision {precision}, scale {scale} must round to an absolute value less than 10^{}",
+                precision - scale
+            );
+        }
+        Ok(rounded)
+    }
+
+    pub fn neg(&self) -> Numeric {
+        Numeric::new(!self.negative, self.digits.clone(), self.scale)
+    }
+
+    pub fn add(&self, other: &Numeric) -> Numeric {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.aligned(scale), other.aligned(scale));
+        if self.negative == other.negative {
+            return Numeric::new(self.negative, add_magnitudes(&a, &b), scale);
+        }
+        match compare_magnitudes(&a, &b) {
+            Ordering::Less => Numeric::new(other.negative, sub_magnitudes(&b, &a), scale),
+            _ => Numeric::new(self.negative, sub_magnitudes(&a, &b), scale),
+        }
+    }
+
+    pub fn sub(&self, other: &Numeric) -> Numeric {
+        self.add(&other.neg())
+    }
+
+    /// Multiply exactly, rounding to MAX_RESULT_SCALE if the product has more
+    /// decimal places
+    pub fn mul(&self, other: &Numeric) -> Numeric {
+        let product = Numeric::new(
+            self.negative != other.negative,
+            mul_magnitudes(&self.digits, &other.digits),
+            self.scale + other.scale,
+        );
+        if product.scale > MAX_RESULT_SCALE as u32 {
+            return product.round(MAX_RESULT_SCALE as u32);
+        }
+        product
+    }
+
+    /// Divide, choosing the result scale the way PostgreSQL does: at least 16
+    /// significant digits and no less than either input's scale.
+    /// Returns None on division by zero.
+    pub fn div(&self, other: &Numeric) -> Option<Numeric> {
+        if other.is_zero() {
+            return None;
+        }
+        let (weight1, first1) = self.leading_group();
+        let (weight2, first2) = other.leading_group();
+        let mut qweight = weight1 - weight2;
+        if first1 <= first2 {
+            qweight -= 1;
+        }
+        let rscale = (MIN_SIG_DIGITS - qweight * 4)
+            .max(self.scale as i32)
+            .max(other.scale as i32)
+            .clamp(0, MAX_RESULT_SCALE) as u32;
+
+        // Compute one guard digit past rscale, then round
+        let shift = (rscale + 1 + other.scale - self.scale) as usize;
+        let mut dividend = self.digits.clone();
+        dividend.extend(std::iter::repeat_n(0, shift));
+        let quotient = div_magnitudes(&dividend, &other.digits);
+        let exact = Numeric::new(self.negative != other.negative, quotient, rscale + 1);
+        Some(exact.round(rscale))
+    }
+
+    /// Square root with at least 16 significant digits and no less than the
+    /// input's scale. Returns None for a negative input.
+    pub fn sqrt(&self) -> Option<Numeric> {
+        if self.negative {
+            return None;
+        }
+        // The root has about half as many digits before the point
+        let int_digits = self.digits.len() as i32 - self.scale as i32;
+        let root_weight = (int_digits + 1).div_euclid(2);
+        let rscale = (MIN_SIG_DIGITS - root_weight)
+            .max(self.scale as i32)
+            .clamp(0, MAX_RESULT_SCALE) as u32;
+        // Integer square root of the value scaled by 10^(2 * (rscale + 1)),
+        // keeping one guard digit to round away
+        let root = isqrt_magnitude(&self.aligned(2 * (rscale + 1)));
+        Some(Numeric::new(false, root, rscale + 1).round(rscale))
+    }
+
+    /// Weight and value of the leading base-10000 digit, mirroring
+    /// PostgreSQL's internal representation (used to pick division scale).
+    fn leading_group(&self) -> (i32, u32) {
+        if self.is_zero() {
+            return (0, 0);
+        }
+        let exponent = self.digits.len() as i32 - 1 - self.scale as i32;
+        let weight = exponent.div_euclid(4);
+        let width = (exponent - weight * 4 + 1) as usize;
+        let first = (0..width).fold(0, |acc, i| {
+            acc * 10 + self.digits.get(i).copied().unwrap_or(0) as u32
+        });
+        (weight, first)
+    }
+
+    /// Unscaled digits padded with trailing zeros to `scale` (scale >= self.scale)
+    fn aligned(&self, scale: u32) -> Vec<u8> {
+        let mut digits = self.digits.clone();
+        digits.extend(std::iter::repeat_n(0, (scale - self.scale) as usize));
+        digits
+    }
+
+    /// Digits and scale with trailing fractional zeros removed
+    fn normalized(&self) -> (&[u8], u32) {
+        let trailing = self
+            .digits
+            .iter()
+            .rev()
+            .take(self.scale as usize)
+            .take_while(|&&d| d == 0)
+            .count();
+        (
+            &self.digits[..self.digits.len() - trailing],
+            self.scale - trailing as u32,
+        )
+    }
+
+    /// Serialize as [negative: u8][scale: u16][digit count: u32][digits...]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(7 + self.digits.len());
+        buf.push(self.negative as u8);
+        buf.extend_from_slice(&(self.scale as u16).to_ne_bytes());
+        buf.extend_from_slice(&(self.digits.len() as u32).to_ne_bytes());
+        buf.extend_from_slice(&self.digits);
+        buf
+    }
+
+    /// Inverse of `to_bytes`; returns the value and the number of bytes read
+    pub fn from_bytes(data: &[u8]) -> Result<(Numeric, usize)> {
+        let negative = data[0] != 0;
+        let scale = u16::from_ne_bytes(data[1..3].try_into()?) as u32;
+        let len = u32::from_ne_bytes(data[3..7].try_into()?) as usize;
+        let digits = data[7..7 + len].to_vec();
+        Ok((Numeric::new(negative, digits, scale), 7 + len))
+    }
+
+    /// PostgreSQL's binary wire format: [ndigits: i16][weight: i16]
+    /// [sign: u16][dscale: u16] followed by base-10000 digits (i16), where
+    /// the first digit is multiplied by 10000^weight
+    pub fn to_binary(&self) -> Vec<u8> {
+        // Pad the decimal digits so that both the integer part and the
+        // fraction are whole groups of four
+        let int_len = self.digits.len() as i64 - self.scale as i64;
+        let front = (4 - int_len.rem_euclid(4)) % 4;
+        let back = (4 - self.scale as i64 % 4) % 4;
+        let mut padded = vec![0u8; front as usize];
+        padded.extend_from_slice(&self.digits);
+        padded.extend(std::iter::repeat_n(0, back as usize));
+        let mut groups: Vec<i16> = padded
+            .chunks(4)
+            .map(|c| c.iter().fold(0i16, |acc, &d| acc * 10 + d as i16))
+            .collect();
+        let mut weight = (int_len + front) / 4 - 1;
+
+        let leading = groups.iter().take_while(|&&g| g == 0).count();
+        groups.drain(..leading);
+        weight -= leading as i64;
+        while groups.last() == Some(&0) {
+            groups.pop();
+        }
+        if groups.is_empty() {
+            weight = 0;
+        }
+
+        let mut buf = Vec::with_capacity(8 + 2 * groups.len());
+        buf.extend_from_slice(&(groups.len() as i16).to_be_bytes());
+        buf.extend_from_slice(&(weight as i16).to_be_bytes());
+        buf.extend_from_slice(&(if self.negative { 0x4000u16 } else { 0 }).to_be_bytes());
+        buf.extend_from_slice(&(self.scale as u16).to_be_bytes());
+        for group in groups {
+            buf.extend_from_slice(&group.to_be_bytes());
+        }
+        buf
+    }
+
+    /// Inverse of `to_binary`
+    pub fn from_binary(data: &[u8]) -> Result<Numeric> {
+        if data.len() < 8 {
+            bail!("insufficient data left in message");
+        }
+        let read = |at: usize| i16::from_be_bytes([data[at], data[at + 1]]);
+        let ndigits = read(0);
+        let weight = read(2) as i64;
+        let sign = read(4) as u16;
+        let scale = read(6) as u16 as u32;
+        if ndigits < 0 || data.len() != 8 + 2 * ndigits as usize {
+            bail!("invalid length in external \"numeric\" value");
+        }
+        if scale > MAX_RESULT_SCALE as u32 {
+            bail!("invalid scale in external \"numeric\" value");
+        }
+        let negative = match sign {
+            0x0000 => false,
+            0x4000 => true,
+            _ => bail!("invalid sign in external \"numeric\" value"),
+        };
+
+        let mut digits = Vec::with_capacity(4 * ndigits as usize);
+        for i in 0..ndigits as usize {
+            let group = read(8 + 2 * i);
+            if !(0..10000).contains(&group) {
+                bail!("invalid digit in external \"numeric\" value");
+            }
+            digits.extend(
+                [group / 1000, group / 100 % 10, group / 10 % 10, group % 10].map(|d| d as u8),
+            );
+        }
+        // Place the decimal point (weight + 1) groups from the first digit,
+        // then cut or pad the fraction to the display scale
+        let mut point = (weight + 1) * 4;
+        if point < 0 {
+            digits.splice(0..0, std::iter::repeat_n(0, -point as usize));
+            point = 0;
+        }
+        let point = point as usize;
+        digits.resize(point + scale as usize, 0);
+        Ok(Numeric::new(negative, digits, scale))
+    }
+}
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = self.scale as usize;
+        let mut text: String = self.digits.iter().map(|d| (b'0' + d) as char).collect();
+        if text.len() <= scale {
+            text.insert_str(0, &"0".repeat(scale + 1 - text.len()));
+        }
+        if scale > 0 {
+            text.insert(text.len() - scale, '.');
+        }
+        if self.negative {
+            text.insert(0, '-');
+        }
+        f.write_str(&text)
+    }
+}
+
+impl PartialEq for Numeric {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Numeric {}
+
+impl PartialOrd for Numeric {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Numeric {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => return Ordering::Greater,
+            (true, false) => return Ordering::Less,
+            _ => {}
+        }
+        let scale = self.scale.max(other.scale);
+        let ord = compare_magnitudes(&self.aligned(scale), &other.aligned(scale));
+        if self.negative {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+impl Hash for Numeric {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Equal values must hash alike regardless of trailing zeros
+        let (digits, scale) = self.normalized();
+        self.negative.hash(state);
+        digits.hash(state);
+        scale.hash(state);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Magnitude arithmetic on most-significant-first decimal digit vectors
+// ----------------------------------------------------------------------------
+
+fn compare_magnitudes(a: &[u8], b: &[u8]) -> Ordering {
+    let a = &a[a.iter().take_while(|&&d| d == 0).count()..];
+    let b = &b[b.iter().take_while(|&&d| d == 0).count()..];
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn add_magnitudes(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let (mut i, mut j, mut carry) = (a.len(), b.len(), 0);
+    while i > 0 || j > 0 || carry > 0 {
+        let mut sum = carry;
+        if i > 0 {
+            i -= 1;
+            sum += a[i];
+        }
+        if j > 0 {
+            j -= 1;
+            sum += b[j];
+        }
+        result.push(sum % 10);
+        carry = sum / 10;
+    }
+    result.reverse();
+    result
+}
+
+/// a - b, where a >= b
+fn sub_magnitudes(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(a.len());
+    let (mut j, mut borrow) = (b.len(), 0i8);
+    for i in (0..a.len()).rev() {
+        let mut diff = a[i] as i8 - borrow;
+        if j > 0 {
+            j -= 1;
+            diff -= b[j] as i8;
+        }
+        borrow = (diff < 0) as i8;
+        result.push((diff + borrow * 10) as u8);
+    }
+    result.reverse();
+    result
+}
+
+fn mul_magnitudes(a: &[u8], b: &[u8]) -> Vec<u8> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut acc = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate().rev() {
+        for (j, &y) in b.iter().enumerate().rev() {
+            acc[i + j + 1] += x as u32 * y as u32;
+        }
+    }
+    for k in (1..acc.len()).rev() {
+        acc[k - 1] += acc[k] / 10;
+        acc[k] %= 10;
+    }
+    acc.into_iter().map(|d| d as u8).collect()
+}
+
+/// Truncating long division of magnitudes (divisor must be non-zero)
+fn div_magnitudes(dividend: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut quotient = Vec::with_capacity(dividend.len());
+    let mut remainder: Vec<u8> = Vec::new();
+    for &d in dividend {
+        remainder.push(d);
+        let mut q = 0;
+        while compare_magnitudes(&remainder, divisor) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, divisor);
+            q += 1;
+        }
+        quotient.push(q);
+    }
+    quotient
+}
+
+/// Floor of the square root of a magnitude, by Newton's iteration from an
+/// initial guess above the root
+fn isqrt_magnitude(n: &[u8]) -> Vec<u8> {
+    let n = &n[n.iter().take_while(|&&d| d == 0).count()..];
+    if n.is_empty() {
+        return vec![];
+    }
+    let mut x = vec![0; n.len().div_ceil(2) + 1];
+    x[0] = 1;
+    loop {
+        let sum = add_magnitudes(&x, &div_magnitudes(n, &x));
+        let next = div_magnitudes(&sum, &[2]);
+        if compare_magnitudes(&next, &x) != Ordering::Less {
+            return x;
+        }
+        x = next[next.iter().take_while(|&&d| d == 0).count()..].to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(s: &str) -> Numeric {
+        Numeric::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(num("12.340").to_string(), "12.340");
+        assert_eq!(num("-0.05").to_string(), "-0.05");
+        assert_eq!(num(".5").to_string(), "0.5");
+        assert_eq!(num("1.5e3").to_string(), "1500");
+        assert_eq!(num("-0").to_string(), "0");
+        assert!(Numeric::parse("1.2.3").is_err());
+        assert!(Numeric::parse("abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_exponent_bounds() {
+        assert_eq!(num("1e2000").digits.len(), 2001);
+        assert_eq!(num("1e-1000").to_string(), format!("0.{}1", "0".repeat(999)));
+        for text in ["1e200000000", "1e-2147483648", "1e2147483647", "-5e-2001", "1e2001"] {
+            let err = Numeric::parse(text).unwrap_err().to_string();
+            assert_eq!(err, "value overflows numeric format", "{text}");
+        }
+        // Exponents that do not fit in an i32 are not numbers at all
+        assert!(Numeric::parse("1e99999999999").is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(num("1.25").add(&num("-3.5")).to_string(), "-2.25");
+        assert_eq!(num("10").sub(&num("0.01")).to_string(), "9.99");
+        assert_eq!(num("-1.5").mul(&num("2.25")).to_string(), "-3.375");
+        assert_eq!(num("1").div(&num("3")).unwrap().to_string(), "0.33333333333333333333");
+        assert_eq!(num("3").div(&num("2")).unwrap().to_string(), "1.5000000000000000");
+        assert_eq!(num("2").div(&num("3")).unwrap().to_string(), "0.66666666666666666667");
+        assert!(num("1").div(&num("0.00")).is_none());
+
+        // Products keep at most MAX_RESULT_SCALE decimal places
+        let tiny = num("1e-1000");
+        assert_eq!(tiny.mul(&tiny).to_string(), format!("0.{}", "0".repeat(1000)));
+        let product = num("0.5").mul(&num(&format!("0.{}1", "0".repeat(999))));
+        assert_eq!(product.to_string(), format!("0.{}1", "0".repeat(999)));
+        let (decoded, _) = Numeric::from_bytes(&tiny.mul(&tiny).to_bytes()).unwrap();
+        assert_eq!(decoded.scale, 1000);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(num("2").sqrt().unwrap().to_string(), "1.414213562373095");
+        assert_eq!(num("16").sqrt().unwrap().to_string(), "4.000000000000000");
+        assert_eq!(num("1.6666666666666667").sqrt().unwrap().to_string(), "1.2909944487358056");
+        assert_eq!(num("0.0025").sqrt().unwrap().to_string(), "0.05000000000000000");
+        assert_eq!(num("0").sqrt().unwrap().to_string(), "0.0000000000000000");
+        assert!(num("-1").sqrt().is_none());
+    }
+
+    #[test]
+    fn test_round_and_typmod() {
+        assert_eq!(num("2.345").round(2).to_string(), "2.35");
+        assert_eq!(num("-2.345").round(2).to_string(), "-2.35");
+        assert_eq!(num("0.004").round(2).to_string(), "0.00");
+        assert_eq!(num("9.995").apply_typmod(4, 2).unwrap().to_string(), "10.00");
+        assert!(num("99.995").apply_typmod(4, 2).is_err());
+        assert_eq!(num("2.5").to_i64(), Some(3));
+        assert_eq!(num("-2.5").to_i64(), Some(-3));
+        assert_eq!(num("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(num("9223372036854775808").to_i64(), None);
+    }
+
+    #[test]
+    fn test_compare_and_serialize() {
+        assert_eq!(num("1.50"), num("1.5"));
+        assert!(num("-2") < num("-1.5"));
+        assert!(num("0.001") > num("0"));
+        let n = num("-123.4500");
+        let (decoded, len) = Numeric::from_bytes(&n.to_bytes()).unwrap();
+        assert_eq!(len, n.to_bytes().len());
+        assert_eq!(decoded.to_string(), "-123.4500");
+    }
+
+    #[test]
+    fn test_binary_format() {
+        // 12345.678 is the base-10000 digits 1, 2345, 6780 with weight 1
+        assert_eq!(
+            num("12345.678").to_binary(),
+            [0, 3, 0, 1, 0, 0, 0, 3, 0, 1, 0x09, 0x29, 0x1a, 0x7c]
+        );
+        assert_eq!(num("0.00").to_binary(), [0, 0, 0, 0, 0, 0, 0, 2]);
+        let values = ["0", "-1", "10000", "0.0001", "-0.00012300", "123456789.5"];
+        for text in values {
+            let binary = num(text).to_binary();
+            assert_eq!(Numeric::from_binary(&binary).unwrap().to_string(), text);
+        }
+        assert!(Numeric::from_binary(&[0, 1, 0, 0, 0, 0, 0, 0, 0x27, 0x10]).is_err());
+        assert!(Numeric::from_binary(&[0, 0, 0, 0, 0xc0, 0, 0, 0]).is_err());
+        // dscale 1001
+        let error = Numeric::from_binary(&[0, 0, 0, 0, 0, 0, 0x03, 0xe9]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid scale in external \"numeric\" value");
+    }
+}
@@ -1,3 +1,5 @@
+use anyhow::{bail, Result};
+
 use crate::btree::IndexKey;
 use super::PAGE_SIZE;
 
@@ -94,7 +96,7 @@ impl InternalNode {
         data[slot_offset + 2..slot_offset + 4].copy_from_slice(&length.to_le_bytes());
     }
 
-    pub fn get_key(data: &[u8], idx: u16) -> IndexKey {
+    pub fn get_key(data: &[u8], idx: u16) -> Result<IndexKey> {
         let (offset, length) = Self::get_slot(data, idx);
         let entry_data = &data[offset as usize..(offset + length) as usize];
         // Entry format: [key_data][child_page_id]
@@ -120,30 +122,30 @@ impl InternalNode {
 
     /// Find the child page to follow for the given key.
     /// Returns the page_id of the appropriate child.
-    pub fn find_child(data: &[u8], key: &IndexKey) -> u32 {
+    pub fn find_child(data: &[u8], key: &IndexKey) -> Result<u32> {
         let count = Self::key_count(data);
 
         // Linear search (can optimize to binary search later)
         for i in 0..count {
-            let k = Self::get_key(data, i);
+            let k = Self::get_key(data, i)?;
             if *key < k {
-                return Self::get_child(data, i);
+                return Ok(Self::get_child(data, i));
             }
         }
         // Key >= all keys, return rightmost child
-        Self::get_rightmost_child(data)
+        Ok(Self::get_rightmost_child(data))
     }
 
     /// Find insertion point for key. Returns index where key should be inserted.
-    fn find_insert_point(data: &[u8], key: &IndexKey) -> u16 {
+    fn find_insert_point(data: &[u8], key: &IndexKey) -> Result<u16> {
         let count = Self::key_count(data);
         for i in 0..count {
-            let k = Self::get_key(data, i);
+            let k = Self::get_key(data, i)?;
             if *key < k {
-                return i;
+                return Ok(i);
             }
         }
-        count
+        Ok(count)
     }
 
     // === Free space ===
@@ -152,11 +154,7 @@ impl InternalNode {
         let count = Self::key_count(data) as usize;
         let slots_end = HEADER_SIZE + count * SLOT_SIZE;
         let free_offset = Self::free_space_offset(data) as usize;
-        if free_offset > slots_end {
-            free_offset - slots_end
-        } else {
-            0
-        }
+        free_offset.saturating_sub(slots_end)
     }
 
     pub fn has_space_for(data: &[u8], key: &IndexKey) -> bool {
@@ -173,17 +171,17 @@ impl InternalNode {
     ///
     /// insert(key, left_child) inserts the entry (key, left_child).
     /// The caller must update rightmost_child or the next entry appropriately.
-    pub fn insert(data: &mut [u8], key: &IndexKey, left_child: u32) -> Result<(), NodeFull> {
+    pub fn insert(data: &mut [u8], key: &IndexKey, left_child: u32) -> Result<()> {
         let key_bytes = key.serialize();
         let entry_size = key_bytes.len() + CHILD_SIZE;
         let required = entry_size + SLOT_SIZE;
 
         if Self::free_space(data) < required {
-            return Err(NodeFull);
+            bail!("index internal page is full");
         }
 
         let count = Self::key_count(data);
-        let insert_idx = Self::find_insert_point(data, key);
+        let insert_idx = Self::find_insert_point(data, key)?;
 
         // Allocate space for entry
         let new_offset = Self::free_space_offset(data) - entry_size as u16;
@@ -221,12 +219,12 @@ impl InternalNode {
     /// - src contains keys[0..mid-1] with original children, rightmost = child[mid-1]
     /// - dst contains keys[mid+1..] with children, rightmost = original rightmost
     /// - split_key = keys[mid] is pushed up to parent
-    pub fn split(src: &mut [u8], dst: &mut [u8]) -> (IndexKey, u32) {
+    pub fn split(src: &mut [u8], dst: &mut [u8]) -> Result<(IndexKey, u32)> {
         let count = Self::key_count(src);
         let mid = count / 2;
 
         // Get the middle key (this will be pushed up)
-        let split_key = Self::get_key(src, mid);
+        let split_key = Self::get_key(src, mid)?;
         let mid_child = Self::get_child(src, mid);
 
         // Initialize dst with rightmost_child from src
@@ -235,16 +233,16 @@ impl InternalNode {
 
         // Copy entries [mid+1..count) to dst
         for i in (mid + 1)..count {
-            let key = Self::get_key(src, i);
+            let key = Self::get_key(src, i)?;
             let child = Self::get_child(src, i);
-            Self::insert(dst, &key, child).expect("dst should have space");
+            Self::insert(dst, &key, child)?;
         }
 
         // Truncate src to keep entries [0..mid)
         // The new rightmost_child for src is mid_child (the child that was with split_key)
         let mut write_offset = PAGE_SIZE;
         for i in 0..mid {
-            let key = Self::get_key(src, i);
+            let key = Self::get_key(src, i)?;
             let child = Self::get_child(src, i);
             let key_bytes = key.serialize();
             let entry_size = key_bytes.len() + CHILD_SIZE;
@@ -262,13 +260,10 @@ impl InternalNode {
         Self::set_free_space_offset(src, write_offset as u16);
         Self::set_rightmost_child(src, mid_child);
 
-        (split_key, mid_child)
+        Ok((split_key, mid_child))
     }
 }
 
-#[derive(Debug)]
-pub struct NodeFull;
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,25 +301,25 @@ mod tests {
         // Test find_child
         // key 5 < 10, should go to child of key 10, which is 5
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(5)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(5)])).unwrap(),
             5
         );
 
         // key 15: 10 <= 15 < 20, should go to child of key 20, which is 10
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(15)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(15)])).unwrap(),
             10
         );
 
         // key 25: 20 <= 25 < 40, should go to child of key 40, which is 30
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(25)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(25)])).unwrap(),
             30
         );
 
         // key 50: >= 40, should go to rightmost child, which is 100
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(50)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(50)])).unwrap(),
             100
         );
     }
@@ -341,7 +336,7 @@ mod tests {
             InternalNode::insert(&mut src, &key, i as u32).unwrap();
         }
 
-        let (split_key, _) = InternalNode::split(&mut src, &mut dst);
+        let (split_key, _) = InternalNode::split(&mut src, &mut dst).unwrap();
 
         let src_count = InternalNode::key_count(&src);
         let dst_count = InternalNode::key_count(&dst);
@@ -352,12 +347,12 @@ mod tests {
 
         // All keys in src should be < split_key
         for i in 0..src_count {
-            assert!(InternalNode::get_key(&src, i) < split_key);
+            assert!(InternalNode::get_key(&src, i).unwrap() < split_key);
         }
 
         // All keys in dst should be > split_key (split_key itself is pushed up)
         for i in 0..dst_count {
-            assert!(InternalNode::get_key(&dst, i) > split_key);
+            assert!(InternalNode::get_key(&dst, i).unwrap() > split_key);
         }
     }
 
@@ -390,25 +385,25 @@ mod tests {
         assert_eq!(InternalNode::get_child(&data, 2), 33);
 
         // Keys should be unchanged
-        assert_eq!(InternalNode::get_key(&data, 0), key10);
-        assert_eq!(InternalNode::get_key(&data, 1), key20);
-        assert_eq!(InternalNode::get_key(&data, 2), key30);
+        assert_eq!(InternalNode::get_key(&data, 0).unwrap(), key10);
+        assert_eq!(InternalNode::get_key(&data, 1).unwrap(), key20);
+        assert_eq!(InternalNode::get_key(&data, 2).unwrap(), key30);
 
         // find_child should work correctly with updated children
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(5)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(5)])).unwrap(),
             11
         );
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(15)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(15)])).unwrap(),
             22
         );
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(25)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(25)])).unwrap(),
             33
         );
         assert_eq!(
-            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(50)])),
+            InternalNode::find_child(&data, &IndexKey::new(vec![Value::Int(50)])).unwrap(),
             100
         );
     }
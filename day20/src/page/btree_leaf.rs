@@ -1,3 +1,5 @@
+use anyhow::{bail, Result};
+
 use crate::btree::IndexKey;
 use crate::executor::Rid;
 use super::PAGE_SIZE;
@@ -107,7 +109,7 @@ impl LeafNode {
         data[slot_offset + 2..slot_offset + 4].copy_from_slice(&length.to_le_bytes());
     }
 
-    pub fn get_key(data: &[u8], idx: u16) -> IndexKey {
+    pub fn get_key(data: &[u8], idx: u16) -> Result<IndexKey> {
         let (offset, length) = Self::get_slot(data, idx);
         let entry_data = &data[offset as usize..(offset + length) as usize];
         // Entry format: [key_data][rid]
@@ -128,10 +130,10 @@ impl LeafNode {
 
     /// Binary search for key. Returns Some(rid) if found.
     #[allow(dead_code)]
-    pub fn search(data: &[u8], key: &IndexKey) -> Option<Rid> {
+    pub fn search(data: &[u8], key: &IndexKey) -> Result<Option<Rid>> {
         let count = Self::key_count(data);
         if count == 0 {
-            return None;
+            return Ok(None);
         }
 
         // Binary search
@@ -139,22 +141,22 @@ impl LeafNode {
         let mut right = count;
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_key = Self::get_key(data, mid);
+            let mid_key = Self::get_key(data, mid)?;
             match key.cmp(&mid_key) {
-                std::cmp::Ordering::Equal => return Some(Self::get_rid(data, mid)),
+                std::cmp::Ordering::Equal => return Ok(Some(Self::get_rid(data, mid))),
                 std::cmp::Ordering::Less => right = mid,
                 std::cmp::Ordering::Greater => left = mid + 1,
             }
         }
-        None
+        Ok(None)
     }
 
     /// Find insertion point for (key, rid). Returns index where entry should be inserted.
     /// Entries are ordered by (key, rid) to ensure stable ordering for duplicate keys.
-    pub fn find_insert_point(data: &[u8], key: &IndexKey, rid: &Rid) -> u16 {
+    pub fn find_insert_point(data: &[u8], key: &IndexKey, rid: &Rid) -> Result<u16> {
         let count = Self::key_count(data);
         if count == 0 {
-            return 0;
+            return Ok(0);
         }
 
         // Binary search for first (key, rid) > (target_key, target_rid)
@@ -162,7 +164,7 @@ impl LeafNode {
         let mut right = count;
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_key = Self::get_key(data, mid);
+            let mid_key = Self::get_key(data, mid)?;
             let mid_rid = Self::get_rid(data, mid);
             // Compare (key, rid) tuples
             let cmp = match mid_key.cmp(key) {
@@ -175,30 +177,29 @@ impl LeafNode {
                 right = mid;
             }
         }
-        left
+        Ok(left)
     }
 
     /// Find insertion point for key only (used for search).
     /// Returns index of first entry with key >= target.
-    #[allow(dead_code)]
-    pub fn find_key_position(data: &[u8], key: &IndexKey) -> u16 {
+    pub fn find_key_position(data: &[u8], key: &IndexKey) -> Result<u16> {
         let count = Self::key_count(data);
         if count == 0 {
-            return 0;
+            return Ok(0);
         }
 
         let mut left = 0u16;
         let mut right = count;
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_key = Self::get_key(data, mid);
+            let mid_key = Self::get_key(data, mid)?;
             if mid_key < *key {
                 left = mid + 1;
             } else {
                 right = mid;
             }
         }
-        left
+        Ok(left)
     }
 
     // === Free space calculation ===
@@ -207,11 +208,7 @@ impl LeafNode {
         let count = Self::key_count(data) as usize;
         let slots_end = HEADER_SIZE + count * SLOT_SIZE;
         let free_offset = Self::free_space_offset(data) as usize;
-        if free_offset > slots_end {
-            free_offset - slots_end
-        } else {
-            0
-        }
+        free_offset.saturating_sub(slots_end)
     }
 
     pub fn has_space_for(data: &[u8], key: &IndexKey) -> bool {
@@ -224,17 +221,17 @@ impl LeafNode {
 
     /// Insert key-rid pair. Returns Err if no space.
     /// Entries are ordered by (key, rid) for stable duplicate key handling.
-    pub fn insert(data: &mut [u8], key: &IndexKey, rid: Rid) -> Result<(), NodeFull> {
+    pub fn insert(data: &mut [u8], key: &IndexKey, rid: Rid) -> Result<()> {
         let key_bytes = key.serialize();
         let entry_size = key_bytes.len() + RID_SIZE;
         let required = entry_size + SLOT_SIZE;
 
         if Self::free_space(data) < required {
-            return Err(NodeFull);
+            bail!("index leaf page is full");
         }
 
         let count = Self::key_count(data);
-        let insert_idx = Self::find_insert_point(data, key, &rid);
+        let insert_idx = Self::find_insert_point(data, key, &rid)?;
 
         // Allocate space for entry (grow backward)
         let new_offset = Self::free_space_offset(data) - entry_size as u16;
@@ -271,7 +268,7 @@ impl LeafNode {
     }
 
     /// Split this node. Move upper half to dst. Returns the split key (first key of dst).
-    pub fn split(src: &mut [u8], dst: &mut [u8]) -> IndexKey {
+    pub fn split(src: &mut [u8], dst: &mut [u8]) -> Result<IndexKey> {
         let count = Self::key_count(src);
         let mid = count / 2;
 
@@ -280,19 +277,19 @@ impl LeafNode {
 
         // Copy upper half entries to dst
         for i in mid..count {
-            let key = Self::get_key(src, i);
+            let key = Self::get_key(src, i)?;
             let rid = Self::get_rid(src, i);
             // This should always succeed since dst is empty
-            Self::insert(dst, &key, rid).expect("dst should have space");
+            Self::insert(dst, &key, rid)?;
         }
 
         // Get split key before truncating src
-        let split_key = Self::get_key(dst, 0);
+        let split_key = Self::get_key(dst, 0)?;
 
         // Compact src: rewrite entries for lower half
         let mut write_offset = PAGE_SIZE;
         for i in 0..mid {
-            let key = Self::get_key(src, i);
+            let key = Self::get_key(src, i)?;
             let rid = Self::get_rid(src, i);
             let key_bytes = key.serialize();
             let entry_size = key_bytes.len() + RID_SIZE;
@@ -317,13 +314,10 @@ impl LeafNode {
         Self::set_prev_leaf(dst, None); // Will be set by caller
         Self::set_next_leaf(dst, src_next);
 
-        split_key
+        Ok(split_key)
     }
 }
 
-#[derive(Debug)]
-pub struct NodeFull;
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -369,18 +363,18 @@ mod tests {
         assert_eq!(LeafNode::key_count(&data), 3);
 
         // Search
-        assert_eq!(LeafNode::search(&data, &key1), Some(rid1));
-        assert_eq!(LeafNode::search(&data, &key2), Some(rid2));
-        assert_eq!(LeafNode::search(&data, &key3), Some(rid3));
+        assert_eq!(LeafNode::search(&data, &key1).unwrap(), Some(rid1));
+        assert_eq!(LeafNode::search(&data, &key2).unwrap(), Some(rid2));
+        assert_eq!(LeafNode::search(&data, &key3).unwrap(), Some(rid3));
         assert_eq!(
-            LeafNode::search(&data, &IndexKey::new(vec![Value::Int(15)])),
+            LeafNode::search(&data, &IndexKey::new(vec![Value::Int(15)])).unwrap(),
             None
         );
 
         // Keys should be sorted
-        assert_eq!(LeafNode::get_key(&data, 0), key3); // 5
-        assert_eq!(LeafNode::get_key(&data, 1), key1); // 10
-        assert_eq!(LeafNode::get_key(&data, 2), key2); // 20
+        assert_eq!(LeafNode::get_key(&data, 0).unwrap(), key3); // 5
+        assert_eq!(LeafNode::get_key(&data, 1).unwrap(), key1); // 10
+        assert_eq!(LeafNode::get_key(&data, 2).unwrap(), key2); // 20
     }
 
     #[test]
@@ -399,7 +393,7 @@ mod tests {
             LeafNode::insert(&mut src, &key, rid).unwrap();
         }
 
-        let split_key = LeafNode::split(&mut src, &mut dst);
+        let split_key = LeafNode::split(&mut src, &mut dst).unwrap();
 
         // Both nodes should have entries
         let src_count = LeafNode::key_count(&src);
@@ -409,16 +403,16 @@ mod tests {
         assert_eq!(src_count + dst_count, 50);
 
         // Split key should be first key of dst
-        assert_eq!(split_key, LeafNode::get_key(&dst, 0));
+        assert_eq!(split_key, LeafNode::get_key(&dst, 0).unwrap());
 
         // All keys in src should be < split_key
         for i in 0..src_count {
-            assert!(LeafNode::get_key(&src, i) < split_key);
+            assert!(LeafNode::get_key(&src, i).unwrap() < split_key);
         }
 
         // All keys in dst should be >= split_key
         for i in 0..dst_count {
-            assert!(LeafNode::get_key(&dst, i) >= split_key);
+            assert!(LeafNode::get_key(&dst, i).unwrap() >= split_key);
         }
     }
 }
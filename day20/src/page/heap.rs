@@ -151,6 +151,9 @@ impl HeapPage {
         if length != 0 {
             bail!("slot {slot_id} is not deleted");
         }
+        if offset == 0 {
+            bail!("slot {slot_id} was vacuumed");
+        }
         // Restore the tuple data and length
         let data_len = data.len() as u16;
         self.data[offset as usize..(offset + data_len) as usize].copy_from_slice(data);
@@ -158,6 +161,34 @@ impl HeapPage {
         Ok(())
     }
 
+    /// True if the slot's tuple was removed by VACUUM, whose space is gone
+    pub fn is_vacuumed(&self, slot_id: u16) -> bool {
+        slot_id < self.tuple_count() && self.get_slot(slot_id) == (0, 0)
+    }
+
+    /// Move the remaining tuples to the end of the page, reclaiming the space
+    /// of deleted ones. Slot IDs stay valid; deleted slots are marked
+    /// vacuumed (offset 0) and are never reused, as indexes may still point
+    /// at them.
+    pub fn compact(&mut self) {
+        let mut offset = PAGE_SIZE;
+        let mut live = Vec::new();
+        for slot_id in 0..self.tuple_count() {
+            let (start, length) = self.get_slot(slot_id);
+            if length == 0 {
+                self.set_slot(slot_id, 0, 0);
+            } else {
+                live.push((slot_id, self.data[start as usize..(start + length) as usize].to_vec()));
+            }
+        }
+        for (slot_id, tuple) in live {
+            offset -= tuple.len();
+            self.data[offset..offset + tuple.len()].copy_from_slice(&tuple);
+            self.set_slot(slot_id, offset as u16, tuple.len() as u16);
+        }
+        self.set_free_space_offset(offset as u16);
+    }
+
     // Update xmax field of a tuple (for MVCC logical delete)
     // MVCC tuple format: [xmin: 8 bytes][xmax: 8 bytes][data...]
     pub fn set_tuple_xmax(&mut self, slot_id: u16, xmax: u64) -> Result<()> {
@@ -2,6 +2,10 @@ use anyhow::{bail, Result};
 
 use crate::ast::*;
 use crate::lexer::Token;
+use crate::numeric::MAX_PRECISION;
+
+/// Largest declared length for VARCHAR(n) and CHAR(n), as in PostgreSQL
+const MAX_STRING_LENGTH: u32 = 10_485_760;
 
 pub struct Parser {
     tokens: Vec<Token>,
@@ -55,6 +59,34 @@ impl Parser {
                 self.advance();
                 Statement::Checkpoint
             }
+            Token::Vacuum => {
+                self.advance();
+                let table = match self.peek() {
+                    Token::Ident(_) => Some(self.parse_ident()?),
+                    _ => None,
+                };
+                Statement::Vacuum(table)
+            }
+            Token::Set => self.parse_set()?,
+            Token::Ident(s) if s.eq_ignore_ascii_case("show") => {
+                self.advance();
+                Statement::Show(self.parse_ident()?)
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("alter") => {
+                self.advance();
+                Statement::AlterRole(self.parse_role()?)
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("copy") => {
+                self.advance();
+                self.parse_copy()?
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("reset") => {
+                self.advance();
+                Statement::Set {
+                    name: self.parse_ident()?,
+                    value: None,
+                }
+            }
             _ => bail!("unexpected token: {:?}", self.peek()),
         };
 
@@ -70,22 +102,7 @@ impl Parser {
         self.expect(Token::Select)?;
 
         // Parse column list
-        let mut columns = Vec::new();
-        loop {
-            if self.peek() == &Token::Asterisk {
-                self.advance();
-                columns.push(SelectColumn::Asterisk);
-            } else {
-                let expr = self.parse_expr()?;
-                columns.push(SelectColumn::Expr(expr));
-            }
-
-            if self.peek() == &Token::Comma {
-                self.advance();
-            } else {
-                break;
-            }
-        }
+        let columns = self.parse_select_list()?;
 
         // FROM clause with optional JOINs
         self.expect(Token::From)?;
@@ -103,16 +120,16 @@ impl Parser {
         let group_by = if self.peek() == &Token::Group {
             self.advance();
             self.expect(Token::By)?;
-            let mut exprs = Vec::new();
+            let mut items = Vec::new();
             loop {
-                exprs.push(self.parse_expr()?);
+                items.push(self.parse_group_by_item()?);
                 if self.peek() == &Token::Comma {
                     self.advance();
                 } else {
                     break;
                 }
             }
-            exprs
+            items
         } else {
             Vec::new()
         };
@@ -134,6 +151,127 @@ impl Parser {
         }))
     }
 
+    // expr | ROLLUP (...) | CUBE (...) | GROUPING SETS (...)
+    fn parse_group_by_item(&mut self) -> Result<GroupByItem> {
+        let next_is_lparen = self.tokens.get(self.pos + 1) == Some(&Token::LParen);
+        match self.peek() {
+            Token::Ident(s) if s.eq_ignore_ascii_case("rollup") && next_is_lparen => {
+                self.advance();
+                Ok(GroupByItem::Rollup(self.parse_grouping_elements()?))
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("cube") && next_is_lparen => {
+                self.advance();
+                Ok(GroupByItem::Cube(self.parse_grouping_elements()?))
+            }
+            Token::Ident(s)
+                if s.eq_ignore_ascii_case("grouping")
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(t)) if t.eq_ignore_ascii_case("sets")) =>
+            {
+                self.advance();
+                self.advance();
+                self.expect(Token::LParen)?;
+                let mut sets = Vec::new();
+                loop {
+                    sets.push(match self.parse_expr_set()? {
+                        Some(exprs) => GroupByItem::Set(exprs),
+                        None => self.parse_group_by_item()?,
+                    });
+                    if self.peek() != &Token::Comma {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(Token::RParen)?;
+                Ok(GroupByItem::GroupingSets(sets))
+            }
+            _ => Ok(GroupByItem::Expr(self.parse_expr()?)),
+        }
+    }
+
+    // (element, ...) of ROLLUP or CUBE, where an element is an expression or
+    // a parenthesized list
+    fn parse_grouping_elements(&mut self) -> Result<Vec<Vec<Expr>>> {
+        self.expect(Token::LParen)?;
+        let mut elements = Vec::new();
+        loop {
+            elements.push(match self.parse_expr_set()? {
+                Some(exprs) => exprs,
+                None => vec![self.parse_expr()?],
+            });
+            if self.peek() != &Token::Comma {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RParen)?;
+        Ok(elements)
+    }
+
+    // A parenthesized list such as (a, b) or () that makes up a whole
+    // grouping element. Returns None, consuming nothing, if the parentheses
+    // instead start an expression such as (a + b) * 2.
+    fn parse_expr_set(&mut self) -> Result<Option<Vec<Expr>>> {
+        if self.peek() != &Token::LParen {
+            return Ok(None);
+        }
+        let start = self.pos;
+        self.advance();
+        let mut exprs = Vec::new();
+        if self.peek() != &Token::RParen {
+            loop {
+                match self.parse_expr() {
+                    Ok(expr) => exprs.push(expr),
+                    Err(_) => {
+                        self.pos = start;
+                        return Ok(None);
+                    }
+                }
+                if self.peek() != &Token::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        if self.peek() == &Token::RParen
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Comma | Token::RParen))
+        {
+            self.advance();
+            return Ok(Some(exprs));
+        }
+        self.pos = start;
+        Ok(None)
+    }
+
+    fn parse_select_list(&mut self) -> Result<Vec<SelectColumn>> {
+        let mut columns = Vec::new();
+        loop {
+            if self.peek() == &Token::Asterisk {
+                self.advance();
+                columns.push(SelectColumn::Asterisk);
+            } else {
+                let expr = self.parse_expr()?;
+                columns.push(SelectColumn::Expr(expr));
+            }
+
+            if self.peek() == &Token::Comma {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok(columns)
+    }
+
+    // Optional RETURNING clause of INSERT/UPDATE/DELETE (empty if absent)
+    fn parse_returning(&mut self) -> Result<Vec<SelectColumn>> {
+        if self.peek() == &Token::Returning {
+            self.advance();
+            self.parse_select_list()
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
     fn parse_insert(&mut self) -> Result<Statement> {
         self.expect(Token::Insert)?;
         self.expect(Token::Into)?;
@@ -155,7 +293,65 @@ impl Parser {
 
         self.expect(Token::RParen)?;
 
-        Ok(Statement::Insert(InsertStatement { table, values }))
+        let on_conflict = if self.peek() == &Token::On {
+            Some(self.parse_on_conflict()?)
+        } else {
+            None
+        };
+        let returning = self.parse_returning()?;
+
+        Ok(Statement::Insert(InsertStatement {
+            table,
+            values,
+            on_conflict,
+            returning,
+        }))
+    }
+
+    fn parse_on_conflict(&mut self) -> Result<OnConflict> {
+        // ON CONFLICT [(column, ...)] DO NOTHING | DO UPDATE SET ... [WHERE ...]
+        self.expect(Token::On)?;
+        self.expect(Token::Conflict)?;
+
+        let mut target = Vec::new();
+        if self.peek() == &Token::LParen {
+            self.advance();
+            loop {
+                target.push(self.parse_ident()?);
+                if self.peek() == &Token::Comma {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+            self.expect(Token::RParen)?;
+        }
+
+        self.expect(Token::Do)?;
+        let action = match self.peek() {
+            Token::Nothing => {
+                self.advance();
+                OnConflictAction::DoNothing
+            }
+            Token::Update => {
+                self.advance();
+                self.expect(Token::Set)?;
+                let assignments = self.parse_assignments()?;
+                let where_clause = if self.peek() == &Token::Where {
+                    self.advance();
+                    Some(self.parse_expr()?)
+                } else {
+                    None
+                };
+                OnConflictAction::DoUpdate {
+                    assignments,
+                    where_clause,
+                }
+            }
+            _ => bail!("expected NOTHING or UPDATE after DO, got {:?}", self.peek()),
+        };
+
+        Ok(OnConflict { target, action })
     }
 
     fn parse_create(&mut self) -> Result<Statement> {
@@ -163,11 +359,219 @@ impl Parser {
 
         match self.peek() {
             Token::Table => self.parse_create_table(),
-            Token::Index => self.parse_create_index(),
+            Token::Index => self.parse_create_index(false),
+            Token::Unique => {
+                self.advance();
+                self.parse_create_index(true)
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("role") || s.eq_ignore_ascii_case("user") => {
+                Ok(Statement::CreateRole(self.parse_role()?))
+            }
             _ => bail!("expected TABLE or INDEX after CREATE, got {:?}", self.peek()),
         }
     }
 
+    // {ROLE | USER} name [WITH] option ..., after CREATE or ALTER. Options are
+    // [NO]SUPERUSER, [NO]LOGIN and PASSWORD {'password' | NULL}. CREATE USER
+    // is CREATE ROLE with LOGIN as the default.
+    fn parse_role(&mut self) -> Result<RoleStatement> {
+        let user = match self.advance().clone() {
+            Token::Ident(s) if s.eq_ignore_ascii_case("role") => false,
+            Token::Ident(s) if s.eq_ignore_ascii_case("user") => true,
+            other => bail!("expected ROLE or USER, got {other:?}"),
+        };
+        let mut role = RoleStatement {
+            name: self.parse_ident()?,
+            superuser: None,
+            login: user.then_some(true),
+            password: None,
+        };
+        if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("with")) {
+            self.advance();
+        }
+        loop {
+            match self.peek().clone() {
+                Token::Ident(s) if s.eq_ignore_ascii_case("superuser") => role.superuser = Some(true),
+                Token::Ident(s) if s.eq_ignore_ascii_case("nosuperuser") => {
+                    role.superuser = Some(false)
+                }
+                Token::Ident(s) if s.eq_ignore_ascii_case("login") => role.login = Some(true),
+                Token::Ident(s) if s.eq_ignore_ascii_case("nologin") => role.login = Some(false),
+                Token::Ident(s) if s.eq_ignore_ascii_case("password") => {
+                    self.advance();
+                    role.password = match self.peek().clone() {
+                        Token::String(password) => Some(Some(password)),
+                        Token::Null => Some(None),
+                        other => bail!("expected a password or NULL, got {other:?}"),
+                    };
+                }
+                Token::Semicolon | Token::Eof => break,
+                other => bail!("unrecognized role option {other:?}"),
+            }
+            self.advance();
+        }
+        Ok(role)
+    }
+
+    // COPY table [(columns)] FROM STDIN [options]
+    // COPY {table [(columns)] | (query)} TO STDOUT [options]
+    fn parse_copy(&mut self) -> Result<Statement> {
+        let (table, columns, query) = if self.peek() == &Token::LParen {
+            self.advance();
+            let Statement::Select(query) = self.parse_select()? else {
+                unreachable!()
+            };
+            self.expect(Token::RParen)?;
+            (None, Vec::new(), Some(query))
+        } else {
+            let table = self.parse_ident()?;
+            let mut columns = Vec::new();
+            if self.peek() == &Token::LParen {
+                self.advance();
+                loop {
+                    columns.push(self.parse_ident()?);
+                    if self.peek() != &Token::Comma {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(Token::RParen)?;
+            }
+            (Some(table), columns, None)
+        };
+
+        let target = if self.peek() == &Token::From {
+            self.advance();
+            self.expect_ident("stdin")?;
+            let Some(table) = table else {
+                bail!("COPY FROM does not support a query");
+            };
+            CopyTarget::From { table, columns }
+        } else {
+            self.expect_ident("to")?;
+            self.expect_ident("stdout")?;
+            let query = query.unwrap_or_else(|| {
+                let columns = if columns.is_empty() {
+                    vec![SelectColumn::Asterisk]
+                } else {
+                    columns
+                        .into_iter()
+                        .map(|name| SelectColumn::Expr(Expr::Column { table: None, name }))
+                        .collect()
+                };
+                SelectStatement {
+                    columns,
+                    from: FromClause::Table(TableRef {
+                        name: table.unwrap(),
+                        alias: None,
+                        function_args: None,
+                    }),
+                    where_clause: None,
+                    group_by: Vec::new(),
+                    having: None,
+                }
+            });
+            CopyTarget::To(Box::new(query))
+        };
+
+        let options = self.parse_copy_options()?;
+        Ok(Statement::Copy(CopyStatement { target, options }))
+    }
+
+    // [WITH] (FORMAT csv, DELIMITER 'x', ...) or the older unparenthesized
+    // form: [WITH] [CSV] [HEADER] [DELIMITER [AS] 'x'] [NULL [AS] 'x'] ...
+    fn parse_copy_options(&mut self) -> Result<CopyOptions> {
+        let mut options = CopyOptions::default();
+        if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("with")) {
+            self.advance();
+        }
+        let parenthesized = self.peek() == &Token::LParen;
+        if parenthesized {
+            self.advance();
+        }
+        loop {
+            let option = match self.peek().clone() {
+                Token::Ident(s) => s.to_ascii_lowercase(),
+                Token::Null => "null".to_string(),
+                Token::RParen if parenthesized => {
+                    self.advance();
+                    break;
+                }
+                Token::Semicolon | Token::Eof if !parenthesized => break,
+                other => bail!("unexpected token in COPY options: {other:?}"),
+            };
+            self.advance();
+            match option.as_str() {
+                "format" if parenthesized => {
+                    let format = self.parse_copy_option_word()?;
+                    options.csv = match format.as_str() {
+                        "csv" => true,
+                        "text" => false,
+                        _ => bail!("COPY format \"{format}\" not recognized"),
+                    };
+                }
+                "csv" if !parenthesized => options.csv = true,
+                "header" => {
+                    // Only the parenthesized form takes a value: HEADER [bool]
+                    let value = match self.peek() {
+                        Token::True | Token::On => Some(true),
+                        Token::False => Some(false),
+                        Token::Ident(s) if s.eq_ignore_ascii_case("off") => Some(false),
+                        _ => None,
+                    };
+                    options.header = match value {
+                        Some(value) if parenthesized => {
+                            self.advance();
+                            value
+                        }
+                        _ => true,
+                    };
+                }
+                "delimiter" => options.delimiter = Some(self.parse_copy_option_string(parenthesized)?),
+                "null" => options.null = Some(self.parse_copy_option_string(parenthesized)?),
+                "quote" => options.quote = Some(self.parse_copy_option_string(parenthesized)?),
+                _ => bail!("option \"{option}\" not recognized"),
+            }
+            if parenthesized {
+                if self.peek() != &Token::Comma {
+                    self.expect(Token::RParen)?;
+                    break;
+                }
+                self.advance();
+            }
+        }
+        Ok(options)
+    }
+
+    /// A word given as a COPY option value: a string, or a name, which is
+    /// lowercased. Some of the names, such as TEXT, are keywords.
+    fn parse_copy_option_word(&mut self) -> Result<String> {
+        let word = match self.advance().clone() {
+            Token::String(s) => s,
+            Token::Ident(s) => s.to_ascii_lowercase(),
+            Token::Text => "text".to_string(),
+            Token::Char => "char".to_string(),
+            Token::Varchar => "varchar".to_string(),
+            Token::Boolean => "boolean".to_string(),
+            Token::Null => "null".to_string(),
+            Token::On => "on".to_string(),
+            Token::True => "true".to_string(),
+            Token::False => "false".to_string(),
+            other => bail!("expected a word in COPY options, got {other:?}"),
+        };
+        Ok(word)
+    }
+
+    fn parse_copy_option_string(&mut self, parenthesized: bool) -> Result<String> {
+        if !parenthesized && matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("as")) {
+            self.advance();
+        }
+        match self.advance().clone() {
+            Token::String(s) => Ok(s),
+            other => bail!("expected a string in COPY options, got {other:?}"),
+        }
+    }
+
     fn parse_create_table(&mut self) -> Result<Statement> {
         self.expect(Token::Table)?;
         let table = self.parse_ident()?;
@@ -177,7 +581,17 @@ impl Parser {
         loop {
             let name = self.parse_ident()?;
             let data_type = self.parse_data_type()?;
-            columns.push(ColumnDef { name, data_type });
+            let collation = if self.peek() == &Token::Collate {
+                self.advance();
+                Some(self.parse_ident()?)
+            } else {
+                None
+            };
+            columns.push(ColumnDef {
+                name,
+                data_type,
+                collation,
+            });
 
             if self.peek() == &Token::Comma {
                 self.advance();
@@ -191,18 +605,18 @@ impl Parser {
         Ok(Statement::CreateTable(CreateTableStatement { table, columns }))
     }
 
-    fn parse_create_index(&mut self) -> Result<Statement> {
-        // CREATE INDEX index_name ON table_name (column1, column2, ...)
+    fn parse_create_index(&mut self, unique: bool) -> Result<Statement> {
+        // CREATE [UNIQUE] INDEX index_name ON table_name (key, ...), where each key
+        // is a column name or an expression such as (data->>'name') or lower(name)
         self.expect(Token::Index)?;
         let index_name = self.parse_ident()?;
         self.expect(Token::On)?;
         let table_name = self.parse_ident()?;
         self.expect(Token::LParen)?;
 
-        let mut column_names = Vec::new();
+        let mut columns = Vec::new();
         loop {
-            let col_name = self.parse_ident()?;
-            column_names.push(col_name);
+            columns.push(self.parse_expr()?);
 
             if self.peek() == &Token::Comma {
                 self.advance();
@@ -215,8 +629,9 @@ impl Parser {
 
         Ok(Statement::CreateIndex(CreateIndexStatement {
             index_name,
+            unique,
             table_name,
-            column_names,
+            columns,
         }))
     }
 
@@ -225,21 +640,62 @@ impl Parser {
         self.expect(Token::From)?;
         let table = self.parse_ident()?;
 
+        // Optional USING list of tables joined through the WHERE clause
+        let using = if self.peek() == &Token::Using {
+            self.advance();
+            self.parse_table_list()?
+        } else {
+            Vec::new()
+        };
+
         let where_clause = if self.peek() == &Token::Where {
             self.advance();
             Some(self.parse_expr()?)
         } else {
             None
         };
+        let returning = self.parse_returning()?;
 
-        Ok(Statement::Delete(DeleteStatement { table, where_clause }))
+        Ok(Statement::Delete(DeleteStatement {
+            table,
+            using,
+            where_clause,
+            returning,
+        }))
     }
 
     fn parse_update(&mut self) -> Result<Statement> {
         self.expect(Token::Update)?;
         let table = self.parse_ident()?;
         self.expect(Token::Set)?;
+        let assignments = self.parse_assignments()?;
 
+        // Optional FROM list of tables joined through the WHERE clause
+        let from = if self.peek() == &Token::From {
+            self.advance();
+            self.parse_table_list()?
+        } else {
+            Vec::new()
+        };
+
+        let where_clause = if self.peek() == &Token::Where {
+            self.advance();
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let returning = self.parse_returning()?;
+
+        Ok(Statement::Update(UpdateStatement {
+            table,
+            assignments,
+            from,
+            where_clause,
+            returning,
+        }))
+    }
+
+    fn parse_assignments(&mut self) -> Result<Vec<Assignment>> {
         let mut assignments = Vec::new();
         loop {
             let column = self.parse_ident()?;
@@ -253,32 +709,209 @@ impl Parser {
                 break;
             }
         }
+        Ok(assignments)
+    }
 
-        let where_clause = if self.peek() == &Token::Where {
+    // A type name, optionally followed by [] (or [n], size ignored) for a
+    // one-dimensional array of that type
+    fn parse_data_type(&mut self) -> Result<DataType> {
+        let data_type = self.parse_scalar_type()?;
+        if self.peek() != &Token::LBracket {
+            return Ok(data_type);
+        }
+        self.advance();
+        if let Token::Integer(_) = self.peek() {
             self.advance();
-            Some(self.parse_expr()?)
-        } else {
-            None
-        };
-
-        Ok(Statement::Update(UpdateStatement {
-            table,
-            assignments,
-            where_clause,
-        }))
+        }
+        self.expect(Token::RBracket)?;
+        if self.peek() == &Token::LBracket {
+            bail!("multidimensional arrays are not supported");
+        }
+        Ok(DataType::Array(Box::new(data_type)))
     }
 
-    fn parse_data_type(&mut self) -> Result<DataType> {
+    fn parse_scalar_type(&mut self) -> Result<DataType> {
         match self.peek() {
             Token::Int => {
                 self.advance();
                 Ok(DataType::Int)
             }
+            Token::SmallInt => {
+                self.advance();
+                Ok(DataType::SmallInt)
+            }
+            Token::BigInt => {
+                self.advance();
+                Ok(DataType::BigInt)
+            }
+            Token::Real => {
+                self.advance();
+                Ok(DataType::Real)
+            }
+            Token::Float => {
+                self.advance();
+                Ok(DataType::Double)
+            }
+            Token::Double => {
+                self.advance();
+                match self.peek() {
+                    Token::Ident(s) if s.eq_ignore_ascii_case("precision") => {
+                        self.advance();
+                        Ok(DataType::Double)
+                    }
+                    other => bail!("expected PRECISION after DOUBLE, got {other:?}"),
+                }
+            }
+            Token::Numeric => {
+                self.advance();
+                self.parse_numeric_typmod()
+            }
             Token::Varchar => {
                 self.advance();
-                Ok(DataType::Varchar)
+                Ok(DataType::Varchar(self.parse_length_typmod("character varying")?))
+            }
+            Token::Char => {
+                self.advance();
+                match self.peek() {
+                    Token::Ident(s) if s.eq_ignore_ascii_case("varying") => {
+                        self.advance();
+                        Ok(DataType::Varchar(self.parse_length_typmod("character varying")?))
+                    }
+                    _ => Ok(DataType::Char(self.parse_length_typmod("character")?.unwrap_or(1))),
+                }
+            }
+            Token::Text => {
+                self.advance();
+                Ok(DataType::Text)
+            }
+            Token::Boolean => {
+                self.advance();
+                Ok(DataType::Bool)
+            }
+            _ => match self.parse_unreserved_type()? {
+                Some(data_type) => Ok(data_type),
+                None => bail!("expected data type, got {:?}", self.peek()),
+            },
+        }
+    }
+
+    // Type names that are not reserved words arrive as identifiers: DATE, TIME,
+    // TIMESTAMP [WITH|WITHOUT TIME ZONE], TIMESTAMPTZ, INTERVAL, BYTEA and JSONB
+    // (JSON is accepted as a synonym)
+    fn parse_unreserved_type(&mut self) -> Result<Option<DataType>> {
+        let Token::Ident(name) = self.peek() else {
+            return Ok(None);
+        };
+        let data_type = match name.to_ascii_lowercase().as_str() {
+            "date" => DataType::Date,
+            "time" => DataType::Time,
+            "timestamp" => DataType::Timestamp,
+            "timestamptz" => DataType::TimestampTz,
+            "interval" => DataType::Interval,
+            "bytea" => DataType::Bytea,
+            "json" | "jsonb" => DataType::Jsonb,
+            _ => return Ok(None),
+        };
+        self.advance();
+        if matches!(data_type, DataType::Time | DataType::Timestamp) {
+            let with_zone = match self.peek() {
+                Token::Ident(s) if s.eq_ignore_ascii_case("with") => true,
+                Token::Ident(s) if s.eq_ignore_ascii_case("without") => false,
+                _ => return Ok(Some(data_type)),
+            };
+            self.advance();
+            self.expect_ident("time")?;
+            self.expect_ident("zone")?;
+            if with_zone {
+                if data_type == DataType::Time {
+                    bail!("TIME WITH TIME ZONE is not supported");
+                }
+                return Ok(Some(DataType::TimestampTz));
+            }
+        }
+        Ok(Some(data_type))
+    }
+
+    // SET [SESSION] name { = | TO } { value | DEFAULT }
+    fn parse_set(&mut self) -> Result<Statement> {
+        self.expect(Token::Set)?;
+        if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("session")) {
+            self.advance();
+        }
+        let name = self.parse_ident()?;
+        match self.peek() {
+            Token::Eq => {
+                self.advance();
+            }
+            _ => self.expect_ident("to")?,
+        }
+        let value = match self.advance().clone() {
+            Token::Ident(s) if s.eq_ignore_ascii_case("default") => None,
+            Token::Ident(s) | Token::String(s) | Token::Decimal(s) => Some(s),
+            Token::Integer(n) => Some(n.to_string()),
+            other => bail!("expected a value for parameter \"{name}\", got {other:?}"),
+        };
+        Ok(Statement::Set { name, value })
+    }
+
+    fn expect_ident(&mut self, word: &str) -> Result<()> {
+        match self.peek() {
+            Token::Ident(s) if s.eq_ignore_ascii_case(word) => {
+                self.advance();
+                Ok(())
             }
-            _ => bail!("expected data type, got {:?}", self.peek()),
+            other => bail!("expected {}, got {other:?}", word.to_uppercase()),
+        }
+    }
+
+    // Optional (precision [, scale]) after NUMERIC
+    fn parse_numeric_typmod(&mut self) -> Result<DataType> {
+        if self.peek() != &Token::LParen {
+            return Ok(DataType::Numeric(None));
+        }
+        self.advance();
+        let precision = self.parse_type_modifier()?;
+        let scale = if self.peek() == &Token::Comma {
+            self.advance();
+            self.parse_type_modifier()?
+        } else {
+            0
+        };
+        self.expect(Token::RParen)?;
+
+        if !(1..=MAX_PRECISION).contains(&precision) {
+            bail!("NUMERIC precision {precision} must be between 1 and {MAX_PRECISION}");
+        }
+        if scale > precision {
+            bail!("NUMERIC scale {scale} must be between 0 and precision {precision}");
+        }
+        Ok(DataType::Numeric(Some((precision, scale))))
+    }
+
+    // Optional (length) after VARCHAR or CHAR
+    fn parse_length_typmod(&mut self, type_name: &str) -> Result<Option<u32>> {
+        if self.peek() != &Token::LParen {
+            return Ok(None);
+        }
+        self.advance();
+        let length = self.parse_type_modifier()?;
+        self.expect(Token::RParen)?;
+        if length < 1 {
+            bail!("length for type {type_name} must be at least 1");
+        }
+        if length > MAX_STRING_LENGTH {
+            bail!("length for type {type_name} cannot exceed {MAX_STRING_LENGTH}");
+        }
+        Ok(Some(length))
+    }
+
+    fn parse_type_modifier(&mut self) -> Result<u32> {
+        match self.peek().clone() {
+            Token::Integer(n) => {
+                self.advance();
+                u32::try_from(n).map_err(|_| anyhow::anyhow!("invalid type modifier: {n}"))
+            }
+            other => bail!("expected type modifier, got {other:?}"),
         }
     }
 
@@ -294,6 +927,29 @@ impl Parser {
 
     fn parse_table_ref(&mut self) -> Result<TableRef> {
         let name = self.parse_ident()?;
+        // A function call such as unnest(ARRAY[1, 2]) is also a FROM item
+        let function_args = if self.peek() == &Token::LParen {
+            self.advance();
+            let mut args = Vec::new();
+            if self.peek() != &Token::RParen {
+                loop {
+                    args.push(self.parse_expr()?);
+                    if self.peek() != &Token::Comma {
+                        break;
+                    }
+                    self.advance();
+                }
+            }
+            self.expect(Token::RParen)?;
+            Some(args)
+        } else {
+            None
+        };
+        if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("as")) {
+            self.advance();
+            let alias = Some(self.parse_ident()?);
+            return Ok(TableRef { name, alias, function_args });
+        }
         // Optional alias (with or without AS)
         let alias = match self.peek() {
             // Don't consume keywords as aliases
@@ -312,7 +968,24 @@ impl Parser {
             }
             _ => None,
         };
-        Ok(TableRef { name, alias })
+        Ok(TableRef {
+            name,
+            alias,
+            function_args,
+        })
+    }
+
+    fn parse_table_list(&mut self) -> Result<Vec<TableRef>> {
+        let mut tables = Vec::new();
+        loop {
+            tables.push(self.parse_table_ref()?);
+            if self.peek() == &Token::Comma {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok(tables)
     }
 
     fn parse_from_clause(&mut self) -> Result<FromClause> {
@@ -401,7 +1074,7 @@ impl Parser {
     }
 
     fn parse_comparison(&mut self) -> Result<Expr> {
-        let left = self.parse_additive()?;
+        let left = self.parse_other_op()?;
 
         let op = match self.peek() {
             Token::Eq => BinaryOperator::Eq,
@@ -413,7 +1086,23 @@ impl Parser {
             _ => return Ok(left),
         };
         self.advance();
-        let right = self.parse_additive()?;
+
+        // left op ANY (array); SOME is a synonym
+        if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("any") || s.eq_ignore_ascii_case("some"))
+            && self.tokens.get(self.pos + 1) == Some(&Token::LParen)
+        {
+            self.advance();
+            self.advance();
+            let right = self.parse_expr()?;
+            self.expect(Token::RParen)?;
+            return Ok(Expr::Any {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            });
+        }
+
+        let right = self.parse_other_op()?;
 
         Ok(Expr::BinaryOp {
             left: Box::new(left),
@@ -422,6 +1111,30 @@ impl Parser {
         })
     }
 
+    // JSON operators bind looser than arithmetic and tighter than comparisons,
+    // like PostgreSQL's "any other operator" precedence level
+    fn parse_other_op(&mut self) -> Result<Expr> {
+        let mut left = self.parse_additive()?;
+        loop {
+            let op = match self.peek() {
+                Token::Arrow => BinaryOperator::Arrow,
+                Token::LongArrow => BinaryOperator::LongArrow,
+                Token::HashArrow => BinaryOperator::HashArrow,
+                Token::HashLongArrow => BinaryOperator::HashLongArrow,
+                Token::AtArrow => BinaryOperator::AtArrow,
+                _ => break,
+            };
+            self.advance();
+            let right = self.parse_additive()?;
+            left = Expr::BinaryOp {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
     fn parse_additive(&mut self) -> Result<Expr> {
         let mut left = self.parse_multiplicative()?;
         loop {
@@ -478,7 +1191,41 @@ impl Parser {
                     expr: Box::new(expr),
                 })
             }
-            _ => self.parse_primary(),
+            _ => self.parse_postfix(),
+        }
+    }
+
+    // Array subscripts, casts and collations after a primary expression:
+    // tags[1], x::int, name COLLATE "C"
+    fn parse_postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek() {
+                Token::LBracket => {
+                    self.advance();
+                    let index = self.parse_expr()?;
+                    self.expect(Token::RBracket)?;
+                    expr = Expr::Subscript {
+                        expr: Box::new(expr),
+                        index: Box::new(index),
+                    };
+                }
+                Token::DoubleColon => {
+                    self.advance();
+                    expr = Expr::Cast {
+                        expr: Box::new(expr),
+                        data_type: self.parse_data_type()?,
+                    };
+                }
+                Token::Collate => {
+                    self.advance();
+                    expr = Expr::Collate {
+                        expr: Box::new(expr),
+                        collation: self.parse_ident()?,
+                    };
+                }
+                _ => return Ok(expr),
+            }
         }
     }
 
@@ -488,6 +1235,14 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Literal(Literal::Integer(n)))
             }
+            Token::Parameter(n) => {
+                self.advance();
+                Ok(Expr::Parameter(n))
+            }
+            Token::Decimal(s) => {
+                self.advance();
+                Ok(Expr::Literal(Literal::Decimal(s)))
+            }
             Token::String(s) => {
                 self.advance();
                 Ok(Expr::Literal(Literal::String(s)))
@@ -507,6 +1262,67 @@ impl Parser {
             Token::Count | Token::Sum | Token::Avg | Token::Min | Token::Max => {
                 self.parse_aggregate()
             }
+            Token::Ident(s)
+                if AggregateFunction::from_name(&s).is_some()
+                    && self.tokens.get(self.pos + 1) == Some(&Token::LParen) =>
+            {
+                self.parse_aggregate()
+            }
+            Token::Ident(s)
+                if s.eq_ignore_ascii_case("cast")
+                    && self.tokens.get(self.pos + 1) == Some(&Token::LParen) =>
+            {
+                self.advance();
+                self.advance();
+                let expr = self.parse_expr()?;
+                match self.advance() {
+                    Token::Ident(s) if s.eq_ignore_ascii_case("as") => {}
+                    other => bail!("expected AS in CAST, got {other:?}"),
+                }
+                let data_type = self.parse_data_type()?;
+                self.expect(Token::RParen)?;
+                Ok(Expr::Cast {
+                    expr: Box::new(expr),
+                    data_type,
+                })
+            }
+            Token::Ident(s)
+                if s.eq_ignore_ascii_case("array")
+                    && self.tokens.get(self.pos + 1) == Some(&Token::LBracket) =>
+            {
+                self.advance();
+                self.advance();
+                let mut elements = Vec::new();
+                if self.peek() != &Token::RBracket {
+                    loop {
+                        elements.push(self.parse_expr()?);
+                        if self.peek() != &Token::Comma {
+                            break;
+                        }
+                        self.advance();
+                    }
+                }
+                self.expect(Token::RBracket)?;
+                Ok(Expr::Array(elements))
+            }
+            Token::Ident(s) if self.is_typed_literal() => {
+                let data_type = self.parse_data_type()?;
+                match self.advance().clone() {
+                    Token::String(value) => Ok(Expr::Literal(Literal::Typed(data_type, value))),
+                    other => bail!("expected string constant after {s}, got {other:?}"),
+                }
+            }
+            Token::Ident(s) if Self::is_niladic_function(&s) => {
+                self.advance();
+                Ok(Expr::Function {
+                    name: s.to_ascii_lowercase(),
+                    args: Vec::new(),
+                })
+            }
+            Token::Ident(s) if self.tokens.get(self.pos + 1) == Some(&Token::LParen) => {
+                self.advance();
+                self.parse_function_call(s.to_ascii_lowercase())
+            }
             Token::Ident(s) => {
                 self.advance();
                 // Check for table-qualified column (table.column)
@@ -534,6 +1350,82 @@ impl Parser {
         }
     }
 
+    // An unreserved type name followed by a string constant, e.g. DATE '2024-01-01',
+    // BYTEA '\x00ff' or TIMESTAMP WITH TIME ZONE '2024-01-01 12:00+02'
+    fn is_typed_literal(&self) -> bool {
+        let mut pos = self.pos;
+        match self.tokens.get(pos) {
+            Some(Token::Ident(s))
+                if [
+                    "date",
+                    "time",
+                    "timestamp",
+                    "timestamptz",
+                    "interval",
+                    "bytea",
+                    "json",
+                    "jsonb",
+                ]
+                .iter()
+                .any(|t| s.eq_ignore_ascii_case(t)) => {}
+            _ => return false,
+        }
+        pos += 1;
+        if let Some(Token::Ident(s)) = self.tokens.get(pos) {
+            if s.eq_ignore_ascii_case("with") || s.eq_ignore_ascii_case("without") {
+                pos += 3;
+            }
+        }
+        matches!(self.tokens.get(pos), Some(Token::String(_)))
+    }
+
+    // SQL-standard functions written without parentheses
+    fn is_niladic_function(name: &str) -> bool {
+        ["current_date", "current_time", "current_timestamp", "localtimestamp"]
+            .iter()
+            .any(|f| name.eq_ignore_ascii_case(f))
+    }
+
+    // name(arg, ...) after the name; extract(field FROM expr) passes the field
+    // as a string argument, and substring(s FROM start FOR count) is
+    // substring(s, start, count)
+    fn parse_function_call(&mut self, name: String) -> Result<Expr> {
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        let keyword_args = name == "substring"
+            && self.tokens[self.pos..]
+                .iter()
+                .take_while(|t| **t != Token::RParen)
+                .any(|t| *t == Token::From);
+        if keyword_args {
+            args.push(self.parse_expr()?);
+            self.expect(Token::From)?;
+            args.push(self.parse_expr()?);
+            if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("for")) {
+                self.advance();
+                args.push(self.parse_expr()?);
+            }
+        } else if name == "extract" {
+            let field = match self.advance().clone() {
+                Token::Ident(s) | Token::String(s) => s,
+                other => bail!("expected field name in EXTRACT, got {other:?}"),
+            };
+            self.expect(Token::From)?;
+            args.push(Expr::Literal(Literal::String(field)));
+            args.push(self.parse_expr()?);
+        } else if self.peek() != &Token::RParen {
+            loop {
+                args.push(self.parse_expr()?);
+                if self.peek() != &Token::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(Expr::Function { name, args })
+    }
+
     fn parse_aggregate(&mut self) -> Result<Expr> {
         let func = match self.peek() {
             Token::Count => AggregateFunction::Count,
@@ -541,24 +1433,110 @@ impl Parser {
             Token::Avg => AggregateFunction::Avg,
             Token::Min => AggregateFunction::Min,
             Token::Max => AggregateFunction::Max,
+            Token::Ident(s) => match AggregateFunction::from_name(s) {
+                Some(func) => func,
+                None => bail!("expected aggregate function"),
+            },
             _ => bail!("expected aggregate function"),
         };
         self.advance();
 
         self.expect(Token::LParen)?;
 
-        let arg = if self.peek() == &Token::Asterisk {
-            self.advance();
-            AggregateArg::Asterisk
+        let (arg, extra_arg, order_by) = if func.is_ordered_set() {
+            // percentile_cont(fraction) WITHIN GROUP (ORDER BY expr)
+            let fraction = self.parse_expr()?;
+            self.expect(Token::RParen)?;
+            self.expect_ident("within")?;
+            self.expect(Token::Group)?;
+            self.expect(Token::LParen)?;
+            self.expect_ident("order")?;
+            self.expect(Token::By)?;
+            let order_by = self.parse_order_by_expr()?;
+            self.expect(Token::RParen)?;
+            let arg = AggregateArg::Expr(order_by.expr.clone());
+            (arg, Some(Box::new(fraction)), vec![order_by])
         } else {
-            AggregateArg::Expr(self.parse_expr()?)
+            let arg = if self.peek() == &Token::Asterisk {
+                self.advance();
+                AggregateArg::Asterisk
+            } else {
+                AggregateArg::Expr(self.parse_expr()?)
+            };
+            let extra_arg = if self.peek() == &Token::Comma {
+                self.advance();
+                Some(Box::new(self.parse_expr()?))
+            } else {
+                None
+            };
+            let mut order_by = Vec::new();
+            if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("order")) {
+                self.advance();
+                self.expect(Token::By)?;
+                loop {
+                    order_by.push(self.parse_order_by_expr()?);
+                    if self.peek() != &Token::Comma {
+                        break;
+                    }
+                    self.advance();
+                }
+            }
+            self.expect(Token::RParen)?;
+            (arg, extra_arg, order_by)
         };
 
-        self.expect(Token::RParen)?;
+        // FILTER (WHERE condition)
+        let filter = if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("filter"))
+            && self.tokens.get(self.pos + 1) == Some(&Token::LParen)
+        {
+            self.advance();
+            self.advance();
+            self.expect(Token::Where)?;
+            let condition = self.parse_expr()?;
+            self.expect(Token::RParen)?;
+            Some(Box::new(condition))
+        } else {
+            None
+        };
 
         Ok(Expr::Aggregate {
             func,
             arg: Box::new(arg),
+            extra_arg,
+            order_by,
+            filter,
+        })
+    }
+
+    // expr [ASC | DESC] [NULLS {FIRST | LAST}]
+    fn parse_order_by_expr(&mut self) -> Result<OrderByExpr> {
+        let expr = self.parse_expr()?;
+        let descending = match self.peek() {
+            Token::Ident(s) if s.eq_ignore_ascii_case("asc") => {
+                self.advance();
+                false
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("desc") => {
+                self.advance();
+                true
+            }
+            _ => false,
+        };
+        let nulls_first =
+            if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("nulls")) {
+                self.advance();
+                match self.advance() {
+                    Token::Ident(s) if s.eq_ignore_ascii_case("first") => Some(true),
+                    Token::Ident(s) if s.eq_ignore_ascii_case("last") => Some(false),
+                    other => bail!("expected FIRST or LAST, got {other:?}"),
+                }
+            } else {
+                None
+            };
+        Ok(OrderByExpr {
+            expr,
+            descending,
+            nulls_first,
         })
     }
 }
@@ -569,3 +1547,73 @@ pub fn parse(sql: &str) -> Result<Statement> {
     let mut parser = Parser::new(tokens);
     parser.parse()
 }
+
+/// Parse a statement of the extended query protocol, which may use $n
+/// parameters. Also returns the highest parameter number used.
+pub fn parse_prepared(sql: &str) -> Result<(Statement, usize)> {
+    let mut lexer = crate::lexer::Lexer::new(sql);
+    let tokens = lexer.tokenize()?;
+    let parameter_count = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Parameter(n) => Some(*n),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let mut parser = Parser::new(tokens);
+    Ok((parser.parse()?, parameter_count))
+}
+
+/// Parse a comma-separated list of expressions, such as the stored text of
+/// index expressions
+pub fn parse_expr_list(sql: &str) -> Result<Vec<Expr>> {
+    let mut lexer = crate::lexer::Lexer::new(sql);
+    let mut parser = Parser::new(lexer.tokenize()?);
+    let mut exprs = vec![parser.parse_expr()?];
+    while parser.peek() == &Token::Comma {
+        parser.advance();
+        exprs.push(parser.parse_expr()?);
+    }
+    parser.expect(Token::Eof)?;
+    Ok(exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy_options(sql: &str) -> Result<CopyOptions> {
+        match parse(sql)? {
+            Statement::Copy(copy) => Ok(copy.options),
+            other => panic!("not a COPY: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_copy_options() {
+        let options = copy_options("COPY t FROM STDIN (FORMAT text)").unwrap();
+        assert_eq!(options, CopyOptions::default());
+        let options = copy_options("COPY t FROM STDIN (FORMAT TEXT, HEADER ON)").unwrap();
+        assert!(!options.csv && options.header);
+        let options = copy_options("COPY t TO STDOUT (FORMAT csv, HEADER false)").unwrap();
+        assert!(options.csv && !options.header);
+        let options = copy_options("COPY t TO STDOUT WITH CSV HEADER").unwrap();
+        assert!(options.csv && options.header);
+
+        assert_eq!(
+            copy_options("COPY t FROM STDIN (FORMAT binary)")
+                .unwrap_err()
+                .to_string(),
+            "COPY format \"binary\" not recognized"
+        );
+        assert_eq!(
+            copy_options("COPY t FROM STDIN (FORMAT boolean)")
+                .unwrap_err()
+                .to_string(),
+            "COPY format \"boolean\" not recognized"
+        );
+        assert!(copy_options("COPY t FROM STDIN (FORMAT 'csv')").unwrap().csv);
+        assert!(copy_options("COPY t FROM STDIN (FORMAT 1)").is_err());
+    }
+}
@@ -1,30 +1,308 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
+use rustls::{ServerConnection, StreamOwned};
 
 // PostgreSQL Wire Protocol implementation
 
 const PROTOCOL_VERSION_3: i32 = 196608; // 3.0
 
-pub struct Connection {
+/// "Protocol version" of an SSLRequest
+const SSL_REQUEST_CODE: i32 = 80877103;
+
+/// "Protocol version" of a CancelRequest
+const CANCEL_REQUEST_CODE: i32 = 80877102;
+
+/// Longest startup packet accepted, as in PostgreSQL
+const MAX_STARTUP_PACKET_LEN: i32 = 10000;
+
+/// Longest password or SASL message accepted, length field included, as
+/// in PostgreSQL
+const MAX_AUTH_MESSAGE_LEN: i32 = 10000;
+
+// SQLSTATE codes of the errors reported with one
+pub const INTERNAL_ERROR: &str = "XX000";
+pub const IN_FAILED_SQL_TRANSACTION: &str = "25P02";
+pub const QUERY_CANCELED: &str = "57014";
+pub const INVALID_AUTHORIZATION_SPECIFICATION: &str = "28000";
+pub const INVALID_PASSWORD: &str = "28P01";
+pub const PROTOCOL_VIOLATION: &str = "08P01";
+pub const INSUFFICIENT_PRIVILEGE: &str = "42501";
+pub const TOO_MANY_CONNECTIONS: &str = "53300";
+
+/// Severity of an ErrorResponse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The statement failed
+    Error,
+    /// The session ends
+    Fatal,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "ERROR",
+            Severity::Fatal => "FATAL",
+        }
+    }
+}
+
+/// An error reported to the client with its own severity and SQLSTATE;
+/// any other error is an ERROR with XX000 (internal_error)
+#[derive(Debug)]
+pub struct SqlError {
+    pub severity: Severity,
+    pub code: &'static str,
+    message: String,
+}
+
+impl SqlError {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        SqlError {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn fatal(code: &'static str, message: impl Into<String>) -> Self {
+        SqlError {
+            severity: Severity::Fatal,
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// The SqlError in an error or the errors it wraps
+    pub fn find(error: &anyhow::Error) -> Option<&SqlError> {
+        error.chain().find_map(|e| e.downcast_ref::<SqlError>())
+    }
+}
+
+impl std::fmt::Display for SqlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SqlError {}
+
+/// A byte stream to a client
+pub trait Stream: Read + Write {
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Make reads and writes fail from `deadline` on; None lifts it
+    fn set_deadline(&mut self, deadline: Option<Instant>) -> io::Result<()>;
+}
+
+/// A client's TCP stream, with a deadline for all of its reads and writes
+/// together, not for each one: a client sending a byte at a time cannot
+/// keep a connection for longer
+pub struct ClientStream {
     stream: TcpStream,
+    deadline: Option<Instant>,
 }
 
-impl Connection {
+impl ClientStream {
     pub fn new(stream: TcpStream) -> Self {
+        ClientStream {
+            stream,
+            deadline: None,
+        }
+    }
+
+    /// Time left until the deadline, as the timeout of the next read or
+    /// write; fails once it has passed
+    fn time_left(&self) -> io::Result<Duration> {
+        let deadline = self.deadline.expect("only called with a deadline");
+        match deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(left),
+            _ => Err(io::Error::new(ErrorKind::TimedOut, "deadline passed")),
+        }
+    }
+}
+
+impl Read for ClientStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.deadline.is_some() {
+            self.stream.set_read_timeout(Some(self.time_left()?))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+impl Write for ClientStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.deadline.is_some() {
+            self.stream.set_write_timeout(Some(self.time_left()?))?;
+        }
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Stream for ClientStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) -> io::Result<()> {
+        if deadline.is_none() {
+            self.stream.set_read_timeout(None)?;
+            self.stream.set_write_timeout(None)?;
+        }
+        self.deadline = deadline;
+        Ok(())
+    }
+}
+
+/// A TCP stream upgraded to TLS after SSLRequest
+pub type TlsStream = StreamOwned<ServerConnection, ClientStream>;
+
+impl Stream for TlsStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.peer_addr()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) -> io::Result<()> {
+        self.sock.set_deadline(deadline)
+    }
+}
+
+/// A client in memory, for tests: reads come from `input`, writes collect
+/// in `output`
+#[cfg(test)]
+pub struct TestStream {
+    pub input: io::Cursor<Vec<u8>>,
+    pub output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl Read for TestStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for TestStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Stream for TestStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::from(([127, 0, 0, 1], 5433)))
+    }
+
+    fn set_deadline(&mut self, _deadline: Option<Instant>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Connection<S: Stream> {
+    stream: S,
+}
+
+/// A connection once the client has chosen whether to use TLS
+pub enum Negotiated {
+    Plain(Connection<ClientStream>),
+    Tls(Box<Connection<TlsStream>>),
+}
+
+#[cfg(test)]
+impl Connection<TestStream> {
+    /// Connection to an in-memory client that sends `input`
+    pub fn for_test(input: Vec<u8>) -> Self {
+        Connection::new(TestStream {
+            input: io::Cursor::new(input),
+            output: Vec::new(),
+        })
+    }
+
+    /// Bytes sent to the client so far
+    pub fn output(&self) -> &[u8] {
+        &self.stream.output
+    }
+}
+
+impl Connection<ClientStream> {
+    /// Read the startup message. A client may first send SSLRequest: with
+    /// TLS configured the server answers 'S' and the stream continues
+    /// encrypted, otherwise 'N' and the client goes on in plain text.
+    pub fn negotiate(
+        mut self,
+        tls: Option<&Arc<rustls::ServerConfig>>,
+    ) -> Result<(Negotiated, Startup)> {
+        if let Some(startup) = self.read_startup_packet()? {
+            return Ok((Negotiated::Plain(self), startup));
+        }
+        let Some(tls) = tls else {
+            self.stream.write_all(b"N")?;
+            let startup = self.read_startup()?;
+            return Ok((Negotiated::Plain(self), startup));
+        };
+        self.stream.write_all(b"S")?;
+        // The handshake runs on the first read of the startup message
+        let session = ServerConnection::new(Arc::clone(tls))?;
+        let mut conn = Connection::new(StreamOwned::new(session, self.stream));
+        let startup = conn.read_startup()?;
+        Ok((Negotiated::Tls(Box::new(conn)), startup))
+    }
+}
+
+impl<S: Stream> Connection<S> {
+    pub fn new(stream: S) -> Self {
         Connection { stream }
     }
 
+    // Address of the client
+    pub fn peer_addr(&self) -> Result<IpAddr> {
+        Ok(self.stream.peer_addr()?.ip())
+    }
+
+    /// Fail reads and writes from `deadline` on, or no longer with None
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) -> Result<()> {
+        Ok(self.stream.set_deadline(deadline)?)
+    }
+
     // Read startup message from client
-    pub fn read_startup(&mut self) -> Result<StartupMessage> {
-        let len = self.read_i32()? as usize;
+    pub fn read_startup(&mut self) -> Result<Startup> {
+        match self.read_startup_packet()? {
+            Some(startup) => Ok(startup),
+            None => bail!("unexpected SSLRequest"),
+        }
+    }
+
+    // Read a startup message, or None for an SSLRequest
+    fn read_startup_packet(&mut self) -> Result<Option<Startup>> {
+        let len = self.read_i32()?;
+        if !(8..=MAX_STARTUP_PACKET_LEN).contains(&len) {
+            bail!("invalid length of startup packet");
+        }
         let protocol_version = self.read_i32()?;
 
-        if protocol_version == 80877103 {
-            // SSLRequest - we don't support SSL, send 'N'
-            self.stream.write_all(b"N")?;
-            return self.read_startup(); // Read actual startup
+        if protocol_version == SSL_REQUEST_CODE {
+            return Ok(None);
+        }
+
+        if protocol_version == CANCEL_REQUEST_CODE {
+            let pid = self.read_i32()?;
+            let secret = self.read_i32()?;
+            return Ok(Some(Startup::Cancel { pid, secret }));
         }
 
         if protocol_version != PROTOCOL_VERSION_3 {
@@ -33,7 +311,7 @@ impl Connection {
 
         // Read parameters (null-terminated strings)
         let mut params = Vec::new();
-        let remaining = len - 8; // subtract length and version
+        let remaining = len as usize - 8; // subtract length and version
         let mut buf = vec![0u8; remaining];
         self.stream.read_exact(&mut buf)?;
 
@@ -54,7 +332,7 @@ impl Connection {
             params.push((key, val));
         }
 
-        Ok(StartupMessage { params })
+        Ok(Some(Startup::Session(StartupMessage { params })))
     }
 
     // Read a query message
@@ -64,16 +342,66 @@ impl Connection {
             Err(_) => return Ok(None), // Connection closed
         };
 
-        let len = self.read_i32()? as usize - 4; // subtract length field itself
-        let mut buf = vec![0u8; len];
+        let len = self.read_i32()?;
+        if len < 4 {
+            bail!("invalid message length {len}");
+        }
+        let mut buf = vec![0u8; len as usize - 4]; // subtract length field itself
         self.stream.read_exact(&mut buf)?;
 
+        let mut body = MessageBody { buf: &buf, pos: 0 };
         match msg_type {
             b'Q' => {
                 // Query - null terminated string
-                let query = String::from_utf8_lossy(&buf[..buf.len() - 1]).to_string();
-                Ok(Some(FrontendMessage::Query(query)))
+                Ok(Some(FrontendMessage::Query(body.read_cstr()?)))
+            }
+            b'P' => {
+                let name = body.read_cstr()?;
+                let query = body.read_cstr()?;
+                let parameter_types = (0..body.read_i16()?)
+                    .map(|_| body.read_i32())
+                    .collect::<Result<_>>()?;
+                Ok(Some(FrontendMessage::Parse {
+                    name,
+                    query,
+                    parameter_types,
+                }))
+            }
+            b'B' => {
+                let portal = body.read_cstr()?;
+                let statement = body.read_cstr()?;
+                let parameter_formats = (0..body.read_i16()?)
+                    .map(|_| body.read_i16())
+                    .collect::<Result<_>>()?;
+                let parameters = (0..body.read_i16()?)
+                    .map(|_| match body.read_i32()? {
+                        -1 => Ok(None),
+                        len => Ok(Some(body.read_bytes(len as usize)?.to_vec())),
+                    })
+                    .collect::<Result<_>>()?;
+                let result_formats = (0..body.read_i16()?)
+                    .map(|_| body.read_i16())
+                    .collect::<Result<_>>()?;
+                Ok(Some(FrontendMessage::Bind {
+                    portal,
+                    statement,
+                    parameter_formats,
+                    parameters,
+                    result_formats,
+                }))
+            }
+            b'D' => Ok(Some(FrontendMessage::Describe(body.read_target()?))),
+            b'E' => {
+                let portal = body.read_cstr()?;
+                let max_rows = body.read_i32()?;
+                Ok(Some(FrontendMessage::Execute { portal, max_rows }))
             }
+            b'C' => Ok(Some(FrontendMessage::Close(body.read_target()?))),
+            b'S' => Ok(Some(FrontendMessage::Sync)),
+            b'H' => Ok(Some(FrontendMessage::Flush)),
+            b'd' => Ok(Some(FrontendMessage::CopyData(buf))),
+            b'c' => Ok(Some(FrontendMessage::CopyDone)),
+            b'f' => Ok(Some(FrontendMessage::CopyFail(body.read_cstr()?))),
             b'X' => Ok(Some(FrontendMessage::Terminate)),
             _ => {
                 println!("[Protocol] Unknown message type: {}", msg_type as char);
@@ -87,6 +415,81 @@ impl Connection {
         self.write_message(b'R', &0i32.to_be_bytes())
     }
 
+    // Send AuthenticationMD5Password with the salt to hash the password with
+    pub fn send_auth_md5_password(&mut self, salt: &[u8; 4]) -> Result<()> {
+        let mut buf = 5i32.to_be_bytes().to_vec();
+        buf.extend_from_slice(salt);
+        self.write_message(b'R', &buf)
+    }
+
+    // Send AuthenticationSASL: the mechanisms the client may choose from
+    pub fn send_auth_sasl(&mut self, mechanisms: &[&str]) -> Result<()> {
+        let mut buf = 10i32.to_be_bytes().to_vec();
+        for mechanism in mechanisms {
+            buf.extend_from_slice(mechanism.as_bytes());
+            buf.push(0);
+        }
+        buf.push(0);
+        self.write_message(b'R', &buf)
+    }
+
+    // Send AuthenticationSASLContinue with a server challenge
+    pub fn send_auth_sasl_continue(&mut self, data: &[u8]) -> Result<()> {
+        let mut buf = 11i32.to_be_bytes().to_vec();
+        buf.extend_from_slice(data);
+        self.write_message(b'R', &buf)
+    }
+
+    // Send AuthenticationSASLFinal with the server's proof
+    pub fn send_auth_sasl_final(&mut self, data: &[u8]) -> Result<()> {
+        let mut buf = 12i32.to_be_bytes().to_vec();
+        buf.extend_from_slice(data);
+        self.write_message(b'R', &buf)
+    }
+
+    // Read a PasswordMessage or SASLResponse: both are a 'p' message, and
+    // only the authentication request being answered tells them apart
+    pub fn read_password_message(&mut self) -> Result<Vec<u8>> {
+        let msg_type = self.read_u8()?;
+        if msg_type != b'p' {
+            bail!(
+                "expected password response, got message type {}",
+                msg_type as char
+            );
+        }
+        // Checked before allocating: the client has not authenticated yet
+        let len = self.read_i32()?;
+        if !(4..=MAX_AUTH_MESSAGE_LEN).contains(&len) {
+            return Err(SqlError::fatal(
+                PROTOCOL_VIOLATION,
+                format!("invalid password packet size {len}"),
+            )
+            .into());
+        }
+        let mut buf = vec![0u8; len as usize - 4];
+        self.stream.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // Read SASLInitialResponse: the chosen mechanism and its first message
+    pub fn read_sasl_initial_response(&mut self) -> Result<(String, Vec<u8>)> {
+        let buf = self.read_password_message()?;
+        let mut body = MessageBody { buf: &buf, pos: 0 };
+        let mechanism = body.read_cstr()?;
+        let data = match body.read_i32()? {
+            -1 => vec![],
+            len if len >= 0 => body.read_bytes(len as usize)?.to_vec(),
+            len => {
+                return Err(SqlError::fatal(
+                    PROTOCOL_VIOLATION,
+                    format!("invalid SASL response length {len}"),
+                )
+                .into())
+            }
+        };
+        Ok((mechanism, data))
+    }
+
     // Send ParameterStatus
     pub fn send_parameter_status(&mut self, name: &str, value: &str) -> Result<()> {
         let mut buf = Vec::new();
@@ -106,39 +509,50 @@ impl Connection {
     }
 
     // Send ReadyForQuery
-    pub fn send_ready_for_query(&mut self) -> Result<()> {
-        self.write_message(b'Z', b"I") // 'I' = idle
+    pub fn send_ready_for_query(&mut self, status: TransactionStatus) -> Result<()> {
+        let status = match status {
+            TransactionStatus::Idle => b"I",
+            TransactionStatus::InTransaction => b"T",
+            TransactionStatus::Failed => b"E",
+        };
+        self.write_message(b'Z', status)
     }
 
-    // Send RowDescription
-    pub fn send_row_description(&mut self, columns: &[ColumnDesc]) -> Result<()> {
+    // Send RowDescription. `formats` holds the format code of each column
+    // (0 = text, 1 = binary); an empty slice means all text.
+    pub fn send_row_description(
+        &mut self,
+        columns: &[ColumnDesc],
+        formats: &[i16],
+    ) -> Result<()> {
         let mut buf = Vec::new();
         buf.extend_from_slice(&(columns.len() as i16).to_be_bytes());
 
-        for col in columns {
+        for (i, col) in columns.iter().enumerate() {
             buf.extend_from_slice(col.name.as_bytes());
             buf.push(0); // null terminator
             buf.extend_from_slice(&0i32.to_be_bytes()); // table OID
             buf.extend_from_slice(&0i16.to_be_bytes()); // column attr number
             buf.extend_from_slice(&col.type_oid.to_be_bytes()); // type OID
             buf.extend_from_slice(&col.type_size.to_be_bytes()); // type size
-            buf.extend_from_slice(&(-1i32).to_be_bytes()); // type modifier
-            buf.extend_from_slice(&0i16.to_be_bytes()); // format code (0 = text)
+            buf.extend_from_slice(&col.type_modifier.to_be_bytes()); // type modifier
+            let format = formats.get(i).copied().unwrap_or(0);
+            buf.extend_from_slice(&format.to_be_bytes()); // format code
         }
 
         self.write_message(b'T', &buf)
     }
 
     // Send DataRow
-    pub fn send_data_row(&mut self, values: &[Option<String>]) -> Result<()> {
+    pub fn send_data_row(&mut self, values: &[Option<Vec<u8>>]) -> Result<()> {
         let mut buf = Vec::new();
         buf.extend_from_slice(&(values.len() as i16).to_be_bytes());
 
         for val in values {
             match val {
-                Some(s) => {
-                    buf.extend_from_slice(&(s.len() as i32).to_be_bytes());
-                    buf.extend_from_slice(s.as_bytes());
+                Some(data) => {
+                    buf.extend_from_slice(&(data.len() as i32).to_be_bytes());
+                    buf.extend_from_slice(data);
                 }
                 None => {
                     buf.extend_from_slice(&(-1i32).to_be_bytes()); // NULL
@@ -157,14 +571,25 @@ impl Connection {
         self.write_message(b'C', &buf)
     }
 
-    // Send ErrorResponse
-    pub fn send_error(&mut self, message: &str) -> Result<()> {
+    // Send ErrorResponse, with the severity and SQLSTATE of a SqlError
+    pub fn send_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        let (severity, code) = match SqlError::find(error) {
+            Some(e) => (e.severity.as_str(), e.code),
+            None => (Severity::Error.as_str(), INTERNAL_ERROR),
+        };
         let mut buf = Vec::new();
         buf.push(b'S'); // Severity
-        buf.extend_from_slice(b"ERROR");
+        buf.extend_from_slice(severity.as_bytes());
+        buf.push(0);
+        buf.push(b'V'); // Severity, never localized
+        buf.extend_from_slice(severity.as_bytes());
+        buf.push(0);
+        // SQLSTATE; drivers such as tokio-postgres reject errors without one
+        buf.push(b'C');
+        buf.extend_from_slice(code.as_bytes());
         buf.push(0);
         buf.push(b'M'); // Message
-        buf.extend_from_slice(message.as_bytes());
+        buf.extend_from_slice(error.to_string().as_bytes());
         buf.push(0);
         buf.push(0); // terminator
         self.write_message(b'E', &buf)
@@ -175,6 +600,61 @@ impl Connection {
         self.write_message(b'I', &[])
     }
 
+    // Send ParseComplete
+    pub fn send_parse_complete(&mut self) -> Result<()> {
+        self.write_message(b'1', &[])
+    }
+
+    // Send BindComplete
+    pub fn send_bind_complete(&mut self) -> Result<()> {
+        self.write_message(b'2', &[])
+    }
+
+    // Send CloseComplete
+    pub fn send_close_complete(&mut self) -> Result<()> {
+        self.write_message(b'3', &[])
+    }
+
+    // Send ParameterDescription: the type OID of each parameter
+    pub fn send_parameter_description(&mut self, type_oids: &[i32]) -> Result<()> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(type_oids.len() as i16).to_be_bytes());
+        for oid in type_oids {
+            buf.extend_from_slice(&oid.to_be_bytes());
+        }
+        self.write_message(b't', &buf)
+    }
+
+    // Send NoData, the description of a statement that returns no rows
+    pub fn send_no_data(&mut self) -> Result<()> {
+        self.write_message(b'n', &[])
+    }
+
+    // Send PortalSuspended: Execute reached its row limit
+    pub fn send_portal_suspended(&mut self) -> Result<()> {
+        self.write_message(b's', &[])
+    }
+
+    // Send CopyInResponse: the client should now send COPY data in text form
+    pub fn send_copy_in_response(&mut self, column_count: usize) -> Result<()> {
+        self.write_message(b'G', &copy_response(column_count))
+    }
+
+    // Send CopyOutResponse: COPY data in text form follows
+    pub fn send_copy_out_response(&mut self, column_count: usize) -> Result<()> {
+        self.write_message(b'H', &copy_response(column_count))
+    }
+
+    // Send CopyData: one row of COPY TO output
+    pub fn send_copy_data(&mut self, data: &[u8]) -> Result<()> {
+        self.write_message(b'd', data)
+    }
+
+    // Send CopyDone: the end of COPY TO output
+    pub fn send_copy_done(&mut self) -> Result<()> {
+        self.write_message(b'c', &[])
+    }
+
     fn write_message(&mut self, msg_type: u8, data: &[u8]) -> Result<()> {
         let len = (data.len() + 4) as i32;
         self.stream.write_all(&[msg_type])?;
@@ -197,6 +677,16 @@ impl Connection {
     }
 }
 
+/// First message of a connection
+#[derive(Debug)]
+pub enum Startup {
+    /// StartupMessage: open a session
+    Session(StartupMessage),
+    /// CancelRequest: cancel the statement running in the session with
+    /// this key, then close the connection
+    Cancel { pid: i32, secret: i32 },
+}
+
 #[derive(Debug)]
 pub struct StartupMessage {
     pub params: Vec<(String, String)>,
@@ -205,31 +695,237 @@ pub struct StartupMessage {
 #[derive(Debug)]
 pub enum FrontendMessage {
     Query(String),
+    /// Prepare a statement; a parameter type of 0 is left to the server
+    Parse {
+        name: String,
+        query: String,
+        parameter_types: Vec<i32>,
+    },
+    /// Create a portal from a prepared statement and parameter values
+    Bind {
+        portal: String,
+        statement: String,
+        parameter_formats: Vec<i16>,
+        parameters: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<i16>,
+    },
+    Describe(Target),
+    /// Run a portal, returning at most max_rows rows (0 = no limit)
+    Execute {
+        portal: String,
+        max_rows: i32,
+    },
+    Close(Target),
+    Sync,
+    Flush,
+    /// COPY FROM STDIN data; chunks need not end at row boundaries
+    CopyData(Vec<u8>),
+    CopyDone,
+    /// The client aborts COPY FROM STDIN with this message
+    CopyFail(String),
     Terminate,
     Unknown(u8),
 }
 
+/// The prepared statement or portal a Describe or Close refers to; the
+/// empty name is the unnamed one
 #[derive(Debug)]
+pub enum Target {
+    Statement(String),
+    Portal(String),
+}
+
+// Body of CopyInResponse and CopyOutResponse: overall format and the format
+// of each column, all text
+fn copy_response(column_count: usize) -> Vec<u8> {
+    let mut buf = vec![0];
+    buf.extend_from_slice(&(column_count as i16).to_be_bytes());
+    for _ in 0..column_count {
+        buf.extend_from_slice(&0i16.to_be_bytes());
+    }
+    buf
+}
+
+// Reader over the body of a frontend message
+struct MessageBody<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl MessageBody<'_> {
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(len);
+        let Some(bytes) = end.and_then(|end| self.buf.get(self.pos..end)) else {
+            bail!("invalid message format");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.read_bytes(2)?.try_into()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    // Null-terminated string
+    fn read_cstr(&mut self) -> Result<String> {
+        let Some(len) = self.buf[self.pos..].iter().position(|&b| b == 0) else {
+            bail!("invalid string in message");
+        };
+        let s = String::from_utf8_lossy(&self.buf[self.pos..self.pos + len]).to_string();
+        self.pos += len + 1;
+        Ok(s)
+    }
+
+    // 'S' or 'P' and a name
+    fn read_target(&mut self) -> Result<Target> {
+        let kind = self.read_bytes(1)?[0];
+        let name = self.read_cstr()?;
+        match kind {
+            b'S' => Ok(Target::Statement(name)),
+            b'P' => Ok(Target::Portal(name)),
+            _ => bail!("invalid DESCRIBE/CLOSE message subtype {}", kind as char),
+        }
+    }
+}
+
+/// Transaction status reported in ReadyForQuery
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionStatus {
+    /// Not in a transaction block
+    Idle,
+    /// In a transaction block
+    InTransaction,
+    /// In a failed transaction block; statements are rejected until ROLLBACK
+    Failed,
+}
+
+#[derive(Debug, Clone)]
 pub struct ColumnDesc {
     pub name: String,
     pub type_oid: i32,
     pub type_size: i16,
+    pub type_modifier: i32,
 }
 
 impl ColumnDesc {
+    /// Set the type modifier, e.g. the declared length of VARCHAR(n)
+    pub fn with_type_modifier(mut self, type_modifier: i32) -> Self {
+        self.type_modifier = type_modifier;
+        self
+    }
+
+    /// The array type whose elements have this column's type, e.g. INT4[]
+    /// (_int4) for INT4
+    pub fn into_array(self) -> Self {
+        let type_oid = match self.type_oid {
+            16 => 1000,   // BOOL
+            17 => 1001,   // BYTEA
+            20 => 1016,   // INT8
+            21 => 1005,   // INT2
+            23 => 1007,   // INT4
+            25 => 1009,   // TEXT
+            700 => 1021,  // FLOAT4
+            701 => 1022,  // FLOAT8
+            1042 => 1014, // BPCHAR
+            1043 => 1015, // VARCHAR
+            1082 => 1182, // DATE
+            1083 => 1183, // TIME
+            1114 => 1115, // TIMESTAMP
+            1184 => 1185, // TIMESTAMPTZ
+            1186 => 1187, // INTERVAL
+            1700 => 1231, // NUMERIC
+            3802 => 3807, // JSONB
+            _ => 1009,
+        };
+        ColumnDesc {
+            type_oid,
+            type_size: -1, // variable
+            ..self
+        }
+    }
+
     pub fn new_int(name: &str) -> Self {
         ColumnDesc {
             name: name.to_string(),
             type_oid: 23,  // INT4
             type_size: 4,
+            type_modifier: -1,
         }
     }
 
-    pub fn new_varchar(name: &str) -> Self {
+    pub fn new_int2(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 21,  // INT2
+            type_size: 2,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_int8(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 20,  // INT8
+            type_size: 8,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_float4(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 700, // FLOAT4
+            type_size: 4,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_float8(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 701, // FLOAT8
+            type_size: 8,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_numeric(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1700, // NUMERIC
+            type_size: -1,  // variable
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_text(name: &str) -> Self {
         ColumnDesc {
             name: name.to_string(),
             type_oid: 25,  // TEXT
             type_size: -1, // variable
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_varchar(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1043, // VARCHAR
+            type_size: -1,  // variable
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_bpchar(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1042, // BPCHAR (CHAR(n))
+            type_size: -1,  // variable
+            type_modifier: -1,
         }
     }
 
@@ -238,6 +934,321 @@ impl ColumnDesc {
             name: name.to_string(),
             type_oid: 16,  // BOOL
             type_size: 1,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_bytea(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 17,  // BYTEA
+            type_size: -1, // variable
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_jsonb(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 3802, // JSONB
+            type_size: -1,  // variable
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_date(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1082, // DATE
+            type_size: 4,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_time(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1083, // TIME
+            type_size: 8,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_timestamp(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1114, // TIMESTAMP
+            type_size: 8,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_timestamptz(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1184, // TIMESTAMPTZ
+            type_size: 8,
+            type_modifier: -1,
+        }
+    }
+
+    pub fn new_interval(name: &str) -> Self {
+        ColumnDesc {
+            name: name.to_string(),
+            type_oid: 1186, // INTERVAL
+            type_size: 16,
+            type_modifier: -1,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frontend message: type, length and body
+    fn message(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![msg_type];
+        buf.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    fn cstr(s: &str) -> Vec<u8> {
+        [s.as_bytes(), &[0]].concat()
+    }
+
+    /// Fields of the ErrorResponse in `output`, by type
+    fn error_fields(output: &[u8]) -> Vec<(u8, String)> {
+        assert_eq!(output[0], b'E');
+        output[5..output.len() - 1]
+            .split(|&b| b == 0)
+            .filter(|field| !field.is_empty())
+            .map(|field| (field[0], String::from_utf8(field[1..].to_vec()).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_error_severity_and_sqlstate() {
+        let mut conn = Connection::for_test(Vec::new());
+        let error = SqlError::error(IN_FAILED_SQL_TRANSACTION, "current transaction is aborted");
+        conn.send_error(&error.into()).unwrap();
+        assert_eq!(
+            error_fields(conn.output()),
+            [
+                (b'S', "ERROR".to_string()),
+                (b'V', "ERROR".to_string()),
+                (b'C', "25P02".to_string()),
+                (b'M', "current transaction is aborted".to_string()),
+            ]
+        );
+
+        // Errors without a SQLSTATE of their own are internal errors
+        let mut conn = Connection::for_test(Vec::new());
+        conn.send_error(&anyhow::anyhow!("oops").context("while testing")).unwrap();
+        let fields = error_fields(conn.output());
+        assert_eq!(fields[2], (b'C', "XX000".to_string()));
+        // ... but a wrapped SqlError keeps its own
+        let mut conn = Connection::for_test(Vec::new());
+        let error = anyhow::Error::from(SqlError::error(IN_FAILED_SQL_TRANSACTION, "aborted"));
+        conn.send_error(&error.context("while testing")).unwrap();
+        assert_eq!(error_fields(conn.output())[2], (b'C', "25P02".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_lengths_are_errors() {
+        let startup = |len: i32| {
+            let mut conn = Connection::for_test(
+                [len.to_be_bytes(), PROTOCOL_VERSION_3.to_be_bytes()].concat(),
+            );
+            conn.read_startup().map(|_| ()).unwrap_err().to_string()
+        };
+        assert_eq!(startup(4), "invalid length of startup packet");
+        assert_eq!(startup(-1), "invalid length of startup packet");
+        assert_eq!(startup(1 << 20), "invalid length of startup packet");
+
+        let read = |input: Vec<u8>| Connection::for_test(input).read_message();
+        let header = |msg_type: u8, len: i32| [&[msg_type][..], &len.to_be_bytes()].concat();
+        assert_eq!(read(header(b'Q', 3)).unwrap_err().to_string(), "invalid message length 3");
+        assert_eq!(read(header(b'S', -4)).unwrap_err().to_string(), "invalid message length -4");
+        assert!(read(header(b'Q', 4)).is_err());
+        assert!(read(message(b'Q', b"SELECT 1")).is_err());
+        assert!(matches!(
+            read(message(b'Q', &cstr("SELECT 1"))).unwrap(),
+            Some(FrontendMessage::Query(sql)) if sql == "SELECT 1"
+        ));
+
+        // A parameter length that would run past the end of the message
+        let bind = [
+            cstr(""),
+            cstr(""),
+            0i16.to_be_bytes().to_vec(),
+            1i16.to_be_bytes().to_vec(),
+            (-2i32).to_be_bytes().to_vec(),
+        ]
+        .concat();
+        assert!(read(message(b'B', &bind)).is_err());
+    }
+
+    #[test]
+    fn test_auth_message_length_checked() {
+        let violation = |input: Vec<u8>, sasl: bool| {
+            let mut conn = Connection::for_test(input);
+            let error = if sasl {
+                conn.read_sasl_initial_response().unwrap_err()
+            } else {
+                conn.read_password_message().unwrap_err()
+            };
+            let sql_error = SqlError::find(&error).unwrap();
+            assert_eq!(sql_error.severity, Severity::Fatal);
+            assert_eq!(sql_error.code, PROTOCOL_VIOLATION);
+            error.to_string()
+        };
+        let header = |len: i32| [&[b'p'][..], &len.to_be_bytes()].concat();
+        assert_eq!(violation(header(-1), false), "invalid password packet size -1");
+        assert_eq!(violation(header(3), false), "invalid password packet size 3");
+        assert_eq!(
+            violation(header(i32::MAX), false),
+            format!("invalid password packet size {}", i32::MAX)
+        );
+
+        let body = [cstr("SCRAM-SHA-256"), (-2i32).to_be_bytes().to_vec()].concat();
+        let input = [header(body.len() as i32 + 4), body].concat();
+        assert_eq!(violation(input, true), "invalid SASL response length -2");
+
+        let mut conn = Connection::for_test(message(b'p', &cstr("secret")));
+        assert_eq!(conn.read_password_message().unwrap(), cstr("secret"));
+    }
+
+    #[test]
+    fn test_read_extended_query_messages() {
+        let parse = [
+            cstr("s1"),
+            cstr("SELECT $1, $2"),
+            2i16.to_be_bytes().to_vec(),
+            23i32.to_be_bytes().to_vec(),
+            0i32.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        let bind = [
+            cstr("p1"),
+            cstr("s1"),
+            // One format code, binary, for every parameter
+            1i16.to_be_bytes().to_vec(),
+            1i16.to_be_bytes().to_vec(),
+            // $1 = 7, $2 = NULL
+            2i16.to_be_bytes().to_vec(),
+            4i32.to_be_bytes().to_vec(),
+            7i32.to_be_bytes().to_vec(),
+            (-1i32).to_be_bytes().to_vec(),
+            2i16.to_be_bytes().to_vec(),
+            0i16.to_be_bytes().to_vec(),
+            1i16.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        let input = [
+            message(b'P', &parse),
+            message(b'B', &bind),
+            message(b'D', &[&[b'S'][..], &cstr("s1")].concat()),
+            message(b'E', &[cstr("p1"), 10i32.to_be_bytes().to_vec()].concat()),
+            message(b'C', &[&[b'P'][..], &cstr("")].concat()),
+            message(b'H', &[]),
+            message(b'S', &[]),
+        ]
+        .concat();
+        let mut conn = Connection::for_test(input);
+
+        let Some(FrontendMessage::Parse {
+            name,
+            query,
+            parameter_types,
+        }) = conn.read_message().unwrap()
+        else {
+            panic!("expected Parse");
+        };
+        assert_eq!((name.as_str(), query.as_str()), ("s1", "SELECT $1, $2"));
+        assert_eq!(parameter_types, [23, 0]);
+
+        let Some(FrontendMessage::Bind {
+            portal,
+            statement,
+            parameter_formats,
+            parameters,
+            result_formats,
+        }) = conn.read_message().unwrap()
+        else {
+            panic!("expected Bind");
+        };
+        assert_eq!((portal.as_str(), statement.as_str()), ("p1", "s1"));
+        assert_eq!(parameter_formats, [1]);
+        assert_eq!(parameters, [Some(vec![0, 0, 0, 7]), None]);
+        assert_eq!(result_formats, [0, 1]);
+
+        assert!(matches!(
+            conn.read_message().unwrap(),
+            Some(FrontendMessage::Describe(Target::Statement(name))) if name == "s1"
+        ));
+        assert!(matches!(
+            conn.read_message().unwrap(),
+            Some(FrontendMessage::Execute { portal, max_rows: 10 }) if portal == "p1"
+        ));
+        assert!(matches!(
+            conn.read_message().unwrap(),
+            Some(FrontendMessage::Close(Target::Portal(name))) if name.is_empty()
+        ));
+        assert!(matches!(conn.read_message().unwrap(), Some(FrontendMessage::Flush)));
+        assert!(matches!(conn.read_message().unwrap(), Some(FrontendMessage::Sync)));
+        // The client went away
+        assert!(conn.read_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_malformed_messages() {
+        let error = |input: Vec<u8>| {
+            Connection::for_test(input).read_message().unwrap_err().to_string()
+        };
+
+        // A Bind announcing two parameters but carrying one
+        let bind = [
+            cstr(""),
+            cstr(""),
+            0i16.to_be_bytes().to_vec(),
+            2i16.to_be_bytes().to_vec(),
+            (-1i32).to_be_bytes().to_vec(),
+        ]
+        .concat();
+        assert_eq!(error(message(b'B', &bind)), "invalid message format");
+        // A parameter longer than the message
+        let bind = [
+            cstr(""),
+            cstr(""),
+            0i16.to_be_bytes().to_vec(),
+            1i16.to_be_bytes().to_vec(),
+            100i32.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        assert_eq!(error(message(b'B', &bind)), "invalid message format");
+        // A query string without its terminator
+        assert_eq!(
+            error(message(b'P', &[cstr("s1"), b"SELECT 1".to_vec()].concat())),
+            "invalid string in message"
+        );
+        assert_eq!(
+            error(message(b'D', &[&[b'X'][..], &cstr("s1")].concat())),
+            "invalid DESCRIBE/CLOSE message subtype X"
+        );
+    }
+
+    #[test]
+    fn test_send_parameter_description() {
+        let mut conn = Connection::for_test(Vec::new());
+        conn.send_parameter_description(&[23, 25]).unwrap();
+        let body = [
+            2i16.to_be_bytes().to_vec(),
+            23i32.to_be_bytes().to_vec(),
+            25i32.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        assert_eq!(conn.output(), message(b't', &body));
+    }
+}
@@ -149,6 +149,10 @@ impl RecoveryManager {
                     // Update DPT - only record first LSN that dirtied the page
                     dpt.entry(rid.page_id).or_insert(record.lsn);
                 }
+                WalRecordType::MultiInsert { page_id, .. } => {
+                    att.insert(record.txn_id, record.lsn);
+                    dpt.entry(*page_id).or_insert(record.lsn);
+                }
                 WalRecordType::CLR { redo, .. } => {
                     // Update ATT
                     att.insert(record.txn_id, record.lsn);
@@ -169,6 +173,10 @@ impl RecoveryManager {
                         dpt.entry(*prev_page_id).or_insert(record.lsn);
                     }
                 }
+                WalRecordType::Vacuum { page_id, .. } => {
+                    // No transaction to track, only the page
+                    dpt.entry(*page_id).or_insert(record.lsn);
+                }
             }
         }
 
@@ -208,18 +216,30 @@ impl RecoveryManager {
             }
 
             match &record.record_type {
-                WalRecordType::Insert { rid, data } => {
-                    if Self::should_redo(bpm, rid.page_id, record.lsn)? {
-                        Self::redo_insert(bpm, rid, data, record.lsn)?;
-                        count += 1;
-                    }
+                WalRecordType::Insert { rid, data }
+                    if Self::should_redo(bpm, rid.page_id, record.lsn)? =>
+                {
+                    Self::redo_insert(bpm, rid, data, record.lsn)?;
+                    count += 1;
                 }
-                WalRecordType::Delete { rid, xmax } => {
-                    // MVCC logical delete: set xmax
-                    if Self::should_redo(bpm, rid.page_id, record.lsn)? {
-                        Self::redo_set_xmax(bpm, rid, *xmax, record.lsn)?;
-                        count += 1;
+                WalRecordType::MultiInsert { page_id, tuples }
+                    if Self::should_redo(bpm, *page_id, record.lsn)? =>
+                {
+                    for (slot_id, data) in tuples {
+                        let rid = Rid {
+                            page_id: *page_id,
+                            slot_id: *slot_id,
+                        };
+                        Self::redo_insert(bpm, &rid, data, record.lsn)?;
                     }
+                    count += 1;
+                }
+                // MVCC logical delete: set xmax
+                WalRecordType::Delete { rid, xmax }
+                    if Self::should_redo(bpm, rid.page_id, record.lsn)? =>
+                {
+                    Self::redo_set_xmax(bpm, rid, *xmax, record.lsn)?;
+                    count += 1;
                 }
                 WalRecordType::CLR { redo, .. } => {
                     // Redo CLRs too
@@ -240,11 +260,17 @@ impl RecoveryManager {
                         }
                     }
                 }
-                WalRecordType::AllocatePage { page_id, prev_page_id, .. } => {
-                    if Self::should_redo(bpm, *page_id, record.lsn)? {
-                        Self::redo_allocate_page(bpm, *page_id, *prev_page_id, record.lsn)?;
-                        count += 1;
-                    }
+                WalRecordType::AllocatePage { page_id, prev_page_id, .. }
+                    if Self::should_redo(bpm, *page_id, record.lsn)? =>
+                {
+                    Self::redo_allocate_page(bpm, *page_id, *prev_page_id, record.lsn)?;
+                    count += 1;
+                }
+                WalRecordType::Vacuum { page_id, slots }
+                    if Self::should_redo(bpm, *page_id, record.lsn)? =>
+                {
+                    Self::redo_vacuum(bpm, *page_id, slots, record.lsn)?;
+                    count += 1;
                 }
                 _ => {}
             }
@@ -339,6 +365,35 @@ impl RecoveryManager {
         Ok(())
     }
 
+    /// Redo VACUUM: remove the dead tuples again and compact the page
+    fn redo_vacuum(
+        bpm: &Arc<Mutex<BufferPoolManager>>,
+        page_id: u32,
+        slots: &[u16],
+        lsn: Lsn,
+    ) -> Result<()> {
+        let mut bpm_guard = bpm.lock().unwrap();
+
+        if bpm_guard.page_count() <= page_id {
+            return Ok(());
+        }
+
+        let page_arc = bpm_guard.fetch_page_mut(page_id)?;
+        let mut page = page_arc.write().unwrap();
+        for &slot_id in slots {
+            if page.get_tuple(slot_id).is_some() {
+                page.delete(slot_id)?;
+            }
+        }
+        page.compact();
+        page.page_lsn = lsn;
+
+        drop(page);
+        bpm_guard.unpin_page(page_id, true)?;
+
+        Ok(())
+    }
+
     /// Redo page allocation
     fn redo_allocate_page(
         bpm: &Arc<Mutex<BufferPoolManager>>,
@@ -357,6 +412,10 @@ impl RecoveryManager {
         {
             let page_arc = bpm_guard.fetch_page_mut(page_id)?;
             let mut page = page_arc.write().unwrap();
+            // A page allocated in the file but never written is all zeroes
+            if page.free_space_offset() == 0 {
+                *page = HeapPage::new(page_id);
+            }
             page.set_next_page_id(crate::page::NO_NEXT_PAGE);
             page.page_lsn = lsn;
             drop(page);
@@ -469,6 +528,44 @@ impl RecoveryManager {
                     undo_next.insert(txn_id, record.prev_lsn);
                     count += 1;
                 }
+                WalRecordType::MultiInsert { page_id, tuples } => {
+                    // Undo every tuple of the page, one CLR each. All but the
+                    // last CLR point back at this record, so a crash part way
+                    // through undoes it again (deleting is idempotent).
+                    let mut clr_lsn = current_last_lsn;
+                    for (i, (slot_id, _)) in tuples.iter().enumerate() {
+                        let rid = Rid {
+                            page_id: *page_id,
+                            slot_id: *slot_id,
+                        };
+                        Self::undo_insert(bpm, &rid)?;
+                        let undo_next_lsn = if i + 1 == tuples.len() {
+                            record.prev_lsn
+                        } else {
+                            record.lsn
+                        };
+                        clr_lsn = wal_manager.append(
+                            txn_id,
+                            clr_lsn,
+                            WalRecordType::CLR {
+                                undo_next_lsn,
+                                redo: CLRRedo::UndoInsert { rid },
+                            },
+                        );
+                    }
+
+                    {
+                        let page_arc = bpm.lock().unwrap().fetch_page_mut(*page_id)?;
+                        let mut page = page_arc.write().unwrap();
+                        page.page_lsn = clr_lsn;
+                        drop(page);
+                        bpm.lock().unwrap().unpin_page(*page_id, true)?;
+                    }
+
+                    last_lsn_for_txn.insert(txn_id, clr_lsn);
+                    undo_next.insert(txn_id, record.prev_lsn);
+                    count += 1;
+                }
                 WalRecordType::Delete { rid, .. } => {
                     // Undo DELETE by resetting xmax to 0
                     Self::undo_delete(bpm, rid)?;
@@ -573,6 +670,11 @@ impl RecoveryManager {
             return Ok(());
         }
 
+        // A vacuumed tuple was dead for good; there is nothing to redo
+        if page.is_vacuumed(slot_id) {
+            return Ok(());
+        }
+
         // If slot exists but is deleted, restore it
         if slot_id < page.tuple_count() && page.get_tuple(slot_id).is_none() {
             page.restore(slot_id, data)?;
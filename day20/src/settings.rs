@@ -0,0 +1,290 @@
+use std::cell::RefCell;
+
+use anyhow::{bail, Result};
+
+// ============================================================================
+// Session settings
+// ============================================================================
+//
+// Run-time parameters changed with SET, read with SHOW and restored with
+// RESET. A session runs on a single worker thread, so the values live in a
+// thread-local, put back to the defaults when the next session starts there.
+
+/// Default work_mem: 4MB, as in PostgreSQL
+const DEFAULT_WORK_MEM_KB: i64 = 4 * 1024;
+
+/// Smallest accepted work_mem, in kB
+const MIN_WORK_MEM_KB: i64 = 64;
+
+/// Largest accepted work_mem, in kB
+const MAX_WORK_MEM_KB: i64 = i32::MAX as i64;
+
+/// Largest accepted statement_timeout, in milliseconds
+const MAX_STATEMENT_TIMEOUT_MS: i64 = i32::MAX as i64;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    /// Memory a query operation may use before spilling to temp files, in kB
+    work_mem_kb: i64,
+    /// Store new passwords as SCRAM-SHA-256 verifiers rather than MD5
+    password_scram: bool,
+    /// Time a statement may run before it is cancelled, in milliseconds;
+    /// 0 disables the limit
+    statement_timeout_ms: i64,
+    /// Role the connection authenticated as; set once at startup
+    session_user: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            work_mem_kb: DEFAULT_WORK_MEM_KB,
+            password_scram: true,
+            statement_timeout_ms: 0,
+            session_user: String::new(),
+        }
+    }
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Settings> = RefCell::new(Settings::default());
+}
+
+/// Start a session with every setting at its default
+pub fn reset_session() {
+    SETTINGS.with(|s| *s.borrow_mut() = Settings::default());
+}
+
+/// Memory budget of one hash table or sort on this connection, in bytes
+pub fn work_mem() -> usize {
+    SETTINGS.with(|s| s.borrow().work_mem_kb as usize * 1024)
+}
+
+/// Whether CREATE ROLE and ALTER ROLE store passwords as SCRAM-SHA-256
+pub fn password_scram() -> bool {
+    SETTINGS.with(|s| s.borrow().password_scram)
+}
+
+/// Time limit of a statement in milliseconds, or 0 for none
+pub fn statement_timeout_ms() -> i64 {
+    SETTINGS.with(|s| s.borrow().statement_timeout_ms)
+}
+
+/// Role this connection authenticated as
+pub fn session_user() -> String {
+    SETTINGS.with(|s| s.borrow().session_user.clone())
+}
+
+pub fn set_session_user(user: &str) {
+    SETTINGS.with(|s| s.borrow_mut().session_user = user.to_string());
+}
+
+/// SET name = value. A value of None restores the default (SET name TO
+/// DEFAULT, RESET name).
+pub fn set(name: &str, value: Option<&str>) -> Result<()> {
+    match name.to_ascii_lowercase().as_str() {
+        "work_mem" => {
+            let kb = match value {
+                Some(value) => parse_memory(name, value, MIN_WORK_MEM_KB, MAX_WORK_MEM_KB)?,
+                None => DEFAULT_WORK_MEM_KB,
+            };
+            SETTINGS.with(|s| s.borrow_mut().work_mem_kb = kb);
+            Ok(())
+        }
+        "password_encryption" => {
+            let scram = match value.map(str::to_ascii_lowercase).as_deref() {
+                None | Some("scram-sha-256") => true,
+                Some("md5") => false,
+                Some(_) => bail!(
+                    "invalid value for parameter \"{name}\": \"{}\"",
+                    value.unwrap_or_default()
+                ),
+            };
+            SETTINGS.with(|s| s.borrow_mut().password_scram = scram);
+            Ok(())
+        }
+        "statement_timeout" => {
+            let ms = match value {
+                Some(value) => parse_duration(name, value, MAX_STATEMENT_TIMEOUT_MS)?,
+                None => 0,
+            };
+            SETTINGS.with(|s| s.borrow_mut().statement_timeout_ms = ms);
+            Ok(())
+        }
+        "session_authorization" => bail!("parameter \"{name}\" cannot be changed"),
+        _ => bail!("unrecognized configuration parameter \"{name}\""),
+    }
+}
+
+/// Current value of a setting, formatted as SHOW displays it
+pub fn show(name: &str) -> Result<String> {
+    match name.to_ascii_lowercase().as_str() {
+        "work_mem" => Ok(format_memory(SETTINGS.with(|s| s.borrow().work_mem_kb))),
+        "password_encryption" => Ok(match password_scram() {
+            true => "scram-sha-256".to_string(),
+            false => "md5".to_string(),
+        }),
+        "statement_timeout" => Ok(format_duration(statement_timeout_ms())),
+        "session_authorization" => Ok(session_user()),
+        _ => bail!("unrecognized configuration parameter \"{name}\""),
+    }
+}
+
+/// Parse a memory size such as '64kB', '4MB' or 1024 (kB when no unit is
+/// given) into kB
+fn parse_memory(name: &str, value: &str, min_kb: i64, max_kb: i64) -> Result<i64> {
+    let trimmed = value.trim();
+    let digits_end = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '-')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(digits_end);
+    let invalid = || anyhow::anyhow!("invalid value for parameter \"{name}\": \"{value}\"");
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: i64 = match unit.trim() {
+        "" | "kB" => 1,
+        "MB" => 1024,
+        "GB" => 1024 * 1024,
+        "TB" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    let kb = number.saturating_mul(multiplier);
+    if kb < min_kb || kb > max_kb {
+        bail!(
+            "{kb} kB is outside the valid range for parameter \"{name}\" ({min_kb} .. {max_kb})"
+        );
+    }
+    Ok(kb)
+}
+
+/// Format a size in kB with the largest unit that divides it evenly
+fn format_memory(kb: i64) -> String {
+    if kb % (1024 * 1024) == 0 {
+        format!("{}GB", kb / (1024 * 1024))
+    } else if kb % 1024 == 0 {
+        format!("{}MB", kb / 1024)
+    } else {
+        format!("{kb}kB")
+    }
+}
+
+/// Parse a duration such as '30s', '500ms' or 1000 (milliseconds when no
+/// unit is given) into milliseconds
+pub fn parse_duration(name: &str, value: &str, max_ms: i64) -> Result<i64> {
+    let trimmed = value.trim();
+    let digits_end = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '-')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(digits_end);
+    let invalid = || anyhow::anyhow!("invalid value for parameter \"{name}\": \"{value}\"");
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: i64 = match unit.trim() {
+        "" | "ms" => 1,
+        "s" => 1000,
+        "min" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return Err(invalid()),
+    };
+    let ms = number.saturating_mul(multiplier);
+    if !(0..=max_ms).contains(&ms) {
+        bail!("{ms} ms is outside the valid range for parameter \"{name}\" (0 .. {max_ms})");
+    }
+    Ok(ms)
+}
+
+/// Format a duration in milliseconds with the largest unit that divides it
+/// evenly
+fn format_duration(ms: i64) -> String {
+    const UNITS: [(i64, &str); 4] = [
+        (24 * 60 * 60 * 1000, "d"),
+        (60 * 60 * 1000, "h"),
+        (60 * 1000, "min"),
+        (1000, "s"),
+    ];
+    if ms == 0 {
+        return "0".to_string();
+    }
+    for (size, unit) in UNITS {
+        if ms % size == 0 {
+            return format!("{}{unit}", ms / size);
+        }
+    }
+    format!("{ms}ms")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory() {
+        let parse = |v| parse_memory("work_mem", v, MIN_WORK_MEM_KB, MAX_WORK_MEM_KB);
+        assert_eq!(parse("64kB").unwrap(), 64);
+        assert_eq!(parse("1024").unwrap(), 1024);
+        assert_eq!(parse("4MB").unwrap(), 4096);
+        assert_eq!(parse("1 GB").unwrap(), 1024 * 1024);
+        assert!(parse("32kB").is_err());
+        assert!(parse("4mb").is_err());
+        assert!(parse("lots").is_err());
+        assert!(parse("-1").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("1.5MB").is_err());
+
+        // Limits, with sizes that overflow counted as too large
+        assert_eq!(parse("  64 kB ").unwrap(), 64);
+        assert_eq!(parse("1TB").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(
+            parse("2TB").unwrap_err().to_string(),
+            "2147483648 kB is outside the valid range for parameter \"work_mem\" (64 .. 2147483647)"
+        );
+        assert!(parse("9223372036854775807TB").is_err());
+        assert!(parse("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_format_memory() {
+        assert_eq!(format_memory(64), "64kB");
+        assert_eq!(format_memory(4096), "4MB");
+        assert_eq!(format_memory(1536), "1536kB");
+        assert_eq!(format_memory(2 * 1024 * 1024), "2GB");
+    }
+
+    #[test]
+    fn test_parse_and_format_duration() {
+        let parse = |v| parse_duration("statement_timeout", v, MAX_STATEMENT_TIMEOUT_MS);
+        assert_eq!(parse("250").unwrap(), 250);
+        assert_eq!(parse("30s").unwrap(), 30_000);
+        assert_eq!(parse("2 min").unwrap(), 120_000);
+        assert!(parse("-1").is_err());
+        assert!(parse("5 seconds").is_err());
+        assert_eq!(format_duration(0), "0");
+        assert_eq!(format_duration(1500), "1500ms");
+        assert_eq!(format_duration(30_000), "30s");
+        assert_eq!(format_duration(3_600_000), "1h");
+    }
+
+    #[test]
+    fn test_set_and_show() {
+        assert_eq!(show("work_mem").unwrap(), "4MB");
+        set("WORK_MEM", Some("256kB")).unwrap();
+        assert_eq!(show("work_mem").unwrap(), "256kB");
+        assert_eq!(work_mem(), 256 * 1024);
+        // A rejected value leaves the setting as it was
+        assert!(set("work_mem", Some("32kB")).is_err());
+        assert_eq!(work_mem(), 256 * 1024);
+        set("work_mem", None).unwrap();
+        assert_eq!(show("work_mem").unwrap(), "4MB");
+        set("password_encryption", Some("md5")).unwrap();
+        assert_eq!(show("password_encryption").unwrap(), "md5");
+        assert!(!password_scram());
+        assert!(set("password_encryption", Some("plain")).is_err());
+        set("password_encryption", None).unwrap();
+        assert_eq!(show("password_encryption").unwrap(), "scram-sha-256");
+        set("statement_timeout", Some("5s")).unwrap();
+        assert_eq!(statement_timeout_ms(), 5000);
+        set("statement_timeout", None).unwrap();
+        assert_eq!(show("statement_timeout").unwrap(), "0");
+        assert!(set("no_such_setting", Some("1")).is_err());
+        assert!(show("no_such_setting").is_err());
+    }
+}
@@ -0,0 +1,587 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::buffer_pool::BufferPoolManager;
+use crate::executor::Rid;
+use crate::page::{NO_NEXT_PAGE, PAGE_SIZE};
+use crate::tuple::{
+    deserialize_tuple_mvcc, deserialize_value, serialize_value, Column, DataType, Schema, Value,
+};
+
+// ============================================================================
+// TOAST: storage for oversized values
+// ============================================================================
+//
+// A row whose values serialize to more than TOAST_TUPLE_THRESHOLD bytes has
+// its largest variable-length values (strings, BYTEA, JSONB, arrays)
+// compressed in place. If the row is still too large, values are moved out
+// of line into the table's TOAST relation, split into chunk rows that link to
+// each other.
+//
+// Either way the value's leading u32 (its length, or an array's element
+// count) gets a flag bit, and the row holds a pointer instead of the value:
+//   compressed: [COMPRESSED | n][raw_size: u32][compressed bytes]
+//   external:   [EXTERNAL (| COMPRESSED) | 14][raw_size: u32][stored_size: u32]
+//               [first chunk page: u32][first chunk slot: u16]
+// where n counts the bytes after the header. The stored bytes are the
+// value's normal serialized form, compressed or not.
+//
+// Reading a row yields Value::Toast for such values; they are only fetched
+// and decompressed by detoast(), when an expression needs them.
+
+const EXTERNAL: u32 = 1 << 31;
+const COMPRESSED: u32 = 1 << 30;
+const LENGTH_MASK: u32 = COMPRESSED - 1;
+
+/// Size of an external pointer in a row
+const EXTERNAL_POINTER_SIZE: usize = 18;
+
+/// Rows larger than this have their values compressed or moved out of line
+pub const TOAST_TUPLE_THRESHOLD: usize = PAGE_SIZE / 4;
+
+/// Bytes of a value stored in each chunk row; two chunks fit in a page
+pub const TOAST_CHUNK_SIZE: usize = 2000;
+
+/// Values smaller than this are not worth compressing
+const MIN_COMPRESS_SIZE: usize = 32;
+
+/// A value stored compressed or out of line, not fetched yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToastPointer {
+    /// Type of the value, to decode it once fetched
+    pub data_type: DataType,
+    /// Size of the value's serialized form
+    pub raw_size: u32,
+    pub storage: ToastStorage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ToastStorage {
+    /// Compressed, in the row itself
+    Inline(Vec<u8>),
+    /// Chunks in the TOAST relation, starting at `first_chunk`
+    External {
+        first_chunk: Rid,
+        stored_size: u32,
+        compressed: bool,
+    },
+}
+
+/// Values of these types can be toasted
+pub fn is_toastable(data_type: &DataType) -> bool {
+    data_type.is_string() || matches!(data_type, DataType::Bytea | DataType::Jsonb | DataType::Array(_))
+}
+
+/// Schema of the chunk rows in a TOAST relation. The last chunk of a value
+/// has next_page = -1.
+pub fn chunk_schema() -> Schema {
+    let column = |name: &str, data_type| Column {
+        name: name.to_string(),
+        data_type,
+    };
+    Schema {
+        columns: vec![
+            column("next_page", DataType::Int),
+            column("next_slot", DataType::Int),
+            column("chunk_data", DataType::Bytea),
+        ],
+    }
+}
+
+/// Serialized form of a pointer, as stored in the row
+pub fn encode_pointer(pointer: &ToastPointer) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match &pointer.storage {
+        ToastStorage::Inline(data) => {
+            buf.extend_from_slice(&(COMPRESSED | (4 + data.len() as u32)).to_ne_bytes());
+            buf.extend_from_slice(&pointer.raw_size.to_ne_bytes());
+            buf.extend_from_slice(data);
+        }
+        ToastStorage::External {
+            first_chunk,
+            stored_size,
+            compressed,
+        } => {
+            let flags = if *compressed { EXTERNAL | COMPRESSED } else { EXTERNAL };
+            buf.extend_from_slice(&(flags | (EXTERNAL_POINTER_SIZE as u32 - 4)).to_ne_bytes());
+            buf.extend_from_slice(&pointer.raw_size.to_ne_bytes());
+            buf.extend_from_slice(&stored_size.to_ne_bytes());
+            buf.extend_from_slice(&first_chunk.page_id.to_ne_bytes());
+            buf.extend_from_slice(&first_chunk.slot_id.to_ne_bytes());
+        }
+    }
+    buf
+}
+
+/// Read a pointer at the start of a stored value, or None if the value is
+/// stored plainly. Returns the pointer and its size.
+pub fn decode_pointer(data: &[u8], data_type: &DataType) -> Result<Option<(ToastPointer, usize)>> {
+    let header = u32::from_ne_bytes(data[0..4].try_into()?);
+    if header & (EXTERNAL | COMPRESSED) == 0 {
+        return Ok(None);
+    }
+    let len = 4 + (header & LENGTH_MASK) as usize;
+    let raw_size = u32::from_ne_bytes(data[4..8].try_into()?);
+    let storage = if header & EXTERNAL != 0 {
+        ToastStorage::External {
+            first_chunk: Rid {
+                page_id: u32::from_ne_bytes(data[12..16].try_into()?),
+                slot_id: u16::from_ne_bytes(data[16..18].try_into()?),
+            },
+            stored_size: u32::from_ne_bytes(data[8..12].try_into()?),
+            compressed: header & COMPRESSED != 0,
+        }
+    } else {
+        ToastStorage::Inline(data[8..len].to_vec())
+    };
+    let pointer = ToastPointer {
+        data_type: data_type.clone(),
+        raw_size,
+        storage,
+    };
+    Ok(Some((pointer, len)))
+}
+
+/// Prepare a row for storage. While the row is larger than
+/// TOAST_TUPLE_THRESHOLD, its largest values are compressed, and then moved
+/// out of line; `store_chunk` writes one chunk row and returns where it went.
+/// Toasted values passed in are fetched first, so the row gets its own chunks.
+pub fn toast_row(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    values: &[Value],
+    types: &[DataType],
+    mut store_chunk: impl FnMut(&[Value]) -> Result<Rid>,
+) -> Result<Vec<Value>> {
+    let mut row = values.iter().map(|v| detoast(bpm, v)).collect::<Result<Vec<_>>>()?;
+    let mut sizes: Vec<usize> = row.iter().map(|v| serialize_value(v).len()).collect();
+    let too_large = |sizes: &[usize]| sizes.iter().sum::<usize>() > TOAST_TUPLE_THRESHOLD;
+    let largest = |sizes: &[usize], eligible: &dyn Fn(usize) -> bool| {
+        (0..sizes.len())
+            .filter(|&i| eligible(i))
+            .max_by_key(|&i| sizes[i])
+    };
+
+    // Compress in place, largest value first
+    let mut tried = vec![false; row.len()];
+    while too_large(&sizes) {
+        let candidate = largest(&sizes, &|i| {
+            !tried[i] && row[i] != Value::Null && is_toastable(&types[i])
+        });
+        let Some(i) = candidate else {
+            break;
+        };
+        tried[i] = true;
+        let raw = serialize_value(&row[i]);
+        if let Some(compressed) = compress(&raw) {
+            row[i] = Value::Toast(Box::new(ToastPointer {
+                data_type: types[i].clone(),
+                raw_size: raw.len() as u32,
+                storage: ToastStorage::Inline(compressed),
+            }));
+            sizes[i] = serialize_value(&row[i]).len();
+        }
+    }
+
+    // Still too large: move values out of line
+    while too_large(&sizes) {
+        let candidate = largest(&sizes, &|i| {
+            sizes[i] > EXTERNAL_POINTER_SIZE && is_toastable(&types[i])
+        });
+        let Some(i) = candidate else {
+            break;
+        };
+        let (stored, raw_size, compressed) = match &row[i] {
+            Value::Toast(pointer) => match &pointer.storage {
+                ToastStorage::Inline(data) => (data.clone(), pointer.raw_size, true),
+                ToastStorage::External { .. } => unreachable!("pointer sizes are not candidates"),
+            },
+            value => {
+                let raw = serialize_value(value);
+                let raw_size = raw.len() as u32;
+                (raw, raw_size, false)
+            }
+        };
+
+        // Written back to front so that each chunk knows the next one
+        let mut next = Rid {
+            page_id: NO_NEXT_PAGE,
+            slot_id: 0,
+        };
+        for chunk in stored.chunks(TOAST_CHUNK_SIZE).rev() {
+            next = store_chunk(&[
+                Value::Int(next.page_id as i32),
+                Value::Int(next.slot_id as i32),
+                Value::Bytea(chunk.to_vec()),
+            ])?;
+        }
+        row[i] = Value::Toast(Box::new(ToastPointer {
+            data_type: types[i].clone(),
+            raw_size,
+            storage: ToastStorage::External {
+                first_chunk: next,
+                stored_size: stored.len() as u32,
+                compressed,
+            },
+        }));
+        sizes[i] = EXTERNAL_POINTER_SIZE;
+    }
+
+    Ok(row)
+}
+
+/// The actual value behind a toasted one, fetched and decompressed. Other
+/// values are returned as they are.
+pub fn detoast(bpm: &Arc<Mutex<BufferPoolManager>>, value: &Value) -> Result<Value> {
+    let Value::Toast(pointer) = value else {
+        return Ok(value.clone());
+    };
+    let raw = match &pointer.storage {
+        ToastStorage::Inline(data) => decompress(data, pointer.raw_size as usize)?,
+        ToastStorage::External {
+            first_chunk,
+            stored_size,
+            compressed,
+        } => {
+            let mut stored = Vec::with_capacity(*stored_size as usize);
+            for (_, chunk) in read_chunks(bpm, *first_chunk)? {
+                stored.extend(chunk);
+            }
+            if stored.len() != *stored_size as usize {
+                bail!("unexpected size of toasted value: {} bytes, expected {stored_size}", stored.len());
+            }
+            if *compressed {
+                decompress(&stored, pointer.raw_size as usize)?
+            } else {
+                stored
+            }
+        }
+    };
+    let (value, _) = deserialize_value(&raw, &pointer.data_type, false)?;
+    Ok(value)
+}
+
+/// Locations of the chunks of an out-of-line value; empty for any other value
+pub fn chunk_rids(bpm: &Arc<Mutex<BufferPoolManager>>, value: &Value) -> Result<Vec<Rid>> {
+    match value {
+        Value::Toast(pointer) => match &pointer.storage {
+            ToastStorage::External { first_chunk, .. } => {
+                Ok(read_chunks(bpm, *first_chunk)?.into_iter().map(|(rid, _)| rid).collect())
+            }
+            ToastStorage::Inline(_) => Ok(Vec::new()),
+        },
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Follow a chain of chunk rows. Chunks are reached through a row that is
+/// visible to the reader, so their own visibility is not checked.
+fn read_chunks(
+    bpm: &Arc<Mutex<BufferPoolManager>>,
+    first_chunk: Rid,
+) -> Result<Vec<(Rid, Vec<u8>)>> {
+    let schema = chunk_schema();
+    let mut chunks = Vec::new();
+    let mut rid = first_chunk;
+    while rid.page_id != NO_NEXT_PAGE {
+        let page_arc = bpm.lock().unwrap().fetch_page(rid.page_id)?;
+        let data = page_arc
+            .read()
+            .unwrap()
+            .get_tuple(rid.slot_id)
+            .map(|d| d.to_vec());
+        bpm.lock().unwrap().unpin_page(rid.page_id, false)?;
+
+        let Some(data) = data else {
+            bail!("missing chunk for toast value at ({}, {})", rid.page_id, rid.slot_id);
+        };
+        let (_, _, values) = deserialize_tuple_mvcc(&data, &schema)?;
+        let [Value::Int(next_page), Value::Int(next_slot), Value::Bytea(chunk)] = &values[..] else {
+            bail!("corrupt toast chunk at ({}, {})", rid.page_id, rid.slot_id);
+        };
+        chunks.push((rid, chunk.clone()));
+        rid = Rid {
+            page_id: *next_page as u32,
+            slot_id: *next_slot as u16,
+        };
+    }
+    Ok(chunks)
+}
+
+// ============================================================================
+// Compression
+// ============================================================================
+//
+// A byte-oriented LZ77 scheme in the style of PostgreSQL's pglz. Output is a
+// sequence of groups: a control byte, then up to eight items, one per control
+// bit from the lowest. A clear bit is a literal byte; a set bit is a
+// back-reference of 2 or 3 bytes:
+//   [offset high 4 bits | length - 3 (4 bits)][offset low 8 bits]([extra length])
+// copying `length` bytes starting `offset` (1..=4095) bytes back. A length
+// nibble of 15 is followed by a byte with the remaining length.
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = MIN_MATCH + 15 + 255;
+const MAX_OFFSET: usize = 4095;
+const HASH_SIZE: usize = 4096;
+const MAX_CANDIDATES: usize = 16;
+
+/// Compress `data`, or None if that would not save at least a quarter of it
+pub fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < MIN_COMPRESS_SIZE {
+        return None;
+    }
+    let limit = data.len() - data.len() / 4;
+    let hash = |p: usize| {
+        let h = (data[p] as usize) << 8 ^ (data[p + 1] as usize) << 4 ^ data[p + 2] as usize;
+        h % HASH_SIZE
+    };
+    // Most recent position + 1 of each 3-byte prefix, 0 for none, and for
+    // each position the one before it with the same prefix hash
+    let mut recent = vec![0usize; HASH_SIZE];
+    let mut previous = vec![0usize; data.len()];
+    let remember = |p: usize, recent: &mut [usize], previous: &mut [usize]| {
+        let h = hash(p);
+        previous[p] = recent[h];
+        recent[h] = p + 1;
+    };
+
+    let mut out = Vec::with_capacity(limit);
+    let mut pos = 0;
+    while pos < data.len() {
+        let control = out.len();
+        out.push(0u8);
+        for bit in 0..8 {
+            if pos >= data.len() {
+                break;
+            }
+
+            // Take the longest match among the last few candidates
+            let mut best: Option<(usize, usize)> = None;
+            if pos + MIN_MATCH <= data.len() {
+                let max_len = (data.len() - pos).min(MAX_MATCH);
+                let mut candidate = recent[hash(pos)];
+                for _ in 0..MAX_CANDIDATES {
+                    let Some(start) = candidate.checked_sub(1) else {
+                        break;
+                    };
+                    let offset = pos - start;
+                    if offset > MAX_OFFSET {
+                        break;
+                    }
+                    let len = (0..max_len)
+                        .take_while(|&i| data[start + i] == data[pos + i])
+                        .count();
+                    if len >= MIN_MATCH && best.is_none_or(|(_, best_len)| len > best_len) {
+                        best = Some((offset, len));
+                    }
+                    candidate = previous[start];
+                }
+                remember(pos, &mut recent, &mut previous);
+            }
+
+            match best {
+                Some((offset, len)) => {
+                    out[control] |= 1 << bit;
+                    let code = (len - MIN_MATCH).min(15);
+                    out.push(((offset >> 8) as u8) << 4 | code as u8);
+                    out.push(offset as u8);
+                    if code == 15 {
+                        out.push((len - MIN_MATCH - 15) as u8);
+                    }
+                    for p in pos + 1..(pos + len).min(data.len() - MIN_MATCH + 1) {
+                        remember(p, &mut recent, &mut previous);
+                    }
+                    pos += len;
+                }
+                None => {
+                    out.push(data[pos]);
+                    pos += 1;
+                }
+            }
+        }
+        if out.len() >= limit {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+/// Reverse compress(); `raw_size` is the size of the original data
+pub fn decompress(data: &[u8], raw_size: usize) -> Result<Vec<u8>> {
+    let corrupt = || anyhow::anyhow!("compressed data is corrupt");
+    let mut out = Vec::with_capacity(raw_size);
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        for bit in 0..8 {
+            if i >= data.len() {
+                break;
+            }
+            if control & (1 << bit) == 0 {
+                out.push(data[i]);
+                i += 1;
+                continue;
+            }
+            let (first, second) = (data[i], *data.get(i + 1).ok_or_else(corrupt)?);
+            i += 2;
+            let offset = ((first >> 4) as usize) << 8 | second as usize;
+            let mut len = (first & 0x0f) as usize + MIN_MATCH;
+            if first & 0x0f == 15 {
+                len += *data.get(i).ok_or_else(corrupt)? as usize;
+                i += 1;
+            }
+            if offset == 0 || offset > out.len() {
+                return Err(corrupt());
+            }
+            // Byte by byte: the source may overlap what is being written
+            let start = out.len() - offset;
+            for k in 0..len {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    if out.len() != raw_size {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::executor::testing::TestDb;
+
+    /// Incompressible bytes from a xorshift generator
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let repetitive = "abcabcabcabc hello hello hello ".repeat(200).into_bytes();
+        let compressed = compress(&repetitive).unwrap();
+        assert!(compressed.len() < repetitive.len() / 10);
+        assert_eq!(decompress(&compressed, repetitive.len()).unwrap(), repetitive);
+
+        // Long runs use the extra length byte and overlapping copies
+        let run = vec![b'x'; 5000];
+        let compressed = compress(&run).unwrap();
+        assert_eq!(decompress(&compressed, run.len()).unwrap(), run);
+
+        // Data that does not shrink is left alone
+        let noise = noise(4000);
+        assert!(compress(&noise).is_none());
+        assert!(compress(b"short").is_none());
+
+        assert!(decompress(&[0x01, 0x00, 0x05], 3).is_err());
+    }
+
+    #[test]
+    fn test_pointer_encoding() {
+        let pointer = ToastPointer {
+            data_type: DataType::Text,
+            raw_size: 100_000,
+            storage: ToastStorage::External {
+                first_chunk: Rid {
+                    page_id: 42,
+                    slot_id: 3,
+                },
+                stored_size: 12_345,
+                compressed: true,
+            },
+        };
+        let bytes = encode_pointer(&pointer);
+        assert_eq!(bytes.len(), EXTERNAL_POINTER_SIZE);
+        assert_eq!(
+            decode_pointer(&bytes, &DataType::Text).unwrap(),
+            Some((pointer, EXTERNAL_POINTER_SIZE))
+        );
+
+        let inline = ToastPointer {
+            data_type: DataType::Bytea,
+            raw_size: 500,
+            storage: ToastStorage::Inline(vec![1, 2, 3]),
+        };
+        let bytes = encode_pointer(&inline);
+        assert_eq!(decode_pointer(&bytes, &DataType::Bytea).unwrap(), Some((inline, 11)));
+
+        // A plain value is not a pointer
+        let plain = serialize_value(&Value::Varchar("hello".to_string()));
+        assert_eq!(decode_pointer(&plain, &DataType::Text).unwrap(), None);
+    }
+
+    #[test]
+    fn test_toast_row() {
+        let db = TestDb::new();
+        let types = [DataType::Int, DataType::Text, DataType::Bytea];
+        let stored = RefCell::new(Vec::new());
+        let store_chunk = |values: &[Value]| {
+            let mut stored = stored.borrow_mut();
+            stored.push(values.to_vec());
+            Ok(Rid {
+                page_id: 7,
+                slot_id: stored.len() as u16 - 1,
+            })
+        };
+
+        // Small rows are stored as they are
+        let small = [Value::Int(1), Value::Varchar("x".repeat(100)), Value::Null];
+        assert_eq!(toast_row(&db.bpm, &small, &types, &store_chunk).unwrap(), small);
+
+        // Compressible values are compressed in place
+        let text = Value::Varchar("toast ".repeat(1000));
+        let row = toast_row(
+            &db.bpm,
+            &[Value::Int(1), text.clone(), Value::Null],
+            &types,
+            &store_chunk,
+        )
+        .unwrap();
+        match &row[1] {
+            Value::Toast(pointer) => assert!(matches!(pointer.storage, ToastStorage::Inline(_))),
+            other => panic!("expected a toasted value, got {other:?}"),
+        }
+        assert_eq!(detoast(&db.bpm, &row[1]).unwrap(), text);
+        assert!(stored.borrow().is_empty());
+
+        // Incompressible ones go out of line, in chunks linked back to front
+        let noise = noise(5000);
+        let row = toast_row(
+            &db.bpm,
+            &[Value::Int(1), Value::Null, Value::Bytea(noise)],
+            &types,
+            &store_chunk,
+        )
+        .unwrap();
+        let stored = stored.borrow();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0][0], Value::Int(NO_NEXT_PAGE as i32));
+        assert_eq!(stored[1][..2], [Value::Int(7), Value::Int(0)]);
+        match &row[2] {
+            Value::Toast(pointer) => assert_eq!(
+                pointer.storage,
+                ToastStorage::External {
+                    first_chunk: Rid {
+                        page_id: 7,
+                        slot_id: 2
+                    },
+                    stored_size: 5004,
+                    compressed: false,
+                }
+            ),
+            other => panic!("expected a toasted value, got {other:?}"),
+        }
+    }
+}
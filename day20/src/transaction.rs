@@ -10,6 +10,8 @@ use crate::wal::Lsn;
 pub enum TransactionState {
     Inactive,
     Active,
+    // A statement failed inside BEGIN ... COMMIT; only ROLLBACK is accepted
+    Aborted,
 }
 
 // Undo log entry for rollback
@@ -33,6 +35,9 @@ pub struct Transaction {
     // Snapshot for REPEATABLE READ isolation level
     // Taken at transaction start and used for all reads within the transaction
     pub snapshot: Option<Snapshot>,
+    // Opened by Execute of the extended query protocol rather than BEGIN;
+    // it ends at the next Sync
+    pub implicit: bool,
 }
 
 impl Transaction {
@@ -44,6 +49,7 @@ impl Transaction {
             held_locks: HashSet::new(),
             last_lsn: 0,
             snapshot: None,
+            implicit: false,
         }
     }
 
@@ -55,6 +61,7 @@ impl Transaction {
         self.held_locks.clear();
         self.last_lsn = 0;
         self.snapshot = Some(snapshot);
+        self.implicit = false;
     }
 
     pub fn commit(&mut self) {
@@ -63,11 +70,23 @@ impl Transaction {
         self.state = TransactionState::Inactive;
         self.last_lsn = 0;
         self.snapshot = None;
+        self.implicit = false;
         // held_locks is cleared by take_held_locks before this
     }
 
+    // True while a transaction is open, including an aborted one
     pub fn is_active(&self) -> bool {
-        self.state == TransactionState::Active
+        self.state != TransactionState::Inactive
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.state == TransactionState::Aborted
+    }
+
+    // Mark the transaction block failed; its changes stay in place until
+    // ROLLBACK undoes them
+    pub fn set_aborted(&mut self) {
+        self.state = TransactionState::Aborted;
     }
 
     pub fn add_undo_entry(&mut self, entry: UndoLogEntry) {
@@ -83,6 +102,7 @@ impl Transaction {
         self.state = TransactionState::Inactive;
         self.last_lsn = 0;
         self.snapshot = None;
+        self.implicit = false;
         std::mem::take(&mut self.undo_log)
     }
 
@@ -24,6 +24,9 @@ pub struct TransactionManager {
     next_txn_id: AtomicU64,
     // Active Transaction Table: txn_id -> last_lsn
     att: Mutex<HashMap<u64, Lsn>>,
+    // Oldest transaction each active transaction may still see as running:
+    // the smallest active ID when it began. Guarded by the ATT lock.
+    horizons: Mutex<HashMap<TxnId, TxnId>>,
     // CLOG for transaction status persistence
     clog: Arc<Clog>,
 }
@@ -33,6 +36,7 @@ impl TransactionManager {
         TransactionManager {
             next_txn_id: AtomicU64::new(1),
             att: Mutex::new(HashMap::new()),
+            horizons: Mutex::new(HashMap::new()),
             clog,
         }
     }
@@ -41,6 +45,8 @@ impl TransactionManager {
     pub fn begin(&self) -> u64 {
         let txn_id = self.next_txn_id.fetch_add(1, Ordering::SeqCst);
         let mut att = self.att.lock().unwrap();
+        let horizon = att.keys().copied().min().unwrap_or(txn_id).min(txn_id);
+        self.horizons.lock().unwrap().insert(txn_id, horizon);
         att.insert(txn_id, 0); // Initial last_lsn is 0
         // Status is InProgress (default in CLOG)
         txn_id
@@ -58,6 +64,7 @@ impl TransactionManager {
     pub fn commit(&self, txn_id: u64) {
         let mut att = self.att.lock().unwrap();
         att.remove(&txn_id);
+        self.horizons.lock().unwrap().remove(&txn_id);
         drop(att);
         // Record committed status in CLOG
         self.clog.set_status(txn_id, TxnStatus::Committed);
@@ -67,6 +74,7 @@ impl TransactionManager {
     pub fn abort(&self, txn_id: u64) {
         let mut att = self.att.lock().unwrap();
         att.remove(&txn_id);
+        self.horizons.lock().unwrap().remove(&txn_id);
         drop(att);
         // Record aborted status in CLOG
         self.clog.set_status(txn_id, TxnStatus::Aborted);
@@ -98,6 +106,19 @@ impl TransactionManager {
         Snapshot::new(txn_id, xmin, xmax, active_txns)
     }
 
+    /// Oldest transaction ID that some active transaction may still consider
+    /// running. Rows deleted by a committed transaction below it are dead to
+    /// every snapshot.
+    pub fn oldest_xmin(&self) -> TxnId {
+        let _att = self.att.lock().unwrap();
+        let horizons = self.horizons.lock().unwrap();
+        horizons
+            .values()
+            .copied()
+            .min()
+            .unwrap_or_else(|| self.next_txn_id.load(Ordering::SeqCst))
+    }
+
     /// Get transaction status for visibility checking (from CLOG).
     pub fn get_txn_status(&self, txn_id: TxnId) -> TxnStatus {
         self.clog.get_status(txn_id)
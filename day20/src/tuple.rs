@@ -1,5 +1,15 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
 use anyhow::Result;
 
+use crate::array;
+use crate::bytea;
+use crate::datetime::{self, Interval};
+use crate::json::Json;
+use crate::numeric::Numeric;
+use crate::toast::{self, ToastPointer};
+
 // Transaction ID type for MVCC
 pub type TxnId = u64;
 
@@ -12,8 +22,136 @@ pub const TUPLE_HEADER_SIZE: usize = 16;
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     Int,
-    Varchar,
+    /// VARCHAR with optional maximum length in characters
+    Varchar(Option<u32>),
+    /// CHAR(n): blank-padded to exactly n characters
+    Char(u32),
+    Text,
     Bool,
+    SmallInt,
+    BigInt,
+    Real,
+    Double,
+    /// NUMERIC with optional (precision, scale) constraint
+    Numeric(Option<(u32, u32)>),
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+    Interval,
+    Bytea,
+    Jsonb,
+    /// One-dimensional array of the element type
+    Array(Box<DataType>),
+}
+
+impl DataType {
+    /// Any integer, NUMERIC or floating point type
+    pub fn is_numeric(&self) -> bool {
+        self.numeric_rank() > 0
+    }
+
+    /// Rank in the implicit promotion order of numeric types:
+    /// SMALLINT < INT < BIGINT < NUMERIC < REAL < DOUBLE PRECISION
+    pub fn numeric_rank(&self) -> u8 {
+        match self {
+            DataType::SmallInt => 1,
+            DataType::Int => 2,
+            DataType::BigInt => 3,
+            DataType::Numeric(_) => 4,
+            DataType::Real => 5,
+            DataType::Double => 6,
+            _ => 0,
+        }
+    }
+
+    /// Result type of arithmetic between two numeric types. A NUMERIC result
+    /// carries no precision constraint.
+    pub fn wider_numeric(&self, other: &DataType) -> DataType {
+        let wider = if self.numeric_rank() >= other.numeric_rank() {
+            self
+        } else {
+            other
+        };
+        match wider {
+            DataType::Numeric(_) => DataType::Numeric(None),
+            other => other.clone(),
+        }
+    }
+
+    /// PostgreSQL-style type modifier stored in pg_attribute (-1 = none)
+    pub fn typmod(&self) -> i32 {
+        match self {
+            DataType::Numeric(Some((precision, scale))) => {
+                (((*precision as i32) << 16) | *scale as i32) + 4
+            }
+            DataType::Varchar(Some(length)) | DataType::Char(length) => *length as i32 + 4,
+            // An array column keeps its element's modifier, as in VARCHAR(10)[]
+            DataType::Array(element) => element.typmod(),
+            _ => -1,
+        }
+    }
+
+    /// Reattach a type modifier read back from pg_attribute
+    pub fn with_typmod(self, typmod: i32) -> DataType {
+        match self {
+            DataType::Numeric(_) if typmod >= 4 => {
+                let typmod = (typmod - 4) as u32;
+                DataType::Numeric(Some((typmod >> 16, typmod & 0xffff)))
+            }
+            DataType::Varchar(_) if typmod >= 4 => DataType::Varchar(Some((typmod - 4) as u32)),
+            DataType::Char(_) if typmod >= 4 => DataType::Char((typmod - 4) as u32),
+            DataType::Array(element) => DataType::Array(Box::new(element.with_typmod(typmod))),
+            other => other,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let name = match self {
+            DataType::Array(element) => return format!("{}[]", element.name()),
+            DataType::Int => "integer",
+            DataType::Varchar(_) => "character varying",
+            DataType::Char(_) => "character",
+            DataType::Text => "text",
+            DataType::Bool => "boolean",
+            DataType::SmallInt => "smallint",
+            DataType::BigInt => "bigint",
+            DataType::Real => "real",
+            DataType::Double => "double precision",
+            DataType::Numeric(_) => "numeric",
+            DataType::Date => "date",
+            DataType::Time => "time without time zone",
+            DataType::Timestamp => "timestamp without time zone",
+            DataType::TimestampTz => "timestamp with time zone",
+            DataType::Interval => "interval",
+            DataType::Bytea => "bytea",
+            DataType::Jsonb => "jsonb",
+        };
+        name.to_string()
+    }
+
+    /// TEXT, VARCHAR or CHAR: all stored as Value::Varchar
+    pub fn is_string(&self) -> bool {
+        matches!(self, DataType::Varchar(_) | DataType::Char(_) | DataType::Text)
+    }
+
+    /// Strings, and arrays of them, compare according to a collation
+    pub fn is_collatable(&self) -> bool {
+        match self {
+            DataType::Array(element) => element.is_collatable(),
+            other => other.is_string(),
+        }
+    }
+
+    /// DATE, TIMESTAMP or TIMESTAMPTZ: points in time that compare with each other
+    pub fn is_datetime(&self) -> bool {
+        matches!(self, DataType::Date | DataType::Timestamp | DataType::TimestampTz)
+    }
+
+    /// Any date/time type, including TIME and INTERVAL
+    pub fn is_temporal(&self) -> bool {
+        self.is_datetime() || matches!(self, DataType::Time | DataType::Interval)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -28,15 +166,574 @@ pub struct Schema {
     pub columns: Vec<Column>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Null,
     Int(i32),
     Varchar(String),
     Bool(bool),
+    SmallInt(i16),
+    BigInt(i64),
+    Real(f32),
+    Double(f64),
+    Numeric(Numeric),
+    Date(i32),
+    Time(i64),
+    Timestamp(i64),
+    TimestampTz(i64),
+    Interval(Interval),
+    Bytea(Vec<u8>),
+    Jsonb(Json),
+    /// Elements of an array; any of them may be NULL
+    Array(Vec<Value>),
+    /// A compressed or out-of-line value, fetched by toast::detoast
+    Toast(Box<ToastPointer>),
+}
+
+// Floats compare bitwise so that Value can be a hash key (GROUP BY, IndexKey),
+// after folding -0.0 into 0.0 and every NaN into one; NUMERIC compares by
+// value (1.50 = 1.5), and so do integers of different widths (SMALLINT 1 =
+// BIGINT 1), as compare_values in the B-tree does.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        if let (Some(a), Some(b)) = (self.as_i64(), other.as_i64()) {
+            return a == b;
+        }
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Varchar(a), Value::Varchar(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Real(a), Value::Real(b)) => float_bits(*a as f64) == float_bits(*b as f64),
+            (Value::Double(a), Value::Double(b)) => float_bits(*a) == float_bits(*b),
+            (Value::Numeric(a), Value::Numeric(b)) => a == b,
+            (Value::Date(a), Value::Date(b)) => a == b,
+            (Value::Time(a), Value::Time(b)) => a == b,
+            (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+            (Value::TimestampTz(a), Value::TimestampTz(b)) => a == b,
+            (Value::Interval(a), Value::Interval(b)) => a == b,
+            (Value::Bytea(a), Value::Bytea(b)) => a == b,
+            (Value::Jsonb(a), Value::Jsonb(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Toast(a), Value::Toast(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+/// Bits of a float for equality and hashing, the same for 0.0 and -0.0 and
+/// for all NaNs
+fn float_bits(f: f64) -> u64 {
+    if f == 0.0 {
+        0
+    } else if f.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        f.to_bits()
+    }
 }
 
-fn serialize_value(value: &Value) -> Vec<u8> {
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // All integer widths hash alike, widened to i64
+        if let Some(n) = self.as_i64() {
+            std::mem::discriminant(&Value::BigInt(0)).hash(state);
+            n.hash(state);
+            return;
+        }
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Null => {}
+            Value::SmallInt(_) | Value::Int(_) | Value::BigInt(_) => unreachable!(),
+            Value::Varchar(s) => s.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Real(f) => float_bits(*f as f64).hash(state),
+            Value::Double(f) => float_bits(*f).hash(state),
+            Value::Numeric(n) => n.hash(state),
+            Value::Date(d) => d.hash(state),
+            Value::Time(t) | Value::Timestamp(t) | Value::TimestampTz(t) => t.hash(state),
+            Value::Interval(i) => i.hash(state),
+            Value::Bytea(b) => b.hash(state),
+            Value::Jsonb(j) => j.hash(state),
+            Value::Array(elements) => elements.hash(state),
+            Value::Toast(pointer) => pointer.storage.hash(state),
+        }
+    }
+}
+
+impl Value {
+    /// Integer payload widened to i64, or None for non-integer values.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::SmallInt(n) => Some(*n as i64),
+            Value::Int(n) => Some(*n as i64),
+            Value::BigInt(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Any numeric value as f64, or None for non-numeric values.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Real(f) => Some(*f as f64),
+            Value::Double(f) => Some(*f),
+            Value::Numeric(n) => Some(n.to_f64()),
+            other => other.as_i64().map(|n| n as f64),
+        }
+    }
+
+    /// Integer or NUMERIC value as an exact Numeric.
+    pub fn to_numeric(&self) -> Option<Numeric> {
+        match self {
+            Value::Numeric(n) => Some(n.clone()),
+            other => other.as_i64().map(Numeric::from_i64),
+        }
+    }
+
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::Null => None,
+            Value::Int(_) => Some(DataType::Int),
+            Value::Varchar(_) => Some(DataType::Text),
+            Value::Bool(_) => Some(DataType::Bool),
+            Value::SmallInt(_) => Some(DataType::SmallInt),
+            Value::BigInt(_) => Some(DataType::BigInt),
+            Value::Real(_) => Some(DataType::Real),
+            Value::Double(_) => Some(DataType::Double),
+            Value::Numeric(_) => Some(DataType::Numeric(None)),
+            Value::Date(_) => Some(DataType::Date),
+            Value::Time(_) => Some(DataType::Time),
+            Value::Timestamp(_) => Some(DataType::Timestamp),
+            Value::TimestampTz(_) => Some(DataType::TimestampTz),
+            Value::Interval(_) => Some(DataType::Interval),
+            Value::Bytea(_) => Some(DataType::Bytea),
+            Value::Jsonb(_) => Some(DataType::Jsonb),
+            // Typed by the first non-NULL element; TEXT[] when there is none
+            Value::Array(elements) => {
+                let element = elements.iter().find_map(Value::data_type);
+                Some(DataType::Array(Box::new(element.unwrap_or(DataType::Text))))
+            }
+            Value::Toast(pointer) => Some(pointer.data_type.clone()),
+        }
+    }
+
+    pub fn is_temporal(&self) -> bool {
+        self.data_type().is_some_and(|t| t.is_temporal())
+    }
+
+    /// DATE, TIMESTAMP or TIMESTAMPTZ as microseconds since 2000-01-01 (UTC);
+    /// an error for a DATE beyond the TIMESTAMP range.
+    pub fn as_timestamp(&self) -> Option<Result<i64>> {
+        match self {
+            Value::Date(d) => Some(datetime::date_to_timestamp(*d)),
+            Value::Timestamp(ts) | Value::TimestampTz(ts) => Some(Ok(*ts)),
+            _ => None,
+        }
+    }
+}
+
+/// Order two date/time values, or None if they are not comparable. DATE,
+/// TIMESTAMP and TIMESTAMPTZ compare with each other on a common timeline.
+pub fn compare_datetimes(a: &Value, b: &Value) -> Option<Ordering> {
+    // In i128, so that every DATE has a place on the timeline, even one
+    // beyond the TIMESTAMP range
+    let micros = |value: &Value| match value {
+        Value::Date(d) => Some(*d as i128 * datetime::USECS_PER_DAY as i128),
+        Value::Timestamp(ts) | Value::TimestampTz(ts) => Some(*ts as i128),
+        _ => None,
+    };
+    match (a, b) {
+        (Value::Time(x), Value::Time(y)) => Some(x.cmp(y)),
+        (Value::Interval(x), Value::Interval(y)) => Some(x.cmp(y)),
+        _ => Some(micros(a)?.cmp(&micros(b)?)),
+    }
+}
+
+/// Order two numeric values of possibly different types, or None if either is
+/// not numeric. Exact types compare exactly; floats compare as f64 with NaN
+/// sorting above every other value.
+pub fn compare_numbers(a: &Value, b: &Value) -> Option<Ordering> {
+    if let (Some(x), Some(y)) = (a.as_i64(), b.as_i64()) {
+        return Some(x.cmp(&y));
+    }
+    if matches!(a, Value::Real(_) | Value::Double(_)) || matches!(b, Value::Real(_) | Value::Double(_))
+    {
+        let (x, y) = (a.as_f64()?, b.as_f64()?);
+        return Some(x.partial_cmp(&y).unwrap_or_else(|| x.is_nan().cmp(&y.is_nan())));
+    }
+    Some(a.to_numeric()?.cmp(&b.to_numeric()?))
+}
+
+/// Parse the text form of a value, as written in a typed constant
+/// (DATE '2024-01-01'), a string literal compared with a column, or an
+/// element of an array literal ('{1,2,3}').
+pub fn parse_value(text: &str, data_type: &DataType) -> Result<Value> {
+    let invalid = || {
+        anyhow::anyhow!("invalid input syntax for type {}: \"{text}\"", data_type.name())
+    };
+    match data_type {
+        DataType::SmallInt | DataType::Int | DataType::BigInt => {
+            let n = text.trim().parse::<i64>().map_err(|_| invalid())?;
+            integer_value(n, data_type)
+        }
+        DataType::Real | DataType::Double => {
+            let trimmed = text.trim();
+            let f = trimmed.parse::<f64>().map_err(|_| invalid())?;
+            // Out-of-range input parses as infinity too; only the spellings
+            // of infinity may give one
+            let infinity = matches!(
+                trimmed.trim_start_matches(['+', '-']).to_ascii_lowercase().as_str(),
+                "inf" | "infinity"
+            );
+            let overflows = match data_type {
+                DataType::Real => (f as f32).is_infinite(),
+                _ => f.is_infinite(),
+            };
+            if overflows && !infinity {
+                anyhow::bail!("\"{text}\" is out of range for type {}", data_type.name());
+            }
+            coerce_value(Value::Double(f), data_type)
+        }
+        DataType::Numeric(_) => coerce_value(Value::Numeric(Numeric::parse(text)?), data_type),
+        DataType::Bool => match text.trim().to_ascii_lowercase().as_str() {
+            "t" | "true" | "y" | "yes" | "on" | "1" => Ok(Value::Bool(true)),
+            "f" | "false" | "n" | "no" | "off" | "0" => Ok(Value::Bool(false)),
+            _ => Err(invalid()),
+        },
+        t if t.is_string() => coerce_value(Value::Varchar(text.to_string()), data_type),
+        DataType::Date => Ok(Value::Date(datetime::parse_date(text)?)),
+        DataType::Time => Ok(Value::Time(datetime::parse_time(text)?)),
+        DataType::Timestamp => Ok(Value::Timestamp(datetime::parse_timestamp(text)?)),
+        DataType::TimestampTz => Ok(Value::TimestampTz(datetime::parse_timestamptz(text)?)),
+        DataType::Interval => Ok(Value::Interval(Interval::parse(text)?)),
+        DataType::Bytea => Ok(Value::Bytea(bytea::parse(text)?)),
+        DataType::Jsonb => Ok(Value::Jsonb(Json::parse(text)?)),
+        DataType::Array(element_type) => {
+            let elements = array::parse(text)?
+                .into_iter()
+                .map(|element| match element {
+                    Some(text) => parse_value(&text, element_type),
+                    None => Ok(Value::Null),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Value::Array(elements))
+        }
+        other => anyhow::bail!("cannot parse a constant of type {}", other.name()),
+    }
+}
+
+/// Build an integer value of the given type, failing if `n` does not fit.
+pub fn integer_value(n: i64, data_type: &DataType) -> Result<Value> {
+    match data_type {
+        DataType::SmallInt => i16::try_from(n)
+            .map(Value::SmallInt)
+            .map_err(|_| anyhow::anyhow!("smallint out of range")),
+        DataType::Int => i32::try_from(n)
+            .map(Value::Int)
+            .map_err(|_| anyhow::anyhow!("integer out of range")),
+        DataType::BigInt => Ok(Value::BigInt(n)),
+        other => anyhow::bail!("{} is not an integer type", other.name()),
+    }
+}
+
+/// Text output format of a value, None for NULL
+pub fn format_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Int(n) => Some(n.to_string()),
+        Value::SmallInt(n) => Some(n.to_string()),
+        Value::BigInt(n) => Some(n.to_string()),
+        Value::Real(f) => Some(format_float(*f as f64, &format!("{f:e}"), 6)),
+        Value::Double(f) => Some(format_float(*f, &format!("{f:e}"), 15)),
+        Value::Numeric(n) => Some(n.to_string()),
+        Value::Date(d) => Some(datetime::format_date(*d)),
+        Value::Time(t) => Some(datetime::format_time(*t)),
+        Value::Timestamp(ts) => Some(datetime::format_timestamp(*ts)),
+        Value::TimestampTz(ts) => Some(datetime::format_timestamptz(*ts)),
+        Value::Interval(i) => Some(i.to_string()),
+        Value::Bytea(b) => Some(bytea::format(b)),
+        Value::Jsonb(j) => Some(j.to_string()),
+        Value::Varchar(s) => Some(s.clone()),
+        Value::Bool(b) => Some(if *b { "t" } else { "f" }.to_string()),
+        Value::Array(elements) => Some(array::format(elements.iter().map(format_value))),
+        Value::Toast(_) => unreachable!("results are detoasted before they are formatted"),
+        Value::Null => None,
+    }
+}
+
+/// Binary output format of a value, as PostgreSQL's send functions write
+/// it; None for NULL
+pub fn format_binary(value: &Value) -> Result<Option<Vec<u8>>> {
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::Int(n) => n.to_be_bytes().to_vec(),
+        Value::SmallInt(n) => n.to_be_bytes().to_vec(),
+        Value::BigInt(n) => n.to_be_bytes().to_vec(),
+        Value::Real(f) => f.to_be_bytes().to_vec(),
+        Value::Double(f) => f.to_be_bytes().to_vec(),
+        Value::Numeric(n) => n.to_binary(),
+        Value::Bool(b) => vec![*b as u8],
+        Value::Varchar(s) => s.as_bytes().to_vec(),
+        Value::Bytea(b) => b.clone(),
+        // Dates and times count from 2000-01-01 on the wire as on disk
+        Value::Date(d) => d.to_be_bytes().to_vec(),
+        Value::Time(t) | Value::Timestamp(t) | Value::TimestampTz(t) => t.to_be_bytes().to_vec(),
+        Value::Interval(i) => [
+            &i.micros.to_be_bytes()[..],
+            &i.days.to_be_bytes(),
+            &i.months.to_be_bytes(),
+        ]
+        .concat(),
+        // JSONB is a version byte followed by the text
+        Value::Jsonb(j) => [&[1u8][..], j.to_string().as_bytes()].concat(),
+        Value::Array(_) => anyhow::bail!("binary format is not supported for arrays"),
+        Value::Toast(_) => unreachable!("results are detoasted before they are formatted"),
+    }))
+}
+
+/// Parse the binary form of a value of the given type, as a client sends a
+/// parameter in binary format
+pub fn parse_binary(data: &[u8], data_type: &DataType) -> Result<Value> {
+    let invalid = || anyhow::anyhow!("incorrect binary data format for type {}", data_type.name());
+    let fixed = |len: usize| -> Result<&[u8]> {
+        if data.len() == len {
+            Ok(data)
+        } else {
+            Err(invalid())
+        }
+    };
+    match data_type {
+        DataType::SmallInt => Ok(Value::SmallInt(i16::from_be_bytes(fixed(2)?.try_into()?))),
+        DataType::Int => Ok(Value::Int(i32::from_be_bytes(fixed(4)?.try_into()?))),
+        DataType::BigInt => Ok(Value::BigInt(i64::from_be_bytes(fixed(8)?.try_into()?))),
+        DataType::Real => Ok(Value::Real(f32::from_be_bytes(fixed(4)?.try_into()?))),
+        DataType::Double => Ok(Value::Double(f64::from_be_bytes(fixed(8)?.try_into()?))),
+        DataType::Numeric(_) => {
+            coerce_value(Value::Numeric(Numeric::from_binary(data)?), data_type)
+        }
+        DataType::Bool => Ok(Value::Bool(fixed(1)?[0] != 0)),
+        t if t.is_string() => {
+            let text = String::from_utf8(data.to_vec()).map_err(|_| invalid())?;
+            coerce_value(Value::Varchar(text), data_type)
+        }
+        DataType::Bytea => Ok(Value::Bytea(data.to_vec())),
+        DataType::Date => Ok(Value::Date(i32::from_be_bytes(fixed(4)?.try_into()?))),
+        DataType::Time => Ok(Value::Time(i64::from_be_bytes(fixed(8)?.try_into()?))),
+        DataType::Timestamp => Ok(Value::Timestamp(i64::from_be_bytes(fixed(8)?.try_into()?))),
+        DataType::TimestampTz => {
+            let micros = i64::from_be_bytes(fixed(8)?.try_into()?);
+            Ok(Value::TimestampTz(micros))
+        }
+        DataType::Interval => {
+            let data = fixed(16)?;
+            Ok(Value::Interval(Interval::new(
+                i32::from_be_bytes(data[12..16].try_into()?),
+                i32::from_be_bytes(data[8..12].try_into()?),
+                i64::from_be_bytes(data[..8].try_into()?),
+            )))
+        }
+        DataType::Jsonb => match data.split_first() {
+            Some((1, text)) => {
+                let text = std::str::from_utf8(text).map_err(|_| invalid())?;
+                Ok(Value::Jsonb(Json::parse(text)?))
+            }
+            _ => Err(invalid()),
+        },
+        other => anyhow::bail!("binary format is not supported for type {}", other.name()),
+    }
+}
+
+/// Text output for REAL/DOUBLE PRECISION in PostgreSQL's style: the shortest
+/// round-trip digits (`scientific` is the value formatted with `{:e}`), in
+/// exponent form when the decimal exponent is < -4 or >= `max_exponent`.
+fn format_float(value: f64, scientific: &str, max_exponent: i32) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if value != 0.0 && (exponent < -4 || exponent >= max_exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{mantissa}e{sign}{:02}", exponent.abs())
+    } else {
+        // Plain notation from the same shortest digits
+        let negative = mantissa.starts_with('-');
+        let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+        let point = exponent + 1;
+        let mut text = if point <= 0 {
+            format!("0.{}{digits}", "0".repeat((-point) as usize))
+        } else if point as usize >= digits.len() {
+            format!("{digits}{}", "0".repeat(point as usize - digits.len()))
+        } else {
+            format!("{}.{}", &digits[..point as usize], &digits[point as usize..])
+        };
+        if negative {
+            text.insert(0, '-');
+        }
+        text
+    }
+}
+
+/// Convert a value to a column's type on assignment (INSERT/UPDATE).
+/// Numeric values convert among all numeric types: narrowing is range-checked,
+/// NUMERIC and floats round to integers, and NUMERIC(p,s) rounds to its scale.
+/// DATE, TIMESTAMP and TIMESTAMPTZ convert among each other. Strings are
+/// checked against VARCHAR(n)/CHAR(n) lengths. Array elements are converted
+/// to the element type. Other values pass through.
+pub fn coerce_value(value: Value, data_type: &DataType) -> Result<Value> {
+    if let (Value::Array(elements), DataType::Array(element_type)) = (&value, data_type) {
+        let elements = elements
+            .iter()
+            .map(|element| coerce_value(element.clone(), element_type))
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(Value::Array(elements));
+    }
+    if let Value::Varchar(s) = &value {
+        return match data_type {
+            DataType::Varchar(Some(length)) => {
+                Ok(Value::Varchar(fit_length(s, *length, "character varying")?))
+            }
+            DataType::Char(length) => {
+                let s = fit_length(s, *length, "character")?;
+                let padding = (*length as usize).saturating_sub(s.chars().count());
+                Ok(Value::Varchar(s + &" ".repeat(padding)))
+            }
+            _ => Ok(value),
+        };
+    }
+    if let Some(ts) = value.as_timestamp() {
+        return Ok(match (data_type, &value) {
+            (DataType::Date, Value::Date(_)) => value,
+            (DataType::Date, _) => Value::Date(datetime::timestamp_to_date(ts?)?),
+            (DataType::Timestamp, _) => Value::Timestamp(ts?),
+            (DataType::TimestampTz, _) => Value::TimestampTz(ts?),
+            _ => value,
+        });
+    }
+    if !value.data_type().is_some_and(|t| t.is_numeric()) {
+        return Ok(value);
+    }
+    match data_type {
+        DataType::SmallInt | DataType::Int | DataType::BigInt => {
+            let n = match &value {
+                Value::Real(_) | Value::Double(_) => {
+                    let f = value.as_f64().unwrap().round_ties_even();
+                    // i64::MAX as f64 rounds up to 2^63, so the bound is exclusive
+                    (f >= i64::MIN as f64 && f < i64::MAX as f64).then_some(f as i64)
+                }
+                Value::Numeric(n) => n.to_i64(),
+                other => other.as_i64(),
+            };
+            match n {
+                Some(n) => integer_value(n, data_type),
+                None => anyhow::bail!("{} out of range", data_type.name()),
+            }
+        }
+        DataType::Numeric(typmod) => {
+            let n = match &value {
+                Value::Real(_) | Value::Double(_) => Numeric::from_f64(value.as_f64().unwrap())?,
+                other => other.to_numeric().unwrap(),
+            };
+            match typmod {
+                Some((precision, scale)) => Ok(Value::Numeric(n.apply_typmod(*precision, *scale)?)),
+                None => Ok(Value::Numeric(n)),
+            }
+        }
+        DataType::Real | DataType::Double => {
+            let f = value.as_f64().unwrap();
+            // NUMERIC has no infinities, so an infinite result did not fit
+            if f.is_infinite() && matches!(value, Value::Numeric(_)) {
+                anyhow::bail!("value out of range: overflow");
+            }
+            if *data_type == DataType::Double {
+                return Ok(Value::Double(f));
+            }
+            let narrowed = f as f32;
+            if narrowed.is_infinite() && f.is_finite() {
+                anyhow::bail!("value out of range: overflow");
+            }
+            Ok(Value::Real(narrowed))
+        }
+        _ => Ok(value),
+    }
+}
+
+/// Explicit conversion for CAST(value AS type) and value::type. Strings are
+/// read with the input syntax of the target type and every type casts to a
+/// string through its output form. Numbers, dates/timestamps and arrays
+/// convert as on assignment, integers and booleans convert to each other,
+/// and JSON numbers and booleans convert to SQL ones. Unlike assignment, a
+/// cast to VARCHAR(n) or CHAR(n) truncates.
+pub fn cast_value(value: Value, data_type: &DataType) -> Result<Value> {
+    use DataType::*;
+    let is_integer = matches!(data_type, SmallInt | Int | BigInt);
+    match (&value, data_type) {
+        (Value::Null, _) => Ok(Value::Null),
+        (Value::Array(elements), Array(element_type)) => {
+            let elements = elements
+                .iter()
+                .map(|element| cast_value(element.clone(), element_type))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Value::Array(elements))
+        }
+        (Value::Varchar(text), t) if !t.is_string() => parse_value(text, t),
+        (_, t) if t.is_string() => {
+            let text = format_value(&value).unwrap_or_default();
+            let text = match t {
+                Varchar(Some(length)) | Char(length) => text.chars().take(*length as usize).collect(),
+                _ => text,
+            };
+            coerce_value(Value::Varchar(text), t)
+        }
+        (Value::Bool(b), _) if is_integer => integer_value(*b as i64, data_type),
+        (v, Bool) if v.as_i64().is_some() => Ok(Value::Bool(v.as_i64() != Some(0))),
+        (v, t) if t.is_numeric() && v.data_type().is_some_and(|s| s.is_numeric()) => {
+            coerce_value(value, t)
+        }
+        (v, Date | Timestamp | TimestampTz) if v.as_timestamp().is_some() => coerce_value(value, data_type),
+        (Value::Timestamp(ts) | Value::TimestampTz(ts), Time) => {
+            Ok(Value::Time(ts.rem_euclid(datetime::USECS_PER_DAY)))
+        }
+        (Value::Time(t), Interval) => Ok(Value::Interval(datetime::Interval::new(0, 0, *t))),
+        (Value::Interval(i), Time) => Ok(Value::Time(i.micros.rem_euclid(datetime::USECS_PER_DAY))),
+        (Value::Jsonb(Json::Number(n)), t) if t.is_numeric() => {
+            coerce_value(Value::Numeric(n.clone()), t)
+        }
+        (Value::Jsonb(Json::Bool(b)), Bool) => Ok(Value::Bool(*b)),
+        (Value::Jsonb(json), t) if t.is_numeric() || *t == Bool => {
+            let kind = match json {
+                Json::Null => "null",
+                Json::Bool(_) => "boolean",
+                Json::Number(_) => "numeric",
+                Json::String(_) => "string",
+                Json::Array(_) => "array",
+                Json::Object(_) => "object",
+            };
+            anyhow::bail!("cannot cast jsonb {kind} to type {}", t.name())
+        }
+        (v, t) if v.data_type().as_ref() == Some(t) => Ok(value),
+        (v, t) => anyhow::bail!(
+            "cannot cast type {} to {}",
+            v.data_type().map_or("unknown".to_string(), |s| s.name()),
+            t.name()
+        ),
+    }
+}
+
+/// Enforce a maximum length in characters. As in PostgreSQL, excess
+/// characters are dropped if they are all spaces; otherwise it is an error.
+fn fit_length(s: &str, length: u32, type_name: &str) -> Result<String> {
+    match s.char_indices().nth(length as usize) {
+        None => Ok(s.to_string()),
+        Some((end, _)) if s[end..].chars().all(|c| c == ' ') => Ok(s[..end].to_string()),
+        Some(_) => anyhow::bail!("value too long for type {type_name}({length})"),
+    }
+}
+
+pub fn serialize_value(value: &Value) -> Vec<u8> {
     match value {
         Value::Null => Vec::new(),
         Value::Int(v) => v.to_ne_bytes().to_vec(),
@@ -47,19 +744,59 @@ fn serialize_value(value: &Value) -> Vec<u8> {
             buf
         }
         Value::Bool(v) => vec![if *v { 1 } else { 0 }],
+        Value::SmallInt(v) => v.to_ne_bytes().to_vec(),
+        Value::BigInt(v) => v.to_ne_bytes().to_vec(),
+        Value::Real(v) => v.to_ne_bytes().to_vec(),
+        Value::Double(v) => v.to_ne_bytes().to_vec(),
+        Value::Numeric(v) => v.to_bytes(),
+        Value::Date(v) => v.to_ne_bytes().to_vec(),
+        Value::Time(v) | Value::Timestamp(v) | Value::TimestampTz(v) => v.to_ne_bytes().to_vec(),
+        Value::Bytea(v) => {
+            let mut buf = (v.len() as u32).to_ne_bytes().to_vec();
+            buf.extend_from_slice(v);
+            buf
+        }
+        Value::Jsonb(v) => {
+            let bytes = v.to_bytes();
+            let mut buf = (bytes.len() as u32).to_ne_bytes().to_vec();
+            buf.extend(bytes);
+            buf
+        }
+        Value::Interval(v) => {
+            let mut buf = v.micros.to_ne_bytes().to_vec();
+            buf.extend_from_slice(&v.days.to_ne_bytes());
+            buf.extend_from_slice(&v.months.to_ne_bytes());
+            buf
+        }
+        Value::Array(elements) => {
+            // Element count, then the elements laid out like a tuple
+            let mut buf = (elements.len() as u32).to_ne_bytes().to_vec();
+            buf.extend(serialize_tuple(elements));
+            buf
+        }
+        Value::Toast(pointer) => toast::encode_pointer(pointer),
     }
 }
 
-fn deserialize_value(data: &[u8], data_type: &DataType, is_null: bool) -> Result<(Value, usize)> {
+pub fn deserialize_value(
+    data: &[u8],
+    data_type: &DataType,
+    is_null: bool,
+) -> Result<(Value, usize)> {
     if is_null {
         return Ok((Value::Null, 0));
     }
+    if toast::is_toastable(data_type) {
+        if let Some((pointer, len)) = toast::decode_pointer(data, data_type)? {
+            return Ok((Value::Toast(Box::new(pointer)), len));
+        }
+    }
     match data_type {
         DataType::Int => {
             let v = i32::from_ne_bytes(data[0..4].try_into()?);
             Ok((Value::Int(v), 4))
         }
-        DataType::Varchar => {
+        DataType::Varchar(_) | DataType::Char(_) | DataType::Text => {
             let len = u32::from_ne_bytes(data[0..4].try_into()?) as usize;
             let v = String::from_utf8(data[4..4 + len].to_vec())?;
             Ok((Value::Varchar(v), 4 + len))
@@ -68,6 +805,67 @@ fn deserialize_value(data: &[u8], data_type: &DataType, is_null: bool) -> Result
             let v = data[0] != 0;
             Ok((Value::Bool(v), 1))
         }
+        DataType::SmallInt => {
+            let v = i16::from_ne_bytes(data[0..2].try_into()?);
+            Ok((Value::SmallInt(v), 2))
+        }
+        DataType::BigInt => {
+            let v = i64::from_ne_bytes(data[0..8].try_into()?);
+            Ok((Value::BigInt(v), 8))
+        }
+        DataType::Real => {
+            let v = f32::from_ne_bytes(data[0..4].try_into()?);
+            Ok((Value::Real(v), 4))
+        }
+        DataType::Double => {
+            let v = f64::from_ne_bytes(data[0..8].try_into()?);
+            Ok((Value::Double(v), 8))
+        }
+        DataType::Numeric(_) => {
+            let (v, len) = Numeric::from_bytes(data)?;
+            Ok((Value::Numeric(v), len))
+        }
+        DataType::Date => {
+            let v = i32::from_ne_bytes(data[0..4].try_into()?);
+            Ok((Value::Date(v), 4))
+        }
+        DataType::Time | DataType::Timestamp | DataType::TimestampTz => {
+            let v = i64::from_ne_bytes(data[0..8].try_into()?);
+            let value = match data_type {
+                DataType::Time => Value::Time(v),
+                DataType::Timestamp => Value::Timestamp(v),
+                _ => Value::TimestampTz(v),
+            };
+            Ok((value, 8))
+        }
+        DataType::Bytea => {
+            let len = u32::from_ne_bytes(data[0..4].try_into()?) as usize;
+            Ok((Value::Bytea(data[4..4 + len].to_vec()), 4 + len))
+        }
+        DataType::Jsonb => {
+            let len = u32::from_ne_bytes(data[0..4].try_into()?) as usize;
+            let (v, _) = Json::from_bytes(&data[4..4 + len])?;
+            Ok((Value::Jsonb(v), 4 + len))
+        }
+        DataType::Interval => {
+            let micros = i64::from_ne_bytes(data[0..8].try_into()?);
+            let days = i32::from_ne_bytes(data[8..12].try_into()?);
+            let months = i32::from_ne_bytes(data[12..16].try_into()?);
+            Ok((Value::Interval(Interval::new(months, days, micros)), 16))
+        }
+        DataType::Array(element_type) => {
+            let count = u32::from_ne_bytes(data[0..4].try_into()?) as usize;
+            let bitmap = &data[4..4 + null_bitmap_size(count)];
+            let mut offset = 4 + bitmap.len();
+            let mut elements = Vec::with_capacity(count);
+            for i in 0..count {
+                let is_null = bitmap[i / 8] & (1 << (i % 8)) == 0;
+                let (value, len) = deserialize_value(&data[offset..], element_type, is_null)?;
+                elements.push(value);
+                offset += len;
+            }
+            Ok((Value::Array(elements), offset))
+        }
     }
 }
 
@@ -170,3 +968,133 @@ pub fn get_tuple_xmin(data: &[u8]) -> TxnId {
 pub fn get_tuple_xmax(data: &[u8]) -> TxnId {
     u64::from_le_bytes(data[8..16].try_into().unwrap_or([0; 8]))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash_of(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_integer_widths_equal_and_hash_alike() {
+        let values = [Value::SmallInt(7), Value::Int(7), Value::BigInt(7)];
+        for a in &values {
+            for b in &values {
+                assert_eq!(a, b);
+                assert_eq!(hash_of(a), hash_of(b));
+            }
+        }
+        assert_ne!(Value::Int(7), Value::BigInt(8));
+        assert_ne!(Value::Int(-1), Value::BigInt(u32::MAX as i64));
+        // Other types stay distinct from integers
+        assert_ne!(Value::Int(1), Value::Bool(true));
+        assert_ne!(Value::Int(1), Value::Double(1.0));
+
+        let keys: std::collections::HashSet<_> = values.into_iter().collect();
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn test_float_zeros_and_nans_equal_and_hash_alike() {
+        for (a, b) in [
+            (Value::Double(0.0), Value::Double(-0.0)),
+            (Value::Real(0.0), Value::Real(-0.0)),
+            (Value::Double(f64::NAN), Value::Double(-f64::NAN)),
+            (Value::Real(f32::NAN), Value::Real(-f32::NAN)),
+        ] {
+            assert_eq!(a, b);
+            assert_eq!(hash_of(&a), hash_of(&b));
+        }
+        assert_ne!(Value::Double(0.0), Value::Double(f64::MIN_POSITIVE));
+        assert_ne!(Value::Double(1.5), Value::Double(-1.5));
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Varchar(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_float_out_of_range() {
+        assert_eq!(
+            parse_value("1e400", &DataType::Double).unwrap_err().to_string(),
+            "\"1e400\" is out of range for type double precision"
+        );
+        assert_eq!(
+            parse_value("-1e40", &DataType::Real).unwrap_err().to_string(),
+            "\"-1e40\" is out of range for type real"
+        );
+        assert_eq!(parse_value("1e40", &DataType::Double).unwrap(), Value::Double(1e40));
+        for text in ["inf", "Infinity", "-infinity", " +INF "] {
+            for data_type in [DataType::Double, DataType::Real] {
+                let value = parse_value(text, &data_type).unwrap();
+                let infinite = match value {
+                    Value::Double(f) => f.is_infinite(),
+                    Value::Real(f) => f.is_infinite(),
+                    other => panic!("{other:?}"),
+                };
+                assert!(infinite, "{text}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_numeric_to_float_overflow() {
+        let huge = Value::Numeric(Numeric::parse("1e400").unwrap());
+        for data_type in [DataType::Double, DataType::Real] {
+            assert_eq!(
+                cast_value(huge.clone(), &data_type).unwrap_err().to_string(),
+                "value out of range: overflow"
+            );
+        }
+        // Fits a DOUBLE PRECISION but not a REAL
+        let big = Value::Numeric(Numeric::parse("1e100").unwrap());
+        assert_eq!(cast_value(big.clone(), &DataType::Double).unwrap(), Value::Double(1e100));
+        assert!(cast_value(big, &DataType::Real).is_err());
+        assert_eq!(
+            coerce_value(Value::Numeric(Numeric::parse("2.5").unwrap()), &DataType::Double).unwrap(),
+            Value::Double(2.5)
+        );
+    }
+
+    #[test]
+    fn test_char_pads_and_checks_length() {
+        assert_eq!(coerce_value(text("ab"), &DataType::Char(4)).unwrap(), text("ab  "));
+        assert_eq!(coerce_value(text("abcd"), &DataType::Char(4)).unwrap(), text("abcd"));
+        // Trailing spaces past the length are dropped rather than rejected
+        assert_eq!(coerce_value(text("abc   "), &DataType::Char(4)).unwrap(), text("abc "));
+        // Length counts characters, not bytes
+        assert_eq!(coerce_value(text("äö"), &DataType::Char(3)).unwrap(), text("äö "));
+        assert_eq!(
+            coerce_value(text("abcde"), &DataType::Char(4)).unwrap_err().to_string(),
+            "value too long for type character(4)"
+        );
+    }
+
+    #[test]
+    fn test_varchar_checks_length_without_padding() {
+        let varchar = DataType::Varchar(Some(3));
+        assert_eq!(coerce_value(text("ab"), &varchar).unwrap(), text("ab"));
+        assert_eq!(coerce_value(text("abc  "), &varchar).unwrap(), text("abc"));
+        assert_eq!(
+            coerce_value(text("abcd"), &varchar).unwrap_err().to_string(),
+            "value too long for type character varying(3)"
+        );
+        assert_eq!(coerce_value(text("abcd"), &DataType::Varchar(None)).unwrap(), text("abcd"));
+    }
+
+    #[test]
+    fn test_char_cast_truncates() {
+        assert_eq!(cast_value(text("abcdef"), &DataType::Char(3)).unwrap(), text("abc"));
+        assert_eq!(cast_value(text("a"), &DataType::Char(3)).unwrap(), text("a  "));
+        assert_eq!(cast_value(Value::Int(12345), &DataType::Char(2)).unwrap(), text("12"));
+        assert_eq!(
+            cast_value(text("abcdef"), &DataType::Varchar(Some(4))).unwrap(),
+            text("abcd")
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{bail, Result};
+
+use crate::tuple::{DataType, Value};
+
+// ============================================================================
+// User-defined functions
+// ============================================================================
+//
+// Scalar and aggregate functions written in Rust and registered at startup,
+// before the server accepts connections. The analyzer resolves a call to a
+// built-in function first and then to a registered one, by name and argument
+// types; the executor then calls it like any other function.
+//
+//     udf::register_scalar_function(Levenshtein)?;
+//     udf::register_aggregate_function(GeometricMean)?;
+
+/// Whether a function returns the same result for the same arguments. Only
+/// immutable functions may be used in index expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Volatility {
+    Immutable,
+    Volatile,
+}
+
+pub trait ScalarUdf: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Result type of a call with these argument types, or None if the
+    /// function does not accept them
+    fn return_type(&self, arg_types: &[DataType]) -> Option<DataType>;
+
+    fn volatility(&self) -> Volatility {
+        Volatility::Immutable
+    }
+
+    /// Strict functions return NULL for any NULL argument without being called
+    fn is_strict(&self) -> bool {
+        true
+    }
+
+    /// Compute the result from arguments of the types accepted by return_type
+    fn evaluate(&self, args: &[Value]) -> Result<Value>;
+}
+
+pub trait AggregateUdf: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Result type for an argument of this type, or None if the aggregate
+    /// does not accept it
+    fn return_type(&self, arg_type: &DataType) -> Option<DataType>;
+
+    /// Fresh state for one group
+    fn accumulator(&self, arg_type: &DataType) -> Box<dyn Accumulator>;
+}
+
+/// Running state of a user-defined aggregate over one group
+pub trait Accumulator: fmt::Debug + Send {
+    /// Add one input row's value; NULLs are passed in too
+    fn accumulate(&mut self, value: &Value) -> Result<()>;
+
+    /// Result for the rows seen so far
+    fn finalize(&self) -> Result<Value>;
+}
+
+/// A registered scalar function, as referenced from analyzed expressions
+#[derive(Clone)]
+pub struct ScalarUdfRef(Arc<dyn ScalarUdf>);
+
+/// A registered aggregate, as referenced from analyzed expressions
+#[derive(Clone)]
+pub struct AggregateUdfRef(Arc<dyn AggregateUdf>);
+
+impl std::ops::Deref for ScalarUdfRef {
+    type Target = dyn ScalarUdf;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl std::ops::Deref for AggregateUdfRef {
+    type Target = dyn AggregateUdf;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+// Functions are compared by identity: two calls use the same function only
+// if they resolved to the same registration.
+impl PartialEq for ScalarUdfRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for AggregateUdfRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for ScalarUdfRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ScalarUdf({})", self.name())
+    }
+}
+
+impl fmt::Debug for AggregateUdfRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AggregateUdf({})", self.name())
+    }
+}
+
+struct Registry {
+    scalars: Vec<ScalarUdfRef>,
+    aggregates: Vec<AggregateUdfRef>,
+}
+
+static REGISTRY: RwLock<Registry> = RwLock::new(Registry {
+    scalars: Vec::new(),
+    aggregates: Vec::new(),
+});
+
+/// Make a scalar function callable from SQL. Several functions may share a
+/// name as long as they accept different argument types.
+pub fn register_scalar_function(function: impl ScalarUdf + 'static) -> Result<()> {
+    let name = function.name().to_string();
+    if name.is_empty() || name != name.to_ascii_lowercase() {
+        bail!("function name \"{name}\" must be lowercase");
+    }
+    let mut registry = REGISTRY.write().unwrap();
+    if registry.aggregates.iter().any(|a| a.name() == name) {
+        bail!("function \"{name}\" is already registered as an aggregate");
+    }
+    registry.scalars.push(ScalarUdfRef(Arc::new(function)));
+    Ok(())
+}
+
+/// Make an aggregate callable from SQL as name(expr)
+pub fn register_aggregate_function(function: impl AggregateUdf + 'static) -> Result<()> {
+    let name = function.name().to_string();
+    if name.is_empty() || name != name.to_ascii_lowercase() {
+        bail!("function name \"{name}\" must be lowercase");
+    }
+    let mut registry = REGISTRY.write().unwrap();
+    if registry.scalars.iter().any(|s| s.name() == name) {
+        bail!("function \"{name}\" is already registered as a scalar function");
+    }
+    if registry.aggregates.iter().any(|a| a.name() == name) {
+        bail!("aggregate \"{name}\" is already registered");
+    }
+    registry.aggregates.push(AggregateUdfRef(Arc::new(function)));
+    Ok(())
+}
+
+/// The registered scalar function `name` that accepts `arg_types`, with its
+/// result type
+pub fn resolve_scalar(name: &str, arg_types: &[DataType]) -> Option<(ScalarUdfRef, DataType)> {
+    let registry = REGISTRY.read().unwrap();
+    registry
+        .scalars
+        .iter()
+        .filter(|f| f.name() == name)
+        .find_map(|f| Some((f.clone(), f.return_type(arg_types)?)))
+}
+
+/// Whether `name` is a registered aggregate, whatever its argument types
+pub fn is_aggregate(name: &str) -> bool {
+    let registry = REGISTRY.read().unwrap();
+    registry.aggregates.iter().any(|a| a.name() == name)
+}
+
+/// The registered aggregate `name` accepting `arg_type`, with its result type
+pub fn resolve_aggregate(name: &str, arg_type: &DataType) -> Option<(AggregateUdfRef, DataType)> {
+    let registry = REGISTRY.read().unwrap();
+    registry
+        .aggregates
+        .iter()
+        .filter(|a| a.name() == name)
+        .find_map(|a| Some((a.clone(), a.return_type(arg_type)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Double;
+
+    impl ScalarUdf for Double {
+        fn name(&self) -> &str {
+            "test_double"
+        }
+        fn return_type(&self, arg_types: &[DataType]) -> Option<DataType> {
+            matches!(arg_types, [DataType::Int]).then_some(DataType::BigInt)
+        }
+        fn evaluate(&self, args: &[Value]) -> Result<Value> {
+            match args {
+                [Value::Int(n)] => Ok(Value::BigInt(*n as i64 * 2)),
+                _ => bail!("test_double expects an integer"),
+            }
+        }
+    }
+
+    struct Concat;
+
+    #[derive(Debug)]
+    struct ConcatState(String);
+
+    impl AggregateUdf for Concat {
+        fn name(&self) -> &str {
+            "test_concat"
+        }
+        fn return_type(&self, arg_type: &DataType) -> Option<DataType> {
+            arg_type.is_string().then_some(DataType::Text)
+        }
+        fn accumulator(&self, _arg_type: &DataType) -> Box<dyn Accumulator> {
+            Box::new(ConcatState(String::new()))
+        }
+    }
+
+    impl Accumulator for ConcatState {
+        fn accumulate(&mut self, value: &Value) -> Result<()> {
+            if let Value::Varchar(s) = value {
+                self.0.push_str(s);
+            }
+            Ok(())
+        }
+        fn finalize(&self) -> Result<Value> {
+            Ok(Value::Varchar(self.0.clone()))
+        }
+    }
+
+    #[test]
+    fn test_scalar_registration() {
+        register_scalar_function(Double).unwrap();
+        let (function, result_type) = resolve_scalar("test_double", &[DataType::Int]).unwrap();
+        assert_eq!(result_type, DataType::BigInt);
+        assert_eq!(function.evaluate(&[Value::Int(21)]).unwrap(), Value::BigInt(42));
+        assert_eq!(function, resolve_scalar("test_double", &[DataType::Int]).unwrap().0);
+
+        assert!(resolve_scalar("test_double", &[DataType::Text]).is_none());
+        assert!(resolve_scalar("test_missing", &[]).is_none());
+        assert!(register_scalar_function(Upper).is_err());
+    }
+
+    struct Upper;
+
+    impl ScalarUdf for Upper {
+        fn name(&self) -> &str {
+            "Test_Upper"
+        }
+        fn return_type(&self, _arg_types: &[DataType]) -> Option<DataType> {
+            None
+        }
+        fn evaluate(&self, _args: &[Value]) -> Result<Value> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_aggregate_registration() {
+        register_aggregate_function(Concat).unwrap();
+        assert!(is_aggregate("test_concat"));
+        assert!(resolve_aggregate("test_concat", &DataType::Int).is_none());
+
+        let (aggregate, result_type) = resolve_aggregate("test_concat", &DataType::Text).unwrap();
+        assert_eq!(result_type, DataType::Text);
+        let mut state = aggregate.accumulator(&DataType::Text);
+        for value in [Value::Varchar("a".into()), Value::Null, Value::Varchar("b".into())] {
+            state.accumulate(&value).unwrap();
+        }
+        assert_eq!(state.finalize().unwrap(), Value::Varchar("ab".into()));
+
+        // One name cannot be both kinds of function
+        assert!(register_aggregate_function(Concat).is_err());
+    }
+}
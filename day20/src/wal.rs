@@ -21,6 +21,8 @@ pub enum WalRecordType {
     Commit,
     Abort,
     Insert { rid: Rid, data: Vec<u8> },
+    // Several tuples added to one page by a bulk load: (slot_id, data) each
+    MultiInsert { page_id: u32, tuples: Vec<(u16, Vec<u8>)> },
     // MVCC logical delete: set xmax to mark tuple as deleted
     Delete { rid: Rid, xmax: u64 },
     // Compensation Log Record - written during undo
@@ -39,6 +41,9 @@ pub enum WalRecordType {
         table_id: u32,
         prev_page_id: u32,  // u32::MAX means no previous page (first page of table)
     },
+    // VACUUM removed dead tuples from a page, then compacted it. Not part of
+    // any transaction (txn_id 0).
+    Vacuum { page_id: u32, slots: Vec<u16> },
 }
 
 #[derive(Debug, Clone)]
@@ -58,6 +63,8 @@ const TAG_DELETE: u8 = 4;
 const TAG_CLR: u8 = 5;
 const TAG_CHECKPOINT: u8 = 6;
 const TAG_ALLOCATE_PAGE: u8 = 7;
+const TAG_MULTI_INSERT: u8 = 8;
+const TAG_VACUUM: u8 = 9;
 
 // CLR redo type tags
 const CLR_UNDO_INSERT: u8 = 0;
@@ -100,6 +107,19 @@ impl WalRecord {
                 buf.extend_from_slice(&rid.slot_id.to_le_bytes());
                 buf.extend_from_slice(data);
             }
+            WalRecordType::MultiInsert { page_id, tuples } => {
+                buf.push(TAG_MULTI_INSERT);
+                // page_id + count, then slot_id + length + data per tuple
+                let data_len = 4 + 2 + tuples.iter().map(|(_, d)| 2 + 2 + d.len()).sum::<usize>();
+                buf.extend_from_slice(&(data_len as u32).to_le_bytes());
+                buf.extend_from_slice(&page_id.to_le_bytes());
+                buf.extend_from_slice(&(tuples.len() as u16).to_le_bytes());
+                for (slot_id, data) in tuples {
+                    buf.extend_from_slice(&slot_id.to_le_bytes());
+                    buf.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(data);
+                }
+            }
             WalRecordType::Delete { rid, xmax } => {
                 buf.push(TAG_DELETE);
                 let data_len = 4 + 2 + 8; // page_id + slot_id + xmax
@@ -161,6 +181,16 @@ impl WalRecord {
                 buf.extend_from_slice(&table_id.to_le_bytes());
                 buf.extend_from_slice(&prev_page_id.to_le_bytes());
             }
+            WalRecordType::Vacuum { page_id, slots } => {
+                buf.push(TAG_VACUUM);
+                let data_len = 4 + 2 + slots.len() * 2; // page_id + count + slot_ids
+                buf.extend_from_slice(&(data_len as u32).to_le_bytes());
+                buf.extend_from_slice(&page_id.to_le_bytes());
+                buf.extend_from_slice(&(slots.len() as u16).to_le_bytes());
+                for slot_id in slots {
+                    buf.extend_from_slice(&slot_id.to_le_bytes());
+                }
+            }
         }
 
         buf
@@ -378,7 +408,12 @@ fn read_segment_file(path: &Path) -> std::io::Result<Vec<WalRecord>> {
         let len = u32::from_le_bytes(len_buf) as usize;
 
         let mut data = vec![0u8; len];
-        reader.read_exact(&mut data)?;
+        match reader.read_exact(&mut data) {
+            Ok(_) => {}
+            // A record cut short by a crash while it was written: the log ends before it
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
 
         if let Some(record) = parse_record(&data) {
             records.push(record);
@@ -412,6 +447,20 @@ fn parse_record(data: &[u8]) -> Option<WalRecord> {
                 data: tuple_data,
             }
         }
+        TAG_MULTI_INSERT => {
+            let page_id = u32::from_le_bytes(data[29..33].try_into().unwrap());
+            let count = u16::from_le_bytes(data[33..35].try_into().unwrap());
+            let mut offset = 35;
+            let mut tuples = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let slot_id = u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap());
+                let len = u16::from_le_bytes(data[offset + 2..offset + 4].try_into().unwrap()) as usize;
+                offset += 4;
+                tuples.push((slot_id, data[offset..offset + len].to_vec()));
+                offset += len;
+            }
+            WalRecordType::MultiInsert { page_id, tuples }
+        }
         TAG_DELETE => {
             let page_id = u32::from_le_bytes(data[29..33].try_into().unwrap());
             let slot_id = u16::from_le_bytes(data[33..35].try_into().unwrap());
@@ -476,6 +525,14 @@ fn parse_record(data: &[u8]) -> Option<WalRecord> {
             let prev_page_id = u32::from_le_bytes(data[37..41].try_into().unwrap());
             WalRecordType::AllocatePage { page_id, table_id, prev_page_id }
         }
+        TAG_VACUUM => {
+            let page_id = u32::from_le_bytes(data[29..33].try_into().unwrap());
+            let count = u16::from_le_bytes(data[33..35].try_into().unwrap()) as usize;
+            let slots = (0..count)
+                .map(|i| u16::from_le_bytes(data[35 + i * 2..37 + i * 2].try_into().unwrap()))
+                .collect();
+            WalRecordType::Vacuum { page_id, slots }
+        }
         _ => return None,
     };
 